@@ -0,0 +1,69 @@
+//! # rust-miniscript integration test
+//!
+//! Compare descriptors against Bitcoin Core with the differential testing
+//! module, through the RPC interface of a running node
+//!
+
+use std::str::FromStr;
+
+use bitcoind::client::client_sync::Error as RpcError;
+use bitcoind::Client;
+use miniscript::bitcoin::Network;
+use miniscript::coretest::{self, CoreDescriptorInfo, CoreRpc};
+use miniscript::{Descriptor, DescriptorPublicKey};
+
+mod setup;
+
+/// The RPC interface of the node under test
+struct NodeRpc<'a>(&'a Client);
+
+impl CoreRpc for NodeRpc<'_> {
+    type Error = RpcError;
+
+    fn get_descriptor_info(&self, descriptor: &str) -> Result<CoreDescriptorInfo, Self::Error> {
+        let info: serde_json::Value = self.0.call("getdescriptorinfo", &[descriptor.into()])?;
+        Ok(CoreDescriptorInfo {
+            descriptor: info["descriptor"].as_str().unwrap().to_owned(),
+            checksum: info["checksum"].as_str().unwrap().to_owned(),
+            is_range: info["isrange"].as_bool().unwrap(),
+        })
+    }
+
+    fn derive_addresses(
+        &self,
+        descriptor: &str,
+        range: Option<(u32, u32)>,
+    ) -> Result<Vec<String>, Self::Error> {
+        let mut args = vec![descriptor.into()];
+        if let Some((start, end)) = range {
+            args.push(serde_json::json!([start, end]));
+        }
+        self.0.call("deriveaddresses", &args)
+    }
+}
+
+#[test]
+fn compare_with_core() {
+    let bitcoind = setup::setup();
+    let rpc = NodeRpc(&bitcoind.client);
+
+    let xpub = "tpubD6NzVbkrYhZ4XHndKkuB8FifXm8r5FQHwrN6oZuWCz13qb93rtgKvD4PQsqC4HP4yhV3tA2fqr2RbY5mNXfM7RxXUoeABoDtsFUq2zJq6YK";
+    let pk = "02a34b99f22c790c4e36b2b3c2c35a36db06226e41c692fc82b8b56ac1c540c5bd";
+    let xonly = "a34b99f22c790c4e36b2b3c2c35a36db06226e41c692fc82b8b56ac1c540c5bd";
+    let descriptors = [
+        format!("pkh([d34db33f/44'/1'/0']{}/0/*)", xpub),
+        format!("wpkh({}/0/*)", xpub),
+        format!("sh(wpkh({}))", pk),
+        format!("wsh(multi(2,{}/0/*,{}/1/*,{}))", xpub, xpub, pk),
+        format!("sh(wsh(sortedmulti(1,{}/0/*,{})))", xpub, pk),
+        format!("wsh(and_v(v:pk({}/0/*),older(144)))", xpub),
+        format!("tr({}/0/*)", xpub),
+        format!("tr({}/0/*,{{pk({}),pk({}/1/*)}})", xpub, xonly, xpub),
+        format!("pk({})", pk),
+    ];
+    for desc in &descriptors {
+        let desc = Descriptor::<DescriptorPublicKey>::from_str(desc).unwrap();
+        let report = coretest::compare(&rpc, &desc, Network::Regtest, 0..10).unwrap();
+        assert!(report.is_compatible(), "{}", report);
+    }
+}
@@ -241,6 +241,9 @@ pub fn test_desc_satisfy(
                     miniscript::descriptor::WshInner::Ms(ref ms) => find_sks_ms(ms, testdata),
                 },
                 Descriptor::Tr(_tr) => unreachable!("Tr checked earlier"),
+                Descriptor::Data(_) => unreachable!("Data descriptors can't be spent"),
+                Descriptor::Wother(_) => unreachable!("Future witness versions can't be spent"),
+                Descriptor::RawTr(_) => unreachable!("No rawtr descriptors in the test data"),
             };
             let msg = psbt
                 .sighash_msg(0, &mut sighash_cache, None)
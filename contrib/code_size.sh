@@ -0,0 +1,110 @@
+#!/usr/bin/env bash
+#
+# Report the size of a binary using the library in minimal configurations.
+#
+# Builds a small program parsing a descriptor, deriving it, planning and
+# computing its satisfaction, with the library in each configuration, and
+# prints the size of the stripped binary. Pass a target triple to build for
+# it rather than for the host.
+
+set -euo pipefail
+
+REPO_DIR=$(git rev-parse --show-toplevel)
+TARGET=${1:-}
+
+WORK_DIR=$(mktemp -d)
+trap 'rm -rf "$WORK_DIR"' EXIT
+
+mkdir "$WORK_DIR/src"
+cat > "$WORK_DIR/Cargo.toml" <<EOF
+[package]
+name = "code-size"
+version = "0.1.0"
+edition = "2021"
+publish = false
+
+[features]
+std = ["miniscript/std"]
+compiler = ["miniscript/compiler"]
+compile = ["compiler"]
+
+[dependencies]
+miniscript = { path = "$REPO_DIR", default-features = false }
+
+[profile.release]
+codegen-units = 1
+lto = true
+opt-level = "z"
+panic = "abort"
+strip = true
+
+[workspace]
+EOF
+
+cat > "$WORK_DIR/src/main.rs" <<'EOF'
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+use miniscript::bitcoin::secp256k1::{ecdsa, Secp256k1};
+use miniscript::bitcoin::{self, Network};
+use miniscript::plan::Assets;
+use miniscript::{Descriptor, DescriptorPublicKey, ForEachKey};
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    let desc = Descriptor::<DescriptorPublicKey>::from_str(&args[1]).unwrap();
+    let index = u32::from_str(&args[2]).unwrap();
+    let secp = Secp256k1::verification_only();
+
+    let definite = desc.at_derivation_index(index).unwrap();
+    let derived = definite.derived_descriptor(&secp).unwrap();
+    println!("{}", derived.address(Network::Bitcoin).unwrap());
+
+    let mut assets = Assets::new();
+    desc.for_each_key(|pk| {
+        assets = assets.clone().add(pk.clone());
+        true
+    });
+    let plan = definite.clone().plan(&assets).unwrap();
+    println!("{}", plan.satisfaction_weight());
+
+    let sig = bitcoin::ecdsa::Signature::sighash_all(
+        ecdsa::Signature::from_compact(&[1; 64]).unwrap(),
+    );
+    let mut satisfier = BTreeMap::new();
+    derived.for_each_key(|pk| satisfier.insert(*pk, sig).is_none());
+    let (witness, script_sig) = derived.get_satisfaction(&satisfier).unwrap();
+    println!("{} {}", witness.len(), script_sig.len());
+
+    #[cfg(feature = "compile")]
+    {
+        use miniscript::policy::Concrete;
+        let policy = Concrete::<String>::from_str(&args[3]).unwrap();
+        println!("{}", policy.compile::<miniscript::Segwitv0>().unwrap());
+    }
+}
+EOF
+
+size_of() {
+    local features=$1
+    local target_args=()
+    if [ -n "$TARGET" ]; then
+        target_args=(--target "$TARGET")
+    fi
+    RUSTFLAGS="${RUSTFLAGS:-} --cap-lints allow" cargo build --quiet --release --manifest-path "$WORK_DIR/Cargo.toml" \
+        --target-dir "$WORK_DIR/target" --features "$features" "${target_args[@]}"
+    stat --format=%s "$WORK_DIR/target/${TARGET:+$TARGET/}release/code-size"
+}
+
+echo "$(rustc --version), target ${TARGET:-$(rustc -vV | sed -n 's/^host: //p')}"
+echo
+echo "| Configuration                        | Binary size |"
+echo "|--------------------------------------|-------------|"
+MINIMAL=$(size_of "")
+COMPILER=$(size_of compiler)
+COMPILE=$(size_of compile)
+STD=$(size_of std)
+printf "| %-36s | %9s B |\n" '`default-features = false`' "$MINIMAL"
+printf "| %-36s | %9s B |\n" 'with `compiler`, not called' "$COMPILER"
+printf "| %-36s | %9s B |\n" 'with `compiler`, calling `compile`' "$COMPILE"
+printf "| %-36s | %9s B |\n" 'with `std`' "$STD"
@@ -5,10 +5,10 @@
 # shellcheck disable=SC2034
 
 # Test all these features with "std" enabled.
-FEATURES_WITH_STD="compiler trace serde rand base64"
+FEATURES_WITH_STD="compiler trace graph serde rand base64 test-hashes coretest zeroize"
 
 # Test all these features without "std" enabled.
-FEATURES_WITHOUT_STD="compiler trace serde rand base64"
+FEATURES_WITHOUT_STD="compiler trace graph serde rand base64 test-hashes coretest zeroize"
 
 # Run these examples.
 # Note `examples/big` should not be run.
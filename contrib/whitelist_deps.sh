@@ -6,3 +6,5 @@
 
 # Remove once we upgrade to `bitcoin v0.32.0`.
 DUPLICATE_DEPS=("bech32")
+# MuSig2 key aggregation needs a newer `secp256k1` than the one of `bitcoin v0.32`.
+DUPLICATE_DEPS+=("secp256k1" "secp256k1-sys")
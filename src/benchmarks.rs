@@ -297,6 +297,38 @@ deep_taproot_bigtree!(parse_descriptor_tr_deep_bigtree_g_100, 100);
 deep_taproot_bigtree!(parse_descriptor_tr_deep_bigtree_h_128, 128);
 // taproot trees are not allowed to be 129 deep
 
+/// A ranged taproot descriptor whose tree of 64 leaves does not depend on the derivation index.
+fn ranged_tr_fixed_tree() -> Desc {
+    let tree = generate_balanced_tree_str(64, |left, right| format!("{{{left},{right}}}"));
+    let s = format!("tr(xpub6BgBgsespWvERF3LHQu6CnqdvfEvtMcQjYrcRzx53QJjSxarj2afYWcLteoGVky7D3UKDP9QyrLprQ3VCECoY49yfdDEHGCtMMj92pReUsQ/0/*,{})", tree);
+    Desc::from_str(&s).unwrap()
+}
+
+#[bench]
+fn scan_tr_fixed_tree_a_derive_each(bh: &mut Bencher) {
+    let secp = Secp256k1::verification_only();
+    let desc = ranged_tr_fixed_tree();
+    bh.iter(|| {
+        for i in 0..100 {
+            black_box(desc.derived_descriptor(&secp, i).unwrap().script_pubkey());
+        }
+    })
+}
+
+#[bench]
+fn scan_tr_fixed_tree_b_cached(bh: &mut Bencher) {
+    let secp = Secp256k1::verification_only();
+    let desc = ranged_tr_fixed_tree();
+    let missing = bitcoin::ScriptBuf::new();
+    bh.iter(|| {
+        // Scans the full range since no index matches the empty script
+        black_box(
+            desc.find_derivation_index_for_spk(&secp, &missing, 0..100)
+                .unwrap(),
+        )
+    })
+}
+
 #[cfg(feature = "compiler")]
 mod compiler_benches {
     use super::*;
@@ -0,0 +1,366 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! # BIP 322 Generic Signed Messages
+//!
+//! Signing and verification of messages with any descriptor, following BIP 322
+//! `https://github.com/bitcoin/bips/blob/master/bip-0322.mediawiki`.
+//!
+//! A message is signed by satisfying the descriptor in the virtual `to_sign`
+//! transaction, which spends the output of the virtual `to_spend` transaction
+//! committing to the message. The signature is the `to_sign` transaction in the
+//! full format, or only its witness in the simple format.
+//!
+//! Proofs of funds, with additional inputs to `to_sign`, aren't supported.
+//!
+
+use core::fmt;
+#[cfg(feature = "std")]
+use std::error;
+
+use bitcoin::blockdata::opcodes;
+use bitcoin::consensus::encode;
+use bitcoin::hashes::{sha256, Hash, HashEngine};
+use bitcoin::psbt::Psbt;
+use bitcoin::sighash::Prevouts;
+use bitcoin::transaction::Version;
+use bitcoin::{
+    absolute, script, secp256k1, Amount, OutPoint, Script, ScriptBuf, Sequence, Transaction, TxIn,
+    TxOut, Txid, Witness,
+};
+
+use crate::prelude::*;
+use crate::psbt::{PsbtExt, UtxoUpdateError};
+use crate::{
+    interpreter, DefiniteDescriptorKey, Descriptor, MiniscriptKey, Satisfier, ToPublicKey,
+};
+
+/// The tag of the hash of the message committed to by `to_spend`
+const MESSAGE_TAG: &[u8] = b"BIP0322-signed-message";
+
+/// Error signing or verifying a BIP 322 message
+#[derive(Debug)]
+pub enum Error {
+    /// The `to_sign` transaction isn't a valid BIP 322 `to_sign` transaction
+    InvalidToSign(&'static str),
+    /// A simple signature can't be used with script pubkeys requiring a `scriptSig`
+    NotSegwit,
+    /// The simple signature couldn't be decoded
+    Encoding(encode::Error),
+    /// The descriptor couldn't be satisfied
+    Miniscript(crate::Error),
+    /// The PSBT input couldn't be updated with the descriptor
+    UtxoUpdate(UtxoUpdateError),
+    /// The signature doesn't satisfy the script pubkey
+    Interpreter(interpreter::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::InvalidToSign(ref s) => write!(f, "invalid to_sign transaction: {}", s),
+            Error::NotSegwit => {
+                f.write_str("simple signatures are only defined for segwit script pubkeys")
+            }
+            Error::Encoding(ref e) => write!(f, "simple signature: {}", e),
+            Error::Miniscript(ref e) => fmt::Display::fmt(e, f),
+            Error::UtxoUpdate(ref e) => fmt::Display::fmt(e, f),
+            Error::Interpreter(ref e) => write!(f, "signature verification: {}", e),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl error::Error for Error {
+    fn cause(&self) -> Option<&dyn error::Error> {
+        match self {
+            Error::InvalidToSign(_) | Error::NotSegwit => None,
+            Error::Encoding(e) => Some(e),
+            Error::Miniscript(e) => Some(e),
+            Error::UtxoUpdate(e) => Some(e),
+            Error::Interpreter(e) => Some(e),
+        }
+    }
+}
+
+/// The tagged hash of `message` committed to by the `to_spend` transaction
+pub fn message_hash(message: &[u8]) -> sha256::Hash {
+    let tag = sha256::Hash::hash(MESSAGE_TAG);
+    let mut engine = sha256::Hash::engine();
+    engine.input(tag.as_byte_array());
+    engine.input(tag.as_byte_array());
+    engine.input(message);
+    sha256::Hash::from_engine(engine)
+}
+
+/// The virtual `to_spend` transaction, whose output is locked by `script_pubkey`
+pub fn to_spend(script_pubkey: &Script, message: &[u8]) -> Transaction {
+    let script_sig = script::Builder::new()
+        .push_opcode(opcodes::OP_0)
+        .push_slice(message_hash(message).to_byte_array())
+        .into_script();
+    Transaction {
+        version: Version(0),
+        lock_time: absolute::LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: OutPoint { txid: Txid::all_zeros(), vout: 0xFFFFFFFF },
+            script_sig,
+            sequence: Sequence::ZERO,
+            witness: Witness::new(),
+        }],
+        output: vec![TxOut { value: Amount::ZERO, script_pubkey: script_pubkey.to_owned() }],
+    }
+}
+
+/// The unsigned virtual `to_sign` transaction, spending the output of `to_spend`
+pub fn to_sign(to_spend: &Transaction) -> Transaction {
+    Transaction {
+        version: Version(0),
+        lock_time: absolute::LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: OutPoint { txid: to_spend.compute_txid(), vout: 0 },
+            script_sig: ScriptBuf::new(),
+            sequence: Sequence::ZERO,
+            witness: Witness::new(),
+        }],
+        output: vec![TxOut {
+            value: Amount::ZERO,
+            script_pubkey: script::Builder::new()
+                .push_opcode(opcodes::all::OP_RETURN)
+                .into_script(),
+        }],
+    }
+}
+
+/// A PSBT of the `to_sign` transaction for `descriptor`, to be signed and finalized like
+/// any other PSBT before extracting the signed `to_sign` transaction
+pub fn to_sign_psbt(
+    descriptor: &Descriptor<DefiniteDescriptorKey>,
+    message: &[u8],
+) -> Result<Psbt, Error> {
+    let to_spend = to_spend(&descriptor.script_pubkey(), message);
+    let mut psbt = Psbt::from_unsigned_tx(to_sign(&to_spend))
+        .expect("to_sign has empty scriptSigs and witnesses");
+    psbt.inputs[0].witness_utxo = Some(to_spend.output[0].clone());
+    psbt.inputs[0].non_witness_utxo = Some(to_spend);
+    psbt.update_input_with_descriptor(0, descriptor)
+        .map_err(Error::UtxoUpdate)?;
+    Ok(psbt)
+}
+
+/// Signs `message` with `descriptor`, returning the signed `to_sign` transaction
+///
+/// The satisfier must provide signatures for the input of the [`to_sign`] transaction,
+/// spending the output of [`to_spend`].
+pub fn sign<Pk, S>(
+    descriptor: &Descriptor<Pk>,
+    message: &[u8],
+    satisfier: S,
+) -> Result<Transaction, Error>
+where
+    Pk: MiniscriptKey + ToPublicKey,
+    S: Satisfier<Pk>,
+{
+    let mut tx = to_sign(&to_spend(&descriptor.script_pubkey(), message));
+    descriptor
+        .satisfy(&mut tx.input[0], satisfier)
+        .map_err(Error::Miniscript)?;
+    Ok(tx)
+}
+
+/// Verifies that `to_sign` is a valid signature of `message` by `script_pubkey`, in the
+/// full format
+///
+/// The timelocks of the script pubkey are checked against the version, lock time and
+/// sequence of `to_sign`, which may differ from the ones of [`to_sign`].
+pub fn verify<C: secp256k1::Verification>(
+    secp: &secp256k1::Secp256k1<C>,
+    script_pubkey: &Script,
+    message: &[u8],
+    to_sign: &Transaction,
+) -> Result<(), Error> {
+    let to_spend = to_spend(script_pubkey, message);
+    let input = match to_sign.input[..] {
+        [ref input] => input,
+        _ => return Err(Error::InvalidToSign("expected a single input")),
+    };
+    if input.previous_output != (OutPoint { txid: to_spend.compute_txid(), vout: 0 }) {
+        return Err(Error::InvalidToSign("input doesn't spend to_spend"));
+    }
+    match to_sign.output[..] {
+        [ref output] if output.value == Amount::ZERO && output.script_pubkey.is_op_return() => {}
+        _ => return Err(Error::InvalidToSign("expected a single empty OP_RETURN output")),
+    }
+
+    let interpreter = interpreter::Interpreter::from_txdata(
+        &to_spend.output[0].script_pubkey,
+        &input.script_sig,
+        &input.witness,
+        input.sequence,
+        to_sign.lock_time,
+    )
+    .map_err(Error::Interpreter)?;
+    let prevouts = Prevouts::All(&to_spend.output);
+    let mut iter = interpreter.iter(secp, to_sign, 0, &prevouts);
+    match iter.find_map(Result::err) {
+        Some(e) => Err(Error::Interpreter(e)),
+        None => Ok(()),
+    }
+}
+
+/// Encodes the signed `to_sign` transaction as a simple signature, i.e. its witness
+pub fn encode_simple(to_sign: &Transaction) -> Result<Vec<u8>, Error> {
+    let input = to_sign
+        .input
+        .first()
+        .ok_or(Error::InvalidToSign("expected a single input"))?;
+    if !input.script_sig.is_empty() {
+        return Err(Error::NotSegwit);
+    }
+    Ok(encode::serialize(&input.witness))
+}
+
+/// Decodes a simple signature of `message` by `script_pubkey` into the signed `to_sign`
+/// transaction, to pass to [`verify`]
+pub fn decode_simple(
+    script_pubkey: &Script,
+    message: &[u8],
+    signature: &[u8],
+) -> Result<Transaction, Error> {
+    if !script_pubkey.is_witness_program() {
+        return Err(Error::NotSegwit);
+    }
+    let mut tx = to_sign(&to_spend(script_pubkey, message));
+    tx.input[0].witness = encode::deserialize(signature).map_err(Error::Encoding)?;
+    Ok(tx)
+}
+
+#[cfg(test)]
+mod tests {
+    use core::str::FromStr;
+
+    use bitcoin::base64::prelude::{Engine as _, BASE64_STANDARD};
+    use bitcoin::sighash::{EcdsaSighashType, SighashCache, TapSighashType};
+    use bitcoin::taproot::{LeafVersion, TapLeafHash};
+    use bitcoin::{Address, Network, PrivateKey};
+
+    use super::*;
+
+    #[test]
+    fn bip322_vectors() {
+        assert_eq!(
+            message_hash(b"").to_string(),
+            "c90c269c4f8fcbe6880f72a721ddfbf1914268a794cbb21cfafee13770ae19f1"
+        );
+        assert_eq!(
+            message_hash(b"Hello World").to_string(),
+            "f0eb03b1a75ac6d9847f55c624a99169b5dccba2a31f5b23bea77ba270de0a7a"
+        );
+
+        let address = Address::from_str("bc1q9vza2e8x573nczrlzms0wvx3gsqjx7vavgkx0l")
+            .unwrap()
+            .assume_checked();
+        let spk = address.script_pubkey();
+        let to_spend_tx = to_spend(&spk, b"");
+        assert_eq!(
+            to_spend_tx.compute_txid().to_string(),
+            "c5680aa69bb8d860bf82d4e9cd3504b55dde018de765a91bb566283c545a99a7"
+        );
+        assert_eq!(
+            to_sign(&to_spend_tx).compute_txid().to_string(),
+            "1e9654e951a5ba44c8604c4de6c67fd78a27e81dcadcfe1edf638ba3aaebaed6"
+        );
+
+        let sig = BASE64_STANDARD.decode("AkcwRAIgZRfIY3p7/DoVTty6YZbWS71bc5Vct9p9Fia83eRmw2QCICK/ENGfwLtptFluMGs2KsqoNSk89pO7F29zJLUx9a/sASECx/EgAxlkQpQ9hYjgGu6EBCPMVPwVIVJqO4XCsMvViHI=").unwrap();
+        let secp = secp256k1::Secp256k1::verification_only();
+        let tx = decode_simple(&spk, b"Hello World", &sig).unwrap();
+        verify(&secp, &spk, b"Hello World", &tx).unwrap();
+        assert!(verify(&secp, &spk, b"Hello World!", &tx).is_err());
+        let tx = decode_simple(&spk, b"", &sig).unwrap();
+        assert!(verify(&secp, &spk, b"", &tx).is_err());
+        assert_eq!(encode_simple(&tx).unwrap(), sig);
+    }
+
+    #[test]
+    fn bip322_sign_multisig_and_tapscript() {
+        let secp = secp256k1::Secp256k1::new();
+        let sks: Vec<_> = (1..=2u8)
+            .map(|i| {
+                PrivateKey::new(
+                    secp256k1::SecretKey::from_slice(&[i; 32]).unwrap(),
+                    Network::Bitcoin,
+                )
+            })
+            .collect();
+        let pks: Vec<_> = sks.iter().map(|sk| sk.public_key(&secp)).collect();
+        let message = b"proof of control";
+
+        // A 2-of-2 multisig
+        let desc = Descriptor::<bitcoin::PublicKey>::from_str(&format!(
+            "wsh(multi(2,{},{}))",
+            pks[0], pks[1]
+        ))
+        .unwrap();
+        let spk = desc.script_pubkey();
+        let unsigned = to_sign(&to_spend(&spk, message));
+        let sighash = SighashCache::new(&unsigned)
+            .p2wsh_signature_hash(
+                0,
+                &desc.explicit_script().unwrap(),
+                Amount::ZERO,
+                EcdsaSighashType::All,
+            )
+            .unwrap();
+        let msg = secp256k1::Message::from_digest(sighash.to_byte_array());
+        let mut sigs = BTreeMap::new();
+        for (sk, pk) in sks.iter().zip(pks.iter()) {
+            let sig = secp.sign_ecdsa(&msg, &sk.inner);
+            sigs.insert(*pk, bitcoin::ecdsa::Signature::sighash_all(sig));
+        }
+        let tx = sign(&desc, message, &sigs).unwrap();
+        verify(&secp, &spk, message, &tx).unwrap();
+        let simple = encode_simple(&tx).unwrap();
+        verify(&secp, &spk, message, &decode_simple(&spk, message, &simple).unwrap()).unwrap();
+
+        // A single signature is not enough
+        sigs.remove(&pks[1]);
+        assert!(sign(&desc, message, &sigs).is_err());
+
+        // A taproot script path
+        let desc = Descriptor::<bitcoin::PublicKey>::from_str(&format!(
+            "tr(0250929b74c1a04954b78b4b6035e97a5e078a5a0f28ec96d547bfee9ace803ac0,pk({}))",
+            pks[0]
+        ))
+        .unwrap();
+        let spk = desc.script_pubkey();
+        let to_spend_tx = to_spend(&spk, message);
+        let unsigned = to_sign(&to_spend_tx);
+        let leaf_script = desc
+            .as_tr()
+            .unwrap()
+            .iter_scripts()
+            .next()
+            .unwrap()
+            .1
+            .encode();
+        let leaf_hash = TapLeafHash::from_script(&leaf_script, LeafVersion::TapScript);
+        let sighash = SighashCache::new(&unsigned)
+            .taproot_script_spend_signature_hash(
+                0,
+                &Prevouts::All(&to_spend_tx.output),
+                leaf_hash,
+                TapSighashType::Default,
+            )
+            .unwrap();
+        let msg = secp256k1::Message::from_digest(sighash.to_byte_array());
+        let keypair = secp256k1::Keypair::from_secret_key(&secp, &sks[0].inner);
+        let sig = bitcoin::taproot::Signature {
+            signature: secp.sign_schnorr(&msg, &keypair),
+            sighash_type: TapSighashType::Default,
+        };
+        let mut sigs = BTreeMap::new();
+        sigs.insert((pks[0], leaf_hash), sig);
+        let tx = sign(&desc, message, &sigs).unwrap();
+        verify(&secp, &spk, message, &tx).unwrap();
+        assert!(verify(&secp, &spk, b"another message", &tx).is_err());
+    }
+}
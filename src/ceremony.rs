@@ -0,0 +1,369 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! # Descriptor Ceremonies
+//!
+//! Commit-reveal verification of a descriptor assembled by a coordinator from
+//! the keys of several parties. The parties agree on a template descriptor
+//! whose keys are their names, e.g. `wsh(multi(2,alice,bob,carol))`. Each
+//! party commits to its key with a [`KeyCommitment`], and only reveals the key
+//! once every commitment has been collected, so that no party can choose its
+//! key after seeing the others. The descriptor the coordinator hands out is then
+//! checked to be the template with exactly the committed keys in place, so that
+//! a key can't be swapped without the parties noticing.
+//!
+
+use core::fmt;
+use core::str::FromStr;
+#[cfg(feature = "std")]
+use std::error;
+
+use bitcoin::hashes::{hash160, ripemd160, sha256, Hash, HashEngine};
+use bitcoin::hex::DisplayHex;
+
+use crate::descriptor::{Descriptor, DescriptorPublicKey};
+use crate::prelude::*;
+use crate::{hash256, ForEachKey, TranslateErr, Translator};
+
+/// The tag of the hashes of [`KeyCommitment`]
+const KEY_COMMITMENT_TAG: &[u8] = b"miniscript/KeyCommitment";
+
+/// A commitment of a party to its key, see [`KeyReveal`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct KeyCommitment([u8; 32]);
+
+impl KeyCommitment {
+    /// Commits to `key`, blinded with `nonce`
+    ///
+    /// The commitment is the BIP340-style tagged hash, with tag `miniscript/KeyCommitment`,
+    /// of `nonce || key`, the key being serialized as a string. The nonce must be random and
+    /// kept secret until the key is revealed, otherwise the key could be guessed from the
+    /// commitment.
+    pub fn new(key: &DescriptorPublicKey, nonce: &[u8; 32]) -> Self {
+        let tag = sha256::Hash::hash(KEY_COMMITMENT_TAG);
+        let mut engine = sha256::Hash::engine();
+        engine.input(tag.as_byte_array());
+        engine.input(tag.as_byte_array());
+        engine.input(nonce);
+        engine.input(key.to_string().as_bytes());
+        KeyCommitment(sha256::Hash::from_engine(engine).to_byte_array())
+    }
+
+    /// The bytes of the commitment
+    pub fn to_byte_array(self) -> [u8; 32] { self.0 }
+
+    /// Create a commitment from its bytes, e.g. received from another party
+    pub fn from_byte_array(bytes: [u8; 32]) -> Self { KeyCommitment(bytes) }
+}
+
+impl fmt::Display for KeyCommitment {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { fmt::LowerHex::fmt(&self.0.as_hex(), f) }
+}
+
+/// The opening of a [`KeyCommitment`]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct KeyReveal {
+    /// The key committed to
+    pub key: DescriptorPublicKey,
+    /// The nonce the key was blinded with
+    pub nonce: [u8; 32],
+}
+
+impl KeyReveal {
+    /// The commitment this reveal opens
+    pub fn commitment(&self) -> KeyCommitment { KeyCommitment::new(&self.key, &self.nonce) }
+}
+
+/// An error in a [`Ceremony`]
+#[derive(Debug)]
+pub enum CeremonyError {
+    /// No party of this name committed to a key
+    UnknownParty(String),
+    /// The party already revealed its key
+    AlreadyRevealed(String),
+    /// The reveal of the party doesn't open its commitment
+    CommitmentMismatch(String),
+    /// The party hasn't revealed its key yet
+    MissingReveal(String),
+    /// A hash of the template isn't a valid hex-encoded hash
+    Hash(bitcoin::hex::HexToArrayError),
+    /// The template couldn't be turned into a descriptor with the revealed keys
+    Template(crate::Error),
+    /// The key at this position of the descriptor isn't the one of the template
+    KeyMismatch {
+        /// The position of the key, in the order keys appear in the descriptor
+        position: usize,
+        /// The key of the template at this position
+        expected: Box<DescriptorPublicKey>,
+        /// The key of the descriptor at this position
+        found: Box<DescriptorPublicKey>,
+    },
+    /// The descriptor has other keys, or another structure, than the template
+    DescriptorMismatch,
+}
+
+impl fmt::Display for CeremonyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CeremonyError::UnknownParty(ref name) => write!(f, "unknown party {}", name),
+            CeremonyError::AlreadyRevealed(ref name) => {
+                write!(f, "party {} already revealed its key", name)
+            }
+            CeremonyError::CommitmentMismatch(ref name) => {
+                write!(f, "the key revealed by {} doesn't match its commitment", name)
+            }
+            CeremonyError::MissingReveal(ref name) => {
+                write!(f, "party {} hasn't revealed its key", name)
+            }
+            CeremonyError::Hash(ref e) => write!(f, "invalid hash in template: {}", e),
+            CeremonyError::Template(ref e) => write!(f, "invalid template: {}", e),
+            CeremonyError::KeyMismatch { position, ref expected, ref found } => write!(
+                f,
+                "key {} of the descriptor is {}, but the committed key is {}",
+                position, found, expected
+            ),
+            CeremonyError::DescriptorMismatch => {
+                f.write_str("the descriptor doesn't match the template")
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl error::Error for CeremonyError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            CeremonyError::Hash(e) => Some(e),
+            CeremonyError::Template(e) => Some(e),
+            CeremonyError::UnknownParty(_)
+            | CeremonyError::AlreadyRevealed(_)
+            | CeremonyError::CommitmentMismatch(_)
+            | CeremonyError::MissingReveal(_)
+            | CeremonyError::KeyMismatch { .. }
+            | CeremonyError::DescriptorMismatch => None,
+        }
+    }
+}
+
+/// The commitments and reveals of the parties to a descriptor
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Ceremony {
+    parties: BTreeMap<String, (KeyCommitment, Option<DescriptorPublicKey>)>,
+}
+
+impl Ceremony {
+    /// Starts a ceremony once the commitments of all the parties have been collected,
+    /// keyed by the names of the parties in the template
+    pub fn new(commitments: BTreeMap<String, KeyCommitment>) -> Self {
+        Ceremony {
+            parties: commitments
+                .into_iter()
+                .map(|(name, commitment)| (name, (commitment, None)))
+                .collect(),
+        }
+    }
+
+    /// Records the key revealed by `party`, if it opens the commitment of the party
+    pub fn reveal(&mut self, party: &str, reveal: &KeyReveal) -> Result<(), CeremonyError> {
+        let (commitment, key) = self
+            .parties
+            .get_mut(party)
+            .ok_or_else(|| CeremonyError::UnknownParty(party.to_owned()))?;
+        if key.is_some() {
+            return Err(CeremonyError::AlreadyRevealed(party.to_owned()));
+        }
+        if reveal.commitment() != *commitment {
+            return Err(CeremonyError::CommitmentMismatch(party.to_owned()));
+        }
+        *key = Some(reveal.key.clone());
+        Ok(())
+    }
+
+    /// The parties which haven't revealed their key yet
+    pub fn pending(&self) -> impl Iterator<Item = &str> {
+        self.parties
+            .iter()
+            .filter(|(_, (_, key))| key.is_none())
+            .map(|(name, _)| name.as_str())
+    }
+
+    /// Whether every party has revealed its key
+    pub fn is_complete(&self) -> bool { self.pending().next().is_none() }
+
+    /// The descriptor of `template` with the name of each party replaced by its revealed key
+    ///
+    /// # Errors
+    ///
+    /// If the template names a party which didn't commit or hasn't revealed its key, or isn't
+    /// a valid descriptor once the keys are replaced.
+    pub fn assemble(
+        &self,
+        template: &Descriptor<String>,
+    ) -> Result<Descriptor<DescriptorPublicKey>, CeremonyError> {
+        struct Revealed<'a>(&'a Ceremony);
+
+        impl Translator<String> for Revealed<'_> {
+            type TargetPk = DescriptorPublicKey;
+            type Error = CeremonyError;
+
+            fn pk(&mut self, name: &String) -> Result<DescriptorPublicKey, CeremonyError> {
+                match self.0.parties.get(name) {
+                    Some((_, Some(key))) => Ok(key.clone()),
+                    Some((_, None)) => Err(CeremonyError::MissingReveal(name.clone())),
+                    None => Err(CeremonyError::UnknownParty(name.clone())),
+                }
+            }
+
+            fn sha256(&mut self, hash: &String) -> Result<sha256::Hash, CeremonyError> {
+                sha256::Hash::from_str(hash).map_err(CeremonyError::Hash)
+            }
+
+            fn hash256(&mut self, hash: &String) -> Result<hash256::Hash, CeremonyError> {
+                hash256::Hash::from_str(hash).map_err(CeremonyError::Hash)
+            }
+
+            fn ripemd160(&mut self, hash: &String) -> Result<ripemd160::Hash, CeremonyError> {
+                ripemd160::Hash::from_str(hash).map_err(CeremonyError::Hash)
+            }
+
+            fn hash160(&mut self, hash: &String) -> Result<hash160::Hash, CeremonyError> {
+                hash160::Hash::from_str(hash).map_err(CeremonyError::Hash)
+            }
+        }
+
+        template
+            .translate_pk(&mut Revealed(self))
+            .map_err(|e| match e {
+                TranslateErr::TranslatorErr(e) => e,
+                TranslateErr::OuterError(e) => CeremonyError::Template(e),
+            })
+    }
+
+    /// Checks that `descriptor` is `template` with exactly the revealed keys in place
+    ///
+    /// # Errors
+    ///
+    /// If the template can't be [assembled](Ceremony::assemble), or differs from the
+    /// descriptor. The first key which differs is reported, if any.
+    pub fn verify(
+        &self,
+        template: &Descriptor<String>,
+        descriptor: &Descriptor<DescriptorPublicKey>,
+    ) -> Result<(), CeremonyError> {
+        let expected = self.assemble(template)?;
+        if expected == *descriptor {
+            return Ok(());
+        }
+        let keys = |desc: &Descriptor<DescriptorPublicKey>| {
+            let mut keys = vec![];
+            desc.for_each_key(|key| {
+                keys.push(key.clone());
+                true
+            });
+            keys
+        };
+        let mismatch = keys(&expected)
+            .into_iter()
+            .zip(keys(descriptor))
+            .enumerate()
+            .find(|(_, (expected, found))| expected != found);
+        match mismatch {
+            Some((position, (expected, found))) => Err(CeremonyError::KeyMismatch {
+                position,
+                expected: Box::new(expected),
+                found: Box::new(found),
+            }),
+            None => Err(CeremonyError::DescriptorMismatch),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEYS: [&str; 4] = [
+        "[d34db33f/48'/0'/0'/2']xpub6ERApfZwUNrhLCkDtcHTcxd75RbzS1ed54G1LkBUHQVHQKqhMkhgbmJbZRkrgZw4koxb5JaHWkY4ALHY2grBGRjaDMzQLcgJvLJuZZvRcEL/0/*",
+        "02c2fd50ceae468857bb7eb32ae9cd4083e6c7e42fbbec179d81134b3e3830586c",
+        "0257f4a2816338436cccabc43aa724cf6e69e43e84c3c8a305212761389dd73a8a",
+        "03500a2b48b0f66c8183cc0d6645ab21cc19c7fad8a33ff04d41c3ece54b0bc1c5",
+    ];
+
+    fn reveal(i: usize) -> KeyReveal {
+        KeyReveal { key: DescriptorPublicKey::from_str(KEYS[i]).unwrap(), nonce: [i as u8; 32] }
+    }
+
+    #[test]
+    fn ceremony() {
+        let names = ["alice", "bob", "carol"];
+        let commitments = names
+            .iter()
+            .enumerate()
+            .map(|(i, name)| (name.to_string(), reveal(i).commitment()))
+            .collect();
+        let mut ceremony = Ceremony::new(commitments);
+        let template = Descriptor::<String>::from_str("wsh(multi(2,alice,bob,carol))").unwrap();
+        assert_eq!(ceremony.pending().collect::<Vec<_>>(), names);
+        assert!(matches!(
+            ceremony.assemble(&template).unwrap_err(),
+            CeremonyError::MissingReveal(ref name) if name == "alice"
+        ));
+
+        // Carol tries to reveal another key than the one she committed to
+        assert!(matches!(
+            ceremony.reveal("carol", &reveal(3)).unwrap_err(),
+            CeremonyError::CommitmentMismatch(ref name) if name == "carol"
+        ));
+        assert!(matches!(
+            ceremony.reveal("dave", &reveal(3)).unwrap_err(),
+            CeremonyError::UnknownParty(ref name) if name == "dave"
+        ));
+        for (i, name) in names.iter().enumerate() {
+            ceremony.reveal(name, &reveal(i)).unwrap();
+        }
+        assert!(ceremony.is_complete());
+        assert!(matches!(
+            ceremony.reveal("bob", &reveal(1)).unwrap_err(),
+            CeremonyError::AlreadyRevealed(ref name) if name == "bob"
+        ));
+
+        let descriptor = Descriptor::<DescriptorPublicKey>::from_str(&format!(
+            "wsh(multi(2,{},{},{}))",
+            KEYS[0], KEYS[1], KEYS[2]
+        ))
+        .unwrap();
+        assert_eq!(ceremony.assemble(&template).unwrap(), descriptor);
+        ceremony.verify(&template, &descriptor).unwrap();
+
+        // The coordinator swaps carol's key for its own
+        let swapped = Descriptor::<DescriptorPublicKey>::from_str(&format!(
+            "wsh(multi(2,{},{},{}))",
+            KEYS[0], KEYS[1], KEYS[3]
+        ))
+        .unwrap();
+        assert!(matches!(
+            ceremony.verify(&template, &swapped).unwrap_err(),
+            CeremonyError::KeyMismatch { position: 2, ref expected, ref found }
+                if **expected == reveal(2).key && **found == reveal(3).key
+        ));
+        // or changes the threshold
+        let one_of_three = Descriptor::<DescriptorPublicKey>::from_str(&format!(
+            "wsh(multi(1,{},{},{}))",
+            KEYS[0], KEYS[1], KEYS[2]
+        ))
+        .unwrap();
+        assert!(matches!(
+            ceremony.verify(&template, &one_of_three).unwrap_err(),
+            CeremonyError::DescriptorMismatch
+        ));
+
+        let bad_hash =
+            Descriptor::<String>::from_str("wsh(and_v(v:pk(alice),sha256(bob)))").unwrap();
+        assert!(matches!(ceremony.assemble(&bad_hash).unwrap_err(), CeremonyError::Hash(_)));
+
+        let unknown = Descriptor::<String>::from_str("wsh(multi(2,alice,dave))").unwrap();
+        assert!(matches!(
+            ceremony.assemble(&unknown).unwrap_err(),
+            CeremonyError::UnknownParty(ref name) if name == "dave"
+        ));
+    }
+}
@@ -0,0 +1,375 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! # Bitcoin Core Differential Testing
+//!
+//! Compares the descriptors of this crate against the implementation of Bitcoin
+//! Core, for users who must certify that both agree on checksums, canonical
+//! forms, addresses and weights before deploying descriptors. The RPC calls are
+//! made through [`CoreRpc`], which users implement on top of the RPC client of
+//! their choice, and [`compare`] collects every disagreement in a [`Report`]
+//! rather than stopping at the first one.
+//!
+
+use core::fmt;
+use core::ops::Range;
+
+use bitcoin::{Network, Weight};
+
+use crate::descriptor::{ConversionError, Descriptor, DescriptorPublicKey};
+use crate::prelude::*;
+
+/// The result of the `getdescriptorinfo` RPC of Bitcoin Core
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct CoreDescriptorInfo {
+    /// The descriptor in canonical form, its checksum may be appended
+    pub descriptor: String,
+    /// The checksum of the descriptor as given
+    pub checksum: String,
+    /// Whether the descriptor has wildcards
+    pub is_range: bool,
+}
+
+/// Access to the RPC interface of a Bitcoin Core node
+pub trait CoreRpc {
+    /// The error of RPC calls
+    type Error;
+
+    /// Calls `getdescriptorinfo` with `descriptor`
+    fn get_descriptor_info(&self, descriptor: &str) -> Result<CoreDescriptorInfo, Self::Error>;
+
+    /// Calls `deriveaddresses` with `descriptor` and, for ranged descriptors, the
+    /// inclusive range of derivation indexes
+    fn derive_addresses(
+        &self,
+        descriptor: &str,
+        range: Option<(u32, u32)>,
+    ) -> Result<Vec<String>, Self::Error>;
+
+    /// The maximum weight Bitcoin Core expects a satisfaction of `descriptor` to add
+    /// to an input, as computed by [`Descriptor::max_weight_to_satisfy`]
+    ///
+    /// Bitcoin Core only reports it through its wallet, e.g. in the input weights
+    /// of `walletcreatefundedpsbt` with `solving_data`, so it isn't compared by
+    /// default.
+    fn max_weight_to_satisfy(&self, _descriptor: &str) -> Result<Option<Weight>, Self::Error> {
+        Ok(None)
+    }
+}
+
+/// A disagreement between this crate and Bitcoin Core
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Mismatch {
+    /// The checksums differ
+    Checksum {
+        /// The checksum of this crate
+        ours: String,
+        /// The checksum of Bitcoin Core
+        core: String,
+    },
+    /// The canonical forms of the descriptor differ, other than by the `'` or `h`
+    /// marking hardened derivation steps
+    Descriptor {
+        /// The descriptor as displayed by this crate
+        ours: String,
+        /// The descriptor as canonicalized by Bitcoin Core
+        core: String,
+    },
+    /// Only one implementation considers the descriptor ranged
+    IsRange {
+        /// Whether the descriptor has wildcards for this crate
+        ours: bool,
+        /// Whether the descriptor is ranged for Bitcoin Core
+        core: bool,
+    },
+    /// The addresses derived at an index differ, or only one implementation
+    /// derived an address at it
+    Address {
+        /// The derivation index
+        index: u32,
+        /// The address derived by this crate
+        ours: Option<String>,
+        /// The address derived by Bitcoin Core
+        core: Option<String>,
+    },
+    /// This crate couldn't derive a descriptor with an address format at an index
+    Derivation {
+        /// The derivation index
+        index: u32,
+        /// The error of this crate
+        error: ConversionError,
+    },
+    /// The maximum satisfaction weights differ
+    MaxWeightToSatisfy {
+        /// The weight computed by this crate, if any
+        ours: Option<Weight>,
+        /// The weight computed by Bitcoin Core
+        core: Weight,
+    },
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fn or_none<T: fmt::Display>(x: &Option<T>) -> String {
+            x.as_ref()
+                .map(ToString::to_string)
+                .unwrap_or_else(|| "none".to_owned())
+        }
+        match *self {
+            Mismatch::Checksum { ref ours, ref core } => {
+                write!(f, "checksum {} (core: {})", ours, core)
+            }
+            Mismatch::Descriptor { ref ours, ref core } => {
+                write!(f, "descriptor {} (core: {})", ours, core)
+            }
+            Mismatch::IsRange { ours, core } => write!(f, "ranged {} (core: {})", ours, core),
+            Mismatch::Address { index, ref ours, ref core } => {
+                write!(f, "address at {}: {} (core: {})", index, or_none(ours), or_none(core))
+            }
+            Mismatch::Derivation { index, ref error } => {
+                write!(f, "address at {} not derived: {}", index, error)
+            }
+            Mismatch::MaxWeightToSatisfy { ref ours, core } => {
+                write!(f, "max weight to satisfy {} (core: {})", or_none(ours), core)
+            }
+        }
+    }
+}
+
+/// The disagreements between this crate and Bitcoin Core on a descriptor
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Report {
+    /// The descriptor, with its checksum
+    pub descriptor: String,
+    /// Every disagreement found, in the order they were checked
+    pub mismatches: Vec<Mismatch>,
+}
+
+impl Report {
+    /// Whether both implementations agree on everything compared
+    pub fn is_compatible(&self) -> bool { self.mismatches.is_empty() }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_compatible() {
+            return write!(f, "{}: compatible", self.descriptor);
+        }
+        write!(f, "{}: {} mismatches", self.descriptor, self.mismatches.len())?;
+        for mismatch in &self.mismatches {
+            write!(f, "\n  {}", mismatch)?;
+        }
+        Ok(())
+    }
+}
+
+/// Compares `descriptor` between this crate and the Bitcoin Core node behind `rpc`
+///
+/// The checksum, the canonical form and whether the descriptor is ranged are
+/// compared through `getdescriptorinfo`. Addresses on `network` are compared
+/// through `deriveaddresses`, at each index of `range` for ranged descriptors and
+/// once otherwise, unless the descriptor has no address format. This crate
+/// failing to derive them is reported as [`Mismatch::Derivation`]. Bitcoin Core must run on a
+/// chain using the address format of `network`. Weights are compared if `rpc`
+/// reports them, see [`CoreRpc::max_weight_to_satisfy`].
+///
+/// Multipath descriptors must be split with [`Descriptor::into_single_descriptors`]
+/// first.
+///
+/// # Errors
+///
+/// If an RPC call fails, including when Bitcoin Core rejects the descriptor.
+pub fn compare<R: CoreRpc>(
+    rpc: &R,
+    descriptor: &Descriptor<DescriptorPublicKey>,
+    network: Network,
+    range: Range<u32>,
+) -> Result<Report, R::Error> {
+    let full = descriptor.to_string();
+    let ours = format!("{:#}", descriptor);
+    let checksum = full[ours.len()..].trim_start_matches('#').to_owned();
+    let mut mismatches = vec![];
+
+    let info = rpc.get_descriptor_info(&full)?;
+    if info.checksum != checksum {
+        mismatches.push(Mismatch::Checksum { ours: checksum, core: info.checksum });
+    }
+    // Core appends the checksum of the canonical form, which isn't compared, and
+    // may mark hardened steps differently
+    let core = info.descriptor.split('#').next().unwrap_or_default();
+    if core.replace('\'', "h") != ours.replace('\'', "h") {
+        mismatches.push(Mismatch::Descriptor { ours, core: core.to_owned() });
+    }
+    if info.is_range != descriptor.has_wildcard() {
+        mismatches.push(Mismatch::IsRange { ours: descriptor.has_wildcard(), core: info.is_range });
+    }
+
+    let range = if descriptor.has_wildcard() {
+        range
+    } else {
+        0..1
+    };
+    // Without addresses, there is nothing to compare `deriveaddresses` with
+    let has_address = !matches!(descriptor, Descriptor::Bare(_) | Descriptor::Data(_));
+    if has_address && !range.is_empty() {
+        let derived = range
+            .clone()
+            .map(|index| {
+                descriptor
+                    .at_derivation_index(index)
+                    .map_err(|error| Mismatch::Derivation { index, error })
+            })
+            .collect::<Result<Vec<_>, _>>();
+        match derived {
+            Ok(derived) => {
+                let core_range = if descriptor.has_wildcard() {
+                    Some((range.start, range.end - 1))
+                } else {
+                    None
+                };
+                let core = rpc.derive_addresses(&full, core_range)?;
+                for (n, index) in range.enumerate() {
+                    let ours = derived
+                        .get(n)
+                        .and_then(|desc| desc.address(network).ok())
+                        .map(|address| address.to_string());
+                    let core = core.get(n).cloned();
+                    if ours != core {
+                        mismatches.push(Mismatch::Address { index, ours, core });
+                    }
+                }
+            }
+            Err(mismatch) => mismatches.push(mismatch),
+        }
+    }
+
+    if let Some(core) = rpc.max_weight_to_satisfy(&full)? {
+        let ours = descriptor.max_weight_to_satisfy().ok();
+        if ours != Some(core) {
+            mismatches.push(Mismatch::MaxWeightToSatisfy { ours, core });
+        }
+    }
+
+    Ok(Report { descriptor: full, mismatches })
+}
+
+#[cfg(test)]
+mod tests {
+    use core::str::FromStr;
+
+    use super::*;
+
+    /// Answers as this crate would, except for the tampered fields
+    #[derive(Default)]
+    struct MockCore {
+        checksum: Option<&'static str>,
+        hardened_h: bool,
+        address: Option<(u32, &'static str)>,
+        weight: Option<Weight>,
+    }
+
+    impl CoreRpc for MockCore {
+        type Error = crate::Error;
+
+        fn get_descriptor_info(&self, descriptor: &str) -> Result<CoreDescriptorInfo, Self::Error> {
+            let desc = Descriptor::<DescriptorPublicKey>::from_str(descriptor)?;
+            let full = desc.to_string();
+            let checksum = full.split('#').nth(1).unwrap();
+            Ok(CoreDescriptorInfo {
+                descriptor: match self.hardened_h {
+                    true => full.replace('\'', "h"),
+                    false => full.clone(),
+                },
+                checksum: self.checksum.unwrap_or(checksum).to_owned(),
+                is_range: desc.has_wildcard(),
+            })
+        }
+
+        fn derive_addresses(
+            &self,
+            descriptor: &str,
+            range: Option<(u32, u32)>,
+        ) -> Result<Vec<String>, Self::Error> {
+            let desc = Descriptor::<DescriptorPublicKey>::from_str(descriptor)?;
+            let (start, end) = range.unwrap_or((0, 0));
+            let records =
+                desc.generate_address_batch_with_labels(start..end + 1, Network::Bitcoin, None)?;
+            Ok(records
+                .into_iter()
+                .map(|record| match self.address {
+                    Some((index, address)) if index == record.index => address.to_owned(),
+                    _ => record.address.to_string(),
+                })
+                .collect())
+        }
+
+        fn max_weight_to_satisfy(&self, _: &str) -> Result<Option<Weight>, Self::Error> {
+            Ok(self.weight)
+        }
+    }
+
+    #[test]
+    fn compare_with_core() {
+        let desc = Descriptor::<DescriptorPublicKey>::from_str("wpkh(xpub661MyMwAqRbcFtXgS5sYJABqqG9YLmC4Q1Rdap9gSE8NqtwybGhePY2gZ29ESFjqJoCu1Rupje8YtGqsefD265TMg7usUDFdp6W1EGMcet8/0/*)").unwrap();
+        let report = compare(&MockCore::default(), &desc, Network::Bitcoin, 0..3).unwrap();
+        assert!(report.is_compatible());
+        assert_eq!(report.descriptor, desc.to_string());
+        assert!(report.to_string().ends_with(": compatible"));
+
+        let weight = desc.max_weight_to_satisfy().unwrap();
+        let core = MockCore {
+            checksum: Some("00000000"),
+            address: Some((1, "bc1qwrong")),
+            weight: Some(weight + Weight::from_wu(1)),
+            ..Default::default()
+        };
+        let report = compare(&core, &desc, Network::Bitcoin, 0..3).unwrap();
+        let checksum = desc.to_string().split('#').nth(1).unwrap().to_owned();
+        let ours = desc
+            .at_derivation_index(1)
+            .unwrap()
+            .address(Network::Bitcoin)
+            .unwrap();
+        assert_eq!(
+            report.mismatches,
+            vec![
+                Mismatch::Checksum { ours: checksum, core: "00000000".to_owned() },
+                Mismatch::Address {
+                    index: 1,
+                    ours: Some(ours.to_string()),
+                    core: Some("bc1qwrong".to_owned()),
+                },
+                Mismatch::MaxWeightToSatisfy {
+                    ours: Some(weight),
+                    core: weight + Weight::from_wu(1),
+                },
+            ]
+        );
+        assert_eq!(report.to_string().lines().count(), 4);
+
+        // Descriptors without an address format only compare their info
+        let bare = Descriptor::<DescriptorPublicKey>::from_str(
+            "pk(02a34b99f22c790c4e36b2b3c2c35a36db06226e41c692fc82b8b56ac1c540c5bd)",
+        )
+        .unwrap();
+        let core = MockCore { address: Some((0, "bc1qwrong")), ..Default::default() };
+        assert!(compare(&core, &bare, Network::Bitcoin, 0..3)
+            .unwrap()
+            .is_compatible());
+
+        // Hardened steps may be marked differently
+        let hardened = Descriptor::<DescriptorPublicKey>::from_str("wpkh([d34db33f/84'/0'/0']xpub661MyMwAqRbcFtXgS5sYJABqqG9YLmC4Q1Rdap9gSE8NqtwybGhePY2gZ29ESFjqJoCu1Rupje8YtGqsefD265TMg7usUDFdp6W1EGMcet8/0/*)").unwrap();
+        let core = MockCore { hardened_h: true, ..Default::default() };
+        assert!(compare(&core, &hardened, Network::Bitcoin, 0..3)
+            .unwrap()
+            .is_compatible());
+
+        // Our derivation errors are reported
+        let report =
+            compare(&MockCore::default(), &desc, Network::Bitcoin, (1 << 31) - 1..(1 << 31) + 1)
+                .unwrap();
+        assert_eq!(
+            report.mismatches,
+            vec![Mismatch::Derivation { index: 1 << 31, error: ConversionError::HardenedChild }]
+        );
+    }
+}
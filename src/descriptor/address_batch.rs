@@ -0,0 +1,317 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! # Address Batches
+//!
+//! Derivation of batches of addresses from a descriptor, each along with the
+//! origins of the keys it is derived from and a label, as wallets record them
+//! when handing out addresses.
+//!
+
+use core::ops::Range;
+
+use bitcoin::{bip32, Address, Network};
+
+use super::{Descriptor, DescriptorPublicKey};
+use crate::prelude::*;
+use crate::{Error, ForEachKey};
+
+/// An address derived from a descriptor, see [`Descriptor::generate_address_batch_with_labels`]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct AddressRecord {
+    /// The derivation index the descriptor was derived at
+    pub index: u32,
+    /// The address
+    pub address: Address,
+    /// The master fingerprint and full derivation path of each key of the derived
+    /// descriptor, in the order the keys appear in the descriptor
+    pub key_origins: Vec<bip32::KeySource>,
+    /// The label of the address, if a label template was given
+    pub label: Option<String>,
+}
+
+impl Descriptor<DescriptorPublicKey> {
+    /// Derives the address of the descriptor at each index of `range`, along with the
+    /// origins of its keys and, if `label_template` is given, a label
+    ///
+    /// The label is the template with `{index}` replaced by the derivation index and
+    /// `{address}` by the address. Descriptors without wildcards are only derived once,
+    /// at index 0. The records of multipath descriptors are given path by path.
+    ///
+    /// # Errors
+    ///
+    /// If `range` is empty and the descriptor has wildcards, if the descriptor can't be
+    /// derived at an index of `range`, or has no address format, like `bare` descriptors.
+    pub fn generate_address_batch_with_labels(
+        &self,
+        range: Range<u32>,
+        network: Network,
+        label_template: Option<&str>,
+    ) -> Result<Vec<AddressRecord>, Error> {
+        let range = self.checked_derivation_range(range)?;
+
+        let mut records = Vec::with_capacity(range.len());
+        for (index, derived) in self.derive_paths(range)? {
+            let address = derived.address(network)?;
+            let mut key_origins = vec![];
+            derived.for_each_key(|key| {
+                let path = key
+                    .full_derivation_path()
+                    .expect("definite keys have a single derivation path");
+                key_origins.push((key.master_fingerprint(), path));
+                true
+            });
+            let label = label_template.map(|template| {
+                template
+                    .replace("{index}", &index.to_string())
+                    .replace("{address}", &address.to_string())
+            });
+            records.push(AddressRecord { index, address, key_origins, label });
+        }
+        Ok(records)
+    }
+}
+
+#[cfg(feature = "serde")]
+mod serde_impls {
+    use core::fmt;
+    use core::str::FromStr;
+
+    use bitcoin::address::NetworkUnchecked;
+    use serde::de::{Error as _, MapAccess, Visitor};
+    use serde::ser::{SerializeMap, SerializeSeq};
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    use super::*;
+
+    /// A key origin, serialized as a map with a `fingerprint` and a `path`
+    struct KeyOrigin(bip32::KeySource);
+
+    impl Serialize for KeyOrigin {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            let mut map = serializer.serialize_map(Some(2))?;
+            map.serialize_entry("fingerprint", &self.0 .0.to_string())?;
+            map.serialize_entry("path", &self.0 .1.to_string())?;
+            map.end()
+        }
+    }
+
+    impl<'de> Deserialize<'de> for KeyOrigin {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            let fields = BTreeMap::<String, String>::deserialize(deserializer)?;
+            let field = |name| {
+                fields
+                    .get(name)
+                    .ok_or_else(|| D::Error::missing_field(name))
+            };
+            let fingerprint = bip32::Fingerprint::from_str(field("fingerprint")?)
+                .map_err(|e| D::Error::custom(format_args!("fingerprint: {}", e)))?;
+            let path = bip32::DerivationPath::from_str(field("path")?)
+                .map_err(|e| D::Error::custom(format_args!("path: {}", e)))?;
+            Ok(KeyOrigin((fingerprint, path)))
+        }
+    }
+
+    /// The key origins of a record, serialized as a sequence of [`KeyOrigin`]s
+    struct KeyOrigins<'a>(&'a [bip32::KeySource]);
+
+    impl Serialize for KeyOrigins<'_> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            let mut seq = serializer.serialize_seq(Some(self.0.len()))?;
+            for origin in self.0 {
+                seq.serialize_element(&KeyOrigin(origin.clone()))?;
+            }
+            seq.end()
+        }
+    }
+
+    impl Serialize for AddressRecord {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            let mut map = serializer.serialize_map(None)?;
+            map.serialize_entry("index", &self.index)?;
+            map.serialize_entry("address", &self.address.to_string())?;
+            map.serialize_entry("key_origins", &KeyOrigins(&self.key_origins))?;
+            if let Some(ref label) = self.label {
+                map.serialize_entry("label", label)?;
+            }
+            map.end()
+        }
+    }
+
+    impl<'de> Deserialize<'de> for AddressRecord {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            struct RecordVisitor;
+
+            impl<'de> Visitor<'de> for RecordVisitor {
+                type Value = AddressRecord;
+
+                fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                    f.write_str("an address record")
+                }
+
+                fn visit_map<A: MapAccess<'de>>(
+                    self,
+                    mut map: A,
+                ) -> Result<AddressRecord, A::Error> {
+                    let mut index = None;
+                    let mut address = None;
+                    let mut key_origins = None;
+                    let mut label = None;
+                    while let Some(key) = map.next_key::<String>()? {
+                        match key.as_str() {
+                            "index" => index = Some(map.next_value::<u32>()?),
+                            "address" => {
+                                let s = map.next_value::<String>()?;
+                                // The network isn't recorded, the address is trusted to be for
+                                // the network it was derived for
+                                let unchecked =
+                                    Address::<NetworkUnchecked>::from_str(&s).map_err(|e| {
+                                        A::Error::custom(format_args!("address: {}", e))
+                                    })?;
+                                address = Some(unchecked.assume_checked());
+                            }
+                            "key_origins" => {
+                                let origins = map.next_value::<Vec<KeyOrigin>>()?;
+                                key_origins = Some(origins.into_iter().map(|o| o.0).collect());
+                            }
+                            "label" => label = Some(map.next_value::<String>()?),
+                            key => {
+                                return Err(A::Error::unknown_field(
+                                    key,
+                                    &["index", "address", "key_origins", "label"],
+                                ))
+                            }
+                        }
+                    }
+                    Ok(AddressRecord {
+                        index: index.ok_or_else(|| A::Error::missing_field("index"))?,
+                        address: address.ok_or_else(|| A::Error::missing_field("address"))?,
+                        key_origins: key_origins
+                            .ok_or_else(|| A::Error::missing_field("key_origins"))?,
+                        label,
+                    })
+                }
+            }
+
+            deserializer.deserialize_map(RecordVisitor)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::str::FromStr;
+
+    use super::*;
+    use crate::test_utils::xpub_desc;
+
+    #[test]
+    fn address_batch() {
+        let single = "02c6047f9441ed7d6d3045406e95c07cd85c778e4b8cef3ca7abac09b95c709ee5";
+        let wsh = xpub_desc(&format!(
+            "wsh(multi(1,[d34db33f/48'/0'/0'/2']XPUB/0/*,[01020304/0']{}))",
+            single
+        ));
+        let records = wsh
+            .generate_address_batch_with_labels(5..8, Network::Bitcoin, Some("recv #{index}"))
+            .unwrap();
+        assert_eq!(records.len(), 3);
+        for (record, index) in records.iter().zip(5..) {
+            assert_eq!(record.index, index);
+            assert_eq!(
+                record.address,
+                wsh.at_derivation_index(index)
+                    .unwrap()
+                    .address(Network::Bitcoin)
+                    .unwrap()
+            );
+            assert_eq!(record.label, Some(format!("recv #{}", index)));
+            assert_eq!(
+                record.key_origins,
+                vec![
+                    (
+                        bip32::Fingerprint::from_str("d34db33f").unwrap(),
+                        bip32::DerivationPath::from_str(&format!("m/48'/0'/0'/2'/0/{}", index))
+                            .unwrap()
+                    ),
+                    (
+                        bip32::Fingerprint::from_str("01020304").unwrap(),
+                        bip32::DerivationPath::from_str("m/0'").unwrap()
+                    ),
+                ]
+            );
+        }
+
+        let records = xpub_desc("wpkh(XPUB/0/*)")
+            .generate_address_batch_with_labels(0..2, Network::Testnet, Some("{address}"))
+            .unwrap();
+        assert_eq!(records[1].label, Some(records[1].address.to_string()));
+        assert!(records[1].address.to_string().starts_with("tb1"));
+
+        // Descriptors without wildcards are derived once, and without labels
+        let records = xpub_desc("pkh(XPUB/0/7)")
+            .generate_address_batch_with_labels(0..10, Network::Bitcoin, None)
+            .unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].label, None);
+
+        // Multipath descriptors are derived path by path
+        let records = xpub_desc("wpkh(XPUB/<0;1>/*)")
+            .generate_address_batch_with_labels(0..2, Network::Bitcoin, None)
+            .unwrap();
+        let paths: Vec<_> = records
+            .iter()
+            .map(|record| (record.index, record.key_origins[0].1.to_string()))
+            .collect();
+        assert_eq!(
+            paths,
+            [(0, "0/0"), (1, "0/1"), (0, "1/0"), (1, "1/1")]
+                .map(|(index, path)| (index, path.to_owned()))
+        );
+
+        assert_eq!(
+            xpub_desc("wpkh(XPUB/0/*)")
+                .generate_address_batch_with_labels(2..2, Network::Bitcoin, None)
+                .unwrap_err()
+                .to_string(),
+            "empty derivation range 2..2"
+        );
+        assert!(xpub_desc(&format!("pk({})", single))
+            .generate_address_batch_with_labels(0..2, Network::Bitcoin, None)
+            .is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn address_record_serde() {
+        use serde_test::{assert_tokens, Token};
+
+        let record = xpub_desc("wpkh([d34db33f/84'/0'/0']XPUB/0/*)")
+            .generate_address_batch_with_labels(3..4, Network::Bitcoin, Some("invoice {index}"))
+            .unwrap()
+            .remove(0);
+        let address = record.address.to_string();
+        let address: &'static str = Box::leak(address.into_boxed_str());
+        assert_tokens(
+            &record,
+            &[
+                Token::Map { len: None },
+                Token::Str("index"),
+                Token::U32(3),
+                Token::Str("address"),
+                Token::Str(address),
+                Token::Str("key_origins"),
+                Token::Seq { len: Some(1) },
+                Token::Map { len: Some(2) },
+                Token::Str("fingerprint"),
+                Token::Str("d34db33f"),
+                Token::Str("path"),
+                Token::Str("84'/0'/0'/0/3"),
+                Token::MapEnd,
+                Token::SeqEnd,
+                Token::Str("label"),
+                Token::Str("invoice 3"),
+                Token::MapEnd,
+            ],
+        );
+    }
+}
@@ -0,0 +1,361 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! # Descriptor Analysis
+//!
+//! A single report on a descriptor string, gathering its sizes, weights,
+//! spending paths, timelocks, sanity warnings and the resource usage of each of
+//! its scripts against the limits of their context, as a command line tool
+//! would print it.
+//!
+
+use core::fmt;
+use core::str::FromStr;
+
+use bitcoin::Weight;
+
+use super::{
+    DefiniteDescriptorKey, DerivationRangeError, Descriptor, DescriptorPublicKey, DescriptorType,
+    DetailedSpendPath, ShInner, SighashContext, TapTree, WshInner,
+};
+use crate::miniscript::limits::MAX_STACK_SIZE;
+use crate::policy::semantic::SpendPathCount;
+use crate::policy::Liftable;
+use crate::prelude::*;
+use crate::{AnalysisError, Error, ExtParams, Miniscript, MiniscriptKey, ScriptContext};
+
+/// Maximum number of spending paths listed in an [`Analysis`]
+pub const MAX_ANALYZED_SPEND_PATHS: usize = 1000;
+
+/// The usage of a resource against a limit of the script context
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ResourceUsage {
+    /// The worst case usage, `None` if the script can't be satisfied
+    pub used: Option<usize>,
+    /// The limit of the context, `None` if the context doesn't limit the resource
+    pub limit: Option<usize>,
+}
+
+impl ResourceUsage {
+    /// How much of the resource is left under the limit, zero if it's exceeded
+    ///
+    /// `None` if the resource isn't limited or the usage is unknown.
+    pub fn headroom(&self) -> Option<usize> { Some(self.limit?.saturating_sub(self.used?)) }
+
+    /// Whether the usage exceeds the limit
+    pub fn exceeds_limit(&self) -> bool {
+        matches!((self.used, self.limit), (Some(used), Some(limit)) if used > limit)
+    }
+}
+
+impl fmt::Display for ResourceUsage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.used {
+            Some(used) => write!(f, "{}", used)?,
+            None => f.write_str("-")?,
+        }
+        if let Some(limit) = self.limit {
+            write!(f, "/{}", limit)?;
+        }
+        Ok(())
+    }
+}
+
+/// The analysis of a script of a descriptor, see [`Analysis::scripts`]
+#[derive(Debug, PartialEq)]
+pub struct ScriptAnalysis {
+    /// The context of the script
+    pub context: SighashContext,
+    /// The size of the script in bytes, against the standardness limit of the
+    /// context or the consensus one if there is none
+    pub script_size: ResourceUsage,
+    /// The number of non-push opcodes executed by the largest satisfaction
+    pub ops: ResourceUsage,
+    /// The number of witness elements of the largest satisfaction, not counting
+    /// the script
+    pub witness_elems: ResourceUsage,
+    /// The maximum number of stack elements during the execution of a satisfaction
+    pub stack_size: ResourceUsage,
+    /// The number of signature operations, against the standardness limit of the
+    /// context, see [`Miniscript::sigop_count`]
+    pub sigops: ResourceUsage,
+    /// Every check of [`Miniscript::ext_check`] the script fails under
+    /// [`ExtParams::sane`]
+    pub warnings: Vec<AnalysisError>,
+}
+
+impl ScriptAnalysis {
+    fn new<Pk: MiniscriptKey, Ctx: ScriptContext>(
+        context: SighashContext,
+        ms: &Miniscript<Pk, Ctx>,
+    ) -> Self {
+        let limits = Ctx::limits();
+        let warnings = ms.failed_checks(&ExtParams::sane());
+        let stack_size = match (ms.ext.exec_stack_elem_count_sat, ms.ext.stack_elem_count_sat) {
+            (Some(exec), Some(initial)) => Some(exec + initial),
+            _ => None,
+        };
+        ScriptAnalysis {
+            context,
+            script_size: ResourceUsage {
+                used: Some(ms.script_size()),
+                limit: limits.max_standard_script_size.or(limits.max_script_size),
+            },
+            ops: ResourceUsage { used: ms.ext.ops.op_count(), limit: limits.max_ops },
+            witness_elems: ResourceUsage {
+                used: ms.ext.stack_elem_count_sat,
+                limit: limits.max_witness_elems,
+            },
+            stack_size: ResourceUsage { used: stack_size, limit: Some(MAX_STACK_SIZE) },
+            sigops: ResourceUsage {
+                used: Some(ms.sigop_count()),
+                limit: limits.max_standard_sigops,
+            },
+            warnings,
+        }
+    }
+}
+
+impl fmt::Display for ScriptAnalysis {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}: script {} bytes, ops {}, witness {} elements, stack {}, sigops {}",
+            self.context,
+            self.script_size,
+            self.ops,
+            self.witness_elems,
+            self.stack_size,
+            self.sigops
+        )?;
+        for warning in &self.warnings {
+            write!(f, "\n  warning: {}", warning)?;
+        }
+        Ok(())
+    }
+}
+
+/// A report on a descriptor, see [`analyze`]
+#[derive(Debug, PartialEq)]
+pub struct Analysis {
+    /// The analyzed descriptor, the first path of a multipath descriptor at
+    /// derivation index 0
+    pub descriptor: Descriptor<DefiniteDescriptorKey>,
+    /// The type of the descriptor
+    pub desc_type: DescriptorType,
+    /// The size of the scriptPubKey in bytes
+    pub script_pubkey_size: usize,
+    /// The maximum weight of a satisfaction, see [`Descriptor::max_weight_to_satisfy`]
+    ///
+    /// `None` if the descriptor can't be satisfied.
+    pub max_weight_to_satisfy: Option<Weight>,
+    /// The spending paths, with the keys which must sign each of them, see
+    /// [`Descriptor::spend_paths`]
+    ///
+    /// `None` if there are more than [`MAX_ANALYZED_SPEND_PATHS`] paths.
+    pub spend_paths: Option<Vec<DetailedSpendPath>>,
+    /// The relative timelocks of the descriptor, sorted and deduplicated
+    pub relative_timelocks: Vec<u32>,
+    /// The absolute timelocks of the descriptor, sorted and deduplicated
+    pub absolute_timelocks: Vec<u32>,
+    /// The analysis of each script of the descriptor, in the order of the tap
+    /// leaves for Taproot descriptors
+    pub scripts: Vec<ScriptAnalysis>,
+}
+
+impl Analysis {
+    /// Whether any script of the descriptor fails a sanity check
+    pub fn has_warnings(&self) -> bool { self.scripts.iter().any(|s| !s.warnings.is_empty()) }
+}
+
+impl fmt::Display for Analysis {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{}", self.descriptor)?;
+        write!(
+            f,
+            "type: {:?}, script_pubkey: {} bytes",
+            self.desc_type, self.script_pubkey_size
+        )?;
+        match self.max_weight_to_satisfy {
+            Some(weight) => writeln!(f, ", max satisfaction weight: {} wu", weight.to_wu())?,
+            None => writeln!(f, ", unsatisfiable")?,
+        }
+        for (name, locks) in [
+            ("relative", &self.relative_timelocks),
+            ("absolute", &self.absolute_timelocks),
+        ] {
+            if !locks.is_empty() {
+                write!(f, "{} timelocks:", name)?;
+                for lock in locks {
+                    write!(f, " {}", lock)?;
+                }
+                writeln!(f)?;
+            }
+        }
+        match self.spend_paths {
+            Some(ref paths) => {
+                writeln!(f, "spend paths:")?;
+                for path in paths {
+                    writeln!(f, "  {}", path)?;
+                }
+            }
+            None => writeln!(f, "spend paths: more than {}", MAX_ANALYZED_SPEND_PATHS)?,
+        }
+        if !self.scripts.is_empty() {
+            writeln!(f, "scripts:")?;
+            for script in &self.scripts {
+                for line in script.to_string().lines() {
+                    writeln!(f, "  {}", line)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Parses and analyzes a descriptor string, see [`Descriptor::analyze`]
+///
+/// # Errors
+///
+/// If the string isn't a valid descriptor or it can't be analyzed.
+pub fn analyze(s: &str) -> Result<Analysis, Error> {
+    Descriptor::<DescriptorPublicKey>::from_str(s)?.analyze()
+}
+
+impl Descriptor<DescriptorPublicKey> {
+    /// Analyzes the descriptor
+    ///
+    /// Ranged descriptors are analyzed at derivation index 0, and multipath ones on
+    /// their first path. The report gathers [`Descriptor::max_weight_to_satisfy`],
+    /// [`Descriptor::spend_paths`], the timelocks of the lifted policy and, for each
+    /// script, its resource usage against [`ScriptContext::limits`] with every
+    /// failed check of [`Miniscript::ext_check`] under [`ExtParams::sane`]. The descriptor parser rejects
+    /// scripts failing these checks, so warnings are only reported for descriptors
+    /// built from Miniscripts parsed with [`Miniscript::from_str_ext`].
+    ///
+    /// # Errors
+    ///
+    /// If the descriptor can't be derived or lifted to a semantic policy.
+    pub fn analyze(&self) -> Result<Analysis, Error> {
+        // A descriptor always has at least one path.
+        let descriptor = self.clone().into_single_descriptors()?.swap_remove(0);
+        let descriptor = descriptor
+            .at_derivation_index(0)
+            .map_err(|e| Error::DerivationRange(DerivationRangeError::Derivation(0, e)))?;
+
+        let policy = descriptor.lift()?;
+        let spend_paths = match descriptor.spend_path_count(MAX_ANALYZED_SPEND_PATHS)? {
+            SpendPathCount::Exact(_) => Some(descriptor.spend_paths()?.collect()),
+            SpendPathCount::ExceedsLimit => None,
+        };
+
+        let mut scripts = vec![];
+        match descriptor {
+            Descriptor::Bare(ref bare) => {
+                scripts.push(ScriptAnalysis::new(SighashContext::Legacy, bare.as_inner()))
+            }
+            Descriptor::Sh(ref sh) => match *sh.as_inner() {
+                ShInner::Wsh(ref wsh) => scripts.push(wsh_script(wsh.as_inner())),
+                ShInner::SortedMulti(ref smv) => scripts
+                    .push(ScriptAnalysis::new(SighashContext::Legacy, &smv.sorted_miniscript())),
+                ShInner::Ms(ref ms) => {
+                    scripts.push(ScriptAnalysis::new(SighashContext::Legacy, ms))
+                }
+                ShInner::Wpkh(..) => {}
+            },
+            Descriptor::Wsh(ref wsh) => scripts.push(wsh_script(wsh.as_inner())),
+            Descriptor::Tr(ref tr) => {
+                for (leaf, (_, ms)) in tr.tap_tree().iter().flat_map(TapTree::iter).enumerate() {
+                    scripts.push(ScriptAnalysis::new(SighashContext::TaprootScriptSpend(leaf), ms));
+                }
+            }
+            Descriptor::Pkh(..)
+            | Descriptor::Wpkh(..)
+            | Descriptor::RawTr(..)
+            | Descriptor::Data(..)
+            | Descriptor::Wother(..) => {}
+        }
+
+        Ok(Analysis {
+            desc_type: descriptor.desc_type(),
+            script_pubkey_size: descriptor.script_pubkey().len(),
+            max_weight_to_satisfy: descriptor.max_weight_to_satisfy().ok(),
+            spend_paths,
+            relative_timelocks: policy.relative_timelocks(),
+            absolute_timelocks: policy.absolute_timelocks(),
+            scripts,
+            descriptor,
+        })
+    }
+}
+
+fn wsh_script(inner: &WshInner<DefiniteDescriptorKey>) -> ScriptAnalysis {
+    match *inner {
+        WshInner::SortedMulti(ref smv) => {
+            ScriptAnalysis::new(SighashContext::SegwitV0, &smv.sorted_miniscript())
+        }
+        WshInner::Ms(ref ms) => ScriptAnalysis::new(SighashContext::SegwitV0, ms),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn analyze_descriptors() {
+        let analysis = analyze("wsh(or_d(multi(2,020000000000000000000000000000000000000000000000000000000000000002,030000000000000000000000000000000000000000000000000000000000000003),and_v(v:pkh(020000000000000000000000000000000000000000000000000000000000000004),older(144))))").unwrap();
+        assert_eq!(analysis.desc_type, DescriptorType::Wsh);
+        assert_eq!(analysis.script_pubkey_size, 34);
+        assert_eq!(analysis.relative_timelocks, vec![144]);
+        assert!(analysis.absolute_timelocks.is_empty());
+        let paths = analysis.spend_paths.as_ref().unwrap();
+        assert_eq!(paths.len(), 2);
+        assert_eq!(paths[0].keys.len(), 2);
+        assert_eq!(paths[1].keys.len(), 1);
+        assert_eq!(analysis.scripts.len(), 1);
+        let script = &analysis.scripts[0];
+        assert_eq!(script.context, SighashContext::SegwitV0);
+        assert_eq!(script.ops.limit, Some(201));
+        assert!(script.ops.headroom().unwrap() > 180);
+        assert!(script.warnings.is_empty());
+        assert!(!analysis.has_warnings());
+
+        // Sanity issues are reported instead of failing
+        let ms = Miniscript::<DescriptorPublicKey, crate::Segwitv0>::from_str_ext(
+            "and_v(v:pk(020000000000000000000000000000000000000000000000000000000000000002),or_i(older(10),older(20)))",
+            &crate::ExtParams::insane(),
+        )
+        .unwrap();
+        let analysis = Descriptor::new_wsh(ms).unwrap().analyze().unwrap();
+        assert_eq!(analysis.scripts[0].warnings, vec![AnalysisError::Malleable]);
+        assert!(analysis.has_warnings());
+        assert_eq!(analysis.relative_timelocks, vec![10, 20]);
+
+        let analysis = analyze("tr(020000000000000000000000000000000000000000000000000000000000000002,{pk(030000000000000000000000000000000000000000000000000000000000000003),pk(020000000000000000000000000000000000000000000000000000000000000004)})").unwrap();
+        assert_eq!(analysis.scripts.len(), 2);
+        assert_eq!(analysis.scripts[1].context, SighashContext::TaprootScriptSpend(1));
+        assert_eq!(analysis.scripts[1].ops.limit, None);
+        assert_eq!(analysis.spend_paths.unwrap().len(), 3);
+
+        // Key-only descriptors have no scripts
+        let analysis =
+            analyze("wpkh(xpub6ERApfZwUNrhLCkDtcHTcxd75RbzS1ed54G1LkBUHQVHQKqhMkhgbmJbZRkrgZw4koxb5JaHWkY4ALHY2grBGRjaDMzQLcgJvLJuZZvRcEL/<0;1>/*)").unwrap();
+        assert!(analysis.scripts.is_empty());
+        assert_eq!(analysis.spend_paths.unwrap().len(), 1);
+        assert_eq!(analysis.max_weight_to_satisfy, Some(Weight::from_wu(107)));
+
+        assert!(analyze("wsh(pk(A))").is_err());
+    }
+
+    #[test]
+    fn resource_usage() {
+        let usage = ResourceUsage { used: Some(10), limit: Some(8) };
+        assert_eq!(usage.headroom(), Some(0));
+        assert!(usage.exceeds_limit());
+        assert_eq!(usage.to_string(), "10/8");
+        let usage = ResourceUsage { used: None, limit: None };
+        assert_eq!(usage.headroom(), None);
+        assert!(!usage.exceeds_limit());
+        assert_eq!(usage.to_string(), "-");
+    }
+}
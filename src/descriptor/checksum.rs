@@ -8,8 +8,9 @@
 //! [BIP-380]: <https://github.com/bitcoin/bips/blob/master/bip-0380.mediawiki>
 
 use core::convert::TryFrom;
-use core::fmt;
 use core::iter::FromIterator;
+use core::ops::Range;
+use core::{cmp, fmt};
 
 use bech32::primitives::checksum::PackedFe32;
 use bech32::{Checksum, Fe32};
@@ -34,6 +35,7 @@ const CHAR_MAP: [u8; 95] = [
 
 /// Error validating descriptor checksum.
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
 pub enum Error {
     /// Character outside of descriptor charset.
     InvalidCharacter {
@@ -56,6 +58,17 @@ pub enum Error {
         /// The checksum that should have been there, assuming the string is valid.
         expected: [char; CHECKSUM_LENGTH],
     },
+    /// Checksum was invalid, and a single character of the string is wrong.
+    SingleCharacterError(ChecksumError),
+    /// The range of an edit is out of the bounds of the descriptor.
+    InvalidEditRange {
+        /// The start of the range.
+        start: usize,
+        /// The end of the range.
+        end: usize,
+        /// The length of the descriptor, excluding its checksum.
+        len: usize,
+    },
 }
 
 impl fmt::Display for Error {
@@ -78,6 +91,10 @@ impl fmt::Display for Error {
                 }
                 Ok(())
             }
+            Error::SingleCharacterError(ref e) => write!(f, "invalid checksum; {}", e),
+            Error::InvalidEditRange { start, end, len } => {
+                write!(f, "invalid edit range {}..{} (descriptor length {})", start, end, len)
+            }
         }
     }
 }
@@ -87,6 +104,114 @@ impl std::error::Error for Error {
     fn cause(&self) -> Option<&dyn std::error::Error> { None }
 }
 
+/// The location of a single wrong character of a descriptor string, see
+/// [`verify_or_suggest`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ChecksumError {
+    /// The position of the wrong character in the string, including its checksum.
+    pub position: usize,
+    /// The character which makes the checksum valid.
+    pub expected: char,
+}
+
+impl fmt::Display for ChecksumError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "expected '{}' at position {}", self.expected, self.position)
+    }
+}
+
+/// Verifies the checksum like [`verify_checksum`], and locates the error if a single
+/// character of the descriptor or its checksum is wrong.
+///
+/// If replacing exactly one character makes the checksum valid, the error is
+/// [`Error::SingleCharacterError`] with its position and the expected character.
+/// This is always the case for a single typo in descriptors of up to a few hundred
+/// characters; typos in longer ones may not be located unambiguously, and other
+/// errors, e.g. missing or swapped characters, are not located.
+pub fn verify_or_suggest(s: &str) -> Result<&str, Error> {
+    match verify_checksum(s) {
+        Err(Error::InvalidChecksum { actual, expected }) => {
+            let desc = &s[..s.len() - CHECKSUM_LENGTH - 1];
+            match locate_error(desc, actual, expected) {
+                Some(e) => Err(Error::SingleCharacterError(e)),
+                None => Err(Error::InvalidChecksum { actual, expected }),
+            }
+        }
+        res => res,
+    }
+}
+
+/// Finds the single character of `desc` or of its checksum `actual` which is wrong, if
+/// exactly one can be replaced to make the checksum valid.
+fn locate_error(
+    desc: &str,
+    actual: [char; CHECKSUM_LENGTH],
+    expected: [char; CHECKSUM_LENGTH],
+) -> Option<ChecksumError> {
+    let mut candidates = vec![];
+    // A wrong character of the checksum
+    let wrong: Vec<_> = (0..CHECKSUM_LENGTH)
+        .filter(|&i| actual[i] != expected[i])
+        .collect();
+    if let [i] = wrong[..] {
+        candidates.push(ChecksumError { position: desc.len() + 1 + i, expected: expected[i] });
+    }
+
+    // A wrong character of the descriptor changes the symbols of its group, and the
+    // checksum by their change shifted past the symbols after them and the checksum
+    let pack = |chars: [char; CHECKSUM_LENGTH]| {
+        chars.iter().try_fold(0, |acc, &ch| {
+            Fe32::from_char(ch)
+                .ok()
+                .map(|fe| (acc << 5) | u64::from(fe.to_u8()))
+        })
+    };
+    if let Some(target) = pack(actual).map(|actual| actual ^ pack(expected).expect("valid")) {
+        let bytes = desc.as_bytes();
+        let total_symbols = n_symbols(bytes.len());
+        for (g, group) in bytes.chunks(3).enumerate() {
+            let symbols_after = total_symbols - (g * 4 + group.len() + 1);
+            let shift = xpow(symbols_after + CHECKSUM_LENGTH);
+            let mut old = vec![];
+            for_each_symbol(group, |fe| old.push(fe));
+            for i in 0..group.len() {
+                for ch in (32..127u8).filter(|&ch| ch != b'#' && ch != group[i]) {
+                    let mut edited = group.to_vec();
+                    edited[i] = ch;
+                    let mut delta = 0;
+                    let mut idx = 0;
+                    for_each_symbol(&edited, |fe| {
+                        delta = polymod_step(delta, fe ^ old[idx]);
+                        idx += 1;
+                    });
+                    if mulmod(delta, shift) == target {
+                        candidates
+                            .push(ChecksumError { position: g * 3 + i, expected: char::from(ch) });
+                    }
+                }
+            }
+        }
+    }
+
+    match candidates[..] {
+        [e] => Some(e),
+        _ => None,
+    }
+}
+
+/// Appends the checksum to the descriptor string `s`, if it doesn't have one.
+///
+/// A checksum already present is verified, and `s` is then returned unchanged.
+pub fn add_checksum(s: &str) -> Result<String, Error> {
+    let desc = verify_checksum(s)?;
+    if desc.len() < s.len() {
+        return Ok(s.to_owned());
+    }
+    let mut eng = Engine::new();
+    eng.input_unchecked(desc.as_bytes());
+    Ok(format!("{}#{}", desc, eng.checksum()))
+}
+
 /// Helper function for `FromStr` for various descriptor types.
 ///
 /// Checks and verifies the checksum if it is present and returns the descriptor
@@ -202,6 +327,245 @@ impl Engine {
     }
 }
 
+/// Checksum of a descriptor string which can be updated as parts of it are replaced.
+///
+/// Replacing a substring by one of the same length, e.g. rotating a key, only
+/// processes the characters around the replaced substring, rather than the whole
+/// string. Replacements of a different length fall back to processing the whole
+/// edited string, see [`edit_and_rechecksum`] to only process the string after the edit.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct IncrementalEngine {
+    /// Polymod of the string, before the checksum is appended.
+    residue: u64,
+    /// Length of the string.
+    len: usize,
+}
+
+impl IncrementalEngine {
+    /// Computes the checksum state of a descriptor string, without its checksum.
+    pub fn new(s: &str) -> Result<Self, Error> {
+        check_charset(s)?;
+        let mut residue = 1;
+        for_each_symbol(s.as_bytes(), |fe| residue = polymod_step(residue, fe));
+        Ok(IncrementalEngine { residue, len: s.len() })
+    }
+
+    /// The length of the string this is the checksum of.
+    pub fn len(&self) -> usize { self.len }
+
+    /// Whether the string this is the checksum of is empty.
+    pub fn is_empty(&self) -> bool { self.len == 0 }
+
+    /// Updates the checksum of `s` for `range` being replaced by `replacement`.
+    ///
+    /// `s` must be the string the checksum currently is of. Its characters are
+    /// all checked, but only those around `range` are processed if `replacement`
+    /// has the same length as the range, and the whole edited string is
+    /// processed otherwise.
+    pub fn splice(&mut self, s: &str, range: Range<usize>, replacement: &str) -> Result<(), Error> {
+        if s.len() != self.len
+            || range.start > range.end
+            || range.end > s.len()
+            || !s.is_char_boundary(range.start)
+            || !s.is_char_boundary(range.end)
+        {
+            return Err(Error::InvalidEditRange {
+                start: range.start,
+                end: range.end,
+                len: s.len(),
+            });
+        }
+        check_charset(s)?;
+        check_charset(replacement)?;
+        if replacement.len() != range.len() {
+            let mut edited = String::with_capacity(s.len() - range.len() + replacement.len());
+            edited.push_str(&s[..range.start]);
+            edited.push_str(replacement);
+            edited.push_str(&s[range.end..]);
+            *self = IncrementalEngine::new(&edited)?;
+            return Ok(());
+        }
+        if range.is_empty() {
+            return Ok(());
+        }
+
+        // The symbols of the groups of 3 characters the range overlaps
+        let start = range.start - range.start % 3;
+        let end = cmp::min(s.len(), range.end + (3 - range.end % 3) % 3);
+        let mut old = vec![];
+        for_each_symbol(&s.as_bytes()[start..end], |fe| old.push(fe));
+        let mut new_region = Vec::with_capacity(end - start);
+        new_region.extend_from_slice(&s.as_bytes()[start..range.start]);
+        new_region.extend_from_slice(replacement.as_bytes());
+        new_region.extend_from_slice(&s.as_bytes()[range.end..end]);
+        let mut delta = 0;
+        let mut idx = 0;
+        for_each_symbol(&new_region, |fe| {
+            delta = polymod_step(delta, fe ^ old[idx]);
+            idx += 1;
+        });
+
+        // The polymod is linear, so the change of the symbols only needs to be
+        // shifted past the symbols after them.
+        let symbols_after = n_symbols(s.len()) - (start / 3 * 4 + old.len());
+        self.residue ^= mulmod(delta, xpow(symbols_after));
+        Ok(())
+    }
+
+    /// Obtains the checksum characters of the string.
+    pub fn checksum_chars(&self) -> [char; CHECKSUM_LENGTH] {
+        let mut residue = self.residue;
+        for _ in 0..CHECKSUM_LENGTH {
+            residue = polymod_step(residue, 0);
+        }
+        residue ^= 1;
+
+        let mut chars = [0 as char; CHECKSUM_LENGTH];
+        for (i, checksum_ch) in chars.iter_mut().enumerate() {
+            let unpacked = (residue >> (5 * (CHECKSUM_LENGTH - 1 - i))) & 31;
+            *checksum_ch = Fe32::try_from(unpacked)
+                .expect("5 bits fits in an fe32")
+                .to_char();
+        }
+        chars
+    }
+
+    /// Obtains the checksum of the string.
+    pub fn checksum(&self) -> String { String::from_iter(self.checksum_chars().iter().copied()) }
+}
+
+/// Replaces `range` of the descriptor string `s` by `replacement`, and returns the edited
+/// descriptor with its checksum.
+///
+/// If `s` has a checksum it is verified, `range` then indexes into `s` without it. The
+/// characters before `range` are only processed once: the checksum state at the start of the
+/// group of 3 characters `range` starts in is resumed both to verify `s` and to checksum the
+/// edited descriptor.
+pub fn edit_and_rechecksum(
+    s: &str,
+    range: Range<usize>,
+    replacement: &str,
+) -> Result<String, Error> {
+    check_charset(s)?;
+    check_charset(replacement)?;
+    if let Some(pos) = replacement.find('#') {
+        return Err(Error::InvalidCharacter { ch: '#', pos });
+    }
+    let (desc, checksum) = match s.rfind('#') {
+        Some(pos) => (&s[..pos], Some(&s[pos + 1..])),
+        None => (s, None),
+    };
+    if range.start > range.end || range.end > desc.len() {
+        return Err(Error::InvalidEditRange {
+            start: range.start,
+            end: range.end,
+            len: desc.len(),
+        });
+    }
+
+    let start = range.start - range.start % 3;
+    let mut prefix = 1;
+    for_each_symbol(&desc.as_bytes()[..start], |fe| prefix = polymod_step(prefix, fe));
+
+    if let Some(checksum) = checksum {
+        if checksum.len() != CHECKSUM_LENGTH {
+            return Err(Error::InvalidChecksumLength {
+                actual: checksum.len(),
+                expected: CHECKSUM_LENGTH,
+            });
+        }
+        let mut residue = prefix;
+        for_each_symbol(&desc.as_bytes()[start..], |fe| residue = polymod_step(residue, fe));
+        let expected = IncrementalEngine { residue, len: desc.len() }.checksum_chars();
+        let mut actual = ['_'; CHECKSUM_LENGTH];
+        for (act, ch) in actual.iter_mut().zip(checksum.chars()) {
+            *act = ch;
+        }
+        if expected != actual {
+            return Err(Error::InvalidChecksum { actual, expected });
+        }
+    }
+
+    let mut edited =
+        String::with_capacity(desc.len() - range.len() + replacement.len() + 1 + CHECKSUM_LENGTH);
+    edited.push_str(&desc[..range.start]);
+    edited.push_str(replacement);
+    edited.push_str(&desc[range.end..]);
+    let mut residue = prefix;
+    for_each_symbol(&edited.as_bytes()[start..], |fe| residue = polymod_step(residue, fe));
+    let checksum = IncrementalEngine { residue, len: edited.len() }.checksum_chars();
+    edited.push('#');
+    edited.extend(checksum.iter());
+    Ok(edited)
+}
+
+fn check_charset(s: &str) -> Result<(), Error> {
+    for (pos, ch) in s.char_indices() {
+        if !(32..127).contains(&u32::from(ch)) {
+            return Err(Error::InvalidCharacter { ch, pos });
+        }
+    }
+    Ok(())
+}
+
+/// Feeds the symbols of the checksummed data of `s` to `f`: one symbol per character, and
+/// one more for each group of 3 characters. `s` must start at a group boundary.
+fn for_each_symbol<F: FnMut(u8)>(s: &[u8], mut f: F) {
+    for group in s.chunks(3) {
+        let mut cls = 0;
+        for ch in group {
+            let pos = CHAR_MAP[usize::from(*ch) - 32];
+            f(pos & 31);
+            cls = cls * 3 + (pos >> 5);
+        }
+        f(cls);
+    }
+}
+
+/// The number of checksummed symbols of a string of `len` characters.
+fn n_symbols(len: usize) -> usize { len + (len + 2) / 3 }
+
+/// Multiplies the packed polynomial `c` by x, adds `fe`, and reduces it by the generator.
+fn polymod_step(c: u64, fe: u8) -> u64 {
+    let c0 = c >> 35;
+    let mut c = ((c & 0x7_ffff_ffff) << 5) ^ u64::from(fe);
+    for (i, gen) in GEN.iter().enumerate() {
+        if (c0 >> i) & 1 == 1 {
+            c ^= gen;
+        }
+    }
+    c
+}
+
+/// Multiplies two packed polynomials modulo the generator.
+fn mulmod(a: u64, b: u64) -> u64 {
+    let mut acc = 0;
+    let mut a_xj = a;
+    for j in 0..CHECKSUM_LENGTH {
+        let b_j = Fe32::try_from((b >> (5 * j)) & 31).expect("5 bits fits in an fe32");
+        for i in 0..CHECKSUM_LENGTH {
+            let a_i = Fe32::try_from((a_xj >> (5 * i)) & 31).expect("5 bits fits in an fe32");
+            acc ^= u64::from((a_i * b_j).to_u8()) << (5 * i);
+        }
+        a_xj = polymod_step(a_xj, 0);
+    }
+    acc
+}
+
+/// Computes x^n modulo the generator, as a packed polynomial.
+fn xpow(mut n: usize) -> u64 {
+    let mut result = 1;
+    let mut base = 1 << 5;
+    while n > 0 {
+        if n & 1 == 1 {
+            result = mulmod(result, base);
+        }
+        base = mulmod(base, base);
+        n >>= 1;
+    }
+    result
+}
+
 /// The Output Script Descriptor checksum algorithm, defined in [BIP-380].
 ///
 /// [BIP-380]: <https://github.com/bitcoin/bips/blob/master/bip-0380.mediawiki>
@@ -292,6 +656,138 @@ mod test {
         );
     }
 
+    #[test]
+    fn incremental_checksum() {
+        let desc = "sh(multi(2,[00000000/111'/222]xprvA1RpRA33e1JQ7ifknakTFpgNXPmW2YvmhqLQYMmrj4xJXXWYpDPS3xz7iAxn8L39njGVyuoseXzU6rcxFLJ8HFsTjSyQbLYnMpCqE2VbFWc,xprv9uPDJpEQgRQfDcW7BkF7eTya6RPxXeJCqCJGHuCJ4GiRVLzkTXBAJMu2qaMWPrS7AANYqdq6vcBcBUdJCVVFceUvJFjaPdGZ2y9WACViL4L/0))";
+        let mut eng = IncrementalEngine::new(desc).unwrap();
+        assert_eq!(eng.checksum(), "ggrsrxfy");
+        assert_eq!(eng.len(), desc.len());
+
+        // Every alignment of equal length replacements, and other lengths
+        for start in [
+            0,
+            1,
+            2,
+            3,
+            4,
+            5,
+            31,
+            32,
+            33,
+            desc.len() - 3,
+            desc.len() - 2,
+            desc.len() - 1,
+        ] {
+            for len in [0, 1, 2, 3, 4, 7] {
+                let end = cmp::min(start + len, desc.len());
+                let reversed: String = desc[start..end].chars().rev().collect();
+                for replacement in [reversed.as_str(), "xpub"] {
+                    let edited = format!("{}{}{}", &desc[..start], replacement, &desc[end..]);
+                    let mut spliced = eng;
+                    spliced.splice(desc, start..end, replacement).unwrap();
+                    let mut full = Engine::new();
+                    full.input(&edited).unwrap();
+                    assert_eq!(spliced.checksum(), full.checksum(), "{}..{}", start, end);
+                    assert_eq!(spliced.len(), edited.len());
+                }
+            }
+        }
+
+        // Rotating the first key
+        let old_key = "xprvA1RpRA33e1JQ7ifknakTFpgNXPmW2YvmhqLQYMmrj4xJXXWYpDPS3xz7iAxn8L39njGVyuoseXzU6rcxFLJ8HFsTjSyQbLYnMpCqE2VbFWc";
+        let new_key = "xpub6ERApfZwUNrhLCkDtcHTcxd75RbzS1ed54G1LkBUHQVHQKqhMkhgbmJbZRkrgZw4koxb5JaHWkY4ALHY2grBGRjaDMzQLcgJvLJuZZvRcEL";
+        let start = desc.find(old_key).unwrap();
+        let edited = edit_and_rechecksum(
+            &format!("{}#ggrsrxfy", desc),
+            start..start + old_key.len(),
+            new_key,
+        )
+        .unwrap();
+        assert_eq!(verify_checksum(&edited).unwrap(), desc.replace(old_key, new_key));
+        eng.splice(desc, start..start + old_key.len(), new_key)
+            .unwrap();
+        assert_eq!(format!("{}#{}", desc.replace(old_key, new_key), eng.checksum()), edited);
+
+        assert!(edit_and_rechecksum(&format!("{}#ggrsrxfx", desc), 0..2, "ab").is_err());
+        assert_eq!(
+            edit_and_rechecksum(desc, 10..desc.len() + 1, "ab"),
+            Err(Error::InvalidEditRange { start: 10, end: desc.len() + 1, len: desc.len() })
+        );
+        assert!(edit_and_rechecksum(desc, 0..2, "a#").is_err());
+        assert!(eng.splice("raw(deadbeef)", 0..3, "sh(").is_err());
+        let edited = edit_and_rechecksum("raw(deadbeef)#89f8spxm", 4..12, "").unwrap();
+        assert_eq!(
+            edited,
+            format!("raw()#{}", IncrementalEngine::new("raw()").unwrap().checksum())
+        );
+    }
+
+    #[test]
+    fn incremental_checksum_rejects_invalid_characters() {
+        let mut eng = IncrementalEngine::new("pk(AB)").unwrap();
+        assert_eq!(
+            eng.splice("pk(\u{e9})", 3..5, "AB"),
+            Err(Error::InvalidCharacter { ch: '\u{e9}', pos: 3 })
+        );
+        assert_eq!(
+            eng.splice("pk(AB)", 3..5, "\u{7}\u{7}"),
+            Err(Error::InvalidCharacter { ch: '\u{7}', pos: 0 })
+        );
+        assert_eq!(eng, IncrementalEngine::new("pk(AB)").unwrap());
+    }
+
+    #[test]
+    fn locate_single_character_errors() {
+        let desc = "wpkh(tprv8ZgxMBicQKsPdpkqS7Eair4YxjcuuvDPNYmKX3sCniCf16tHEVrjjiSXEkFRnUH77yXc6ZcwHHcLNfjdi5qUvw3VDfgYiH5mNsj5izuiu2N/1/2/*)#tqz0nc62";
+        assert_eq!(verify_or_suggest(desc), verify_checksum(desc));
+
+        // Typos in the descriptor and in the checksum
+        for (position, typo) in [
+            (0, 'W'),
+            (1, 'x'),
+            (17, '0'),
+            (63, ']'),
+            (127, '.'),
+            (129, 'p'),
+        ] {
+            let mut edited = desc.to_owned();
+            edited.replace_range(position..position + 1, &typo.to_string());
+            let expected = char::from(desc.as_bytes()[position]);
+            assert_eq!(
+                verify_or_suggest(&edited),
+                Err(Error::SingleCharacterError(ChecksumError { position, expected }))
+            );
+        }
+        assert_eq!(verify_or_suggest("raw(deadbeef)#89f8spxm").unwrap(), "raw(deadbeef)");
+        assert_eq!(
+            verify_or_suggest("raw(dEadbeef)#89f8spxm")
+                .unwrap_err()
+                .to_string(),
+            "invalid checksum; expected 'e' at position 5"
+        );
+
+        // Other errors aren't located
+        assert!(matches!(
+            verify_or_suggest("raw(dEadbeeF)#89f8spxm"),
+            Err(Error::InvalidChecksum { .. })
+        ));
+        assert!(matches!(
+            verify_or_suggest("raw(dedbeef)#89f8spxm"),
+            Err(Error::InvalidChecksum { .. })
+        ));
+        assert!(matches!(
+            verify_or_suggest("raw(deadbeef)#89f8spx"),
+            Err(Error::InvalidChecksumLength { .. })
+        ));
+    }
+
+    #[test]
+    fn add_missing_checksum() {
+        assert_eq!(add_checksum("raw(deadbeef)").unwrap(), "raw(deadbeef)#89f8spxm");
+        assert_eq!(add_checksum("raw(deadbeef)#89f8spxm").unwrap(), "raw(deadbeef)#89f8spxm");
+        assert!(add_checksum("raw(deadbeef)#89f8spxx").is_err());
+    }
+
     #[test]
     fn test_desc_checksum_invalid_character() {
         let sparkle_heart = vec![240, 159, 146, 150];
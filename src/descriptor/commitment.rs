@@ -0,0 +1,155 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! # Script Commitments
+//!
+//! Hashes of the script pubkeys derived from a descriptor, which two
+//! deployments can exchange to detect they were accidentally configured with
+//! the same descriptor, without revealing their scripts to each other or to
+//! anyone watching the chain.
+//!
+
+use core::fmt;
+use core::ops::Range;
+
+use bitcoin::hashes::{sha256, Hash, HashEngine};
+use bitcoin::hex::DisplayHex;
+use bitcoin::Script;
+
+use super::{Descriptor, DescriptorPublicKey};
+use crate::prelude::*;
+use crate::Error;
+
+/// The tag of the hashes of [`ScriptCommitment`]
+const SCRIPT_COMMITMENT_TAG: &[u8] = b"miniscript/ScriptCommitment";
+
+/// A salted hash of a script pubkey, see [`Descriptor::script_commitments`]
+///
+/// Its `PartialEq` implementation isn't constant time, use [`ScriptCommitment::ct_eq`]
+/// to compare commitments received from another party.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ScriptCommitment([u8; 32]);
+
+impl ScriptCommitment {
+    /// Commits to `script_pubkey`, salted with `salt`
+    ///
+    /// The commitment is the BIP340-style tagged hash, with tag
+    /// `miniscript/ScriptCommitment`, of `sha256(salt) || script_pubkey`.
+    pub fn new(script_pubkey: &Script, salt: &[u8]) -> Self {
+        let tag = sha256::Hash::hash(SCRIPT_COMMITMENT_TAG);
+        let mut engine = sha256::Hash::engine();
+        engine.input(tag.as_byte_array());
+        engine.input(tag.as_byte_array());
+        engine.input(sha256::Hash::hash(salt).as_byte_array());
+        engine.input(script_pubkey.as_bytes());
+        ScriptCommitment(sha256::Hash::from_engine(engine).to_byte_array())
+    }
+
+    /// The bytes of the commitment
+    pub fn to_byte_array(self) -> [u8; 32] { self.0 }
+
+    /// Create a commitment from its bytes, e.g. received from another deployment
+    pub fn from_byte_array(bytes: [u8; 32]) -> Self { ScriptCommitment(bytes) }
+
+    /// Compares two commitments in constant time
+    pub fn ct_eq(&self, other: &ScriptCommitment) -> bool {
+        let diff = self
+            .0
+            .iter()
+            .zip(other.0.iter())
+            .fold(0u8, |acc, (a, b)| acc | (a ^ b));
+        diff == 0
+    }
+
+    /// Whether any commitment of `ours` is also in `theirs`
+    ///
+    /// Every pair is compared, so that the time taken only depends on the number
+    /// of commitments and not on which of them match.
+    pub fn any_match(ours: &[ScriptCommitment], theirs: &[ScriptCommitment]) -> bool {
+        ours.iter()
+            .flat_map(|a| theirs.iter().map(move |b| a.ct_eq(b)))
+            .fold(false, |acc, eq| acc | eq)
+    }
+}
+
+impl fmt::Display for ScriptCommitment {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { fmt::LowerHex::fmt(&self.0.as_hex(), f) }
+}
+
+impl Descriptor<DescriptorPublicKey> {
+    /// Commits to the script pubkeys derived at the indexes in `range`, for every path
+    /// of a multipath descriptor, with an empty salt
+    ///
+    /// See [`Descriptor::script_commitments_salted`].
+    pub fn script_commitments(&self, range: Range<u32>) -> Result<Vec<ScriptCommitment>, Error> {
+        self.script_commitments_salted(range, &[])
+    }
+
+    /// Commits to the script pubkeys derived at the indexes in `range`, for every path
+    /// of a multipath descriptor
+    ///
+    /// The commitments are in path then index order. Descriptors without wildcards are
+    /// only derived once per path. Without a salt agreed upon by the deployments
+    /// comparing their commitments, anyone knowing a script pubkey, e.g. from the chain,
+    /// can recognize its commitment.
+    ///
+    /// # Errors
+    ///
+    /// If `range` is empty and the descriptor has wildcards, or if it can't be derived.
+    pub fn script_commitments_salted(
+        &self,
+        range: Range<u32>,
+        salt: &[u8],
+    ) -> Result<Vec<ScriptCommitment>, Error> {
+        let range = self.checked_derivation_range(range)?;
+        Ok(self
+            .derive_paths(range)?
+            .into_iter()
+            .map(|(_, derived)| ScriptCommitment::new(&derived.script_pubkey(), salt))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::descriptor::DerivationRangeError;
+    use crate::test_utils::xpub_desc;
+
+    #[test]
+    fn script_commitments() {
+        let multipath = xpub_desc("wpkh(XPUB/<0;1>/*)");
+        let commitments = multipath.script_commitments(0..5).unwrap();
+        assert_eq!(commitments.len(), 10);
+
+        let receive = xpub_desc("wpkh(XPUB/0/*)")
+            .script_commitments(3..4)
+            .unwrap();
+        assert!(commitments[3].ct_eq(&receive[0]));
+        assert!(!commitments[4].ct_eq(&receive[0]));
+        assert!(ScriptCommitment::any_match(&commitments, &receive));
+
+        // Other deployments with the same keys in other descriptors or salts don't match
+        let other = xpub_desc("tr(XPUB/<0;1>/*)")
+            .script_commitments(0..5)
+            .unwrap();
+        assert!(!ScriptCommitment::any_match(&commitments, &other));
+        let salted = multipath.script_commitments_salted(0..5, b"salt").unwrap();
+        assert!(!ScriptCommitment::any_match(&commitments, &salted));
+
+        let commitment = commitments[0];
+        assert_eq!(ScriptCommitment::from_byte_array(commitment.to_byte_array()), commitment);
+        assert_eq!(commitment.to_string().len(), 64);
+
+        assert_eq!(
+            xpub_desc("wpkh(XPUB/0)")
+                .script_commitments(0..100)
+                .unwrap()
+                .len(),
+            1
+        );
+        assert!(matches!(
+            multipath.script_commitments(5..5),
+            Err(Error::DerivationRange(DerivationRangeError::EmptyRange(_)))
+        ));
+    }
+}
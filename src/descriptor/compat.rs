@@ -0,0 +1,166 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! # Compatibility Parsing
+//!
+//! Lenient parsing of descriptor strings produced by other libraries, which
+//! fixes the known quirks of their output rather than rejecting it.
+//!
+
+use core::fmt;
+use core::str::FromStr;
+
+use super::checksum::verify_checksum;
+use super::Descriptor;
+use crate::expression::ParseTreeError;
+use crate::prelude::*;
+use crate::{Error, FromStrKey};
+
+/// Hex strings of these lengths are fingerprints, hashes or public keys.
+const HEX_LENGTHS: [usize; 5] = [8, 40, 64, 66, 130];
+
+/// Characters separating the arguments of a descriptor, and the steps of a
+/// derivation path.
+const SEPARATORS: &[char] = &['(', ')', ',', '{', '}', '[', ']', '/'];
+
+/// A quirk fixed by [`Descriptor::from_str_compat`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum CompatFix {
+    /// The descriptor had no checksum
+    MissingChecksum,
+    /// Whitespace was removed
+    Whitespace,
+    /// Hex keys, hashes or fingerprints were converted to lowercase
+    UppercaseHex,
+    /// Commas before a closing parenthesis or brace were removed
+    TrailingComma,
+}
+
+impl fmt::Display for CompatFix {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CompatFix::MissingChecksum => f.write_str("added missing checksum"),
+            CompatFix::Whitespace => f.write_str("removed whitespace"),
+            CompatFix::UppercaseHex => f.write_str("converted uppercase hex to lowercase"),
+            CompatFix::TrailingComma => f.write_str("removed trailing commas"),
+        }
+    }
+}
+
+impl<Pk: FromStrKey> Descriptor<Pk> {
+    /// Parses a descriptor produced by another library, fixing the known quirks
+    /// of their output
+    ///
+    /// Returns the descriptor along with the fixes which were applied, the
+    /// canonical form of the descriptor being its `Display` output. A checksum
+    /// is still verified if present, against the descriptor stripped of its
+    /// whitespace.
+    ///
+    /// # Errors
+    ///
+    /// If the checksum is invalid, or the descriptor is invalid once fixed.
+    pub fn from_str_compat(s: &str) -> Result<(Self, Vec<CompatFix>), Error> {
+        let mut fixes = vec![];
+
+        let stripped: String = s.chars().filter(|c| !c.is_whitespace()).collect();
+        if stripped.len() != s.len() {
+            fixes.push(CompatFix::Whitespace);
+        }
+
+        let mut desc = verify_checksum(&stripped)
+            .map_err(|e| Error::ParseTree(ParseTreeError::from(e)))?
+            .to_owned();
+        if desc.len() == stripped.len() {
+            fixes.push(CompatFix::MissingChecksum);
+        }
+
+        if desc.contains(",)") || desc.contains(",}") {
+            while desc.contains(",)") || desc.contains(",}") {
+                desc = desc.replace(",)", ")").replace(",}", "}");
+            }
+            fixes.push(CompatFix::TrailingComma);
+        }
+
+        if let Some(lowercase) = lowercase_hex(&desc) {
+            desc = lowercase;
+            fixes.push(CompatFix::UppercaseHex);
+        }
+
+        Ok((Descriptor::from_str(&desc)?, fixes))
+    }
+}
+
+/// Converts the hex keys, hashes and fingerprints of `s` to lowercase, or
+/// returns `None` if there are none in uppercase.
+fn lowercase_hex(s: &str) -> Option<String> {
+    let mut ret = String::with_capacity(s.len());
+    let mut changed = false;
+    for token in s.split_inclusive(SEPARATORS) {
+        let body = token.trim_end_matches(SEPARATORS);
+        if HEX_LENGTHS.contains(&body.len())
+            && body.bytes().all(|b| b.is_ascii_hexdigit())
+            && body.bytes().any(|b| b.is_ascii_uppercase())
+        {
+            ret.push_str(&body.to_ascii_lowercase());
+            ret.push_str(&token[body.len()..]);
+            changed = true;
+        } else {
+            ret.push_str(token);
+        }
+    }
+    if changed {
+        Some(ret)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::XPUB;
+    use crate::DescriptorPublicKey;
+
+    const PK: &str = "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798";
+
+    fn compat(s: &str) -> (String, Vec<CompatFix>) {
+        let s = s.replace("PK", PK).replace("XPUB", XPUB);
+        let (desc, fixes) = Descriptor::<DescriptorPublicKey>::from_str_compat(&s).unwrap();
+        (desc.to_string(), fixes)
+    }
+
+    #[test]
+    fn from_str_compat() {
+        let canonical = Descriptor::<DescriptorPublicKey>::from_str(&format!(
+            "wsh(multi(1,{},[deadbeef/84'/0'/0']{}/0/*))",
+            PK, XPUB
+        ))
+        .unwrap()
+        .to_string();
+
+        // Already canonical
+        assert_eq!(compat(&canonical), (canonical.clone(), vec![]));
+        let (no_checksum, _) = canonical.split_once('#').unwrap();
+        assert_eq!(compat(no_checksum).1, vec![CompatFix::MissingChecksum]);
+
+        // Whitespace, with a checksum of the stripped descriptor
+        let spaced = canonical.replace(',', ", ").replace("wsh(", "wsh( ");
+        assert_eq!(compat(&spaced), (canonical.clone(), vec![CompatFix::Whitespace]));
+
+        // Uppercase hex, but not in the xpub
+        let (desc, fixes) =
+            compat(&format!("wsh(multi(1,{},[DEADBEEF/84'/0'/0']XPUB/0/*))", PK.to_uppercase()));
+        assert_eq!(desc, canonical);
+        assert_eq!(fixes, vec![CompatFix::MissingChecksum, CompatFix::UppercaseHex]);
+
+        // Trailing commas
+        let (desc, fixes) = compat("tr(PK,{pk(PK),pk(XPUB/0/*),},)");
+        assert_eq!(desc, compat("tr(PK,{pk(PK),pk(XPUB/0/*)})").0);
+        assert_eq!(fixes, vec![CompatFix::MissingChecksum, CompatFix::TrailingComma]);
+
+        // A wrong checksum is still an error
+        let mut corrupted = canonical.clone();
+        corrupted.pop();
+        corrupted.push('x');
+        assert!(Descriptor::<DescriptorPublicKey>::from_str_compat(&corrupted).is_err());
+    }
+}
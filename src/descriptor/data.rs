@@ -0,0 +1,117 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! # Data Carrier Descriptors
+//!
+//! Implementation of the `data(hex)` descriptor, describing an `OP_RETURN`
+//! output carrying arbitrary data. Such outputs can never be spent and
+//! don't have an address.
+//!
+
+use core::fmt;
+
+use bitcoin::hex::{DisplayHex, FromHex};
+use bitcoin::script::{self, PushBytesBuf};
+use bitcoin::ScriptBuf;
+
+use crate::descriptor::write_descriptor;
+use crate::expression::{self, FromTree};
+use crate::policy::{semantic, Liftable};
+use crate::prelude::*;
+use crate::{Error, MiniscriptKey};
+
+/// Maximum size of the data carried by an output which is relayed by default
+/// by Bitcoin Core (`-datacarriersize` of 83 bytes, minus the `OP_RETURN` and
+/// the push opcodes).
+const MAX_STANDARD_DATA_LEN: usize = 80;
+
+/// A data carrier descriptor, i.e. an `OP_RETURN` output pushing some data.
+#[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Hash)]
+pub struct Data {
+    /// the pushed data
+    data: PushBytesBuf,
+}
+
+impl Data {
+    /// Create a new data carrier descriptor
+    ///
+    /// Errors if the data is too large to be pushed in a script.
+    pub fn new(data: Vec<u8>) -> Result<Self, Error> {
+        let data = PushBytesBuf::try_from(data)
+            .map_err(|_| Error::BadDescriptor("data too large to be pushed".to_owned()))?;
+        Ok(Self { data })
+    }
+
+    /// get the inner
+    pub fn as_bytes(&self) -> &[u8] { self.data.as_bytes() }
+
+    /// get the inner
+    pub fn into_inner(self) -> Vec<u8> { self.data.into() }
+
+    /// Checks whether the descriptor is safe.
+    ///
+    /// Errors if the output wouldn't be relayed by default because it carries
+    /// more than 80 bytes of data.
+    pub fn sanity_check(&self) -> Result<(), Error> {
+        if self.data.len() > MAX_STANDARD_DATA_LEN {
+            return Err(Error::BadDescriptor(format!(
+                "data carrier output with {} bytes of data is non-standard, the maximum is {}",
+                self.data.len(),
+                MAX_STANDARD_DATA_LEN
+            )));
+        }
+        Ok(())
+    }
+
+    /// Computes the size of the script pubkey without encoding it.
+    pub fn script_pubkey_size(&self) -> usize {
+        // OP_RETURN <data>
+        1 + crate::push_opcode_size(self.data.len()) + self.data.len()
+    }
+
+    /// Obtains the corresponding script pubkey for this descriptor.
+    pub fn script_pubkey(&self) -> ScriptBuf {
+        script::Builder::new()
+            .push_opcode(bitcoin::opcodes::all::OP_RETURN)
+            .push_slice(&self.data)
+            .into_script()
+    }
+}
+
+impl fmt::Debug for Data {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "data({})", self.data.as_bytes().as_hex())
+    }
+}
+
+impl fmt::Display for Data {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_descriptor!(f, "data({})", self.data.as_bytes().as_hex())
+    }
+}
+
+impl<Pk: MiniscriptKey> Liftable<Pk> for Data {
+    fn lift(&self) -> Result<semantic::Policy<Pk>, Error> { Ok(semantic::Policy::Unsatisfiable) }
+}
+
+impl FromTree for Data {
+    fn from_tree(top: &expression::Tree) -> Result<Self, Error> {
+        if top.name == "data" && top.args.len() == 1 {
+            let data = expression::terminal(&top.args[0], Vec::<u8>::from_hex)?;
+            Data::new(data)
+        } else {
+            Err(Error::Unexpected(format!(
+                "{}({} args) while parsing data descriptor",
+                top.name,
+                top.args.len(),
+            )))
+        }
+    }
+}
+
+impl core::str::FromStr for Data {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let top = expression::Tree::from_str(s)?;
+        Self::from_tree(&top)
+    }
+}
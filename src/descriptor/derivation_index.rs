@@ -0,0 +1,198 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! # Derivation Index Iteration
+//!
+//! Lazy derivation of a descriptor at a range of indexes, along each of the paths
+//! of multipath descriptors, e.g. to scan the receive and change chains of a
+//! wallet with a single `<0;1>` descriptor.
+//!
+
+use core::fmt;
+use core::ops::Range;
+#[cfg(feature = "std")]
+use std::error;
+
+use super::{ConversionError, DefiniteDescriptorKey, Descriptor, DescriptorPublicKey};
+use crate::prelude::*;
+use crate::Error;
+
+/// Error deriving a descriptor at a range of derivation indexes
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum DerivationRangeError {
+    /// The descriptor has wildcards but the range is empty
+    EmptyRange(Range<u32>),
+    /// The descriptor can't be derived at an index
+    Derivation(u32, ConversionError),
+}
+
+impl fmt::Display for DerivationRangeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DerivationRangeError::EmptyRange(ref range) => {
+                write!(f, "empty derivation range {}..{}", range.start, range.end)
+            }
+            DerivationRangeError::Derivation(index, ref e) => {
+                write!(f, "deriving descriptor at index {}: {}", index, e)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl error::Error for DerivationRangeError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            DerivationRangeError::EmptyRange(..) => None,
+            DerivationRangeError::Derivation(_, ref e) => Some(e),
+        }
+    }
+}
+
+/// An iterator deriving a descriptor at a range of indexes, along each of its paths
+///
+/// Yields `(path_index, child_index, descriptor)` tuples, for every path at the
+/// first index of the range, then at the next one, and so on, so that unbounded
+/// ranges scan all the paths. Descriptors without wildcards are only derived once
+/// per path, at index 0. Created by [`Descriptor::derivation_index_iter`].
+#[derive(Clone, Debug)]
+pub struct DerivationIndexIterator {
+    /// The single-path descriptors, see [`Descriptor::into_single_descriptors`]
+    paths: Vec<Descriptor<DescriptorPublicKey>>,
+    /// The indexes left to derive
+    range: Range<u32>,
+    /// The path to derive at the current index
+    path_index: usize,
+}
+
+impl Iterator for DerivationIndexIterator {
+    type Item = Result<(usize, u32, Descriptor<DefiniteDescriptorKey>), ConversionError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.range.is_empty() {
+            return None;
+        }
+        let (path_index, child_index) = (self.path_index, self.range.start);
+        let item = self.paths[path_index]
+            .at_derivation_index(child_index)
+            .map(|desc| (path_index, child_index, desc));
+
+        self.path_index += 1;
+        if self.path_index == self.paths.len() {
+            self.path_index = 0;
+            self.range.start += 1;
+        }
+        Some(item)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match self.range.len().checked_mul(self.paths.len()) {
+            Some(len) => (len - self.path_index, Some(len - self.path_index)),
+            None => (usize::MAX, None),
+        }
+    }
+}
+
+impl Descriptor<DescriptorPublicKey> {
+    /// Derives the descriptor at each index of `range`, along each of its paths
+    ///
+    /// See [`DerivationIndexIterator`] for the order of the derived descriptors.
+    /// Failures to derive the descriptor at an index are yielded as errors.
+    ///
+    /// # Errors
+    ///
+    /// If the multipath keys of the descriptor have different numbers of paths.
+    pub fn derivation_index_iter(
+        &self,
+        range: Range<u32>,
+    ) -> Result<DerivationIndexIterator, Error> {
+        let range = if self.has_wildcard() {
+            range
+        } else {
+            0..range.len().min(1) as u32
+        };
+        let paths = self.clone().into_single_descriptors()?;
+        Ok(DerivationIndexIterator { paths, range, path_index: 0 })
+    }
+
+    /// The indexes to derive the descriptor at for a range given by the user: the range
+    /// itself if the descriptor has wildcards, only index 0 otherwise
+    pub(super) fn checked_derivation_range(
+        &self,
+        range: Range<u32>,
+    ) -> Result<Range<u32>, DerivationRangeError> {
+        if !self.has_wildcard() {
+            Ok(0..1)
+        } else if range.is_empty() {
+            Err(DerivationRangeError::EmptyRange(range))
+        } else {
+            Ok(range)
+        }
+    }
+
+    /// Derives each path of the descriptor at each of `indexes`, path by path
+    pub(super) fn derive_paths<I>(
+        &self,
+        indexes: I,
+    ) -> Result<Vec<(u32, Descriptor<DefiniteDescriptorKey>)>, Error>
+    where
+        I: IntoIterator<Item = u32>,
+        I::IntoIter: Clone,
+    {
+        let indexes = indexes.into_iter();
+        let mut derived = vec![];
+        for desc in self.clone().into_single_descriptors()? {
+            for index in indexes.clone() {
+                let at_index = desc
+                    .at_derivation_index(index)
+                    .map_err(|e| DerivationRangeError::Derivation(index, e))?;
+                derived.push((index, at_index));
+            }
+        }
+        Ok(derived)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::str::FromStr;
+
+    use super::*;
+
+    #[test]
+    fn derivation_index_iter() {
+        let xpub = "xpub661MyMwAqRbcFtXgS5sYJABqqG9YLmC4Q1Rdap9gSE8NqtwybGhePY2gZ29ESFjqJoCu1Rupje8YtGqsefD265TMg7usUDFdp6W1EGMcet8";
+        let desc = Descriptor::<DescriptorPublicKey>::from_str(&format!("wpkh({}/<0;1>/*)", xpub))
+            .unwrap();
+
+        let iter = desc.derivation_index_iter(5..8).unwrap();
+        assert_eq!(iter.size_hint(), (6, Some(6)));
+        let derived: Vec<_> = iter.map(Result::unwrap).collect();
+        let order: Vec<_> = derived.iter().map(|(p, c, _)| (*p, *c)).collect();
+        assert_eq!(order, vec![(0, 5), (1, 5), (0, 6), (1, 6), (0, 7), (1, 7)]);
+        for (path_index, child_index, desc) in derived {
+            let expected = Descriptor::<DescriptorPublicKey>::from_str(&format!(
+                "wpkh({}/{}/*)",
+                xpub, path_index
+            ))
+            .unwrap()
+            .at_derivation_index(child_index)
+            .unwrap();
+            assert_eq!(desc, expected);
+        }
+
+        // Unbounded ranges reach every path
+        let mut iter = desc.derivation_index_iter(0..u32::MAX).unwrap();
+        assert_eq!(iter.nth(3).unwrap().unwrap().0, 1);
+
+        // Without wildcards, each path is derived once
+        let desc =
+            Descriptor::<DescriptorPublicKey>::from_str(&format!("wpkh({}/<0;1>)", xpub)).unwrap();
+        let order: Vec<_> = desc
+            .derivation_index_iter(5..8)
+            .unwrap()
+            .map(|res| res.map(|(p, c, _)| (p, c)).unwrap())
+            .collect();
+        assert_eq!(order, vec![(0, 0), (1, 0)]);
+        assert_eq!(desc.derivation_index_iter(5..5).unwrap().count(), 0);
+    }
+}
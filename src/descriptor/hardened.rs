@@ -0,0 +1,164 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! # Hardened Derivation
+//!
+//! Validation of the derivation paths of the keys of a descriptor against a
+//! policy forbidding unhardened derivation beyond the account level, as
+//! required by some custody setups for air-gapped signers.
+//!
+
+use core::fmt;
+
+use bitcoin::bip32::ChildNumber;
+
+use super::{Descriptor, DescriptorPublicKey, Wildcard};
+use crate::prelude::*;
+use crate::ForEachKey;
+
+/// A policy on the derivation paths of the keys of a descriptor
+///
+/// Unhardened derivation steps are only allowed up to the account level; all
+/// the steps deeper than it, including wildcards, must be hardened.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct HardenedPolicy {
+    /// The depth of the account level from the master key, 3 for BIP 44 style
+    /// paths `m/purpose'/coin_type'/account'`
+    pub account_depth: usize,
+}
+
+impl Default for HardenedPolicy {
+    fn default() -> Self { HardenedPolicy { account_depth: 3 } }
+}
+
+/// A key of a descriptor violating a [`HardenedPolicy`]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct HardenedViolation {
+    /// The key, as it appears in the descriptor
+    pub key: DescriptorPublicKey,
+    /// The depth from the master key of the first unhardened step beyond the
+    /// account level
+    pub depth: usize,
+}
+
+impl fmt::Display for HardenedViolation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "key {} uses unhardened derivation at depth {}", self.key, self.depth)
+    }
+}
+
+impl HardenedPolicy {
+    /// Checks the derivation paths of `key`, returning the depth of its first
+    /// unhardened step beyond the account level if any
+    ///
+    /// The depth of the steps of keys without origin is counted from the depth
+    /// of their extended key.
+    pub fn check_key(&self, key: &DescriptorPublicKey) -> Option<usize> {
+        let (origin, depth, paths, wildcard) = match *key {
+            DescriptorPublicKey::Single(ref single) => (&single.origin, 0, &[][..], Wildcard::None),
+            DescriptorPublicKey::XPub(ref xpub) => (
+                &xpub.origin,
+                xpub.xkey.depth,
+                core::slice::from_ref(&xpub.derivation_path),
+                xpub.wildcard,
+            ),
+            DescriptorPublicKey::MultiXPub(ref xpub) => {
+                (&xpub.origin, xpub.xkey.depth, &xpub.derivation_paths.paths()[..], xpub.wildcard)
+            }
+            DescriptorPublicKey::Musig(ref musig) => {
+                return musig
+                    .keys()
+                    .iter()
+                    .filter_map(|key| self.check_key(key))
+                    .min()
+            }
+        };
+        let (origin_steps, base) = match *origin {
+            Some((_, ref path)) => (path.as_ref(), path.len()),
+            None => (&[][..], usize::from(depth)),
+        };
+
+        let first_unhardened = |steps: &[ChildNumber], base: usize| {
+            steps
+                .iter()
+                .enumerate()
+                .map(|(i, step)| (base + i + 1, step))
+                .find(|&(depth, step)| depth > self.account_depth && step.is_normal())
+                .map(|(depth, _)| depth)
+        };
+        if let Some(depth) = first_unhardened(origin_steps, 0) {
+            return Some(depth);
+        }
+        paths
+            .iter()
+            .filter_map(|path| {
+                first_unhardened(path.as_ref(), base).or_else(|| {
+                    let depth = base + path.len() + 1;
+                    (wildcard == Wildcard::Unhardened && depth > self.account_depth)
+                        .then_some(depth)
+                })
+            })
+            .min()
+    }
+}
+
+impl Descriptor<DescriptorPublicKey> {
+    /// Checks that the keys of the descriptor don't use unhardened derivation
+    /// beyond the account level defined by `policy`
+    ///
+    /// # Errors
+    ///
+    /// The keys violating the policy, in the order they appear in the descriptor.
+    pub fn check_hardened(&self, policy: &HardenedPolicy) -> Result<(), Vec<HardenedViolation>> {
+        let mut violations = vec![];
+        self.for_each_key(|key| {
+            if let Some(depth) = policy.check_key(key) {
+                violations.push(HardenedViolation { key: key.clone(), depth });
+            }
+            true
+        });
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(violations)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::xpub_desc;
+
+    const PK: &str = "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798";
+
+    fn check(s: &str, account_depth: usize) -> Result<(), Vec<usize>> {
+        let desc = xpub_desc(&s.replace("PK", PK));
+        desc.check_hardened(&HardenedPolicy { account_depth })
+            .map_err(|violations| violations.into_iter().map(|v| v.depth).collect())
+    }
+
+    #[test]
+    fn check_hardened() {
+        // Standard wallet descriptors derive publicly beyond the account level
+        assert_eq!(check("wpkh([deadbeef/84'/0'/0']XPUB/0/*)", 3), Err(vec![4]));
+        assert_eq!(check("wpkh([deadbeef/84'/0'/0']XPUB/<0;1>/*)", 3), Err(vec![4]));
+        assert_eq!(check("wpkh([deadbeef/84'/0'/0']XPUB/0/*)", 5), Ok(()));
+        assert_eq!(check("wpkh([deadbeef/84'/0'/0']XPUB/0/*)", 4), Err(vec![5]));
+
+        // Keys without unhardened steps beyond the account level pass
+        assert_eq!(check("wpkh([deadbeef/84'/0'/0']XPUB)", 3), Ok(()));
+        assert_eq!(check("wpkh([deadbeef/84'/0'/0'/0/5]PK)", 5), Ok(()));
+        assert_eq!(check("wpkh([deadbeef/84'/0'/0'/0/5]PK)", 3), Err(vec![4]));
+        assert_eq!(check("wpkh(PK)", 0), Ok(()));
+
+        // Without origin, the depth of the xpub is used, 4 here
+        assert_eq!(check("wpkh(XPUB/*)", 4), Err(vec![5]));
+        assert_eq!(check("wpkh(XPUB/*)", 5), Ok(()));
+
+        // All violating keys are returned
+        let desc = "wsh(multi(1,[deadbeef/48'/0'/0'/2']XPUB/0/*,PK,[deadbeef/48'/0'/0'/2']XPUB/1))";
+        assert_eq!(check(desc, 4), Err(vec![5, 5]));
+        assert_eq!(check(desc, 5), Err(vec![6]));
+        assert_eq!(check(desc, 6), Ok(()));
+    }
+}
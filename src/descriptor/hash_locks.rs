@@ -0,0 +1,276 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! # Hash Locks
+//!
+//! Listing the hash locks of a descriptor, where they are and whether the
+//! descriptor can be satisfied without their preimages.
+//!
+
+use core::fmt;
+
+use super::{Descriptor, ShInner, WshInner};
+use crate::iter::TreeLike;
+use crate::miniscript::decode::Terminal;
+use crate::policy::semantic;
+use crate::prelude::*;
+use crate::{Miniscript, MiniscriptKey, ScriptContext};
+
+/// A hash lock fragment
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub enum HashLock<Pk: MiniscriptKey> {
+    /// `sha256(h)`
+    Sha256(Pk::Sha256),
+    /// `hash256(h)`
+    Hash256(Pk::Hash256),
+    /// `ripemd160(h)`
+    Ripemd160(Pk::Ripemd160),
+    /// `hash160(h)`
+    Hash160(Pk::Hash160),
+}
+
+impl<Pk: MiniscriptKey> HashLock<Pk> {
+    /// The hash lock of a fragment, if it is one
+    pub fn from_terminal<Ctx: ScriptContext>(term: &Terminal<Pk, Ctx>) -> Option<Self> {
+        match *term {
+            Terminal::Sha256(ref h) => Some(HashLock::Sha256(h.clone())),
+            Terminal::Hash256(ref h) => Some(HashLock::Hash256(h.clone())),
+            Terminal::Ripemd160(ref h) => Some(HashLock::Ripemd160(h.clone())),
+            Terminal::Hash160(ref h) => Some(HashLock::Hash160(h.clone())),
+            _ => None,
+        }
+    }
+
+    /// The semantic policy requiring the preimage of this hash
+    pub fn to_semantic(&self) -> semantic::Policy<Pk> {
+        match *self {
+            HashLock::Sha256(ref h) => semantic::Policy::Sha256(h.clone()),
+            HashLock::Hash256(ref h) => semantic::Policy::Hash256(h.clone()),
+            HashLock::Ripemd160(ref h) => semantic::Policy::Ripemd160(h.clone()),
+            HashLock::Hash160(ref h) => semantic::Policy::Hash160(h.clone()),
+        }
+    }
+}
+
+impl<Pk: MiniscriptKey> fmt::Display for HashLock<Pk> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            HashLock::Sha256(ref h) => write!(f, "sha256({})", h),
+            HashLock::Hash256(ref h) => write!(f, "hash256({})", h),
+            HashLock::Ripemd160(ref h) => write!(f, "ripemd160({})", h),
+            HashLock::Hash160(ref h) => write!(f, "hash160({})", h),
+        }
+    }
+}
+
+/// A hash lock of a descriptor, see [`Descriptor::hash_locks`]
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct HashLockInfo<Pk: MiniscriptKey> {
+    /// The hash lock
+    pub lock: HashLock<Pk>,
+    /// For Taproot descriptors, the position of the leaf containing the lock, in the order of
+    /// [`super::TapTree::iter`]
+    pub leaf: Option<usize>,
+    /// The position of the lock in its miniscript, as the index of the child to take at each
+    /// fragment from the root (see [`Miniscript::get_nth_child`])
+    pub path: Vec<usize>,
+    /// Whether the descriptor can be satisfied without the preimage of this hash
+    ///
+    /// This considers the hash itself rather than this particular fragment: if the same hash
+    /// appears in several fragments, not knowing its preimage disables all of them.
+    pub bypassable: bool,
+}
+
+impl<Pk: MiniscriptKey> Descriptor<Pk> {
+    /// Lists every hash lock of the descriptor, in the order they appear in the descriptor
+    /// string, along with their position and whether their preimage is required
+    ///
+    /// Keys and timelocks are assumed to be satisfiable when checking whether a lock can be
+    /// bypassed, so for Taproot descriptors every lock can be bypassed by the key path.
+    pub fn hash_locks(&self) -> Vec<HashLockInfo<Pk>> {
+        match *self {
+            Descriptor::Bare(ref bare) => ms_hash_locks(bare.as_inner()),
+            Descriptor::Pkh(..)
+            | Descriptor::Wpkh(..)
+            | Descriptor::Data(..)
+            | Descriptor::Wother(..)
+            | Descriptor::RawTr(..) => vec![],
+            Descriptor::Sh(ref sh) => match *sh.as_inner() {
+                ShInner::Wsh(ref wsh) => match *wsh.as_inner() {
+                    WshInner::Ms(ref ms) => ms_hash_locks(ms),
+                    WshInner::SortedMulti(..) => vec![],
+                },
+                ShInner::Ms(ref ms) => ms_hash_locks(ms),
+                ShInner::Wpkh(..) | ShInner::SortedMulti(..) => vec![],
+            },
+            Descriptor::Wsh(ref wsh) => match *wsh.as_inner() {
+                WshInner::Ms(ref ms) => ms_hash_locks(ms),
+                WshInner::SortedMulti(..) => vec![],
+            },
+            Descriptor::Tr(ref tr) => {
+                let mut locks = vec![];
+                for (i, (_, ms)) in tr.iter_scripts().enumerate() {
+                    push_hash_locks(ms, Some(i), &mut locks);
+                }
+                locks
+                    .into_iter()
+                    .map(|(lock, leaf, path)| HashLockInfo { lock, leaf, path, bypassable: true })
+                    .collect()
+            }
+        }
+    }
+}
+
+/// The hash locks of a miniscript which is the whole script of a descriptor
+fn ms_hash_locks<Pk: MiniscriptKey, Ctx: ScriptContext>(
+    ms: &Miniscript<Pk, Ctx>,
+) -> Vec<HashLockInfo<Pk>> {
+    let mut locks = vec![];
+    push_hash_locks(ms, None, &mut locks);
+    locks
+        .into_iter()
+        .map(|(lock, leaf, path)| {
+            let bypassable = satisfiable_without(ms, &lock);
+            HashLockInfo { lock, leaf, path, bypassable }
+        })
+        .collect()
+}
+
+/// Whether `ms` can be satisfied without the preimage of `lock`, assuming its keys,
+/// timelocks and other hash locks can be satisfied.
+fn satisfiable_without<Pk: MiniscriptKey, Ctx: ScriptContext>(
+    ms: &Miniscript<Pk, Ctx>,
+    lock: &HashLock<Pk>,
+) -> bool {
+    let mut stack = vec![];
+    for item in ms.rtl_post_order_iter() {
+        let satisfiable = match item.node.node {
+            Terminal::Sha256(..)
+            | Terminal::Hash256(..)
+            | Terminal::Ripemd160(..)
+            | Terminal::Hash160(..) => {
+                HashLock::from_terminal(&item.node.node).as_ref() != Some(lock)
+            }
+            Terminal::PkK(..)
+            | Terminal::PkH(..)
+            | Terminal::RawPkH(..)
+            | Terminal::After(..)
+            | Terminal::Older(..)
+            | Terminal::True
+            | Terminal::Multi(..)
+            | Terminal::MultiA(..) => true,
+            Terminal::False => false,
+            Terminal::Alt(..)
+            | Terminal::Swap(..)
+            | Terminal::Check(..)
+            | Terminal::DupIf(..)
+            | Terminal::Verify(..)
+            | Terminal::NonZero(..)
+            | Terminal::ZeroNotEqual(..) => stack.pop().unwrap(),
+            Terminal::AndV(..) | Terminal::AndB(..) => {
+                let (left, right) = (stack.pop().unwrap(), stack.pop().unwrap());
+                left && right
+            }
+            Terminal::AndOr(..) => {
+                let (a, b, c) = (stack.pop().unwrap(), stack.pop().unwrap(), stack.pop().unwrap());
+                (a && b) || c
+            }
+            Terminal::OrB(..) | Terminal::OrD(..) | Terminal::OrC(..) | Terminal::OrI(..) => {
+                let (left, right) = (stack.pop().unwrap(), stack.pop().unwrap());
+                left || right
+            }
+            Terminal::Thresh(ref thresh) => {
+                let satisfiable = (0..thresh.n()).filter(|_| stack.pop().unwrap()).count();
+                satisfiable >= thresh.k()
+            }
+        };
+        stack.push(satisfiable);
+    }
+    assert_eq!(stack.len(), 1);
+    stack.pop().unwrap()
+}
+
+/// Pushes the hash locks of `ms` in pre-order, along with their path from the root.
+fn push_hash_locks<Pk: MiniscriptKey, Ctx: ScriptContext>(
+    ms: &Miniscript<Pk, Ctx>,
+    leaf: Option<usize>,
+    locks: &mut Vec<(HashLock<Pk>, Option<usize>, Vec<usize>)>,
+) {
+    let mut stack = vec![(ms, vec![])];
+    while let Some((node, path)) = stack.pop() {
+        if let Some(lock) = HashLock::from_terminal(&node.node) {
+            locks.push((lock, leaf, path));
+            continue;
+        }
+        for (i, child) in node.branches().into_iter().enumerate().rev() {
+            let mut child_path = path.clone();
+            child_path.push(i);
+            stack.push((child, child_path));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::str::FromStr;
+
+    use super::*;
+    use crate::descriptor::Wsh;
+    use crate::policy::Liftable;
+
+    const H1: &str = "1111111111111111111111111111111111111111111111111111111111111111";
+    const H2: &str = "2222222222222222222222222222222222222222";
+
+    fn locks(s: &str) -> Vec<HashLockInfo<String>> {
+        let desc = Descriptor::<String>::from_str(&s.replace("H1", H1).replace("H2", H2)).unwrap();
+        desc.hash_locks()
+    }
+
+    #[test]
+    fn hash_locks() {
+        assert_eq!(locks("wsh(multi(2,A,B))"), vec![]);
+
+        // HTLC: the preimage can be bypassed by the timeout path
+        let htlc = locks("wsh(andor(pk(A),sha256(H1),and_v(v:pk(B),older(144))))");
+        assert_eq!(
+            htlc,
+            vec![HashLockInfo {
+                lock: HashLock::Sha256(H1.to_owned()),
+                leaf: None,
+                path: vec![1],
+                bypassable: true,
+            }]
+        );
+        assert_eq!(htlc[0].lock.to_string(), format!("sha256({})", H1));
+
+        // Both preimages are required
+        let both = locks("sh(and_v(and_v(v:sha256(H1),v:hash160(H2)),pk(A)))");
+        assert_eq!(both.len(), 2);
+        assert_eq!(both[0].lock, HashLock::Sha256(H1.to_owned()));
+        assert_eq!(both[0].path, vec![0, 0, 0]);
+        assert!(!both[0].bypassable);
+        assert_eq!(both[1].lock, HashLock::Hash160(H2.to_owned()));
+        assert_eq!(both[1].path, vec![0, 1, 0]);
+        assert!(!both[1].bypassable);
+
+        // Either preimage is enough, in different tap leaves (and the key path needs neither)
+        let either = locks("tr(A,{and_v(v:pk(B),sha256(H1)),and_v(v:pk(C),ripemd160(H2))})");
+        assert_eq!(either.len(), 2);
+        assert_eq!((either[0].leaf, either[0].path.clone()), (Some(0), vec![1]));
+        assert_eq!(either[1].lock, HashLock::Ripemd160(H2.to_owned()));
+        assert_eq!((either[1].leaf, either[1].path.clone()), (Some(1), vec![1]));
+        assert!(either.iter().all(|lock| lock.bypassable));
+
+        // Descriptors which can't be lifted, here because of mixed timelocks, are walked too
+        let ms = Miniscript::<String, crate::Segwitv0>::from_str_insane(&format!(
+            "or_d(sha256({}),and_v(v:after(100),after(500000001)))",
+            H1
+        ))
+        .unwrap();
+        let desc = Descriptor::Wsh(Wsh::new(ms).unwrap());
+        assert!(desc.lift().is_err());
+        let mixed = desc.hash_locks();
+        assert_eq!(mixed.len(), 1);
+        assert_eq!(mixed[0].path, vec![0]);
+        assert!(mixed[0].bypassable);
+    }
+}
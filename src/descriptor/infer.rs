@@ -0,0 +1,609 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! Descriptor inference
+//!
+//! Heuristics to recover the descriptor of a wallet from an extended public key and a few
+//! addresses known to belong to it, by trying the standard single-signature templates, and
+//! reconstruction of the descriptor of an output from its `script_pubkey` and the scripts
+//! revealed by its spends.
+
+use core::fmt;
+use core::ops::Range;
+use core::str::FromStr;
+
+use bitcoin::bip32::Xpub;
+use bitcoin::script::Instruction;
+use bitcoin::secp256k1::XOnlyPublicKey;
+use bitcoin::taproot::{self, ControlBlock, LeafVersion, NodeInfo, TapLeafHash, TapNodeHash};
+use bitcoin::{secp256k1, Address, Script, ScriptBuf};
+
+use super::set::ScriptSet;
+use super::{
+    ConversionError, Descriptor, DescriptorPublicKey, TapTree, TapTreeConversionError, Tr,
+};
+use crate::miniscript::{BareCtx, Legacy, Segwitv0};
+use crate::prelude::*;
+use crate::{Error, Miniscript, Translator};
+
+/// An error inferring a descriptor, see [`infer_template`] and
+/// [`Descriptor::from_script_pubkey`]
+#[derive(Debug)]
+pub enum InferError {
+    /// No revealed key or script matches an output
+    NotRevealed {
+        /// What was looked for, e.g. `"witness script"`
+        what: &'static str,
+        /// The kind of output, e.g. `"sh(wsh)"`
+        output: &'static str,
+    },
+    /// Only some leaves of the tap tree were revealed
+    IncompleteTapTree,
+    /// The revealed leaves of the tap tree couldn't be combined
+    TapTreeNode(taproot::TaprootBuilderError),
+    /// A revealed leaf of the tap tree couldn't be converted
+    TapTree(TapTreeConversionError),
+    /// The output key of a Taproot output isn't a valid key
+    OutputKey(secp256k1::Error),
+    /// An `OP_RETURN` output doesn't push exactly one piece of data
+    OpReturn,
+    /// A revealed script isn't a valid Miniscript, or a descriptor couldn't be built
+    Descriptor(Error),
+    /// The script pubkeys of a template couldn't be derived
+    Derivation(ConversionError),
+    /// The inferred descriptor doesn't produce the script pubkey
+    ScriptPubkeyMismatch,
+}
+
+impl fmt::Display for InferError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            InferError::NotRevealed { what, output } => {
+                write!(f, "no revealed {} matches the {} output", what, output)
+            }
+            InferError::IncompleteTapTree => {
+                f.write_str("not all leaves of the tap tree were revealed")
+            }
+            InferError::TapTreeNode(ref e) => e.fmt(f),
+            InferError::TapTree(ref e) => e.fmt(f),
+            InferError::OutputKey(ref e) => e.fmt(f),
+            InferError::OpReturn => f.write_str("OP_RETURN output doesn't push exactly one data"),
+            InferError::Descriptor(ref e) => e.fmt(f),
+            InferError::Derivation(ref e) => e.fmt(f),
+            InferError::ScriptPubkeyMismatch => {
+                f.write_str("inferred descriptor doesn't match the script_pubkey")
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for InferError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match *self {
+            InferError::TapTreeNode(ref e) => Some(e),
+            InferError::TapTree(ref e) => Some(e),
+            InferError::OutputKey(ref e) => Some(e),
+            InferError::Descriptor(ref e) => Some(e),
+            InferError::Derivation(ref e) => Some(e),
+            InferError::NotRevealed { .. }
+            | InferError::IncompleteTapTree
+            | InferError::OpReturn
+            | InferError::ScriptPubkeyMismatch => None,
+        }
+    }
+}
+
+#[doc(hidden)]
+impl From<Error> for InferError {
+    fn from(e: Error) -> Self { InferError::Descriptor(e) }
+}
+
+/// A standard single-signature descriptor template
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum StandardTemplate {
+    /// `pkh(KEY)`, used by BIP44 wallets
+    Bip44,
+    /// `sh(wpkh(KEY))`, used by BIP49 wallets
+    Bip49,
+    /// `wpkh(KEY)`, used by BIP84 wallets
+    Bip84,
+    /// `tr(KEY)`, used by BIP86 wallets
+    Bip86,
+}
+
+impl StandardTemplate {
+    /// All the templates, in the order they are tried
+    pub const ALL: [StandardTemplate; 4] = [
+        StandardTemplate::Bip44,
+        StandardTemplate::Bip49,
+        StandardTemplate::Bip84,
+        StandardTemplate::Bip86,
+    ];
+
+    /// The descriptor for this template using `key`
+    pub fn descriptor(
+        &self,
+        key: DescriptorPublicKey,
+    ) -> Result<Descriptor<DescriptorPublicKey>, Error> {
+        match *self {
+            StandardTemplate::Bip44 => Descriptor::new_pkh(key),
+            StandardTemplate::Bip49 => Descriptor::new_sh_wpkh(key),
+            StandardTemplate::Bip84 => Descriptor::new_wpkh(key),
+            StandardTemplate::Bip86 => Descriptor::new_tr(key, None),
+        }
+    }
+}
+
+impl fmt::Display for StandardTemplate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            StandardTemplate::Bip44 => f.write_str("bip44"),
+            StandardTemplate::Bip49 => f.write_str("bip49"),
+            StandardTemplate::Bip84 => f.write_str("bip84"),
+            StandardTemplate::Bip86 => f.write_str("bip86"),
+        }
+    }
+}
+
+/// An address found while inferring a template
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct AddressMatch {
+    /// Position of the address in the addresses given to [`infer_template`]
+    pub address: usize,
+    /// The chain the address was found on (`0` for receive, `1` for change), or `None` if the
+    /// extended key was used directly as the chain key
+    pub chain: Option<u32>,
+    /// The derivation index of the address on its chain
+    pub index: u32,
+}
+
+/// The result of [`infer_template`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InferredTemplate {
+    /// The template which matched
+    pub template: StandardTemplate,
+    /// The descriptor of the wallet
+    ///
+    /// This is a multipath descriptor with a receive and a change chain if the extended key is
+    /// an account key, otherwise the extended key is used directly with a wildcard.
+    pub descriptor: Descriptor<DescriptorPublicKey>,
+    /// The given addresses which the descriptor produces, and where it produces them
+    pub matches: Vec<AddressMatch>,
+}
+
+/// Infers the descriptor of a wallet from its extended public key and some of its addresses
+///
+/// Every standard template is tried with `xpub` both as an account key (with receive and
+/// change chains `<0;1>/*`) and as a chain key (`/*`), deriving the addresses at each index in
+/// `range`. The candidate matching the most addresses is returned along with the matches, or
+/// `None` if no address is found.
+///
+/// The network of the addresses isn't checked, only their `script_pubkey`.
+pub fn infer_template<C: secp256k1::Verification>(
+    secp: &secp256k1::Secp256k1<C>,
+    xpub: &Xpub,
+    addresses: &[Address],
+    range: Range<u32>,
+) -> Result<Option<InferredTemplate>, InferError> {
+    let account_key =
+        DescriptorPublicKey::from_str(&format!("{}/<0;1>/*", xpub)).expect("valid multipath key");
+    let chain_key = DescriptorPublicKey::from_str(&format!("{}/*", xpub)).expect("valid key");
+
+    let mut best: Option<InferredTemplate> = None;
+    for template in StandardTemplate::ALL {
+        for (key, is_account) in [(account_key.clone(), true), (chain_key.clone(), false)] {
+            let descriptor = template.descriptor(key)?;
+            let chains = descriptor.clone().into_single_descriptors()?;
+            let set = ScriptSet::from_descriptors(secp, &chains, range.clone())
+                .map_err(InferError::Derivation)?;
+
+            let matches: Vec<_> = addresses
+                .iter()
+                .enumerate()
+                .filter_map(|(i, address)| {
+                    let origin = set.origins(&address.script_pubkey()).first().copied()?;
+                    Some(AddressMatch {
+                        address: i,
+                        chain: if is_account {
+                            Some(origin.descriptor as u32)
+                        } else {
+                            None
+                        },
+                        index: origin.index,
+                    })
+                })
+                .collect();
+
+            if !matches.is_empty()
+                && best
+                    .as_ref()
+                    .map_or(true, |best| best.matches.len() < matches.len())
+            {
+                best = Some(InferredTemplate { template, descriptor, matches });
+            }
+        }
+    }
+    Ok(best)
+}
+
+/// Scripts and keys revealed by the spends of an output, see [`Descriptor::from_script_pubkey`]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RevealedData {
+    /// Redeem scripts, witness scripts and tap leaf scripts
+    pub scripts: Vec<ScriptBuf>,
+    /// Public keys, for outputs paying to a key hash and Taproot outputs without script paths
+    pub keys: Vec<bitcoin::PublicKey>,
+    /// Control blocks of Taproot script path spends
+    pub control_blocks: Vec<ControlBlock>,
+}
+
+impl RevealedData {
+    /// The first revealed script whose commitment `to_script_pubkey` is `target`
+    fn script(
+        &self,
+        target: &Script,
+        to_script_pubkey: fn(&Script) -> ScriptBuf,
+    ) -> Option<&Script> {
+        self.scripts
+            .iter()
+            .map(ScriptBuf::as_script)
+            .find(|script| to_script_pubkey(script) == *target)
+    }
+
+    /// The first descriptor built from a revealed key with the given `script_pubkey`
+    fn key_descriptor(
+        &self,
+        script_pubkey: &Script,
+        new: fn(bitcoin::PublicKey) -> Result<Descriptor<bitcoin::PublicKey>, Error>,
+    ) -> Option<Descriptor<bitcoin::PublicKey>> {
+        self.keys
+            .iter()
+            .filter_map(|pk| new(*pk).ok())
+            .find(|desc| desc.script_pubkey() == *script_pubkey)
+    }
+}
+
+fn not_revealed(what: &'static str, output: &'static str) -> InferError {
+    InferError::NotRevealed { what, output }
+}
+
+impl Descriptor<bitcoin::PublicKey> {
+    /// Reconstructs the descriptor of an output from its `script_pubkey` and the scripts and keys
+    /// revealed by its spends, e.g. found in the witnesses of transactions spending it
+    ///
+    /// - `pkh`, `wpkh` and `sh(wpkh)` outputs need their key.
+    /// - `sh`, `wsh` and `sh(wsh)` outputs need their redeem and witness scripts, which must be
+    ///   Miniscripts.
+    /// - `tr` outputs need the control blocks and scripts of all their leaves, or their internal
+    ///   key if they have no leaves. Without either, a `rawtr` of the output key is returned.
+    /// - Bare scripts must be Miniscripts, `OP_RETURN` outputs pushing some data give a data
+    ///   carrier descriptor and other witness programs give a descriptor of their version.
+    ///
+    /// As for [`crate::interpreter::Interpreter::inferred_descriptor`], sorted multisigs are
+    /// inferred as `multi` and x-only keys are given a `0x02` prefix.
+    ///
+    /// # Errors
+    ///
+    /// If a script or key needed to reconstruct the descriptor wasn't revealed, if a revealed
+    /// script isn't a valid Miniscript, or if only some leaves of a tap tree were revealed.
+    pub fn from_script_pubkey<C: secp256k1::Verification>(
+        secp: &secp256k1::Secp256k1<C>,
+        script_pubkey: &Script,
+        revealed: &RevealedData,
+    ) -> Result<Self, InferError> {
+        let descriptor = if script_pubkey.is_p2pkh() {
+            revealed
+                .key_descriptor(script_pubkey, Descriptor::new_pkh)
+                .ok_or_else(|| not_revealed("key", "pkh"))?
+        } else if script_pubkey.is_p2wpkh() {
+            revealed
+                .key_descriptor(script_pubkey, Descriptor::new_wpkh)
+                .ok_or_else(|| not_revealed("key", "wpkh"))?
+        } else if script_pubkey.is_p2sh() {
+            if let Some(desc) = revealed.key_descriptor(script_pubkey, Descriptor::new_sh_wpkh) {
+                desc
+            } else {
+                let redeem_script = revealed
+                    .script(script_pubkey, Script::to_p2sh)
+                    .ok_or_else(|| not_revealed("redeem script", "sh"))?;
+                if redeem_script.is_p2wsh() {
+                    let witness_script = revealed
+                        .script(redeem_script, Script::to_p2wsh)
+                        .ok_or_else(|| not_revealed("witness script", "sh(wsh)"))?;
+                    Descriptor::new_sh_wsh(Miniscript::<_, Segwitv0>::parse(witness_script)?)?
+                } else {
+                    Descriptor::new_sh(Miniscript::<_, Legacy>::parse(redeem_script)?)?
+                }
+            }
+        } else if script_pubkey.is_p2wsh() {
+            let witness_script = revealed
+                .script(script_pubkey, Script::to_p2wsh)
+                .ok_or_else(|| not_revealed("witness script", "wsh"))?;
+            Descriptor::new_wsh(Miniscript::<_, Segwitv0>::parse(witness_script)?)?
+        } else if script_pubkey.is_p2tr() {
+            infer_tr(secp, script_pubkey, revealed)?
+        } else if script_pubkey.is_op_return() {
+            match script_pubkey
+                .instructions()
+                .skip(1)
+                .collect::<Result<Vec<_>, _>>()
+            {
+                Ok(ref instructions) if instructions.len() == 1 => match instructions[0] {
+                    Instruction::PushBytes(data) => Descriptor::new_data(data.as_bytes().to_vec())?,
+                    Instruction::Op(_) => return Err(InferError::OpReturn),
+                },
+                _ => return Err(InferError::OpReturn),
+            }
+        } else if let Some(version) = script_pubkey.witness_version() {
+            Descriptor::new_wother(version, &script_pubkey.as_bytes()[2..])?
+        } else {
+            Descriptor::new_bare(Miniscript::<_, BareCtx>::parse(script_pubkey)?)?
+        };
+
+        if descriptor.script_pubkey() != *script_pubkey {
+            return Err(InferError::ScriptPubkeyMismatch);
+        }
+        Ok(descriptor)
+    }
+}
+
+/// Reconstructs a Taproot descriptor from its revealed leaves, or from its internal key
+fn infer_tr<C: secp256k1::Verification>(
+    secp: &secp256k1::Secp256k1<C>,
+    script_pubkey: &Script,
+    revealed: &RevealedData,
+) -> Result<Descriptor<bitcoin::PublicKey>, InferError> {
+    struct FullKeys;
+
+    impl Translator<XOnlyPublicKey> for FullKeys {
+        type TargetPk = bitcoin::PublicKey;
+        type Error = core::convert::Infallible;
+
+        fn pk(&mut self, pk: &XOnlyPublicKey) -> Result<bitcoin::PublicKey, Self::Error> {
+            Ok(bitcoin::PublicKey::new(pk.public_key(secp256k1::Parity::Even)))
+        }
+
+        translate_hash_clone!(XOnlyPublicKey, bitcoin::PublicKey, Self::Error);
+    }
+
+    let output_key = XOnlyPublicKey::from_slice(&script_pubkey.as_bytes()[2..])
+        .map_err(InferError::OutputKey)?;
+
+    // Each revealed leaf with the hashes of its ancestors, from itself to the root
+    let mut internal_key = None;
+    let mut leaves: Vec<(&Script, Vec<TapNodeHash>)> = vec![];
+    for control_block in &revealed.control_blocks {
+        for script in &revealed.scripts {
+            if control_block.leaf_version != LeafVersion::TapScript
+                || !control_block.verify_taproot_commitment(secp, output_key, script)
+            {
+                continue;
+            }
+            internal_key = Some(control_block.internal_key);
+            let mut ancestors = vec![TapNodeHash::from(TapLeafHash::from_script(
+                script,
+                LeafVersion::TapScript,
+            ))];
+            for sibling in control_block.merkle_branch.iter() {
+                let node = TapNodeHash::from_node_hashes(ancestors[ancestors.len() - 1], *sibling);
+                ancestors.push(node);
+            }
+            if leaves
+                .iter()
+                .all(|(leaf, path)| *leaf != script.as_script() || *path != ancestors)
+            {
+                leaves.push((script, ancestors));
+            }
+        }
+    }
+
+    let descriptor = match internal_key {
+        Some(internal_key) => {
+            let root = leaves[0].1[leaves[0].1.len() - 1];
+            let node = tap_node(&leaves, root, 0)?;
+            let tree =
+                taproot::TapTree::try_from(node).map_err(|_| InferError::IncompleteTapTree)?;
+            let tree = TapTree::from_bitcoin_tap_tree(&tree).map_err(InferError::TapTree)?;
+            Descriptor::Tr(Tr::new(internal_key, Some(tree))?)
+        }
+        None => {
+            return match revealed.key_descriptor(script_pubkey, |pk| Descriptor::new_tr(pk, None)) {
+                Some(desc) => Ok(desc),
+                None => Ok(Descriptor::new_rawtr(bitcoin::PublicKey::new(
+                    output_key.public_key(secp256k1::Parity::Even),
+                ))?),
+            };
+        }
+    };
+    Ok(descriptor
+        .translate_pk(&mut FullKeys)
+        .expect("compressed keys are valid in Taproot descriptors"))
+}
+
+/// Rebuilds the node with hash `hash` at `depth` from the leaves below it
+///
+/// Nodes which have no revealed leaf below them are hidden.
+fn tap_node(
+    leaves: &[(&Script, Vec<TapNodeHash>)],
+    hash: TapNodeHash,
+    depth: usize,
+) -> Result<NodeInfo, InferError> {
+    // The node at `depth` on the path of a leaf with `n` ancestors is its `n - depth`th
+    let mut below = leaves
+        .iter()
+        .filter(|(_, path)| path.len() > depth && path[path.len() - 1 - depth] == hash);
+    match below.next() {
+        None => Ok(NodeInfo::new_hidden_node(hash)),
+        Some((script, path)) if path.len() == depth + 1 => {
+            Ok(NodeInfo::new_leaf_with_ver(script.to_owned().into(), LeafVersion::TapScript))
+        }
+        Some((_, path)) => {
+            // The children of the node are the next ancestor of the leaf and its sibling
+            let child = path[path.len() - 2 - depth];
+            let sibling = leaves
+                .iter()
+                .filter_map(|(_, other)| {
+                    let idx = other.len().checked_sub(2 + depth)?;
+                    (other[idx + 1] == hash && other[idx] != child).then(|| other[idx])
+                })
+                .next();
+            let left = tap_node(leaves, child, depth + 1)?;
+            let right = match sibling {
+                Some(sibling) => tap_node(leaves, sibling, depth + 1)?,
+                None => return Err(InferError::IncompleteTapTree),
+            };
+            NodeInfo::combine(left, right).map_err(InferError::TapTreeNode)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::Network;
+
+    use super::*;
+    use crate::test_utils::{xpub_desc, XPUB};
+
+    fn address(desc: &str, index: u32) -> Address {
+        let secp = secp256k1::Secp256k1::verification_only();
+        xpub_desc(desc)
+            .derived_descriptor(&secp, index)
+            .unwrap()
+            .address(Network::Bitcoin)
+            .unwrap()
+    }
+
+    #[test]
+    fn infer() {
+        let secp = secp256k1::Secp256k1::verification_only();
+        let xpub = Xpub::from_str(XPUB).unwrap();
+
+        let addresses = [
+            address("wpkh(XPUB/0/*)", 3),
+            address("wpkh(XPUB/1/*)", 0),
+            address("pkh(XPUB/0/*)", 40),
+        ];
+        let inferred = infer_template(&secp, &xpub, &addresses, 0..20)
+            .unwrap()
+            .unwrap();
+        assert_eq!(inferred.template, StandardTemplate::Bip84);
+        assert_eq!(inferred.descriptor.to_string(), format!("wpkh({}/<0;1>/*)#h4mk3q39", XPUB));
+        assert_eq!(
+            inferred.matches,
+            vec![
+                AddressMatch { address: 0, chain: Some(0), index: 3 },
+                AddressMatch { address: 1, chain: Some(1), index: 0 },
+            ]
+        );
+
+        // A chain key
+        let addresses = [address("tr(XPUB/*)", 7)];
+        let inferred = infer_template(&secp, &xpub, &addresses, 0..20)
+            .unwrap()
+            .unwrap();
+        assert_eq!(inferred.template, StandardTemplate::Bip86);
+        assert_eq!(inferred.matches, vec![AddressMatch { address: 0, chain: None, index: 7 }]);
+
+        // Out of range, or not from this key
+        let addresses = [
+            address("pkh(XPUB/0/*)", 40),
+            address("wsh(pk(XPUB/0/*))", 0),
+        ];
+        assert_eq!(infer_template(&secp, &xpub, &addresses, 0..20).unwrap(), None);
+    }
+
+    #[test]
+    fn from_script_pubkey() {
+        let secp = secp256k1::Secp256k1::verification_only();
+        let keys = [
+            "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798",
+            "02c6047f9441ed7d6d3045406e95c07cd85c778e4b8cef3ca7abac09b95c709ee5",
+            "02f9308a019258c31049344f85f89d5229b531c845836f99b08601f113bce036f9",
+        ];
+        let desc = |s: &str| {
+            let s = s
+                .replace("A", keys[0])
+                .replace("B", keys[1])
+                .replace("C", keys[2]);
+            Descriptor::<bitcoin::PublicKey>::from_str(&s).unwrap()
+        };
+        let infer = |desc: &Descriptor<bitcoin::PublicKey>, revealed: &RevealedData| {
+            Descriptor::from_script_pubkey(&secp, &desc.script_pubkey(), revealed)
+        };
+        let with_keys = RevealedData {
+            keys: keys
+                .iter()
+                .map(|k| bitcoin::PublicKey::from_str(k).unwrap())
+                .collect(),
+            ..Default::default()
+        };
+
+        for s in ["pkh(B)", "wpkh(B)", "sh(wpkh(B))", "tr(B)"] {
+            assert_eq!(infer(&desc(s), &with_keys).unwrap(), desc(s));
+        }
+        assert!(matches!(
+            infer(&desc("wpkh(B)"), &RevealedData::default()),
+            Err(InferError::NotRevealed { what: "key", output: "wpkh" })
+        ));
+        let rawtr = infer(&desc("tr(B)"), &RevealedData::default()).unwrap();
+        assert!(matches!(rawtr, Descriptor::RawTr(..)));
+
+        // Scripts, given in any order along with unrelated ones
+        for s in [
+            "wsh(multi(2,A,B,C))",
+            "sh(wsh(and_v(v:pk(A),older(144))))",
+            "sh(or_d(pk(A),pk(B)))",
+            "pk(C)",
+        ] {
+            let d = desc(s);
+            let script = d.explicit_script().unwrap();
+            let revealed = RevealedData {
+                scripts: vec![
+                    desc("wsh(pk(A))").explicit_script().unwrap(),
+                    script.to_p2wsh(),
+                    script,
+                ],
+                ..Default::default()
+            };
+            assert_eq!(infer(&d, &revealed).unwrap(), d);
+        }
+        assert!(matches!(
+            infer(&desc("wsh(pk(A))"), &RevealedData::default()),
+            Err(InferError::NotRevealed { what: "witness script", output: "wsh" })
+        ));
+        let data = desc("wsh(pk(A))").explicit_script().unwrap();
+        let op_return = ScriptBuf::new_op_return(
+            <&bitcoin::script::PushBytes>::try_from(data.as_bytes()).unwrap(),
+        );
+        let inferred = Descriptor::from_script_pubkey(&secp, &op_return, &RevealedData::default());
+        assert_eq!(inferred.unwrap().script_pubkey(), op_return);
+
+        // Taproot trees need all their leaves
+        let d = desc("tr(A,{pk(B),{pk(C),and_v(v:pk(A),older(10))}})");
+        let tr = match d {
+            Descriptor::Tr(ref tr) => tr,
+            _ => unreachable!(),
+        };
+        let spend_info = tr.spend_info();
+        let mut revealed = RevealedData::default();
+        for (_, ms) in tr.iter_scripts() {
+            let script = ms.encode();
+            revealed.control_blocks.push(
+                spend_info
+                    .control_block(&(script.clone(), LeafVersion::TapScript))
+                    .unwrap(),
+            );
+            revealed.scripts.push(script);
+        }
+        let inferred = infer(&d, &revealed).unwrap();
+        match inferred {
+            Descriptor::Tr(ref inferred) => {
+                assert_eq!(inferred.internal_key(), tr.internal_key());
+                assert_eq!(inferred.iter_scripts().count(), 3);
+            }
+            _ => panic!("{}", inferred),
+        }
+        revealed.control_blocks.remove(1);
+        assert!(matches!(infer(&d, &revealed), Err(InferError::IncompleteTapTree)));
+    }
+}
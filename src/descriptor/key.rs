@@ -9,14 +9,15 @@ use std::error;
 use bitcoin::bip32::{self, XKeyIdentifier};
 use bitcoin::hashes::{hash160, ripemd160, sha256, Hash, HashEngine};
 use bitcoin::key::XOnlyPublicKey;
-use bitcoin::secp256k1::{Secp256k1, Signing, Verification};
+use bitcoin::secp256k1::{self, Secp256k1, Signing, Verification};
+use secp256k1_musig::musig::KeyAggCache;
 
 use crate::prelude::*;
 #[cfg(feature = "serde")]
 use crate::serde::{Deserialize, Deserializer, Serialize, Serializer};
 use crate::{hash256, MiniscriptKey, ToPublicKey};
 
-/// The descriptor pubkey, either a single pubkey or an xpub.
+/// The descriptor pubkey, either a single pubkey, an xpub or a MuSig2 aggregate of those.
 #[derive(Debug, Eq, PartialEq, Clone, Ord, PartialOrd, Hash)]
 pub enum DescriptorPublicKey {
     /// Single public key.
@@ -25,9 +26,17 @@ pub enum DescriptorPublicKey {
     XPub(DescriptorXKey<bip32::Xpub>),
     /// Multiple extended public keys.
     MultiXPub(DescriptorMultiXKey<bip32::Xpub>),
+    /// MuSig2 aggregate of public keys.
+    Musig(DescriptorMusigKey),
 }
 
 /// The descriptor secret key, either a single private key or an xprv.
+///
+/// With the `zeroize` feature, the private keys and chain codes are overwritten
+/// with volatile writes when the key is dropped, including when it is held in a
+/// [`super::KeyMap`], and the key implements `Zeroize`. Copies made before, such
+/// as by [`bitcoin::PrivateKey`] being `Copy`, or the strings returned by its
+/// `Display` implementation, aren't.
 #[derive(Debug, Eq, PartialEq, Clone)]
 pub enum DescriptorSecretKey {
     /// Single private key.
@@ -103,6 +112,60 @@ pub struct DescriptorMultiXKey<K: InnerXKey> {
     pub wildcard: Wildcard,
 }
 
+/// The keys of a `musig(KEY,KEY,...)` key expression, as specified in BIP 390.
+///
+/// The aggregate key is computed with the `KeyAgg` algorithm of BIP 327, from the keys sorted
+/// with its `KeySort` algorithm, so it doesn't depend on the order the keys are given in. It can
+/// only be used in Taproot descriptors.
+///
+/// A single key is accepted, since BIP 327 defines `KeyAgg` for any non zero number of keys and
+/// BIP 390 doesn't restrict it further. Its aggregate is a different key than the key itself,
+/// which signs through the MuSig2 protocol like any other aggregate.
+#[derive(Debug, Eq, PartialEq, Clone, Ord, PartialOrd, Hash)]
+pub struct DescriptorMusigKey(Vec<DescriptorPublicKey>);
+
+impl DescriptorMusigKey {
+    /// Create the aggregate of a non empty list of compressed keys, which may have a single key.
+    ///
+    /// Aggregates can't be nested, and the keys can't have multiple derivation paths.
+    pub fn new(keys: Vec<DescriptorPublicKey>) -> Result<Self, DescriptorKeyParseError> {
+        if keys.is_empty() {
+            return Err(DescriptorKeyParseError("musig() requires at least one key"));
+        }
+        for key in &keys {
+            match *key {
+                DescriptorPublicKey::Single(SinglePub { key: SinglePubKey::XOnly(_), .. }) => {
+                    return Err(DescriptorKeyParseError("x-only keys are not allowed in musig()"))
+                }
+                DescriptorPublicKey::Single(SinglePub {
+                    key: SinglePubKey::FullKey(ref key),
+                    ..
+                }) if !key.compressed => {
+                    return Err(DescriptorKeyParseError(
+                        "uncompressed keys are not allowed in musig()",
+                    ))
+                }
+                DescriptorPublicKey::MultiXPub(_) => {
+                    return Err(DescriptorKeyParseError(
+                        "multipath keys are not supported in musig()",
+                    ))
+                }
+                DescriptorPublicKey::Musig(_) => {
+                    return Err(DescriptorKeyParseError("musig() expressions can't be nested"))
+                }
+                DescriptorPublicKey::Single(_) | DescriptorPublicKey::XPub(_) => {}
+            }
+        }
+        Ok(DescriptorMusigKey(keys))
+    }
+
+    /// Get the list of aggregated keys.
+    pub fn keys(&self) -> &[DescriptorPublicKey] { &self.0 }
+
+    /// Get the list of aggregated keys.
+    pub fn into_keys(self) -> Vec<DescriptorPublicKey> { self.0 }
+}
+
 /// Single public key without any origin or range information.
 #[derive(Debug, Eq, PartialEq, Clone, Ord, PartialOrd, Hash)]
 pub enum SinglePubKey {
@@ -116,6 +179,48 @@ pub enum SinglePubKey {
 #[derive(Debug, Eq, PartialEq, Clone, Ord, PartialOrd, Hash)]
 pub struct DefiniteDescriptorKey(DescriptorPublicKey);
 
+impl DescriptorSecretKey {
+    /// Overwrites the private keys and chain codes held by this key.
+    #[cfg(any(feature = "zeroize", test))]
+    fn erase(&mut self) {
+        match *self {
+            DescriptorSecretKey::Single(ref mut sk) => sk.key.inner.non_secure_erase(),
+            DescriptorSecretKey::XPrv(ref mut xprv) => erase_xpriv(&mut xprv.xkey),
+            DescriptorSecretKey::MultiXPrv(ref mut xprv) => erase_xpriv(&mut xprv.xkey),
+        }
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl zeroize::Zeroize for DescriptorSecretKey {
+    fn zeroize(&mut self) { self.erase() }
+}
+
+#[cfg(feature = "zeroize")]
+impl Drop for DescriptorSecretKey {
+    fn drop(&mut self) { self.erase() }
+}
+
+#[cfg(feature = "zeroize")]
+impl zeroize::ZeroizeOnDrop for DescriptorSecretKey {}
+
+/// Overwrites the private key and chain code of an intermediate xpriv.
+///
+/// The secret key is overwritten by `non_secure_erase`, which like `zeroize` uses
+/// a volatile write, with a fixed valid key rather than zeroes. The chain code is
+/// zeroed with a volatile write only with the `zeroize` feature; without it the
+/// plain write may be optimized away.
+pub(crate) fn erase_xpriv(xprv: &mut bip32::Xpriv) {
+    xprv.private_key.non_secure_erase();
+    let chain_code: &mut [u8; 32] = xprv.chain_code.as_mut();
+    #[cfg(feature = "zeroize")]
+    zeroize::Zeroize::zeroize(chain_code);
+    #[cfg(not(feature = "zeroize"))]
+    {
+        *chain_code = [0; 32];
+    }
+}
+
 impl fmt::Display for DescriptorSecretKey {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
@@ -220,11 +325,12 @@ impl DescriptorXKey<bip32::Xpriv> {
         let hardened_path = &self.derivation_path[..last_hardened_idx];
         let unhardened_path = &self.derivation_path[last_hardened_idx..];
 
-        let xprv = self
+        let mut xprv = self
             .xkey
             .derive_priv(secp, &hardened_path)
             .map_err(|_| DescriptorKeyParseError("Unable to derive the hardened steps"))?;
         let xpub = bip32::Xpub::from_priv(secp, &xprv);
+        erase_xpriv(&mut xprv);
 
         let origin = match &self.origin {
             Some((fingerprint, path)) => {
@@ -292,11 +398,12 @@ impl DescriptorMultiXKey<bip32::Xpriv> {
         let hardened_path = &shared_prefix[..last_hardened_idx];
         let unhardened_path = &shared_prefix[last_hardened_idx..];
 
-        let xprv = self
+        let mut xprv = self
             .xkey
             .derive_priv(secp, &hardened_path)
             .map_err(|_| DescriptorKeyParseError("Unable to derive the hardened steps"))?;
         let xpub = bip32::Xpub::from_priv(secp, &xprv);
+        erase_xpriv(&mut xprv);
 
         let origin = match &self.origin {
             Some((fingerprint, path)) => {
@@ -371,6 +478,16 @@ impl fmt::Display for DescriptorPublicKey {
                 }
                 Ok(())
             }
+            DescriptorPublicKey::Musig(ref musig) => {
+                f.write_str("musig(")?;
+                for (i, key) in musig.keys().iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(",")?;
+                    }
+                    key.fmt(f)?;
+                }
+                f.write_str(")")
+            }
         }
     }
 }
@@ -414,21 +531,19 @@ impl DescriptorSecretKey {
     pub fn into_single_keys(self) -> Vec<DescriptorSecretKey> {
         match self {
             DescriptorSecretKey::Single(..) | DescriptorSecretKey::XPrv(..) => vec![self],
-            DescriptorSecretKey::MultiXPrv(xpub) => {
-                let DescriptorMultiXKey { origin, xkey, derivation_paths, wildcard } = xpub;
-                derivation_paths
-                    .into_paths()
-                    .into_iter()
-                    .map(|derivation_path| {
-                        DescriptorSecretKey::XPrv(DescriptorXKey {
-                            origin: origin.clone(),
-                            xkey,
-                            derivation_path,
-                            wildcard,
-                        })
+            DescriptorSecretKey::MultiXPrv(ref xpub) => xpub
+                .derivation_paths
+                .paths()
+                .iter()
+                .map(|derivation_path| {
+                    DescriptorSecretKey::XPrv(DescriptorXKey {
+                        origin: xpub.origin.clone(),
+                        xkey: xpub.xkey,
+                        derivation_path: derivation_path.clone(),
+                        wildcard: xpub.wildcard,
                     })
-                    .collect()
-            }
+                })
+                .collect(),
         }
     }
 }
@@ -483,6 +598,14 @@ impl FromStr for DescriptorPublicKey {
     type Err = DescriptorKeyParseError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(keys) = s.strip_prefix("musig(").and_then(|s| s.strip_suffix(')')) {
+            let keys = keys
+                .split(',')
+                .map(DescriptorPublicKey::from_str)
+                .collect::<Result<Vec<_>, _>>()?;
+            return DescriptorMusigKey::new(keys).map(DescriptorPublicKey::Musig);
+        }
+
         // A "raw" public key without any origin is the least we accept.
         if s.len() < 64 {
             return Err(DescriptorKeyParseError(
@@ -549,6 +672,8 @@ pub enum ConversionError {
     HardenedChild,
     /// Attempted to convert a key with multiple derivation paths to a bitcoin public key
     MultiKey,
+    /// Attempted to convert a key with a wildcard to a definite key without a derivation index
+    Wildcard,
 }
 
 impl fmt::Display for ConversionError {
@@ -556,6 +681,7 @@ impl fmt::Display for ConversionError {
         f.write_str(match *self {
             ConversionError::HardenedChild => "hardened child step in bip32 path",
             ConversionError::MultiKey => "multiple existing keys",
+            ConversionError::Wildcard => "wildcard in a key without a derivation index",
         })
     }
 }
@@ -566,15 +692,18 @@ impl error::Error for ConversionError {
         use self::ConversionError::*;
 
         match self {
-            HardenedChild | MultiKey => None,
+            HardenedChild | MultiKey | Wildcard => None,
         }
     }
 }
 
 impl DescriptorPublicKey {
     /// The fingerprint of the master key associated with this key, `0x00000000` if none.
+    ///
+    /// MuSig2 aggregate keys have no master key.
     pub fn master_fingerprint(&self) -> bip32::Fingerprint {
         match *self {
+            DescriptorPublicKey::Musig(_) => bip32::Fingerprint::from([0; 4]),
             DescriptorPublicKey::XPub(ref xpub) => {
                 if let Some((fingerprint, _)) = xpub.origin {
                     fingerprint
@@ -616,7 +745,8 @@ impl DescriptorPublicKey {
     /// can get full paths by appending one additional derivation step, according
     /// to the wildcard type (hardened or normal).
     ///
-    /// For multipath extended keys, this returns `None`.
+    /// For multipath extended keys, this returns `None`. For MuSig2 aggregate keys, this
+    /// returns an empty path.
     pub fn full_derivation_path(&self) -> Option<bip32::DerivationPath> {
         match *self {
             DescriptorPublicKey::XPub(ref xpub) => {
@@ -635,6 +765,7 @@ impl DescriptorPublicKey {
                 })
             }
             DescriptorPublicKey::MultiXPub(_) => None,
+            DescriptorPublicKey::Musig(_) => Some(bip32::DerivationPath::from(vec![])),
         }
     }
 
@@ -675,6 +806,9 @@ impl DescriptorPublicKey {
             DescriptorPublicKey::Single(..) => false,
             DescriptorPublicKey::XPub(ref xpub) => xpub.wildcard != Wildcard::None,
             DescriptorPublicKey::MultiXPub(ref xpub) => xpub.wildcard != Wildcard::None,
+            DescriptorPublicKey::Musig(ref musig) => {
+                musig.keys().iter().any(|key| key.has_wildcard())
+            }
         }
     }
 
@@ -691,6 +825,7 @@ impl DescriptorPublicKey {
     ///
     /// - If this key is not an xpub, returns `self`.
     /// - If this key is an xpub but does not have a wildcard, returns `self`.
+    /// - If this key is a MuSig2 aggregate, returns the aggregate of its keys at derivation `index`.
     /// - Otherwise, returns the xpub at derivation `index` (removing the wildcard).
     ///
     /// # Errors
@@ -722,6 +857,17 @@ impl DescriptorPublicKey {
                 })
             }
             DescriptorPublicKey::MultiXPub(_) => return Err(ConversionError::MultiKey),
+            DescriptorPublicKey::Musig(musig) => {
+                let keys = musig
+                    .into_keys()
+                    .into_iter()
+                    .map(|key| {
+                        key.at_derivation_index(index)
+                            .map(DescriptorPublicKey::from)
+                    })
+                    .collect::<Result<_, _>>()?;
+                DescriptorPublicKey::Musig(DescriptorMusigKey(keys))
+            }
         };
 
         Ok(DefiniteDescriptorKey::new(definite)
@@ -731,19 +877,23 @@ impl DescriptorPublicKey {
     /// Whether or not this key has multiple derivation paths.
     pub fn is_multipath(&self) -> bool {
         match *self {
-            DescriptorPublicKey::Single(..) | DescriptorPublicKey::XPub(..) => false,
+            DescriptorPublicKey::Single(..)
+            | DescriptorPublicKey::XPub(..)
+            | DescriptorPublicKey::Musig(..) => false,
             DescriptorPublicKey::MultiXPub(_) => true,
         }
     }
 
     /// Get as many keys as derivation paths in this key.
     ///
-    /// For raw public key and single-path extended keys it will return the key itself.
-    /// For multipath extended keys it will return a single-path extended key per derivation
-    /// path.
+    /// For raw public key, single-path extended keys and MuSig2 aggregate keys it will return
+    /// the key itself. For multipath extended keys it will return a single-path extended key per
+    /// derivation path.
     pub fn into_single_keys(self) -> Vec<DescriptorPublicKey> {
         match self {
-            DescriptorPublicKey::Single(..) | DescriptorPublicKey::XPub(..) => vec![self],
+            DescriptorPublicKey::Single(..)
+            | DescriptorPublicKey::XPub(..)
+            | DescriptorPublicKey::Musig(..) => vec![self],
             DescriptorPublicKey::MultiXPub(xpub) => {
                 let DescriptorMultiXKey { origin, xkey, derivation_paths, wildcard } = xpub;
                 derivation_paths
@@ -1041,27 +1191,37 @@ impl MiniscriptKey for DescriptorPublicKey {
     type Ripemd160 = ripemd160::Hash;
     type Hash160 = hash160::Hash;
 
-    fn is_uncompressed(&self) -> bool {
+    fn uncompressed_key(&self) -> Option<bitcoin::PublicKey> {
         match self {
             DescriptorPublicKey::Single(SinglePub {
                 key: SinglePubKey::FullKey(ref key), ..
-            }) => key.is_uncompressed(),
-            _ => false,
+            }) => key.uncompressed_key(),
+            _ => None,
         }
     }
 
+    /// MuSig2 aggregate keys are x-only, they can only be used in Taproot.
     fn is_x_only_key(&self) -> bool {
         matches!(
             self,
-            DescriptorPublicKey::Single(SinglePub { key: SinglePubKey::XOnly(ref _key), .. })
+            DescriptorPublicKey::Single(SinglePub { key: SinglePubKey::XOnly(_), .. })
+                | DescriptorPublicKey::Musig(_)
         )
     }
 
+    fn is_musig_key(&self) -> bool { matches!(self, DescriptorPublicKey::Musig(_)) }
+
     fn num_der_paths(&self) -> usize {
         match self {
             DescriptorPublicKey::Single(_) => 0,
             DescriptorPublicKey::XPub(_) => 1,
             DescriptorPublicKey::MultiXPub(xpub) => xpub.derivation_paths.paths().len(),
+            DescriptorPublicKey::Musig(musig) => musig
+                .keys()
+                .iter()
+                .map(|key| key.num_der_paths())
+                .max()
+                .unwrap_or(0),
         }
     }
 }
@@ -1070,7 +1230,8 @@ impl DefiniteDescriptorKey {
     /// Computes the public key corresponding to this descriptor key.
     /// When deriving from an XOnlyPublicKey, it adds the default 0x02 y-coordinate
     /// and returns the obtained full [`bitcoin::PublicKey`]. All BIP32 derivations
-    /// always return a compressed key
+    /// always return a compressed key. MuSig2 aggregate keys are derived by aggregating
+    /// the derived keys.
     ///
     /// Will return an error if the descriptor key has any hardened derivation steps in its path. To
     /// avoid this error you should replace any such public keys first with [`crate::Descriptor::translate_pk`].
@@ -1098,14 +1259,25 @@ impl DefiniteDescriptorKey {
             DescriptorPublicKey::MultiXPub(_) => {
                 unreachable!("A definite key cannot contain a multipath key.")
             }
+            DescriptorPublicKey::Musig(ref musig) => {
+                let mut keys = musig
+                    .keys()
+                    .iter()
+                    .map(|key| DefiniteDescriptorKey(key.clone()).derive_public_key(secp))
+                    .collect::<Result<Vec<_>, _>>()?;
+                // The `KeySort` algorithm of BIP 327, as BIP 390 specifies
+                keys.sort_by_key(|key| key.inner.serialize());
+                Ok(bitcoin::PublicKey::new(musig_key_agg(&keys)))
+            }
         }
     }
 
-    /// Construct an instance from a descriptor key and a derivation index
+    /// Construct an instance from a descriptor key
     ///
-    /// Returns `None` if the key contains a wildcard
+    /// Returns `None` if the key contains a wildcard or multiple derivation paths, since those
+    /// don't correspond to a single public key.
     fn new(key: DescriptorPublicKey) -> Option<Self> {
-        if key.has_wildcard() {
+        if key.has_wildcard() || key.is_multipath() {
             None
         } else {
             Some(Self(key))
@@ -1133,13 +1305,26 @@ impl DefiniteDescriptorKey {
     pub fn into_descriptor_public_key(self) -> DescriptorPublicKey { self.0 }
 }
 
+/// Aggregates `keys` with the `KeyAgg` algorithm of BIP 327, in the order they are given.
+pub(crate) fn musig_key_agg(keys: &[bitcoin::PublicKey]) -> secp256k1::PublicKey {
+    let keys = keys
+        .iter()
+        .map(|key| {
+            secp256k1_musig::PublicKey::from_byte_array_compressed(key.inner.serialize())
+                .expect("valid key")
+        })
+        .collect::<Vec<_>>();
+    let aggregate = KeyAggCache::new(&keys.iter().collect::<Vec<_>>()).agg_pk_full();
+    secp256k1::PublicKey::from_slice(&aggregate.serialize()).expect("valid key")
+}
+
 impl FromStr for DefiniteDescriptorKey {
     type Err = DescriptorKeyParseError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         let inner = DescriptorPublicKey::from_str(s)?;
         DefiniteDescriptorKey::new(inner).ok_or(DescriptorKeyParseError(
-            "cannot parse key with a wilcard as a DerivedDescriptorKey",
+            "cannot parse key with a wildcard or multiple derivation paths as a DefiniteDescriptorKey",
         ))
     }
 }
@@ -1154,10 +1339,19 @@ impl MiniscriptKey for DefiniteDescriptorKey {
     type Ripemd160 = ripemd160::Hash;
     type Hash160 = hash160::Hash;
 
-    fn is_uncompressed(&self) -> bool { self.0.is_uncompressed() }
+    fn uncompressed_key(&self) -> Option<bitcoin::PublicKey> { self.0.uncompressed_key() }
 
     fn is_x_only_key(&self) -> bool { self.0.is_x_only_key() }
 
+    fn is_musig_key(&self) -> bool { self.0.is_musig_key() }
+
+    fn sorting_key(&self) -> Option<XOnlyPublicKey> {
+        let secp = Secp256k1::verification_only();
+        self.derive_public_key(&secp)
+            .ok()
+            .map(|pk| pk.inner.x_only_public_key().0)
+    }
+
     fn num_der_paths(&self) -> usize { self.0.num_der_paths() }
 }
 
@@ -1176,6 +1370,21 @@ impl ToPublicKey for DefiniteDescriptorKey {
     fn to_hash160(hash: &hash160::Hash) -> hash160::Hash { *hash }
 }
 
+impl TryFrom<DescriptorPublicKey> for DefiniteDescriptorKey {
+    type Error = ConversionError;
+
+    /// Converts a key without wildcard into a definite key, without needing a derivation index.
+    fn try_from(key: DescriptorPublicKey) -> Result<Self, ConversionError> {
+        if key.is_multipath() {
+            Err(ConversionError::MultiKey)
+        } else if key.has_wildcard() {
+            Err(ConversionError::Wildcard)
+        } else {
+            Ok(Self(key))
+        }
+    }
+}
+
 impl From<DefiniteDescriptorKey> for DescriptorPublicKey {
     fn from(d: DefiniteDescriptorKey) -> Self { d.0 }
 }
@@ -1209,15 +1418,16 @@ impl Serialize for DescriptorPublicKey {
 mod test {
     use core::str::FromStr;
 
-    use bitcoin::bip32;
+    use bitcoin::{bip32, secp256k1};
     #[cfg(feature = "serde")]
     use serde_test::{assert_tokens, Token};
 
     use super::{
-        DescriptorKeyParseError, DescriptorMultiXKey, DescriptorPublicKey, DescriptorSecretKey,
-        MiniscriptKey, Wildcard,
+        ConversionError, DefiniteDescriptorKey, DescriptorKeyParseError, DescriptorMultiXKey,
+        DescriptorPublicKey, DescriptorSecretKey, MiniscriptKey, Wildcard,
     };
     use crate::prelude::*;
+    use crate::ToPublicKey;
 
     #[test]
     fn parse_descriptor_key_errors() {
@@ -1376,7 +1586,7 @@ mod test {
     fn get_multipath_xprv(key_str: &str) -> DescriptorMultiXKey<bip32::Xpriv> {
         let desc_key = DescriptorSecretKey::from_str(key_str).unwrap();
         match desc_key {
-            DescriptorSecretKey::MultiXPrv(xprv) => xprv,
+            DescriptorSecretKey::MultiXPrv(ref xprv) => xprv.clone(),
             _ => unreachable!(),
         }
     }
@@ -1572,12 +1782,55 @@ mod test {
         get_multipath_xprv("tprv8ZgxMBicQKsPcwcD4gSnMti126ZiETsuX7qwrtMypr6FBwAP65puFn4v6c3jrN9VwtMRMph6nyT63NrfUL4C3nBzPcduzVSuHD7zbX2JKVc/1/2/<3;4>/5/6'").to_public(&secp).unwrap_err();
     }
 
+    #[test]
+    fn definite_key_conversions() {
+        let xpub = "xpub6ERApfZwUNrhLCkDtcHTcxd75RbzS1ed54G1LkBUHQVHQKqhMkhgbmJbZRkrgZw4koxb5JaHWkY4ALHY2grBGRjaDMzQLcgJvLJuZZvRcEL";
+
+        let key = DescriptorPublicKey::from_str(&format!("{}/1/2", xpub)).unwrap();
+        let definite = DefiniteDescriptorKey::try_from(key.clone()).unwrap();
+        assert_eq!(definite, key.clone().at_derivation_index(0).unwrap());
+        assert_eq!(DescriptorPublicKey::from(definite), key);
+
+        let key = DescriptorPublicKey::from_str(&format!("{}/1/*", xpub)).unwrap();
+        assert_eq!(DefiniteDescriptorKey::try_from(key), Err(ConversionError::Wildcard));
+        assert!(DefiniteDescriptorKey::from_str(&format!("{}/1/*", xpub)).is_err());
+
+        // Multipath keys don't correspond to a single public key even without a wildcard
+        let key = DescriptorPublicKey::from_str(&format!("{}/<0;1>/2", xpub)).unwrap();
+        assert_eq!(DefiniteDescriptorKey::try_from(key), Err(ConversionError::MultiKey));
+        assert!(DefiniteDescriptorKey::from_str(&format!("{}/<0;1>/2", xpub)).is_err());
+    }
+
+    #[test]
+    fn erase_secret_keys() {
+        let mut xprv = DescriptorSecretKey::from_str("[d34db33f/0']tprv8ZgxMBicQKsPcwcD4gSnMti126ZiETsuX7qwrtMypr6FBwAP65puFn4v6c3jrN9VwtMRMph6nyT63NrfUL4C3nBzPcduzVSuHD7zbX2JKVc/<0;1>/*").unwrap();
+        let original = xprv.clone();
+        xprv.erase();
+        match (&xprv, &original) {
+            (DescriptorSecretKey::MultiXPrv(erased), DescriptorSecretKey::MultiXPrv(original)) => {
+                assert_ne!(erased.xkey.private_key, original.xkey.private_key);
+                assert_eq!(erased.xkey.chain_code.as_bytes(), &[0; 32]);
+                // Only the secret material is erased
+                assert_eq!(erased.origin, original.origin);
+                assert_eq!(erased.derivation_paths, original.derivation_paths);
+            }
+            _ => unreachable!(),
+        }
+
+        let mut single =
+            DescriptorSecretKey::from_str("5HueCGU8rMjxEXxiPuD5BDku4MkFqeZyd4dZ1jvhTVqvbTLvyTJ")
+                .unwrap();
+        let original = single.clone();
+        single.erase();
+        assert_ne!(single, original);
+    }
+
     #[test]
     fn test_parse_wif() {
         let secret_key = "[0dd03d09/0'/1/2']5HueCGU8rMjxEXxiPuD5BDku4MkFqeZyd4dZ1jvhTVqvbTLvyTJ"
             .parse()
             .unwrap();
-        if let DescriptorSecretKey::Single(single) = secret_key {
+        if let DescriptorSecretKey::Single(ref single) = secret_key {
             assert_eq!(
                 single.key.inner,
                 "0C28FCA386C7A227600B2FE50B7CAE11EC86D3BF1FBE471BE89827E19D72AA1D"
@@ -1593,6 +1846,126 @@ mod test {
         }
     }
 
+    #[test]
+    fn musig_keys() {
+        // Test vectors of the BIP 327 KeyAgg algorithm
+        let x = [
+            "02f9308a019258c31049344f85f89d5229b531c845836f99b08601f113bce036f9",
+            "03dff1d77f2a671c5f36183726db2341be58feae1da2deced843240f7b502ba659",
+            "023590a94e768f8e1815c2f24b4d80a8e3149316c3518ce7b7ad338368d038ca66",
+        ];
+        for (indices, aggregate) in [
+            (
+                &[0, 1, 2][..],
+                "90539eede565f5d054f32cc0c220126889ed1e5d193baf15aef344fe59d4610c",
+            ),
+            (
+                &[2, 1, 0][..],
+                "6204de8b083426dc6eaf9502d27024d53fc826bf7d2012148a0575435df54b2b",
+            ),
+            (
+                &[0, 0, 0][..],
+                "b436e3bad62b8cd409969a224731c193d051162d8c5ae8b109306127da3aa935",
+            ),
+            (
+                &[0, 0, 1, 1][..],
+                "69bc22bfa5d106306e48a20679de1d7389386124d07571d0d872686028c26a3e",
+            ),
+        ] {
+            let keys = indices
+                .iter()
+                .map(|&i| bitcoin::PublicKey::from_str(x[i]).unwrap())
+                .collect::<Vec<_>>();
+            let aggregate = bitcoin::key::XOnlyPublicKey::from_str(aggregate).unwrap();
+            assert_eq!(super::musig_key_agg(&keys).x_only_public_key().0, aggregate);
+        }
+
+        // `musig()` sorts the keys with KeySort before aggregating them, so the vectors whose
+        // keys are sorted hold for any order of the keys
+        for (keys, aggregate) in [
+            (
+                [x[0], x[0], x[0]].join(","),
+                "b436e3bad62b8cd409969a224731c193d051162d8c5ae8b109306127da3aa935",
+            ),
+            (
+                [x[1], x[0], x[1], x[0]].join(","),
+                "69bc22bfa5d106306e48a20679de1d7389386124d07571d0d872686028c26a3e",
+            ),
+        ] {
+            let musig = format!("musig({})", keys);
+            let key = DefiniteDescriptorKey::from_str(&musig).unwrap();
+            assert_eq!(key.to_string(), musig);
+            assert!(key.is_x_only_key());
+            assert_eq!(key.to_x_only_pubkey().to_string(), aggregate);
+        }
+        let sorted =
+            DefiniteDescriptorKey::from_str(&format!("musig({},{},{})", x[2], x[0], x[1])).unwrap();
+        let unsorted =
+            DefiniteDescriptorKey::from_str(&format!("musig({},{},{})", x[0], x[1], x[2])).unwrap();
+        assert_ne!(sorted, unsorted);
+        assert_eq!(sorted.to_public_key(), unsorted.to_public_key());
+
+        // Aggregates of extended keys are derived by deriving the keys
+        let xpubs = [
+            "xpub6ERApfZwUNrhLCkDtcHTcxd75RbzS1ed54G1LkBUHQVHQKqhMkhgbmJbZRkrgZw4koxb5JaHWkY4ALHY2grBGRjaDMzQLcgJvLJuZZvRcEL",
+            "xpub6FC1fXFP1GXLX5TKtcjHGT4q89SDRehkQLtbKJ2PzWcvbBHtyDsJPLtpLtkGqYNYZdVVAjRQ5kug9CsapegmmeRutpP7PW4u4wVF9JfkDhw",
+        ];
+        let musig = DescriptorPublicKey::from_str(&format!(
+            "musig([d34db33f/48'/0'/0'/2']{}/0/*,{}/1)",
+            xpubs[0], xpubs[1]
+        ))
+        .unwrap();
+        assert!(musig.has_wildcard());
+        assert_eq!(musig.num_der_paths(), 1);
+        assert_eq!(musig.master_fingerprint(), bip32::Fingerprint::from([0; 4]));
+        let derived = musig.at_derivation_index(7).unwrap();
+        assert_eq!(
+            derived.to_string(),
+            format!("musig([d34db33f/48'/0'/0'/2']{}/0/7,{}/1)", xpubs[0], xpubs[1])
+        );
+        let secp = secp256k1::Secp256k1::verification_only();
+        let keys = [format!("{}/0/7", xpubs[0]), format!("{}/1", xpubs[1])]
+            .iter()
+            .map(|key| {
+                DefiniteDescriptorKey::from_str(key)
+                    .unwrap()
+                    .derive_public_key(&secp)
+                    .unwrap()
+                    .to_string()
+            })
+            .collect::<Vec<_>>();
+        let single_keys =
+            DefiniteDescriptorKey::from_str(&format!("musig({})", keys.join(","))).unwrap();
+        assert_eq!(
+            derived.derive_public_key(&secp).unwrap(),
+            single_keys.derive_public_key(&secp).unwrap()
+        );
+
+        for (key, err) in [
+            ("musig()", "Key too short (<66 char), doesn't match any format"),
+            (
+                "musig(f9308a019258c31049344f85f89d5229b531c845836f99b08601f113bce036f9)",
+                "x-only keys are not allowed in musig()",
+            ),
+            (
+                "musig(04a34b99f22c790c4e36b2b3c2c35a36db06226e41c692fc82b8b56ac1c540c5bd5b8dec5235a0fa8722476c7709c02559e3aa73aa03918ba2d492eea75abea235)",
+                "uncompressed keys are not allowed in musig()",
+            ),
+            (
+                &format!("musig({}/<0;1>/*)", xpubs[0]),
+                "multipath keys are not supported in musig()",
+            ),
+        ] {
+            assert_eq!(
+                DescriptorPublicKey::from_str(key).unwrap_err().to_string(),
+                err,
+                "{}",
+                key
+            );
+        }
+        assert!(DescriptorPublicKey::from_str(&format!("musig(musig({}),{})", x[0], x[1])).is_err());
+    }
+
     #[test]
     #[cfg(feature = "serde")]
     fn test_descriptor_public_key_serde() {
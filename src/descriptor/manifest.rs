@@ -0,0 +1,287 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! # Signing Manifests
+//!
+//! Decomposition of a descriptor into its spending paths, grouped by cosigner,
+//! for coordinators to brief each participant of a multi-party wallet on what
+//! they may be asked to sign.
+//!
+
+use core::fmt;
+
+use super::{Descriptor, DescriptorType};
+use crate::policy::semantic::{Policy, PolicyError, SpendPathCount};
+use crate::policy::Liftable;
+use crate::prelude::*;
+use crate::{AbsLockTime, Error, MiniscriptKey, RelLockTime};
+
+/// Maximum number of spending paths of a descriptor for which a signing
+/// manifest is built.
+const MAX_MANIFEST_PATHS: usize = 1000;
+
+/// The context in which the signatures of a spending path are made
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum SighashContext {
+    /// Pre-segwit ECDSA signatures
+    Legacy,
+    /// Segwit v0 ECDSA signatures, as specified in BIP 143
+    SegwitV0,
+    /// A Taproot key spend, signed with the tweaked internal key, or with the output key of a
+    /// `rawtr()` descriptor
+    TaprootKeySpend,
+    /// A Taproot script spend of the leaf at this index, in the order of
+    /// [`Descriptor::tap_tree_iter`]
+    TaprootScriptSpend(usize),
+}
+
+impl fmt::Display for SighashContext {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SighashContext::Legacy => f.write_str("legacy"),
+            SighashContext::SegwitV0 => f.write_str("segwit v0"),
+            SighashContext::TaprootKeySpend => f.write_str("taproot key spend"),
+            SighashContext::TaprootScriptSpend(leaf) => {
+                write!(f, "taproot script spend of leaf {}", leaf)
+            }
+        }
+    }
+}
+
+/// A minimal set of conditions under which a descriptor can be spent
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct SpendPath<Pk: MiniscriptKey> {
+    /// The context of the signatures
+    pub context: SighashContext,
+    /// The keys which must all sign
+    pub cosigners: Vec<Pk>,
+    /// The relative timelock the spend must wait for, if any
+    pub older: Option<RelLockTime>,
+    /// The absolute timelock the spend must wait for, if any
+    pub after: Option<AbsLockTime>,
+    /// The number of hash preimages which must be revealed
+    pub preimages: usize,
+}
+
+impl<Pk: MiniscriptKey> SpendPath<Pk> {
+    fn new(context: SighashContext, conditions: Vec<Policy<Pk>>) -> Self {
+        let mut path =
+            SpendPath { context, cosigners: vec![], older: None, after: None, preimages: 0 };
+        for condition in conditions {
+            match condition {
+                Policy::Key(pk) => path.cosigners.push(pk),
+                Policy::Older(n) => path.older = path.older.max(Some(n)),
+                Policy::After(n) => path.after = path.after.max(Some(n)),
+                Policy::Sha256(..)
+                | Policy::Hash256(..)
+                | Policy::Ripemd160(..)
+                | Policy::Hash160(..) => path.preimages += 1,
+                Policy::Trivial | Policy::Unsatisfiable | Policy::Thresh(..) => {}
+            }
+        }
+        path
+    }
+}
+
+impl<Pk: MiniscriptKey> fmt::Display for SpendPath<Pk> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: ", self.context)?;
+        for (i, pk) in self.cosigners.iter().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            write!(f, "{}", pk)?;
+        }
+        if self.cosigners.is_empty() {
+            f.write_str("no signature")?;
+        }
+        if let Some(n) = self.older {
+            write!(f, ", older({})", n)?;
+        }
+        if let Some(n) = self.after {
+            write!(f, ", after({})", n)?;
+        }
+        if self.preimages > 0 {
+            write!(f, ", {} preimage(s)", self.preimages)?;
+        }
+        Ok(())
+    }
+}
+
+/// The spending paths a cosigner participates in
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct CosignerBrief<Pk: MiniscriptKey> {
+    /// The key of the cosigner
+    pub key: Pk,
+    /// The indexes of the paths of the [`SigningManifest`] requiring a signature
+    /// by this key
+    pub paths: Vec<usize>,
+}
+
+/// The spending paths of a descriptor, grouped by cosigner
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct SigningManifest<Pk: MiniscriptKey> {
+    /// All the spending paths
+    pub paths: Vec<SpendPath<Pk>>,
+    /// A brief for each key of the descriptor which signs in any path, in order
+    /// of first appearance
+    pub cosigners: Vec<CosignerBrief<Pk>>,
+}
+
+impl<Pk: MiniscriptKey> SigningManifest<Pk> {
+    /// The brief for `key`, if it signs in any path
+    pub fn brief(&self, key: &Pk) -> Option<&CosignerBrief<Pk>> {
+        self.cosigners.iter().find(|brief| brief.key == *key)
+    }
+
+    /// The paths `brief` participates in
+    pub fn paths_of<'a>(
+        &'a self,
+        brief: &'a CosignerBrief<Pk>,
+    ) -> impl Iterator<Item = &'a SpendPath<Pk>> + 'a {
+        brief.paths.iter().map(move |&i| &self.paths[i])
+    }
+}
+
+impl<Pk: MiniscriptKey> Descriptor<Pk> {
+    /// Decomposes the descriptor into its spending paths, grouped by the
+    /// cosigners whose signature each path requires
+    ///
+    /// The key spend path of a Taproot descriptor is always listed, even if its
+    /// internal key is unspendable.
+    ///
+    /// # Errors
+    ///
+    /// If the descriptor can't be lifted, or if it has more than 1000 spending
+    /// paths, reported as [`PolicyError::TooManySpendPaths`].
+    pub fn signing_manifest(&self) -> Result<SigningManifest<Pk>, Error> {
+        let mut paths = vec![];
+        match *self {
+            Descriptor::Tr(ref tr) => {
+                paths.push(SpendPath::new(
+                    SighashContext::TaprootKeySpend,
+                    vec![Policy::Key(tr.internal_key().clone())],
+                ));
+                for (leaf, (_, ms)) in tr.iter_scripts().enumerate() {
+                    let policy = ms.lift()?.normalized();
+                    push_paths(&mut paths, SighashContext::TaprootScriptSpend(leaf), policy)?;
+                }
+            }
+            Descriptor::RawTr(ref rawtr) => paths.push(SpendPath::new(
+                SighashContext::TaprootKeySpend,
+                vec![Policy::Key(rawtr.as_inner().clone())],
+            )),
+            _ => {
+                let context = match self.desc_type() {
+                    DescriptorType::Bare
+                    | DescriptorType::Sh
+                    | DescriptorType::Pkh
+                    | DescriptorType::ShSortedMulti
+                    | DescriptorType::Data => SighashContext::Legacy,
+                    _ => SighashContext::SegwitV0,
+                };
+                push_paths(&mut paths, context, self.lift()?.normalized())?;
+            }
+        }
+
+        let mut cosigners: Vec<CosignerBrief<Pk>> = vec![];
+        for (i, path) in paths.iter().enumerate() {
+            for pk in &path.cosigners {
+                match cosigners.iter_mut().find(|brief| brief.key == *pk) {
+                    Some(brief) => brief.paths.push(i),
+                    None => cosigners.push(CosignerBrief { key: pk.clone(), paths: vec![i] }),
+                }
+            }
+        }
+        Ok(SigningManifest { paths, cosigners })
+    }
+}
+
+/// Appends the spending paths of `policy` to `paths`, failing if there are
+/// more than [`MAX_MANIFEST_PATHS`] in total.
+fn push_paths<Pk: MiniscriptKey>(
+    paths: &mut Vec<SpendPath<Pk>>,
+    context: SighashContext,
+    policy: Policy<Pk>,
+) -> Result<(), Error> {
+    let limit = MAX_MANIFEST_PATHS.saturating_sub(paths.len());
+    if policy.spend_path_count(limit) == SpendPathCount::ExceedsLimit {
+        return Err(Error::SemanticPolicy(PolicyError::TooManySpendPaths(MAX_MANIFEST_PATHS)));
+    }
+    paths.extend(
+        policy
+            .spend_paths_iter()
+            .map(|conditions| SpendPath::new(context, conditions)),
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use core::str::FromStr;
+
+    use super::*;
+
+    fn manifest(s: &str) -> SigningManifest<String> {
+        Descriptor::<String>::from_str(s)
+            .unwrap()
+            .signing_manifest()
+            .unwrap()
+    }
+
+    fn briefs(manifest: &SigningManifest<String>) -> Vec<(String, Vec<String>)> {
+        manifest
+            .cosigners
+            .iter()
+            .map(|brief| {
+                let paths = manifest.paths_of(brief).map(ToString::to_string).collect();
+                (brief.key.clone(), paths)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn signing_manifest() {
+        let m = manifest("wsh(or_d(multi(2,A,B,C),and_v(v:pk(D),older(144))))");
+        assert_eq!(m.paths.len(), 4);
+        assert_eq!(m.cosigners.len(), 4);
+        assert_eq!(
+            m.paths_of(m.brief(&"A".to_owned()).unwrap())
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+            vec!["segwit v0: A, B", "segwit v0: A, C"]
+        );
+        assert_eq!(
+            briefs(&m).last().unwrap(),
+            &("D".to_owned(), vec!["segwit v0: D, older(144)".to_owned()])
+        );
+        assert!(m.brief(&"E".to_owned()).is_none());
+
+        let m = manifest("tr(A,{pk(B),and_v(v:pk(A),after(100))})");
+        assert_eq!(
+            briefs(&m),
+            vec![
+                (
+                    "A".to_owned(),
+                    vec![
+                        "taproot key spend: A".to_owned(),
+                        "taproot script spend of leaf 1: A, after(100)".to_owned()
+                    ]
+                ),
+                ("B".to_owned(), vec!["taproot script spend of leaf 0: B".to_owned()]),
+            ]
+        );
+
+        let m = manifest("sh(and_v(v:pk(A),sha256(H)))");
+        assert_eq!(m.paths[0].to_string(), "legacy: A, 1 preimage(s)");
+
+        // Too many paths
+        let keys: Vec<_> = (0..20).map(|i| format!("K{}", i)).collect();
+        let desc = format!("wsh(multi(10,{}))", keys.join(","));
+        assert!(matches!(
+            Descriptor::<String>::from_str(&desc)
+                .unwrap()
+                .signing_manifest(),
+            Err(Error::SemanticPolicy(PolicyError::TooManySpendPaths(MAX_MANIFEST_PATHS)))
+        ));
+    }
+}
@@ -15,41 +15,89 @@ use core::fmt;
 use core::ops::Range;
 use core::str::{self, FromStr};
 
-use bitcoin::hashes::{hash160, ripemd160, sha256};
+use bitcoin::hashes::{hash160, ripemd160, sha256, Hash};
 use bitcoin::{
-    secp256k1, Address, Network, Script, ScriptBuf, TxIn, Weight, Witness, WitnessVersion,
+    secp256k1, Address, Amount, Network, OutPoint, Script, ScriptBuf, TxIn, TxOut, Weight, Witness,
+    WitnessVersion,
 };
 use sync::Arc;
 
 use crate::miniscript::decode::Terminal;
-use crate::miniscript::{satisfy, Legacy, Miniscript, Segwitv0};
-use crate::plan::{AssetProvider, Plan};
+use crate::miniscript::{satisfy, shuffle, Aliases, Legacy, Miniscript, Segwitv0};
+use crate::plan::{
+    ApprovedSatisfier, AssetProvider, Assets, BudgetAssets, ClassifyError, Plan, PlanBudget,
+    PlanError, Spendability, WalletUtxo,
+};
+use crate::policy::semantic::{SpendPathCount, SpendPaths};
+use crate::policy::Liftable;
 use crate::prelude::*;
+use crate::util::varint_len;
 use crate::{
-    expression, hash256, BareCtx, Error, ForEachKey, FromStrKey, MiniscriptKey, Satisfier,
-    ToPublicKey, TranslateErr, Translator,
+    expression, hash256, BareCtx, ChainTip, Error, ForEachKey, FromStrKey, KeyPermutation,
+    MiniscriptKey, PermutationError, Satisfier, SigType, ToPublicKey, TranslateErr, Translator,
 };
 
+mod address_batch;
+mod analysis;
 mod bare;
+mod commitment;
+mod compat;
+mod data;
+mod derivation_index;
+mod hardened;
+mod hash_locks;
+mod manifest;
+mod pretty;
+mod rawtr;
+mod scan;
 mod segwitv0;
 mod sh;
 mod sortedmulti;
+mod spend_paths;
 mod tr;
+mod tweak;
+mod wallet_policy;
+mod weights;
+mod wother;
 
 // Descriptor Exports
+pub use self::address_batch::AddressRecord;
+pub use self::analysis::{
+    analyze, Analysis, ResourceUsage, ScriptAnalysis, MAX_ANALYZED_SPEND_PATHS,
+};
 pub use self::bare::{Bare, Pkh};
+pub use self::commitment::ScriptCommitment;
+pub use self::compat::CompatFix;
+pub use self::data::Data;
+pub use self::derivation_index::{DerivationIndexIterator, DerivationRangeError};
+pub use self::hardened::{HardenedPolicy, HardenedViolation};
+pub use self::hash_locks::{HashLock, HashLockInfo};
+pub(crate) use self::key::{erase_xpriv, musig_key_agg};
+pub use self::manifest::{CosignerBrief, SighashContext, SigningManifest, SpendPath};
+pub use self::rawtr::RawTr;
+pub use self::scan::{ChainSource, GapScan, ScanError, ScanObject, ScriptActivity, SpkIter};
 pub use self::segwitv0::{Wpkh, Wsh, WshInner};
 pub use self::sh::{Sh, ShInner};
 pub use self::sortedmulti::SortedMultiVec;
-pub use self::tr::{TapTree, Tr};
+pub use self::spend_paths::{DetailedSpendPath, DetailedSpendPaths};
+pub(crate) use self::tr::parse_tr_tree_with_checksum;
+pub use self::tr::{
+    KeyPosition, RebalanceAdvice, RepeatedKey, TapTree, TapTreeConversionError, Tr, TrBuilder,
+};
+pub use self::tweak::{KeyTweak, TweakedDescriptor};
+pub use self::wallet_policy::{WalletPolicy, WalletPolicyError};
+pub use self::weights::WeightStats;
+pub use self::wother::Wother;
 
 pub mod checksum;
+pub mod infer;
 mod key;
+pub mod set;
 
 pub use self::key::{
     ConversionError, DefiniteDescriptorKey, DerivPaths, DescriptorKeyParseError,
-    DescriptorMultiXKey, DescriptorPublicKey, DescriptorSecretKey, DescriptorXKey, InnerXKey,
-    SinglePriv, SinglePub, SinglePubKey, Wildcard,
+    DescriptorMultiXKey, DescriptorMusigKey, DescriptorPublicKey, DescriptorSecretKey,
+    DescriptorXKey, InnerXKey, SinglePriv, SinglePub, SinglePubKey, Wildcard,
 };
 
 /// Alias type for a map of public key to secret key
@@ -58,8 +106,40 @@ pub use self::key::{
 /// [`Descriptor::parse_descriptor`], since the descriptor will always only contain
 /// public keys. This map allows looking up the corresponding secret key given a
 /// public key from the descriptor.
+///
+/// The secret keys are overwritten when the map, or any of its entries, is
+/// dropped.
 pub type KeyMap = BTreeMap<DescriptorPublicKey, DescriptorSecretKey>;
 
+/// How [`Descriptor::export_for_party`] replaces the keys of the other parties
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum KeyRedaction {
+    /// Replace each key with the HASH160 of its public key. For extended keys this is the hash
+    /// of the key expression without its origin, so that keys derived from the same extended
+    /// key at different paths get different placeholders.
+    KeyHash,
+    /// Replace each key with the bare public key it derives to at the given index.
+    DerivedAt(u32),
+}
+
+/// A satisfaction returned by [`Descriptor::get_satisfaction_with_fallback`]
+#[derive(Debug)]
+pub struct FallbackSatisfaction {
+    /// The witness
+    pub witness: Vec<Vec<u8>>,
+    /// The scriptSig
+    pub script_sig: ScriptBuf,
+    /// Why no non-malleable satisfaction could be produced, if the satisfaction
+    /// is possibly malleable
+    pub fallback_reason: Option<Error>,
+}
+
+impl FallbackSatisfaction {
+    /// Whether the satisfaction may be malleable, i.e. the non-malleable
+    /// satisfier failed
+    pub fn is_malleable(&self) -> bool { self.fallback_reason.is_some() }
+}
+
 /// Script descriptor
 #[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum Descriptor<Pk: MiniscriptKey> {
@@ -75,6 +155,12 @@ pub enum Descriptor<Pk: MiniscriptKey> {
     Wsh(Wsh<Pk>),
     /// Pay-to-Taproot
     Tr(Tr<Pk>),
+    /// Unspendable `OP_RETURN` output carrying data
+    Data(Data),
+    /// Output to a future witness version, which can't be satisfied
+    Wother(Wother),
+    /// Pay-to-Taproot with an untweaked output key, spendable through the key path only
+    RawTr(RawTr<Pk>),
 }
 
 impl<Pk: MiniscriptKey> From<Bare<Pk>> for Descriptor<Pk> {
@@ -107,6 +193,48 @@ impl<Pk: MiniscriptKey> From<Tr<Pk>> for Descriptor<Pk> {
     fn from(inner: Tr<Pk>) -> Self { Descriptor::Tr(inner) }
 }
 
+impl<Pk: MiniscriptKey> From<Data> for Descriptor<Pk> {
+    #[inline]
+    fn from(inner: Data) -> Self { Descriptor::Data(inner) }
+}
+
+impl<Pk: MiniscriptKey> From<Wother> for Descriptor<Pk> {
+    #[inline]
+    fn from(inner: Wother) -> Self { Descriptor::Wother(inner) }
+}
+
+impl<Pk: MiniscriptKey> From<RawTr<Pk>> for Descriptor<Pk> {
+    #[inline]
+    fn from(inner: RawTr<Pk>) -> Self { Descriptor::RawTr(inner) }
+}
+
+macro_rules! impl_try_from_descriptor {
+    ($variant:ident, $inner:ty) => {
+        impl<Pk: MiniscriptKey> TryFrom<Descriptor<Pk>> for $inner {
+            /// The descriptor, if it isn't of the requested type
+            type Error = Descriptor<Pk>;
+
+            #[inline]
+            fn try_from(desc: Descriptor<Pk>) -> Result<Self, Descriptor<Pk>> {
+                match desc {
+                    Descriptor::$variant(inner) => Ok(inner),
+                    desc => Err(desc),
+                }
+            }
+        }
+    };
+}
+
+impl_try_from_descriptor!(Bare, Bare<Pk>);
+impl_try_from_descriptor!(Pkh, Pkh<Pk>);
+impl_try_from_descriptor!(Wpkh, Wpkh<Pk>);
+impl_try_from_descriptor!(Sh, Sh<Pk>);
+impl_try_from_descriptor!(Wsh, Wsh<Pk>);
+impl_try_from_descriptor!(Tr, Tr<Pk>);
+impl_try_from_descriptor!(Data, Data);
+impl_try_from_descriptor!(Wother, Wother);
+impl_try_from_descriptor!(RawTr, RawTr<Pk>);
+
 /// Descriptor Type of the descriptor
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
 pub enum DescriptorType {
@@ -132,20 +260,28 @@ pub enum DescriptorType {
     ShWshSortedMulti,
     /// Tr Descriptor
     Tr,
+    /// Data carrier Descriptor
+    Data,
+    /// Future witness version Descriptor
+    Wother,
+    /// Raw Tr Descriptor
+    RawTr,
 }
 
 impl DescriptorType {
     /// Returns the segwit version implied by the descriptor type.
     ///
     /// This will return `Some(WitnessVersion::V0)` whether it is "native" segwitv0 or "wrapped" p2sh segwit.
+    /// It returns `None` for [`DescriptorType::Wother`], whose version isn't part of the type, see
+    /// [`Wother::version`].
     pub fn segwit_version(&self) -> Option<WitnessVersion> {
         use self::DescriptorType::*;
         match self {
-            Tr => Some(WitnessVersion::V1),
+            Tr | RawTr => Some(WitnessVersion::V1),
             Wpkh | ShWpkh | Wsh | ShWsh | ShWshSortedMulti | WshSortedMulti => {
                 Some(WitnessVersion::V0)
             }
-            Bare | Sh | Pkh | ShSortedMulti => None,
+            Bare | Sh | Pkh | ShSortedMulti | Data | Wother => None,
         }
     }
 }
@@ -240,6 +376,101 @@ impl<Pk: MiniscriptKey> Descriptor<Pk> {
         Ok(Descriptor::Tr(Tr::new(key, script)?))
     }
 
+    /// Create a new data carrier descriptor, i.e. an `OP_RETURN` output pushing `data`
+    /// Errors when the data is too large to be pushed
+    pub fn new_data(data: Vec<u8>) -> Result<Self, Error> { Ok(Descriptor::Data(Data::new(data)?)) }
+
+    /// Create a new descriptor for an output to the future witness `version` with `program`
+    /// Errors when the version is below 2 or the program isn't between 2 and 40 bytes long
+    pub fn new_wother(version: WitnessVersion, program: &[u8]) -> Result<Self, Error> {
+        Ok(Descriptor::Wother(Wother::new(version, program)?))
+    }
+
+    /// Create a new rawtr descriptor, paying to the untweaked output key `key`
+    /// Errors when the key isn't valid in Tap context
+    pub fn new_rawtr(key: Pk) -> Result<Self, Error> { Ok(Descriptor::RawTr(RawTr::new(key)?)) }
+
+    /// For a bare descriptor, returns the inner [`Bare`].
+    pub fn as_bare(&self) -> Option<&Bare<Pk>> {
+        if let Descriptor::Bare(ref inner) = self {
+            Some(inner)
+        } else {
+            None
+        }
+    }
+
+    /// For a `pkh` descriptor, returns the inner [`Pkh`].
+    pub fn as_pkh(&self) -> Option<&Pkh<Pk>> {
+        if let Descriptor::Pkh(ref inner) = self {
+            Some(inner)
+        } else {
+            None
+        }
+    }
+
+    /// For a `wpkh` descriptor, returns the inner [`Wpkh`].
+    pub fn as_wpkh(&self) -> Option<&Wpkh<Pk>> {
+        if let Descriptor::Wpkh(ref inner) = self {
+            Some(inner)
+        } else {
+            None
+        }
+    }
+
+    /// For an `sh` descriptor, including nested segwit ones, returns the inner [`Sh`].
+    pub fn as_sh(&self) -> Option<&Sh<Pk>> {
+        if let Descriptor::Sh(ref inner) = self {
+            Some(inner)
+        } else {
+            None
+        }
+    }
+
+    /// For a `wsh` descriptor, returns the inner [`Wsh`].
+    pub fn as_wsh(&self) -> Option<&Wsh<Pk>> {
+        if let Descriptor::Wsh(ref inner) = self {
+            Some(inner)
+        } else {
+            None
+        }
+    }
+
+    /// For a Taproot descriptor, returns the inner [`Tr`].
+    pub fn as_tr(&self) -> Option<&Tr<Pk>> {
+        if let Descriptor::Tr(ref inner) = self {
+            Some(inner)
+        } else {
+            None
+        }
+    }
+
+    /// For a data carrier descriptor, returns the inner [`Data`].
+    pub fn as_data(&self) -> Option<&Data> {
+        if let Descriptor::Data(ref inner) = self {
+            Some(inner)
+        } else {
+            None
+        }
+    }
+
+    /// For a future witness version descriptor, returns the inner [`Wother`].
+    pub fn as_wother(&self) -> Option<&Wother> {
+        if let Descriptor::Wother(ref inner) = self {
+            Some(inner)
+        } else {
+            None
+        }
+    }
+
+    /// For a `rawtr` descriptor, returns the inner [`RawTr`].
+    pub fn as_rawtr(&self) -> Option<&RawTr<Pk>> {
+        if let Descriptor::RawTr(ref inner) = self {
+            Some(inner)
+        } else {
+            None
+        }
+    }
+
     /// For a Taproot descriptor, returns the internal key.
     pub fn internal_key(&self) -> Option<&Pk> {
         if let Descriptor::Tr(ref tr) = self {
@@ -274,6 +505,27 @@ impl<Pk: MiniscriptKey> Descriptor<Pk> {
         tr::TapTreeIter::empty()
     }
 
+    /// Counts the spending paths of the descriptor, or returns
+    /// [`SpendPathCount::ExceedsLimit`] if there are more than `limit`.
+    ///
+    /// See [`semantic::Policy::spend_path_count`].
+    ///
+    /// # Errors
+    /// If the descriptor can't be lifted to a semantic policy.
+    pub fn spend_path_count(&self, limit: usize) -> Result<SpendPathCount, Error> {
+        Ok(self.lift()?.normalized().spend_path_count(limit))
+    }
+
+    /// Lazily enumerates the spending paths of the descriptor.
+    ///
+    /// See [`semantic::Policy::spend_paths_iter`].
+    ///
+    /// # Errors
+    /// If the descriptor can't be lifted to a semantic policy.
+    pub fn spend_paths_iter(&self) -> Result<SpendPaths<'_, Pk>, Error> {
+        Ok(SpendPaths::new(self.lift()?.normalized()))
+    }
+
     /// Get the [DescriptorType] of [Descriptor]
     pub fn desc_type(&self) -> DescriptorType {
         match *self {
@@ -294,6 +546,9 @@ impl<Pk: MiniscriptKey> Descriptor<Pk> {
                 WshInner::Ms(ref _ms) => DescriptorType::Wsh,
             },
             Descriptor::Tr(ref _tr) => DescriptorType::Tr,
+            Descriptor::Data(ref _data) => DescriptorType::Data,
+            Descriptor::Wother(ref _wother) => DescriptorType::Wother,
+            Descriptor::RawTr(ref _rawtr) => DescriptorType::RawTr,
         }
     }
 
@@ -314,6 +569,9 @@ impl<Pk: MiniscriptKey> Descriptor<Pk> {
             Descriptor::Wsh(ref wsh) => wsh.sanity_check(),
             Descriptor::Sh(ref sh) => sh.sanity_check(),
             Descriptor::Tr(ref tr) => tr.sanity_check(),
+            Descriptor::Data(ref data) => data.sanity_check(),
+            Descriptor::Wother(ref wother) => wother.sanity_check(),
+            Descriptor::RawTr(ref rawtr) => rawtr.sanity_check(),
         }
     }
 
@@ -363,10 +621,46 @@ impl<Pk: MiniscriptKey> Descriptor<Pk> {
             Descriptor::Wsh(ref wsh) => wsh.max_weight_to_satisfy()?,
             Descriptor::Sh(ref sh) => sh.max_weight_to_satisfy()?,
             Descriptor::Tr(ref tr) => tr.max_weight_to_satisfy()?,
+            Descriptor::RawTr(ref rawtr) => rawtr.max_weight_to_satisfy(),
+            Descriptor::Data(_) | Descriptor::Wother(_) => {
+                return Err(Error::ImpossibleSatisfaction)
+            }
         };
         Ok(weight)
     }
 
+    /// Computes the size, in bytes, of the scriptpubkey of the descriptor.
+    ///
+    /// This is equal to `self.script_pubkey().len()`, but is computed from the
+    /// descriptor without encoding any script, and doesn't allocate.
+    pub fn script_pubkey_size(&self) -> usize {
+        match *self {
+            Descriptor::Bare(ref bare) => bare.as_inner().script_size(),
+            // OP_DUP OP_HASH160 <20 bytes> OP_EQUALVERIFY OP_CHECKSIG
+            Descriptor::Pkh(_) => 25,
+            // OP_0 <20 bytes>
+            Descriptor::Wpkh(_) => 22,
+            // OP_0 <32 bytes>
+            Descriptor::Wsh(_) => 34,
+            // OP_HASH160 <20 bytes> OP_EQUAL
+            Descriptor::Sh(_) => 23,
+            // OP_1 <32 bytes>
+            Descriptor::Tr(_) | Descriptor::RawTr(_) => 34,
+            Descriptor::Data(ref data) => data.script_pubkey_size(),
+            Descriptor::Wother(ref wother) => wother.script_pubkey_size(),
+        }
+    }
+
+    /// Computes the weight of an output paying to the descriptor.
+    ///
+    /// This is equal to the weight of `self.unsigned_txout(amount)` for any
+    /// amount, but doesn't allocate.
+    pub fn txout_weight(&self) -> Weight {
+        let spk_size = self.script_pubkey_size();
+        // amount + scriptpubkey length + scriptpubkey
+        Weight::from_non_witness_data_size((8 + varint_len(spk_size) + spk_size) as u64)
+    }
+
     /// Computes an upper bound on the weight of a satisfying witness to the
     /// transaction.
     ///
@@ -389,10 +683,204 @@ impl<Pk: MiniscriptKey> Descriptor<Pk> {
             Descriptor::Wsh(ref wsh) => wsh.max_satisfaction_weight()?,
             Descriptor::Sh(ref sh) => sh.max_satisfaction_weight()?,
             Descriptor::Tr(ref tr) => tr.max_satisfaction_weight()?,
+            Descriptor::RawTr(ref rawtr) => rawtr.max_satisfaction_weight(),
+            Descriptor::Data(_) | Descriptor::Wother(_) => {
+                return Err(Error::ImpossibleSatisfaction)
+            }
         };
         Ok(weight)
     }
 
+    /// Displays the descriptor, with its checksum, using only the given aliases in its
+    /// Miniscripts.
+    ///
+    /// Together with [`Aliases::detect`], this allows reproducing the exact string a descriptor
+    /// was parsed from. With [`Aliases::all`] this is the same as `to_string`.
+    pub fn to_string_with_aliases(&self, aliases: Aliases) -> String {
+        fn tap_tree_string<Pk: MiniscriptKey>(tree: &TapTree<Pk>, aliases: Aliases) -> String {
+            match *tree {
+                TapTree::Tree { ref left, ref right, height: _ } => format!(
+                    "{{{},{}}}",
+                    tap_tree_string(left, aliases),
+                    tap_tree_string(right, aliases)
+                ),
+                TapTree::Leaf(ref ms) => ms.to_string_with_aliases(aliases),
+                TapTree::SortedMultiA(ref keys, _) => keys.to_string(),
+            }
+        }
+
+        let wsh_string = |wsh: &Wsh<Pk>| match *wsh.as_inner() {
+            WshInner::SortedMulti(..) => format!("{:#}", wsh),
+            WshInner::Ms(ref ms) => format!("wsh({})", ms.to_string_with_aliases(aliases)),
+        };
+        let desc = match *self {
+            Descriptor::Bare(ref bare) => bare.as_inner().to_string_with_aliases(aliases),
+            Descriptor::Pkh(..)
+            | Descriptor::Wpkh(..)
+            | Descriptor::Data(..)
+            | Descriptor::Wother(..)
+            | Descriptor::RawTr(..) => return self.to_string(),
+            Descriptor::Sh(ref sh) => match *sh.as_inner() {
+                ShInner::Wsh(ref wsh) => format!("sh({})", wsh_string(wsh)),
+                ShInner::Ms(ref ms) => format!("sh({})", ms.to_string_with_aliases(aliases)),
+                ShInner::Wpkh(..) | ShInner::SortedMulti(..) => return self.to_string(),
+            },
+            Descriptor::Wsh(ref wsh) => wsh_string(wsh),
+            Descriptor::Tr(ref tr) => match *tr.tap_tree() {
+                Some(ref tree) => {
+                    format!("tr({},{})", tr.internal_key(), tap_tree_string(tree, aliases))
+                }
+                None => return self.to_string(),
+            },
+        };
+        let mut eng = checksum::Engine::new();
+        eng.input(&desc)
+            .expect("descriptors are displayed with ASCII characters");
+        format!("{}#{}", desc, eng.checksum())
+    }
+
+    /// The number of keys of each `multi` and `multi_a` fragment, in the order they appear in
+    /// the descriptor string
+    ///
+    /// `sortedmulti` fragments are not included since their keys are sorted anyway.
+    pub fn multi_key_counts(&self) -> Vec<usize> {
+        let wsh_counts = |wsh: &Wsh<Pk>| match *wsh.as_inner() {
+            WshInner::SortedMulti(..) => vec![],
+            WshInner::Ms(ref ms) => ms.multi_key_counts(),
+        };
+        match *self {
+            Descriptor::Bare(ref bare) => bare.as_inner().multi_key_counts(),
+            Descriptor::Pkh(..)
+            | Descriptor::Wpkh(..)
+            | Descriptor::Data(..)
+            | Descriptor::Wother(..)
+            | Descriptor::RawTr(..) => vec![],
+            Descriptor::Sh(ref sh) => match *sh.as_inner() {
+                ShInner::Wsh(ref wsh) => wsh_counts(wsh),
+                ShInner::Ms(ref ms) => ms.multi_key_counts(),
+                ShInner::Wpkh(..) | ShInner::SortedMulti(..) => vec![],
+            },
+            Descriptor::Wsh(ref wsh) => wsh_counts(wsh),
+            Descriptor::Tr(ref tr) => tr
+                .iter_scripts()
+                .flat_map(|(_, ms)| ms.multi_key_counts())
+                .collect(),
+        }
+    }
+
+    /// Shuffles the keys of every `multi` and `multi_a` fragment of the descriptor
+    ///
+    /// `rng(m)` must return a random value in `0..m`. Returns the shuffled descriptor, whose
+    /// checksum is recomputed when displayed, along with the permutation applied to each fragment
+    /// in the order of [`Self::multi_key_counts`]. See [`Miniscript::shuffle_multi_keys`].
+    pub fn shuffle_multi_keys<F: FnMut(usize) -> usize>(
+        &self,
+        mut rng: F,
+    ) -> (Self, Vec<KeyPermutation>) {
+        let perms: Vec<_> = self
+            .multi_key_counts()
+            .into_iter()
+            .map(|n| KeyPermutation::random(n, &mut rng))
+            .collect();
+        let shuffled = self
+            .permute_multi_keys(&perms)
+            .expect("permutations match the fragments");
+        (shuffled, perms)
+    }
+
+    /// Shuffles the keys of every `multi` and `multi_a` fragment of the descriptor
+    /// deterministically from `seed`
+    ///
+    /// See [`Miniscript::shuffle_multi_keys_seeded`].
+    pub fn shuffle_multi_keys_seeded(&self, seed: &[u8]) -> (Self, Vec<KeyPermutation>) {
+        let mut rng = shuffle::seeded_rng(seed);
+        let perms: Vec<_> = self
+            .multi_key_counts()
+            .into_iter()
+            .enumerate()
+            .map(|(i, n)| KeyPermutation::random(n, |m| rng(i, m)))
+            .collect();
+        let shuffled = self
+            .permute_multi_keys(&perms)
+            .expect("permutations match the fragments");
+        (shuffled, perms)
+    }
+
+    /// Applies `perms` to the keys of the `multi` and `multi_a` fragments of the descriptor, in
+    /// the order of [`Self::multi_key_counts`]
+    ///
+    /// Applying the [`KeyPermutation::inverse`] of the permutations returned by a shuffle
+    /// restores the original descriptor.
+    ///
+    /// # Errors
+    ///
+    /// If the number of permutations or their lengths don't match the fragments.
+    pub fn permute_multi_keys(&self, perms: &[KeyPermutation]) -> Result<Self, PermutationError> {
+        // Reordering keys changes neither the type nor the size of a script, so the descriptors
+        // rebuilt around the permuted miniscripts are always valid.
+        const VALID: &str = "reordering keys keeps the descriptor valid";
+
+        fn permute_tree<Pk: MiniscriptKey>(
+            tree: &TapTree<Pk>,
+            perms: &mut &[KeyPermutation],
+        ) -> Result<TapTree<Pk>, PermutationError> {
+            match *tree {
+                TapTree::Tree { ref left, ref right, height: _ } => {
+                    let left = permute_tree(left, perms)?;
+                    let right = permute_tree(right, perms)?;
+                    Ok(TapTree::combine(left, right))
+                }
+                TapTree::Leaf(ref ms) => {
+                    Ok(TapTree::Leaf(Arc::new(ms.permute_multi_keys_partial(perms)?)))
+                }
+                TapTree::SortedMultiA(..) => Ok(tree.clone()),
+            }
+        }
+
+        let mut remaining = perms;
+        let wsh_permute = |wsh: &Wsh<Pk>, perms: &mut &[KeyPermutation]| match *wsh.as_inner() {
+            WshInner::SortedMulti(..) => Ok(wsh.clone()),
+            WshInner::Ms(ref ms) => {
+                Ok(Wsh::new(ms.permute_multi_keys_partial(perms)?).expect(VALID))
+            }
+        };
+        let desc = match *self {
+            Descriptor::Bare(ref bare) => Descriptor::Bare(
+                Bare::new(bare.as_inner().permute_multi_keys_partial(&mut remaining)?)
+                    .expect(VALID),
+            ),
+            Descriptor::Pkh(..)
+            | Descriptor::Wpkh(..)
+            | Descriptor::Data(..)
+            | Descriptor::Wother(..)
+            | Descriptor::RawTr(..) => self.clone(),
+            Descriptor::Sh(ref sh) => match *sh.as_inner() {
+                ShInner::Wsh(ref wsh) => {
+                    Descriptor::Sh(Sh::new_with_wsh(wsh_permute(wsh, &mut remaining)?))
+                }
+                ShInner::Ms(ref ms) => Descriptor::Sh(
+                    Sh::new(ms.permute_multi_keys_partial(&mut remaining)?).expect(VALID),
+                ),
+                ShInner::Wpkh(..) | ShInner::SortedMulti(..) => self.clone(),
+            },
+            Descriptor::Wsh(ref wsh) => Descriptor::Wsh(wsh_permute(wsh, &mut remaining)?),
+            Descriptor::Tr(ref tr) => match *tr.tap_tree() {
+                Some(ref tree) => Descriptor::Tr(
+                    Tr::new(tr.internal_key().clone(), Some(permute_tree(tree, &mut remaining)?))
+                        .expect(VALID),
+                ),
+                None => self.clone(),
+            },
+        };
+        if !remaining.is_empty() {
+            return Err(PermutationError::FragmentCount {
+                perms: perms.len(),
+                fragments: perms.len() - remaining.len(),
+            });
+        }
+        Ok(desc)
+    }
+
     /// Converts a descriptor using one kind of keys to another kind of key.
     pub fn translate_pk<T>(
         &self,
@@ -408,6 +896,9 @@ impl<Pk: MiniscriptKey> Descriptor<Pk> {
             Descriptor::Sh(ref sh) => Descriptor::Sh(sh.translate_pk(t)?),
             Descriptor::Wsh(ref wsh) => Descriptor::Wsh(wsh.translate_pk(t)?),
             Descriptor::Tr(ref tr) => Descriptor::Tr(tr.translate_pk(t)?),
+            Descriptor::Data(ref data) => Descriptor::Data(data.clone()),
+            Descriptor::Wother(ref wother) => Descriptor::Wother(wother.clone()),
+            Descriptor::RawTr(ref rawtr) => Descriptor::RawTr(rawtr.translate_pk(t)?),
         };
         Ok(desc)
     }
@@ -419,7 +910,7 @@ impl<Pk: MiniscriptKey + ToPublicKey> Descriptor<Pk> {
     /// Some descriptors like pk() don't have an address.
     ///
     /// # Errors
-    /// For raw/bare descriptors that don't have an address.
+    /// For raw/bare and data carrier descriptors that don't have an address.
     pub fn address(&self, network: Network) -> Result<Address, Error> {
         match *self {
             Descriptor::Bare(_) => Err(Error::BareDescriptorAddr),
@@ -428,6 +919,9 @@ impl<Pk: MiniscriptKey + ToPublicKey> Descriptor<Pk> {
             Descriptor::Wsh(ref wsh) => Ok(wsh.address(network)),
             Descriptor::Sh(ref sh) => Ok(sh.address(network)),
             Descriptor::Tr(ref tr) => Ok(tr.address(network)),
+            Descriptor::Data(_) => Err(Error::DataDescriptorAddr),
+            Descriptor::Wother(ref wother) => Ok(wother.address(network)),
+            Descriptor::RawTr(ref rawtr) => Ok(rawtr.address(network)),
         }
     }
 
@@ -440,9 +934,35 @@ impl<Pk: MiniscriptKey + ToPublicKey> Descriptor<Pk> {
             Descriptor::Wsh(ref wsh) => wsh.script_pubkey(),
             Descriptor::Sh(ref sh) => sh.script_pubkey(),
             Descriptor::Tr(ref tr) => tr.script_pubkey(),
+            Descriptor::Data(ref data) => data.script_pubkey(),
+            Descriptor::Wother(ref wother) => wother.script_pubkey(),
+            Descriptor::RawTr(ref rawtr) => rawtr.script_pubkey(),
         }
     }
 
+    /// Computes the output paying `value` to the descriptor.
+    pub fn unsigned_txout(&self, value: Amount) -> TxOut {
+        TxOut { value, script_pubkey: self.script_pubkey() }
+    }
+
+    /// Maps the hashes of the keys of the descriptor to the keys
+    ///
+    /// The keys are hashed as they would be in `pkh` fragments of the
+    /// descriptor, so the map resolves the `pkh` fragments of scripts parsed from
+    /// Bitcoin Script, see [`PkhResolver`](crate::PkhResolver).
+    pub fn pkh_resolver(&self) -> BTreeMap<hash160::Hash, Pk> {
+        let sig_type = match *self {
+            Descriptor::Tr(_) | Descriptor::RawTr(_) => SigType::Schnorr,
+            _ => SigType::Ecdsa,
+        };
+        let mut map = BTreeMap::new();
+        self.for_each_key(|pk| {
+            map.insert(pk.to_pubkeyhash(sig_type), pk.clone());
+            true
+        });
+        map
+    }
+
     /// Computes the scriptSig that will be in place for an unsigned input
     /// spending an output with this descriptor. For pre-segwit descriptors,
     /// which use the scriptSig for signatures, this returns the empty script.
@@ -458,15 +978,16 @@ impl<Pk: MiniscriptKey + ToPublicKey> Descriptor<Pk> {
             Descriptor::Wsh(_) => ScriptBuf::new(),
             Descriptor::Sh(ref sh) => sh.unsigned_script_sig(),
             Descriptor::Tr(_) => ScriptBuf::new(),
+            Descriptor::Data(_) | Descriptor::Wother(_) | Descriptor::RawTr(_) => ScriptBuf::new(),
         }
     }
 
     /// Computes the the underlying script before any hashing is done. For
-    /// `Bare`, `Pkh` and `Wpkh` this is the scriptPubkey; for `ShWpkh` and `Sh`
+    /// `Bare`, `Pkh`, `Wpkh` and `Data` this is the scriptPubkey; for `ShWpkh` and `Sh`
     /// this is the redeemScript; for the others it is the witness script.
     ///
     /// # Errors
-    /// If the descriptor is a taproot descriptor.
+    /// If the descriptor is a taproot, rawtr or a future witness version descriptor.
     pub fn explicit_script(&self) -> Result<ScriptBuf, Error> {
         match *self {
             Descriptor::Bare(ref bare) => Ok(bare.script_pubkey()),
@@ -474,7 +995,9 @@ impl<Pk: MiniscriptKey + ToPublicKey> Descriptor<Pk> {
             Descriptor::Wpkh(ref wpkh) => Ok(wpkh.script_pubkey()),
             Descriptor::Wsh(ref wsh) => Ok(wsh.inner_script()),
             Descriptor::Sh(ref sh) => Ok(sh.inner_script()),
-            Descriptor::Tr(_) => Err(Error::TrNoScriptCode),
+            Descriptor::Tr(_) | Descriptor::RawTr(_) => Err(Error::TrNoScriptCode),
+            Descriptor::Data(ref data) => Ok(data.script_pubkey()),
+            Descriptor::Wother(_) => Err(Error::ImpossibleSatisfaction),
         }
     }
 
@@ -484,7 +1007,8 @@ impl<Pk: MiniscriptKey + ToPublicKey> Descriptor<Pk> {
     /// serialized in the sighash when evaluating a `CHECKSIG` & co. OP code.
     ///
     /// # Errors
-    /// If the descriptor is a taproot descriptor.
+    /// If the descriptor is a taproot, a rawtr, a data carrier or a future witness version
+    /// descriptor.
     pub fn script_code(&self) -> Result<ScriptBuf, Error> {
         match *self {
             Descriptor::Bare(ref bare) => Ok(bare.ecdsa_sighash_script_code()),
@@ -492,7 +1016,8 @@ impl<Pk: MiniscriptKey + ToPublicKey> Descriptor<Pk> {
             Descriptor::Wpkh(ref wpkh) => Ok(wpkh.ecdsa_sighash_script_code()),
             Descriptor::Wsh(ref wsh) => Ok(wsh.ecdsa_sighash_script_code()),
             Descriptor::Sh(ref sh) => Ok(sh.ecdsa_sighash_script_code()),
-            Descriptor::Tr(_) => Err(Error::TrNoScriptCode),
+            Descriptor::Tr(_) | Descriptor::RawTr(_) => Err(Error::TrNoScriptCode),
+            Descriptor::Data(_) | Descriptor::Wother(_) => Err(Error::ImpossibleSatisfaction),
         }
     }
 
@@ -510,6 +1035,8 @@ impl<Pk: MiniscriptKey + ToPublicKey> Descriptor<Pk> {
             Descriptor::Wsh(ref wsh) => wsh.get_satisfaction(satisfier),
             Descriptor::Sh(ref sh) => sh.get_satisfaction(satisfier),
             Descriptor::Tr(ref tr) => tr.get_satisfaction(&satisfier),
+            Descriptor::RawTr(ref rawtr) => rawtr.get_satisfaction(satisfier),
+            Descriptor::Data(_) | Descriptor::Wother(_) => Err(Error::ImpossibleSatisfaction),
         }
     }
 
@@ -527,9 +1054,37 @@ impl<Pk: MiniscriptKey + ToPublicKey> Descriptor<Pk> {
             Descriptor::Wsh(ref wsh) => wsh.get_satisfaction_mall(satisfier),
             Descriptor::Sh(ref sh) => sh.get_satisfaction_mall(satisfier),
             Descriptor::Tr(ref tr) => tr.get_satisfaction_mall(&satisfier),
+            Descriptor::RawTr(ref rawtr) => rawtr.get_satisfaction_mall(satisfier),
+            Descriptor::Data(_) | Descriptor::Wother(_) => Err(Error::ImpossibleSatisfaction),
         }
     }
 
+    /// Returns a satisfying witness and scriptSig to spend an output controlled by the given
+    /// descriptor, falling back to a possibly malleable one if no non-malleable satisfaction
+    /// can be constructed using the satisfier S.
+    ///
+    /// The returned satisfaction records why the non-malleable satisfier failed, if it did.
+    ///
+    /// # Errors
+    ///
+    /// The error of the malleable satisfier, if it fails as well.
+    pub fn get_satisfaction_with_fallback<S>(
+        &self,
+        satisfier: S,
+    ) -> Result<FallbackSatisfaction, Error>
+    where
+        S: Satisfier<Pk>,
+    {
+        let (witness, script_sig, fallback_reason) = match self.get_satisfaction(&satisfier) {
+            Ok((witness, script_sig)) => (witness, script_sig, None),
+            Err(e) => {
+                let (witness, script_sig) = self.get_satisfaction_mall(&satisfier)?;
+                (witness, script_sig, Some(e))
+            }
+        };
+        Ok(FallbackSatisfaction { witness, script_sig, fallback_reason })
+    }
+
     /// Attempts to produce a non-malleable satisfying witness and scriptSig to spend an
     /// output controlled by the given descriptor; add the data to a given
     /// `TxIn` output.
@@ -559,6 +1114,8 @@ impl Descriptor<DefiniteDescriptorKey> {
             Descriptor::Wsh(ref wsh) => wsh.plan_satisfaction(provider),
             Descriptor::Sh(ref sh) => sh.plan_satisfaction(provider),
             Descriptor::Tr(ref tr) => tr.plan_satisfaction(provider),
+            Descriptor::RawTr(ref rawtr) => rawtr.plan_satisfaction(provider),
+            Descriptor::Data(_) | Descriptor::Wother(_) => return Err(self),
         };
 
         if let satisfy::Witness::Stack(stack) = satisfaction.stack {
@@ -573,6 +1130,96 @@ impl Descriptor<DefiniteDescriptorKey> {
         }
     }
 
+    /// Satisfies the descriptor in two stages, letting `review` approve the spending path first
+    ///
+    /// The satisfier is first used to choose the cheapest non-malleable spending path, which
+    /// is passed to `review` as a [`Plan`]: its [`Plan::requirements`] are the exact signatures
+    /// and preimages the witness will use, and it also records the timelocks and the tap leaf
+    /// of the path. The witness is only completed if `review` returns `true`, and then only
+    /// the approved signatures, preimages and timelocks are made available to the satisfier.
+    ///
+    /// # Errors
+    ///
+    /// [`Error::CouldNotSatisfy`] if the satisfier can't satisfy the descriptor, and
+    /// [`Error::SatisfactionRejected`] if `review` returns `false`.
+    pub fn satisfy_with_review<Sat, F>(
+        &self,
+        satisfier: &Sat,
+        review: F,
+    ) -> Result<(Vec<Vec<u8>>, ScriptBuf), Error>
+    where
+        Sat: Satisfier<DefiniteDescriptorKey>,
+        F: FnOnce(&Plan) -> bool,
+    {
+        let plan = self
+            .clone()
+            .plan(satisfier)
+            .map_err(|_| Error::CouldNotSatisfy)?;
+        if !review(&plan) {
+            return Err(Error::SatisfactionRejected);
+        }
+        self.get_satisfaction(ApprovedSatisfier { plan: &plan, inner: satisfier })
+    }
+
+    /// Returns a plan if the provided assets are sufficient to produce a non-malleable
+    /// satisfaction, without searching more than `budget` allows
+    ///
+    /// The nodes visited by the search are counted as it goes, and it is given up once
+    /// they are over budget, so planning untrusted descriptors takes bounded time.
+    ///
+    /// # Errors
+    ///
+    /// The descriptor, with [`PlanError::BudgetExceeded`] if the search was given up, or
+    /// with [`PlanError::InsufficientAssets`] if no plan was found.
+    pub fn plan_with_budget<P>(
+        self,
+        provider: &P,
+        budget: PlanBudget,
+    ) -> Result<Plan, Box<(Self, PlanError)>>
+    where
+        P: AssetProvider<DefiniteDescriptorKey>,
+    {
+        let assets = BudgetAssets::new(provider, budget);
+        let res = self.plan(&assets);
+        Self::check_plan_budget(res, &assets, budget)
+    }
+
+    /// Returns a plan if the provided assets are sufficient to produce a malleable
+    /// satisfaction, without searching more than `budget` allows
+    ///
+    /// See [`Descriptor::plan_with_budget`].
+    pub fn plan_mall_with_budget<P>(
+        self,
+        provider: &P,
+        budget: PlanBudget,
+    ) -> Result<Plan, Box<(Self, PlanError)>>
+    where
+        P: AssetProvider<DefiniteDescriptorKey>,
+    {
+        let assets = BudgetAssets::new(provider, budget);
+        let res = self.plan_mall(&assets);
+        Self::check_plan_budget(res, &assets, budget)
+    }
+
+    fn check_plan_budget<P>(
+        res: Result<Plan, Self>,
+        assets: &BudgetAssets<P>,
+        budget: PlanBudget,
+    ) -> Result<Plan, Box<(Self, PlanError)>> {
+        match res {
+            // A search given up on a branch could have returned a worse plan
+            Ok(plan) if assets.exceeded() => Err(Box::new((
+                plan.descriptor,
+                PlanError::BudgetExceeded { max_nodes: budget.max_nodes },
+            ))),
+            Ok(plan) => Ok(plan),
+            Err(desc) if assets.exceeded() => {
+                Err(Box::new((desc, PlanError::BudgetExceeded { max_nodes: budget.max_nodes })))
+            }
+            Err(desc) => Err(Box::new((desc, PlanError::InsufficientAssets))),
+        }
+    }
+
     /// Returns a plan if the provided assets are sufficient to produce a malleable satisfaction
     ///
     /// If the assets aren't sufficient for generating a Plan, the descriptor is returned
@@ -587,6 +1234,8 @@ impl Descriptor<DefiniteDescriptorKey> {
             Descriptor::Wsh(ref wsh) => wsh.plan_satisfaction_mall(provider),
             Descriptor::Sh(ref sh) => sh.plan_satisfaction_mall(provider),
             Descriptor::Tr(ref tr) => tr.plan_satisfaction_mall(provider),
+            Descriptor::RawTr(ref rawtr) => rawtr.plan_satisfaction_mall(provider),
+            Descriptor::Data(_) | Descriptor::Wother(_) => return Err(self),
         };
 
         if let satisfy::Witness::Stack(stack) = satisfaction.stack {
@@ -612,6 +1261,8 @@ impl<Pk: MiniscriptKey> ForEachKey<Pk> for Descriptor<Pk> {
             Descriptor::Wsh(ref wsh) => wsh.for_each_key(pred),
             Descriptor::Sh(ref sh) => sh.for_each_key(pred),
             Descriptor::Tr(ref tr) => tr.for_each_key(pred),
+            Descriptor::RawTr(ref rawtr) => rawtr.for_each_key(pred),
+            Descriptor::Data(_) | Descriptor::Wother(_) => true,
         }
     }
 }
@@ -653,6 +1304,32 @@ impl Descriptor<DescriptorPublicKey> {
             .map_err(|e| e.expect_translator_err("No Context errors while translating"))
     }
 
+    /// Converts a descriptor without wildcards into a *definite* descriptor, without needing a
+    /// derivation index.
+    ///
+    /// # Errors
+    /// - If the descriptor contains wildcards (use [`Self::at_derivation_index`] instead)
+    /// - If the descriptor contains multi-path derivations
+    pub fn to_definite(&self) -> Result<Descriptor<DefiniteDescriptorKey>, ConversionError> {
+        struct Definite;
+
+        impl Translator<DescriptorPublicKey> for Definite {
+            type TargetPk = DefiniteDescriptorKey;
+            type Error = ConversionError;
+
+            fn pk(
+                &mut self,
+                pk: &DescriptorPublicKey,
+            ) -> Result<DefiniteDescriptorKey, ConversionError> {
+                DefiniteDescriptorKey::try_from(pk.clone())
+            }
+
+            translate_hash_clone!(DescriptorPublicKey, DescriptorPublicKey, ConversionError);
+        }
+        self.translate_pk(&mut Definite)
+            .map_err(|e| e.expect_translator_err("No Context errors while translating"))
+    }
+
     #[deprecated(note = "use at_derivation_index instead")]
     /// Deprecated name for [`Self::at_derivation_index`].
     pub fn derive(&self, index: u32) -> Result<Descriptor<DefiniteDescriptorKey>, ConversionError> {
@@ -694,6 +1371,24 @@ impl Descriptor<DescriptorPublicKey> {
         self.at_derivation_index(index)?.derived_descriptor(secp)
     }
 
+    /// Derives the `script_pubkey` of the descriptor at each index in `range`, e.g. to scan the
+    /// outputs of a wallet up to its gap limit.
+    ///
+    /// This gives the same results as calling [`Self::derived_descriptor`] and `script_pubkey` at
+    /// each index, but collects [`Self::spk_iter`], so that the derivation steps before the
+    /// wildcard of each key are only derived once for the whole range.
+    ///
+    /// # Errors
+    ///
+    /// As [`Self::spk_iter`].
+    pub fn script_pubkey_at_derivation_range<C: secp256k1::Verification>(
+        &self,
+        secp: &secp256k1::Secp256k1<C>,
+        range: Range<u32>,
+    ) -> Result<Vec<ScriptBuf>, ConversionError> {
+        Ok(self.spk_iter(range, secp)?.map(|(_, spk)| spk).collect())
+    }
+
     /// Parse a descriptor that may contain secret keys
     ///
     /// Internally turns every secret key found into the corresponding public key and then returns a
@@ -821,6 +1516,39 @@ impl Descriptor<DescriptorPublicKey> {
         descriptor.to_string()
     }
 
+    /// Split a descriptor and its keymap into a watch-only descriptor and the secret keys
+    /// needed to sign for it
+    ///
+    /// The returned keymap only contains the entries of `key_map` for keys present in the
+    /// descriptor. See [`Descriptor::keymap_is_sufficient`] to check it can sign for a spend.
+    pub fn split_keymap(&self, key_map: &KeyMap) -> (Descriptor<DescriptorPublicKey>, KeyMap) {
+        let mut signing = KeyMap::new();
+        self.for_each_key(|pk| {
+            if let Some(sk) = key_map.get(pk) {
+                signing.insert(pk.clone(), sk.clone());
+            }
+            true
+        });
+        (self.clone(), signing)
+    }
+
+    /// Whether the secret keys of `key_map` are enough to satisfy at least one complete
+    /// spending path of the descriptor
+    ///
+    /// Timelocks are assumed to be eventually satisfied, while hash preimages are assumed to
+    /// be unavailable, see [`semantic::Policy::satisfiable_with_keys`].
+    ///
+    /// # Errors
+    ///
+    /// If the descriptor can't be lifted to a semantic policy.
+    ///
+    /// [`semantic::Policy::satisfiable_with_keys`]: crate::policy::semantic::Policy::satisfiable_with_keys
+    pub fn keymap_is_sufficient(&self, key_map: &KeyMap) -> Result<bool, Error> {
+        Ok(self
+            .lift()?
+            .satisfiable_with_keys(|pk| key_map.contains_key(pk)))
+    }
+
     /// Utility method for deriving the descriptor at each index in a range to find one matching
     /// `script_pubkey`.
     ///
@@ -836,6 +1564,18 @@ impl Descriptor<DescriptorPublicKey> {
     ) -> Result<Option<(u32, Descriptor<bitcoin::PublicKey>)>, ConversionError> {
         let range = if self.has_wildcard() { range } else { 0..1 };
 
+        if let Descriptor::Tr(ref tr) = *self {
+            // Avoid recomputing the whole tap tree for every index when only the internal key
+            // depends on the derivation index.
+            let mut cache = tr::TrSpkCache::new(tr);
+            for i in range {
+                if cache.script_pubkey_at(tr, secp, i)?.as_script() == script_pubkey {
+                    return Ok(Some((i, self.derived_descriptor(secp, i)?)));
+                }
+            }
+            return Ok(None);
+        }
+
         for i in range {
             let concrete = self.derived_descriptor(secp, i)?;
             if &concrete.script_pubkey() == script_pubkey {
@@ -846,6 +1586,40 @@ impl Descriptor<DescriptorPublicKey> {
         Ok(None)
     }
 
+    /// Classify the outputs locked by this descriptor by whether they can be spent
+    ///
+    /// Each output is checked against the descriptor derived at its derivation index, and is
+    /// classified as spendable in the next block, spendable once some timelocks are satisfied,
+    /// or not spendable with the given `assets`. The cheapest plan is returned for the first two
+    /// cases. Timelocks are checked against `tip` rather than against the maximum timelocks set
+    /// in `assets`. Relative timelocks of unconfirmed outputs are never considered satisfied.
+    ///
+    /// Returns an error if an output isn't locked by this descriptor at its derivation index.
+    pub fn spendable_balance_classifier(
+        &self,
+        utxos: &[WalletUtxo],
+        tip: ChainTip,
+        assets: &Assets,
+    ) -> Result<Vec<(OutPoint, Spendability)>, ClassifyError> {
+        utxos
+            .iter()
+            .map(|utxo| {
+                let descriptor = self
+                    .at_derivation_index(utxo.derivation_index)
+                    .map_err(|e| ClassifyError::Derivation(utxo.outpoint, e))?;
+                if descriptor.script_pubkey() != utxo.txout.script_pubkey {
+                    return Err(ClassifyError::ScriptMismatch(
+                        utxo.outpoint,
+                        utxo.derivation_index,
+                    ));
+                }
+                let spendability =
+                    Spendability::classify(descriptor, assets, tip, utxo.confirmed_on);
+                Ok((utxo.outpoint, spendability))
+            })
+            .collect()
+    }
+
     /// Whether this descriptor contains a key that has multiple derivation paths.
     pub fn is_multipath(&self) -> bool { self.for_any_key(DescriptorPublicKey::is_multipath) }
 
@@ -853,6 +1627,7 @@ impl Descriptor<DescriptorPublicKey> {
     ///
     /// For multipath descriptors it will return as many descriptors as there is
     /// "parallel" paths. For regular descriptors it will just return itself.
+    /// To derive each of them lazily, see [`Descriptor::derivation_index_iter`].
     #[allow(clippy::blocks_in_conditions)]
     pub fn into_single_descriptors(self) -> Result<Vec<Descriptor<DescriptorPublicKey>>, Error> {
         // All single-path descriptors contained in this descriptor.
@@ -866,7 +1641,9 @@ impl Descriptor<DescriptorPublicKey> {
             }
 
             match key {
-                DescriptorPublicKey::Single(..) | DescriptorPublicKey::XPub(..) => false,
+                DescriptorPublicKey::Single(..)
+                | DescriptorPublicKey::XPub(..)
+                | DescriptorPublicKey::Musig(..) => false,
                 DescriptorPublicKey::MultiXPub(xpub) => {
                     for _ in 0..xpub.derivation_paths.paths().len() {
                         descriptors.push(self.clone());
@@ -888,9 +1665,9 @@ impl Descriptor<DescriptorPublicKey> {
 
             fn pk(&mut self, pk: &DescriptorPublicKey) -> Result<DescriptorPublicKey, Error> {
                 match pk {
-                    DescriptorPublicKey::Single(..) | DescriptorPublicKey::XPub(..) => {
-                        Ok(pk.clone())
-                    }
+                    DescriptorPublicKey::Single(..)
+                    | DescriptorPublicKey::XPub(..)
+                    | DescriptorPublicKey::Musig(..) => Ok(pk.clone()),
                     DescriptorPublicKey::MultiXPub(_) => pk
                         .clone()
                         .into_single_keys()
@@ -911,6 +1688,101 @@ impl Descriptor<DescriptorPublicKey> {
 
         Ok(descriptors)
     }
+
+    /// Export this descriptor for one of its cosigners, hiding the keys of the other parties.
+    ///
+    /// Keys in `party_keys` are kept as they are, while every other key is replaced according to
+    /// `redaction`. This lets a coordinator share the spending policy with each cosigner while
+    /// only revealing what that cosigner needs to verify it.
+    ///
+    /// The result is a [`Descriptor<String>`] since the placeholders aren't valid descriptor
+    /// keys. Hashes in the descriptor are kept as they are.
+    ///
+    /// # Errors
+    ///
+    /// When using [`KeyRedaction::DerivedAt`], returns an error if a redacted key can't be
+    /// derived at the given index (it is multipath, or has a hardened derivation step).
+    pub fn export_for_party<C: secp256k1::Verification>(
+        &self,
+        secp: &secp256k1::Secp256k1<C>,
+        party_keys: &[DescriptorPublicKey],
+        redaction: KeyRedaction,
+    ) -> Result<Descriptor<String>, ConversionError> {
+        struct Redactor<'a, C: secp256k1::Verification> {
+            secp: &'a secp256k1::Secp256k1<C>,
+            party_keys: &'a [DescriptorPublicKey],
+            redaction: KeyRedaction,
+        }
+
+        impl<'a, C: secp256k1::Verification> Translator<DescriptorPublicKey> for Redactor<'a, C> {
+            type TargetPk = String;
+            type Error = ConversionError;
+
+            fn pk(&mut self, pk: &DescriptorPublicKey) -> Result<String, ConversionError> {
+                if self.party_keys.contains(pk) {
+                    return Ok(pk.to_string());
+                }
+                match self.redaction {
+                    KeyRedaction::KeyHash => {
+                        let hash = match *pk {
+                            DescriptorPublicKey::Single(ref single) => match single.key {
+                                SinglePubKey::FullKey(ref key) => key.pubkey_hash().to_raw_hash(),
+                                SinglePubKey::XOnly(ref key) => {
+                                    hash160::Hash::hash(&key.serialize())
+                                }
+                            },
+                            DescriptorPublicKey::XPub(ref xpub) => {
+                                let xpub = DescriptorXKey { origin: None, ..xpub.clone() };
+                                hash160::Hash::hash(
+                                    DescriptorPublicKey::XPub(xpub).to_string().as_bytes(),
+                                )
+                            }
+                            DescriptorPublicKey::MultiXPub(ref xpub) => {
+                                let xpub = DescriptorMultiXKey { origin: None, ..xpub.clone() };
+                                hash160::Hash::hash(
+                                    DescriptorPublicKey::MultiXPub(xpub).to_string().as_bytes(),
+                                )
+                            }
+                            DescriptorPublicKey::Musig(_) => {
+                                hash160::Hash::hash(pk.to_string().as_bytes())
+                            }
+                        };
+                        Ok(hash.to_string())
+                    }
+                    KeyRedaction::DerivedAt(index) => {
+                        let key = pk
+                            .clone()
+                            .at_derivation_index(index)
+                            .and_then(|key| key.derive_public_key(self.secp))?;
+                        Ok(key.to_string())
+                    }
+                }
+            }
+
+            fn sha256(&mut self, sha256: &sha256::Hash) -> Result<String, ConversionError> {
+                Ok(sha256.to_string())
+            }
+
+            fn hash256(&mut self, hash256: &hash256::Hash) -> Result<String, ConversionError> {
+                Ok(hash256.to_string())
+            }
+
+            fn ripemd160(
+                &mut self,
+                ripemd160: &ripemd160::Hash,
+            ) -> Result<String, ConversionError> {
+                Ok(ripemd160.to_string())
+            }
+
+            fn hash160(&mut self, hash160: &hash160::Hash) -> Result<String, ConversionError> {
+                Ok(hash160.to_string())
+            }
+        }
+
+        let mut redactor = Redactor { secp, party_keys, redaction };
+        self.translate_pk(&mut redactor)
+            .map_err(|e| e.expect_translator_err("No Context errors while translating"))
+    }
 }
 
 impl Descriptor<DefiniteDescriptorKey> {
@@ -973,6 +1845,9 @@ impl<Pk: FromStrKey> crate::expression::FromTree for Descriptor<Pk> {
             ("sh", 1) => Descriptor::Sh(Sh::from_tree(top)?),
             ("wsh", 1) => Descriptor::Wsh(Wsh::from_tree(top)?),
             ("tr", _) => Descriptor::Tr(Tr::from_tree(top)?),
+            ("data", 1) => Descriptor::Data(Data::from_tree(top)?),
+            ("wother", 2) => Descriptor::Wother(Wother::from_tree(top)?),
+            ("rawtr", 1) => Descriptor::RawTr(RawTr::from_tree(top)?),
             _ => Descriptor::Bare(Bare::from_tree(top)?),
         })
     }
@@ -1004,6 +1879,9 @@ impl<Pk: MiniscriptKey> fmt::Debug for Descriptor<Pk> {
             Descriptor::Sh(ref sub) => fmt::Debug::fmt(sub, f),
             Descriptor::Wsh(ref sub) => fmt::Debug::fmt(sub, f),
             Descriptor::Tr(ref tr) => fmt::Debug::fmt(tr, f),
+            Descriptor::Data(ref data) => fmt::Debug::fmt(data, f),
+            Descriptor::Wother(ref wother) => fmt::Debug::fmt(wother, f),
+            Descriptor::RawTr(ref rawtr) => fmt::Debug::fmt(rawtr, f),
         }
     }
 }
@@ -1017,6 +1895,9 @@ impl<Pk: MiniscriptKey> fmt::Display for Descriptor<Pk> {
             Descriptor::Sh(ref sub) => fmt::Display::fmt(sub, f),
             Descriptor::Wsh(ref sub) => fmt::Display::fmt(sub, f),
             Descriptor::Tr(ref tr) => fmt::Display::fmt(tr, f),
+            Descriptor::Data(ref data) => fmt::Display::fmt(data, f),
+            Descriptor::Wother(ref wother) => fmt::Display::fmt(wother, f),
+            Descriptor::RawTr(ref rawtr) => fmt::Display::fmt(rawtr, f),
         }
     }
 }
@@ -1128,6 +2009,83 @@ mod tests {
             .unwrap_err();
     }
 
+    #[test]
+    fn uncompressed_key_weights() {
+        let secp = secp256k1::Secp256k1::signing_only();
+        let sig = bitcoin::ecdsa::Signature::sighash_all(secp.sign_ecdsa(
+            &secp256k1::Message::from_digest([1; 32]),
+            &secp256k1::SecretKey::from_slice(&[1; 32]).unwrap(),
+        ));
+        let keys = |compressed: bool| -> Vec<PublicKey> {
+            (1..=8u8)
+                .map(|i| {
+                    let sk = secp256k1::SecretKey::from_slice(&[i; 32]).unwrap();
+                    PublicKey { inner: sk.public_key(&secp), compressed }
+                })
+                .collect()
+        };
+
+        // The weight estimation is off by the same amount whatever the size of the keys,
+        // the difference is only due to the signature being shorter than the maximum.
+        let overestimate = |desc: &str, compressed: bool| {
+            let pks = keys(compressed);
+            let desc = StdDescriptor::from_str(
+                &desc
+                    .replace('A', &pks[0].to_string())
+                    .replace('B', &pks[1].to_string()),
+            )
+            .unwrap();
+            let sigs: BTreeMap<_, _> = pks.iter().map(|pk| (*pk, sig)).collect();
+            let mut txin = bitcoin::TxIn::default();
+            desc.satisfy(&mut txin, &sigs).unwrap();
+            let weight = desc.max_weight_to_satisfy().unwrap();
+            weight.to_wu() as i64 - 4 * txin.script_sig.len() as i64
+        };
+        for desc in [
+            "pk(A)",
+            "pkh(A)",
+            "sh(pk(A))",
+            "sh(pkh(A))",
+            "sh(multi(1,A,B))",
+        ] {
+            assert_eq!(overestimate(desc, true), overestimate(desc, false), "{}", desc);
+        }
+
+        // The script of a P2SH can't exceed 520 bytes
+        let pks = keys(false);
+        let multi = |n: usize| {
+            let pks: Vec<_> = pks[..n].iter().map(|pk| pk.to_string()).collect();
+            format!("sh(multi(1,{}))", pks.join(","))
+        };
+        StdDescriptor::from_str(&multi(7)).unwrap();
+        StdDescriptor::from_str(&multi(8)).unwrap_err();
+    }
+
+    #[test]
+    fn script_pubkey_size() {
+        let pk = "020000000000000000000000000000000000000000000000000000000000000002";
+        let uncompressed = "04a34b99f22c790c4e36b2b3c2c35a36db06226e41c692fc82b8b56ac1c540c5bd5b8dec5235a0fa8722476c7709c02559e3aa73aa03918ba2d492eea75abea235";
+        for desc in [
+            format!("pk({})", pk),
+            format!("pk({})", uncompressed),
+            format!("multi(1,{},{})", pk, uncompressed),
+            format!("pkh({})", pk),
+            format!("wpkh({})", pk),
+            format!("sh(wpkh({}))", pk),
+            format!("wsh(pk({}))", pk),
+            format!("tr({})", pk),
+            "data()".to_owned(),
+            "data(deadbeef)".to_owned(),
+            format!("data({})", "ab".repeat(80)),
+        ] {
+            let desc = StdDescriptor::from_str(&desc).unwrap();
+            assert_eq!(desc.script_pubkey_size(), desc.script_pubkey().len(), "{}", desc);
+            let txout = desc.unsigned_txout(bitcoin::Amount::from_sat(1000));
+            assert_eq!(txout.script_pubkey, desc.script_pubkey());
+            assert_eq!(desc.txout_weight(), txout.weight(), "{}", desc);
+        }
+    }
+
     #[test]
     pub fn script_pubkey() {
         let bare = StdDescriptor::from_str(
@@ -1467,6 +2425,152 @@ mod tests {
         );
     }
 
+    #[test]
+    fn to_definite() {
+        let xpub = "xpub6ERApfZwUNrhLCkDtcHTcxd75RbzS1ed54G1LkBUHQVHQKqhMkhgbmJbZRkrgZw4koxb5JaHWkY4ALHY2grBGRjaDMzQLcgJvLJuZZvRcEL";
+        let desc =
+            Descriptor::<DescriptorPublicKey>::from_str(&format!("wpkh({}/1/2)", xpub)).unwrap();
+        let definite = desc.to_definite().unwrap();
+        assert_eq!(definite, desc.at_derivation_index(0).unwrap());
+
+        let ranged =
+            Descriptor::<DescriptorPublicKey>::from_str(&format!("wpkh({}/1/*)", xpub)).unwrap();
+        assert_eq!(ranged.to_definite(), Err(ConversionError::Wildcard));
+
+        let multi = Descriptor::<DescriptorPublicKey>::from_str(&format!("wpkh({}/<0;1>/2)", xpub))
+            .unwrap();
+        assert_eq!(multi.to_definite(), Err(ConversionError::MultiKey));
+    }
+
+    #[test]
+    fn data_descriptor() {
+        let desc = StdDescriptor::from_str("data(deadbeef)").unwrap();
+        assert_eq!(desc.desc_type(), DescriptorType::Data);
+        assert_eq!(desc.script_pubkey(), hex_script("6a04deadbeef"));
+        assert_eq!(desc.unsigned_script_sig(), ScriptBuf::new());
+        assert!(matches!(desc.address(Network::Bitcoin), Err(Error::DataDescriptorAddr)));
+        assert!(matches!(desc.max_weight_to_satisfy(), Err(Error::ImpossibleSatisfaction)));
+        assert!(desc
+            .get_satisfaction(BTreeMap::<PublicKey, _>::new())
+            .is_err());
+        desc.sanity_check().unwrap();
+        assert_eq!(desc, StdDescriptor::new_data(vec![0xde, 0xad, 0xbe, 0xef]).unwrap());
+
+        let roundtrip = StdDescriptor::from_str(&desc.to_string()).unwrap();
+        assert_eq!(desc, roundtrip);
+        let empty = StdDescriptor::from_str("data()").unwrap();
+        assert_eq!(empty.script_pubkey(), hex_script("6a00"));
+
+        // Keys can be translated through a data descriptor
+        let desc = Descriptor::<DescriptorPublicKey>::from_str("data(deadbeef)").unwrap();
+        let secp = secp256k1::Secp256k1::verification_only();
+        assert_eq!(
+            desc.derived_descriptor(&secp, 0).unwrap().script_pubkey(),
+            hex_script("6a04deadbeef")
+        );
+
+        // Above the default relay limit
+        let big = StdDescriptor::new_data(vec![0; 81]).unwrap();
+        assert!(big.sanity_check().is_err());
+        assert!(StdDescriptor::from_str("data(0)").is_err());
+        assert!(StdDescriptor::from_str("data(zz)").is_err());
+    }
+
+    #[test]
+    fn shuffle_multi_keys() {
+        let desc = Descriptor::<String>::from_str(
+            "tr(A,{multi_a(1,B,C,D),and_v(v:pk(E),multi_a(2,F,G))})",
+        )
+        .unwrap();
+        assert_eq!(desc.multi_key_counts(), vec![3, 2]);
+        let (shuffled, perms) = desc.shuffle_multi_keys(|_| 0);
+        let expected = Descriptor::<String>::from_str(
+            "tr(A,{multi_a(1,C,D,B),and_v(v:pk(E),multi_a(2,G,F))})",
+        )
+        .unwrap();
+        assert_eq!(shuffled, expected);
+        assert_eq!(shuffled.to_string(), expected.to_string());
+        let inverses: Vec<_> = perms.iter().map(KeyPermutation::inverse).collect();
+        assert_eq!(shuffled.permute_multi_keys(&inverses).unwrap(), desc);
+        assert!(desc.permute_multi_keys(&perms[..1]).is_err());
+
+        // sortedmulti is left alone, nested wsh is shuffled
+        let desc =
+            Descriptor::<String>::from_str("sh(wsh(or_d(multi(1,A,B),multi(1,C,D))))").unwrap();
+        let (shuffled, perms) = desc.shuffle_multi_keys_seeded(b"index 42");
+        assert_eq!(perms.len(), 2);
+        assert_eq!(desc.shuffle_multi_keys_seeded(b"index 42"), (shuffled, perms));
+        let desc = Descriptor::<String>::from_str("wsh(sortedmulti(1,A,B))").unwrap();
+        assert_eq!(desc.multi_key_counts(), Vec::<usize>::new());
+        assert_eq!(desc.shuffle_multi_keys(|_| 0), (desc, vec![]));
+    }
+
+    #[test]
+    fn rawtr_descriptor() {
+        let key = "a34b99f22c790c4e36b2b3c2c35a36db06226e41c692fc82b8b56ac1c540c5bd";
+        let desc = StdDescriptor::from_str(&format!("rawtr(02{})", key)).unwrap();
+        assert_eq!(desc.desc_type(), DescriptorType::RawTr);
+        assert_eq!(desc.desc_type().segwit_version(), Some(WitnessVersion::V1));
+        // The output key is not tweaked
+        assert_eq!(desc.script_pubkey(), hex_script(&format!("5120{}", key)));
+        assert_eq!(desc.script_pubkey().len(), desc.script_pubkey_size());
+        assert_eq!(desc.unsigned_script_sig(), ScriptBuf::new());
+        assert_eq!(desc.max_weight_to_satisfy().unwrap(), Weight::from_wu(66));
+        assert!(matches!(desc.explicit_script(), Err(Error::TrNoScriptCode)));
+        assert!(desc
+            .get_satisfaction(BTreeMap::<PublicKey, _>::new())
+            .is_err());
+        desc.sanity_check().unwrap();
+        assert_eq!(desc.as_rawtr().unwrap().as_inner().to_string(), format!("02{}", key));
+        assert_eq!(desc, StdDescriptor::from_str(&desc.to_string()).unwrap());
+
+        let desc = Descriptor::<DescriptorPublicKey>::from_str(
+            "rawtr([d34db33f/86'/0'/0']xpub6ERApfZwUNrhLCkDtcHTcxd75RbzS1ed54G1LkBUHQVHQKqhMkhgbmJbZRkrgZw4koxb5JaHWkY4ALHY2grBGRjaDMzQLcgJvLJuZZvRcEL/0/*)",
+        )
+        .unwrap();
+        assert!(desc.has_wildcard());
+        let derived = desc.at_derivation_index(0).unwrap();
+        assert_eq!(derived.desc_type(), DescriptorType::RawTr);
+        assert_eq!(desc, Descriptor::from_str(&desc.to_string()).unwrap());
+
+        assert!(StdDescriptor::from_str("rawtr()").is_err());
+        assert!(StdDescriptor::from_str(&format!("rawtr(02{},02{})", key, key)).is_err());
+        // Uncompressed keys aren't allowed in taproot
+        assert!(StdDescriptor::from_str("rawtr(04a34b99f22c790c4e36b2b3c2c35a36db06226e41c692fc82b8b56ac1c540c5bd5b8dec5235a0fa8722476c7709c02559e3aa73aa03918ba2d492eea75abea235)").is_err());
+    }
+
+    #[test]
+    fn wother_descriptor() {
+        let desc = StdDescriptor::from_str("wother(2,0001)").unwrap();
+        assert_eq!(desc.desc_type(), DescriptorType::Wother);
+        assert_eq!(desc.desc_type().segwit_version(), None);
+        assert_eq!(desc.as_wother().unwrap().version(), WitnessVersion::V2);
+        assert_eq!(desc.script_pubkey(), hex_script("52020001"));
+        assert_eq!(desc.script_pubkey().len(), desc.script_pubkey_size());
+        assert_eq!(desc.unsigned_script_sig(), ScriptBuf::new());
+        assert_eq!(desc.address(Network::Bitcoin).unwrap().to_string(), "bc1zqqqse63q6q");
+        assert!(matches!(desc.max_weight_to_satisfy(), Err(Error::ImpossibleSatisfaction)));
+        assert!(matches!(desc.explicit_script(), Err(Error::ImpossibleSatisfaction)));
+        assert!(desc
+            .get_satisfaction(BTreeMap::<PublicKey, _>::new())
+            .is_err());
+        desc.sanity_check().unwrap();
+        assert_eq!(desc, StdDescriptor::new_wother(WitnessVersion::V2, &[0x00, 0x01]).unwrap());
+        assert_eq!(desc, StdDescriptor::from_str(&desc.to_string()).unwrap());
+
+        let v16 = StdDescriptor::from_str(&format!("wother(16,{})", "ab".repeat(40))).unwrap();
+        assert_eq!(v16.script_pubkey().len(), v16.script_pubkey_size());
+        let addr = v16.address(Network::Testnet).unwrap();
+        assert_eq!(addr.script_pubkey(), v16.script_pubkey());
+
+        // v0 and v1 have their own descriptors, and programs are 2 to 40 bytes
+        assert!(StdDescriptor::from_str("wother(0,0001)").is_err());
+        assert!(StdDescriptor::from_str("wother(1,0001)").is_err());
+        assert!(StdDescriptor::from_str("wother(17,0001)").is_err());
+        assert!(StdDescriptor::from_str("wother(2,00)").is_err());
+        assert!(StdDescriptor::from_str(&format!("wother(2,{})", "00".repeat(41))).is_err());
+    }
+
     #[test]
     fn after_is_cltv() {
         let descriptor = Descriptor::<bitcoin::PublicKey>::from_str("wsh(after(1000))").unwrap();
@@ -1885,6 +2989,103 @@ pk(03f28773c2d975288bc7d1d205c3748651b075fbc6610e58cddeeddf8f19405aa8))";
         assert_eq!(res_descriptor.to_string(), definite_descriptor.to_string());
     }
 
+    #[test]
+    fn split_keymap() {
+        let secp = &secp256k1::Secp256k1::signing_only();
+        let xprv_a = "xprvA1RpRA33e1JQ7ifknakTFpgNXPmW2YvmhqLQYMmrj4xJXXWYpDPS3xz7iAxn8L39njGVyuoseXzU6rcxFLJ8HFsTjSyQbLYnMpCqE2VbFWc";
+        let xprv_b = "xprv9uPDJpEQgRQfDcW7BkF7eTya6RPxXeJCqCJGHuCJ4GiRVLzkTXBAJMu2qaMWPrS7AANYqdq6vcBcBUdJCVVFceUvJFjaPdGZ2y9WACViL4L";
+        let xpub_c = "xpub6BgBgsespWvERF3LHQu6CnqdvfEvtMcQjYrcRzx53QJjSxarj2afYWcLteoGVky7D3UKDP9QyrLprQ3VCECoY49yfdDEHGCtMMj92pReUsQ";
+        let (descriptor, keymap) = Descriptor::parse_descriptor(
+            secp,
+            &format!(
+                "wsh(or_d(multi(2,{}/0/*,{}/0/*),and_v(v:pk({}/0/*),older(10))))",
+                xprv_a, xprv_b, xpub_c
+            ),
+        )
+        .unwrap();
+        assert_eq!(keymap.len(), 2);
+
+        // Keys which aren't in the descriptor are dropped
+        let (_, mut full) = Descriptor::parse_descriptor(
+            secp,
+            "wpkh(xprv9s21ZrQH143K4CTb63EaMxja1YiTnSEWKMbn23uoEnAzxjdUJRQkazCAtzxGm4LSoTSVTptoV9RbchnKPW9HxKtZumdyxyikZFDLhogJ5Uj/44'/0'/0'/0/*)",
+        )
+        .unwrap();
+        full.extend(keymap.clone());
+        assert_eq!(full.len(), 3);
+        let (watch_only, signing) = descriptor.split_keymap(&full);
+        assert_eq!(watch_only, descriptor);
+        assert_eq!(signing, keymap);
+
+        assert!(descriptor.keymap_is_sufficient(&signing).unwrap());
+        let mut partial = signing.clone();
+        let first = partial.keys().next().unwrap().clone();
+        partial.remove(&first);
+        assert!(!descriptor.keymap_is_sufficient(&partial).unwrap());
+        assert!(!descriptor.keymap_is_sufficient(&KeyMap::new()).unwrap());
+    }
+
+    #[test]
+    fn pay_to_contract() {
+        let secp = &secp256k1::Secp256k1::new();
+        let tweak = KeyTweak::from_contract(b"proof of liabilities, height 850000");
+        let other = KeyTweak::from_contract(b"proof of liabilities, height 850001");
+
+        let desc = Descriptor::<DescriptorPublicKey>::from_str("tr(xpub6BgBgsespWvERF3LHQu6CnqdvfEvtMcQjYrcRzx53QJjSxarj2afYWcLteoGVky7D3UKDP9QyrLprQ3VCECoY49yfdDEHGCtMMj92pReUsQ/0/*)").unwrap();
+        let tweaked = TweakedDescriptor::new(desc.clone(), tweak).unwrap();
+        let address = tweaked.address(secp, 3, Network::Bitcoin).unwrap();
+        assert_ne!(
+            address,
+            desc.at_derivation_index(3)
+                .unwrap()
+                .address(Network::Bitcoin)
+                .unwrap()
+        );
+        assert!(tweaked.verify_address(secp, 3, &address).unwrap());
+        assert!(!tweaked.verify_address(secp, 4, &address).unwrap());
+        assert!(!TweakedDescriptor::new(desc, other)
+            .unwrap()
+            .verify_address(secp, 3, &address)
+            .unwrap());
+        assert_eq!(
+            tweaked
+                .find_index(secp, 0..10, &address.script_pubkey())
+                .unwrap(),
+            Some(3)
+        );
+        assert_eq!(
+            tweaked
+                .find_index(secp, 4..10, &address.script_pubkey())
+                .unwrap(),
+            None
+        );
+        assert!(matches!(
+            tweaked.address(secp, 1 << 31, Network::Bitcoin),
+            Err(Error::DerivationRange(DerivationRangeError::Derivation(
+                0x8000_0000,
+                ConversionError::HardenedChild
+            )))
+        ));
+
+        // The tweaked private key matches the tweaked public key
+        let (desc, keymap) = Descriptor::parse_descriptor(
+            secp,
+            "wpkh(KwdMAjGmerYanjeui5SHS7JkmpZvVipYvB2LJGU1ZxJwYvP98617)",
+        )
+        .unwrap();
+        let sk = match keymap.values().next().unwrap() {
+            DescriptorSecretKey::Single(single) => single.key,
+            _ => unreachable!(),
+        };
+        let tweaked = TweakedDescriptor::new(desc, tweak).unwrap();
+        let tweaked_sk = tweak.tweak_private_key(secp, &sk).unwrap();
+        let expected = Descriptor::new_wpkh(tweaked_sk.public_key(secp)).unwrap();
+        assert_eq!(tweaked.derived_descriptor(secp, 0).unwrap(), expected);
+
+        let multipath = Descriptor::<DescriptorPublicKey>::from_str("wpkh(xpub6BgBgsespWvERF3LHQu6CnqdvfEvtMcQjYrcRzx53QJjSxarj2afYWcLteoGVky7D3UKDP9QyrLprQ3VCECoY49yfdDEHGCtMMj92pReUsQ/<0;1>/*)").unwrap();
+        assert!(TweakedDescriptor::new(multipath, tweak).is_err());
+    }
+
     #[test]
     fn parse_with_secrets() {
         let secp = &secp256k1::Secp256k1::signing_only();
@@ -1950,6 +3151,150 @@ pk(03f28773c2d975288bc7d1d205c3748651b075fbc6610e58cddeeddf8f19405aa8))";
         );
     }
 
+    #[test]
+    fn script_pubkey_at_derivation_range() {
+        let secp = secp256k1::Secp256k1::verification_only();
+        let xpub_a = "xpub6BgBgsespWvERF3LHQu6CnqdvfEvtMcQjYrcRzx53QJjSxarj2afYWcLteoGVky7D3UKDP9QyrLprQ3VCECoY49yfdDEHGCtMMj92pReUsQ";
+        let xpub_b = "xpub6ERApfZwUNrhLCkDtcHTcxd75RbzS1ed54G1LkBUHQVHQKqhMkhgbmJbZRkrgZw4koxb5JaHWkY4ALHY2grBGRjaDMzQLcgJvLJuZZvRcEL";
+        let single = "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798";
+        for desc in [
+            format!("wpkh([73c5da0a/84'/0'/0']{}/0/*)", xpub_a),
+            format!("sh(wsh(multi(2,{}/1/2/*,{}/*,{})))", xpub_a, xpub_b, single),
+            format!("tr({}/0/*,{{pk({}/1/*),pk({})}})", xpub_a, xpub_b, single),
+            format!("tr({}/0/*,pk({}))", xpub_a, single),
+            format!("wpkh({}/0/1)", xpub_b),
+            format!("tr(musig({}/0/*,{}),pk({}/1/*))", xpub_a, single, xpub_b),
+        ] {
+            let desc = Descriptor::<DescriptorPublicKey>::from_str(&desc).unwrap();
+            let expected = (5..15)
+                .map(|i| desc.derived_descriptor(&secp, i).unwrap().script_pubkey())
+                .collect::<Vec<_>>();
+            assert_eq!(
+                desc.script_pubkey_at_derivation_range(&secp, 5..15)
+                    .unwrap(),
+                expected
+            );
+        }
+
+        let hardened =
+            Descriptor::<DescriptorPublicKey>::from_str(&format!("wpkh({}/0/*h)", xpub_a)).unwrap();
+        assert_eq!(
+            hardened.script_pubkey_at_derivation_range(&secp, 0..2),
+            Err(ConversionError::HardenedChild)
+        );
+    }
+
+    #[test]
+    fn export_for_party() {
+        let secp = secp256k1::Secp256k1::verification_only();
+        let xpub_a = "xpub6BgBgsespWvERF3LHQu6CnqdvfEvtMcQjYrcRzx53QJjSxarj2afYWcLteoGVky7D3UKDP9QyrLprQ3VCECoY49yfdDEHGCtMMj92pReUsQ";
+        let xpub_b = "xpub6ERApfZwUNrhLCkDtcHTcxd75RbzS1ed54G1LkBUHQVHQKqhMkhgbmJbZRkrgZw4koxb5JaHWkY4ALHY2grBGRjaDMzQLcgJvLJuZZvRcEL";
+        let key_a = format!("[73c5da0a/86'/0'/0']{}/0/*", xpub_a);
+        let key_b = format!("{}/0/*", xpub_b);
+        let key_c = "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798";
+        let descriptor = Descriptor::<DescriptorPublicKey>::from_str(&format!(
+            "wsh(and_v(v:multi(2,{},{},{}),sha256({})))",
+            key_a,
+            key_b,
+            key_c,
+            sha256::Hash::hash(&[]),
+        ))
+        .unwrap();
+        let party = [DescriptorPublicKey::from_str(&key_a).unwrap()];
+
+        let exported = descriptor
+            .export_for_party(&secp, &party, KeyRedaction::KeyHash)
+            .unwrap();
+        let hash_b = hash160::Hash::hash(key_b.as_bytes());
+        let hash_c = PublicKey::from_str(key_c).unwrap().pubkey_hash();
+        assert_eq!(
+            exported.to_string(),
+            Descriptor::<String>::from_str(&format!(
+                "wsh(and_v(v:multi(2,{},{},{}),sha256({})))",
+                key_a,
+                hash_b,
+                hash_c,
+                sha256::Hash::hash(&[]),
+            ))
+            .unwrap()
+            .to_string()
+        );
+        assert!(!exported.to_string().contains(xpub_b));
+
+        // Two paths of the same extended key get different placeholders
+        let descriptor_paths = Descriptor::<DescriptorPublicKey>::from_str(&format!(
+            "wsh(multi(1,{},{}/0/*,{}/1/*))",
+            key_a, xpub_b, xpub_b
+        ))
+        .unwrap();
+        let exported = descriptor_paths
+            .export_for_party(&secp, &party, KeyRedaction::KeyHash)
+            .unwrap();
+        let hash_b0 = hash160::Hash::hash(format!("{}/0/*", xpub_b).as_bytes());
+        let hash_b1 = hash160::Hash::hash(format!("{}/1/*", xpub_b).as_bytes());
+        assert_ne!(hash_b0, hash_b1);
+        assert_eq!(
+            exported.to_string(),
+            Descriptor::<String>::from_str(&format!(
+                "wsh(multi(1,{},{},{}))",
+                key_a, hash_b0, hash_b1
+            ))
+            .unwrap()
+            .to_string()
+        );
+
+        // Without any party key, this is the same as the derived descriptor
+        let exported = descriptor
+            .export_for_party(&secp, &[], KeyRedaction::DerivedAt(1))
+            .unwrap();
+        assert_eq!(
+            exported.to_string(),
+            descriptor.derived_descriptor(&secp, 1).unwrap().to_string()
+        );
+        let exported = descriptor
+            .export_for_party(&secp, &party, KeyRedaction::DerivedAt(1))
+            .unwrap();
+        assert!(exported.to_string().contains(&key_a));
+        assert!(!exported.to_string().contains(xpub_b));
+
+        // Multipath keys can't be derived at a single index
+        let descriptor = Descriptor::<DescriptorPublicKey>::from_str(&format!(
+            "wsh(multi(1,{},{}/<0;1>/*))",
+            key_a, xpub_b
+        ))
+        .unwrap();
+        assert_eq!(
+            descriptor.export_for_party(&secp, &party, KeyRedaction::DerivedAt(1)),
+            Err(ConversionError::MultiKey)
+        );
+        assert!(descriptor
+            .export_for_party(&secp, &party, KeyRedaction::KeyHash)
+            .is_ok());
+    }
+
+    #[test]
+    fn to_string_with_aliases() {
+        for s in [
+            "wsh(and_v(vc:pk_k(A),andor(pkh(B),older(10),0)))",
+            "sh(wsh(or_i(and_v(v:pk(A),1),0)))",
+            "sh(or_d(c:pk_k(A),c:pk_h(B)))",
+            "tr(A,{c:pk_k(B),and_v(vc:pk_k(C),1)})",
+            "wpkh(A)",
+            "tr(A)",
+        ] {
+            let desc = Descriptor::<String>::from_str(s).unwrap();
+            let aliases = Aliases::detect(s).unwrap();
+            let with_aliases = desc.to_string_with_aliases(aliases);
+            assert_eq!(with_aliases.split('#').next(), Some(s));
+            // The checksum is valid
+            assert_eq!(Descriptor::<String>::from_str(&with_aliases).unwrap(), desc);
+            assert_eq!(Aliases::detect(&with_aliases).unwrap(), aliases);
+        }
+
+        let desc = Descriptor::<String>::from_str("wsh(c:pk_k(A))").unwrap();
+        assert_eq!(desc.to_string_with_aliases(Aliases::all()), desc.to_string());
+    }
+
     #[test]
     fn display_alternate() {
         let bare = StdDescriptor::from_str(
@@ -2083,6 +3428,26 @@ pk(03f28773c2d975288bc7d1d205c3748651b075fbc6610e58cddeeddf8f19405aa8))";
         .unwrap_err();
     }
 
+    #[test]
+    fn try_from_variants() {
+        let desc = Descriptor::<String>::from_str("wsh(pk(A))").unwrap();
+        assert!(desc.as_wsh().is_some());
+        assert!(desc.as_tr().is_none());
+        assert!(desc.as_sh().is_none());
+
+        let wsh = Wsh::try_from(desc.clone()).unwrap();
+        assert_eq!(Some(&wsh), desc.as_wsh());
+        assert_eq!(Descriptor::from(wsh), desc);
+
+        // The descriptor is returned on failure
+        assert_eq!(Tr::try_from(desc.clone()).unwrap_err(), desc);
+        assert_eq!(Data::try_from(desc.clone()).unwrap_err(), desc);
+
+        let desc = Descriptor::<String>::from_str("tr(A,pk(B))").unwrap();
+        assert_eq!(desc.as_tr().map(Tr::internal_key), desc.internal_key());
+        assert!(Tr::try_from(desc).is_ok());
+    }
+
     #[test]
     fn test_context_pks() {
         let comp_key = bitcoin::PublicKey::from_str(
@@ -2132,4 +3497,48 @@ pk(03f28773c2d975288bc7d1d205c3748651b075fbc6610e58cddeeddf8f19405aa8))";
         Desc::from_str(&format!("tr({},pk({}))", x_only_key, uncomp_key)).unwrap_err();
         Desc::from_str(&format!("tr({},pk({}))", x_only_key, x_only_key)).unwrap();
     }
+
+    #[test]
+    fn get_satisfaction_with_fallback() {
+        use bitcoin::secp256k1::{Message, Secp256k1, SecretKey};
+
+        let secp = Secp256k1::new();
+        let sk = SecretKey::from_slice(&[1; 32]).unwrap();
+        let pk = PublicKey::new(sk.public_key(&secp));
+        let sig = bitcoin::ecdsa::Signature::sighash_all(
+            secp.sign_ecdsa(&Message::from_digest([2; 32]), &sk),
+        );
+        let mut sigs = BTreeMap::new();
+        sigs.insert(pk, sig);
+
+        // Either timelock can be used, and a third party can pick the other one
+        let ms = Miniscript::<PublicKey, Segwitv0>::from_str_ext(
+            &format!("and_v(v:pk({}),or_i(older(10),older(20)))", pk),
+            &crate::ExtParams::insane(),
+        )
+        .unwrap();
+        let desc = Descriptor::new_wsh(ms).unwrap();
+
+        let sat = desc
+            .get_satisfaction_with_fallback((&sigs, Sequence::from_height(20)))
+            .unwrap();
+        assert!(sat.is_malleable());
+        assert!(matches!(sat.fallback_reason, Some(Error::CouldNotSatisfy)));
+        assert_eq!(
+            sat.witness,
+            desc.get_satisfaction_mall((&sigs, Sequence::from_height(20)))
+                .unwrap()
+                .0
+        );
+
+        // Only one timelock can be used
+        let sat = desc
+            .get_satisfaction_with_fallback((&sigs, Sequence::from_height(10)))
+            .unwrap();
+        assert!(!sat.is_malleable());
+
+        assert!(desc
+            .get_satisfaction_with_fallback((&sigs, Sequence::ZERO))
+            .is_err());
+    }
 }
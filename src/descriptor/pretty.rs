@@ -0,0 +1,207 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! # Pretty Printing
+//!
+//! Multi-line, indented rendering of descriptors for human review, and parsing
+//! of that rendering back into descriptors.
+//!
+
+use core::mem;
+use core::str::FromStr;
+
+use super::checksum::verify_checksum;
+use super::Descriptor;
+use crate::expression::ParseTreeError;
+use crate::prelude::*;
+use crate::{Error, FromStrKey, MiniscriptKey};
+
+/// Number of spaces by which each level of the tree is indented.
+const INDENT: usize = 2;
+
+/// Maximum width of a line on which a fragment is kept with its arguments.
+const MAX_INLINE_WIDTH: usize = 80;
+
+/// A fragment, or a taproot branch, and its arguments
+struct Node {
+    name: String,
+    delims: (char, char),
+    args: Vec<Node>,
+}
+
+impl Node {
+    fn leaf(name: String) -> Self { Node { name, delims: ('(', ')'), args: vec![] } }
+
+    /// Parses the string representation of a descriptor, without checksum.
+    fn parse(s: &str) -> Self {
+        let mut stack = vec![Node::leaf(String::new())];
+        let mut name = String::new();
+        for ch in s.chars() {
+            match ch {
+                '(' | '{' => {
+                    let close = if ch == '(' { ')' } else { '}' };
+                    stack.push(Node {
+                        name: mem::take(&mut name),
+                        delims: (ch, close),
+                        args: vec![],
+                    });
+                }
+                ',' | ')' | '}' => {
+                    let top = stack.last_mut().expect("non-empty stack");
+                    if !name.is_empty() {
+                        top.args.push(Node::leaf(mem::take(&mut name)));
+                    }
+                    if ch != ',' && stack.len() > 1 {
+                        let node = stack.pop().expect("non-empty stack");
+                        stack.last_mut().expect("non-empty stack").args.push(node);
+                    }
+                }
+                _ => name.push(ch),
+            }
+        }
+        let mut root = stack.swap_remove(0);
+        if !name.is_empty() {
+            root.args.push(Node::leaf(name));
+        }
+        root.args.pop().unwrap_or_else(|| Node::leaf(String::new()))
+    }
+
+    fn write_inline(&self, out: &mut String) {
+        out.push_str(&self.name);
+        if !self.args.is_empty() {
+            out.push(self.delims.0);
+            for (i, arg) in self.args.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                arg.write_inline(out);
+            }
+            out.push(self.delims.1);
+        }
+    }
+
+    fn write_pretty(&self, depth: usize, out: &mut String) {
+        let indent = depth * INDENT;
+        out.extend(core::iter::repeat(' ').take(indent));
+
+        let mut inline = String::new();
+        self.write_inline(&mut inline);
+        if self.args.iter().all(|arg| arg.args.is_empty())
+            && indent + inline.len() <= MAX_INLINE_WIDTH
+        {
+            out.push_str(&inline);
+            return;
+        }
+
+        out.push_str(&self.name);
+        out.push(self.delims.0);
+        out.push('\n');
+        for (i, arg) in self.args.iter().enumerate() {
+            arg.write_pretty(depth + 1, out);
+            if i + 1 < self.args.len() {
+                out.push(',');
+            }
+            out.push('\n');
+        }
+        out.extend(core::iter::repeat(' ').take(indent));
+        out.push(self.delims.1);
+    }
+}
+
+impl<Pk: MiniscriptKey> Descriptor<Pk> {
+    /// Renders the descriptor on several lines, with one fragment per line
+    /// indented by its depth in the tree, and Taproot trees drawn as nested
+    /// branches
+    ///
+    /// Fragments whose arguments are all keys or values, like `pk(A)` or
+    /// `multi(2,A,B,C)`, are kept on a single line if it is short enough. The
+    /// checksum follows the last line. The result is parsed back with
+    /// [`Descriptor::from_str_pretty`].
+    pub fn pretty(&self) -> String {
+        let s = self.to_string();
+        let (desc, checksum) = s.split_once('#').unwrap_or((&s, ""));
+        let mut ret = String::with_capacity(2 * s.len());
+        Node::parse(desc).write_pretty(0, &mut ret);
+        if !checksum.is_empty() {
+            ret.push('#');
+            ret.push_str(checksum);
+        }
+        ret
+    }
+}
+
+impl<Pk: FromStrKey> Descriptor<Pk> {
+    /// Parses a descriptor rendered by [`Descriptor::pretty`]
+    ///
+    /// All whitespace is ignored, so the descriptor may have been reindented or
+    /// wrapped. A checksum is verified if present.
+    pub fn from_str_pretty(s: &str) -> Result<Self, Error> {
+        let stripped: String = s.chars().filter(|c| !c.is_whitespace()).collect();
+        verify_checksum(&stripped).map_err(|e| Error::ParseTree(ParseTreeError::from(e)))?;
+        Descriptor::from_str(&stripped)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pretty() {
+        let desc = Descriptor::<String>::from_str(
+            "wsh(or_d(multi(2,A,B,C),and_v(v:pk(D),or_i(older(144),after(500)))))",
+        )
+        .unwrap();
+        let pretty = desc.pretty();
+        let checksum = desc.to_string().split_once('#').unwrap().1.to_owned();
+        assert_eq!(
+            pretty,
+            format!(
+                "wsh(
+  or_d(
+    multi(2,A,B,C),
+    and_v(
+      v:pk(D),
+      or_i(
+        older(144),
+        after(500)
+      )
+    )
+  )
+)#{}",
+                checksum
+            )
+        );
+        assert_eq!(Descriptor::from_str_pretty(&pretty).unwrap(), desc);
+
+        // Taproot trees
+        let desc = Descriptor::<String>::from_str("tr(K,{pk(A),{and_v(v:pk(B),older(1)),pk(C)}})")
+            .unwrap();
+        let pretty = desc.pretty();
+        assert!(pretty.starts_with(
+            "tr(
+  K,
+  {
+    pk(A),
+    {
+      and_v(
+        v:pk(B),
+        older(1)
+      ),
+      pk(C)
+    }
+  }
+)#"
+        ));
+        assert_eq!(Descriptor::from_str_pretty(&pretty).unwrap(), desc);
+
+        // Reindented descriptors parse, but the checksum is still checked
+        let reindented = pretty.replace("  ", "\t").replace('\n', " \r\n");
+        assert_eq!(Descriptor::<String>::from_str_pretty(&reindented).unwrap(), desc);
+        let corrupted = pretty.replace("older(1)", "older(2)");
+        assert!(Descriptor::<String>::from_str_pretty(&corrupted).is_err());
+
+        // Short descriptors stay on one line
+        let desc = Descriptor::<String>::from_str("wpkh(A)").unwrap();
+        assert_eq!(desc.pretty(), desc.to_string());
+    }
+}
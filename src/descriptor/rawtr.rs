@@ -0,0 +1,206 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! # Raw Taproot Descriptors
+//!
+//! Implementation of the `rawtr(KEY)` descriptor, describing a Taproot output
+//! whose output key is `KEY` itself, without any tweak. Such outputs can only be
+//! spent through the key path, by a signer that knows how to sign for the output
+//! key, see [`Satisfier::lookup_raw_tr_schnorr_sig`].
+//!
+
+use core::fmt;
+
+use bitcoin::key::TweakedPublicKey;
+use bitcoin::{Address, Network, ScriptBuf, Weight};
+
+use crate::descriptor::{write_descriptor, DefiniteDescriptorKey};
+use crate::expression::{self, FromTree};
+use crate::miniscript::context::ScriptContext;
+use crate::miniscript::satisfy::{Placeholder, Satisfaction, SchnorrSigType, Witness};
+use crate::plan::AssetProvider;
+use crate::policy::{semantic, Liftable};
+use crate::prelude::*;
+use crate::util::varint_len;
+use crate::{
+    Error, ForEachKey, FromStrKey, MiniscriptKey, Satisfier, Tap, ToPublicKey, TranslateErr,
+    Translator,
+};
+
+/// A descriptor for a Taproot output whose output key is given directly
+#[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Hash)]
+pub struct RawTr<Pk: MiniscriptKey> {
+    /// the output key
+    pk: Pk,
+}
+
+impl<Pk: MiniscriptKey> RawTr<Pk> {
+    /// Create a new RawTr descriptor
+    pub fn new(pk: Pk) -> Result<Self, Error> {
+        Tap::check_pk(&pk)?;
+        Ok(RawTr { pk })
+    }
+
+    /// Get a reference to the output key
+    pub fn as_inner(&self) -> &Pk { &self.pk }
+
+    /// Get the output key
+    pub fn into_inner(self) -> Pk { self.pk }
+
+    /// Checks whether the descriptor is safe.
+    ///
+    /// A single key can't be unsafe, so this never errors.
+    pub fn sanity_check(&self) -> Result<(), Error> { Ok(()) }
+
+    /// Computes an upper bound on the difference between a non-satisfied
+    /// `TxIn`'s `segwit_weight` and a satisfied `TxIn`'s `segwit_weight`
+    ///
+    /// Assumes the Schnorr signature is 66 bytes, including push opcode and
+    /// sighash suffix.
+    pub fn max_weight_to_satisfy(&self) -> Weight {
+        // item: varint(sig+sigHash) + <sig(64)+sigHash(1)>
+        let item_sig_size = 1 + 65;
+        // 1 stack item
+        let stack_varint_diff = varint_len(1) - varint_len(0);
+
+        Weight::from_wu((stack_varint_diff + item_sig_size) as u64)
+    }
+
+    /// Computes an upper bound on the weight of a satisfying witness to the
+    /// transaction.
+    ///
+    /// Assumes the Schnorr signature is 66 bytes, including push opcode and
+    /// sighash suffix. Includes the weight of the VarInts encoding the
+    /// scriptSig and witness stack length.
+    #[deprecated(
+        since = "10.0.0",
+        note = "Use max_weight_to_satisfy instead. The method to count bytes was redesigned and the results will differ from max_weight_to_satisfy. For more details check rust-bitcoin/rust-miniscript#476."
+    )]
+    pub fn max_satisfaction_weight(&self) -> usize {
+        // scriptSigLen(4) + stackLen(1) + stack[Sig]Len(1) + stack[Sig](65)
+        4 + 1 + 1 + 65
+    }
+
+    /// Converts the keys in a script from one type to another.
+    pub fn translate_pk<T>(&self, t: &mut T) -> Result<RawTr<T::TargetPk>, TranslateErr<T::Error>>
+    where
+        T: Translator<Pk>,
+    {
+        RawTr::new(t.pk(&self.pk)?).map_err(TranslateErr::OuterError)
+    }
+}
+
+impl<Pk: MiniscriptKey + ToPublicKey> RawTr<Pk> {
+    /// Obtains the output key, which isn't tweaked
+    pub fn output_key(&self) -> TweakedPublicKey {
+        TweakedPublicKey::dangerous_assume_tweaked(self.pk.to_x_only_pubkey())
+    }
+
+    /// Obtains the corresponding script pubkey for this descriptor.
+    pub fn script_pubkey(&self) -> ScriptBuf { ScriptBuf::new_p2tr_tweaked(self.output_key()) }
+
+    /// Obtains the corresponding address for this descriptor.
+    pub fn address(&self, network: Network) -> Address {
+        Address::p2tr_tweaked(self.output_key(), network)
+    }
+
+    /// Returns satisfying non-malleable witness and scriptSig with minimum
+    /// weight to spend an output controlled by the given descriptor if it is
+    /// possible to construct one using the `satisfier`.
+    pub fn get_satisfaction<S>(&self, satisfier: S) -> Result<(Vec<Vec<u8>>, ScriptBuf), Error>
+    where
+        S: Satisfier<Pk>,
+    {
+        if let Some(sig) = satisfier.lookup_raw_tr_schnorr_sig(&self.pk) {
+            Ok((vec![sig.to_vec()], ScriptBuf::new()))
+        } else {
+            Err(Error::CouldNotSatisfy)
+        }
+    }
+
+    /// Returns satisfying, possibly malleable, witness and scriptSig with
+    /// minimum weight to spend an output controlled by the given descriptor if
+    /// it is possible to construct one using the `satisfier`.
+    pub fn get_satisfaction_mall<S>(&self, satisfier: S) -> Result<(Vec<Vec<u8>>, ScriptBuf), Error>
+    where
+        S: Satisfier<Pk>,
+    {
+        self.get_satisfaction(satisfier)
+    }
+}
+
+impl RawTr<DefiniteDescriptorKey> {
+    /// Returns a plan if the provided assets are sufficient to produce a non-malleable satisfaction
+    pub fn plan_satisfaction<P>(
+        &self,
+        provider: &P,
+    ) -> Satisfaction<Placeholder<DefiniteDescriptorKey>>
+    where
+        P: AssetProvider<DefiniteDescriptorKey>,
+    {
+        let stack = match provider.provider_lookup_raw_tr_sig(&self.pk) {
+            Some(size) if provider.check_tap_path(None) => {
+                Witness::Stack(vec![Placeholder::SchnorrSigPk(
+                    self.pk.clone(),
+                    SchnorrSigType::RawKeySpend,
+                    size,
+                )])
+            }
+            _ => Witness::Unavailable,
+        };
+
+        Satisfaction { stack, has_sig: true, relative_timelock: None, absolute_timelock: None }
+    }
+
+    /// Returns a plan if the provided assets are sufficient to produce a malleable satisfaction
+    pub fn plan_satisfaction_mall<P>(
+        &self,
+        provider: &P,
+    ) -> Satisfaction<Placeholder<DefiniteDescriptorKey>>
+    where
+        P: AssetProvider<DefiniteDescriptorKey>,
+    {
+        self.plan_satisfaction(provider)
+    }
+}
+
+impl<Pk: MiniscriptKey> fmt::Debug for RawTr<Pk> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { write!(f, "rawtr({:?})", self.pk) }
+}
+
+impl<Pk: MiniscriptKey> fmt::Display for RawTr<Pk> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_descriptor!(f, "rawtr({})", self.pk)
+    }
+}
+
+impl<Pk: MiniscriptKey> Liftable<Pk> for RawTr<Pk> {
+    fn lift(&self) -> Result<semantic::Policy<Pk>, Error> {
+        Ok(semantic::Policy::Key(self.pk.clone()))
+    }
+}
+
+impl<Pk: FromStrKey> FromTree for RawTr<Pk> {
+    fn from_tree(top: &expression::Tree) -> Result<Self, Error> {
+        if top.name == "rawtr" && top.args.len() == 1 {
+            RawTr::new(expression::terminal(&top.args[0], |pk| Pk::from_str(pk))?)
+        } else {
+            Err(Error::Unexpected(format!(
+                "{}({} args) while parsing rawtr descriptor",
+                top.name,
+                top.args.len(),
+            )))
+        }
+    }
+}
+
+impl<Pk: FromStrKey> core::str::FromStr for RawTr<Pk> {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let top = expression::Tree::from_str(s)?;
+        Self::from_tree(&top)
+    }
+}
+
+impl<Pk: MiniscriptKey> ForEachKey<Pk> for RawTr<Pk> {
+    fn for_each_key<'a, F: FnMut(&'a Pk) -> bool>(&'a self, mut pred: F) -> bool { pred(&self.pk) }
+}
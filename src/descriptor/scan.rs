@@ -0,0 +1,582 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! # Scanning
+//!
+//! Conversion of descriptors to the scan objects accepted by the
+//! `scantxoutset` and `scanblocks` RPCs of Bitcoin Core, gap limit scanning
+//! of the derivation indexes of a descriptor against a user-provided source of
+//! chain data, and lazy derivation of the script pubkeys of a range of
+//! derivation indexes for indexers.
+//!
+
+use core::iter::FusedIterator;
+use core::ops::Range;
+use core::{cmp, fmt};
+
+use bitcoin::secp256k1::{self, Secp256k1};
+use bitcoin::{bip32, Amount, Script, ScriptBuf};
+
+use super::key::{DescriptorMusigKey, DescriptorXKey, SinglePub, SinglePubKey, Wildcard};
+use super::tr::TrSpkCache;
+use super::{ConversionError, Descriptor, DescriptorPublicKey};
+use crate::prelude::*;
+use crate::{Error, Translator};
+
+/// A scan object of the `scantxoutset` and `scanblocks` RPCs of Bitcoin Core
+///
+/// Its `Display` implementation is the JSON value expected by the RPCs: the
+/// descriptor string for descriptors without wildcards, an object with the
+/// descriptor and the range to scan otherwise.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ScanObject {
+    /// The descriptor, with its checksum
+    pub desc: String,
+    /// The first and last derivation indexes to scan, if the descriptor has wildcards
+    pub range: Option<(u32, u32)>,
+}
+
+impl fmt::Display for ScanObject {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.range {
+            Some((begin, end)) => {
+                write!(f, "{{\"desc\":\"{}\",\"range\":[{},{}]}}", self.desc, begin, end)
+            }
+            None => write!(f, "\"{}\"", self.desc),
+        }
+    }
+}
+
+impl Descriptor<DescriptorPublicKey> {
+    /// Converts the descriptor to the scan objects to pass to the `scantxoutset`
+    /// or `scanblocks` RPCs of Bitcoin Core to scan the derivation indexes in `range`
+    ///
+    /// Multipath descriptors, which these RPCs don't accept, are split into one
+    /// scan object per path.
+    ///
+    /// # Errors
+    ///
+    /// If `range` is empty and the descriptor has wildcards, or if the descriptor
+    /// is an invalid multipath descriptor.
+    pub fn to_scan_objects(&self, range: Range<u32>) -> Result<Vec<ScanObject>, Error> {
+        let range = self.checked_derivation_range(range)?;
+        let range = if self.has_wildcard() {
+            Some((range.start, range.end - 1))
+        } else {
+            None
+        };
+        Ok(self
+            .clone()
+            .into_single_descriptors()?
+            .into_iter()
+            .map(|desc| ScanObject { desc: desc.to_string(), range })
+            .collect())
+    }
+}
+
+/// The on-chain activity of a script pubkey
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct ScriptActivity {
+    /// The number of transactions paying to or spending from the script pubkey
+    pub tx_count: usize,
+    /// The value of the unspent outputs paying to the script pubkey
+    pub balance: Amount,
+}
+
+impl ScriptActivity {
+    /// Whether the script pubkey appears in any transaction
+    pub fn is_used(&self) -> bool { self.tx_count > 0 }
+}
+
+/// A source of chain data to scan descriptors against, such as an Electrum
+/// server or a block filter index
+pub trait ChainSource {
+    /// The error of the source
+    type Error;
+
+    /// Looks up the activity of a script pubkey
+    fn activity(&mut self, script_pubkey: &Script) -> Result<ScriptActivity, Self::Error>;
+}
+
+/// Error scanning a descriptor with [`Descriptor::scan`]
+#[derive(Debug, PartialEq)]
+pub enum ScanError<E> {
+    /// The descriptor can't be derived at an index
+    Derivation(u32, ConversionError),
+    /// The chain source failed
+    Source(E),
+}
+
+impl<E: fmt::Display> fmt::Display for ScanError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ScanError::Derivation(index, ref e) => {
+                write!(f, "deriving descriptor at index {}: {}", index, e)
+            }
+            ScanError::Source(ref e) => write!(f, "chain source: {}", e),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<E: fmt::Debug + fmt::Display> std::error::Error for ScanError<E> {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match *self {
+            ScanError::Derivation(_, ref e) => Some(e),
+            ScanError::Source(_) => None,
+        }
+    }
+}
+
+/// The state of a gap limit scan of derivation indexes
+///
+/// Indexes are scanned in order until `gap_limit` consecutive indexes are
+/// unused. The window of indexes to scan is extended every time a used index
+/// is recorded. [`Descriptor::scan`] drives a scan against a [`ChainSource`];
+/// use the state machine directly to drive it otherwise, for example to batch
+/// queries.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct GapScan {
+    gap_limit: u32,
+    next: Option<u32>,
+    end: u32,
+    used: Vec<(u32, ScriptActivity)>,
+}
+
+impl GapScan {
+    /// Starts a scan at index `start`, with a gap limit of `gap_limit`
+    pub fn new(start: u32, gap_limit: u32) -> Self {
+        GapScan { gap_limit, next: Some(start), end: start.saturating_add(gap_limit), used: vec![] }
+    }
+
+    /// The next index to look up, or `None` once the scan is done
+    ///
+    /// The scan is done once `gap_limit` consecutive indexes are unused, or the
+    /// last unhardened index was looked up.
+    pub fn next_index(&self) -> Option<u32> {
+        self.next
+            .filter(|&index| index < self.end && index < (1 << 31))
+    }
+
+    /// The indexes which remain to look up in the current window
+    ///
+    /// Looking them all up at once doesn't change the result of the scan, as
+    /// the window is only ever extended.
+    pub fn window(&self) -> Range<u32> {
+        match self.next_index() {
+            Some(index) => index..self.end.min(1 << 31),
+            None => 0..0,
+        }
+    }
+
+    /// Records the activity of the script pubkey at the index returned by
+    /// [`GapScan::next_index`], and moves to the next index
+    pub fn record(&mut self, activity: ScriptActivity) {
+        let index = match self.next_index() {
+            Some(index) => index,
+            None => return,
+        };
+        if activity.is_used() {
+            self.used.push((index, activity));
+            self.end = self
+                .end
+                .max(index.saturating_add(1).saturating_add(self.gap_limit));
+        }
+        self.next = index.checked_add(1);
+    }
+
+    /// Whether the scan is done
+    pub fn is_done(&self) -> bool { self.next_index().is_none() }
+
+    /// The used indexes recorded so far, with their activity
+    pub fn used(&self) -> &[(u32, ScriptActivity)] { &self.used }
+
+    /// The total balance of the used indexes recorded so far
+    pub fn balance(&self) -> Amount { self.used.iter().map(|(_, a)| a.balance).sum() }
+
+    /// The total number of transactions of the used indexes recorded so far
+    ///
+    /// This is an upper bound on the number of transactions of the wallet, as a
+    /// transaction involving several of its script pubkeys is counted for each.
+    pub fn tx_count(&self) -> usize { self.used.iter().map(|(_, a)| a.tx_count).sum() }
+
+    /// The first index after the last used one, i.e. the next index to hand
+    /// out, or `None` if no index is used
+    pub fn next_unused(&self) -> Option<u32> {
+        self.used.last().map(|(index, _)| index.saturating_add(1))
+    }
+}
+
+impl Descriptor<DescriptorPublicKey> {
+    /// Scans the derivation indexes of the descriptor against `source`, from
+    /// index 0 until `gap_limit` consecutive indexes are unused
+    ///
+    /// A descriptor without wildcards only has its single script pubkey looked
+    /// up. Multipath descriptors must be split with
+    /// [`Descriptor::into_single_descriptors`] and each path scanned separately.
+    pub fn scan<S: ChainSource>(
+        &self,
+        gap_limit: u32,
+        source: &mut S,
+    ) -> Result<GapScan, ScanError<S::Error>> {
+        let mut scan = if self.has_wildcard() {
+            GapScan::new(0, gap_limit)
+        } else {
+            GapScan { gap_limit: 0, next: Some(0), end: 1, used: vec![] }
+        };
+        while let Some(index) = scan.next_index() {
+            let spk = self
+                .at_derivation_index(index)
+                .map_err(|e| ScanError::Derivation(index, e))?
+                .script_pubkey();
+            scan.record(source.activity(&spk).map_err(ScanError::Source)?);
+        }
+        Ok(scan)
+    }
+}
+
+/// An iterator over the script pubkeys of a descriptor at each derivation
+/// index of a range, created by [`Descriptor::spk_iter`]
+///
+/// The derivation steps of the keys which don't depend on the index are taken
+/// once when the iterator is created, and the tap tree of a taproot descriptor
+/// is only hashed once if its leaves don't depend on the index, so each script
+/// pubkey costs one child derivation per ranged key. The iterator is cheap to
+/// clone, and can be split with [`SpkIter::split_at`], e.g. to implement the
+/// `Producer` trait of rayon.
+pub struct SpkIter<'s, C: secp256k1::Verification> {
+    secp: &'s Secp256k1<C>,
+    /// The descriptor, with its keys derived up to their wildcard
+    desc: Descriptor<DescriptorPublicKey>,
+    source: SpkSource,
+    range: Range<u32>,
+}
+
+// Manual impls, to not require the bounds of the secp context
+impl<C: secp256k1::Verification> Clone for SpkIter<'_, C> {
+    fn clone(&self) -> Self {
+        SpkIter {
+            secp: self.secp,
+            desc: self.desc.clone(),
+            source: self.source.clone(),
+            range: self.range.clone(),
+        }
+    }
+}
+
+impl<C: secp256k1::Verification> fmt::Debug for SpkIter<'_, C> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SpkIter")
+            .field("desc", &self.desc)
+            .field("range", &self.range)
+            .finish_non_exhaustive()
+    }
+}
+
+/// How the script pubkey at an index is obtained
+#[derive(Clone, Debug)]
+enum SpkSource {
+    /// The descriptor has no wildcard
+    Fixed(ScriptBuf),
+    /// A taproot descriptor
+    Tr(TrSpkCache),
+    /// Any other descriptor, derived at each index
+    Derived,
+}
+
+impl<C: secp256k1::Verification> SpkIter<'_, C> {
+    /// Splits the iterator into the first `index` remaining script pubkeys and
+    /// the rest
+    ///
+    /// If `index` is greater than the number of remaining script pubkeys, the
+    /// second iterator is empty.
+    pub fn split_at(self, index: usize) -> (Self, Self) {
+        let mid = self.range.start + cmp::min(index, self.range.len()) as u32;
+        let left = SpkIter { range: self.range.start..mid, ..self.clone() };
+        let right = SpkIter { range: mid..self.range.end, ..self };
+        (left, right)
+    }
+
+    /// The remaining derivation indexes
+    pub fn range(&self) -> Range<u32> { self.range.clone() }
+
+    fn spk_at(&mut self, index: u32) -> ScriptBuf {
+        let spk = match (&mut self.source, &self.desc) {
+            (SpkSource::Fixed(spk), _) => return spk.clone(),
+            (SpkSource::Tr(cache), Descriptor::Tr(tr)) => {
+                cache.script_pubkey_at(tr, self.secp, index)
+            }
+            (_, desc) => desc
+                .derived_descriptor(self.secp, index)
+                .map(|desc| desc.script_pubkey()),
+        };
+        spk.expect("the range and keys are checked on construction")
+    }
+}
+
+impl<C: secp256k1::Verification> Iterator for SpkIter<'_, C> {
+    type Item = (u32, ScriptBuf);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let index = self.range.next()?;
+        Some((index, self.spk_at(index)))
+    }
+
+    fn nth(&mut self, n: usize) -> Option<Self::Item> {
+        let index = self.range.nth(n)?;
+        Some((index, self.spk_at(index)))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) { self.range.size_hint() }
+}
+
+impl<C: secp256k1::Verification> DoubleEndedIterator for SpkIter<'_, C> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let index = self.range.next_back()?;
+        Some((index, self.spk_at(index)))
+    }
+}
+
+impl<C: secp256k1::Verification> ExactSizeIterator for SpkIter<'_, C> {}
+
+impl<C: secp256k1::Verification> FusedIterator for SpkIter<'_, C> {}
+
+impl Descriptor<DescriptorPublicKey> {
+    /// Lazily derives the script pubkey of the descriptor at each index of
+    /// `range`, yielding the index along with it
+    ///
+    /// A descriptor without wildcards yields its single script pubkey at every
+    /// index. Multipath descriptors must be split with
+    /// [`Descriptor::into_single_descriptors`] first.
+    ///
+    /// # Errors
+    ///
+    /// If the descriptor is multipath, has a hardened wildcard, or has a
+    /// wildcard and `range` includes hardened indexes.
+    pub fn spk_iter<'s, C: secp256k1::Verification>(
+        &self,
+        range: Range<u32>,
+        secp: &'s Secp256k1<C>,
+    ) -> Result<SpkIter<'s, C>, ConversionError> {
+        struct Prederivator<'s, C: secp256k1::Verification>(&'s Secp256k1<C>);
+
+        impl<C: secp256k1::Verification> Translator<DescriptorPublicKey> for Prederivator<'_, C> {
+            type TargetPk = DescriptorPublicKey;
+            type Error = ConversionError;
+
+            fn pk(
+                &mut self,
+                pk: &DescriptorPublicKey,
+            ) -> Result<DescriptorPublicKey, ConversionError> {
+                let xpub = match *pk {
+                    DescriptorPublicKey::XPub(ref xpub) => xpub,
+                    DescriptorPublicKey::Single(_) => return Ok(pk.clone()),
+                    DescriptorPublicKey::MultiXPub(_) => return Err(ConversionError::MultiKey),
+                    DescriptorPublicKey::Musig(ref musig) => {
+                        let keys = musig
+                            .keys()
+                            .iter()
+                            .map(|key| self.pk(key))
+                            .collect::<Result<_, _>>()?;
+                        return Ok(DescriptorPublicKey::Musig(
+                            DescriptorMusigKey::new(keys).expect("derived keys are valid"),
+                        ));
+                    }
+                };
+                let xkey = xpub
+                    .xkey
+                    .derive_pub(self.0, &xpub.derivation_path)
+                    .map_err(|_| ConversionError::HardenedChild)?;
+                Ok(match xpub.wildcard {
+                    Wildcard::None => DescriptorPublicKey::Single(SinglePub {
+                        origin: None,
+                        key: SinglePubKey::FullKey(bitcoin::PublicKey::new(xkey.public_key)),
+                    }),
+                    Wildcard::Unhardened => DescriptorPublicKey::XPub(DescriptorXKey {
+                        origin: None,
+                        xkey,
+                        derivation_path: bip32::DerivationPath::master(),
+                        wildcard: Wildcard::Unhardened,
+                    }),
+                    Wildcard::Hardened => return Err(ConversionError::HardenedChild),
+                })
+            }
+
+            translate_hash_clone!(DescriptorPublicKey, DescriptorPublicKey, ConversionError);
+        }
+
+        // Wildcards of public keys only derive normal children
+        if self.has_wildcard() && range.end > 1 << 31 {
+            return Err(ConversionError::HardenedChild);
+        }
+        let desc = self
+            .translate_pk(&mut Prederivator(secp))
+            .map_err(|e| e.expect_translator_err("No Context errors when deriving keys"))?;
+        let source = if !desc.has_wildcard() {
+            SpkSource::Fixed(desc.derived_descriptor(secp, 0)?.script_pubkey())
+        } else if let Descriptor::Tr(ref tr) = desc {
+            SpkSource::Tr(TrSpkCache::new(tr))
+        } else {
+            SpkSource::Derived
+        };
+        Ok(SpkIter { secp, desc, source, range })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::descriptor::DerivationRangeError;
+    use crate::test_utils::xpub_desc;
+
+    #[test]
+    fn to_scan_objects() {
+        let objects = xpub_desc("wpkh(XPUB/<0;1>/*)")
+            .to_scan_objects(0..1000)
+            .unwrap();
+        assert_eq!(objects.len(), 2);
+        assert_eq!(objects[0].desc, xpub_desc("wpkh(XPUB/0/*)").to_string());
+        assert_eq!(objects[1].desc, xpub_desc("wpkh(XPUB/1/*)").to_string());
+        assert_eq!(
+            objects[0].to_string(),
+            format!("{{\"desc\":\"{}\",\"range\":[0,999]}}", objects[0].desc)
+        );
+
+        let single = xpub_desc("pkh(XPUB/0/5)");
+        let objects = single.to_scan_objects(0..0).unwrap();
+        assert_eq!(objects, vec![ScanObject { desc: single.to_string(), range: None }]);
+        assert_eq!(objects[0].to_string(), format!("\"{}\"", single));
+        assert!(objects[0].desc.contains('#'));
+
+        assert!(matches!(
+            xpub_desc("wpkh(XPUB/0/*)").to_scan_objects(5..5),
+            Err(Error::DerivationRange(DerivationRangeError::EmptyRange(_)))
+        ));
+    }
+
+    /// A chain source with activity at some script pubkeys
+    struct MockSource {
+        activity: Vec<(bitcoin::ScriptBuf, ScriptActivity)>,
+        lookups: usize,
+    }
+
+    impl ChainSource for MockSource {
+        type Error = &'static str;
+
+        fn activity(&mut self, script_pubkey: &Script) -> Result<ScriptActivity, Self::Error> {
+            self.lookups += 1;
+            if self.lookups > 100 {
+                return Err("too many lookups");
+            }
+            Ok(self
+                .activity
+                .iter()
+                .find(|(spk, _)| **spk == *script_pubkey)
+                .map(|(_, a)| *a)
+                .unwrap_or_default())
+        }
+    }
+
+    #[test]
+    fn scan() {
+        let wpkh = xpub_desc("wpkh(XPUB/0/*)");
+        let spk = |i| wpkh.at_derivation_index(i).unwrap().script_pubkey();
+        let used = |tx_count, sat| ScriptActivity { tx_count, balance: Amount::from_sat(sat) };
+        let mut source = MockSource {
+            activity: vec![
+                (spk(2), used(1, 1000)),
+                (spk(6), used(2, 500)),
+                (spk(14), used(1, 1)),
+            ],
+            lookups: 0,
+        };
+
+        // Index 14 is beyond the gap after index 6
+        let scan = wpkh.scan(5, &mut source).unwrap();
+        assert!(scan.is_done());
+        assert_eq!(source.lookups, 12);
+        assert_eq!(scan.used(), &[(2, used(1, 1000)), (6, used(2, 500))]);
+        assert_eq!(scan.balance(), Amount::from_sat(1500));
+        assert_eq!(scan.tx_count(), 3);
+        assert_eq!(scan.next_unused(), Some(7));
+
+        // The window is extended by the used indexes found
+        let mut gap = GapScan::new(0, 5);
+        assert_eq!(gap.window(), 0..5);
+        gap.record(ScriptActivity::default());
+        gap.record(ScriptActivity::default());
+        gap.record(used(1, 0));
+        assert_eq!(gap.window(), 3..8);
+
+        // Descriptors without wildcards are looked up once
+        source.lookups = 0;
+        let single = xpub_desc("wpkh(XPUB/0/6)");
+        let scan = single.scan(20, &mut source).unwrap();
+        assert_eq!(source.lookups, 1);
+        assert_eq!(scan.used(), &[(0, used(2, 500))]);
+
+        source.lookups = 100;
+        assert_eq!(wpkh.scan(5, &mut source), Err(ScanError::Source("too many lookups")));
+        let err = xpub_desc("wpkh(XPUB/<0;1>/*)")
+            .scan(5, &mut source)
+            .unwrap_err();
+        assert_eq!(err, ScanError::Derivation(0, ConversionError::MultiKey));
+        #[cfg(feature = "std")]
+        assert!(std::error::Error::source(&err).is_some());
+    }
+
+    #[test]
+    fn spk_iter() {
+        let secp = secp256k1::Secp256k1::verification_only();
+        let single = "02c6047f9441ed7d6d3045406e95c07cd85c778e4b8cef3ca7abac09b95c709ee5";
+        for s in &[
+            "wpkh(XPUB/0/*)".to_owned(),
+            format!("sh(wsh(multi(2,XPUB/1/*,{},XPUB/2/3)))", single),
+            format!("tr(XPUB/0/*,{{pk({}),pk(XPUB/1/2)}})", single),
+            "tr(XPUB/0/*,pk(XPUB/1/*))".to_owned(),
+            "pkh([d34db33f/44'/0'/0']XPUB/0/7)".to_owned(),
+        ] {
+            let desc = xpub_desc(s);
+            let expected: Vec<_> = (10..20)
+                .map(|i| (i, desc.derived_descriptor(&secp, i).unwrap().script_pubkey()))
+                .collect();
+
+            let mut iter = desc.spk_iter(10..20, &secp).unwrap();
+            assert_eq!(iter.len(), 10);
+            assert_eq!(iter.next(), Some(expected[0].clone()));
+            assert_eq!(iter.size_hint(), (9, Some(9)));
+            assert_eq!(iter.next_back(), Some(expected[9].clone()));
+            assert_eq!(iter.nth(2), Some(expected[3].clone()));
+            assert_eq!(iter.range(), 14..19);
+
+            let (left, right) = iter.clone().split_at(2);
+            assert_eq!(left.collect::<Vec<_>>(), expected[4..6]);
+            assert_eq!(
+                right.rev().collect::<Vec<_>>(),
+                expected[6..9].iter().rev().cloned().collect::<Vec<_>>()
+            );
+            let (left, right) = iter.split_at(10);
+            assert_eq!(left.len(), 5);
+            assert_eq!(right.len(), 0);
+        }
+
+        // Wildcards only derive normal children
+        assert!(xpub_desc("wpkh(XPUB/0/*)")
+            .spk_iter(0..1 << 31, &secp)
+            .is_ok());
+        assert_eq!(
+            xpub_desc("wpkh(XPUB/0/*)")
+                .spk_iter(0..(1 << 31) + 1, &secp)
+                .unwrap_err(),
+            ConversionError::HardenedChild
+        );
+        assert!(xpub_desc("wpkh(XPUB/0/7)")
+            .spk_iter(0..u32::MAX, &secp)
+            .is_ok());
+        assert_eq!(
+            xpub_desc("wpkh(XPUB/<0;1>/*)")
+                .spk_iter(0..10, &secp)
+                .unwrap_err(),
+            ConversionError::MultiKey
+        );
+    }
+}
@@ -330,10 +330,9 @@ impl<Pk: MiniscriptKey> Wpkh<Pk> {
 
     /// Checks whether the descriptor is safe.
     pub fn sanity_check(&self) -> Result<(), Error> {
-        if self.pk.is_uncompressed() {
-            Err(Error::ContextError(ScriptContextError::CompressedOnly(self.pk.to_string())))
-        } else {
-            Ok(())
+        match self.pk.uncompressed_key() {
+            Some(pk) => Err(Error::ContextError(ScriptContextError::CompressedOnly(pk))),
+            None => Ok(()),
         }
     }
 
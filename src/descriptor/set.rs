@@ -0,0 +1,260 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! Descriptor sets
+//!
+//! Utilities to reason about the `script_pubkey`s produced by several descriptors over ranges of
+//! derivation indexes. This allows detecting when two descriptors can produce the same output,
+//! for instance to avoid watching the same script twice or to catch address reuse between an
+//! old wallet and the wallet it is migrated to.
+
+use core::ops::Range;
+
+use bitcoin::{secp256k1, Script, ScriptBuf};
+
+use super::tr::TrSpkCache;
+use super::{ConversionError, Descriptor, DescriptorPublicKey};
+use crate::prelude::*;
+
+/// Where a `script_pubkey` in a [`ScriptSet`] comes from
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ScriptOrigin {
+    /// Position of the descriptor, in the order descriptors were added to the set
+    pub descriptor: usize,
+    /// Derivation index the descriptor was derived at
+    pub index: u32,
+}
+
+/// The set of `script_pubkey`s produced by some descriptors over ranges of derivation indexes
+///
+/// Each script is mapped to all the (descriptor, index) pairs producing it.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ScriptSet {
+    scripts: BTreeMap<ScriptBuf, Vec<ScriptOrigin>>,
+    n_descriptors: usize,
+}
+
+impl ScriptSet {
+    /// Creates an empty set
+    pub fn new() -> Self { Self::default() }
+
+    /// Creates the set of scripts produced by each descriptor over `range`
+    ///
+    /// Descriptors are numbered by their position in `descriptors`. Descriptors without
+    /// wildcards are only derived once.
+    ///
+    /// # Errors
+    ///
+    /// If a descriptor can't be derived, e.g. because it is multipath (see
+    /// [`Descriptor::into_single_descriptors`]) or uses hardened derivation.
+    pub fn from_descriptors<C: secp256k1::Verification>(
+        secp: &secp256k1::Secp256k1<C>,
+        descriptors: &[Descriptor<DescriptorPublicKey>],
+        range: Range<u32>,
+    ) -> Result<Self, ConversionError> {
+        let mut set = Self::new();
+        for descriptor in descriptors {
+            set.insert_descriptor(secp, descriptor, range.clone())?;
+        }
+        Ok(set)
+    }
+
+    /// Adds the scripts produced by `descriptor` over `range`, and returns the position assigned
+    /// to the descriptor
+    ///
+    /// The set is left untouched if the descriptor can't be derived.
+    pub fn insert_descriptor<C: secp256k1::Verification>(
+        &mut self,
+        secp: &secp256k1::Secp256k1<C>,
+        descriptor: &Descriptor<DescriptorPublicKey>,
+        range: Range<u32>,
+    ) -> Result<usize, ConversionError> {
+        let position = self.n_descriptors;
+        let scripts = script_pubkeys(secp, descriptor, range)?;
+        for (index, script) in scripts {
+            self.scripts
+                .entry(script)
+                .or_default()
+                .push(ScriptOrigin { descriptor: position, index });
+        }
+        self.n_descriptors += 1;
+        Ok(position)
+    }
+
+    /// Adds all the scripts of `other` to this set
+    ///
+    /// The descriptors of `other` are numbered after the descriptors already in this set,
+    /// keeping their relative order.
+    pub fn union(&mut self, other: ScriptSet) {
+        let offset = self.n_descriptors;
+        for (script, origins) in other.scripts {
+            self.scripts
+                .entry(script)
+                .or_default()
+                .extend(origins.into_iter().map(|origin| ScriptOrigin {
+                    descriptor: origin.descriptor + offset,
+                    index: origin.index,
+                }));
+        }
+        self.n_descriptors += other.n_descriptors;
+    }
+
+    /// Number of distinct scripts in the set
+    pub fn len(&self) -> usize { self.scripts.len() }
+
+    /// Whether the set contains no script
+    pub fn is_empty(&self) -> bool { self.scripts.is_empty() }
+
+    /// Number of descriptors added to the set
+    pub fn n_descriptors(&self) -> usize { self.n_descriptors }
+
+    /// Whether the set contains `script_pubkey`
+    pub fn contains(&self, script_pubkey: &Script) -> bool {
+        self.scripts.contains_key(script_pubkey)
+    }
+
+    /// All the (descriptor, index) pairs producing `script_pubkey`
+    pub fn origins(&self, script_pubkey: &Script) -> &[ScriptOrigin] {
+        self.scripts
+            .get(script_pubkey)
+            .map(Vec::as_slice)
+            .unwrap_or(&[])
+    }
+
+    /// Iterates over the scripts in the set, along with their origins
+    pub fn iter(&self) -> impl Iterator<Item = (&Script, &[ScriptOrigin])> {
+        self.scripts
+            .iter()
+            .map(|(script, origins)| (script.as_script(), origins.as_slice()))
+    }
+
+    /// Iterates over the scripts produced by more than one descriptor of the set
+    pub fn overlaps(&self) -> impl Iterator<Item = (&Script, &[ScriptOrigin])> {
+        self.iter().filter(|(_, origins)| {
+            origins
+                .iter()
+                .any(|origin| origin.descriptor != origins[0].descriptor)
+        })
+    }
+}
+
+/// Checks whether `a` and `b` produce a common `script_pubkey` when both are derived over `range`
+///
+/// Returns the first common script found, along with the indexes at which `a` and `b` produce it.
+///
+/// # Errors
+///
+/// If one of the descriptors can't be derived, see [`ScriptSet::from_descriptors`].
+pub fn find_overlap<C: secp256k1::Verification>(
+    secp: &secp256k1::Secp256k1<C>,
+    a: &Descriptor<DescriptorPublicKey>,
+    b: &Descriptor<DescriptorPublicKey>,
+    range: Range<u32>,
+) -> Result<Option<(ScriptBuf, u32, u32)>, ConversionError> {
+    let scripts_a: BTreeMap<ScriptBuf, u32> = script_pubkeys(secp, a, range.clone())?
+        .into_iter()
+        .rev()
+        .map(|(index, script)| (script, index))
+        .collect();
+    for (index_b, script) in script_pubkeys(secp, b, range)? {
+        if let Some(&index_a) = scripts_a.get(&script) {
+            return Ok(Some((script, index_a, index_b)));
+        }
+    }
+    Ok(None)
+}
+
+/// Derives the `script_pubkey` of `descriptor` at every index of `range`, or only once if it
+/// doesn't have wildcards.
+fn script_pubkeys<C: secp256k1::Verification>(
+    secp: &secp256k1::Secp256k1<C>,
+    descriptor: &Descriptor<DescriptorPublicKey>,
+    range: Range<u32>,
+) -> Result<Vec<(u32, ScriptBuf)>, ConversionError> {
+    let range = if descriptor.has_wildcard() {
+        range
+    } else {
+        0..1
+    };
+
+    if let Descriptor::Tr(ref tr) = *descriptor {
+        let mut cache = TrSpkCache::new(tr);
+        return range
+            .map(|i| Ok((i, cache.script_pubkey_at(tr, secp, i)?)))
+            .collect();
+    }
+
+    range
+        .map(|i| Ok((i, descriptor.derived_descriptor(secp, i)?.script_pubkey())))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::xpub_desc;
+
+    #[test]
+    fn overlap() {
+        let secp = secp256k1::Secp256k1::verification_only();
+        let ranged = xpub_desc("wpkh(XPUB/0/*)");
+        let single = xpub_desc("wpkh(XPUB/0/7)");
+        let other_type = xpub_desc("pkh(XPUB/0/*)");
+        let other_chain = xpub_desc("wpkh(XPUB/1/*)");
+
+        let (script, index_a, index_b) = find_overlap(&secp, &ranged, &single, 0..10)
+            .unwrap()
+            .unwrap();
+        assert_eq!((index_a, index_b), (7, 0));
+        assert_eq!(script, single.at_derivation_index(0).unwrap().script_pubkey());
+        assert_eq!(find_overlap(&secp, &ranged, &single, 0..7).unwrap(), None);
+        assert_eq!(find_overlap(&secp, &ranged, &other_type, 0..10).unwrap(), None);
+        assert_eq!(find_overlap(&secp, &ranged, &other_chain, 0..10).unwrap(), None);
+
+        let tr = xpub_desc("tr(XPUB/0/*,pk(XPUB/1/*))");
+        let key_only = xpub_desc("tr(XPUB/0/*)");
+        assert_eq!(find_overlap(&secp, &tr, &tr, 3..5).unwrap().unwrap().1, 3);
+        assert_eq!(find_overlap(&secp, &tr, &key_only, 0..5).unwrap(), None);
+
+        assert!(find_overlap(&secp, &xpub_desc("wpkh(XPUB/<0;1>/*)"), &ranged, 0..10).is_err());
+    }
+
+    #[test]
+    fn script_set() {
+        let secp = secp256k1::Secp256k1::verification_only();
+        let descriptors = [xpub_desc("wpkh(XPUB/0/*)"), xpub_desc("pkh(XPUB/0/*)")];
+        let mut set = ScriptSet::from_descriptors(&secp, &descriptors, 0..5).unwrap();
+        assert_eq!(set.len(), 10);
+        assert_eq!(set.n_descriptors(), 2);
+        assert_eq!(set.overlaps().count(), 0);
+
+        let mut migrated = ScriptSet::new();
+        let position = migrated
+            .insert_descriptor(&secp, &xpub_desc("wpkh(XPUB/0/3)"), 0..5)
+            .unwrap();
+        assert_eq!(position, 0);
+        assert_eq!(migrated.len(), 1);
+
+        set.union(migrated);
+        assert_eq!(set.len(), 10);
+        assert_eq!(set.n_descriptors(), 3);
+        let overlaps: Vec<_> = set.overlaps().collect();
+        assert_eq!(overlaps.len(), 1);
+        let (script, origins) = overlaps[0];
+        assert!(set.contains(script));
+        assert_eq!(
+            origins,
+            &[
+                ScriptOrigin { descriptor: 0, index: 3 },
+                ScriptOrigin { descriptor: 2, index: 0 }
+            ]
+        );
+        assert_eq!(set.origins(script), origins);
+
+        // Failing to derive a descriptor doesn't modify the set
+        let before = set.clone();
+        assert!(set
+            .insert_descriptor(&secp, &xpub_desc("wpkh(XPUB/<0;1>/*)"), 0..5)
+            .is_err());
+        assert_eq!(set, before);
+    }
+}
@@ -24,7 +24,7 @@ use crate::prelude::*;
 use crate::util::{varint_len, witness_to_scriptsig};
 use crate::{
     push_opcode_size, Error, ForEachKey, FromStrKey, Legacy, Miniscript, MiniscriptKey, Satisfier,
-    Segwitv0, ToPublicKey, TranslateErr, Translator,
+    Segwitv0, Terminal, Threshold, ToPublicKey, TranslateErr, Translator,
 };
 
 /// A Legacy p2sh Descriptor
@@ -135,6 +135,17 @@ impl<Pk: MiniscriptKey> Sh<Pk> {
         Ok(Self { inner: ShInner::SortedMulti(SortedMultiVec::new(k, pks)?) })
     }
 
+    /// Create a new p2sh multi descriptor with threshold `k` and Vec of `pks`,
+    /// keeping the order of the keys.
+    ///
+    /// Fails if the keys exceed the consensus limit of `CHECKMULTISIG` or the
+    /// redeem script size limit. Use [`Sh::sanity_check`] to check the 15 key
+    /// standardness limit.
+    pub fn new_multi(k: usize, pks: Vec<Pk>) -> Result<Self, Error> {
+        let thresh = Threshold::new(k, pks).map_err(Error::Threshold)?;
+        Self::new(Miniscript::from_ast(Terminal::Multi(thresh))?)
+    }
+
     /// Create a new p2sh wrapped wsh descriptor with the raw miniscript
     pub fn new_wsh(ms: Miniscript<Pk, Segwitv0>) -> Result<Self, Error> {
         Ok(Self { inner: ShInner::Wsh(Wsh::new(ms)?) })
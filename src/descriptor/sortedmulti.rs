@@ -11,47 +11,67 @@ use core::str::FromStr;
 
 use bitcoin::script;
 
-use crate::miniscript::context::ScriptContext;
+use crate::miniscript::context::{ScriptContext, SigType};
 use crate::miniscript::decode::Terminal;
-use crate::miniscript::limits::MAX_PUBKEYS_PER_MULTISIG;
+use crate::miniscript::limits::MAX_PUBKEYS_IN_CHECKSIGADD;
 use crate::miniscript::satisfy::{Placeholder, Satisfaction};
 use crate::plan::AssetProvider;
 use crate::prelude::*;
 use crate::sync::Arc;
 use crate::{
     expression, policy, script_num_size, Error, ForEachKey, Miniscript, MiniscriptKey, Satisfier,
-    Threshold, ToPublicKey, TranslateErr, Translator,
+    Tap, Threshold, ToPublicKey, TranslateErr, Translator,
 };
 
 /// Contents of a "sortedmulti" descriptor
+///
+/// Under Taproot, this is a `multi_a` with sorted keys, which can have up to 999 keys.
+/// Otherwise, it is a `multi` with sorted keys, which can have up to 20 keys.
 #[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct SortedMultiVec<Pk: MiniscriptKey, Ctx: ScriptContext> {
-    inner: Threshold<Pk, MAX_PUBKEYS_PER_MULTISIG>,
+    inner: Threshold<Pk, MAX_PUBKEYS_IN_CHECKSIGADD>,
     /// The current ScriptContext for sortedmulti
     phantom: PhantomData<Ctx>,
 }
 
 impl<Pk: MiniscriptKey, Ctx: ScriptContext> SortedMultiVec<Pk, Ctx> {
-    fn constructor_check(mut self) -> Result<Self, Error> {
+    /// Whether this is a `multi_a` rather than a `multi`
+    fn is_multi_a() -> bool { Ctx::sig_type() == SigType::Schnorr }
+
+    /// The name of the fragment, `sortedmulti_a` under Taproot and `sortedmulti` otherwise
+    fn name() -> &'static str {
+        if Self::is_multi_a() {
+            "sortedmulti_a"
+        } else {
+            "sortedmulti"
+        }
+    }
+
+    /// The `multi` or `multi_a` fragment for the keys, depending on the context
+    fn node(thresh: Threshold<Pk, MAX_PUBKEYS_IN_CHECKSIGADD>) -> Result<Terminal<Pk, Ctx>, Error> {
+        if Self::is_multi_a() {
+            Ok(Terminal::MultiA(thresh))
+        } else {
+            Ok(Terminal::Multi(thresh.set_maximum().map_err(Error::Threshold)?))
+        }
+    }
+
+    fn constructor_check(self) -> Result<Self, Error> {
         // Check the limits before creating a new SortedMultiVec
         // For example, under p2sh context the scriptlen can only be
         // upto 520 bytes.
-        let term: Terminal<Pk, Ctx> = Terminal::Multi(self.inner);
+        let term = Self::node(self.inner.clone())?;
         let ms = Miniscript::from_ast(term)?;
         // This would check all the consensus rules for p2sh/p2wsh and
-        // even tapscript in future
+        // tapscript
         Ctx::check_local_validity(&ms)?;
-        if let Terminal::Multi(inner) = ms.node {
-            self.inner = inner;
-            Ok(self)
-        } else {
-            unreachable!()
-        }
+        Ok(self)
     }
 
     /// Create a new instance of `SortedMultiVec` given a list of keys and the threshold
     ///
     /// Internally checks all the applicable size limits and pubkey types limitations according to the current `Ctx`.
+    /// Under Taproot up to 999 keys are allowed, otherwise up to 20.
     pub fn new(k: usize, pks: Vec<Pk>) -> Result<Self, Error> {
         let ret =
             Self { inner: Threshold::new(k, pks).map_err(Error::Threshold)?, phantom: PhantomData };
@@ -59,11 +79,21 @@ impl<Pk: MiniscriptKey, Ctx: ScriptContext> SortedMultiVec<Pk, Ctx> {
     }
 
     /// Parse an expression tree into a SortedMultiVec
+    ///
+    /// The fragment must be `sortedmulti_a` under Taproot, and `sortedmulti` otherwise.
     pub fn from_tree(tree: &expression::Tree) -> Result<Self, Error>
     where
         Pk: FromStr,
         <Pk as FromStr>::Err: fmt::Display,
     {
+        if tree.name != Self::name() {
+            return Err(Error::Unexpected(format!(
+                "{}({} args) while parsing {}",
+                tree.name,
+                tree.args.len(),
+                Self::name()
+            )));
+        }
         let ret = Self {
             inner: tree
                 .to_null_threshold()
@@ -115,27 +145,43 @@ impl<Pk: MiniscriptKey, Ctx: ScriptContext> SortedMultiVec<Pk, Ctx> {
     /// utility function to sanity a sorted multi vec
     pub fn sanity_check(&self) -> Result<(), Error> {
         let ms: Miniscript<Pk, Ctx> =
-            Miniscript::from_ast(Terminal::Multi(self.inner.clone())).expect("Must typecheck");
+            Miniscript::from_ast(Self::node(self.inner.clone()).expect("Checked on construction"))
+                .expect("Must typecheck");
         ms.sanity_check().map_err(From::from)
     }
 }
 
 impl<Pk: MiniscriptKey, Ctx: ScriptContext> SortedMultiVec<Pk, Ctx> {
-    /// Create Terminal::Multi containing sorted pubkeys
+    /// Create Terminal::Multi, or Terminal::MultiA under Taproot, containing sorted pubkeys
     pub fn sorted_node(&self) -> Terminal<Pk, Ctx>
     where
         Pk: ToPublicKey,
     {
         let mut thresh = self.inner.clone();
-        // Sort pubkeys lexicographically according to BIP 67
-        thresh.data_mut().sort_by(|a, b| {
-            a.to_public_key()
-                .inner
-                .serialize()
-                .partial_cmp(&b.to_public_key().inner.serialize())
-                .unwrap()
-        });
-        Terminal::Multi(thresh)
+        if Self::is_multi_a() {
+            // Sort x-only pubkeys lexicographically, as in `sortedmulti_a` of BIP 387
+            thresh
+                .data_mut()
+                .sort_by_key(|pk| pk.to_x_only_pubkey().serialize());
+        } else {
+            // Sort pubkeys lexicographically according to BIP 67
+            thresh.data_mut().sort_by(|a, b| {
+                a.to_public_key()
+                    .inner
+                    .serialize()
+                    .partial_cmp(&b.to_public_key().inner.serialize())
+                    .unwrap()
+            });
+        }
+        Self::node(thresh).expect("Checked on construction")
+    }
+
+    /// The Miniscript with sorted pubkeys, which under Taproot can be used as a leaf
+    pub fn sorted_miniscript(&self) -> Miniscript<Pk, Ctx>
+    where
+        Pk: ToPublicKey,
+    {
+        Miniscript::from_ast(self.sorted_node()).expect("Multi node typecheck")
     }
 
     /// Encode as a Bitcoin script
@@ -177,10 +223,13 @@ impl<Pk: MiniscriptKey, Ctx: ScriptContext> SortedMultiVec<Pk, Ctx> {
     /// to instead call the corresponding function on a `Descriptor`, which
     /// will handle the segwit/non-segwit technicalities for you.
     pub fn script_size(&self) -> usize {
-        script_num_size(self.k())
-            + 1
-            + script_num_size(self.n())
-            + self.pks().iter().map(|pk| Ctx::pk_len(pk)).sum::<usize>()
+        let pks_size = self.pks().iter().map(|pk| Ctx::pk_len(pk)).sum::<usize>();
+        if Self::is_multi_a() {
+            // <pk> CHECKSIG (<pk> CHECKSIGADD)* <k> NUMEQUAL
+            pks_size + self.n() + script_num_size(self.k()) + 1
+        } else {
+            script_num_size(self.k()) + 1 + script_num_size(self.n()) + pks_size
+        }
     }
 
     /// Maximum number of witness elements used to satisfy the Miniscript
@@ -191,7 +240,14 @@ impl<Pk: MiniscriptKey, Ctx: ScriptContext> SortedMultiVec<Pk, Ctx> {
     /// This function may panic on malformed `Miniscript` objects which do
     /// not correspond to semantically sane Scripts. (Such scripts should be
     /// rejected at parse time. Any exceptions are bugs.)
-    pub fn max_satisfaction_witness_elements(&self) -> usize { 2 + self.k() }
+    pub fn max_satisfaction_witness_elements(&self) -> usize {
+        if Self::is_multi_a() {
+            // A signature or an empty element per key
+            1 + self.n()
+        } else {
+            2 + self.k()
+        }
+    }
 
     /// Maximum size, in bytes, of a satisfying witness.
     /// In general, it is not recommended to use this function directly, but
@@ -200,8 +256,31 @@ impl<Pk: MiniscriptKey, Ctx: ScriptContext> SortedMultiVec<Pk, Ctx> {
     ///
     /// All signatures are assumed to be 73 bytes in size, including the
     /// length prefix (segwit) or push opcode (pre-segwit) and sighash
-    /// postfix.
-    pub fn max_satisfaction_size(&self) -> usize { 1 + 73 * self.k() }
+    /// postfix. Under Taproot, they are assumed to be 66 bytes in size, and
+    /// the keys which don't sign require an empty element.
+    pub fn max_satisfaction_size(&self) -> usize {
+        if Self::is_multi_a() {
+            66 * self.k() + (self.n() - self.k())
+        } else {
+            1 + 73 * self.k()
+        }
+    }
+}
+
+impl<Pk: MiniscriptKey> SortedMultiVec<Pk, Tap> {
+    /// The `multi_a` leaf of a Taproot tree for the keys
+    ///
+    /// The keys are sorted by [`MiniscriptKey::sorting_key`] if all of them have one, and are
+    /// otherwise left in the order they are given.
+    pub(crate) fn tap_leaf(&self) -> Miniscript<Pk, Tap> {
+        let mut thresh = self.inner.clone();
+        if thresh.iter().all(|pk| pk.sorting_key().is_some()) {
+            thresh
+                .data_mut()
+                .sort_by_key(|pk| pk.sorting_key().map(|key| key.serialize()));
+        }
+        Miniscript::from_ast(Terminal::MultiA(thresh)).expect("Multi node typecheck")
+    }
 }
 
 impl<Pk: MiniscriptKey, Ctx: ScriptContext> policy::Liftable<Pk> for SortedMultiVec<Pk, Ctx> {
@@ -220,7 +299,20 @@ impl<Pk: MiniscriptKey, Ctx: ScriptContext> fmt::Debug for SortedMultiVec<Pk, Ct
 
 impl<Pk: MiniscriptKey, Ctx: ScriptContext> fmt::Display for SortedMultiVec<Pk, Ctx> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        fmt::Display::fmt(&self.inner.display("sortedmulti", true), f)
+        fmt::Display::fmt(&self.inner.display(Self::name(), true), f)
+    }
+}
+
+impl<Pk, Ctx> FromStr for SortedMultiVec<Pk, Ctx>
+where
+    Pk: MiniscriptKey + FromStr,
+    <Pk as FromStr>::Err: fmt::Display,
+    Ctx: ScriptContext,
+{
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let top = expression::Tree::from_str(s)?;
+        Self::from_tree(&top)
     }
 }
 
@@ -229,7 +321,8 @@ mod tests {
     use bitcoin::secp256k1::PublicKey;
 
     use super::*;
-    use crate::miniscript::context::Legacy;
+    use crate::miniscript::context::{Legacy, Segwitv0, Tap};
+    use crate::miniscript::limits::MAX_PUBKEYS_PER_MULTISIG;
 
     #[test]
     fn too_many_pubkeys() {
@@ -254,4 +347,83 @@ mod tests {
             other => panic!("unexpected error: {:?}", other),
         }
     }
+
+    #[test]
+    fn large_sorted_multi_a() {
+        let secp = bitcoin::secp256k1::Secp256k1::signing_only();
+        let pks: Vec<bitcoin::PublicKey> = (1..=60u8)
+            .map(|i| {
+                let sk = bitcoin::secp256k1::SecretKey::from_slice(&[i; 32]).unwrap();
+                bitcoin::PublicKey::new(PublicKey::from_secret_key(&secp, &sk))
+            })
+            .collect();
+
+        // Too many keys for a multi
+        let err = SortedMultiVec::<_, Segwitv0>::new(40, pks.clone()).unwrap_err();
+        assert!(matches!(err, Error::Threshold(_)));
+
+        let smv = SortedMultiVec::<_, Tap>::new(40, pks.clone()).unwrap();
+        assert!(smv.to_string().starts_with("sortedmulti_a(40,"));
+        assert_eq!(SortedMultiVec::<_, Tap>::from_str(&smv.to_string()).unwrap(), smv);
+        let wrong_name = smv.to_string().replacen("sortedmulti_a", "sortedmulti", 1);
+        assert!(SortedMultiVec::<bitcoin::PublicKey, Tap>::from_str(&wrong_name).is_err());
+        // As a leaf of a Taproot descriptor, which displays the keys in the order they are
+        // given and sorts them in its script
+        let desc_str = format!("tr({},{})", pks[0], smv);
+        let desc = crate::Descriptor::<bitcoin::PublicKey>::from_str(&desc_str).unwrap();
+        assert_eq!(desc.to_string().split('#').next().unwrap(), desc_str);
+        let tr = match desc {
+            crate::Descriptor::Tr(ref tr) => tr,
+            _ => unreachable!(),
+        };
+        let (depth, leaf) = tr.leaf(0).unwrap();
+        assert_eq!(depth, 0);
+        assert_eq!(*leaf, smv.sorted_miniscript());
+        desc.sanity_check().unwrap();
+        let segwit_smv = SortedMultiVec::<_, Segwitv0>::new(2, pks[..3].to_vec()).unwrap();
+        assert_eq!(
+            SortedMultiVec::<_, Segwitv0>::from_str(&segwit_smv.to_string()).unwrap(),
+            segwit_smv
+        );
+        let ms = smv.sorted_miniscript();
+        assert!(matches!(ms.node, Terminal::MultiA(_)));
+        assert_eq!(smv.script_size(), smv.encode().len());
+        assert_eq!(smv.script_size(), ms.encode().len());
+        assert_eq!(Some(smv.max_satisfaction_size()), ms.max_satisfaction_size().ok());
+        assert_eq!(smv.max_satisfaction_witness_elements(), 61);
+        smv.sanity_check().unwrap();
+
+        // The keys are sorted by their x-only serialization
+        let keys: Vec<_> = ms
+            .iter_pk()
+            .map(|pk| pk.to_x_only_pubkey().serialize())
+            .collect();
+        assert!(keys.windows(2).all(|w| w[0] < w[1]));
+
+        // The same leaf built from its miniscript
+        let tree = crate::descriptor::TapTree::Leaf(Arc::new(ms));
+        let ms_desc = crate::Descriptor::new_tr(pks[0], Some(tree)).unwrap();
+        assert_eq!(ms_desc.script_pubkey(), desc.script_pubkey());
+        assert_eq!(ms_desc.max_weight_to_satisfy().unwrap(), desc.max_weight_to_satisfy().unwrap());
+
+        // The keys of extended keys are sorted once derived
+        let xpubs = [
+            "xpub6ERApfZwUNrhLCkDtcHTcxd75RbzS1ed54G1LkBUHQVHQKqhMkhgbmJbZRkrgZw4koxb5JaHWkY4ALHY2grBGRjaDMzQLcgJvLJuZZvRcEL",
+            "xpub6FC1fXFP1GXLX5TKtcjHGT4q89SDRehkQLtbKJ2PzWcvbBHtyDsJPLtpLtkGqYNYZdVVAjRQ5kug9CsapegmmeRutpP7PW4u4wVF9JfkDhw",
+        ];
+        let derived = |a: &str, b: &str| {
+            let desc = format!("tr({},sortedmulti_a(1,{}/*,{}/*))", pks[0], a, b);
+            let desc = crate::Descriptor::<crate::DescriptorPublicKey>::from_str(&desc).unwrap();
+            assert!(desc
+                .to_string()
+                .contains(&format!("sortedmulti_a(1,{}/*,{}/*)", a, b)));
+            (0..4)
+                .map(|i| desc.at_derivation_index(i).unwrap().script_pubkey())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(derived(xpubs[0], xpubs[1]), derived(xpubs[1], xpubs[0]));
+
+        let err = SortedMultiVec::<_, Tap>::new(1, vec![pks[0]; 1000]).unwrap_err();
+        assert!(matches!(err, Error::Threshold(_)));
+    }
 }
@@ -0,0 +1,253 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! # Detailed Spend Paths
+//!
+//! Enumeration of the spending paths of a descriptor, each with the keys,
+//! preimages and timelocks it requires and the weight of its satisfaction, for
+//! fee estimation and for showing users when each path unlocks.
+//!
+
+use core::fmt;
+
+use bitcoin::taproot::{LeafVersion, TapLeafHash};
+
+use super::{
+    DefiniteDescriptorKey, Descriptor, DescriptorPublicKey, DescriptorType, HashLock,
+    SighashContext, TapTree,
+};
+use crate::plan::Assets;
+use crate::policy::semantic::{Policy, SpendPaths};
+use crate::policy::Liftable;
+use crate::prelude::*;
+use crate::{AbsLockTime, Error, RelLockTime};
+
+/// A spending path of a descriptor with everything it requires, see
+/// [`Descriptor::spend_paths`]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct DetailedSpendPath {
+    /// The context of the signatures
+    pub context: SighashContext,
+    /// The keys which must all sign
+    pub keys: Vec<DefiniteDescriptorKey>,
+    /// The hash locks whose preimages must be revealed
+    pub preimages: Vec<HashLock<DefiniteDescriptorKey>>,
+    /// The relative timelock the spend must wait for, if any
+    pub older: Option<RelLockTime>,
+    /// The absolute timelock the spend must wait for, if any
+    pub after: Option<AbsLockTime>,
+    /// The weight the satisfaction of this path adds to the input, see
+    /// [`crate::plan::Plan::satisfaction_weight`]
+    ///
+    /// `None` if no satisfaction of the path could be planned.
+    pub satisfaction_weight: Option<usize>,
+}
+
+impl fmt::Display for DetailedSpendPath {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: ", self.context)?;
+        for (i, pk) in self.keys.iter().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            write!(f, "{}", pk)?;
+        }
+        if self.keys.is_empty() {
+            f.write_str("no signature")?;
+        }
+        for lock in &self.preimages {
+            write!(f, ", {}", lock)?;
+        }
+        if let Some(n) = self.older {
+            write!(f, ", older({})", n)?;
+        }
+        if let Some(n) = self.after {
+            write!(f, ", after({})", n)?;
+        }
+        match self.satisfaction_weight {
+            Some(weight) => write!(f, " ({} WU)", weight),
+            None => f.write_str(" (unsatisfiable)"),
+        }
+    }
+}
+
+/// Iterator over the detailed spending paths of a descriptor, see
+/// [`Descriptor::spend_paths`]
+pub struct DetailedSpendPaths<'a> {
+    descriptor: &'a Descriptor<DefiniteDescriptorKey>,
+    inner: Box<dyn Iterator<Item = PathConditions> + 'a>,
+}
+
+/// The context, tap leaf and conditions of a spending path
+type PathConditions = (SighashContext, Option<TapLeafHash>, Vec<Policy<DefiniteDescriptorKey>>);
+
+impl Iterator for DetailedSpendPaths<'_> {
+    type Item = DetailedSpendPath;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (context, leaf_hash, conditions) = self.inner.next()?;
+        let mut path = DetailedSpendPath {
+            context,
+            keys: vec![],
+            preimages: vec![],
+            older: None,
+            after: None,
+            satisfaction_weight: None,
+        };
+        for condition in conditions {
+            match condition {
+                Policy::Key(pk) => path.keys.push(pk),
+                Policy::Older(n) => path.older = path.older.max(Some(n)),
+                Policy::After(n) => path.after = path.after.max(Some(n)),
+                Policy::Sha256(h) => path.preimages.push(HashLock::Sha256(h)),
+                Policy::Hash256(h) => path.preimages.push(HashLock::Hash256(h)),
+                Policy::Ripemd160(h) => path.preimages.push(HashLock::Ripemd160(h)),
+                Policy::Hash160(h) => path.preimages.push(HashLock::Hash160(h)),
+                Policy::Trivial | Policy::Unsatisfiable | Policy::Thresh(..) => {}
+            }
+        }
+
+        // Plan with exactly the assets of the path, restricted to its leaf
+        let mut assets: Assets = path
+            .keys
+            .iter()
+            .cloned()
+            .map(DescriptorPublicKey::from)
+            .collect();
+        for lock in &path.preimages {
+            match *lock {
+                HashLock::Sha256(h) => assets.sha256_preimages.insert(h),
+                HashLock::Hash256(h) => assets.hash256_preimages.insert(h),
+                HashLock::Ripemd160(h) => assets.ripemd160_preimages.insert(h),
+                HashLock::Hash160(h) => assets.hash160_preimages.insert(h),
+            };
+        }
+        if let Some(n) = path.older {
+            assets = assets.older(n.into());
+        }
+        if let Some(n) = path.after {
+            assets = assets.after(n.into());
+        }
+        assets.required_leaves.extend(leaf_hash);
+        path.satisfaction_weight = self
+            .descriptor
+            .clone()
+            .plan(&assets)
+            .ok()
+            .map(|plan| plan.satisfaction_weight());
+        Some(path)
+    }
+}
+
+impl Descriptor<DefiniteDescriptorKey> {
+    /// Enumerates the spending paths of the descriptor, with the keys, preimages and
+    /// timelocks each requires and the weight of its satisfaction
+    ///
+    /// The paths are those of [`Descriptor::signing_manifest`]: the key spend path of a
+    /// Taproot descriptor comes first, followed by the paths of each leaf in the order
+    /// of [`Descriptor::tap_tree_iter`]. Paths are enumerated lazily, and each path is
+    /// planned to compute its weight, so use [`Descriptor::spend_path_count`] to check
+    /// the number of paths is tractable beforehand.
+    ///
+    /// # Errors
+    ///
+    /// If the descriptor can't be lifted to a semantic policy.
+    pub fn spend_paths(&self) -> Result<DetailedSpendPaths<'_>, Error> {
+        let mut policies = vec![];
+        match *self {
+            Descriptor::Tr(ref tr) => {
+                policies.push((
+                    SighashContext::TaprootKeySpend,
+                    None,
+                    Policy::Key(tr.internal_key().clone()),
+                ));
+                for (leaf, (_, ms)) in tr.tap_tree().iter().flat_map(TapTree::iter).enumerate() {
+                    let leaf_hash = TapLeafHash::from_script(&ms.encode(), LeafVersion::TapScript);
+                    policies.push((
+                        SighashContext::TaprootScriptSpend(leaf),
+                        Some(leaf_hash),
+                        ms.lift()?.normalized(),
+                    ));
+                }
+            }
+            Descriptor::RawTr(ref rawtr) => policies.push((
+                SighashContext::TaprootKeySpend,
+                None,
+                Policy::Key(rawtr.as_inner().clone()),
+            )),
+            _ => {
+                let context = match self.desc_type() {
+                    DescriptorType::Bare
+                    | DescriptorType::Sh
+                    | DescriptorType::Pkh
+                    | DescriptorType::ShSortedMulti
+                    | DescriptorType::Data => SighashContext::Legacy,
+                    _ => SighashContext::SegwitV0,
+                };
+                policies.push((context, None, self.lift()?.normalized()));
+            }
+        }
+
+        let inner = policies
+            .into_iter()
+            .flat_map(|(context, leaf_hash, policy)| {
+                SpendPaths::new(policy).map(move |path| (context, leaf_hash, path))
+            });
+        Ok(DetailedSpendPaths { descriptor: self, inner: Box::new(inner) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::str::FromStr;
+
+    use super::*;
+
+    fn spend_paths(s: &str) -> Vec<DetailedSpendPath> {
+        Descriptor::<DefiniteDescriptorKey>::from_str(s)
+            .unwrap()
+            .spend_paths()
+            .unwrap()
+            .collect()
+    }
+
+    #[test]
+    fn detailed_spend_paths() {
+        let a = "02a34b99f22c790c4e36b2b3c2c35a36db06226e41c692fc82b8b56ac1c540c5bd";
+        let b = "03a34b99f22c790c4e36b2b3c2c35a36db06226e41c692fc82b8b56ac1c540c5bd";
+        let h = "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa";
+
+        let paths = spend_paths(&format!(
+            "wsh(or_d(pk({}),and_v(v:pk({}),and_v(v:sha256({}),older(1000)))))",
+            a, b, h
+        ));
+        assert_eq!(paths.len(), 2);
+        assert_eq!(paths[0].keys, vec![DefiniteDescriptorKey::from_str(a).unwrap()]);
+        assert!(paths[0].preimages.is_empty() && paths[0].older.is_none());
+        assert_eq!(paths[1].older, Some(RelLockTime::from_height(1000)));
+        assert_eq!(
+            paths[1].preimages,
+            vec![HashLock::Sha256(
+                bitcoin::hashes::sha256::Hash::from_str(h).unwrap()
+            )]
+        );
+        // The timelocked path reveals a preimage and takes another branch
+        assert!(paths[1].satisfaction_weight.unwrap() > paths[0].satisfaction_weight.unwrap());
+        assert!(paths[1].to_string().contains("older(1000)"));
+
+        // The key spend is planned apart from the leaf using the same key
+        let x = &a[2..];
+        let paths = spend_paths(&format!("tr({},and_v(v:pk({}),after(100)))", x, x));
+        assert_eq!(paths[0].context, SighashContext::TaprootKeySpend);
+        assert_eq!(paths[1].context, SighashContext::TaprootScriptSpend(0));
+        assert_eq!(paths[1].after, Some(AbsLockTime::from_consensus(100).unwrap()));
+        assert!(paths[1].satisfaction_weight.unwrap() > paths[0].satisfaction_weight.unwrap());
+
+        // Paths mixing timelock units are rejected when lifting
+        let desc = Descriptor::<DefiniteDescriptorKey>::from_str(&format!(
+            "wsh(and_v(v:pk({}),and_v(v:older(10),older(4194314))))",
+            a
+        ))
+        .unwrap();
+        assert!(desc.spend_paths().is_err());
+    }
+}
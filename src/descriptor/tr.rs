@@ -1,23 +1,27 @@
 // SPDX-License-Identifier: CC0-1.0
 
+use core::convert::TryFrom;
 use core::str::FromStr;
 use core::{cmp, fmt, hash};
 
+use bitcoin::key::{TapTweak, XOnlyPublicKey};
 #[cfg(not(test))] // https://github.com/rust-lang/rust/issues/121684
 use bitcoin::secp256k1;
 use bitcoin::taproot::{
-    LeafVersion, TaprootBuilder, TaprootSpendInfo, TAPROOT_CONTROL_BASE_SIZE,
-    TAPROOT_CONTROL_MAX_NODE_COUNT, TAPROOT_CONTROL_NODE_SIZE,
+    self, LeafVersion, TapLeafHash, TapNodeHash, TapTweakHash, TaprootBuilder, TaprootSpendInfo,
+    TAPROOT_CONTROL_BASE_SIZE, TAPROOT_CONTROL_MAX_NODE_COUNT, TAPROOT_CONTROL_NODE_SIZE,
 };
 use bitcoin::{opcodes, Address, Network, ScriptBuf, Weight};
 use sync::Arc;
 
 use super::checksum::{self, verify_checksum};
-use crate::descriptor::DefiniteDescriptorKey;
+use crate::descriptor::{
+    ConversionError, DefiniteDescriptorKey, DescriptorPublicKey, SortedMultiVec,
+};
 use crate::expression::{self, FromTree};
 use crate::miniscript::satisfy::{Placeholder, Satisfaction, SchnorrSigType, Witness};
 use crate::miniscript::Miniscript;
-use crate::plan::AssetProvider;
+use crate::plan::{AssetProvider, UsageStats};
 use crate::policy::semantic::Policy;
 use crate::policy::Liftable;
 use crate::prelude::*;
@@ -46,6 +50,12 @@ pub enum TapTree<Pk: MiniscriptKey> {
     // in adding a LeafVersion with Leaf type here. All Miniscripts right now
     // are of Leafversion::default
     Leaf(Arc<Miniscript<Pk, Tap>>),
+    /// A `sortedmulti_a` leaf, with the `multi_a` leaf it stands for
+    ///
+    /// Build it with [`TapTree::sorted_multi_a`], which sorts the keys of the `multi_a` leaf as
+    /// soon as they are known, see [`MiniscriptKey::sorting_key`]. The keys are displayed in the
+    /// order they are given.
+    SortedMultiA(Arc<SortedMultiVec<Pk, Tap>>, Arc<Miniscript<Pk, Tap>>),
 }
 
 /// A taproot descriptor
@@ -64,6 +74,131 @@ pub struct Tr<Pk: MiniscriptKey> {
     spend_info: Mutex<Option<Arc<TaprootSpendInfo>>>,
 }
 
+/// Position of a key in a [`Tr`] descriptor, see [`Tr::repeated_keys`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum KeyPosition {
+    /// The internal key
+    Internal,
+    /// A leaf of the script tree
+    Leaf {
+        /// Index of the leaf, in the order of [`Tr::iter_scripts`]
+        index: usize,
+        /// Depth of the leaf in the tree
+        depth: u8,
+    },
+}
+
+/// A key used more than once in a [`Tr`] descriptor, as returned by [`Tr::repeated_keys`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RepeatedKey<Pk: MiniscriptKey> {
+    /// The repeated key
+    pub key: Pk,
+    /// Every position of the key, in order. A leaf using the key several times appears
+    /// as many times.
+    pub positions: Vec<KeyPosition>,
+}
+
+impl<Pk: MiniscriptKey> RepeatedKey<Pk> {
+    /// Whether the key is repeated within a single leaf, which a sane Miniscript doesn't allow
+    pub fn is_repeated_in_leaf(&self) -> bool {
+        self.positions
+            .windows(2)
+            .any(|pair| pair[0] == pair[1] && pair[0] != KeyPosition::Internal)
+    }
+}
+
+/// Recommendation to rebalance the script tree of a [`Tr`] descriptor, as returned by
+/// [`Tr::rebalance_advice`].
+///
+/// Weights are averages over the recorded script-path spends and only account for the
+/// control block, which is the only part of the witness affected by the tree layout.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RebalanceAdvice<Pk: MiniscriptKey> {
+    /// The rebalanced tree, containing the same leaves as the current one
+    pub tree: TapTree<Pk>,
+    /// Expected control block weight of a script-path spend with the current tree
+    pub current_weight: Weight,
+    /// Expected control block weight of a script-path spend with the rebalanced tree
+    pub rebalanced_weight: Weight,
+}
+
+impl<Pk: MiniscriptKey> RebalanceAdvice<Pk> {
+    /// Expected weight saved on every script-path spend by switching to the rebalanced tree
+    pub fn improvement(&self) -> Weight { self.current_weight - self.rebalanced_weight }
+}
+
+/// A [`Tr`] descriptor under construction, see [`Tr::builder`]
+///
+/// Leaves are added with a weight, their relative spend probability, e.g. 9 and 1
+/// for leaves spent 90% and 10% of the time. Like when compiling a policy with
+/// `compile_tr`, the script tree is a Huffman tree, so that likelier leaves have
+/// shorter control blocks.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct TrBuilder<Pk: MiniscriptKey> {
+    /// The internal key
+    internal_key: Pk,
+    /// The leaves added so far, with their weight
+    leaves: Vec<(u64, Arc<Miniscript<Pk, Tap>>)>,
+}
+
+impl<Pk: MiniscriptKey> TrBuilder<Pk> {
+    /// The number of leaves added so far
+    pub fn n(&self) -> usize { self.leaves.len() }
+
+    /// Adds a leaf spent with a relative probability of `weight`
+    pub fn push(&mut self, weight: u64, ms: Arc<Miniscript<Pk, Tap>>) -> &mut Self {
+        self.leaves.push((weight, ms));
+        self
+    }
+
+    /// Builds the descriptor, without script tree if no leaf was added
+    ///
+    /// Among leaves of equal weight, the ones added first end up on the left.
+    ///
+    /// # Errors
+    ///
+    /// If the tree is deeper than the taproot limit of 128 levels, which requires
+    /// leaf weights spanning dozens of orders of magnitude.
+    pub fn build(self) -> Result<Tr<Pk>, Error> {
+        let tree = huffman_tree(self.leaves).map(|(tree, _)| tree);
+        Tr::new(self.internal_key, tree)
+    }
+}
+
+/// Builds a Huffman tree over weighted leaves, along with the depth of each leaf
+///
+/// The lightest nodes are combined first, the ones created first among nodes of
+/// equal weight, which makes the result deterministic.
+fn huffman_tree<Pk, I>(leaves: I) -> Option<(TapTree<Pk>, Vec<u64>)>
+where
+    Pk: MiniscriptKey,
+    I: IntoIterator<Item = (u64, Arc<Miniscript<Pk, Tap>>)>,
+{
+    // The sequence number avoids comparing trees when two nodes have the same weight.
+    let mut heap = BinaryHeap::new();
+    for (i, (weight, ms)) in leaves.into_iter().enumerate() {
+        heap.push((cmp::Reverse(weight), cmp::Reverse(i), TapTree::Leaf(ms), vec![i]));
+    }
+    let mut depths = vec![0u64; heap.len()];
+    let mut seq = heap.len();
+    while heap.len() > 1 {
+        let (w1, _, t1, mut idx1) = heap.pop().expect("len is at least two");
+        let (w2, _, t2, idx2) = heap.pop().expect("len is at least two");
+        idx1.extend(idx2);
+        for &i in &idx1 {
+            depths[i] += 1;
+        }
+        heap.push((
+            cmp::Reverse(w1.0.saturating_add(w2.0)),
+            cmp::Reverse(seq),
+            TapTree::combine(t1, t2),
+            idx1,
+        ));
+        seq += 1;
+    }
+    heap.pop().map(|(_, _, tree, _)| (tree, depths))
+}
+
 impl<Pk: MiniscriptKey> Clone for Tr<Pk> {
     fn clone(&self) -> Self {
         // When cloning, construct a new Mutex so that distinct clones don't
@@ -119,18 +254,40 @@ impl<Pk: MiniscriptKey> TapTree<Pk> {
         TapTree::Tree { left: Arc::new(left), right: Arc::new(right), height }
     }
 
+    /// Creates a `sortedmulti_a` leaf.
+    pub fn sorted_multi_a(keys: SortedMultiVec<Pk, Tap>) -> Self {
+        let leaf = keys.tap_leaf();
+        TapTree::SortedMultiA(Arc::new(keys), Arc::new(leaf))
+    }
+
     /// Returns the height of this tree.
     pub fn height(&self) -> usize {
         match *self {
             TapTree::Tree { left: _, right: _, height } => height,
-            TapTree::Leaf(..) => 0,
+            TapTree::Leaf(..) | TapTree::SortedMultiA(..) => 0,
         }
     }
 
     /// Iterates over all miniscripts in DFS walk order compatible with the
     /// PSBT requirements (BIP 371).
+    ///
+    /// This is the order in which the leaves appear in the descriptor string, see
+    /// [`TapTree::leaf`].
     pub fn iter(&self) -> TapTreeIter<Pk> { TapTreeIter { stack: vec![(0, self)] } }
 
+    /// Returns the depth and miniscript of the leaf at `index`, or `None` if the tree has
+    /// fewer leaves.
+    ///
+    /// Leaves are indexed from 0 in depth-first, left to right order, which is the order in
+    /// which they appear in the descriptor string and the order of [`TapTree::iter`]. The
+    /// index of a leaf is therefore preserved by serializing and parsing the descriptor
+    /// again, as well as by key translation and derivation, and can be used to refer to a
+    /// leaf outside of this library. It is not preserved by
+    /// [`TapTree::from_bitcoin_tap_tree`], which may swap siblings.
+    pub fn leaf(&self, index: usize) -> Option<(u8, &Miniscript<Pk, Tap>)> {
+        self.iter().nth(index)
+    }
+
     // Helper function to translate keys
     fn translate_helper<T>(&self, t: &mut T) -> Result<TapTree<T::TargetPk>, TranslateErr<T::Error>>
     where
@@ -143,11 +300,105 @@ impl<Pk: MiniscriptKey> TapTree<Pk> {
                 height: *height,
             },
             TapTree::Leaf(ref ms) => TapTree::Leaf(Arc::new(ms.translate_pk(t)?)),
+            TapTree::SortedMultiA(ref keys, _) => TapTree::sorted_multi_a(keys.translate_pk(t)?),
         };
         Ok(frag)
     }
 }
 
+impl<Pk: MiniscriptKey + ToPublicKey> TapTree<Pk> {
+    /// Converts this tree into a [`taproot::TapTree`], as used in the PSBT `tap_tree` field.
+    pub fn to_bitcoin_tap_tree(&self) -> taproot::TapTree {
+        let mut builder = TaprootBuilder::new();
+        for (depth, ms) in self.iter() {
+            builder = builder
+                .add_leaf(depth, ms.encode())
+                .expect("Computing spend data on a valid Tree should always succeed");
+        }
+        taproot::TapTree::try_from(builder).expect("The tree should always be valid")
+    }
+}
+
+impl TapTree<XOnlyPublicKey> {
+    /// Parses the leaves of a [`taproot::TapTree`] as Miniscripts, keeping its shape.
+    ///
+    /// A [`taproot::TapTree`] only stores its children in hash order, so the siblings of the
+    /// returned tree may be swapped compared to the tree it was built from. Its merkle root is
+    /// always the same.
+    ///
+    /// Returns an error if a leaf doesn't use the tapscript leaf version, or if its script isn't
+    /// a valid Miniscript.
+    pub fn from_bitcoin_tap_tree(tree: &taproot::TapTree) -> Result<Self, TapTreeConversionError> {
+        // The leaves come in DFS order (with children sorted by hash), so siblings are next to
+        // each other on the stack and can be merged as soon as they have the same depth.
+        let mut stack: Vec<(u8, TapTree<XOnlyPublicKey>)> = vec![];
+        for leaf in tree.script_leaves() {
+            if leaf.version() != LeafVersion::TapScript {
+                return Err(TapTreeConversionError::LeafVersion(leaf.version()));
+            }
+            let ms = Miniscript::<XOnlyPublicKey, Tap>::parse(leaf.script())
+                .map_err(TapTreeConversionError::Miniscript)?;
+            let (mut depth, mut node) =
+                (leaf.merkle_branch().len() as u8, TapTree::Leaf(Arc::new(ms)));
+            while let Some((top_depth, _)) = stack.last() {
+                if *top_depth != depth {
+                    break;
+                }
+                let (_, left) = stack.pop().expect("checked above");
+                node = TapTree::combine(left, node);
+                depth -= 1;
+            }
+            stack.push((depth, node));
+        }
+        match (stack.pop(), stack.is_empty()) {
+            (Some((0, tree)), true) => Ok(tree),
+            _ => unreachable!("a bitcoin TapTree is always a complete binary tree"),
+        }
+    }
+}
+
+/// The reason a [`taproot::TapTree`] couldn't be parsed by [`TapTree::from_bitcoin_tap_tree`]
+#[derive(Debug)]
+pub enum TapTreeConversionError {
+    /// A leaf doesn't use the tapscript leaf version
+    LeafVersion(LeafVersion),
+    /// The script of a leaf isn't a valid Miniscript
+    Miniscript(Error),
+}
+
+impl fmt::Display for TapTreeConversionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TapTreeConversionError::LeafVersion(version) => {
+                write!(f, "unsupported tap leaf version {}", version)
+            }
+            TapTreeConversionError::Miniscript(ref e) => fmt::Display::fmt(e, f),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for TapTreeConversionError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match *self {
+            TapTreeConversionError::LeafVersion(_) => None,
+            TapTreeConversionError::Miniscript(ref e) => Some(e),
+        }
+    }
+}
+
+impl TryFrom<&taproot::TapTree> for TapTree<XOnlyPublicKey> {
+    type Error = TapTreeConversionError;
+
+    fn try_from(tree: &taproot::TapTree) -> Result<Self, TapTreeConversionError> {
+        Self::from_bitcoin_tap_tree(tree)
+    }
+}
+
+impl<Pk: MiniscriptKey + ToPublicKey> From<&TapTree<Pk>> for taproot::TapTree {
+    fn from(tree: &TapTree<Pk>) -> Self { tree.to_bitcoin_tap_tree() }
+}
+
 impl<Pk: MiniscriptKey> fmt::Display for TapTree<Pk> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -155,6 +406,7 @@ impl<Pk: MiniscriptKey> fmt::Display for TapTree<Pk> {
                 write!(f, "{{{},{}}}", *left, *right)
             }
             TapTree::Leaf(ref script) => write!(f, "{}", *script),
+            TapTree::SortedMultiA(ref keys, _) => write!(f, "{}", *keys),
         }
     }
 }
@@ -166,11 +418,16 @@ impl<Pk: MiniscriptKey> fmt::Debug for TapTree<Pk> {
                 write!(f, "{{{:?},{:?}}}", *left, *right)
             }
             TapTree::Leaf(ref script) => write!(f, "{:?}", *script),
+            TapTree::SortedMultiA(ref keys, _) => write!(f, "{:?}", *keys),
         }
     }
 }
 
 impl<Pk: MiniscriptKey> Tr<Pk> {
+    /// Starts building a [`Tr`] descriptor with the given internal key, from leaves
+    /// weighted by their spend probability rather than from a [`TapTree`]
+    pub fn builder(internal_key: Pk) -> TrBuilder<Pk> { TrBuilder { internal_key, leaves: vec![] } }
+
     /// Create a new [`Tr`] descriptor from internal key and [`TapTree`]
     pub fn new(internal_key: Pk, tree: Option<TapTree<Pk>>) -> Result<Self, Error> {
         Tap::check_pk(&internal_key)?;
@@ -195,6 +452,8 @@ impl<Pk: MiniscriptKey> Tr<Pk> {
 
     /// Iterate over all scripts in merkle tree. If there is no script path, the iterator
     /// yields [`None`]
+    ///
+    /// The leaves are yielded in the stable order described in [`TapTree::leaf`].
     pub fn iter_scripts(&self) -> TapTreeIter<Pk> {
         match self.tree {
             Some(ref t) => t.iter(),
@@ -202,6 +461,15 @@ impl<Pk: MiniscriptKey> Tr<Pk> {
         }
     }
 
+    /// Returns the depth and miniscript of the leaf at `index` of the script tree, or `None`
+    /// if there is no such leaf.
+    ///
+    /// See [`TapTree::leaf`] for the ordering of the leaves, which is the one of
+    /// [`Tr::iter_scripts`].
+    pub fn leaf(&self, index: usize) -> Option<(u8, &Miniscript<Pk, Tap>)> {
+        self.tree.as_ref().and_then(|t| t.leaf(index))
+    }
+
     /// Compute the [`TaprootSpendInfo`] associated with this descriptor if spend data is `None`.
     ///
     /// If spend data is already computed (i.e it is not `None`), this does not recompute it.
@@ -245,6 +513,9 @@ impl<Pk: MiniscriptKey> Tr<Pk> {
     }
 
     /// Checks whether the descriptor is safe.
+    ///
+    /// Keys may be reused in different leaves, or as internal key and in leaves, which is
+    /// valid per BIP 341. Such reuse is reported by [`Tr::repeated_keys`].
     pub fn sanity_check(&self) -> Result<(), Error> {
         for (_depth, ms) in self.iter_scripts() {
             ms.sanity_check()?;
@@ -252,6 +523,30 @@ impl<Pk: MiniscriptKey> Tr<Pk> {
         Ok(())
     }
 
+    /// The keys used more than once in the descriptor, with their positions, in the order of
+    /// the keys.
+    ///
+    /// Reusing a key across leaves lets a spender choose between the branches using it, and
+    /// reveals on-chain that they are controlled by the same party. Callers with stricter
+    /// policies than BIP 341 can reject descriptors based on this report.
+    pub fn repeated_keys(&self) -> Vec<RepeatedKey<Pk>> {
+        let mut positions = BTreeMap::<Pk, Vec<KeyPosition>>::new();
+        positions.insert(self.internal_key.clone(), vec![KeyPosition::Internal]);
+        for (index, (depth, ms)) in self.iter_scripts().enumerate() {
+            for key in ms.iter_pk() {
+                positions
+                    .entry(key)
+                    .or_default()
+                    .push(KeyPosition::Leaf { index, depth });
+            }
+        }
+        positions
+            .into_iter()
+            .filter(|(_, positions)| positions.len() > 1)
+            .map(|(key, positions)| RepeatedKey { key, positions })
+            .collect()
+    }
+
     /// Computes an upper bound on the difference between a non-satisfied
     /// `TxIn`'s `segwit_weight` and a satisfied `TxIn`'s `segwit_weight`
     ///
@@ -386,6 +681,131 @@ impl<Pk: MiniscriptKey + ToPublicKey> Tr<Pk> {
         Address::p2tr_tweaked(spend_info.output_key(), network)
     }
 
+    /// Obtains the output key of this descriptor, with the parity of its y
+    /// coordinate, as committed to in the control blocks of script path spends.
+    pub fn output_key_with_parity(&self) -> (XOnlyPublicKey, secp256k1::Parity) {
+        let spend_info = self.spend_info();
+        (spend_info.output_key().to_x_only_public_key(), spend_info.output_key_parity())
+    }
+
+    /// Obtains the BIP341 tweak of the internal key, committing to the merkle
+    /// root of the script tree if any.
+    pub fn tap_tweak(&self) -> TapTweakHash {
+        let spend_info = self.spend_info();
+        TapTweakHash::from_key_and_tweak(spend_info.internal_key(), spend_info.merkle_root())
+    }
+
+    /// Checks that `output_key` is the internal key of this descriptor tweaked
+    /// by [`Tr::tap_tweak`].
+    ///
+    /// The output key is recomputed from the internal key and the merkle root of
+    /// the script tree, independently of the cached spend info.
+    pub fn verify_output_key(&self, output_key: &XOnlyPublicKey) -> bool {
+        let secp = secp256k1::Secp256k1::verification_only();
+        let (tweaked, _parity) = self
+            .internal_key
+            .to_x_only_pubkey()
+            .tap_tweak(&secp, self.merkle_root());
+        tweaked.to_x_only_public_key() == *output_key
+    }
+
+    /// Checks that `address` is a Taproot address paying to this descriptor,
+    /// with [`Tr::verify_output_key`].
+    pub fn verify_address(&self, address: &Address) -> bool {
+        let spk = address.script_pubkey();
+        spk.is_p2tr()
+            && XOnlyPublicKey::from_slice(&spk.as_bytes()[2..])
+                .map(|key| self.verify_output_key(&key))
+                .unwrap_or(false)
+    }
+
+    /// Computes the merkle root of the script tree without the spend info.
+    fn merkle_root(&self) -> Option<TapNodeHash> {
+        let mut stack: Vec<(u8, TapNodeHash)> = vec![];
+        for (depth, ms) in self.iter_scripts() {
+            let mut node = (
+                depth,
+                TapNodeHash::from(TapLeafHash::from_script(&ms.encode(), LeafVersion::TapScript)),
+            );
+            while let Some(&(top_depth, top)) = stack.last() {
+                if top_depth != node.0 {
+                    break;
+                }
+                stack.pop();
+                node = (node.0 - 1, TapNodeHash::from_node_hashes(top, node.1));
+            }
+            stack.push(node);
+        }
+        stack.pop().map(|(_, root)| root)
+    }
+
+    /// Checks whether the script tree should be rebalanced given real usage statistics.
+    ///
+    /// Builds a Huffman tree over the existing leaves, weighted by the number of recorded
+    /// spends of each leaf, and compares its expected control block weight with the one of
+    /// the current tree. Returns the rebalanced tree if the expected saving per script-path
+    /// spend is at least `min_improvement`.
+    ///
+    /// Returns `None` if the descriptor has no script tree, if `stats` contains no script-path
+    /// spend of this descriptor, or if rebalancing does not save enough weight.
+    ///
+    /// With the `compiler` feature, a descriptor compiled from a policy can instead be
+    /// recompiled with `Policy::compile_tr_with_usage`, which weights the leaves of the
+    /// compilation by the same statistics.
+    pub fn rebalance_advice(
+        &self,
+        stats: &UsageStats,
+        min_improvement: Weight,
+    ) -> Option<RebalanceAdvice<Pk>> {
+        let tree = self.tree.as_ref()?;
+
+        let leaves: Vec<(u8, u64, &Miniscript<Pk, Tap>)> = tree
+            .iter()
+            .map(|(depth, ms)| {
+                let leaf_hash = TapLeafHash::from_script(&ms.encode(), LeafVersion::TapScript);
+                (depth, stats.leaf_count(&leaf_hash), ms)
+            })
+            .collect();
+        let total_spends: u64 = leaves.iter().map(|(_, count, _)| count).sum();
+        if total_spends == 0 {
+            return None;
+        }
+
+        let (rebalanced, new_depths) = huffman_tree(
+            leaves
+                .iter()
+                .map(|(_, count, ms)| (*count, Arc::new((*ms).clone()))),
+        )
+        .expect("tree has at least one leaf");
+        if rebalanced.height() > TAPROOT_CONTROL_MAX_NODE_COUNT {
+            return None;
+        }
+
+        let node_size = TAPROOT_CONTROL_NODE_SIZE as u64;
+        let current: u64 = leaves
+            .iter()
+            .map(|(depth, count, _)| count * u64::from(*depth) * node_size)
+            .sum();
+        let new: u64 = leaves
+            .iter()
+            .zip(new_depths)
+            .map(|((_, count, _), depth)| count * depth * node_size)
+            .sum();
+
+        let advice = RebalanceAdvice {
+            tree: rebalanced,
+            current_weight: Weight::from_wu(current / total_spends),
+            rebalanced_weight: Weight::from_wu(new / total_spends),
+        };
+        if advice.current_weight > advice.rebalanced_weight
+            && advice.improvement() >= min_improvement
+        {
+            Some(advice)
+        } else {
+            None
+        }
+    }
+
     /// Returns satisfying non-malleable witness and scriptSig with minimum
     /// weight to spend an output controlled by the given descriptor if it is
     /// possible to construct one using the `satisfier`.
@@ -448,6 +868,87 @@ impl Tr<DefiniteDescriptorKey> {
     }
 }
 
+/// Derives the `script_pubkey`s of a ranged [`Tr`] descriptor across many derivation indices.
+///
+/// When the leaves of the tap tree do not contain any wildcard key, only the internal key
+/// changes from one index to the next. In that case the merkle root is computed once and
+/// reused, so that each index only costs an internal key derivation and a tweak. The cache
+/// must always be used with the descriptor it was created for.
+#[derive(Clone, Debug)]
+pub(crate) struct TrSpkCache {
+    /// Whether the tap tree is the same at every derivation index.
+    fixed_tree: bool,
+    /// Merkle root of the tap tree, populated on first use if `fixed_tree` is set.
+    merkle_root: Option<Option<TapNodeHash>>,
+}
+
+impl TrSpkCache {
+    /// Creates a new cache for the given descriptor.
+    pub(crate) fn new(tr: &Tr<DescriptorPublicKey>) -> Self {
+        let fixed_tree = !tr
+            .iter_scripts()
+            .any(|(_, ms)| ms.for_any_key(|k| k.has_wildcard() || k.is_multipath()));
+        TrSpkCache { fixed_tree, merkle_root: None }
+    }
+
+    /// Computes the `script_pubkey` of the descriptor at the given derivation index.
+    pub(crate) fn script_pubkey_at<C: secp256k1::Verification>(
+        &mut self,
+        tr: &Tr<DescriptorPublicKey>,
+        secp: &secp256k1::Secp256k1<C>,
+        index: u32,
+    ) -> Result<ScriptBuf, ConversionError> {
+        let merkle_root = match self.merkle_root {
+            Some(root) => root,
+            None => {
+                let derived = Self::derived_at(tr, secp, index)?;
+                if !self.fixed_tree {
+                    return Ok(derived.script_pubkey());
+                }
+                let root = derived.spend_info().merkle_root();
+                self.merkle_root = Some(root);
+                return Ok(derived.script_pubkey());
+            }
+        };
+
+        let internal_key = tr
+            .internal_key()
+            .clone()
+            .at_derivation_index(index)?
+            .derive_public_key(secp)?;
+        let (output_key, _parity) =
+            XOnlyPublicKey::from(internal_key.inner).tap_tweak(secp, merkle_root);
+        Ok(ScriptBuf::new_p2tr_tweaked(output_key))
+    }
+
+    fn derived_at<C: secp256k1::Verification>(
+        tr: &Tr<DescriptorPublicKey>,
+        secp: &secp256k1::Secp256k1<C>,
+        index: u32,
+    ) -> Result<Tr<bitcoin::PublicKey>, ConversionError> {
+        struct Derivator<'s, C: secp256k1::Verification>(&'s secp256k1::Secp256k1<C>, u32);
+
+        impl<C: secp256k1::Verification> Translator<DescriptorPublicKey> for Derivator<'_, C> {
+            type TargetPk = bitcoin::PublicKey;
+            type Error = ConversionError;
+
+            fn pk(
+                &mut self,
+                pk: &DescriptorPublicKey,
+            ) -> Result<bitcoin::PublicKey, ConversionError> {
+                pk.clone()
+                    .at_derivation_index(self.1)?
+                    .derive_public_key(self.0)
+            }
+
+            translate_hash_clone!(DescriptorPublicKey, bitcoin::PublicKey, ConversionError);
+        }
+
+        tr.translate_pk(&mut Derivator(secp, index))
+            .map_err(|e| e.expect_translator_err("No Context errors when deriving keys"))
+    }
+}
+
 /// Iterator for Taproot structures
 /// Yields a pair of (depth, miniscript) in a depth first walk
 /// For example, this tree:
@@ -483,7 +984,9 @@ where
                     self.stack.push((depth + 1, right));
                     self.stack.push((depth + 1, left));
                 }
-                TapTree::Leaf(ref ms) => return Some((depth, ms)),
+                TapTree::Leaf(ref ms) | TapTree::SortedMultiA(_, ref ms) => {
+                    return Some((depth, ms))
+                }
             }
         }
         None
@@ -493,8 +996,25 @@ where
 #[rustfmt::skip]
 impl<Pk: FromStrKey> Tr<Pk> {
     // Helper function to parse taproot script path
+    // Parse the internal key, which may be a `musig(KEY,KEY,...)` expression
+    fn parse_internal_key(key: &expression::Tree) -> Result<Pk, Error> {
+        let key = expression::key_expression(key).map_err(|_| {
+            Error::Unexpected(format!(
+                "#{} script associated with `key-path` while parsing taproot descriptor",
+                key.args.len()
+            ))
+        })?;
+        Pk::from_str(&key).map_err(|e| Error::Unexpected(e.to_string()))
+    }
+
     fn parse_tr_script_spend(tree: &expression::Tree,) -> Result<TapTree<Pk>, Error> {
         match tree {
+            expression::Tree { name, args }
+                if name.starts_with("sortedmulti_a(") && args.is_empty() =>
+            {
+                let keys = SortedMultiVec::<Pk, Tap>::from_str(name)?;
+                Ok(TapTree::sorted_multi_a(keys))
+            }
             expression::Tree { name, args } if !name.is_empty() && args.is_empty() => {
                 let script = Miniscript::<Pk, Tap>::from_str(name)?;
                 Ok(TapTree::Leaf(Arc::new(script)))
@@ -516,27 +1036,12 @@ impl<Pk: FromStrKey> crate::expression::FromTree for Tr<Pk> {
     fn from_tree(top: &expression::Tree) -> Result<Self, Error> {
         if top.name == "tr" {
             match top.args.len() {
-                1 => {
-                    let key = &top.args[0];
-                    if !key.args.is_empty() {
-                        return Err(Error::Unexpected(format!(
-                            "#{} script associated with `key-path` while parsing taproot descriptor",
-                            key.args.len()
-                        )));
-                    }
-                    Tr::new(expression::terminal(key, Pk::from_str)?, None)
-                }
+                1 => Tr::new(Self::parse_internal_key(&top.args[0])?, None),
                 2 => {
-                    let key = &top.args[0];
-                    if !key.args.is_empty() {
-                        return Err(Error::Unexpected(format!(
-                            "#{} script associated with `key-path` while parsing taproot descriptor",
-                            key.args.len()
-                        )));
-                    }
+                    let key = Self::parse_internal_key(&top.args[0])?;
                     let tree = &top.args[1];
                     let ret = Self::parse_tr_script_spend(tree)?;
-                    Tr::new(expression::terminal(key, Pk::from_str)?, Some(ret))
+                    Tr::new(key, Some(ret))
                 }
                 _ => Err(Error::Unexpected(format!(
                     "{}[#{} args] while parsing taproot descriptor",
@@ -557,10 +1062,7 @@ impl<Pk: FromStrKey> crate::expression::FromTree for Tr<Pk> {
 impl<Pk: FromStrKey> core::str::FromStr for Tr<Pk> {
     type Err = Error;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let desc_str = verify_checksum(s)
-            .map_err(From::from)
-            .map_err(Error::ParseTree)?;
-        let top = parse_tr_tree(desc_str)?;
+        let top = parse_tr_tree_with_checksum(s)?;
         Self::from_tree(&top)
     }
 }
@@ -587,27 +1089,34 @@ impl<Pk: MiniscriptKey> fmt::Display for Tr<Pk> {
     }
 }
 
+// Helper function to parse a string with an optional checksum into miniscript tree form
+pub(crate) fn parse_tr_tree_with_checksum(s: &str) -> Result<expression::Tree<'_>, Error> {
+    let desc_str = verify_checksum(s)
+        .map_err(From::from)
+        .map_err(Error::ParseTree)?;
+    parse_tr_tree(desc_str)
+}
+
 // Helper function to parse string into miniscript tree form
-fn parse_tr_tree(s: &str) -> Result<expression::Tree, Error> {
+fn parse_tr_tree(s: &str) -> Result<expression::Tree<'_>, Error> {
     if s.len() > 3 && &s[..3] == "tr(" && s.as_bytes()[s.len() - 1] == b')' {
         let rest = &s[3..s.len() - 1];
-        if !rest.contains(',') {
-            let key = expression::Tree::from_str(rest)?;
-            if !key.args.is_empty() {
-                return Err(Error::Unexpected("invalid taproot internal key".to_string()));
-            }
-            let internal_key = expression::Tree { name: key.name, args: vec![] };
+        // A `musig(KEY,KEY,...)` internal key contains commas, but no nested parentheses
+        let key_end = if rest.starts_with("musig(") {
+            rest.find(')').map_or(rest.len(), |i| i + 1)
+        } else {
+            rest.find(',').unwrap_or(rest.len())
+        };
+        let internal_key = expression::Tree::from_str(&rest[..key_end])?;
+        if !internal_key.args.is_empty() && internal_key.name != "musig" {
+            return Err(Error::Unexpected("invalid taproot internal key".to_string()));
+        }
+        if key_end == rest.len() {
             return Ok(expression::Tree { name: "tr", args: vec![internal_key] });
         }
-        // use str::split_once() method to refactor this when compiler version bumps up
-        let (key, script) = split_once(rest, ',')
+        let script = rest[key_end..]
+            .strip_prefix(',')
             .ok_or_else(|| Error::BadDescriptor("invalid taproot descriptor".to_string()))?;
-
-        let key = expression::Tree::from_str(key)?;
-        if !key.args.is_empty() {
-            return Err(Error::Unexpected("invalid taproot internal key".to_string()));
-        }
-        let internal_key = expression::Tree { name: key.name, args: vec![] };
         let (tree, rest) = expression::Tree::from_slice_delim(script, 1, '{')?;
         if rest.is_empty() {
             Ok(expression::Tree { name: "tr", args: vec![internal_key, tree] })
@@ -619,20 +1128,6 @@ fn parse_tr_tree(s: &str) -> Result<expression::Tree, Error> {
     }
 }
 
-fn split_once(inp: &str, delim: char) -> Option<(&str, &str)> {
-    if inp.is_empty() {
-        None
-    } else {
-        // find the first character that matches delim
-        let res = inp
-            .chars()
-            .position(|ch| ch == delim)
-            .map(|idx| (&inp[..idx], &inp[idx + 1..]))
-            .unwrap_or((inp, ""));
-        Some(res)
-    }
-}
-
 impl<Pk: MiniscriptKey> Liftable<Pk> for TapTree<Pk> {
     fn lift(&self) -> Result<Policy<Pk>, Error> {
         fn lift_helper<Pk: MiniscriptKey>(s: &TapTree<Pk>) -> Result<Policy<Pk>, Error> {
@@ -641,6 +1136,7 @@ impl<Pk: MiniscriptKey> Liftable<Pk> for TapTree<Pk> {
                     Threshold::or(Arc::new(lift_helper(left)?), Arc::new(lift_helper(right)?)),
                 )),
                 TapTree::Leaf(ref leaf) => leaf.lift(),
+                TapTree::SortedMultiA(ref keys, _) => keys.lift(),
             }
         }
 
@@ -688,7 +1184,12 @@ where
 {
     let spend_info = desc.spend_info();
     // First try the key spend path
-    if let Some(size) = provider.provider_lookup_tap_key_spend_sig(&desc.internal_key) {
+    let key_spend = if provider.check_tap_path(None) {
+        provider.provider_lookup_tap_key_spend_sig(&desc.internal_key)
+    } else {
+        None
+    };
+    if let Some(size) = key_spend {
         Satisfaction {
             stack: Witness::Stack(vec![Placeholder::SchnorrSigPk(
                 desc.internal_key.clone(),
@@ -710,6 +1211,11 @@ where
         };
         let mut min_wit_len = None;
         for (_depth, ms) in desc.iter_scripts() {
+            let leaf_script = (ms.encode(), LeafVersion::TapScript);
+            let leaf_hash = TapLeafHash::from_script(&leaf_script.0, leaf_script.1);
+            if !provider.check_tap_path(Some(&leaf_hash)) {
+                continue;
+            }
             let mut satisfaction = if allow_mall {
                 match ms.build_template(provider) {
                     s @ Satisfaction { stack: Witness::Stack(_), .. } => s,
@@ -726,7 +1232,6 @@ where
                 _ => unreachable!(),
             };
 
-            let leaf_script = (ms.encode(), LeafVersion::TapScript);
             let control_block = spend_info
                 .control_block(&leaf_script)
                 .expect("Control block must exist in script map for every known leaf");
@@ -781,4 +1286,287 @@ mod tests {
         let tr = Tr::<String>::from_str(&desc).unwrap();
         assert_eq!(tr.tap_tree().as_ref().unwrap().height(), 2);
     }
+
+    #[test]
+    fn bitcoin_tap_tree_roundtrip() {
+        let keys = [
+            "79be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798",
+            "c6047f9441ed7d6d3045406e95c07cd85c778e4b8cef3ca7abac09b95c709ee5",
+            "f9308a019258c31049344f85f89d5229b531c845836f99b08601f113bce036f9",
+            "e493dbf1c10d80f3581e4904930b1404cc6c13900ee0758474fa94abe8c4cd13",
+        ];
+        for tree in [
+            format!("pk({})", keys[0]),
+            format!("{{pk({}),{{pk({}),{{pk({}),pk({})}}}}}}", keys[0], keys[1], keys[2], keys[3]),
+            format!("{{{{pk({}),pk({})}},and_v(v:pk({}),older(10))}}", keys[0], keys[1], keys[2]),
+        ] {
+            let desc = format!("tr({},{})", keys[3], tree);
+            let tr = Tr::<XOnlyPublicKey>::from_str(&desc).unwrap();
+            let tap_tree = tr.tap_tree().as_ref().unwrap();
+
+            let bitcoin_tree = taproot::TapTree::from(tap_tree);
+            let mut leaves = bitcoin_tree
+                .script_leaves()
+                .map(|leaf| (leaf.merkle_branch().len() as u8, leaf.script().to_owned()))
+                .collect::<Vec<_>>();
+            let mut expected = tap_tree
+                .iter()
+                .map(|(depth, ms)| (depth, ms.encode()))
+                .collect::<Vec<_>>();
+            leaves.sort();
+            expected.sort();
+            assert_eq!(leaves, expected);
+
+            // Siblings may come back swapped, but the merkle root must be the same
+            let back = TapTree::try_from(&bitcoin_tree).unwrap();
+            let back_tr = Tr::new(*tr.internal_key(), Some(back)).unwrap();
+            assert_eq!(back_tr.spend_info().merkle_root(), tr.spend_info().merkle_root());
+        }
+
+        // Leaves which aren't Miniscript
+        let builder = TaprootBuilder::new()
+            .add_leaf(0, ScriptBuf::from_bytes(vec![0x6a]))
+            .unwrap();
+        let bitcoin_tree = taproot::TapTree::try_from(builder).unwrap();
+        assert!(matches!(
+            TapTree::from_bitcoin_tap_tree(&bitcoin_tree),
+            Err(TapTreeConversionError::Miniscript(_))
+        ));
+
+        let script = Miniscript::<XOnlyPublicKey, Tap>::from_str(&format!("pk({})", keys[0]))
+            .unwrap()
+            .encode();
+        let builder = TaprootBuilder::new()
+            .add_leaf_with_ver(0, script, LeafVersion::from_consensus(0xc2).unwrap())
+            .unwrap();
+        let bitcoin_tree = taproot::TapTree::try_from(builder).unwrap();
+        assert!(matches!(
+            TapTree::from_bitcoin_tap_tree(&bitcoin_tree),
+            Err(TapTreeConversionError::LeafVersion(_))
+        ));
+    }
+
+    #[test]
+    fn spk_cache() {
+        let secp = secp256k1::Secp256k1::verification_only();
+        let xpub = "xpub6BgBgsespWvERF3LHQu6CnqdvfEvtMcQjYrcRzx53QJjSxarj2afYWcLteoGVky7D3UKDP9QyrLprQ3VCECoY49yfdDEHGCtMMj92pReUsQ";
+        let single = "02c6047f9441ed7d6d3045406e95c07cd85c778e4b8cef3ca7abac09b95c709ee5";
+        let descs = [
+            // Only the internal key is ranged, the tree is reused across indices.
+            format!("tr({}/0/*,{{pk({}),pk({}/1/2)}})", xpub, single, xpub),
+            // A ranged leaf forces a full derivation at each index.
+            format!("tr({}/0/*,{{pk({}),pk({}/1/*)}})", xpub, single, xpub),
+            // No tree at all.
+            format!("tr({}/0/*)", xpub),
+        ];
+        for desc in &descs {
+            let desc = crate::Descriptor::<DescriptorPublicKey>::from_str(desc).unwrap();
+            let tr = match desc {
+                crate::Descriptor::Tr(ref tr) => tr,
+                _ => unreachable!(),
+            };
+            let mut cache = TrSpkCache::new(tr);
+            for i in 0..5 {
+                let expected = desc.derived_descriptor(&secp, i).unwrap().script_pubkey();
+                assert_eq!(cache.script_pubkey_at(tr, &secp, i).unwrap(), expected);
+            }
+            let target = desc.derived_descriptor(&secp, 3).unwrap();
+            assert_eq!(
+                desc.find_derivation_index_for_spk(&secp, &target.script_pubkey(), 0..5)
+                    .unwrap(),
+                Some((3, target))
+            );
+        }
+    }
+
+    #[test]
+    fn output_key_verification() {
+        let secp = secp256k1::Secp256k1::verification_only();
+        let internal = "02c6047f9441ed7d6d3045406e95c07cd85c778e4b8cef3ca7abac09b95c709ee5";
+        let a = "02f9308a019258c31049344f85f89d5229b531c845836f99b08601f113bce036f9";
+        let b = "02e493dbf1c10d80f3581e4904930b1404cc6c13900ee0758474fa94abe8c4cd13";
+        for desc in [
+            format!("tr({})", internal),
+            format!("tr({},pk({}))", internal, a),
+            format!("tr({},{{pk({}),{{pk({}),and_v(v:pk({}),older(10))}}}})", internal, a, b, a),
+        ] {
+            let tr = Tr::<bitcoin::PublicKey>::from_str(&desc).unwrap();
+            let spend_info = tr.spend_info();
+            assert_eq!(tr.merkle_root(), spend_info.merkle_root());
+
+            let (output_key, parity) = tr.output_key_with_parity();
+            let internal_key = tr.internal_key().to_x_only_pubkey();
+            assert!(internal_key.tweak_add_check(
+                &secp,
+                &output_key,
+                parity,
+                tr.tap_tweak().to_scalar()
+            ));
+            assert!(tr.verify_output_key(&output_key));
+            assert!(tr.verify_address(&tr.address(Network::Bitcoin)));
+            assert!(!tr.verify_output_key(&internal_key));
+        }
+
+        let other = Tr::<bitcoin::PublicKey>::from_str(&format!("tr({})", a)).unwrap();
+        let tr =
+            Tr::<bitcoin::PublicKey>::from_str(&format!("tr({},pk({}))", internal, a)).unwrap();
+        assert!(!tr.verify_address(&other.address(Network::Bitcoin)));
+        let pk = bitcoin::PublicKey::from_str(a).unwrap();
+        let compressed = bitcoin::CompressedPublicKey::try_from(pk).unwrap();
+        assert!(!tr.verify_address(&Address::p2wpkh(&compressed, Network::Bitcoin)));
+    }
+
+    #[test]
+    fn leaf_index() {
+        let tr = Tr::<String>::from_str(&descriptor()).unwrap();
+        assert_eq!(tr.leaf(0).unwrap().1.to_string(), "multi_a(3,acc10,acc11,acc12)");
+        assert_eq!(
+            tr.leaf(2).unwrap().1.to_string(),
+            "and_v(v:multi_a(1,acc10,acc11,ac12),after(100))"
+        );
+        assert!(tr.leaf(3).is_none());
+        assert!(Tr::<String>::from_str("tr(acc0)")
+            .unwrap()
+            .leaf(0)
+            .is_none());
+
+        // Indices survive a serialization roundtrip and match the iteration order
+        let reparsed = Tr::<String>::from_str(&tr.to_string()).unwrap();
+        for (index, (depth, ms)) in tr.iter_scripts().enumerate() {
+            assert_eq!(tr.leaf(index), Some((depth, ms)));
+            assert_eq!(reparsed.leaf(index), Some((depth, ms)));
+        }
+    }
+
+    #[test]
+    fn builder() {
+        let ms = |s: &str| Arc::new(Miniscript::<String, Tap>::from_str(s).unwrap());
+
+        let mut builder = Tr::builder("K".to_owned());
+        builder
+            .push(1, ms("pk(A)"))
+            .push(1, ms("pk(B)"))
+            .push(8, ms("pk(C)"));
+        assert_eq!(builder.n(), 3);
+        // The likeliest leaf is closest to the root
+        let tr = builder.build().unwrap();
+        assert_eq!(tr, Tr::from_str("tr(K,{{pk(A),pk(B)},pk(C)})").unwrap());
+        let depths: Vec<_> = tr.iter_scripts().map(|(depth, _)| depth).collect();
+        assert_eq!(depths, vec![2, 2, 1]);
+
+        let mut builder = Tr::builder("K".to_owned());
+        builder.push(0, ms("pk(A)"));
+        assert_eq!(builder.build().unwrap(), Tr::from_str("tr(K,pk(A))").unwrap());
+        assert_eq!(Tr::builder("K".to_owned()).build().unwrap(), Tr::from_str("tr(K)").unwrap());
+    }
+
+    #[test]
+    fn repeated_keys() {
+        let tr = Tr::<String>::from_str("tr(A,{pk(A),{and_v(v:pk(B),pk(C)),pk(B)}})").unwrap();
+        tr.sanity_check().unwrap();
+        let repeated = tr.repeated_keys();
+        assert_eq!(
+            repeated,
+            vec![
+                RepeatedKey {
+                    key: "A".to_owned(),
+                    positions: vec![
+                        KeyPosition::Internal,
+                        KeyPosition::Leaf { index: 0, depth: 1 }
+                    ],
+                },
+                RepeatedKey {
+                    key: "B".to_owned(),
+                    positions: vec![
+                        KeyPosition::Leaf { index: 1, depth: 2 },
+                        KeyPosition::Leaf { index: 2, depth: 2 }
+                    ],
+                },
+            ]
+        );
+        assert!(!repeated.iter().any(RepeatedKey::is_repeated_in_leaf));
+        assert!(Tr::<String>::from_str("tr(A,pk(B))")
+            .unwrap()
+            .repeated_keys()
+            .is_empty());
+
+        // Keys repeated within a leaf are reported too, though they aren't sane
+        let leaf = Miniscript::<String, Tap>::from_str_insane("and_v(v:pk(B),pk(B))").unwrap();
+        let tr = Tr::new("A".to_owned(), Some(TapTree::Leaf(Arc::new(leaf)))).unwrap();
+        assert!(tr.sanity_check().is_err());
+        let repeated = tr.repeated_keys();
+        assert_eq!(repeated.len(), 1);
+        assert!(repeated[0].is_repeated_in_leaf());
+    }
+
+    #[test]
+    fn musig_keys() {
+        use crate::descriptor::{DefiniteDescriptorKey, Descriptor};
+
+        let keys = [
+            "02f9308a019258c31049344f85f89d5229b531c845836f99b08601f113bce036f9",
+            "03dff1d77f2a671c5f36183726db2341be58feae1da2deced843240f7b502ba659",
+            "023590a94e768f8e1815c2f24b4d80a8e3149316c3518ce7b7ad338368d038ca66",
+        ];
+        let musig = format!("musig({},{},{})", keys[0], keys[1], keys[2]);
+
+        // Test vector of BIP 390, with the first key given as the public key of its WIF
+        let tr = Descriptor::<DefiniteDescriptorKey>::from_str(&format!("tr({})", musig)).unwrap();
+        assert_eq!(
+            tr.script_pubkey().to_hex_string(),
+            "512079e6c3e628c9bfbce91de6b7fb28e2aec7713d377cf260ab599dcbc40e542312"
+        );
+        assert_eq!(
+            tr.address(Network::Bitcoin).unwrap().to_string(),
+            "bc1p08nv8e3gexlme6gau6mlk28z4mrhz0fh0nexp26enh9ugrj5yvfq5ttgh9"
+        );
+
+        let aggregate = DefiniteDescriptorKey::from_str(&musig)
+            .unwrap()
+            .to_x_only_pubkey();
+        for (desc, expected) in [
+            (format!("tr({})", musig), format!("tr({})", aggregate)),
+            (
+                format!("tr({},{{pk({}),pk({})}})", musig, musig, keys[0]),
+                format!("tr({},{{pk({}),pk({})}})", aggregate, aggregate, keys[0]),
+            ),
+        ] {
+            let tr = Descriptor::<DefiniteDescriptorKey>::from_str(&desc).unwrap();
+            assert_eq!(tr.to_string(), Descriptor::<String>::from_str(&desc).unwrap().to_string());
+            assert_eq!(tr.to_string().split('#').next().unwrap(), desc);
+            let expected = Descriptor::<DefiniteDescriptorKey>::from_str(&expected).unwrap();
+            assert_eq!(tr.script_pubkey(), expected.script_pubkey());
+            assert_eq!(
+                tr.address(Network::Bitcoin).unwrap(),
+                expected.address(Network::Bitcoin).unwrap()
+            );
+        }
+        let desc = format!("tr({},pk(musig({},{})))", keys[2], keys[0], keys[1]);
+        let tr = Descriptor::<DefiniteDescriptorKey>::from_str(&desc).unwrap();
+        assert_eq!(tr.to_string().split('#').next().unwrap(), desc);
+
+        // The aggregate of a single key isn't the key itself
+        let single =
+            Descriptor::<DefiniteDescriptorKey>::from_str(&format!("tr(musig({}))", keys[0]))
+                .unwrap();
+        let plain =
+            Descriptor::<DefiniteDescriptorKey>::from_str(&format!("tr({})", keys[0])).unwrap();
+        assert_ne!(single.script_pubkey(), plain.script_pubkey());
+
+        // Aggregate keys are only allowed in Taproot, and in `pk()` leaves
+        for desc in [
+            format!("wsh(pk({}))", musig),
+            format!("sh(pk({}))", musig),
+            format!("pkh({})", musig),
+            format!("tr({},pkh({}))", keys[0], musig),
+            format!("tr({},multi_a(1,{}))", keys[0], musig),
+            format!("tr(musig(musig({}),{}))", keys[0], keys[1]),
+        ] {
+            assert!(Descriptor::<DefiniteDescriptorKey>::from_str(&desc).is_err(), "{}", desc);
+        }
+        for desc in [format!("wsh(pk({}))", musig), format!("sh(pk({}))", musig)] {
+            let err = Descriptor::<DefiniteDescriptorKey>::from_str(&desc).unwrap_err();
+            assert!(err.to_string().contains("musig() key"), "{}: {}", desc, err);
+        }
+    }
 }
@@ -0,0 +1,190 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! # Pay-to-contract Tweaks
+//!
+//! Implementation of pay-to-contract commitments on the keys of a descriptor.
+//! Every key `P` derived from a [`TweakedDescriptor`] is replaced by
+//! `P + sha256(P || c)·G`, where `c` is the commitment to the contract data,
+//! so that the resulting addresses prove the contract existed when they were
+//! paid to. Timestamping and proof-of-liability schemes rely on this.
+//!
+
+use core::ops::Range;
+
+use bitcoin::hashes::{sha256, Hash, HashEngine};
+use bitcoin::secp256k1::{self, Scalar};
+use bitcoin::{Address, Network, Script};
+
+use super::{DerivationRangeError, Descriptor, DescriptorPublicKey};
+use crate::prelude::*;
+use crate::{Error, Translator};
+
+/// A pay-to-contract tweak, committing keys to some contract data
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct KeyTweak {
+    /// The commitment to the contract
+    commitment: sha256::Hash,
+}
+
+impl KeyTweak {
+    /// Create a tweak committing to `contract`, i.e. to its SHA256 hash
+    pub fn from_contract(contract: &[u8]) -> Self {
+        KeyTweak { commitment: sha256::Hash::hash(contract) }
+    }
+
+    /// Create a tweak from the commitment to a contract
+    pub fn from_commitment(commitment: sha256::Hash) -> Self { KeyTweak { commitment } }
+
+    /// The commitment to the contract
+    pub fn commitment(&self) -> sha256::Hash { self.commitment }
+
+    /// The scalar added to the private key of `pk`, `sha256(pk || commitment)`
+    ///
+    /// The key is hashed in its compressed form, so that a key and its x-only
+    /// counterpart with even parity get the same tweak. A hash beyond the curve
+    /// order, which happens with negligible probability, is reported as
+    /// [`secp256k1::Error::InvalidTweak`].
+    pub fn tweak_scalar(&self, pk: &bitcoin::PublicKey) -> Result<Scalar, Error> {
+        let mut engine = sha256::Hash::engine();
+        engine.input(&pk.inner.serialize());
+        engine.input(self.commitment.as_byte_array());
+        let hash = sha256::Hash::from_engine(engine);
+        Scalar::from_be_bytes(hash.to_byte_array())
+            .map_err(|_| Error::Secp(secp256k1::Error::InvalidTweak))
+    }
+
+    /// Apply the tweak to a public key
+    pub fn tweak_public_key<C: secp256k1::Verification>(
+        &self,
+        secp: &secp256k1::Secp256k1<C>,
+        pk: &bitcoin::PublicKey,
+    ) -> Result<bitcoin::PublicKey, Error> {
+        let inner = pk
+            .inner
+            .add_exp_tweak(secp, &self.tweak_scalar(pk)?)
+            .map_err(Error::Secp)?;
+        Ok(bitcoin::PublicKey { inner, compressed: pk.compressed })
+    }
+
+    /// Apply the tweak to a private key, so that it matches the tweaked public key
+    pub fn tweak_private_key<C: secp256k1::Signing>(
+        &self,
+        secp: &secp256k1::Secp256k1<C>,
+        sk: &bitcoin::PrivateKey,
+    ) -> Result<bitcoin::PrivateKey, Error> {
+        let scalar = self.tweak_scalar(&sk.public_key(secp))?;
+        let inner = sk.inner.add_tweak(&scalar).map_err(Error::Secp)?;
+        Ok(bitcoin::PrivateKey { inner, ..*sk })
+    }
+}
+
+/// A descriptor whose keys are all committed to a contract on derivation
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct TweakedDescriptor {
+    /// The descriptor, before any tweak
+    descriptor: Descriptor<DescriptorPublicKey>,
+    /// The tweak applied to every derived key
+    tweak: KeyTweak,
+}
+
+impl TweakedDescriptor {
+    /// Create a descriptor whose derived keys commit to the contract of `tweak`
+    ///
+    /// Errors if the descriptor is a multipath descriptor.
+    pub fn new(
+        descriptor: Descriptor<DescriptorPublicKey>,
+        tweak: KeyTweak,
+    ) -> Result<Self, Error> {
+        if descriptor.is_multipath() {
+            return Err(Error::BadDescriptor(
+                "multipath descriptors can't be tweaked, split them first".to_owned(),
+            ));
+        }
+        Ok(TweakedDescriptor { descriptor, tweak })
+    }
+
+    /// The descriptor, before any tweak
+    pub fn descriptor(&self) -> &Descriptor<DescriptorPublicKey> { &self.descriptor }
+
+    /// The tweak applied to every derived key
+    pub fn tweak(&self) -> KeyTweak { self.tweak }
+
+    /// Derive the descriptor at `index` and tweak all its keys
+    ///
+    /// # Errors
+    ///
+    /// If hardened derivation is attempted, reported as
+    /// [`DerivationRangeError::Derivation`], or in the negligible case the
+    /// tweak of a key is invalid.
+    pub fn derived_descriptor<C: secp256k1::Verification>(
+        &self,
+        secp: &secp256k1::Secp256k1<C>,
+        index: u32,
+    ) -> Result<Descriptor<bitcoin::PublicKey>, Error> {
+        struct Tweaker<'a, C: secp256k1::Verification>(&'a secp256k1::Secp256k1<C>, KeyTweak);
+
+        impl<C: secp256k1::Verification> Translator<bitcoin::PublicKey> for Tweaker<'_, C> {
+            type TargetPk = bitcoin::PublicKey;
+            type Error = Error;
+
+            fn pk(&mut self, pk: &bitcoin::PublicKey) -> Result<bitcoin::PublicKey, Error> {
+                self.1.tweak_public_key(self.0, pk)
+            }
+
+            translate_hash_clone!(bitcoin::PublicKey, bitcoin::PublicKey, Error);
+        }
+
+        let derived = self
+            .descriptor
+            .derived_descriptor(secp, index)
+            .map_err(|e| DerivationRangeError::Derivation(index, e))?;
+        derived
+            .translate_pk(&mut Tweaker(secp, self.tweak))
+            .map_err(|e| e.expect_translator_err("tweaking keys doesn't change their size"))
+    }
+
+    /// The address at `index`, committing to the contract
+    pub fn address<C: secp256k1::Verification>(
+        &self,
+        secp: &secp256k1::Secp256k1<C>,
+        index: u32,
+        network: Network,
+    ) -> Result<Address, Error> {
+        self.derived_descriptor(secp, index)?.address(network)
+    }
+
+    /// Whether `script_pubkey` is the one at `index`, i.e. it commits to the contract
+    pub fn verify_script_pubkey<C: secp256k1::Verification>(
+        &self,
+        secp: &secp256k1::Secp256k1<C>,
+        index: u32,
+        script_pubkey: &Script,
+    ) -> Result<bool, Error> {
+        Ok(self.derived_descriptor(secp, index)?.script_pubkey() == *script_pubkey)
+    }
+
+    /// Whether `address` is the one at `index`, i.e. it commits to the contract
+    pub fn verify_address<C: secp256k1::Verification>(
+        &self,
+        secp: &secp256k1::Secp256k1<C>,
+        index: u32,
+        address: &Address,
+    ) -> Result<bool, Error> {
+        self.verify_script_pubkey(secp, index, &address.script_pubkey())
+    }
+
+    /// Find the first index of `range` whose script pubkey is `script_pubkey`
+    pub fn find_index<C: secp256k1::Verification>(
+        &self,
+        secp: &secp256k1::Secp256k1<C>,
+        range: Range<u32>,
+        script_pubkey: &Script,
+    ) -> Result<Option<u32>, Error> {
+        for index in range {
+            if self.verify_script_pubkey(secp, index, script_pubkey)? {
+                return Ok(Some(index));
+            }
+        }
+        Ok(None)
+    }
+}
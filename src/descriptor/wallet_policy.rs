@@ -0,0 +1,487 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! # Wallet Policies
+//!
+//! Conversion between descriptors and the wallet policies of BIP 388, the
+//! format in which hardware signers register descriptors: a descriptor template
+//! whose key expressions are placeholders `@i/**` or `@i/<M;N>/*`, and the
+//! vector of the keys they refer to, each an xpub with its origin and without
+//! derivation steps. For instance the template `wsh(multi(2,@0/**,@1/**))` with
+//! the keys `[f5acc2fd/48'/0'/0'/2']xpub...` and `[c3a2f3e0/48'/0'/0'/2']xpub...`.
+//!
+
+use core::fmt;
+use core::str::FromStr;
+#[cfg(feature = "std")]
+use std::error;
+
+use bitcoin::bip32::{ChildNumber, DerivationPath, Xpub};
+use bitcoin::hashes::{hash160, ripemd160, sha256};
+
+use super::{
+    DerivPaths, Descriptor, DescriptorMultiXKey, DescriptorPublicKey, DescriptorType,
+    DescriptorXKey, Wildcard,
+};
+use crate::prelude::*;
+use crate::{hash256, ForEachKey, MiniscriptKey, TranslateErr, Translator};
+
+/// An error converting between descriptors and [`WalletPolicy`]s
+#[derive(Debug)]
+pub enum WalletPolicyError {
+    /// The key expression of the template isn't a `@i/**` or `@i/<M;N>/*` placeholder
+    InvalidPlaceholder(String),
+    /// A placeholder refers to a key beyond the key vector
+    KeyIndexOutOfRange(usize),
+    /// The key at this index is used before the keys of lower indexes
+    UnorderedKey(usize),
+    /// The key at this index isn't used by the template
+    UnusedKey(usize),
+    /// The key at this index isn't an xpub without derivation steps nor wildcard
+    InvalidKey(usize),
+    /// The key at this index repeats a key of a lower index
+    DuplicateKey(usize),
+    /// The placeholder derives a key at an index another placeholder of the same key
+    /// derives at
+    OverlappingPlaceholder(String),
+    /// The key of the descriptor can't be expressed as a placeholder, as it isn't an
+    /// xpub derived at `/<M;N>/*`
+    UnsupportedKey(Box<DescriptorPublicKey>),
+    /// The template is a descriptor of a type wallet policies don't support
+    UnsupportedType(DescriptorType),
+    /// A hash of the template isn't a valid hex-encoded hash
+    Hash(bitcoin::hex::HexToArrayError),
+    /// The template isn't a valid descriptor
+    Template(crate::Error),
+}
+
+impl fmt::Display for WalletPolicyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            WalletPolicyError::InvalidPlaceholder(ref s) => {
+                write!(f, "invalid key placeholder {}", s)
+            }
+            WalletPolicyError::KeyIndexOutOfRange(index) => {
+                write!(f, "placeholder @{} refers to a key beyond the key vector", index)
+            }
+            WalletPolicyError::UnorderedKey(index) => {
+                write!(f, "key @{} is used before the keys of lower indexes", index)
+            }
+            WalletPolicyError::UnusedKey(index) => {
+                write!(f, "key @{} isn't used by the template", index)
+            }
+            WalletPolicyError::InvalidKey(index) => {
+                write!(f, "key @{} isn't an xpub without derivation steps", index)
+            }
+            WalletPolicyError::DuplicateKey(index) => {
+                write!(f, "key @{} repeats a key of a lower index", index)
+            }
+            WalletPolicyError::OverlappingPlaceholder(ref s) => {
+                write!(f, "placeholder {} overlaps another placeholder of the same key", s)
+            }
+            WalletPolicyError::UnsupportedKey(ref pk) => {
+                write!(f, "key {} can't be expressed in a wallet policy", pk)
+            }
+            WalletPolicyError::UnsupportedType(ty) => {
+                write!(f, "{:?} descriptors are not supported", ty)
+            }
+            WalletPolicyError::Hash(ref e) => write!(f, "invalid hash in template: {}", e),
+            WalletPolicyError::Template(ref e) => write!(f, "invalid template: {}", e),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl error::Error for WalletPolicyError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            WalletPolicyError::Hash(e) => Some(e),
+            WalletPolicyError::Template(e) => Some(e),
+            WalletPolicyError::InvalidPlaceholder(_)
+            | WalletPolicyError::KeyIndexOutOfRange(_)
+            | WalletPolicyError::UnorderedKey(_)
+            | WalletPolicyError::UnusedKey(_)
+            | WalletPolicyError::InvalidKey(_)
+            | WalletPolicyError::DuplicateKey(_)
+            | WalletPolicyError::OverlappingPlaceholder(_)
+            | WalletPolicyError::UnsupportedKey(_)
+            | WalletPolicyError::UnsupportedType(_) => None,
+        }
+    }
+}
+
+/// A BIP 388 wallet policy: a descriptor template and the keys of its placeholders
+///
+/// The template is a descriptor whose keys are the placeholders `@i/**`, short for
+/// `@i/<0;1>/*`, or `@i/<M;N>/*`, `i` being the index of the key in the key vector.
+/// Only `sh`, `wsh`, `pkh`, `wpkh` and `tr` descriptors are supported, every key
+/// must be used and appear once in the key vector, the first placeholder of each key must come after the first
+/// placeholder of the previous key, and the placeholders of a key can't derive at
+/// the same index.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WalletPolicy {
+    template: String,
+    keys: Vec<DescriptorPublicKey>,
+    descriptor: Descriptor<DescriptorPublicKey>,
+}
+
+impl WalletPolicy {
+    /// Creates a wallet policy from its template and keys
+    ///
+    /// # Errors
+    ///
+    /// If the template or the keys don't follow the rules of BIP 388.
+    pub fn new(template: &str, keys: Vec<DescriptorPublicKey>) -> Result<Self, WalletPolicyError> {
+        let template =
+            Descriptor::<String>::from_str(template).map_err(WalletPolicyError::Template)?;
+        match template {
+            Descriptor::Pkh(..)
+            | Descriptor::Wpkh(..)
+            | Descriptor::Sh(..)
+            | Descriptor::Wsh(..)
+            | Descriptor::Tr(..) => {}
+            _ => return Err(WalletPolicyError::UnsupportedType(template.desc_type())),
+        }
+
+        for (index, key) in keys.iter().enumerate() {
+            if keys[..index].contains(key) {
+                return Err(WalletPolicyError::DuplicateKey(index));
+            }
+        }
+
+        let mut placeholders = Placeholders { keys: &keys, used: BTreeSet::new() };
+        let descriptor = template
+            .translate_pk(&mut placeholders)
+            .map_err(|e| match e {
+                TranslateErr::TranslatorErr(e) => e,
+                TranslateErr::OuterError(e) => WalletPolicyError::Template(e),
+            })?;
+        let mut next = 0;
+        for placeholder in keys_in_order(&template) {
+            let index = Placeholders::parse(placeholder).map_or(0, |(index, _, _)| index);
+            if index > next {
+                return Err(WalletPolicyError::UnorderedKey(index));
+            } else if index == next {
+                next += 1;
+            }
+        }
+        if next < keys.len() {
+            return Err(WalletPolicyError::UnusedKey(next));
+        }
+
+        let template = template.to_string();
+        let template = match template.split_once('#') {
+            Some((template, _)) => template.to_owned(),
+            None => template,
+        };
+        Ok(WalletPolicy { template, keys, descriptor })
+    }
+
+    /// The descriptor template, without checksum
+    pub fn template(&self) -> &str { &self.template }
+
+    /// The keys of the placeholders of the template
+    pub fn keys(&self) -> &[DescriptorPublicKey] { &self.keys }
+
+    /// The descriptor of the wallet policy
+    pub fn descriptor(&self) -> &Descriptor<DescriptorPublicKey> { &self.descriptor }
+
+    /// Converts the wallet policy into its descriptor
+    pub fn into_descriptor(self) -> Descriptor<DescriptorPublicKey> { self.descriptor }
+}
+
+impl Descriptor<DescriptorPublicKey> {
+    /// Converts the descriptor into a BIP 388 wallet policy
+    ///
+    /// Every key must be an xpub derived at `/<M;N>/*` with two unhardened indexes,
+    /// the same xpub and origin being a single key of the policy. Keys are numbered
+    /// in the order they first appear in the descriptor.
+    ///
+    /// # Errors
+    ///
+    /// If a key can't be expressed as a placeholder, or the descriptor doesn't
+    /// follow the other rules of BIP 388.
+    pub fn to_wallet_policy(&self) -> Result<WalletPolicy, WalletPolicyError> {
+        struct KeyIndexes<'a>(&'a [DescriptorPublicKey]);
+
+        impl Translator<DescriptorPublicKey> for KeyIndexes<'_> {
+            type TargetPk = String;
+            type Error = WalletPolicyError;
+
+            fn pk(&mut self, pk: &DescriptorPublicKey) -> Result<String, WalletPolicyError> {
+                let unsupported = || WalletPolicyError::UnsupportedKey(Box::new(pk.clone()));
+                let xkey = match *pk {
+                    DescriptorPublicKey::MultiXPub(ref xkey)
+                        if xkey.wildcard == Wildcard::Unhardened =>
+                    {
+                        xkey
+                    }
+                    _ => return Err(unsupported()),
+                };
+                let mut indexes = vec![];
+                for path in xkey.derivation_paths.paths() {
+                    match path.as_ref() {
+                        [ChildNumber::Normal { index }] => indexes.push(*index),
+                        _ => return Err(unsupported()),
+                    }
+                }
+                let (m, n) = match indexes[..] {
+                    [m, n] => (m, n),
+                    _ => return Err(unsupported()),
+                };
+
+                let key = xpub_key(xkey);
+                let index = self
+                    .0
+                    .iter()
+                    .position(|k| *k == key)
+                    .expect("keys are numbered beforehand");
+                Ok(if (m, n) == (0, 1) {
+                    format!("@{}/**", index)
+                } else {
+                    format!("@{}/<{};{}>/*", index, m, n)
+                })
+            }
+
+            fn sha256(&mut self, sha256: &sha256::Hash) -> Result<String, WalletPolicyError> {
+                Ok(sha256.to_string())
+            }
+
+            fn hash256(&mut self, hash256: &hash256::Hash) -> Result<String, WalletPolicyError> {
+                Ok(hash256.to_string())
+            }
+
+            fn ripemd160(
+                &mut self,
+                ripemd160: &ripemd160::Hash,
+            ) -> Result<String, WalletPolicyError> {
+                Ok(ripemd160.to_string())
+            }
+
+            fn hash160(&mut self, hash160: &hash160::Hash) -> Result<String, WalletPolicyError> {
+                Ok(hash160.to_string())
+            }
+        }
+
+        // Number the xpubs in the order they first appear
+        let mut keys = vec![];
+        for pk in keys_in_order(self) {
+            if let DescriptorPublicKey::MultiXPub(ref xkey) = *pk {
+                let key = xpub_key(xkey);
+                if !keys.contains(&key) {
+                    keys.push(key);
+                }
+            }
+        }
+        let mut indexes = KeyIndexes(&keys);
+        let template = self.translate_pk(&mut indexes).map_err(|e| match e {
+            TranslateErr::TranslatorErr(e) => e,
+            TranslateErr::OuterError(e) => WalletPolicyError::Template(e),
+        })?;
+        WalletPolicy::new(&template.to_string(), keys)
+    }
+}
+
+/// Replaces the placeholders of a template with the keys they refer to
+struct Placeholders<'a> {
+    keys: &'a [DescriptorPublicKey],
+    /// The derivation indexes used by each key
+    used: BTreeSet<(usize, u32)>,
+}
+
+impl Placeholders<'_> {
+    /// Parses `@i/**` or `@i/<M;N>/*` into the key index and the two derivation indexes
+    fn parse(s: &str) -> Option<(usize, u32, u32)> {
+        let (index, path) = s.strip_prefix('@')?.split_once('/')?;
+        if index.is_empty() || !index.bytes().all(|ch| ch.is_ascii_digit()) {
+            return None;
+        }
+        let index = index.parse().ok()?;
+        if path == "**" {
+            return Some((index, 0, 1));
+        }
+        let (m, n) = path
+            .strip_prefix('<')?
+            .strip_suffix(">/*")?
+            .split_once(';')?;
+        let (m, n) = (parse_child(m)?, parse_child(n)?);
+        if m == n {
+            return None;
+        }
+        Some((index, m, n))
+    }
+}
+
+/// The key of the key vector of a multipath xpub: the xpub and its origin
+fn xpub_key(xkey: &DescriptorMultiXKey<Xpub>) -> DescriptorPublicKey {
+    DescriptorPublicKey::XPub(DescriptorXKey {
+        origin: xkey.origin.clone(),
+        xkey: xkey.xkey,
+        derivation_path: DerivationPath::master(),
+        wildcard: Wildcard::None,
+    })
+}
+
+/// The keys of a descriptor in the order they appear in its string
+fn keys_in_order<Pk: MiniscriptKey>(descriptor: &Descriptor<Pk>) -> Vec<&Pk> {
+    let mut keys = vec![];
+    match *descriptor {
+        // The internal key is the last key of the iteration of Taproot descriptors
+        Descriptor::Tr(ref tr) => {
+            keys.push(tr.internal_key());
+            for (_, ms) in tr.iter_scripts() {
+                ms.for_each_key(|pk| {
+                    keys.push(pk);
+                    true
+                });
+            }
+        }
+        _ => {
+            descriptor.for_each_key(|pk| {
+                keys.push(pk);
+                true
+            });
+        }
+    }
+    keys
+}
+
+/// Parses an unhardened derivation index
+fn parse_child(s: &str) -> Option<u32> {
+    match ChildNumber::from_str(s).ok()? {
+        ChildNumber::Normal { index } if s.bytes().all(|ch| ch.is_ascii_digit()) => Some(index),
+        _ => None,
+    }
+}
+
+impl Translator<String> for Placeholders<'_> {
+    type TargetPk = DescriptorPublicKey;
+    type Error = WalletPolicyError;
+
+    fn pk(&mut self, s: &String) -> Result<DescriptorPublicKey, WalletPolicyError> {
+        let (index, m, n) = Placeholders::parse(s)
+            .ok_or_else(|| WalletPolicyError::InvalidPlaceholder(s.clone()))?;
+        let xkey = match self.keys.get(index) {
+            Some(DescriptorPublicKey::XPub(xkey))
+                if xkey.derivation_path.is_master() && xkey.wildcard == Wildcard::None =>
+            {
+                xkey
+            }
+            Some(_) => return Err(WalletPolicyError::InvalidKey(index)),
+            None => return Err(WalletPolicyError::KeyIndexOutOfRange(index)),
+        };
+        if !self.used.insert((index, m)) || !self.used.insert((index, n)) {
+            return Err(WalletPolicyError::OverlappingPlaceholder(s.clone()));
+        }
+
+        let paths = vec![
+            vec![ChildNumber::from(m)].into(),
+            vec![ChildNumber::from(n)].into(),
+        ];
+        Ok(DescriptorPublicKey::MultiXPub(DescriptorMultiXKey {
+            origin: xkey.origin.clone(),
+            xkey: xkey.xkey,
+            derivation_paths: DerivPaths::new(paths).expect("two paths"),
+            wildcard: Wildcard::Unhardened,
+        }))
+    }
+
+    fn sha256(&mut self, hash: &String) -> Result<sha256::Hash, WalletPolicyError> {
+        sha256::Hash::from_str(hash).map_err(WalletPolicyError::Hash)
+    }
+
+    fn hash256(&mut self, hash: &String) -> Result<hash256::Hash, WalletPolicyError> {
+        hash256::Hash::from_str(hash).map_err(WalletPolicyError::Hash)
+    }
+
+    fn ripemd160(&mut self, hash: &String) -> Result<ripemd160::Hash, WalletPolicyError> {
+        ripemd160::Hash::from_str(hash).map_err(WalletPolicyError::Hash)
+    }
+
+    fn hash160(&mut self, hash: &String) -> Result<hash160::Hash, WalletPolicyError> {
+        hash160::Hash::from_str(hash).map_err(WalletPolicyError::Hash)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const K0: &str = "[73c5da0a/86'/0'/0']xpub6BgBgsespWvERF3LHQu6CnqdvfEvtMcQjYrcRzx53QJjSxarj2afYWcLteoGVky7D3UKDP9QyrLprQ3VCECoY49yfdDEHGCtMMj92pReUsQ";
+    const K1: &str = "[a12b02f4/44'/0'/0']xpub6BzhLAQUDcBUfHRQHZxDF2AbcJqp4Kaeq6bzJpXrjrWuK26ymTFwkEFbxPra2bJ7yeZKbDjfDeFwxe93JMqpo5SsPJH6dZdvV9kMzJkAZ69";
+
+    fn keys(keys: &[&str]) -> Vec<DescriptorPublicKey> {
+        keys.iter()
+            .map(|k| DescriptorPublicKey::from_str(k).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn wallet_policy_roundtrip() {
+        for (template, descriptor) in [
+            ("wpkh(@0/**)", format!("wpkh({}/<0;1>/*)", K0)),
+            (
+                "wsh(or_d(pk(@0/**),and_v(v:pk(@1/<2;3>/*),older(144))))",
+                format!("wsh(or_d(pk({}/<0;1>/*),and_v(v:pk({}/<2;3>/*),older(144))))", K0, K1),
+            ),
+            (
+                "tr(@0/**,{pk(@1/**),pk(@0/<2;3>/*)})",
+                format!("tr({}/<0;1>/*,{{pk({}/<0;1>/*),pk({}/<2;3>/*)}})", K0, K1, K0),
+            ),
+        ] {
+            let descriptor = Descriptor::<DescriptorPublicKey>::from_str(&descriptor).unwrap();
+            let policy = descriptor.to_wallet_policy().unwrap();
+            assert_eq!(policy.template(), template);
+            let n_keys = if template.contains("@1") { 2 } else { 1 };
+            assert_eq!(policy.keys(), &keys(&[K0, K1][..n_keys])[..]);
+            assert_eq!(WalletPolicy::new(template, policy.keys().to_vec()).unwrap(), policy);
+            assert_eq!(policy.into_descriptor(), descriptor);
+        }
+    }
+
+    #[test]
+    fn wallet_policy_errors() {
+        let err = |template: &str, ks: &[&str]| WalletPolicy::new(template, keys(ks)).unwrap_err();
+        assert!(matches!(
+            err("wsh(pk(@0))", &[K0]),
+            WalletPolicyError::InvalidPlaceholder(ref s) if s == "@0"
+        ));
+        assert!(matches!(
+            err("wsh(pk(@0/<1;1>/*))", &[K0]),
+            WalletPolicyError::InvalidPlaceholder(ref s) if s == "@0/<1;1>/*"
+        ));
+        assert!(matches!(err("wpkh(@1/**)", &[K0]), WalletPolicyError::KeyIndexOutOfRange(1)));
+        assert!(matches!(
+            err("wsh(multi(1,@1/**,@0/**))", &[K0, K1]),
+            WalletPolicyError::UnorderedKey(1)
+        ));
+        assert!(matches!(err("wpkh(@0/**)", &[K0, K1]), WalletPolicyError::UnusedKey(1)));
+        assert!(matches!(
+            err("wsh(multi(1,@0/**,@0/<1;2>/*))", &[K0]),
+            WalletPolicyError::OverlappingPlaceholder(ref s) if s == "@0/<1;2>/*"
+        ));
+        assert!(matches!(
+            err("wpkh(@0/**)", &[&format!("{}/0", K0)]),
+            WalletPolicyError::InvalidKey(0)
+        ));
+        assert!(matches!(
+            err("wsh(multi(1,@0/**,@1/**))", &[K0, K0]),
+            WalletPolicyError::DuplicateKey(1)
+        ));
+        assert!(matches!(
+            err("pk(@0/**)", &[K0]),
+            WalletPolicyError::UnsupportedType(DescriptorType::Bare)
+        ));
+        assert!(matches!(err("wsh(pk(@0/**)", &[K0]), WalletPolicyError::Template(_)));
+        assert!(matches!(
+            err("wsh(and_v(v:pk(@0/**),sha256(00)))", &[K0]),
+            WalletPolicyError::Hash(_)
+        ));
+
+        let descriptor =
+            Descriptor::<DescriptorPublicKey>::from_str(&format!("wpkh({}/0/*)", K0)).unwrap();
+        assert!(matches!(
+            descriptor.to_wallet_policy(),
+            Err(WalletPolicyError::UnsupportedKey(_))
+        ));
+    }
+}
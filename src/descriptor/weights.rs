@@ -0,0 +1,125 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! # Satisfaction Weight Statistics
+//!
+//! Distribution of the satisfaction weight of a ranged or multipath descriptor
+//! over its derivation indexes, for fee estimation policies quoting a single
+//! number per wallet.
+//!
+
+use core::fmt;
+use core::ops::Range;
+
+use bitcoin::Weight;
+
+use super::{Descriptor, DescriptorPublicKey};
+use crate::Error;
+
+/// Maximum number of derivation indexes of each path of a descriptor for which
+/// the satisfaction weight is computed.
+const MAX_WEIGHT_SAMPLES: u32 = 1000;
+
+/// The distribution of the maximum satisfaction weight of a descriptor over
+/// derivation indexes, see [`Descriptor::satisfaction_weight_stats`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct WeightStats {
+    /// The number of derived descriptors the statistics are computed over
+    pub samples: usize,
+    /// The lowest weight
+    pub min: Weight,
+    /// The median weight, the lower one of the two middle weights for an even
+    /// number of samples
+    pub median: Weight,
+    /// The highest weight
+    pub max: Weight,
+}
+
+impl fmt::Display for WeightStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "min {} wu, median {} wu, max {} wu over {} samples",
+            self.min.to_wu(),
+            self.median.to_wu(),
+            self.max.to_wu(),
+            self.samples
+        )
+    }
+}
+
+impl Descriptor<DescriptorPublicKey> {
+    /// Computes the distribution of [`Descriptor::max_weight_to_satisfy`] over the
+    /// derivation indexes in `range`, and over every path of a multipath descriptor
+    ///
+    /// The weights are computed exactly for ranges of up to 1000 indexes. For larger
+    /// ranges, 1000 indexes evenly spread over the range are sampled for each path.
+    /// Descriptors without wildcards are only derived once per path.
+    ///
+    /// # Errors
+    ///
+    /// If `range` is empty and the descriptor has wildcards, if it can't be derived
+    /// or if its satisfaction weight can't be computed.
+    pub fn satisfaction_weight_stats(&self, range: Range<u32>) -> Result<WeightStats, Error> {
+        let range = self.checked_derivation_range(range)?;
+        let len = range.end - range.start;
+        let samples = len.min(MAX_WEIGHT_SAMPLES);
+        let indexes = (0..samples)
+            .map(|i| range.start + (u64::from(i) * u64::from(len) / u64::from(samples)) as u32);
+
+        let mut weights = vec![];
+        for (_, derived) in self.derive_paths(indexes)? {
+            weights.push(derived.max_weight_to_satisfy()?);
+        }
+        weights.sort_unstable();
+        Ok(WeightStats {
+            samples: weights.len(),
+            min: weights[0],
+            median: weights[(weights.len() - 1) / 2],
+            max: weights[weights.len() - 1],
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::descriptor::DerivationRangeError;
+    use crate::test_utils::xpub_desc;
+
+    #[test]
+    fn satisfaction_weight_stats() {
+        let wpkh = xpub_desc("wpkh(XPUB/<0;1>/*)");
+        let stats = wpkh.satisfaction_weight_stats(0..10).unwrap();
+        let weight = xpub_desc("wpkh(XPUB/0/*)")
+            .at_derivation_index(0)
+            .unwrap()
+            .max_weight_to_satisfy()
+            .unwrap();
+        assert_eq!(stats, WeightStats { samples: 20, min: weight, median: weight, max: weight });
+
+        // Large ranges are sampled
+        let stats = wpkh.satisfaction_weight_stats(0..1_000_000).unwrap();
+        assert_eq!(stats.samples, 2000);
+
+        // Derived keys all have the same size, so the weight is constant
+        let stats = xpub_desc("wsh(or_d(pk(XPUB/0/*),and_v(v:pk(XPUB/1/*),older(10))))")
+            .satisfaction_weight_stats(5..8)
+            .unwrap();
+        assert_eq!(stats.samples, 3);
+        assert_eq!(stats.min, stats.max);
+
+        let single = xpub_desc("pkh(XPUB/0/5)")
+            .satisfaction_weight_stats(0..0)
+            .unwrap();
+        assert_eq!(single.samples, 1);
+        assert_eq!(
+            single.to_string(),
+            format!("min {0} wu, median {0} wu, max {0} wu over 1 samples", single.min.to_wu())
+        );
+
+        assert!(matches!(
+            wpkh.satisfaction_weight_stats(3..3),
+            Err(Error::DerivationRange(DerivationRangeError::EmptyRange(_)))
+        ));
+    }
+}
@@ -0,0 +1,118 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! # Future Witness Version Descriptors
+//!
+//! Implementation of the `wother(version,hex)` descriptor, describing an output
+//! to a segwit version 2 or higher whose spending rules aren't defined yet.
+//! Such outputs have an address and a script pubkey, but can't be satisfied.
+//!
+
+use core::fmt;
+
+use bitcoin::hex::{DisplayHex, FromHex};
+use bitcoin::{Address, Network, ScriptBuf, WitnessProgram, WitnessVersion};
+
+use crate::descriptor::write_descriptor;
+use crate::expression::{self, FromTree};
+use crate::policy::{semantic, Liftable};
+use crate::prelude::*;
+use crate::{Error, MiniscriptKey};
+
+/// A descriptor for an output to a future witness version, i.e. v2 to v16.
+#[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Hash)]
+pub struct Wother {
+    /// the witness program, along with its version
+    program: WitnessProgram,
+}
+
+impl Wother {
+    /// Create a new future witness version descriptor
+    ///
+    /// Errors if the version is below 2, as v0 and v1 outputs have their own
+    /// descriptors, or if the program isn't between 2 and 40 bytes long.
+    pub fn new(version: WitnessVersion, program: &[u8]) -> Result<Self, Error> {
+        if version.to_num() < 2 {
+            return Err(Error::BadDescriptor(format!(
+                "witness version {} is not a future witness version",
+                version.to_num()
+            )));
+        }
+        let program = WitnessProgram::new(version, program)
+            .map_err(|e| Error::BadDescriptor(e.to_string()))?;
+        Ok(Self { program })
+    }
+
+    /// get the witness version
+    pub fn version(&self) -> WitnessVersion { self.program.version() }
+
+    /// get the witness program
+    pub fn program(&self) -> &[u8] { self.program.program().as_bytes() }
+
+    /// get the inner
+    pub fn as_inner(&self) -> &WitnessProgram { &self.program }
+
+    /// Checks whether the descriptor is safe.
+    ///
+    /// Outputs to future witness versions are standard, so this never errors.
+    pub fn sanity_check(&self) -> Result<(), Error> { Ok(()) }
+
+    /// Computes the size of the script pubkey without encoding it.
+    pub fn script_pubkey_size(&self) -> usize {
+        // OP_n <program>, programs being at most 40 bytes
+        2 + self.program().len()
+    }
+
+    /// Obtains the corresponding script pubkey for this descriptor.
+    pub fn script_pubkey(&self) -> ScriptBuf { ScriptBuf::new_witness_program(&self.program) }
+
+    /// Obtains the corresponding bech32m address for this descriptor.
+    pub fn address(&self, network: Network) -> Address {
+        Address::from_witness_program(self.program, network)
+    }
+}
+
+impl fmt::Debug for Wother {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "wother({},{})", self.version().to_num(), self.program().as_hex())
+    }
+}
+
+impl fmt::Display for Wother {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_descriptor!(f, "wother({},{})", self.version().to_num(), self.program().as_hex())
+    }
+}
+
+impl<Pk: MiniscriptKey> Liftable<Pk> for Wother {
+    fn lift(&self) -> Result<semantic::Policy<Pk>, Error> { Ok(semantic::Policy::Unsatisfiable) }
+}
+
+impl FromTree for Wother {
+    fn from_tree(top: &expression::Tree) -> Result<Self, Error> {
+        if top.name == "wother" && top.args.len() == 2 {
+            let version = expression::terminal(&top.args[0], expression::parse_num)?;
+            let version = u8::try_from(version)
+                .ok()
+                .and_then(|v| WitnessVersion::try_from(v).ok())
+                .ok_or_else(|| {
+                    Error::BadDescriptor(format!("invalid witness version {}", version))
+                })?;
+            let program = expression::terminal(&top.args[1], Vec::<u8>::from_hex)?;
+            Wother::new(version, &program)
+        } else {
+            Err(Error::Unexpected(format!(
+                "{}({} args) while parsing wother descriptor",
+                top.name,
+                top.args.len(),
+            )))
+        }
+    }
+}
+
+impl core::str::FromStr for Wother {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let top = expression::Tree::from_str(s)?;
+        Self::from_tree(&top)
+    }
+}
@@ -38,12 +38,6 @@ impl PartialEq for Tree<'_> {
     }
 }
 impl Eq for Tree<'_> {}
-// or_b(pk(A),pk(B))
-//
-// A = musig(musig(B,C),D,E)
-// or_b()
-// pk(A), pk(B)
-
 /// A trait for extracting a structure from a Tree representation in token form
 pub trait FromTree: Sized {
     /// Extract a structure from Tree representation
@@ -329,6 +323,19 @@ where
     }
 }
 
+/// The string of a key expression: either a terminal, or a BIP 390 `musig(KEY,KEY,...)`
+/// aggregate of terminals
+pub fn key_expression<'a>(term: &Tree<'a>) -> Result<Cow<'a, str>, Error> {
+    if term.args.is_empty() {
+        Ok(Cow::Borrowed(term.name))
+    } else if term.name == "musig" && term.args.iter().all(|arg| arg.args.is_empty()) {
+        let keys = term.args.iter().map(|arg| arg.name).collect::<Vec<_>>();
+        Ok(Cow::Owned(format!("musig({})", keys.join(","))))
+    } else {
+        Err(errstr(term.name))
+    }
+}
+
 /// Attempts to parse an expression with exactly one child
 pub fn unary<L, T, F>(term: &Tree, convert: F) -> Result<T, Error>
 where
@@ -0,0 +1,256 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! Accounting
+//!
+//! Maps the conditions satisfied by a spend back to the keys of the descriptor
+//! of the spent coin, to record who authorized the spend.
+//!
+
+use bitcoin::bip32::{DerivationPath, Fingerprint};
+use bitcoin::key::XOnlyPublicKey;
+use bitcoin::{absolute, relative, secp256k1, sighash, TxOut};
+
+use super::{Error, HashLockType, Interpreter, KeySigPair, SatisfiedConstraint};
+use crate::prelude::*;
+use crate::{DefiniteDescriptorKey, Descriptor, ForEachKey, ToPublicKey};
+
+/// A key of the descriptor which signed a spend
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Signer {
+    /// The key, as it appears in the descriptor
+    pub key: DefiniteDescriptorKey,
+    /// The fingerprint of the master key the key is derived from
+    pub master_fingerprint: Fingerprint,
+    /// The derivation path of the key from the master key, if it isn't multipath
+    pub derivation_path: Option<DerivationPath>,
+    /// Whether the key signed for a Taproot key spend, i.e. with the tweaked key
+    pub key_spend: bool,
+    /// The public key and signature found in the spend
+    pub key_sig: KeySigPair,
+}
+
+/// The conditions satisfied by a spend, with signatures mapped to the keys of the descriptor
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AccountingRecord {
+    /// The keys of the descriptor which signed, in the order their signatures were found
+    pub signers: Vec<Signer>,
+    /// Signatures by keys which aren't in the descriptor
+    pub unknown_signers: Vec<KeySigPair>,
+    /// The hash locks whose preimage was revealed, with the preimage
+    pub preimages: Vec<(HashLockType, [u8; 32])>,
+    /// The relative timelocks the spend relies on
+    pub relative_timelocks: Vec<relative::LockTime>,
+    /// The absolute timelocks the spend relies on
+    pub absolute_timelocks: Vec<absolute::LockTime>,
+}
+
+impl<'txin> Interpreter<'txin> {
+    /// Records the conditions satisfied by this spend, mapping the signing keys back to the
+    /// keys of `descriptor`, the descriptor of the spent coin derived at its index.
+    ///
+    /// Signatures are verified as by [`Interpreter::iter`], which is required to attribute the
+    /// signatures of a `multi` to the right keys. Signatures by keys not in `descriptor` are
+    /// listed as unknown.
+    pub fn accounting_record<C: secp256k1::Verification, T: Borrow<TxOut>>(
+        &self,
+        secp: &secp256k1::Secp256k1<C>,
+        tx: &'txin bitcoin::Transaction,
+        input_idx: usize,
+        prevouts: &sighash::Prevouts<T>,
+        descriptor: &Descriptor<DefiniteDescriptorKey>,
+    ) -> Result<AccountingRecord, Error> {
+        let mut keys = vec![];
+        descriptor.for_each_key(|key| {
+            keys.push(key.clone());
+            true
+        });
+        let output_key = match *descriptor {
+            Descriptor::Tr(ref tr) => {
+                Some((tr.spend_info().output_key().to_x_only_public_key(), tr.internal_key()))
+            }
+            Descriptor::RawTr(ref rawtr) => {
+                Some((rawtr.as_inner().to_x_only_pubkey(), rawtr.as_inner()))
+            }
+            _ => None,
+        };
+
+        let find_signer = |key_sig: &KeySigPair| {
+            let (key, key_spend) = match *key_sig {
+                KeySigPair::Ecdsa(ref pk, _) => {
+                    (keys.iter().find(|key| key.to_public_key() == *pk)?, false)
+                }
+                KeySigPair::Schnorr(ref pk, _) => match output_key {
+                    Some((output_key, internal_key)) if output_key == *pk => (internal_key, true),
+                    _ => (
+                        keys.iter()
+                            .find(|key| XOnlyPublicKey::from(key.to_public_key().inner) == *pk)?,
+                        false,
+                    ),
+                },
+            };
+            Some(Signer {
+                key: key.clone(),
+                master_fingerprint: key.master_fingerprint(),
+                derivation_path: key.full_derivation_path(),
+                key_spend,
+                key_sig: *key_sig,
+            })
+        };
+
+        let mut record = AccountingRecord::default();
+        for constraint in self.iter(secp, tx, input_idx, prevouts) {
+            match constraint? {
+                SatisfiedConstraint::PublicKey { key_sig }
+                | SatisfiedConstraint::PublicKeyHash { key_sig, .. } => match find_signer(&key_sig)
+                {
+                    Some(signer) => record.signers.push(signer),
+                    None => record.unknown_signers.push(key_sig),
+                },
+                SatisfiedConstraint::HashLock { hash, preimage } => {
+                    record.preimages.push((hash, preimage))
+                }
+                SatisfiedConstraint::RelativeTimelock { n } => record.relative_timelocks.push(n),
+                SatisfiedConstraint::AbsoluteTimelock { n } => record.absolute_timelocks.push(n),
+            }
+        }
+        Ok(record)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::str::FromStr;
+
+    use bitcoin::bip32::{ChildNumber, Xpriv, Xpub};
+    use bitcoin::key::{Keypair, TapTweak};
+    use bitcoin::secp256k1::{Message, Secp256k1};
+    use bitcoin::sighash::SighashCache;
+    use bitcoin::{Amount, Network, OutPoint, Sequence, Transaction, TxIn, Witness};
+
+    use super::*;
+    use crate::DescriptorPublicKey;
+
+    #[test]
+    fn accounting_record() {
+        let secp = Secp256k1::new();
+        let xprivs: Vec<_> = (1..=3u8)
+            .map(|i| Xpriv::new_master(Network::Bitcoin, &[i; 32]).unwrap())
+            .collect();
+        let xpub = |i: usize| {
+            format!("[{}]{}/0/*", xprivs[i].fingerprint(&secp), Xpub::from_priv(&secp, &xprivs[i]))
+        };
+        let secret_key = |i: usize| {
+            let path = [ChildNumber::from(0), ChildNumber::from(5)];
+            xprivs[i].derive_priv(&secp, &path).unwrap().private_key
+        };
+        let multi = format!("wsh(multi(2,{},{},{}))", xpub(0), xpub(1), xpub(2));
+        let derive = |desc: &str, index| {
+            Descriptor::<DescriptorPublicKey>::from_str(desc)
+                .unwrap()
+                .at_derivation_index(index)
+                .unwrap()
+        };
+        let spend = |desc: &Descriptor<DefiniteDescriptorKey>| {
+            let tx = Transaction {
+                version: bitcoin::transaction::Version::TWO,
+                lock_time: absolute::LockTime::ZERO,
+                input: vec![TxIn { previous_output: OutPoint::null(), ..TxIn::default() }],
+                output: vec![],
+            };
+            let prevout =
+                TxOut { value: Amount::from_sat(10_000), script_pubkey: desc.script_pubkey() };
+            (tx, prevout)
+        };
+
+        // Keys A and C sign
+        let desc = derive(&multi, 5);
+        let mut keys = vec![];
+        desc.for_each_key(|key| {
+            keys.push(key.clone());
+            true
+        });
+        let (tx, prevout) = spend(&desc);
+        let sighash = SighashCache::new(&tx)
+            .p2wsh_signature_hash(
+                0,
+                &desc.explicit_script().unwrap(),
+                prevout.value,
+                bitcoin::EcdsaSighashType::All,
+            )
+            .unwrap();
+        let msg = Message::from(sighash);
+        let sign = |i: usize| {
+            bitcoin::ecdsa::Signature::sighash_all(secp.sign_ecdsa(&msg, &secret_key(i)))
+        };
+        let mut sigs = BTreeMap::new();
+        sigs.insert(keys[0].clone(), sign(0));
+        sigs.insert(keys[2].clone(), sign(2));
+        let (witness, script_sig) = desc.get_satisfaction(&sigs).unwrap();
+        let witness = Witness::from_slice(&witness);
+        let interpreter = Interpreter::from_txdata(
+            &prevout.script_pubkey,
+            &script_sig,
+            &witness,
+            Sequence::ZERO,
+            absolute::LockTime::ZERO,
+        )
+        .unwrap();
+        let prevouts = sighash::Prevouts::All(core::slice::from_ref(&prevout));
+
+        let record = interpreter
+            .accounting_record(&secp, &tx, 0, &prevouts, &desc)
+            .unwrap();
+        assert!(record.unknown_signers.is_empty());
+        assert_eq!(record.signers.len(), 2);
+        let mut signed: Vec<_> = record
+            .signers
+            .iter()
+            .map(|signer| keys.iter().position(|key| *key == signer.key).unwrap())
+            .collect();
+        signed.sort();
+        assert_eq!(signed, vec![0, 2]);
+        for signer in &record.signers {
+            let i = keys.iter().position(|key| *key == signer.key).unwrap();
+            assert_eq!(signer.master_fingerprint, xprivs[i].fingerprint(&secp));
+            assert_eq!(signer.derivation_path, Some(DerivationPath::from_str("m/0/5").unwrap()));
+            assert_eq!(signer.key_sig, KeySigPair::Ecdsa(keys[i].to_public_key(), sign(i)));
+            assert!(!signer.key_spend);
+        }
+
+        // Against the descriptor at another index, the signers are unknown
+        let record = interpreter
+            .accounting_record(&secp, &tx, 0, &prevouts, &derive(&multi, 6))
+            .unwrap();
+        assert!(record.signers.is_empty());
+        assert_eq!(record.unknown_signers.len(), 2);
+
+        // Taproot key spends are mapped to the internal key
+        let tr = derive(&format!("tr({})", xpub(1)), 5);
+        let (tx, prevout) = spend(&tr);
+        let prevouts = sighash::Prevouts::All(core::slice::from_ref(&prevout));
+        let sighash = SighashCache::new(&tx)
+            .taproot_key_spend_signature_hash(0, &prevouts, bitcoin::TapSighashType::Default)
+            .unwrap();
+        let keypair = Keypair::from_secret_key(&secp, &secret_key(1)).tap_tweak(&secp, None);
+        let sig = secp.sign_schnorr_no_aux_rand(&Message::from(sighash), &keypair.to_keypair());
+        let witness = Witness::from_slice(&[sig.as_ref()]);
+        let script_sig = bitcoin::ScriptBuf::new();
+        let interpreter = Interpreter::from_txdata(
+            &prevout.script_pubkey,
+            &script_sig,
+            &witness,
+            Sequence::ZERO,
+            absolute::LockTime::ZERO,
+        )
+        .unwrap();
+        let record = interpreter
+            .accounting_record(&secp, &tx, 0, &prevouts, &tr)
+            .unwrap();
+        assert_eq!(record.signers.len(), 1);
+        match tr {
+            Descriptor::Tr(ref tr) => assert_eq!(record.signers[0].key, *tr.internal_key()),
+            _ => unreachable!(),
+        }
+        assert!(record.signers[0].key_spend);
+    }
+}
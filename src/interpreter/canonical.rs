@@ -0,0 +1,248 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! Witness Canonicality
+//!
+//! Checks of witnesses produced by other software against the Miniscript they
+//! satisfy, to detect elements which a third party could change without
+//! invalidating the transaction before it is broadcast.
+//!
+
+use core::{convert, fmt};
+
+use bitcoin::hashes::{hash160, ripemd160, sha256};
+
+use super::stack::{Element, Stack};
+use super::{BitcoinKey, Error, Iter, KeySigPair, NodeEvaluationState, TraceElement};
+use crate::miniscript::context::{NoChecks, SigType};
+use crate::prelude::*;
+use crate::{hash256, Miniscript, ScriptContext, ToPublicKey, Translator};
+
+/// A reason for a witness not to be the canonical satisfaction of a Miniscript
+#[derive(Debug)]
+pub enum WitnessIssue {
+    /// The witness element at this index, from the bottom of the stack, is a
+    /// single byte other than `0x01`, i.e. a non-minimal boolean
+    NonMinimalBoolean(usize),
+    /// The ECDSA signature for this key has a high S value, which anyone can
+    /// flip to the equivalent low S value
+    HighS(bitcoin::PublicKey),
+    /// A hash fragment is dissatisfied by a non-preimage, which anyone can
+    /// replace with another one
+    MalleableDissatisfaction(String),
+    /// The witness has this many elements left once the Miniscript is satisfied
+    UnnecessaryElements(usize),
+    /// The witness doesn't satisfy the Miniscript
+    Unsatisfied(Error),
+}
+
+impl fmt::Display for WitnessIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            WitnessIssue::NonMinimalBoolean(index) => {
+                write!(f, "witness element {} is a non-minimal boolean", index)
+            }
+            WitnessIssue::HighS(ref pk) => write!(f, "signature for {} has a high S value", pk),
+            WitnessIssue::MalleableDissatisfaction(ref fragment) => {
+                write!(f, "{} is dissatisfied by a non-preimage", fragment)
+            }
+            WitnessIssue::UnnecessaryElements(n) => {
+                write!(f, "{} witness elements are left once satisfied", n)
+            }
+            WitnessIssue::Unsatisfied(ref e) => write!(f, "witness doesn't satisfy: {}", e),
+        }
+    }
+}
+
+/// Translates keys to the keys of the Miniscript evaluated by the interpreter
+struct ToBitcoinKey(SigType);
+
+impl<Pk: ToPublicKey> Translator<Pk> for ToBitcoinKey {
+    type TargetPk = BitcoinKey;
+    type Error = convert::Infallible;
+
+    fn pk(&mut self, pk: &Pk) -> Result<BitcoinKey, Self::Error> {
+        Ok(match self.0 {
+            SigType::Ecdsa => BitcoinKey::Fullkey(pk.to_public_key()),
+            SigType::Schnorr => BitcoinKey::XOnlyPublicKey(pk.to_x_only_pubkey()),
+        })
+    }
+
+    fn sha256(&mut self, sha256: &Pk::Sha256) -> Result<sha256::Hash, Self::Error> {
+        Ok(Pk::to_sha256(sha256))
+    }
+
+    fn hash256(&mut self, hash256: &Pk::Hash256) -> Result<hash256::Hash, Self::Error> {
+        Ok(Pk::to_hash256(hash256))
+    }
+
+    fn ripemd160(&mut self, ripemd160: &Pk::Ripemd160) -> Result<ripemd160::Hash, Self::Error> {
+        Ok(Pk::to_ripemd160(ripemd160))
+    }
+
+    fn hash160(&mut self, hash160: &Pk::Hash160) -> Result<hash160::Hash, Self::Error> {
+        Ok(Pk::to_hash160(hash160))
+    }
+}
+
+impl<Pk: ToPublicKey, Ctx: ScriptContext> Miniscript<Pk, Ctx> {
+    /// Checks that `witness`, the witness stack satisfying this Miniscript
+    /// without the script itself, is canonical and can't be malleated
+    ///
+    /// This is meant to be run on witnesses produced by other software before
+    /// broadcasting a transaction they are part of. Signatures are parsed but
+    /// not verified, and timelocks are assumed to be satisfied by the
+    /// transaction.
+    ///
+    /// # Errors
+    ///
+    /// All the issues found. The evaluation of the witness stops at the first
+    /// [`WitnessIssue::Unsatisfied`] issue.
+    pub fn check_witness_canonical(&self, witness: &[Vec<u8>]) -> Result<(), Vec<WitnessIssue>> {
+        let mut issues: Vec<_> = witness
+            .iter()
+            .enumerate()
+            .filter(|(_, elem)| elem.len() == 1 && elem[0] != 1)
+            .map(|(index, _)| WitnessIssue::NonMinimalBoolean(index))
+            .collect();
+
+        let sig_type = Ctx::sig_type();
+        let ms: Miniscript<BitcoinKey, NoChecks> = self
+            .translate_pk_ctx(&mut ToBitcoinKey(sig_type))
+            .expect("translation to the same keys in a context without checks");
+
+        let mut high_s = vec![];
+        let mut iter = Iter {
+            verify_sig: Box::new(|key_sig: &KeySigPair| {
+                if let KeySigPair::Ecdsa(pk, sig) = *key_sig {
+                    let mut low_s = sig.signature;
+                    low_s.normalize_s();
+                    if low_s != sig.signature {
+                        high_s.push(pk);
+                    }
+                }
+                true
+            }),
+            public_key: None,
+            state: vec![NodeEvaluationState { node: &ms, n_evaluated: 0, n_satisfied: 0 }],
+            stack: Stack::from(witness.iter().map(Element::from).collect::<Vec<_>>()),
+            sequence: bitcoin::Sequence::ZERO,
+            lock_time: bitcoin::absolute::LockTime::ZERO,
+            has_errored: false,
+            sig_type,
+            trace: None,
+            assume_timelocks: true,
+        }
+        .with_trace();
+
+        let mut error = None;
+        for res in iter.by_ref() {
+            if let Err(e) = res {
+                error = Some(e);
+            }
+        }
+        let remaining = iter.stack.len();
+        let trace = iter.trace.take().unwrap_or_default();
+        drop(iter);
+
+        issues.extend(high_s.into_iter().map(WitnessIssue::HighS));
+        issues.extend(
+            trace
+                .into_iter()
+                .filter(|step| {
+                    let is_hash = ["sha256(", "hash256(", "ripemd160(", "hash160("]
+                        .iter()
+                        .any(|prefix| step.fragment.starts_with(prefix));
+                    is_hash && step.produced().eq(&[TraceElement::Dissatisfied])
+                })
+                .map(|step| WitnessIssue::MalleableDissatisfaction(step.fragment)),
+        );
+        match error {
+            Some(Error::ScriptSatisfactionError) if remaining > 0 => {
+                issues.push(WitnessIssue::UnnecessaryElements(remaining))
+            }
+            Some(e) => issues.push(WitnessIssue::Unsatisfied(e)),
+            None => {}
+        }
+
+        if issues.is_empty() {
+            Ok(())
+        } else {
+            Err(issues)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::hashes::Hash;
+    use bitcoin::secp256k1::{self, Message, Secp256k1, SecretKey};
+
+    use super::*;
+    use crate::{ExtParams, Segwitv0};
+
+    /// The order of the secp256k1 group
+    const ORDER: [u8; 32] = [
+        0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+        0xfe, 0xba, 0xae, 0xdc, 0xe6, 0xaf, 0x48, 0xa0, 0x3b, 0xbf, 0xd2, 0x5e, 0x8c, 0xd0, 0x36,
+        0x41, 0x41,
+    ];
+
+    #[test]
+    fn check_witness_canonical() {
+        let secp = Secp256k1::new();
+        let sk = SecretKey::from_slice(&[1; 32]).unwrap();
+        let pk = bitcoin::PublicKey::new(sk.public_key(&secp));
+        let preimage = [0xab; 32];
+        let hash = sha256::Hash::hash(&preimage);
+        // Malleable, as a third party can dissatisfy the hash
+        let ms = Miniscript::<bitcoin::PublicKey, Segwitv0>::from_str_ext(
+            &format!("and_v(v:pk({}),or_d(sha256({}),older(10)))", pk, hash),
+            &ExtParams::insane(),
+        )
+        .unwrap();
+
+        let signature = secp.sign_ecdsa(&Message::from_digest([2; 32]), &sk);
+        let sig = bitcoin::ecdsa::Signature::sighash_all(signature).to_vec();
+        let check = |witness: &[Vec<u8>]| match ms.check_witness_canonical(witness) {
+            Ok(()) => vec![],
+            Err(issues) => issues.iter().map(ToString::to_string).collect(),
+        };
+
+        assert!(check(&[preimage.to_vec(), sig.clone()]).is_empty());
+
+        // Dissatisfying the hash is malleable, even though timelocks are assumed satisfied
+        assert_eq!(
+            check(&[vec![0; 32], sig.clone()]),
+            vec![format!(
+                "sha256({}) is dissatisfied by a non-preimage",
+                hash
+            )]
+        );
+
+        // Extra elements below the satisfaction
+        assert_eq!(
+            check(&[vec![], preimage.to_vec(), sig.clone()]),
+            vec!["1 witness elements are left once satisfied"]
+        );
+
+        // The same signature with a high S value
+        let mut compact = signature.serialize_compact();
+        let mut borrow = 0;
+        for i in (0..32).rev() {
+            let diff = i16::from(ORDER[i]) - i16::from(compact[32 + i]) - borrow;
+            compact[32 + i] = diff.rem_euclid(256) as u8;
+            borrow = i16::from(diff < 0);
+        }
+        let high_s = secp256k1::ecdsa::Signature::from_compact(&compact).unwrap();
+        let high_s = bitcoin::ecdsa::Signature::sighash_all(high_s).to_vec();
+        assert_eq!(
+            check(&[preimage.to_vec(), high_s]),
+            vec![format!("signature for {} has a high S value", pk)]
+        );
+
+        // A non-minimal boolean where a signature is expected
+        let issues = check(&[preimage.to_vec(), vec![0x81]]);
+        assert_eq!(issues[0], "witness element 1 is a non-minimal boolean");
+        assert_eq!(issues.len(), 2);
+    }
+}
@@ -7,6 +7,12 @@
 //! iterate over the set of conditions satisfied by a spending transaction,
 //! assuming that the spent coin was descriptor controlled.
 //!
+//! The interpreter only parses scripts and evaluates their structure, and is
+//! available without the `std` feature. Signatures are checked by a
+//! [`SigVerifier`], so that a signing device may use its own verification
+//! routines, or none at all when it only needs to know which spending path a
+//! transaction takes before displaying it for confirmation.
+//!
 
 use core::fmt;
 use core::str::FromStr;
@@ -17,12 +23,16 @@ use bitcoin::{absolute, relative, secp256k1, sighash, taproot, Sequence, TxOut,
 use crate::miniscript::context::{NoChecks, SigType};
 use crate::miniscript::ScriptContext;
 use crate::prelude::*;
-use crate::{hash256, Descriptor, Miniscript, Terminal, ToPublicKey};
+use crate::{hash256, Descriptor, Miniscript, PkhResolver, Terminal, ToPublicKey};
 
+mod accounting;
+mod canonical;
 mod error;
 mod inner;
 mod stack;
 
+pub use self::accounting::{AccountingRecord, Signer};
+pub use self::canonical::WitnessIssue;
 pub use self::error::Error;
 use self::error::PkEvalErrInner;
 use self::stack::Stack;
@@ -39,6 +49,27 @@ pub struct Interpreter<'txin> {
     lock_time: absolute::LockTime,
 }
 
+/// A check of the signatures encountered while interpreting a spend
+///
+/// Implemented for closures taking a [`KeySigPair`], and by [`AssumeValidSigs`].
+pub trait SigVerifier {
+    /// Whether `sig` is a valid signature by its key over the spending transaction.
+    fn verify_sig(&mut self, sig: &KeySigPair) -> bool;
+}
+
+impl<F: FnMut(&KeySigPair) -> bool> SigVerifier for F {
+    fn verify_sig(&mut self, sig: &KeySigPair) -> bool { self(sig) }
+}
+
+/// A [`SigVerifier`] accepting every signature, to find the spending conditions
+/// a transaction satisfies without verifying it
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct AssumeValidSigs;
+
+impl SigVerifier for AssumeValidSigs {
+    fn verify_sig(&mut self, _: &KeySigPair) -> bool { true }
+}
+
 // A type representing functions for checking signatures that accept both
 // Ecdsa and Schnorr signatures
 
@@ -69,6 +100,91 @@ impl KeySigPair {
             KeySigPair::Schnorr(pk, sig) => Some((*pk, *sig)),
         }
     }
+
+    /// The sighash flag the signature commits to the transaction with
+    pub fn sighash_flag(&self) -> SighashFlag {
+        match self {
+            KeySigPair::Ecdsa(_, sig) => SighashFlag::Ecdsa(sig.sighash_type),
+            KeySigPair::Schnorr(_, sig) => SighashFlag::Schnorr(sig.sighash_type),
+        }
+    }
+}
+
+/// The sighash flag of a signature found in a spend
+///
+/// Signatures which don't commit to the whole transaction, i.e. use `NONE`,
+/// `SINGLE` or `ANYONECANPAY`, let others change parts of the spending
+/// transaction without invalidating them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SighashFlag {
+    /// The sighash type of an Ecdsa signature
+    Ecdsa(sighash::EcdsaSighashType),
+    /// The sighash type of a Schnorr signature, `Default` if the signature omits it
+    Schnorr(sighash::TapSighashType),
+}
+
+impl SighashFlag {
+    /// Whether the signature commits to all the inputs and outputs, i.e. its
+    /// flag is `ALL`, or `DEFAULT` for Schnorr signatures
+    pub fn is_all(&self) -> bool {
+        matches!(
+            self,
+            SighashFlag::Ecdsa(sighash::EcdsaSighashType::All)
+                | SighashFlag::Schnorr(sighash::TapSighashType::Default)
+                | SighashFlag::Schnorr(sighash::TapSighashType::All)
+        )
+    }
+
+    /// Whether the signature only commits to its own input (`ANYONECANPAY`)
+    pub fn anyone_can_pay(&self) -> bool {
+        use sighash::{EcdsaSighashType as E, TapSighashType as T};
+
+        matches!(
+            self,
+            SighashFlag::Ecdsa(E::AllPlusAnyoneCanPay)
+                | SighashFlag::Ecdsa(E::NonePlusAnyoneCanPay)
+                | SighashFlag::Ecdsa(E::SinglePlusAnyoneCanPay)
+                | SighashFlag::Schnorr(T::AllPlusAnyoneCanPay)
+                | SighashFlag::Schnorr(T::NonePlusAnyoneCanPay)
+                | SighashFlag::Schnorr(T::SinglePlusAnyoneCanPay)
+        )
+    }
+
+    /// Whether the signature only commits to the output at the index of its
+    /// input (`SINGLE`)
+    pub fn is_single(&self) -> bool {
+        use sighash::{EcdsaSighashType as E, TapSighashType as T};
+
+        matches!(
+            self,
+            SighashFlag::Ecdsa(E::Single)
+                | SighashFlag::Ecdsa(E::SinglePlusAnyoneCanPay)
+                | SighashFlag::Schnorr(T::Single)
+                | SighashFlag::Schnorr(T::SinglePlusAnyoneCanPay)
+        )
+    }
+
+    /// Whether the signature commits to none of the outputs (`NONE`)
+    pub fn is_none(&self) -> bool {
+        use sighash::{EcdsaSighashType as E, TapSighashType as T};
+
+        matches!(
+            self,
+            SighashFlag::Ecdsa(E::None)
+                | SighashFlag::Ecdsa(E::NonePlusAnyoneCanPay)
+                | SighashFlag::Schnorr(T::None)
+                | SighashFlag::Schnorr(T::NonePlusAnyoneCanPay)
+        )
+    }
+}
+
+impl fmt::Display for SighashFlag {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SighashFlag::Ecdsa(ty) => ty.fmt(f),
+            SighashFlag::Schnorr(ty) => ty.fmt(f),
+        }
+    }
 }
 
 // Internally used enum for different types of bitcoin keys
@@ -124,10 +240,10 @@ impl MiniscriptKey for BitcoinKey {
     type Ripemd160 = ripemd160::Hash;
     type Hash160 = hash160::Hash;
 
-    fn is_uncompressed(&self) -> bool {
+    fn uncompressed_key(&self) -> Option<bitcoin::PublicKey> {
         match *self {
-            BitcoinKey::Fullkey(pk) => !pk.compressed,
-            BitcoinKey::XOnlyPublicKey(_) => false,
+            BitcoinKey::Fullkey(pk) => pk.uncompressed_key(),
+            BitcoinKey::XOnlyPublicKey(_) => None,
         }
     }
 }
@@ -176,6 +292,8 @@ impl<'txin> Interpreter<'txin> {
             lock_time: self.lock_time,
             has_errored: false,
             sig_type: self.sig_type(),
+            trace: None,
+            assume_timelocks: false,
         }
     }
 
@@ -312,7 +430,15 @@ impl<'txin> Interpreter<'txin> {
 
     /// Creates an iterator over the satisfied spending conditions without checking signatures
     pub fn iter_assume_sigs<'iter>(&'iter self) -> Iter<'txin, 'iter> {
-        self.iter_custom(Box::new(|_| true))
+        self.iter_with(AssumeValidSigs)
+    }
+
+    /// Same as [`Interpreter::iter`], but checks signatures with `verifier`
+    pub fn iter_with<'iter, V: SigVerifier + 'iter>(
+        &'iter self,
+        mut verifier: V,
+    ) -> Iter<'txin, 'iter> {
+        self.iter_custom(Box::new(move |sig| verifier.verify_sig(sig)))
     }
 
     /// Outputs a "descriptor" string which reproduces the spent coins
@@ -338,8 +464,7 @@ impl<'txin> Interpreter<'txin> {
                 // In tr descriptors, normally the internal key is represented inside the tr part
                 // But there is no way to infer the internal key from output descriptor status
                 // instead we infer a rawtr.
-                // Note that rawtr is parsing is currently not supported.
-                format!("rawtr_not_supported_yet({})", pk)
+                format!("rawtr({})", pk)
             }
             inner::Inner::Script(ref ms, inner::ScriptType::Bare) => format!("{}", ms),
             inner::Inner::Script(ref ms, inner::ScriptType::Sh) => format!("sh({})", ms),
@@ -433,6 +558,32 @@ impl<'txin> Interpreter<'txin> {
         }
     }
 
+    /// Substitutes the hashes of the keys of `pkh` fragments of the script with
+    /// the public keys resolved by `resolver`
+    ///
+    /// Scripts only contain the hash of those keys, so the inferred descriptor
+    /// would otherwise use the `expr_raw_pkh` extension, which isn't parsed as a
+    /// descriptor. Keys not matching their hash in the context of the spend are
+    /// ignored. Interpreting the script yields the same constraints either way.
+    pub fn resolve_raw_pkh<Pk, R>(&mut self, resolver: &R)
+    where
+        Pk: MiniscriptKey + ToPublicKey,
+        R: PkhResolver<Pk>,
+    {
+        let sig_type = self.sig_type();
+        if let inner::Inner::Script(ref mut ms, _) = self.inner {
+            let resolve = |hash: &hash160::Hash| {
+                let pk = resolver.resolve_pkh(hash)?;
+                let key = match sig_type {
+                    SigType::Ecdsa => BitcoinKey::Fullkey(pk.to_public_key()),
+                    SigType::Schnorr => BitcoinKey::XOnlyPublicKey(pk.to_x_only_pubkey()),
+                };
+                Some(key).filter(|key| key.to_pubkeyhash(sig_type) == *hash)
+            };
+            *ms = ms.resolve_raw_pkh(&resolve);
+        }
+    }
+
     /// Outputs a "descriptor" which reproduces the spent coins
     ///
     /// This may not represent the original descriptor used to produce the transaction,
@@ -493,6 +644,113 @@ pub enum SatisfiedConstraint {
     },
 }
 
+impl SatisfiedConstraint {
+    /// The sighash flag of the signature satisfying the constraint, if it is
+    /// satisfied by a signature
+    pub fn sighash_flag(&self) -> Option<SighashFlag> {
+        match self {
+            SatisfiedConstraint::PublicKey { key_sig }
+            | SatisfiedConstraint::PublicKeyHash { key_sig, .. } => Some(key_sig.sighash_flag()),
+            SatisfiedConstraint::HashLock { .. }
+            | SatisfiedConstraint::RelativeTimelock { .. }
+            | SatisfiedConstraint::AbsoluteTimelock { .. } => None,
+        }
+    }
+
+    /// Whether the constraint is satisfied by a signature which doesn't commit to
+    /// the whole transaction, see [`SighashFlag::is_all`]
+    pub fn has_partial_sighash(&self) -> bool {
+        self.sighash_flag().map_or(false, |flag| !flag.is_all())
+    }
+}
+
+/// A witness stack element, as recorded in a [`TraceStep`]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum TraceElement {
+    /// Result of a satisfied fragment, or `OP_1` in the witness
+    Satisfied,
+    /// Result of a dissatisfied fragment, or an empty witness element
+    Dissatisfied,
+    /// Any other witness element
+    Push(Vec<u8>),
+}
+
+impl From<&stack::Element<'_>> for TraceElement {
+    fn from(elem: &stack::Element<'_>) -> Self {
+        match *elem {
+            stack::Element::Satisfied => TraceElement::Satisfied,
+            stack::Element::Dissatisfied => TraceElement::Dissatisfied,
+            stack::Element::Push(data) => TraceElement::Push(data.to_vec()),
+        }
+    }
+}
+
+/// An operation on the witness stack, as recorded in a [`TraceStep`]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum TraceOperation {
+    /// An element was popped from the stack
+    Pop(TraceElement),
+    /// An element was pushed onto the stack
+    Push(TraceElement),
+}
+
+impl From<&stack::StackOperation<'_>> for TraceOperation {
+    fn from(op: &stack::StackOperation<'_>) -> Self {
+        match *op {
+            stack::StackOperation::Pop(ref elem) => TraceOperation::Pop(elem.into()),
+            stack::StackOperation::Push(ref elem) => TraceOperation::Push(elem.into()),
+        }
+    }
+}
+
+/// The outcome of a [`TraceStep`]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum TraceResult {
+    /// The evaluation continues, the step's effect is reflected on the stack
+    Continue,
+    /// The step satisfied a constraint
+    Constraint(SatisfiedConstraint),
+    /// The step failed; the error is returned by the iterator
+    Failed,
+}
+
+/// A single evaluation step of a Miniscript fragment, recorded when tracing is enabled
+///
+/// Fragments with children are visited several times: before their children are evaluated,
+/// and again afterwards to combine the results the children left on the stack.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct TraceStep {
+    /// The fragment being evaluated
+    pub fragment: String,
+    /// The number of children of the fragment already evaluated before this step
+    pub n_evaluated: usize,
+    /// The operations of this step on the stack, in the order they happened
+    ///
+    /// An element popped and pushed back, e.g. the dissatisfaction of a key, is both popped
+    /// and pushed.
+    pub operations: Vec<TraceOperation>,
+    /// The outcome of this step
+    pub result: TraceResult,
+}
+
+impl TraceStep {
+    /// The stack elements popped by this step, in the order they were popped
+    pub fn consumed(&self) -> impl Iterator<Item = &TraceElement> {
+        self.operations.iter().filter_map(|op| match op {
+            TraceOperation::Pop(elem) => Some(elem),
+            TraceOperation::Push(_) => None,
+        })
+    }
+
+    /// The stack elements pushed by this step, in the order they were pushed
+    pub fn produced(&self) -> impl Iterator<Item = &TraceElement> {
+        self.operations.iter().filter_map(|op| match op {
+            TraceOperation::Push(elem) => Some(elem),
+            TraceOperation::Pop(_) => None,
+        })
+    }
+}
+
 ///This is used by the interpreter to know which evaluation state a AstemElem is.
 ///This is required because whenever a same node(for eg. OrB) appears on the stack, we don't
 ///know if the left child has been evaluated or not. And based on the result on
@@ -528,6 +786,10 @@ pub struct Iter<'intp, 'txin: 'intp> {
     lock_time: absolute::LockTime,
     has_errored: bool,
     sig_type: SigType,
+    trace: Option<Vec<TraceStep>>,
+    /// Whether `after` and `older` fragments are satisfied regardless of the
+    /// transaction's locktime and sequence
+    assume_timelocks: bool,
 }
 
 ///Iterator for Iter
@@ -543,6 +805,7 @@ where
             None
         } else {
             let res = self.iter_next();
+            self.end_trace_step(res.as_ref());
             if let Some(Err(_)) = res {
                 self.has_errored = true;
             }
@@ -555,6 +818,21 @@ impl<'intp, 'txin: 'intp> Iter<'intp, 'txin>
 where
     NoChecks: ScriptContext,
 {
+    /// Record every evaluation step performed by this iterator
+    ///
+    /// The recorded steps can be retrieved with [`Iter::trace`], which is useful to find out
+    /// why a witness doesn't satisfy a script.
+    pub fn with_trace(mut self) -> Self {
+        self.trace = Some(vec![]);
+        self
+    }
+
+    /// The evaluation steps performed so far, if tracing was enabled with [`Iter::with_trace`]
+    ///
+    /// Note that the check of the signature for a bare public key (as in `pkh`, `wpkh` or a
+    /// taproot key spend) isn't a Miniscript fragment and isn't recorded.
+    pub fn trace(&self) -> Option<&[TraceStep]> { self.trace.as_deref() }
+
     /// Helper function to push a NodeEvaluationState on state stack
     fn push_evaluation_state(
         &mut self,
@@ -570,6 +848,7 @@ where
     fn iter_next(&mut self) -> Option<Result<SatisfiedConstraint, Error>> {
         while let Some(node_state) = self.state.pop() {
             //non-empty stack
+            self.begin_trace_step(&node_state);
             match node_state.node.node {
                 Terminal::True => {
                     debug_assert_eq!(node_state.n_evaluated, 0);
@@ -611,6 +890,14 @@ where
                         return res;
                     }
                 }
+                Terminal::After(ref n) if self.assume_timelocks => {
+                    self.stack.push(stack::Element::Satisfied);
+                    return Some(Ok(SatisfiedConstraint::AbsoluteTimelock { n: (*n).into() }));
+                }
+                Terminal::Older(ref n) if self.assume_timelocks => {
+                    self.stack.push(stack::Element::Satisfied);
+                    return Some(Ok(SatisfiedConstraint::RelativeTimelock { n: (*n).into() }));
+                }
                 Terminal::After(ref n) => {
                     debug_assert_eq!(node_state.n_evaluated, 0);
                     debug_assert_eq!(node_state.n_satisfied, 0);
@@ -990,6 +1277,7 @@ where
                 //type checked Miniscript
                 _ => return Some(Err(Error::CouldNotEvaluate)),
             };
+            self.end_trace_step(None);
         }
 
         //state empty implies that either the execution has terminated or we have a
@@ -1018,6 +1306,35 @@ where
             }
         }
     }
+
+    /// Start recording the evaluation of `node_state` in the trace, if tracing is enabled
+    fn begin_trace_step(&mut self, node_state: &NodeEvaluationState<'intp>) {
+        if let Some(ref mut trace) = self.trace {
+            trace.push(TraceStep {
+                fragment: node_state.node.to_string(),
+                n_evaluated: node_state.n_evaluated,
+                operations: vec![],
+                result: TraceResult::Continue,
+            });
+            self.stack.start_recording();
+        }
+    }
+
+    /// Finish recording the current step, if any, given what the iterator yields after it
+    fn end_trace_step(&mut self, res: Option<&Result<SatisfiedConstraint, Error>>) {
+        let operations = match self.stack.stop_recording() {
+            Some(ops) => ops,
+            None => return,
+        };
+        if let Some(step) = self.trace.as_mut().and_then(|trace| trace.last_mut()) {
+            step.operations = operations.iter().map(TraceOperation::from).collect();
+            step.result = match res {
+                None => TraceResult::Continue,
+                Some(Ok(constraint)) => TraceResult::Constraint(*constraint),
+                Some(Err(_)) => TraceResult::Failed,
+            };
+        }
+    }
 }
 
 /// Helper function to verify serialized signature
@@ -1114,6 +1431,112 @@ mod tests {
         (pks, der_sigs, ecdsa_sigs, msg, secp, x_only_pks, schnorr_sigs, ser_schnorr_sigs)
     }
 
+    #[test]
+    fn sig_verifiers() {
+        let (pks, der_sigs, ecdsa_sigs, ..) = setup_keys_sigs(1);
+        let spk = bitcoin::script::Builder::new()
+            .push_key(&pks[0])
+            .push_opcode(bitcoin::opcodes::all::OP_CHECKSIG)
+            .into_script();
+        let sig = bitcoin::script::PushBytesBuf::try_from(der_sigs[0].clone()).unwrap();
+        let script_sig = bitcoin::script::Builder::new()
+            .push_slice(sig)
+            .into_script();
+        let witness = Witness::default();
+        let interpreter = Interpreter::from_txdata(
+            &spk,
+            &script_sig,
+            &witness,
+            Sequence::MAX,
+            absolute::LockTime::ZERO,
+        )
+        .unwrap();
+
+        let expected =
+            SatisfiedConstraint::PublicKey { key_sig: KeySigPair::Ecdsa(pks[0], ecdsa_sigs[0]) };
+        let satisfied: Result<Vec<_>, _> = interpreter.iter_with(AssumeValidSigs).collect();
+        assert_eq!(satisfied.unwrap(), vec![expected]);
+        assert!(interpreter
+            .iter_with(|_: &KeySigPair| false)
+            .all(|res| res.is_err()));
+
+        struct Recorder(Vec<KeySigPair>);
+        impl SigVerifier for &mut Recorder {
+            fn verify_sig(&mut self, sig: &KeySigPair) -> bool {
+                self.0.push(*sig);
+                true
+            }
+        }
+        let mut recorder = Recorder(vec![]);
+        assert_eq!(interpreter.iter_with(&mut recorder).count(), 1);
+        assert_eq!(recorder.0, vec![KeySigPair::Ecdsa(pks[0], ecdsa_sigs[0])]);
+    }
+
+    #[test]
+    fn resolve_raw_pkh() {
+        let (pks, _der_sigs, ecdsa_sigs, ..) = setup_keys_sigs(2);
+        let desc = Descriptor::<bitcoin::PublicKey>::from_str(&format!(
+            "wsh(and_v(v:pk({}),pkh({})))",
+            pks[0], pks[1]
+        ))
+        .unwrap();
+        let mut sigs = BTreeMap::new();
+        sigs.insert(pks[0], ecdsa_sigs[0]);
+        sigs.insert(pks[1], ecdsa_sigs[1]);
+        let (witness, script_sig) = desc.get_satisfaction(&sigs).unwrap();
+        let witness = Witness::from_slice(&witness);
+        let mut interpreter = Interpreter::from_txdata(
+            &desc.script_pubkey(),
+            &script_sig,
+            &witness,
+            Sequence::MAX,
+            absolute::LockTime::ZERO,
+        )
+        .unwrap();
+        // The script only contains the hash of the key of the pkh() fragment
+        assert!(interpreter.inferred_descriptor().is_err());
+        let constraints: Vec<_> = interpreter
+            .iter_assume_sigs()
+            .collect::<Result<_, _>>()
+            .unwrap();
+
+        interpreter.resolve_raw_pkh(&desc.pkh_resolver());
+        assert_eq!(interpreter.inferred_descriptor().unwrap(), desc);
+        assert_eq!(
+            interpreter
+                .iter_assume_sigs()
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap(),
+            constraints
+        );
+        assert_eq!(
+            constraints[1],
+            SatisfiedConstraint::PublicKeyHash {
+                keyhash: pks[1].pubkey_hash().to_raw_hash(),
+                key_sig: KeySigPair::Ecdsa(pks[1], ecdsa_sigs[1]),
+            }
+        );
+    }
+
+    fn from_stack<'txin, 'elem>(
+        verify_fn: Box<dyn FnMut(&KeySigPair) -> bool + 'elem>,
+        stack: Stack<'txin>,
+        ms: &'elem Miniscript<BitcoinKey, NoChecks>,
+    ) -> Iter<'elem, 'txin> {
+        Iter {
+            verify_sig: verify_fn,
+            stack,
+            public_key: None,
+            state: vec![NodeEvaluationState { node: ms, n_evaluated: 0, n_satisfied: 0 }],
+            sequence: Sequence::from_height(1002),
+            lock_time: absolute::LockTime::from_height(1002).unwrap(),
+            has_errored: false,
+            sig_type: SigType::Ecdsa,
+            trace: None,
+            assume_timelocks: false,
+        }
+    }
+
     #[test]
     fn sat_constraints() {
         let (pks, der_sigs, ecdsa_sigs, sighash, secp, xpks, schnorr_sigs, ser_schnorr_sigs) =
@@ -1128,23 +1551,6 @@ mod tests {
                 .is_ok(),
         };
 
-        fn from_stack<'txin, 'elem>(
-            verify_fn: Box<dyn FnMut(&KeySigPair) -> bool + 'elem>,
-            stack: Stack<'txin>,
-            ms: &'elem Miniscript<BitcoinKey, NoChecks>,
-        ) -> Iter<'elem, 'txin> {
-            Iter {
-                verify_sig: verify_fn,
-                stack,
-                public_key: None,
-                state: vec![NodeEvaluationState { node: ms, n_evaluated: 0, n_satisfied: 0 }],
-                sequence: Sequence::from_height(1002),
-                lock_time: absolute::LockTime::from_height(1002).unwrap(),
-                has_errored: false,
-                sig_type: SigType::Ecdsa,
-            }
-        }
-
         let pk = no_checks_ms(&format!("c:pk_k({})", pks[0]));
         let pkh = no_checks_ms(&format!("c:pk_h({})", pks[1]));
         //Time
@@ -1171,6 +1577,30 @@ mod tests {
             }]
         );
 
+        assert_eq!(
+            KeySigPair::Ecdsa(pks[0], ecdsa_sigs[0]).sighash_flag(),
+            SighashFlag::Ecdsa(sighash::EcdsaSighashType::All)
+        );
+
+        // Signatures committing to part of the transaction are flagged
+        let mut acp_single_sig = der_sigs[0].clone();
+        *acp_single_sig.last_mut().unwrap() = 0x83;
+        let stack = Stack::from(vec![stack::Element::Push(&acp_single_sig)]);
+        let constraint = from_stack(Box::new(vfyfn), stack, &pk)
+            .next()
+            .unwrap()
+            .unwrap();
+        let flag = constraint.sighash_flag().unwrap();
+        assert_eq!(flag.to_string(), "SIGHASH_SINGLE|SIGHASH_ANYONECANPAY");
+        assert!(flag.anyone_can_pay() && flag.is_single());
+        assert!(!flag.is_all() && !flag.is_none());
+        assert!(constraint.has_partial_sighash());
+        let schnorr_default = KeySigPair::Schnorr(xpks[0], schnorr_sigs[0]).sighash_flag();
+        assert!(schnorr_default.is_all() && !schnorr_default.anyone_can_pay());
+        let timelock = SatisfiedConstraint::AbsoluteTimelock { n: absolute::LockTime::ZERO };
+        assert_eq!(timelock.sighash_flag(), None);
+        assert!(!timelock.has_partial_sighash());
+
         //Check Pk failure with wrong signature
         let stack = Stack::from(vec![stack::Element::Dissatisfied]);
         let constraints = from_stack(Box::new(vfyfn), stack, &pk);
@@ -1554,6 +1984,182 @@ mod tests {
         assert!(multi_a_error.is_err());
     }
 
+    #[test]
+    fn trace() {
+        let (pks, der_sigs, ecdsa_sigs, sighash, secp, ..) = setup_keys_sigs(3);
+        let vfyfn = |pksig: &KeySigPair| match pksig {
+            KeySigPair::Ecdsa(pk, ecdsa_sig) => secp
+                .verify_ecdsa(&sighash, &ecdsa_sig.signature, &pk.inner)
+                .is_ok(),
+            KeySigPair::Schnorr(..) => false,
+        };
+        let preimage = [0xab; 32];
+        let sha256_hash = sha256::Hash::hash(&preimage);
+
+        // Trace of a successful evaluation
+        let stack = Stack::from(vec![
+            stack::Element::Push(&preimage),
+            stack::Element::Push(&der_sigs[0]),
+        ]);
+        let elem = no_checks_ms(&format!("and_v(vc:pk_k({}),sha256({}))", pks[0], sha256_hash));
+        let mut constraints = from_stack(Box::new(vfyfn), stack, &elem).with_trace();
+        assert_eq!(constraints.by_ref().count(), 2);
+        let trace = constraints.trace().unwrap();
+        let fragments = trace
+            .iter()
+            .map(|step| (step.fragment.split('(').next().unwrap(), step.n_evaluated))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            fragments,
+            vec![
+                ("and_v", 0),
+                ("v:pk", 0),
+                ("pk", 0),
+                ("pk_k", 0),
+                ("v:pk", 1),
+                ("sha256", 0)
+            ]
+        );
+        let (sat, dissat) = (TraceElement::Satisfied, TraceElement::Dissatisfied);
+        let sig = |i: usize| TraceElement::Push(der_sigs[i].clone());
+        assert_eq!(
+            trace[3].operations,
+            vec![
+                TraceOperation::Pop(sig(0)),
+                TraceOperation::Push(sat.clone())
+            ]
+        );
+        assert_eq!(
+            trace[3].result,
+            TraceResult::Constraint(SatisfiedConstraint::PublicKey {
+                key_sig: KeySigPair::Ecdsa(pks[0], ecdsa_sigs[0])
+            })
+        );
+        assert_eq!(trace[4].operations, vec![TraceOperation::Pop(sat.clone())]);
+        assert_eq!(trace[4].result, TraceResult::Continue);
+        assert_eq!(
+            trace[5].consumed().collect::<Vec<_>>(),
+            [&TraceElement::Push(preimage.to_vec())]
+        );
+
+        // Trace of a failing evaluation, with the signature for the wrong key
+        let stack = Stack::from(vec![
+            stack::Element::Push(&preimage),
+            stack::Element::Push(&der_sigs[1]),
+        ]);
+        let mut constraints = from_stack(Box::new(vfyfn), stack, &elem).with_trace();
+        assert!(constraints.next().unwrap().is_err());
+        let last = constraints.trace().unwrap().last().unwrap();
+        assert!(last.fragment.starts_with("pk_k"));
+        assert_eq!(last.result, TraceResult::Failed);
+
+        // Stack operations of each step, including elements popped and pushed back unchanged
+        let steps = |ms: String, witness: Vec<stack::Element>| {
+            let elem = no_checks_ms(&ms);
+            let mut constraints =
+                from_stack(Box::new(vfyfn), Stack::from(witness), &elem).with_trace();
+            assert!(constraints.by_ref().all(|res| res.is_ok()));
+            constraints
+                .trace()
+                .unwrap()
+                .iter()
+                .map(|step| {
+                    (
+                        step.fragment.split('(').next().unwrap().to_owned(),
+                        step.n_evaluated,
+                        step.operations.clone(),
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+        let pop = TraceOperation::Pop;
+        let push = TraceOperation::Push;
+
+        let trace = steps(
+            format!("or_d(c:pk_k({}),c:pk_k({}))", pks[0], pks[1]),
+            vec![
+                stack::Element::Push(&der_sigs[1]),
+                stack::Element::Dissatisfied,
+            ],
+        );
+        assert_eq!(
+            trace,
+            vec![
+                ("or_d".to_owned(), 0, vec![]),
+                ("pk".to_owned(), 0, vec![]),
+                ("pk_k".to_owned(), 0, vec![pop(dissat.clone()), push(dissat.clone())]),
+                ("or_d".to_owned(), 1, vec![pop(dissat.clone())]),
+                ("pk".to_owned(), 0, vec![]),
+                ("pk_k".to_owned(), 0, vec![pop(sig(1)), push(sat.clone())]),
+            ]
+        );
+
+        let trace = steps(
+            format!("andor(c:pk_k({}),c:pk_k({}),c:pk_k({}))", pks[0], pks[1], pks[2]),
+            vec![
+                stack::Element::Push(&der_sigs[1]),
+                stack::Element::Push(&der_sigs[0]),
+            ],
+        );
+        assert_eq!(
+            trace,
+            vec![
+                ("andor".to_owned(), 0, vec![]),
+                ("pk".to_owned(), 0, vec![]),
+                ("pk_k".to_owned(), 0, vec![pop(sig(0)), push(sat.clone())]),
+                ("andor".to_owned(), 1, vec![pop(sat.clone())]),
+                ("pk".to_owned(), 0, vec![]),
+                ("pk_k".to_owned(), 0, vec![pop(sig(1)), push(sat.clone())]),
+            ]
+        );
+
+        let trace = steps(
+            format!("thresh(2,c:pk_k({}),s:pk({}),s:pk({}))", pks[0], pks[1], pks[2]),
+            vec![
+                stack::Element::Dissatisfied,
+                stack::Element::Push(&der_sigs[1]),
+                stack::Element::Push(&der_sigs[0]),
+            ],
+        );
+        let thresh_steps = trace
+            .iter()
+            .filter(|(fragment, ..)| fragment == "thresh")
+            .map(|(_, n_evaluated, ops)| (*n_evaluated, ops.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            thresh_steps,
+            vec![
+                (0, vec![]),
+                (1, vec![pop(sat.clone())]),
+                (2, vec![pop(sat.clone())]),
+                (3, vec![pop(dissat.clone()), push(sat)]),
+            ]
+        );
+        assert_eq!(trace[trace.len() - 2].2, vec![pop(dissat.clone()), push(dissat)]);
+
+        // No trace unless enabled
+        let stack = Stack::from(vec![]);
+        assert!(from_stack(Box::new(vfyfn), stack, &elem).trace().is_none());
+    }
+
+    #[test]
+    fn recording_stack_equality() {
+        let mut recording = Stack::from(vec![stack::Element::Satisfied]);
+        let stack = recording.clone();
+        recording.start_recording();
+        recording.push(stack::Element::Dissatisfied);
+        recording.pop();
+        assert_eq!(recording, stack);
+        assert_eq!(recording.cmp(&stack), core::cmp::Ordering::Equal);
+        assert_eq!(
+            recording.stop_recording().unwrap(),
+            vec![
+                stack::StackOperation::Push(stack::Element::Dissatisfied),
+                stack::StackOperation::Pop(stack::Element::Dissatisfied),
+            ]
+        );
+    }
+
     // By design there is no support for parse a miniscript with BitcoinKey
     // because it does not implement FromStr
     fn no_checks_ms(ms: &str) -> Miniscript<BitcoinKey, NoChecks> {
@@ -3,6 +3,8 @@
 
 //! Interpreter stack
 
+use core::{cmp, hash};
+
 use bitcoin::blockdata::{opcodes, script};
 use bitcoin::hashes::{hash160, ripemd160, sha256, Hash};
 use bitcoin::{absolute, relative, Sequence};
@@ -70,13 +72,44 @@ impl<'txin> Element<'txin> {
     }
 }
 
+/// An operation on a [`Stack`], as recorded while it is recording
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub(super) enum StackOperation<'txin> {
+    /// An element was popped
+    Pop(Element<'txin>),
+    /// An element was pushed
+    Push(Element<'txin>),
+}
+
 /// Stack Data structure representing the stack input to Miniscript. This Stack
 /// is created from the combination of ScriptSig and Witness stack.
-#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Default, Hash)]
-pub struct Stack<'txin>(Vec<Element<'txin>>);
+///
+/// While recording, the operations on the stack are kept in the order they happen, so that
+/// the interpreter can trace the effect of each fragment on the stack. They aren't part of
+/// the value of the stack, and are ignored by comparisons and hashing.
+#[derive(Clone, Debug, Default)]
+pub struct Stack<'txin>(Vec<Element<'txin>>, Option<Vec<StackOperation<'txin>>>);
 
 impl<'txin> From<Vec<Element<'txin>>> for Stack<'txin> {
-    fn from(v: Vec<Element<'txin>>) -> Self { Stack(v) }
+    fn from(v: Vec<Element<'txin>>) -> Self { Stack(v, None) }
+}
+
+impl PartialEq for Stack<'_> {
+    fn eq(&self, other: &Self) -> bool { self.0 == other.0 }
+}
+
+impl Eq for Stack<'_> {}
+
+impl PartialOrd for Stack<'_> {
+    fn partial_cmp(&self, other: &Self) -> Option<cmp::Ordering> { Some(self.cmp(other)) }
+}
+
+impl Ord for Stack<'_> {
+    fn cmp(&self, other: &Self) -> cmp::Ordering { self.0.cmp(&other.0) }
+}
+
+impl hash::Hash for Stack<'_> {
+    fn hash<H: hash::Hasher>(&self, state: &mut H) { self.0.hash(state) }
 }
 
 impl<'txin> Stack<'txin> {
@@ -87,14 +120,38 @@ impl<'txin> Stack<'txin> {
     pub fn len(&mut self) -> usize { self.0.len() }
 
     /// Removes the top stack element, if the stack is nonempty
-    pub fn pop(&mut self) -> Option<Element<'txin>> { self.0.pop() }
+    pub fn pop(&mut self) -> Option<Element<'txin>> {
+        let elem = self.0.pop();
+        if let (Some(elem), Some(ops)) = (elem, self.1.as_mut()) {
+            ops.push(StackOperation::Pop(elem));
+        }
+        elem
+    }
 
     /// Pushes an element onto the top of the stack
-    pub fn push(&mut self, elem: Element<'txin>) { self.0.push(elem); }
+    pub fn push(&mut self, elem: Element<'txin>) {
+        if let Some(ops) = self.1.as_mut() {
+            ops.push(StackOperation::Push(elem));
+        }
+        self.0.push(elem);
+    }
 
     /// Returns a new stack representing the top `k` elements of the stack,
     /// removing these elements from the original
-    pub fn split_off(&mut self, k: usize) -> Vec<Element<'txin>> { self.0.split_off(k) }
+    pub fn split_off(&mut self, k: usize) -> Vec<Element<'txin>> {
+        let split = self.0.split_off(k);
+        if let Some(ops) = self.1.as_mut() {
+            ops.extend(split.iter().rev().copied().map(StackOperation::Pop));
+        }
+        split
+    }
+
+    /// Starts recording the operations on the stack
+    pub(super) fn start_recording(&mut self) { self.1 = Some(vec![]); }
+
+    /// Stops recording, returning the operations on the stack since the recording started, in
+    /// the order they happened
+    pub(super) fn stop_recording(&mut self) -> Option<Vec<StackOperation<'txin>>> { self.1.take() }
 
     /// Returns a reference to the top stack element, if the stack is nonempty
     pub fn last(&self) -> Option<&Element<'txin>> { self.0.last() }
@@ -112,7 +112,11 @@ mod pub_macros;
 
 #[cfg(bench)]
 mod benchmarks;
+pub mod bip322;
 mod blanket_traits;
+pub mod ceremony;
+#[cfg(feature = "coretest")]
+pub mod coretest;
 pub mod descriptor;
 pub mod expression;
 pub mod interpreter;
@@ -122,6 +126,9 @@ pub mod plan;
 pub mod policy;
 mod primitives;
 pub mod psbt;
+pub mod signing_request;
+#[cfg(feature = "test-hashes")]
+pub mod test_hashes;
 
 #[cfg(test)]
 mod test_utils;
@@ -139,26 +146,50 @@ pub use crate::blanket_traits::FromStrKey;
 pub use crate::descriptor::{DefiniteDescriptorKey, Descriptor, DescriptorPublicKey};
 pub use crate::expression::{ParseThresholdError, ParseTreeError};
 pub use crate::interpreter::Interpreter;
-pub use crate::miniscript::analyzable::{AnalysisError, ExtParams};
+pub use crate::miniscript::analyzable::{
+    AnalysisError, ExtParams, FragmentMalleability, MalleabilityReport,
+};
 pub use crate::miniscript::context::{BareCtx, Legacy, ScriptContext, Segwitv0, SigType, Tap};
 pub use crate::miniscript::decode::Terminal;
-pub use crate::miniscript::satisfy::{Preimage32, Satisfier};
-pub use crate::miniscript::{hash256, Miniscript};
+pub use crate::miniscript::limits::{ContextLimits, Limits};
+pub use crate::miniscript::satisfy::{
+    HashOracle, HashOracleSatisfier, KeyRegistry, Lookup, OrElse, Override, PartialSig,
+    PkhResolver, PkhResolverSatisfier, Preimage32, PreimageError, PreimageStore, PubNonce,
+    Satisfier, SatisfierExt, SessionError, SessionRound, SigningSession, TracingSatisfier,
+};
+pub use crate::miniscript::shuffle::{KeyPermutation, PermutationError};
+pub use crate::miniscript::{hash256, AliasError, Aliases, Miniscript};
 use crate::prelude::*;
-pub use crate::primitives::absolute_locktime::{AbsLockTime, AbsLockTimeError};
+pub use crate::primitives::absolute_locktime::{AbsLockTime, AbsLockTimeError, ChainTip};
+pub use crate::primitives::locktime_arith::{BlockInterval, LockTimeArithError};
 pub use crate::primitives::relative_locktime::{RelLockTime, RelLockTimeError};
 pub use crate::primitives::threshold::{Threshold, ThresholdError};
 
 /// Public key trait which can be converted to Hash type
 pub trait MiniscriptKey: Clone + Eq + Ord + fmt::Debug + fmt::Display + hash::Hash {
-    /// Returns true if the pubkey is uncompressed. Defaults to `false`.
-    fn is_uncompressed(&self) -> bool { false }
+    /// Returns true if the pubkey is uncompressed. Defaults to whether
+    /// [`MiniscriptKey::uncompressed_key`] returns a key.
+    fn is_uncompressed(&self) -> bool { self.uncompressed_key().is_some() }
+
+    /// Returns the full public key if the pubkey is uncompressed. Defaults to `None`.
+    ///
+    /// Script contexts which don't allow uncompressed keys reject the keys for which this
+    /// returns a key.
+    fn uncompressed_key(&self) -> Option<bitcoin::PublicKey> { None }
 
     /// Returns true if the pubkey is an x-only pubkey. Defaults to `false`.
     // This is required to know what in DescriptorPublicKey to know whether the inner
     // key in allowed in descriptor context
     fn is_x_only_key(&self) -> bool { false }
 
+    /// Returns true if the pubkey is a MuSig2 aggregate of other keys. Defaults to `false`.
+    fn is_musig_key(&self) -> bool { false }
+
+    /// Returns the x-only key ordering this key in the `sortedmulti_a` leaves of Taproot trees,
+    /// if it is known. Defaults to `None`, which leaves the keys of such leaves in the order they
+    /// are given until they are converted to keys for which it is known.
+    fn sorting_key(&self) -> Option<bitcoin::secp256k1::XOnlyPublicKey> { None }
+
     /// Returns the number of different derivation paths in this key. Only >1 for keys
     /// in BIP389 multipath descriptors.
     fn num_der_paths(&self) -> usize { 0 }
@@ -185,11 +216,21 @@ impl MiniscriptKey for bitcoin::secp256k1::PublicKey {
     type Hash256 = hash256::Hash;
     type Ripemd160 = ripemd160::Hash;
     type Hash160 = hash160::Hash;
+
+    fn sorting_key(&self) -> Option<bitcoin::secp256k1::XOnlyPublicKey> {
+        Some(self.to_x_only_pubkey())
+    }
 }
 
 impl MiniscriptKey for bitcoin::PublicKey {
-    /// Returns the compressed-ness of the underlying secp256k1 key.
-    fn is_uncompressed(&self) -> bool { !self.compressed }
+    /// Returns the key if the underlying secp256k1 key is uncompressed.
+    fn uncompressed_key(&self) -> Option<bitcoin::PublicKey> {
+        Some(*self).filter(|pk| !pk.compressed)
+    }
+
+    fn sorting_key(&self) -> Option<bitcoin::secp256k1::XOnlyPublicKey> {
+        Some(self.to_x_only_pubkey())
+    }
 
     type Sha256 = sha256::Hash;
     type Hash256 = hash256::Hash;
@@ -204,6 +245,8 @@ impl MiniscriptKey for bitcoin::secp256k1::XOnlyPublicKey {
     type Hash160 = hash160::Hash;
 
     fn is_x_only_key(&self) -> bool { true }
+
+    fn sorting_key(&self) -> Option<bitcoin::secp256k1::XOnlyPublicKey> { Some(*self) }
 }
 
 impl MiniscriptKey for String {
@@ -449,6 +492,8 @@ pub enum Error {
     MissingSig(bitcoin::PublicKey),
     /// General failure to satisfy
     CouldNotSatisfy,
+    /// The review callback of a two-stage satisfaction rejected the spending path
+    SatisfactionRejected,
     /// Typechecking failed
     TypeCheck(String),
     /// General error in creating descriptor
@@ -477,6 +522,8 @@ pub enum Error {
     ImpossibleSatisfaction,
     /// Bare descriptors don't have any addresses
     BareDescriptorAddr,
+    /// Data carrier descriptors don't have any addresses
+    DataDescriptorAddr,
     /// PubKey invalid under current context
     PubKeyCtxError(miniscript::decode::KeyParseError, &'static str),
     /// No script code for Tr descriptors
@@ -494,6 +541,10 @@ pub enum Error {
     ParseThreshold(ParseThresholdError),
     /// Invalid expression tree.
     ParseTree(ParseTreeError),
+    /// Configured resource limits exceeded.
+    Limits(miniscript::limits::LimitsError),
+    /// Deriving a descriptor at a range of indexes failed.
+    DerivationRange(descriptor::DerivationRangeError),
 }
 
 // https://github.com/sipa/miniscript/pull/5 for discussion on this number
@@ -522,6 +573,7 @@ impl fmt::Display for Error {
             Error::Trailing(ref s) => write!(f, "trailing tokens: {}", s),
             Error::MissingSig(ref pk) => write!(f, "missing signature for key {:?}", pk),
             Error::CouldNotSatisfy => f.write_str("could not satisfy"),
+            Error::SatisfactionRejected => f.write_str("satisfaction rejected by the reviewer"),
             Error::TypeCheck(ref e) => write!(f, "typecheck: {}", e),
             Error::BadDescriptor(ref e) => write!(f, "Invalid descriptor: {}", e),
             Error::Secp(ref e) => fmt::Display::fmt(e, f),
@@ -545,6 +597,7 @@ impl fmt::Display for Error {
             Error::AnalysisError(ref e) => e.fmt(f),
             Error::ImpossibleSatisfaction => write!(f, "Impossible to satisfy Miniscript"),
             Error::BareDescriptorAddr => write!(f, "Bare descriptors don't have address"),
+            Error::DataDescriptorAddr => write!(f, "Data carrier descriptors don't have address"),
             Error::PubKeyCtxError(ref pk, ref ctx) => {
                 write!(f, "Pubkey error: {} under {} scriptcontext", pk, ctx)
             }
@@ -556,6 +609,8 @@ impl fmt::Display for Error {
             Error::Threshold(ref e) => e.fmt(f),
             Error::ParseThreshold(ref e) => e.fmt(f),
             Error::ParseTree(ref e) => e.fmt(f),
+            Error::Limits(ref e) => e.fmt(f),
+            Error::DerivationRange(ref e) => e.fmt(f),
         }
     }
 }
@@ -582,12 +637,14 @@ impl error::Error for Error {
             | Trailing(_)
             | MissingSig(_)
             | CouldNotSatisfy
+            | SatisfactionRejected
             | TypeCheck(_)
             | BadDescriptor(_)
             | MaxRecursiveDepthExceeded
             | NonStandardBareScript
             | ImpossibleSatisfaction
             | BareDescriptorAddr
+            | DataDescriptorAddr
             | TrNoScriptCode
             | MultipathDescLenMismatch => None,
             Script(e) => Some(e),
@@ -607,6 +664,8 @@ impl error::Error for Error {
             Threshold(e) => Some(e),
             ParseThreshold(e) => Some(e),
             ParseTree(e) => Some(e),
+            Limits(e) => Some(e),
+            DerivationRange(e) => Some(e),
         }
     }
 }
@@ -631,6 +690,16 @@ impl From<miniscript::analyzable::AnalysisError> for Error {
     fn from(e: miniscript::analyzable::AnalysisError) -> Error { Error::AnalysisError(e) }
 }
 
+#[doc(hidden)]
+impl From<miniscript::limits::LimitsError> for Error {
+    fn from(e: miniscript::limits::LimitsError) -> Error { Error::Limits(e) }
+}
+
+#[doc(hidden)]
+impl From<descriptor::DerivationRangeError> for Error {
+    fn from(e: descriptor::DerivationRangeError) -> Error { Error::DerivationRange(e) }
+}
+
 #[doc(hidden)]
 impl From<bitcoin::secp256k1::Error> for Error {
     fn from(e: bitcoin::secp256k1::Error) -> Error { Error::Secp(e) }
@@ -9,6 +9,8 @@ use core::fmt;
 #[cfg(feature = "std")]
 use std::error;
 
+use crate::miniscript::limits::MAX_PUBKEYS_PER_MULTISIG;
+use crate::miniscript::types::{Dissat, Malleability};
 use crate::prelude::*;
 use crate::{Miniscript, MiniscriptKey, ScriptContext, Terminal};
 
@@ -24,6 +26,7 @@ use crate::{Miniscript, MiniscriptKey, ScriptContext, Terminal};
 ///    guarantees are not satisfied.
 /// 4. It has repeated public keys
 /// 5. raw pkh fragments without the pk. This could be obtained when parsing miniscript from script
+/// 6. It has more signature operations than standardness rules allow in its context
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Default, Hash)]
 pub struct ExtParams {
     /// Allow parsing of non-safe miniscripts
@@ -39,6 +42,9 @@ pub struct ExtParams {
     /// Allow parsing of miniscripts with raw pkh fragments without the pk.
     /// This could be obtained when parsing miniscript from script
     pub raw_pkh: bool,
+    /// Allow parsing of miniscripts with more signature operations than
+    /// standardness rules allow, e.g. more than 15 in a P2SH redeem script
+    pub nonstandard_sigops: bool,
 }
 
 impl ExtParams {
@@ -51,6 +57,7 @@ impl ExtParams {
             malleability: false,
             repeated_pk: false,
             raw_pkh: false,
+            nonstandard_sigops: false,
         }
     }
 
@@ -68,6 +75,7 @@ impl ExtParams {
             malleability: true,
             repeated_pk: true,
             raw_pkh: false,
+            nonstandard_sigops: true,
         }
     }
 
@@ -80,6 +88,7 @@ impl ExtParams {
             malleability: true,
             repeated_pk: true,
             raw_pkh: true,
+            nonstandard_sigops: true,
         }
     }
 
@@ -118,6 +127,12 @@ impl ExtParams {
         self.raw_pkh = true;
         self
     }
+
+    /// Builder that allows miniscripts with non-standard signature operation counts.
+    pub fn nonstandard_sigops(mut self) -> ExtParams {
+        self.nonstandard_sigops = true;
+        self
+    }
 }
 
 /// Possible reasons Miniscript guarantees can fail
@@ -129,6 +144,7 @@ impl ExtParams {
 /// 3. The script is malleable and thereby some of satisfaction weight
 ///    guarantees are not satisfied.
 /// 4. It has repeated publickeys
+/// 5. It has more signature operations than standardness rules allow
 #[derive(Debug, PartialEq)]
 pub enum AnalysisError {
     /// Top level is not safe.
@@ -143,6 +159,8 @@ pub enum AnalysisError {
     Malleable,
     /// Contains partial descriptor raw pkh
     ContainsRawPkh,
+    /// Contains more signature operations than standardness rules allow
+    NonStandardSigOps,
 }
 
 impl fmt::Display for AnalysisError {
@@ -162,6 +180,9 @@ impl fmt::Display for AnalysisError {
             }
             AnalysisError::Malleable => f.write_str("Miniscript is malleable"),
             AnalysisError::ContainsRawPkh => f.write_str("Miniscript contains raw pkh"),
+            AnalysisError::NonStandardSigOps => {
+                f.write_str("Miniscript exceeds the standard signature operation limit")
+            }
         }
     }
 }
@@ -177,12 +198,141 @@ impl error::Error for AnalysisError {
             | BranchExceedResouceLimits
             | HeightTimelockCombination
             | Malleable
-            | ContainsRawPkh => None,
+            | ContainsRawPkh
+            | NonStandardSigOps => None,
+        }
+    }
+}
+
+/// The malleability properties of a fragment of a Miniscript, see
+/// [`Miniscript::malleability_report`]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct FragmentMalleability {
+    /// The path of the fragment from the root, as the indexes of the children to follow, see
+    /// [`Miniscript::get_nth_child`]
+    pub path: Vec<usize>,
+    /// The name of the fragment without aliases and its arguments which aren't fragments, e.g.
+    /// `pk_k(A)`, `thresh(2)` or `c:`
+    pub fragment: String,
+    /// The malleability properties of the fragment
+    pub malleability: Malleability,
+}
+
+impl fmt::Display for FragmentMalleability {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("[")?;
+        for (i, idx) in self.path.iter().enumerate() {
+            if i > 0 {
+                f.write_str(",")?;
+            }
+            write!(f, "{}", idx)?;
         }
+        write!(f, "] {}", self.fragment)
+    }
+}
+
+/// The malleability analysis of a Miniscript, see [`Miniscript::malleability_report`]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct MalleabilityReport {
+    fragments: Vec<FragmentMalleability>,
+    malleable: Vec<usize>,
+    non_unique_dissat: Vec<usize>,
+    sigless_branches: Vec<usize>,
+}
+
+impl MalleabilityReport {
+    /// Every fragment of the Miniscript, in pre-order
+    pub fn fragments(&self) -> &[FragmentMalleability] { &self.fragments }
+
+    /// Whether the Miniscript has a non-malleable satisfaction, see
+    /// [`Miniscript::is_non_malleable`]
+    pub fn is_non_malleable(&self) -> bool { self.fragments[0].malleability.non_malleable }
+
+    /// Whether every satisfaction of the Miniscript requires a signature, see
+    /// [`Miniscript::requires_sig`]
+    pub fn requires_sig(&self) -> bool { self.fragments[0].malleability.safe }
+
+    /// The fragments without a non-malleable satisfaction while their children have one, that
+    /// is where the malleability of the Miniscript comes from
+    pub fn malleable(&self) -> impl Iterator<Item = &FragmentMalleability> {
+        self.malleable.iter().map(move |&i| &self.fragments[i])
+    }
+
+    /// The fragments whose dissatisfactions third parties may produce, while the ones of their
+    /// children are unique or don't exist
+    pub fn non_unique_dissatisfactions(&self) -> impl Iterator<Item = &FragmentMalleability> {
+        self.non_unique_dissat
+            .iter()
+            .map(move |&i| &self.fragments[i])
+    }
+
+    /// The branches of disjunctions which can be satisfied without a signature, and which make
+    /// the Miniscript satisfiable without one
+    ///
+    /// These are the branches of `or_*` fragments, the last branch of `andor` and the
+    /// sub-fragments of `thresh`, whose ancestors all don't require a signature.
+    pub fn sigless_branches(&self) -> impl Iterator<Item = &FragmentMalleability> {
+        self.sigless_branches
+            .iter()
+            .map(move |&i| &self.fragments[i])
     }
 }
 
 impl<Pk: MiniscriptKey, Ctx: ScriptContext> Miniscript<Pk, Ctx> {
+    /// Reports the malleability properties of every fragment, and the fragments which make
+    /// the Miniscript malleable or satisfiable without a signature
+    ///
+    /// Where [`Miniscript::sanity_check`] only tells whether the Miniscript is malleable or
+    /// sigless, the report points to the fragments to fix, by their path from the root.
+    pub fn malleability_report(&self) -> MalleabilityReport {
+        let mut report = MalleabilityReport {
+            fragments: vec![],
+            malleable: vec![],
+            non_unique_dissat: vec![],
+            sigless_branches: vec![],
+        };
+        // The fragments to visit, with their path, whether their ancestors all don't require a
+        // signature, and whether they are a branch of a disjunction
+        let mut stack = vec![(self, vec![], true, false)];
+        while let Some((node, path, sigless_ancestors, branch)) = stack.pop() {
+            let mall = node.ty.mall;
+            let children = node.branches();
+            let index = report.fragments.len();
+            if !mall.non_malleable && children.iter().all(|c| c.ty.mall.non_malleable) {
+                report.malleable.push(index);
+            }
+            if mall.dissat == Dissat::Unknown
+                && children.iter().all(|c| c.ty.mall.dissat != Dissat::Unknown)
+            {
+                report.non_unique_dissat.push(index);
+            }
+            if sigless_ancestors && branch && !mall.safe {
+                report.sigless_branches.push(index);
+            }
+
+            for (i, child) in children.iter().enumerate().rev() {
+                let branch = match node.node {
+                    Terminal::OrB(..)
+                    | Terminal::OrC(..)
+                    | Terminal::OrD(..)
+                    | Terminal::OrI(..)
+                    | Terminal::Thresh(..) => true,
+                    Terminal::AndOr(..) => i == 2,
+                    _ => false,
+                };
+                let mut child_path = path.clone();
+                child_path.push(i);
+                stack.push((*child, child_path, sigless_ancestors && !mall.safe, branch));
+            }
+            report.fragments.push(FragmentMalleability {
+                path,
+                fragment: node.node.label(),
+                malleability: mall,
+            });
+        }
+        report
+    }
+
     /// Whether all spend paths of miniscript require a signature
     pub fn requires_sig(&self) -> bool { self.ty.mall.safe }
 
@@ -208,6 +358,33 @@ impl<Pk: MiniscriptKey, Ctx: ScriptContext> Miniscript<Pk, Ctx> {
         unique_pkhs_len != all_pkhs_len
     }
 
+    /// Number of signature operations in the script, counted the way Bitcoin Core
+    /// counts them in P2SH redeem scripts: one per `CHECKSIG` and as many as the
+    /// keys of each `CHECKMULTISIG`, or [`MAX_PUBKEYS_PER_MULTISIG`] if the key
+    /// count isn't pushed with a small integer opcode.
+    ///
+    /// [`MAX_PUBKEYS_PER_MULTISIG`]: crate::miniscript::limits::MAX_PUBKEYS_PER_MULTISIG
+    pub fn sigop_count(&self) -> usize {
+        self.iter()
+            .map(|ms| match ms.node {
+                Terminal::Check(_) => 1,
+                Terminal::Multi(ref thresh) if thresh.n() <= 16 => thresh.n(),
+                Terminal::Multi(_) => MAX_PUBKEYS_PER_MULTISIG,
+                Terminal::MultiA(ref thresh) => thresh.n(),
+                _ => 0,
+            })
+            .sum()
+    }
+
+    /// Whether the signature operations of the miniscript are within the
+    /// standardness limit of its context, if any
+    pub fn within_standard_sigops(&self) -> bool {
+        match Ctx::limits().max_standard_sigops {
+            Some(max) => self.sigop_count() <= max,
+            None => true,
+        }
+    }
+
     /// Whether the given miniscript contains a raw pkh fragment
     pub fn contains_raw_pkh(&self) -> bool {
         self.iter().any(|ms| matches!(ms.node, Terminal::RawPkH(_)))
@@ -223,37 +400,44 @@ impl<Pk: MiniscriptKey, Ctx: ScriptContext> Miniscript<Pk, Ctx> {
     /// Most functions of the library like would still
     /// work, but results cannot be relied upon
     pub fn sanity_check(&self) -> Result<(), AnalysisError> {
-        if !self.requires_sig() {
-            Err(AnalysisError::SiglessBranch)
-        } else if !self.is_non_malleable() {
-            Err(AnalysisError::Malleable)
-        } else if !self.within_resource_limits() {
-            Err(AnalysisError::BranchExceedResouceLimits)
-        } else if self.has_repeated_keys() {
-            Err(AnalysisError::RepeatedPubkeys)
-        } else if self.has_mixed_timelocks() {
-            Err(AnalysisError::HeightTimelockCombination)
-        } else {
-            Ok(())
-        }
+        self.ext_check(&ExtParams { raw_pkh: true, ..ExtParams::sane() })
     }
 
     /// Check whether the miniscript follows the given Extra policy [`ExtParams`]
     pub fn ext_check(&self, ext: &ExtParams) -> Result<(), AnalysisError> {
-        if !ext.top_unsafe && !self.requires_sig() {
-            Err(AnalysisError::SiglessBranch)
-        } else if !ext.malleability && !self.is_non_malleable() {
-            Err(AnalysisError::Malleable)
-        } else if !ext.resource_limitations && !self.within_resource_limits() {
-            Err(AnalysisError::BranchExceedResouceLimits)
-        } else if !ext.repeated_pk && self.has_repeated_keys() {
-            Err(AnalysisError::RepeatedPubkeys)
-        } else if !ext.timelock_mixing && self.has_mixed_timelocks() {
-            Err(AnalysisError::HeightTimelockCombination)
-        } else if !ext.raw_pkh && self.contains_raw_pkh() {
-            Err(AnalysisError::ContainsRawPkh)
-        } else {
-            Ok(())
+        match self.failed_checks(ext).into_iter().next() {
+            Some(e) => Err(e),
+            None => Ok(()),
         }
     }
+
+    /// Every check of [`Miniscript::ext_check`] the miniscript fails, in order,
+    /// skipping the ones allowed by `ext`
+    pub(crate) fn failed_checks(&self, ext: &ExtParams) -> Vec<AnalysisError> {
+        [
+            (ext.top_unsafe, !self.requires_sig(), AnalysisError::SiglessBranch),
+            (ext.malleability, !self.is_non_malleable(), AnalysisError::Malleable),
+            (
+                ext.resource_limitations,
+                !self.within_resource_limits(),
+                AnalysisError::BranchExceedResouceLimits,
+            ),
+            (ext.repeated_pk, self.has_repeated_keys(), AnalysisError::RepeatedPubkeys),
+            (
+                ext.timelock_mixing,
+                self.has_mixed_timelocks(),
+                AnalysisError::HeightTimelockCombination,
+            ),
+            (ext.raw_pkh, self.contains_raw_pkh(), AnalysisError::ContainsRawPkh),
+            (
+                ext.nonstandard_sigops,
+                !self.within_standard_sigops(),
+                AnalysisError::NonStandardSigOps,
+            ),
+        ]
+        .into_iter()
+        .filter(|&(allowed, failed, _)| !allowed && failed)
+        .map(|(_, _, e)| e)
+        .collect()
+    }
 }
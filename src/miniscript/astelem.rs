@@ -18,8 +18,8 @@ use crate::miniscript::ScriptContext;
 use crate::prelude::*;
 use crate::util::MsKeyBuilder;
 use crate::{
-    expression, AbsLockTime, Error, FromStrKey, Miniscript, MiniscriptKey, RelLockTime, Terminal,
-    ToPublicKey,
+    errstr, expression, AbsLockTime, Error, FromStrKey, Miniscript, MiniscriptKey, RelLockTime,
+    Terminal, ToPublicKey,
 };
 
 impl<Pk: FromStrKey, Ctx: ScriptContext> crate::expression::FromTree for Arc<Terminal<Pk, Ctx>> {
@@ -30,17 +30,29 @@ impl<Pk: FromStrKey, Ctx: ScriptContext> crate::expression::FromTree for Arc<Ter
 
 impl<Pk: FromStrKey, Ctx: ScriptContext> crate::expression::FromTree for Terminal<Pk, Ctx> {
     fn from_tree(top: &expression::Tree) -> Result<Terminal<Pk, Ctx>, Error> {
+        Terminal::from_tree_with(top, &mut |child| expression::FromTree::from_tree(child))
+    }
+}
+
+impl<Pk: FromStrKey, Ctx: ScriptContext> Terminal<Pk, Ctx> {
+    /// Parse an expression tree into a fragment, parsing the sub-fragments with `parse_child`
+    pub(super) fn from_tree_with<F>(
+        top: &expression::Tree,
+        parse_child: &mut F,
+    ) -> Result<Terminal<Pk, Ctx>, Error>
+    where
+        F: FnMut(&expression::Tree) -> Result<Arc<Miniscript<Pk, Ctx>>, Error>,
+    {
         let (frag_name, frag_wrap) = super::split_expression_name(top.name)?;
+        let mut binary = |wrap: fn(_, _) -> _| -> Result<_, Error> {
+            Ok(wrap(parse_child(&top.args[0])?, parse_child(&top.args[1])?))
+        };
         let unwrapped = match (frag_name, top.args.len()) {
             ("expr_raw_pkh", 1) => expression::terminal(&top.args[0], |x| {
                 hash160::Hash::from_str(x).map(Terminal::RawPkH)
             }),
-            ("pk_k", 1) => {
-                expression::terminal(&top.args[0], |x| Pk::from_str(x).map(Terminal::PkK))
-            }
-            ("pk_h", 1) => {
-                expression::terminal(&top.args[0], |x| Pk::from_str(x).map(Terminal::PkH))
-            }
+            ("pk_k", 1) => parse_key::<Pk, Ctx>(&top.args[0], true).map(Terminal::PkK),
+            ("pk_h", 1) => parse_key::<Pk, Ctx>(&top.args[0], false).map(Terminal::PkH),
             ("after", 1) => expression::terminal(&top.args[0], |x| {
                 expression::parse_num(x)
                     .and_then(|x| AbsLockTime::from_consensus(x).map_err(Error::AbsoluteLockTime))
@@ -65,36 +77,36 @@ impl<Pk: FromStrKey, Ctx: ScriptContext> crate::expression::FromTree for Termina
             }),
             ("1", 0) => Ok(Terminal::True),
             ("0", 0) => Ok(Terminal::False),
-            ("and_v", 2) => expression::binary(top, Terminal::AndV),
-            ("and_b", 2) => expression::binary(top, Terminal::AndB),
+            ("and_v", 2) => binary(Terminal::AndV),
+            ("and_b", 2) => binary(Terminal::AndB),
             ("and_n", 2) => Ok(Terminal::AndOr(
-                expression::FromTree::from_tree(&top.args[0])?,
-                expression::FromTree::from_tree(&top.args[1])?,
+                parse_child(&top.args[0])?,
+                parse_child(&top.args[1])?,
                 Arc::new(Miniscript::FALSE),
             )),
             ("andor", 3) => Ok(Terminal::AndOr(
-                expression::FromTree::from_tree(&top.args[0])?,
-                expression::FromTree::from_tree(&top.args[1])?,
-                expression::FromTree::from_tree(&top.args[2])?,
+                parse_child(&top.args[0])?,
+                parse_child(&top.args[1])?,
+                parse_child(&top.args[2])?,
             )),
-            ("or_b", 2) => expression::binary(top, Terminal::OrB),
-            ("or_d", 2) => expression::binary(top, Terminal::OrD),
-            ("or_c", 2) => expression::binary(top, Terminal::OrC),
-            ("or_i", 2) => expression::binary(top, Terminal::OrI),
+            ("or_b", 2) => binary(Terminal::OrB),
+            ("or_d", 2) => binary(Terminal::OrD),
+            ("or_c", 2) => binary(Terminal::OrC),
+            ("or_i", 2) => binary(Terminal::OrI),
             ("thresh", _) => top
                 .to_null_threshold()
                 .map_err(Error::ParseThreshold)?
-                .translate_by_index(|i| Miniscript::from_tree(&top.args[1 + i]).map(Arc::new))
+                .translate_by_index(|i| parse_child(&top.args[1 + i]))
                 .map(Terminal::Thresh),
             ("multi", _) => top
                 .to_null_threshold()
                 .map_err(Error::ParseThreshold)?
-                .translate_by_index(|i| expression::terminal(&top.args[1 + i], Pk::from_str))
+                .translate_by_index(|i| parse_key::<Pk, Ctx>(&top.args[1 + i], false))
                 .map(Terminal::Multi),
             ("multi_a", _) => top
                 .to_null_threshold()
                 .map_err(Error::ParseThreshold)?
-                .translate_by_index(|i| expression::terminal(&top.args[1 + i], Pk::from_str))
+                .translate_by_index(|i| parse_key::<Pk, Ctx>(&top.args[1 + i], false))
                 .map(Terminal::MultiA),
             _ => Err(Error::Unexpected(format!(
                 "{}({} args) while parsing Miniscript",
@@ -107,6 +119,27 @@ impl<Pk: FromStrKey, Ctx: ScriptContext> crate::expression::FromTree for Termina
     }
 }
 
+/// Parses the key of a fragment, and checks it can be used in the script context.
+///
+/// Errors name the key and the context, e.g. for a 32-byte key outside of Tapscript
+/// or an uncompressed key in Segwit v0.
+///
+/// `musig(KEY,KEY,...)` expressions are only allowed with `aggregate`, i.e. in `pk_k`.
+fn parse_key<Pk: FromStrKey, Ctx: ScriptContext>(
+    term: &expression::Tree,
+    aggregate: bool,
+) -> Result<Pk, Error> {
+    if !aggregate && !term.args.is_empty() {
+        return Err(errstr(term.name));
+    }
+    let key = expression::key_expression(term)?;
+    let pk = Pk::from_str(&key).map_err(|e| {
+        Error::Unexpected(format!("invalid key {} in {}: {}", key, Ctx::name_str(), e))
+    })?;
+    Ctx::check_pk(&pk)?;
+    Ok(pk)
+}
+
 /// Helper trait to add a `push_astelem` method to `script::Builder`
 trait PushAstElem<Pk: MiniscriptKey, Ctx: ScriptContext> {
     fn push_astelem(self, ast: &Miniscript<Pk, Ctx>) -> Self
@@ -10,8 +10,10 @@ use bitcoin::Weight;
 
 use super::decode::ParseableKey;
 use crate::miniscript::limits::{
-    MAX_OPS_PER_SCRIPT, MAX_SCRIPTSIG_SIZE, MAX_SCRIPT_ELEMENT_SIZE, MAX_SCRIPT_SIZE,
-    MAX_STACK_SIZE, MAX_STANDARD_P2WSH_SCRIPT_SIZE, MAX_STANDARD_P2WSH_STACK_ITEMS,
+    ContextLimits, MAX_BLOCK_WEIGHT, MAX_OPS_PER_SCRIPT, MAX_P2SH_SIGOPS,
+    MAX_PUBKEYS_IN_CHECKSIGADD, MAX_PUBKEYS_PER_MULTISIG, MAX_SCRIPTSIG_SIZE,
+    MAX_SCRIPT_ELEMENT_SIZE, MAX_SCRIPT_SIZE, MAX_STACK_SIZE, MAX_STANDARD_P2WSH_SCRIPT_SIZE,
+    MAX_STANDARD_P2WSH_STACK_ITEMS,
 };
 use crate::miniscript::types;
 use crate::prelude::*;
@@ -32,14 +34,14 @@ pub enum ScriptContextError {
     /// Legacy fragments allow non-minimal IF which results in malleability
     MalleableDupIf,
     /// Only Compressed keys allowed under current descriptor
-    /// Segwitv0 fragments do not allow uncompressed pubkeys
-    CompressedOnly(String),
+    /// Segwitv0 and Tap fragments do not allow uncompressed pubkeys
+    CompressedOnly(bitcoin::PublicKey),
     /// XOnly keys are only allowed in Tap context
     /// The first element is key, and second element is current script context
     XOnlyKeysNotAllowed(String, &'static str),
-    /// Tapscript descriptors cannot contain uncompressed keys
-    /// Tap context can contain compressed or xonly
-    UncompressedKeysNotAllowed,
+    /// MuSig2 aggregate keys are only allowed in Tap context
+    /// The first element is key, and second element is current script context
+    MusigKeysNotAllowed(String, &'static str),
     /// At least one satisfaction path in the Miniscript fragment has more than
     /// `MAX_STANDARD_P2WSH_STACK_ITEMS` (100) witness elements.
     MaxWitnessItemsExceeded { actual: usize, limit: usize },
@@ -79,7 +81,7 @@ impl error::Error for ScriptContextError {
             | MalleableDupIf
             | CompressedOnly(_)
             | XOnlyKeysNotAllowed(_, _)
-            | UncompressedKeysNotAllowed
+            | MusigKeysNotAllowed(_, _)
             | MaxWitnessItemsExceeded { .. }
             | MaxOpCountExceeded { .. }
             | MaxWitnessScriptSizeExceeded { .. }
@@ -108,8 +110,8 @@ impl fmt::Display for ScriptContextError {
             ScriptContextError::XOnlyKeysNotAllowed(ref pk, ref ctx) => {
                 write!(f, "x-only key {} not allowed in {}", pk, ctx)
             }
-            ScriptContextError::UncompressedKeysNotAllowed => {
-                write!(f, "uncompressed keys cannot be used in Taproot descriptors.")
+            ScriptContextError::MusigKeysNotAllowed(ref pk, ref ctx) => {
+                write!(f, "musig() key {} not allowed in {}, only in Taproot", pk, ctx)
             }
             ScriptContextError::MaxWitnessItemsExceeded { actual, limit } => write!(
                 f,
@@ -356,6 +358,9 @@ where
 
     /// Local helper function to display error messages with context
     fn name_str() -> &'static str;
+
+    /// The consensus and standardness limits this library enforces in the context
+    fn limits() -> ContextLimits;
 }
 
 /// Signature algorithm type
@@ -390,7 +395,9 @@ impl ScriptContext for Legacy {
 
     // Only compressed and uncompressed public keys are allowed in Legacy context
     fn check_pk<Pk: MiniscriptKey>(pk: &Pk) -> Result<(), ScriptContextError> {
-        if pk.is_x_only_key() {
+        if pk.is_musig_key() {
+            Err(ScriptContextError::MusigKeysNotAllowed(pk.to_string(), Self::name_str()))
+        } else if pk.is_x_only_key() {
             Err(ScriptContextError::XOnlyKeysNotAllowed(pk.to_string(), Self::name_str()))
         } else {
             Ok(())
@@ -489,6 +496,21 @@ impl ScriptContext for Legacy {
 
     fn name_str() -> &'static str { "Legacy/p2sh" }
 
+    fn limits() -> ContextLimits {
+        ContextLimits {
+            max_script_size: Some(MAX_SCRIPT_ELEMENT_SIZE),
+            max_standard_script_size: None,
+            max_ops: Some(MAX_OPS_PER_SCRIPT),
+            max_script_sig_size: Some(MAX_SCRIPTSIG_SIZE),
+            max_witness_elems: None,
+            max_multi_keys: Some(MAX_PUBKEYS_PER_MULTISIG),
+            max_multi_a_keys: None,
+            max_standard_sigops: Some(MAX_P2SH_SIGOPS),
+            uncompressed_keys: true,
+            x_only_keys: false,
+        }
+    }
+
     fn sig_type() -> SigType { SigType::Ecdsa }
 }
 
@@ -506,8 +528,10 @@ impl ScriptContext for Segwitv0 {
 
     // No x-only keys or uncompressed keys in Segwitv0 context
     fn check_pk<Pk: MiniscriptKey>(pk: &Pk) -> Result<(), ScriptContextError> {
-        if pk.is_uncompressed() {
-            Err(ScriptContextError::UncompressedKeysNotAllowed)
+        if let Some(pk) = pk.uncompressed_key() {
+            Err(ScriptContextError::CompressedOnly(pk))
+        } else if pk.is_musig_key() {
+            Err(ScriptContextError::MusigKeysNotAllowed(pk.to_string(), Self::name_str()))
         } else if pk.is_x_only_key() {
             Err(ScriptContextError::XOnlyKeysNotAllowed(pk.to_string(), Self::name_str()))
         } else {
@@ -611,6 +635,21 @@ impl ScriptContext for Segwitv0 {
 
     fn name_str() -> &'static str { "Segwitv0" }
 
+    fn limits() -> ContextLimits {
+        ContextLimits {
+            max_script_size: Some(MAX_SCRIPT_SIZE),
+            max_standard_script_size: Some(MAX_STANDARD_P2WSH_SCRIPT_SIZE),
+            max_ops: Some(MAX_OPS_PER_SCRIPT),
+            max_script_sig_size: None,
+            max_witness_elems: Some(MAX_STANDARD_P2WSH_STACK_ITEMS),
+            max_multi_keys: Some(MAX_PUBKEYS_PER_MULTISIG),
+            max_multi_a_keys: None,
+            max_standard_sigops: None,
+            uncompressed_keys: false,
+            x_only_keys: false,
+        }
+    }
+
     fn sig_type() -> SigType { SigType::Ecdsa }
 }
 
@@ -630,10 +669,9 @@ impl ScriptContext for Tap {
 
     // No uncompressed keys in Tap context
     fn check_pk<Pk: MiniscriptKey>(pk: &Pk) -> Result<(), ScriptContextError> {
-        if pk.is_uncompressed() {
-            Err(ScriptContextError::UncompressedKeysNotAllowed)
-        } else {
-            Ok(())
+        match pk.uncompressed_key() {
+            Some(pk) => Err(ScriptContextError::CompressedOnly(pk)),
+            None => Ok(()),
         }
     }
 
@@ -731,6 +769,21 @@ impl ScriptContext for Tap {
     fn pk_len<Pk: MiniscriptKey>(_pk: &Pk) -> usize { 33 }
 
     fn name_str() -> &'static str { "TapscriptCtx" }
+
+    fn limits() -> ContextLimits {
+        ContextLimits {
+            max_script_size: Some(MAX_BLOCK_WEIGHT),
+            max_standard_script_size: None,
+            max_ops: None,
+            max_script_sig_size: None,
+            max_witness_elems: Some(MAX_STACK_SIZE),
+            max_multi_keys: None,
+            max_multi_a_keys: Some(MAX_PUBKEYS_IN_CHECKSIGADD),
+            max_standard_sigops: None,
+            uncompressed_keys: false,
+            x_only_keys: true,
+        }
+    }
 }
 
 /// Bare ScriptContext
@@ -754,7 +807,9 @@ impl ScriptContext for BareCtx {
 
     // No x-only keys in Bare context
     fn check_pk<Pk: MiniscriptKey>(pk: &Pk) -> Result<(), ScriptContextError> {
-        if pk.is_x_only_key() {
+        if pk.is_musig_key() {
+            Err(ScriptContextError::MusigKeysNotAllowed(pk.to_string(), Self::name_str()))
+        } else if pk.is_x_only_key() {
             Err(ScriptContextError::XOnlyKeysNotAllowed(pk.to_string(), Self::name_str()))
         } else {
             Ok(())
@@ -834,6 +889,21 @@ impl ScriptContext for BareCtx {
 
     fn name_str() -> &'static str { "BareCtx" }
 
+    fn limits() -> ContextLimits {
+        ContextLimits {
+            max_script_size: Some(MAX_SCRIPT_SIZE),
+            max_standard_script_size: None,
+            max_ops: Some(MAX_OPS_PER_SCRIPT),
+            max_script_sig_size: None,
+            max_witness_elems: None,
+            max_multi_keys: Some(MAX_PUBKEYS_PER_MULTISIG),
+            max_multi_a_keys: None,
+            max_standard_sigops: None,
+            uncompressed_keys: true,
+            x_only_keys: false,
+        }
+    }
+
     fn sig_type() -> SigType { SigType::Ecdsa }
 }
 
@@ -893,6 +963,21 @@ impl ScriptContext for NoChecks {
         "NochecksEcdsa"
     }
 
+    fn limits() -> ContextLimits {
+        ContextLimits {
+            max_script_size: None,
+            max_standard_script_size: None,
+            max_ops: None,
+            max_script_sig_size: None,
+            max_witness_elems: None,
+            max_multi_keys: Some(MAX_PUBKEYS_PER_MULTISIG),
+            max_multi_a_keys: Some(MAX_PUBKEYS_IN_CHECKSIGADD),
+            max_standard_sigops: None,
+            uncompressed_keys: true,
+            x_only_keys: true,
+        }
+    }
+
     fn check_witness(_witness: &[Vec<u8>]) -> Result<(), ScriptContextError> {
         // Only really need to do this for segwitv0 and legacy
         // Bare is already restrcited by standardness rules
@@ -14,7 +14,7 @@ use sync::Arc;
 
 use crate::iter::TreeLike;
 use crate::miniscript::lex::{Token as Tk, TokenIter};
-use crate::miniscript::limits::{MAX_PUBKEYS_IN_CHECKSIGADD, MAX_PUBKEYS_PER_MULTISIG};
+use crate::miniscript::limits::{Limits, MAX_PUBKEYS_IN_CHECKSIGADD, MAX_PUBKEYS_PER_MULTISIG};
 use crate::miniscript::ScriptContext;
 use crate::prelude::*;
 #[cfg(doc)]
@@ -316,17 +316,30 @@ macro_rules! match_token {
 
 ///Vec representing terminals stack while decoding.
 #[derive(Debug)]
-struct TerminalStack<Pk: MiniscriptKey, Ctx: ScriptContext>(Vec<Miniscript<Pk, Ctx>>);
+struct TerminalStack<'l, Pk: MiniscriptKey, Ctx: ScriptContext>(
+    Vec<Miniscript<Pk, Ctx>>,
+    /// The limits to check each node against, and the number of nodes decoded so far
+    Option<(&'l Limits, usize)>,
+);
 
-impl<Pk: MiniscriptKey, Ctx: ScriptContext> TerminalStack<Pk, Ctx> {
+impl<'l, Pk: MiniscriptKey, Ctx: ScriptContext> TerminalStack<'l, Pk, Ctx> {
     ///Wrapper around self.0.pop()
     fn pop(&mut self) -> Option<Miniscript<Pk, Ctx>> { self.0.pop() }
 
+    ///check a node against the limits and push it
+    fn push(&mut self, ms: Miniscript<Pk, Ctx>) -> Result<(), Error> {
+        if let Some((limits, ref mut nodes)) = self.1 {
+            *nodes += 1;
+            limits.check_fragment(&ms, *nodes)?;
+        }
+        self.0.push(ms);
+        Ok(())
+    }
+
     ///reduce, type check and push a 0-arg node
     fn reduce0(&mut self, ms: Terminal<Pk, Ctx>) -> Result<(), Error> {
         let ms = Miniscript::from_ast(ms)?;
-        self.0.push(ms);
-        Ok(())
+        self.push(ms)
     }
 
     ///reduce, type check and push a 1-arg node
@@ -355,12 +368,21 @@ impl<Pk: MiniscriptKey, Ctx: ScriptContext> TerminalStack<Pk, Ctx> {
 }
 
 /// Parse a script fragment into an `Miniscript`
-#[allow(unreachable_patterns)]
 pub fn parse<Ctx: ScriptContext>(
     tokens: &mut TokenIter,
+) -> Result<Miniscript<Ctx::Key, Ctx>, Error> {
+    parse_limited(tokens, None)
+}
+
+/// Parse a script fragment into an `Miniscript`, failing as soon as a decoded fragment
+/// exceeds the given limits
+#[allow(unreachable_patterns)]
+pub(super) fn parse_limited<Ctx: ScriptContext>(
+    tokens: &mut TokenIter,
+    limits: Option<&Limits>,
 ) -> Result<Miniscript<Ctx::Key, Ctx>, Error> {
     let mut non_term = Vec::with_capacity(tokens.len());
-    let mut term = TerminalStack(Vec::with_capacity(tokens.len()));
+    let mut term = TerminalStack(Vec::with_capacity(tokens.len()), limits.map(|l| (l, 0)));
 
     // top level cannot be swap, must be B
     non_term.push(NonTerm::MaybeAndV);
@@ -632,7 +654,7 @@ pub fn parse<Ctx: ScriptContext>(
                 let c = term.pop().unwrap();
                 let wrapped_ms = Terminal::AndOr(Arc::new(a), Arc::new(c), Arc::new(b));
 
-                term.0.push(Miniscript::from_ast(wrapped_ms)?);
+                term.push(Miniscript::from_ast(wrapped_ms)?)?;
             }
             Some(NonTerm::ThreshW { n, k }) => {
                 match_token!(
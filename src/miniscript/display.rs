@@ -10,11 +10,173 @@ use crate::iter::{Tree, TreeLike};
 use crate::miniscript::types::Type;
 use crate::miniscript::Terminal;
 use crate::prelude::sync::Arc;
-use crate::{Miniscript, MiniscriptKey, ScriptContext};
+use crate::prelude::*;
+use crate::{expression, Error, Miniscript, MiniscriptKey, ScriptContext};
+
+/// Which aliases are used when displaying a Miniscript.
+///
+/// Aliases are shorthands for some combinations of fragments, which are always accepted when
+/// parsing. By default all of them are used, which is the canonical form of a Miniscript. Use
+/// [`Aliases::detect`] to display a Miniscript or a descriptor the same way as the string it was
+/// parsed from, so that its checksum doesn't change.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Hash)]
+pub struct Aliases {
+    /// Display `c:pk_k(K)` as `pk(K)`
+    pub pk: bool,
+    /// Display `c:pk_h(K)` as `pkh(K)`
+    pub pkh: bool,
+    /// Display `andor(X,Y,0)` as `and_n(X,Y)`
+    pub and_n: bool,
+    /// Display `and_v(X,1)` as `t:X`
+    pub t: bool,
+    /// Display `or_i(0,X)` as `l:X`
+    pub l: bool,
+    /// Display `or_i(X,0)` as `u:X`
+    pub u: bool,
+}
+
+impl Aliases {
+    /// Use all the aliases, as done by `Display`
+    pub fn all() -> Aliases {
+        Aliases { pk: true, pkh: true, and_n: true, t: true, l: true, u: true }
+    }
+
+    /// Don't use any alias, displaying the desugared form of every fragment
+    pub fn none() -> Aliases {
+        Aliases { pk: false, pkh: false, and_n: false, t: false, l: false, u: false }
+    }
+
+    /// Detect which aliases are used in a Miniscript or descriptor string.
+    ///
+    /// Aliases which don't appear in the string, in either form, are considered used.
+    ///
+    /// Returns an error if the string can't be parsed as an expression, or if the same alias is
+    /// both used and not used, since such a string can't be reproduced.
+    pub fn detect(s: &str) -> Result<Aliases, AliasError> {
+        let top = if s.starts_with("tr(") {
+            crate::descriptor::parse_tr_tree_with_checksum(s).map_err(AliasError::Parse)?
+        } else {
+            expression::Tree::from_str(s).map_err(AliasError::Parse)?
+        };
+        let mut detector = AliasDetector::default();
+        detector.visit(&top)?;
+        Ok(detector.finish())
+    }
+}
+
+impl Default for Aliases {
+    fn default() -> Self { Aliases::all() }
+}
+
+/// The reason [`Aliases::detect`] couldn't detect the aliases of a string
+#[derive(Debug)]
+pub enum AliasError {
+    /// The string couldn't be parsed as an expression
+    Parse(Error),
+    /// The alias with this name is both used and not used
+    Inconsistent(&'static str),
+}
+
+impl fmt::Display for AliasError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            AliasError::Parse(ref e) => fmt::Display::fmt(e, f),
+            AliasError::Inconsistent(name) => {
+                write!(f, "alias {} is both used and not used", name)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for AliasError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match *self {
+            AliasError::Parse(ref e) => Some(e),
+            AliasError::Inconsistent(_) => None,
+        }
+    }
+}
+
+#[derive(Default)]
+struct AliasDetector {
+    pk: Option<bool>,
+    pkh: Option<bool>,
+    and_n: Option<bool>,
+    t: Option<bool>,
+    l: Option<bool>,
+    u: Option<bool>,
+}
+
+impl AliasDetector {
+    fn record(seen: &mut Option<bool>, used: bool, name: &'static str) -> Result<(), AliasError> {
+        match *seen {
+            Some(prev) if prev != used => Err(AliasError::Inconsistent(name)),
+            _ => {
+                *seen = Some(used);
+                Ok(())
+            }
+        }
+    }
+
+    fn visit(&mut self, tree: &expression::Tree) -> Result<(), AliasError> {
+        // Leaves of taproot trees are kept unparsed
+        if tree.name.contains('(') {
+            return self.visit(&expression::Tree::from_str(tree.name).map_err(AliasError::Parse)?);
+        }
+        let (wrappers, name) = match tree.name.find(':') {
+            Some(idx) => (&tree.name[..idx], &tree.name[idx + 1..]),
+            None => ("", tree.name),
+        };
+        for ch in wrappers.chars() {
+            match ch {
+                't' => Self::record(&mut self.t, true, "t")?,
+                'l' => Self::record(&mut self.l, true, "l")?,
+                'u' => Self::record(&mut self.u, true, "u")?,
+                _ => {}
+            }
+        }
+        let arg_is =
+            |idx: usize, value: &str| tree.args.get(idx).map(|arg| arg.name) == Some(value);
+        match name {
+            "pk" => Self::record(&mut self.pk, true, "pk")?,
+            "pkh" => Self::record(&mut self.pkh, true, "pkh")?,
+            "pk_k" if wrappers.ends_with('c') => Self::record(&mut self.pk, false, "pk")?,
+            "pk_h" if wrappers.ends_with('c') => Self::record(&mut self.pkh, false, "pkh")?,
+            "and_n" => Self::record(&mut self.and_n, true, "and_n")?,
+            "andor" if arg_is(2, "0") => Self::record(&mut self.and_n, false, "and_n")?,
+            "and_v" if arg_is(1, "1") => Self::record(&mut self.t, false, "t")?,
+            "or_i" => {
+                if arg_is(0, "0") {
+                    Self::record(&mut self.l, false, "l")?;
+                }
+                if arg_is(1, "0") {
+                    Self::record(&mut self.u, false, "u")?;
+                }
+            }
+            _ => {}
+        }
+        for arg in &tree.args {
+            self.visit(arg)?;
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> Aliases {
+        Aliases {
+            pk: self.pk.unwrap_or(true),
+            pkh: self.pkh.unwrap_or(true),
+            and_n: self.and_n.unwrap_or(true),
+            t: self.t.unwrap_or(true),
+            l: self.l.unwrap_or(true),
+            u: self.u.unwrap_or(true),
+        }
+    }
+}
 
 #[derive(Clone)]
 enum DisplayNode<'a, Pk: MiniscriptKey, Ctx: ScriptContext> {
-    Node(Type, &'a Terminal<Pk, Ctx>),
+    Node(Type, &'a Terminal<Pk, Ctx>, Aliases),
     ThresholdK(usize),
     Key(&'a Pk),
     RawKeyHash(&'a hash160::Hash),
@@ -28,7 +190,7 @@ enum DisplayNode<'a, Pk: MiniscriptKey, Ctx: ScriptContext> {
 
 #[derive(Clone)]
 enum NaryChildren<'a, Pk: MiniscriptKey, Ctx: ScriptContext> {
-    Nodes(usize, &'a [Arc<Miniscript<Pk, Ctx>>]),
+    Nodes(usize, &'a [Arc<Miniscript<Pk, Ctx>>], Aliases),
     Keys(usize, &'a [Pk]),
 }
 
@@ -37,7 +199,7 @@ impl<'a, Pk: MiniscriptKey, Ctx: ScriptContext> TreeLike for DisplayNode<'a, Pk,
 
     fn nary_len(tc: &Self::NaryChildren) -> usize {
         match tc {
-            NaryChildren::Nodes(_, n) => 1 + n.len(),
+            NaryChildren::Nodes(_, n, _) => 1 + n.len(),
             NaryChildren::Keys(_, k) => 1 + k.len(),
         }
     }
@@ -45,13 +207,13 @@ impl<'a, Pk: MiniscriptKey, Ctx: ScriptContext> TreeLike for DisplayNode<'a, Pk,
     fn nary_index(tc: Self::NaryChildren, idx: usize) -> Self {
         if idx == 0 {
             match tc {
-                NaryChildren::Nodes(k, _) => DisplayNode::ThresholdK(k),
+                NaryChildren::Nodes(k, _, _) => DisplayNode::ThresholdK(k),
                 NaryChildren::Keys(k, _) => DisplayNode::ThresholdK(k),
             }
         } else {
             match tc {
-                NaryChildren::Nodes(_, n) => {
-                    DisplayNode::Node(n[idx - 1].ty, n[idx - 1].as_inner())
+                NaryChildren::Nodes(_, n, aliases) => {
+                    DisplayNode::Node(n[idx - 1].ty, n[idx - 1].as_inner(), aliases)
                 }
                 NaryChildren::Keys(_, k) => DisplayNode::Key(&k[idx - 1]),
             }
@@ -60,77 +222,75 @@ impl<'a, Pk: MiniscriptKey, Ctx: ScriptContext> TreeLike for DisplayNode<'a, Pk,
 
     fn as_node(&self) -> Tree<Self, Self::NaryChildren> {
         match self {
-            DisplayNode::Node(_, ref node) => match node {
-                Terminal::True | Terminal::False => Tree::Nullary,
-                Terminal::PkK(ref pk) | Terminal::PkH(ref pk) => Tree::Unary(DisplayNode::Key(pk)),
-                Terminal::RawPkH(ref pkh) => Tree::Unary(DisplayNode::RawKeyHash(pkh)),
-                Terminal::After(ref t) => Tree::Unary(DisplayNode::After(t)),
-                Terminal::Older(ref t) => Tree::Unary(DisplayNode::Older(t)),
-                Terminal::Sha256(ref h) => Tree::Unary(DisplayNode::Sha256(h)),
-                Terminal::Hash256(ref h) => Tree::Unary(DisplayNode::Hash256(h)),
-                Terminal::Ripemd160(ref h) => Tree::Unary(DisplayNode::Ripemd160(h)),
-                Terminal::Hash160(ref h) => Tree::Unary(DisplayNode::Hash160(h)),
-                // Check hash to be treated specially as always..
-                Terminal::Check(ref sub) => match sub.as_inner() {
+            DisplayNode::Node(_, ref node, aliases) => {
+                let aliases = *aliases;
+                let child =
+                    |ms: &'a Miniscript<Pk, Ctx>| DisplayNode::Node(ms.ty, ms.as_inner(), aliases);
+                match node {
+                    Terminal::True | Terminal::False => Tree::Nullary,
                     Terminal::PkK(ref pk) | Terminal::PkH(ref pk) => {
                         Tree::Unary(DisplayNode::Key(pk))
                     }
                     Terminal::RawPkH(ref pkh) => Tree::Unary(DisplayNode::RawKeyHash(pkh)),
-                    _ => Tree::Unary(DisplayNode::Node(sub.ty, sub.as_inner())),
-                },
-                Terminal::Alt(ref sub)
-                | Terminal::Swap(ref sub)
-                | Terminal::DupIf(ref sub)
-                | Terminal::Verify(ref sub)
-                | Terminal::NonZero(ref sub)
-                | Terminal::ZeroNotEqual(ref sub) => {
-                    Tree::Unary(DisplayNode::Node(sub.ty, sub.as_inner()))
-                }
-                Terminal::AndV(ref left, ref right)
-                    if matches!(right.as_inner(), Terminal::True) =>
-                {
-                    Tree::Unary(DisplayNode::Node(left.ty, left.as_inner()))
-                }
-                Terminal::OrI(ref left, ref right)
-                    if matches!(left.as_inner(), Terminal::False) =>
-                {
-                    Tree::Unary(DisplayNode::Node(right.ty, right.as_inner()))
-                }
-                Terminal::OrI(ref left, ref right)
-                    if matches!(right.as_inner(), Terminal::False) =>
-                {
-                    Tree::Unary(DisplayNode::Node(left.ty, left.as_inner()))
-                }
-                Terminal::AndV(ref left, ref right)
-                | Terminal::AndB(ref left, ref right)
-                | Terminal::OrB(ref left, ref right)
-                | Terminal::OrD(ref left, ref right)
-                | Terminal::OrC(ref left, ref right)
-                | Terminal::OrI(ref left, ref right) => Tree::Binary(
-                    DisplayNode::Node(left.ty, left.as_inner()),
-                    DisplayNode::Node(right.ty, right.as_inner()),
-                ),
-                Terminal::AndOr(ref a, ref b, ref c) if matches!(c.as_inner(), Terminal::False) => {
-                    Tree::Binary(
-                        DisplayNode::Node(a.ty, a.as_inner()),
-                        DisplayNode::Node(b.ty, b.as_inner()),
-                    )
-                }
-                Terminal::AndOr(ref a, ref b, ref c) => Tree::Ternary(
-                    DisplayNode::Node(a.ty, a.as_inner()),
-                    DisplayNode::Node(b.ty, b.as_inner()),
-                    DisplayNode::Node(c.ty, c.as_inner()),
-                ),
-                Terminal::Thresh(ref thresh) => {
-                    Tree::Nary(NaryChildren::Nodes(thresh.k(), thresh.data()))
-                }
-                Terminal::Multi(ref thresh) => {
-                    Tree::Nary(NaryChildren::Keys(thresh.k(), thresh.data()))
-                }
-                Terminal::MultiA(ref thresh) => {
-                    Tree::Nary(NaryChildren::Keys(thresh.k(), thresh.data()))
+                    Terminal::After(ref t) => Tree::Unary(DisplayNode::After(t)),
+                    Terminal::Older(ref t) => Tree::Unary(DisplayNode::Older(t)),
+                    Terminal::Sha256(ref h) => Tree::Unary(DisplayNode::Sha256(h)),
+                    Terminal::Hash256(ref h) => Tree::Unary(DisplayNode::Hash256(h)),
+                    Terminal::Ripemd160(ref h) => Tree::Unary(DisplayNode::Ripemd160(h)),
+                    Terminal::Hash160(ref h) => Tree::Unary(DisplayNode::Hash160(h)),
+                    // Check hash to be treated specially as always..
+                    Terminal::Check(ref sub) => match sub.as_inner() {
+                        Terminal::PkK(ref pk) if aliases.pk => Tree::Unary(DisplayNode::Key(pk)),
+                        Terminal::PkH(ref pk) if aliases.pkh => Tree::Unary(DisplayNode::Key(pk)),
+                        Terminal::RawPkH(ref pkh) => Tree::Unary(DisplayNode::RawKeyHash(pkh)),
+                        _ => Tree::Unary(child(sub)),
+                    },
+                    Terminal::Alt(ref sub)
+                    | Terminal::Swap(ref sub)
+                    | Terminal::DupIf(ref sub)
+                    | Terminal::Verify(ref sub)
+                    | Terminal::NonZero(ref sub)
+                    | Terminal::ZeroNotEqual(ref sub) => Tree::Unary(child(sub)),
+                    Terminal::AndV(ref left, ref right)
+                        if aliases.t && matches!(right.as_inner(), Terminal::True) =>
+                    {
+                        Tree::Unary(child(left))
+                    }
+                    Terminal::OrI(ref left, ref right)
+                        if aliases.l && matches!(left.as_inner(), Terminal::False) =>
+                    {
+                        Tree::Unary(child(right))
+                    }
+                    Terminal::OrI(ref left, ref right)
+                        if aliases.u && matches!(right.as_inner(), Terminal::False) =>
+                    {
+                        Tree::Unary(child(left))
+                    }
+                    Terminal::AndV(ref left, ref right)
+                    | Terminal::AndB(ref left, ref right)
+                    | Terminal::OrB(ref left, ref right)
+                    | Terminal::OrD(ref left, ref right)
+                    | Terminal::OrC(ref left, ref right)
+                    | Terminal::OrI(ref left, ref right) => Tree::Binary(child(left), child(right)),
+                    Terminal::AndOr(ref a, ref b, ref c)
+                        if aliases.and_n && matches!(c.as_inner(), Terminal::False) =>
+                    {
+                        Tree::Binary(child(a), child(b))
+                    }
+                    Terminal::AndOr(ref a, ref b, ref c) => {
+                        Tree::Ternary(child(a), child(b), child(c))
+                    }
+                    Terminal::Thresh(ref thresh) => {
+                        Tree::Nary(NaryChildren::Nodes(thresh.k(), thresh.data(), aliases))
+                    }
+                    Terminal::Multi(ref thresh) => {
+                        Tree::Nary(NaryChildren::Keys(thresh.k(), thresh.data()))
+                    }
+                    Terminal::MultiA(ref thresh) => {
+                        Tree::Nary(NaryChildren::Keys(thresh.k(), thresh.data()))
+                    }
                 }
-            },
+            }
             // Only nodes have children; the rest are terminals.
             _ => Tree::Nullary,
         }
@@ -148,14 +308,19 @@ enum DisplayTypes {
 }
 
 impl<Pk: MiniscriptKey, Ctx: ScriptContext> Terminal<Pk, Ctx> {
-    fn conditional_fmt(&self, f: &mut fmt::Formatter, display_types: DisplayTypes) -> fmt::Result {
+    fn conditional_fmt(
+        &self,
+        f: &mut fmt::Formatter,
+        display_types: DisplayTypes,
+        aliases: Aliases,
+    ) -> fmt::Result {
         let initial_type = match display_types {
             DisplayTypes::None => Type::FALSE,
             DisplayTypes::All(ty) => ty,
             DisplayTypes::AllBadFirst => Type::FALSE,
         };
 
-        for item in DisplayNode::Node(initial_type, self).verbose_pre_order_iter() {
+        for item in DisplayNode::Node(initial_type, self, aliases).verbose_pre_order_iter() {
             let show_type = match display_types {
                 DisplayTypes::None => false,
                 DisplayTypes::All(_) => true,
@@ -163,8 +328,8 @@ impl<Pk: MiniscriptKey, Ctx: ScriptContext> Terminal<Pk, Ctx> {
             };
 
             match (display_types, item.node) {
-                (_, DisplayNode::Node(ty, node)) => {
-                    if node.is_wrapper() {
+                (_, DisplayNode::Node(ty, node, _)) => {
+                    if node.is_wrapper(aliases) {
                         // Wrappers are very easy: just write the one-character name and maybe the
                         // type and we are done. No parens, no :s, no commas, etc.
                         if item.n_children_yielded == 0 {
@@ -173,13 +338,13 @@ impl<Pk: MiniscriptKey, Ctx: ScriptContext> Terminal<Pk, Ctx> {
                                 fmt::Display::fmt(&ty, f)?;
                                 f.write_str("]")?;
                             }
-                            f.write_str(node.fragment_name())?;
+                            f.write_str(node.aliased_fragment_name(aliases))?;
                         }
                     } else {
                         // Non-wrappers are a little more involved.
                         if item.n_children_yielded == 0 {
-                            if let Some(DisplayNode::Node(_, parent)) = item.parent {
-                                if parent.is_wrapper() {
+                            if let Some(DisplayNode::Node(_, parent, _)) = item.parent {
+                                if parent.is_wrapper(aliases) {
                                     f.write_str(":")?;
                                 }
                             }
@@ -189,7 +354,7 @@ impl<Pk: MiniscriptKey, Ctx: ScriptContext> Terminal<Pk, Ctx> {
                                 fmt::Display::fmt(&ty, f)?;
                                 f.write_str("]")?;
                             }
-                            f.write_str(node.fragment_name())?;
+                            f.write_str(node.aliased_fragment_name(aliases))?;
 
                             if !item.is_complete {
                                 f.write_str("(")?;
@@ -231,7 +396,10 @@ impl<Pk: MiniscriptKey, Ctx: ScriptContext> Terminal<Pk, Ctx> {
     /// it does not contain or indicate any children.
     ///
     /// Not public since we intend to move it to the Inner type once that exists.
-    fn fragment_name(&self) -> &'static str {
+    fn fragment_name(&self) -> &'static str { self.aliased_fragment_name(Aliases::all()) }
+
+    /// The name of the fragment, when displayed with the given aliases.
+    fn aliased_fragment_name(&self, aliases: Aliases) -> &'static str {
         match *self {
             Terminal::True => "1",
             Terminal::False => "0",
@@ -248,8 +416,16 @@ impl<Pk: MiniscriptKey, Ctx: ScriptContext> Terminal<Pk, Ctx> {
             Terminal::Hash160(..) => "hash160",
             Terminal::Alt(..) => "a",
             Terminal::Swap(..) => "s",
-            Terminal::Check(ref sub) if matches!(sub.as_inner(), Terminal::PkK(..)) => "pk",
-            Terminal::Check(ref sub) if matches!(sub.as_inner(), Terminal::PkH(..)) => "pkh",
+            Terminal::Check(ref sub)
+                if aliases.pk && matches!(sub.as_inner(), Terminal::PkK(..)) =>
+            {
+                "pk"
+            }
+            Terminal::Check(ref sub)
+                if aliases.pkh && matches!(sub.as_inner(), Terminal::PkH(..)) =>
+            {
+                "pkh"
+            }
             Terminal::Check(ref sub) if matches!(sub.as_inner(), Terminal::RawPkH(..)) => {
                 "expr_raw_pkh"
             }
@@ -258,16 +434,20 @@ impl<Pk: MiniscriptKey, Ctx: ScriptContext> Terminal<Pk, Ctx> {
             Terminal::Verify(..) => "v",
             Terminal::NonZero(..) => "j",
             Terminal::ZeroNotEqual(..) => "n",
-            Terminal::AndV(_, ref r) if matches!(r.as_inner(), Terminal::True) => "t",
+            Terminal::AndV(_, ref r) if aliases.t && matches!(r.as_inner(), Terminal::True) => "t",
             Terminal::AndV(..) => "and_v",
-            Terminal::AndOr(_, _, ref c) if matches!(c.as_inner(), Terminal::False) => "and_n",
+            Terminal::AndOr(_, _, ref c)
+                if aliases.and_n && matches!(c.as_inner(), Terminal::False) =>
+            {
+                "and_n"
+            }
             Terminal::AndB(..) => "and_b",
             Terminal::AndOr(..) => "andor",
             Terminal::OrB(..) => "or_b",
             Terminal::OrD(..) => "or_d",
             Terminal::OrC(..) => "or_c",
-            Terminal::OrI(_, ref r) if matches!(r.as_inner(), Terminal::False) => "u",
-            Terminal::OrI(ref l, _) if matches!(l.as_inner(), Terminal::False) => "l",
+            Terminal::OrI(_, ref r) if aliases.u && matches!(r.as_inner(), Terminal::False) => "u",
+            Terminal::OrI(ref l, _) if aliases.l && matches!(l.as_inner(), Terminal::False) => "l",
             Terminal::OrI(..) => "or_i",
             Terminal::Thresh(..) => "thresh",
             Terminal::Multi(..) => "multi",
@@ -275,24 +455,175 @@ impl<Pk: MiniscriptKey, Ctx: ScriptContext> Terminal<Pk, Ctx> {
         }
     }
 
+    /// The name of the fragment without aliases, followed by its arguments which aren't
+    /// fragments, see [`Terminal::fmt_label`].
+    pub(crate) fn label(&self) -> String {
+        struct Label<'a, Pk: MiniscriptKey, Ctx: ScriptContext>(&'a Terminal<Pk, Ctx>);
+
+        impl<Pk: MiniscriptKey, Ctx: ScriptContext> fmt::Display for Label<'_, Pk, Ctx> {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { self.0.fmt_label(f) }
+        }
+
+        Label(self).to_string()
+    }
+
+    /// Writes the name of the fragment without aliases, followed by its arguments which aren't
+    /// fragments, e.g. `pk_k(A)`, `thresh(2)` or `c:`.
+    fn fmt_label(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fn fmt_multi<'a, Pk: MiniscriptKey + 'a>(
+            f: &mut fmt::Formatter,
+            name: &str,
+            k: usize,
+            keys: impl Iterator<Item = &'a Pk>,
+        ) -> fmt::Result {
+            write!(f, "{}({}", name, k)?;
+            for pk in keys {
+                write!(f, ",{}", pk)?;
+            }
+            f.write_str(")")
+        }
+
+        let name = self.aliased_fragment_name(Aliases::none());
+        match *self {
+            Terminal::PkK(ref pk) | Terminal::PkH(ref pk) => write!(f, "{}({})", name, pk),
+            Terminal::RawPkH(ref h) => write!(f, "{}({})", name, h),
+            Terminal::After(ref t) => write!(f, "{}({})", name, t),
+            Terminal::Older(ref t) => write!(f, "{}({})", name, t),
+            Terminal::Sha256(ref h) => write!(f, "{}({})", name, h),
+            Terminal::Hash256(ref h) => write!(f, "{}({})", name, h),
+            Terminal::Ripemd160(ref h) => write!(f, "{}({})", name, h),
+            Terminal::Hash160(ref h) => write!(f, "{}({})", name, h),
+            Terminal::Thresh(ref thresh) => write!(f, "{}({})", name, thresh.k()),
+            Terminal::Multi(ref thresh) => fmt_multi(f, name, thresh.k(), thresh.iter()),
+            Terminal::MultiA(ref thresh) => fmt_multi(f, name, thresh.k(), thresh.iter()),
+            _ if self.is_wrapper(Aliases::none()) => write!(f, "{}:", name),
+            _ => f.write_str(name),
+        }
+    }
+
     /// Whether the fragment in question is a "wrapper" such as `s:` or `a:`.
     ///
     /// Not public since we intend to move it to the Inner type once that exists.
-    fn is_wrapper(&self) -> bool {
-        !matches!(self, Terminal::True | Terminal::False) && self.fragment_name().len() == 1
+    fn is_wrapper(&self, aliases: Aliases) -> bool {
+        !matches!(self, Terminal::True | Terminal::False)
+            && self.aliased_fragment_name(aliases).len() == 1
     }
 }
 
 impl<Pk: MiniscriptKey, Ctx: ScriptContext> fmt::Debug for Miniscript<Pk, Ctx> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         self.as_inner()
-            .conditional_fmt(f, DisplayTypes::All(self.ty))
+            .conditional_fmt(f, DisplayTypes::All(self.ty), Aliases::all())
     }
 }
 
 impl<Pk: MiniscriptKey, Ctx: ScriptContext> fmt::Display for Miniscript<Pk, Ctx> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        self.as_inner().conditional_fmt(f, DisplayTypes::None)
+        self.as_inner()
+            .conditional_fmt(f, DisplayTypes::None, Aliases::all())
+    }
+}
+
+impl<Pk: MiniscriptKey, Ctx: ScriptContext> Miniscript<Pk, Ctx> {
+    /// Displays the Miniscript using only the given aliases.
+    ///
+    /// With [`Aliases::all`] this is the same as `to_string`.
+    pub fn to_string_with_aliases(&self, aliases: Aliases) -> String {
+        struct AliasedDisplay<'a, Pk: MiniscriptKey, Ctx: ScriptContext>(
+            &'a Terminal<Pk, Ctx>,
+            Aliases,
+        );
+
+        impl<Pk: MiniscriptKey, Ctx: ScriptContext> fmt::Display for AliasedDisplay<'_, Pk, Ctx> {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                self.0.conditional_fmt(f, DisplayTypes::None, self.1)
+            }
+        }
+
+        AliasedDisplay(self.as_inner(), aliases).to_string()
+    }
+}
+
+/// A Miniscript fragment annotated with its sizes and type, see [`Miniscript::annotate`]
+///
+/// Sizes are in bytes, and satisfaction sizes are given as a pair of the size of the witness
+/// stack and of the scriptSig, of which only one is used depending on the script context, as
+/// in [`crate::miniscript::types::extra_props::ExtData`].
+///
+/// The `Display` implementation renders the tree with one fragment per line, indented by
+/// depth, e.g. `or_d [B/fsm] script: 75, sat: 74/74, dissat: -`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Annotated<'a, Pk: MiniscriptKey, Ctx: ScriptContext> {
+    /// The annotated fragment
+    pub node: &'a Miniscript<Pk, Ctx>,
+    /// The size of the script of the fragment, including its children
+    pub script_size: usize,
+    /// The maximum size of a satisfaction, `None` if it can't be satisfied
+    pub max_sat_size: Option<(usize, usize)>,
+    /// The maximum size of a dissatisfaction, `None` if it can't be dissatisfied
+    pub max_dissat_size: Option<(usize, usize)>,
+    /// The type of the fragment
+    pub ty: Type,
+    /// The annotations of the children of the fragment
+    pub children: Vec<Annotated<'a, Pk, Ctx>>,
+}
+
+impl<Pk: MiniscriptKey, Ctx: ScriptContext> Miniscript<Pk, Ctx> {
+    /// Annotates each fragment of the Miniscript with its script size, maximum satisfaction
+    /// and dissatisfaction sizes and type, e.g. to see which branch makes a script larger than
+    /// expected.
+    ///
+    /// Aliases are expanded, so that wrappers such as `c:` or `t:` get their own annotation.
+    pub fn annotate(&self) -> Annotated<'_, Pk, Ctx> {
+        let mut annotated: Vec<Option<Annotated<Pk, Ctx>>> = vec![];
+        for item in self.post_order_iter() {
+            let children = item
+                .child_indices
+                .iter()
+                .map(|&idx| annotated[idx].take().expect("each child is visited once"))
+                .collect();
+            annotated.push(Some(Annotated {
+                node: item.node,
+                script_size: item.node.script_size(),
+                max_sat_size: item.node.ext.max_sat_size,
+                max_dissat_size: item.node.ext.max_dissat_size,
+                ty: item.node.ty,
+                children,
+            }));
+        }
+        annotated.pop().flatten().expect("the root is visited last")
+    }
+}
+
+impl<Pk: MiniscriptKey, Ctx: ScriptContext> fmt::Display for Annotated<'_, Pk, Ctx> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fn fmt_size(f: &mut fmt::Formatter, size: Option<(usize, usize)>) -> fmt::Result {
+            match size {
+                Some((witness, script_sig)) => write!(f, "{}/{}", witness, script_sig),
+                None => f.write_str("-"),
+            }
+        }
+
+        let mut stack = vec![(self, 0)];
+        while let Some((annotated, depth)) = stack.pop() {
+            if depth > 0 {
+                f.write_str("\n")?;
+            }
+            write!(f, "{:indent$}", "", indent = 2 * depth)?;
+            annotated.node.as_inner().fmt_label(f)?;
+            write!(f, " [{}] script: {}, sat: ", annotated.ty, annotated.script_size)?;
+            fmt_size(f, annotated.max_sat_size)?;
+            f.write_str(", dissat: ")?;
+            fmt_size(f, annotated.max_dissat_size)?;
+            stack.extend(
+                annotated
+                    .children
+                    .iter()
+                    .rev()
+                    .map(|child| (child, depth + 1)),
+            );
+        }
+        Ok(())
     }
 }
 
@@ -303,13 +634,13 @@ impl<Pk: MiniscriptKey, Ctx: ScriptContext> fmt::Debug for Terminal<Pk, Ctx> {
         } else {
             DisplayTypes::AllBadFirst
         };
-        self.conditional_fmt(f, display_types)
+        self.conditional_fmt(f, display_types, Aliases::all())
     }
 }
 
 impl<Pk: MiniscriptKey, Ctx: ScriptContext> fmt::Display for Terminal<Pk, Ctx> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        self.conditional_fmt(f, DisplayTypes::None)
+        self.conditional_fmt(f, DisplayTypes::None, Aliases::all())
     }
 }
 
@@ -326,12 +657,12 @@ impl<Pk: MiniscriptKey, Ctx: ScriptContext> Ord for Terminal<Pk, Ctx> {
             cmp::Ordering::Greater => cmp::Ordering::Greater,
             cmp::Ordering::Equal => {
                 // But if they are equal then we need to iterate
-                for (me, you) in DisplayNode::Node(Type::FALSE, self)
+                for (me, you) in DisplayNode::Node(Type::FALSE, self, Aliases::all())
                     .pre_order_iter()
-                    .zip(DisplayNode::Node(Type::FALSE, other).pre_order_iter())
+                    .zip(DisplayNode::Node(Type::FALSE, other, Aliases::all()).pre_order_iter())
                 {
                     let me_you_cmp = match (me, you) {
-                        (DisplayNode::Node(_, me), DisplayNode::Node(_, you)) => {
+                        (DisplayNode::Node(_, me, _), DisplayNode::Node(_, you, _)) => {
                             me.fragment_name().cmp(you.fragment_name())
                         }
                         (DisplayNode::ThresholdK(me), DisplayNode::ThresholdK(you)) => me.cmp(&you),
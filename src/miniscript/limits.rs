@@ -4,6 +4,13 @@
 //! These constraints can be either Consensus or Policy (standardness) rules, for either Segwitv0
 //! or Legacy scripts.
 
+use core::fmt;
+#[cfg(feature = "std")]
+use std::error;
+
+use crate::prelude::*;
+use crate::{Miniscript, MiniscriptKey, ScriptContext, MAX_RECURSION_DEPTH};
+
 /// Maximum operations per script
 // https://github.com/bitcoin/bitcoin/blob/875e1ccc9fe01e026e564dfd39a64d9a4b332a89/src/script/script.h#L26
 pub const MAX_OPS_PER_SCRIPT: usize = 201;
@@ -33,6 +40,186 @@ pub const MAX_BLOCK_WEIGHT: usize = 4000000;
 /// Maximum pubkeys as arguments to CHECKMULTISIG
 // https://github.com/bitcoin/bitcoin/blob/6acda4b00b3fc1bfac02f5de590e1a5386cbc779/src/script/script.h#L30
 pub const MAX_PUBKEYS_PER_MULTISIG: usize = 20;
+/// Maximum signature operations in a P2SH redeem script allowed by standardness rules
+// https://github.com/bitcoin/bitcoin/blob/v25.0/src/policy/policy.h
+pub const MAX_P2SH_SIGOPS: usize = 15;
 /// Maximum pubkeys in a CHECKSIGADD construction.
 // https://github.com/bitcoin/bitcoin/blob/99b06b7f1d4194fb8036b90e5308101645f968e7/src/script/script.h#L36
 pub const MAX_PUBKEYS_IN_CHECKSIGADD: usize = 999;
+
+/// The consensus and standardness limits of a script context, as enforced by this library
+///
+/// Obtained with [`ScriptContext::limits`]. The values are the `MAX_*` constants of this module
+/// which the context checks against. A `None` limit is not checked in the context.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Hash)]
+pub struct ContextLimits {
+    /// Maximum size of the script allowed by consensus rules, in bytes
+    pub max_script_size: Option<usize>,
+    /// Maximum size of the script allowed by standardness rules, in bytes
+    pub max_standard_script_size: Option<usize>,
+    /// Maximum number of non-push opcodes executed by a satisfaction
+    pub max_ops: Option<usize>,
+    /// Maximum size of the scriptSig of a satisfaction allowed by standardness rules, in bytes
+    pub max_script_sig_size: Option<usize>,
+    /// Maximum number of witness elements of a satisfaction, not counting the witness script
+    pub max_witness_elems: Option<usize>,
+    /// Maximum number of keys in a `multi`, or `None` if `multi` isn't allowed
+    pub max_multi_keys: Option<usize>,
+    /// Maximum number of keys in a `multi_a`, or `None` if `multi_a` isn't allowed
+    pub max_multi_a_keys: Option<usize>,
+    /// Maximum number of signature operations allowed by standardness rules, see
+    /// [`Miniscript::sigop_count`]
+    pub max_standard_sigops: Option<usize>,
+    /// Whether uncompressed public keys are allowed
+    pub uncompressed_keys: bool,
+    /// Whether x-only public keys are allowed
+    pub x_only_keys: bool,
+}
+
+/// Resource limits checked when parsing or satisfying a Miniscript, on top of the rules of its
+/// script context.
+///
+/// Used as a parameter of [`Miniscript::from_str_with_limits`], [`Miniscript::parse_with_limits`]
+/// and [`Miniscript::satisfy_with_limits`]. The default limits are the ones the library always
+/// enforces, so they don't restrict anything further. Limits can only tighten the rules of the
+/// script context: a Miniscript which exceeds consensus or standardness limits is rejected
+/// whatever these values are, and the tree height can't be raised above the recursion limit of
+/// the library (402).
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Hash)]
+pub struct Limits {
+    /// Maximum height of the Miniscript tree
+    pub max_depth: usize,
+    /// Maximum number of fragments in the Miniscript, including wrappers
+    pub max_nodes: usize,
+    /// Maximum size of the encoded script, in bytes
+    pub max_script_size: usize,
+    /// Maximum number of witness elements needed to satisfy the Miniscript, not counting the
+    /// witness script
+    pub max_witness_elems: usize,
+}
+
+impl Limits {
+    /// Create new limits which don't restrict anything more than the script context
+    pub fn new() -> Limits {
+        Limits {
+            max_depth: MAX_RECURSION_DEPTH as usize,
+            max_nodes: usize::MAX,
+            max_script_size: usize::MAX,
+            max_witness_elems: usize::MAX,
+        }
+    }
+
+    /// Check a Miniscript against these limits
+    ///
+    /// The number of witness elements is the one of the largest satisfaction, not counting the
+    /// witness script. Miniscripts which can't be satisfied are not rejected on this limit.
+    pub fn check<Pk: MiniscriptKey, Ctx: ScriptContext>(
+        &self,
+        ms: &Miniscript<Pk, Ctx>,
+    ) -> Result<(), LimitsError> {
+        self.check_depth(ms.ext.tree_height)?;
+        self.check_nodes(ms.iter().count())?;
+        self.check_script_size(ms.script_size())?;
+        if let Some(elems) = ms.ext.stack_elem_count_sat {
+            self.check_witness_elems(elems)?;
+        }
+        Ok(())
+    }
+
+    /// Check a fragment while the Miniscript is being built, given the number of fragments
+    /// built so far, so that parsers can stop as soon as a limit is exceeded
+    pub(crate) fn check_fragment<Pk: MiniscriptKey, Ctx: ScriptContext>(
+        &self,
+        ms: &Miniscript<Pk, Ctx>,
+        nodes: usize,
+    ) -> Result<(), LimitsError> {
+        self.check_depth(ms.ext.tree_height)?;
+        self.check_nodes(nodes)?;
+        self.check_script_size(ms.ext.pk_cost)
+    }
+
+    pub(crate) fn check_depth(&self, depth: usize) -> Result<(), LimitsError> {
+        if depth > self.max_depth {
+            return Err(LimitsError::MaxDepthExceeded { actual: depth, limit: self.max_depth });
+        }
+        Ok(())
+    }
+
+    pub(crate) fn check_nodes(&self, nodes: usize) -> Result<(), LimitsError> {
+        if nodes > self.max_nodes {
+            return Err(LimitsError::MaxNodesExceeded { actual: nodes, limit: self.max_nodes });
+        }
+        Ok(())
+    }
+
+    fn check_script_size(&self, script_size: usize) -> Result<(), LimitsError> {
+        if script_size > self.max_script_size {
+            return Err(LimitsError::MaxScriptSizeExceeded {
+                actual: script_size,
+                limit: self.max_script_size,
+            });
+        }
+        Ok(())
+    }
+
+    /// Check a satisfaction witness against these limits
+    pub fn check_witness(&self, witness: &[Vec<u8>]) -> Result<(), LimitsError> {
+        self.check_witness_elems(witness.len())
+    }
+
+    fn check_witness_elems(&self, elems: usize) -> Result<(), LimitsError> {
+        if elems > self.max_witness_elems {
+            return Err(LimitsError::MaxWitnessElemsExceeded {
+                actual: elems,
+                limit: self.max_witness_elems,
+            });
+        }
+        Ok(())
+    }
+}
+
+impl Default for Limits {
+    fn default() -> Self { Limits::new() }
+}
+
+/// A Miniscript or one of its satisfactions exceeds the configured [`Limits`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[allow(missing_docs)]
+pub enum LimitsError {
+    /// The Miniscript tree is higher than allowed
+    MaxDepthExceeded { actual: usize, limit: usize },
+    /// The Miniscript has more fragments than allowed
+    MaxNodesExceeded { actual: usize, limit: usize },
+    /// The encoded script is larger than allowed
+    MaxScriptSizeExceeded { actual: usize, limit: usize },
+    /// A satisfaction has more witness elements than allowed
+    MaxWitnessElemsExceeded { actual: usize, limit: usize },
+}
+
+impl fmt::Display for LimitsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LimitsError::MaxDepthExceeded { actual, limit } => {
+                write!(f, "Miniscript tree has height {} (limit: {})", actual, limit)
+            }
+            LimitsError::MaxNodesExceeded { actual, limit } => {
+                write!(f, "Miniscript has {} fragments (limit: {})", actual, limit)
+            }
+            LimitsError::MaxScriptSizeExceeded { actual, limit } => {
+                write!(f, "Miniscript script is {} bytes (limit: {})", actual, limit)
+            }
+            LimitsError::MaxWitnessElemsExceeded { actual, limit } => {
+                write!(
+                    f,
+                    "Miniscript satisfaction has {} witness elements (limit: {})",
+                    actual, limit
+                )
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl error::Error for LimitsError {
+    fn cause(&self) -> Option<&dyn error::Error> { None }
+}
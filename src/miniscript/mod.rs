@@ -20,6 +20,8 @@ use bitcoin::taproot::{LeafVersion, TapLeafHash};
 
 use self::analyzable::ExtParams;
 pub use self::context::{BareCtx, Legacy, Segwitv0, Tap};
+pub use self::display::{AliasError, Aliases, Annotated};
+pub use self::thresh_builder::ThreshBuilder;
 use crate::iter::TreeLike;
 use crate::prelude::*;
 use crate::{script_num_size, TranslateErr};
@@ -33,6 +35,8 @@ pub mod iter;
 pub mod lex;
 pub mod limits;
 pub mod satisfy;
+pub mod shuffle;
+mod thresh_builder;
 pub mod types;
 
 use core::cmp;
@@ -40,10 +44,12 @@ use core::cmp;
 use sync::Arc;
 
 use self::lex::{lex, TokenIter};
+use self::limits::{LimitsError, MAX_PUBKEYS_IN_CHECKSIGADD, MAX_PUBKEYS_PER_MULTISIG};
 pub use crate::miniscript::context::ScriptContext;
 use crate::miniscript::decode::Terminal;
 use crate::{
-    expression, plan, Error, ForEachKey, FromStrKey, MiniscriptKey, ToPublicKey, Translator,
+    expression, plan, AbsLockTime, Error, ForEachKey, FromStrKey, Limits, MiniscriptKey,
+    RelLockTime, Threshold, ToPublicKey, Translator,
 };
 #[cfg(test)]
 mod ms_tests;
@@ -313,6 +319,42 @@ impl<Pk: MiniscriptKey, Ctx: ScriptContext> Miniscript<Pk, Ctx> {
         self._satisfy(satisfaction)
     }
 
+    /// Attempt to produce non-malleable satisfying witness for the
+    /// witness script represented by the parse tree, within the given [`Limits`]
+    ///
+    /// Satisfactions of sub-fragments which need too many witness elements are discarded
+    /// during the search, so a satisfaction within the limits is found if there is one.
+    pub fn satisfy_with_limits<S: satisfy::Satisfier<Pk>>(
+        &self,
+        satisfier: S,
+        limits: &Limits,
+    ) -> Result<Vec<Vec<u8>>, Error>
+    where
+        Pk: ToPublicKey,
+    {
+        let (satisfaction, min_discarded) = satisfy::Satisfaction::satisfy_with_max_elems(
+            &self.node,
+            &satisfier,
+            self.ty.mall.safe,
+            &self.leaf_hash_internal(),
+            limits.max_witness_elems,
+        );
+        match (self._satisfy(satisfaction), min_discarded) {
+            (Ok(witness), _) => {
+                limits.check_witness(&witness)?;
+                Ok(witness)
+            }
+            (Err(Error::CouldNotSatisfy), Some(actual)) => {
+                Err(LimitsError::MaxWitnessElemsExceeded {
+                    actual,
+                    limit: limits.max_witness_elems,
+                }
+                .into())
+            }
+            (Err(e), _) => Err(e),
+        }
+    }
+
     /// Attempt to produce a malleable satisfying witness for the
     /// witness script represented by the parse tree
     pub fn satisfy_malleable<S: satisfy::Satisfier<Pk>>(
@@ -379,6 +421,150 @@ impl<Pk: MiniscriptKey, Ctx: ScriptContext> Miniscript<Pk, Ctx> {
     }
 }
 
+/// Typechecked constructors for every miniscript fragment.
+///
+/// These are thin wrappers around [`Miniscript::from_ast`]: each of them builds the
+/// corresponding [`Terminal`] and type checks it, so that programmatic construction does
+/// not need to go through string formatting and reparsing. Sub-fragments can be passed
+/// either as owned [`Miniscript`]s or as `Arc`s, in which case they are shared.
+impl<Pk: MiniscriptKey, Ctx: ScriptContext> Miniscript<Pk, Ctx> {
+    /// Constructs the `pk_k(key)` fragment.
+    pub fn pk_k(pk: Pk) -> Result<Self, Error> { Self::from_ast(Terminal::PkK(pk)) }
+
+    /// Constructs the `pk_h(key)` fragment.
+    pub fn pk_h(pk: Pk) -> Result<Self, Error> { Self::from_ast(Terminal::PkH(pk)) }
+
+    /// Constructs the `pk(key)` fragment, an alias for `c:pk_k(key)`.
+    pub fn pk(pk: Pk) -> Result<Self, Error> { Self::check(Self::pk_k(pk)?) }
+
+    /// Constructs the `pkh(key)` fragment, an alias for `c:pk_h(key)`.
+    pub fn pkh(pk: Pk) -> Result<Self, Error> { Self::check(Self::pk_h(pk)?) }
+
+    /// Constructs the `after(n)` fragment.
+    pub fn after(n: AbsLockTime) -> Result<Self, Error> { Self::from_ast(Terminal::After(n)) }
+
+    /// Constructs the `older(n)` fragment.
+    pub fn older(n: RelLockTime) -> Result<Self, Error> { Self::from_ast(Terminal::Older(n)) }
+
+    /// Constructs the `sha256(h)` fragment.
+    pub fn sha256(hash: Pk::Sha256) -> Result<Self, Error> {
+        Self::from_ast(Terminal::Sha256(hash))
+    }
+
+    /// Constructs the `hash256(h)` fragment.
+    pub fn hash256(hash: Pk::Hash256) -> Result<Self, Error> {
+        Self::from_ast(Terminal::Hash256(hash))
+    }
+
+    /// Constructs the `ripemd160(h)` fragment.
+    pub fn ripemd160(hash: Pk::Ripemd160) -> Result<Self, Error> {
+        Self::from_ast(Terminal::Ripemd160(hash))
+    }
+
+    /// Constructs the `hash160(h)` fragment.
+    pub fn hash160(hash: Pk::Hash160) -> Result<Self, Error> {
+        Self::from_ast(Terminal::Hash160(hash))
+    }
+
+    /// Constructs the `a:X` wrapper.
+    pub fn alt(sub: impl Into<Arc<Self>>) -> Result<Self, Error> {
+        Self::from_ast(Terminal::Alt(sub.into()))
+    }
+
+    /// Constructs the `s:X` wrapper.
+    pub fn swap(sub: impl Into<Arc<Self>>) -> Result<Self, Error> {
+        Self::from_ast(Terminal::Swap(sub.into()))
+    }
+
+    /// Constructs the `c:X` wrapper.
+    pub fn check(sub: impl Into<Arc<Self>>) -> Result<Self, Error> {
+        Self::from_ast(Terminal::Check(sub.into()))
+    }
+
+    /// Constructs the `d:X` wrapper.
+    pub fn dup_if(sub: impl Into<Arc<Self>>) -> Result<Self, Error> {
+        Self::from_ast(Terminal::DupIf(sub.into()))
+    }
+
+    /// Constructs the `v:X` wrapper.
+    pub fn verify(sub: impl Into<Arc<Self>>) -> Result<Self, Error> {
+        Self::from_ast(Terminal::Verify(sub.into()))
+    }
+
+    /// Constructs the `j:X` wrapper.
+    pub fn non_zero(sub: impl Into<Arc<Self>>) -> Result<Self, Error> {
+        Self::from_ast(Terminal::NonZero(sub.into()))
+    }
+
+    /// Constructs the `n:X` wrapper.
+    pub fn zero_not_equal(sub: impl Into<Arc<Self>>) -> Result<Self, Error> {
+        Self::from_ast(Terminal::ZeroNotEqual(sub.into()))
+    }
+
+    /// Constructs the `and_v(X,Y)` fragment.
+    pub fn and_v(left: impl Into<Arc<Self>>, right: impl Into<Arc<Self>>) -> Result<Self, Error> {
+        Self::from_ast(Terminal::AndV(left.into(), right.into()))
+    }
+
+    /// Constructs the `and_b(X,Y)` fragment.
+    pub fn and_b(left: impl Into<Arc<Self>>, right: impl Into<Arc<Self>>) -> Result<Self, Error> {
+        Self::from_ast(Terminal::AndB(left.into(), right.into()))
+    }
+
+    /// Constructs the `and_n(X,Y)` fragment, an alias for `andor(X,Y,0)`.
+    pub fn and_n(left: impl Into<Arc<Self>>, right: impl Into<Arc<Self>>) -> Result<Self, Error> {
+        Self::and_or(left, right, Self::FALSE)
+    }
+
+    /// Constructs the `andor(X,Y,Z)` fragment.
+    pub fn and_or(
+        cond: impl Into<Arc<Self>>,
+        left: impl Into<Arc<Self>>,
+        right: impl Into<Arc<Self>>,
+    ) -> Result<Self, Error> {
+        Self::from_ast(Terminal::AndOr(cond.into(), left.into(), right.into()))
+    }
+
+    /// Constructs the `or_b(X,Z)` fragment.
+    pub fn or_b(left: impl Into<Arc<Self>>, right: impl Into<Arc<Self>>) -> Result<Self, Error> {
+        Self::from_ast(Terminal::OrB(left.into(), right.into()))
+    }
+
+    /// Constructs the `or_d(X,Z)` fragment.
+    pub fn or_d(left: impl Into<Arc<Self>>, right: impl Into<Arc<Self>>) -> Result<Self, Error> {
+        Self::from_ast(Terminal::OrD(left.into(), right.into()))
+    }
+
+    /// Constructs the `or_c(X,Z)` fragment.
+    pub fn or_c(left: impl Into<Arc<Self>>, right: impl Into<Arc<Self>>) -> Result<Self, Error> {
+        Self::from_ast(Terminal::OrC(left.into(), right.into()))
+    }
+
+    /// Constructs the `or_i(X,Z)` fragment.
+    pub fn or_i(left: impl Into<Arc<Self>>, right: impl Into<Arc<Self>>) -> Result<Self, Error> {
+        Self::from_ast(Terminal::OrI(left.into(), right.into()))
+    }
+
+    /// Constructs the `thresh(k,X1,...,Xn)` fragment.
+    pub fn thresh(thresh: Threshold<Arc<Self>, 0>) -> Result<Self, Error> {
+        Self::from_ast(Terminal::Thresh(thresh))
+    }
+
+    /// Constructs the `multi(k,key1,...,keyn)` fragment.
+    ///
+    /// Fails in contexts where `multi` is not allowed, such as Tapscript.
+    pub fn multi(thresh: Threshold<Pk, MAX_PUBKEYS_PER_MULTISIG>) -> Result<Self, Error> {
+        Self::from_ast(Terminal::Multi(thresh))
+    }
+
+    /// Constructs the `multi_a(k,key1,...,keyn)` fragment.
+    ///
+    /// Fails in contexts where `multi_a` is not allowed, i.e. outside of Tapscript.
+    pub fn multi_a(thresh: Threshold<Pk, MAX_PUBKEYS_IN_CHECKSIGADD>) -> Result<Self, Error> {
+        Self::from_ast(Terminal::MultiA(thresh))
+    }
+}
+
 impl Miniscript<<Tap as ScriptContext>::Key, Tap> {
     /// Returns the leaf hash used within a Taproot signature for this script.
     ///
@@ -408,11 +594,19 @@ impl<Ctx: ScriptContext> Miniscript<Ctx::Key, Ctx> {
     pub fn parse_with_ext(
         script: &script::Script,
         ext: &ExtParams,
+    ) -> Result<Miniscript<Ctx::Key, Ctx>, Error> {
+        Self::parse_limited(script, ext, None)
+    }
+
+    fn parse_limited(
+        script: &script::Script,
+        ext: &ExtParams,
+        limits: Option<&Limits>,
     ) -> Result<Miniscript<Ctx::Key, Ctx>, Error> {
         let tokens = lex(script)?;
         let mut iter = TokenIter::new(tokens);
 
-        let top = decode::parse(&mut iter)?;
+        let top = decode::parse_limited(&mut iter, limits)?;
         Ctx::check_global_validity(&top)?;
         let type_check = types::Type::type_check(&top.node)?;
         if type_check.corr.base != types::Base::B {
@@ -463,6 +657,29 @@ impl<Ctx: ScriptContext> Miniscript<Ctx::Key, Ctx> {
         let ms = Self::parse_with_ext(script, &ExtParams::sane())?;
         Ok(ms)
     }
+
+    /// Attempt to parse a Script into Miniscript representation, rejecting
+    /// Miniscripts which exceed the given [`Limits`].
+    ///
+    /// Decoding stops at the first fragment exceeding the limits, so the error reports the
+    /// values at that point rather than the ones of the whole script.
+    ///
+    /// Sanity checks are performed as in [`Miniscript::parse`].
+    pub fn parse_with_limits(
+        script: &script::Script,
+        limits: &Limits,
+    ) -> Result<Miniscript<Ctx::Key, Ctx>, Error> {
+        if script.len() > limits.max_script_size {
+            return Err(LimitsError::MaxScriptSizeExceeded {
+                actual: script.len(),
+                limit: limits.max_script_size,
+            }
+            .into());
+        }
+        let ms = Self::parse_limited(script, &ExtParams::sane(), Some(limits))?;
+        limits.check(&ms)?;
+        Ok(ms)
+    }
 }
 
 /// `PartialOrd` of `Miniscript` must depend only on node and not the type information.
@@ -546,7 +763,7 @@ impl<Pk: MiniscriptKey, Ctx: ScriptContext> Miniscript<Pk, Ctx> {
         self.translate_pk_ctx(t)
     }
 
-    pub(super) fn translate_pk_ctx<CtxQ, T>(
+    pub(crate) fn translate_pk_ctx<CtxQ, T>(
         &self,
         t: &mut T,
     ) -> Result<Miniscript<T::TargetPk, CtxQ>, TranslateErr<T::Error>>
@@ -615,14 +832,25 @@ impl<Pk: MiniscriptKey, Ctx: ScriptContext> Miniscript<Pk, Ctx> {
 
     /// Substitutes raw public keys hashes with the public keys as provided by map.
     pub fn substitute_raw_pkh(&self, pk_map: &BTreeMap<hash160::Hash, Pk>) -> Miniscript<Pk, Ctx> {
+        self.resolve_raw_pkh(pk_map)
+    }
+
+    /// Substitutes raw public keys hashes with the public keys resolved by `resolver`.
+    ///
+    /// Hashes the resolver doesn't know are left as is. The keys are trusted to
+    /// match the hashes, which isn't checked.
+    pub fn resolve_raw_pkh<R: satisfy::PkhResolver<Pk>>(
+        &self,
+        resolver: &R,
+    ) -> Miniscript<Pk, Ctx> {
         let mut stack = vec![];
         for item in self.rtl_post_order_iter() {
             let new_term = match item.node.node {
                 Terminal::PkK(ref p) => Terminal::PkK(p.clone()),
                 Terminal::PkH(ref p) => Terminal::PkH(p.clone()),
                 // This algorithm is identical to Clone::clone except for this line.
-                Terminal::RawPkH(ref hash) => match pk_map.get(hash) {
-                    Some(p) => Terminal::PkH(p.clone()),
+                Terminal::RawPkH(ref hash) => match resolver.resolve_pkh(hash) {
+                    Some(p) => Terminal::PkH(p),
                     None => Terminal::RawPkH(*hash),
                 },
                 Terminal::After(ref n) => Terminal::After(*n),
@@ -790,6 +1018,48 @@ impl<Pk: FromStrKey, Ctx: ScriptContext> Miniscript<Pk, Ctx> {
             Ok(ms)
         }
     }
+
+    /// Attempt to parse a Miniscript from string, rejecting Miniscripts which
+    /// exceed the given [`Limits`].
+    ///
+    /// Parsing stops at the first fragment exceeding the limits, so the error reports the
+    /// values at that point rather than the ones of the whole Miniscript.
+    ///
+    /// Sanity checks are performed as in [`str::FromStr`].
+    pub fn from_str_with_limits(s: &str, limits: &Limits) -> Result<Miniscript<Pk, Ctx>, Error> {
+        let top = expression::Tree::from_str(s)?;
+        let ms = Self::from_tree_limited(&top, limits, 0, &mut 0)?;
+        ms.ext_check(&ExtParams::sane())?;
+        if ms.ty.corr.base != types::Base::B {
+            return Err(Error::NonTopLevel(format!("{:?}", ms)));
+        }
+        limits.check(&ms)?;
+        Ok(ms)
+    }
+
+    /// Parse an expression tree at the given height into a Miniscript, failing as soon as
+    /// the fragments parsed so far exceed the limits
+    fn from_tree_limited(
+        top: &expression::Tree,
+        limits: &Limits,
+        height: usize,
+        nodes: &mut usize,
+    ) -> Result<Miniscript<Pk, Ctx>, Error> {
+        // Check what the name tells before parsing the arguments: the wrappers are fragments
+        // of their own, and each of them adds a level to the tree.
+        let (_, frag_wrap) = split_expression_name(top.name)?;
+        let height = height + frag_wrap.len();
+        *nodes += 1 + frag_wrap.len();
+        limits.check_depth(height)?;
+        limits.check_nodes(*nodes)?;
+
+        let node = Terminal::from_tree_with(top, &mut |child| {
+            Self::from_tree_limited(child, limits, height + 1, nodes).map(Arc::new)
+        })?;
+        let ms = Miniscript::from_ast(node)?;
+        limits.check_fragment(&ms, *nodes)?;
+        Ok(ms)
+    }
 }
 
 impl<Pk: FromStrKey, Ctx: ScriptContext> crate::expression::FromTree for Arc<Miniscript<Pk, Ctx>> {
@@ -843,12 +1113,14 @@ mod tests {
     use sync::Arc;
 
     use super::{Miniscript, ScriptContext, Segwitv0, Tap};
+    use crate::miniscript::limits::{self, LimitsError};
     use crate::miniscript::{types, Terminal};
     use crate::policy::Liftable;
     use crate::prelude::*;
     use crate::test_utils::{StrKeyTranslator, StrXOnlyKeyTranslator};
     use crate::{
-        hex_script, BareCtx, Error, ExtParams, Legacy, RelLockTime, Satisfier, ToPublicKey,
+        hex_script, AliasError, Aliases, BareCtx, Error, ExtParams, Legacy, Limits, RelLockTime,
+        Satisfier, ToPublicKey,
     };
 
     type Segwitv0Script = Miniscript<bitcoin::PublicKey, Segwitv0>;
@@ -1637,6 +1909,45 @@ mod tests {
         }
     }
 
+    #[test]
+    fn fragment_constructors() {
+        type StrMs = Miniscript<String, Segwitv0>;
+
+        let pk_a = StrMs::pk("A".to_owned()).unwrap();
+        let pk_b = StrMs::pk("B".to_owned()).unwrap();
+        let older = StrMs::older(RelLockTime::from_height(144)).unwrap();
+        let ms =
+            StrMs::or_d(pk_a, StrMs::and_v(StrMs::verify(pk_b).unwrap(), older).unwrap()).unwrap();
+        assert_eq!(ms, StrMs::from_str("or_d(pk(A),and_v(v:pk(B),older(144)))").unwrap());
+        assert_eq!(ms.ty, StrMs::from_str(&ms.to_string()).unwrap().ty);
+
+        // Shared sub-fragments can be passed as `Arc`s.
+        let shared = Arc::new(StrMs::pk_k("C".to_owned()).unwrap());
+        let thresh = crate::Threshold::new(
+            1,
+            vec![
+                Arc::new(StrMs::check(Arc::clone(&shared)).unwrap()),
+                Arc::new(StrMs::swap(StrMs::pk("D".to_owned()).unwrap()).unwrap()),
+            ],
+        )
+        .unwrap();
+        assert_eq!(StrMs::thresh(thresh).unwrap().to_string(), "thresh(1,pk(C),s:pk(D))");
+        assert_eq!(
+            StrMs::and_n(StrMs::pk("E".to_owned()).unwrap(), StrMs::TRUE)
+                .unwrap()
+                .to_string(),
+            "and_n(pk(E),1)"
+        );
+
+        // Type checking happens at construction time.
+        assert!(StrMs::and_v(StrMs::pk("A".to_owned()).unwrap(), StrMs::TRUE).is_err());
+        assert!(StrMs::check(StrMs::TRUE).is_err());
+        // Context checks too.
+        let multi_thresh = crate::Threshold::new(1, vec!["A".to_owned()]).unwrap();
+        assert!(StrMs::multi(multi_thresh.clone()).is_ok());
+        assert!(Miniscript::<String, Tap>::multi(multi_thresh).is_err());
+    }
+
     #[test]
     fn test_dos() {
         let ms = "slnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnnn:0";
@@ -1715,4 +2026,639 @@ mod tests {
             "The Miniscript corresponding Script cannot be larger than 10000 bytes, but got 10275 bytes."
         );
     }
+
+    #[test]
+    fn limits() {
+        fn limits_err<T: core::fmt::Debug>(res: Result<T, Error>) -> LimitsError {
+            match res {
+                Err(Error::Limits(e)) => e,
+                res => panic!("expected a limits error, got {:?}", res),
+            }
+        }
+
+        let keys = pubkeys(3);
+        let ms_str = format!("and_v(v:pk({}),or_b(pk({}),s:pk({})))", keys[0], keys[1], keys[2]);
+        let ms = Segwitv0Script::from_str(&ms_str).unwrap();
+
+        let limits = Limits::default();
+        assert_eq!(Segwitv0Script::from_str_with_limits(&ms_str, &limits).unwrap(), ms);
+        assert_eq!(Segwitv0Script::parse_with_limits(&ms.encode(), &limits).unwrap(), ms);
+
+        // Parsing stops at the first fragment exceeding the limits
+        let limits = Limits { max_depth: 2, ..Limits::default() };
+        assert_eq!(
+            limits_err(Segwitv0Script::from_str_with_limits(&ms_str, &limits)),
+            LimitsError::MaxDepthExceeded { actual: 3, limit: 2 }
+        );
+        let limits = Limits { max_nodes: 5, ..Limits::default() };
+        assert_eq!(
+            limits_err(Segwitv0Script::parse_with_limits(&ms.encode(), &limits)),
+            LimitsError::MaxNodesExceeded { actual: 6, limit: 5 }
+        );
+        assert_eq!(
+            limits_err(Segwitv0Script::from_str_with_limits(&ms_str, &limits)),
+            LimitsError::MaxNodesExceeded { actual: 7, limit: 5 }
+        );
+        let limits = Limits { max_script_size: 100, ..Limits::default() };
+        assert_eq!(
+            limits_err(Segwitv0Script::from_str_with_limits(&ms_str, &limits)),
+            LimitsError::MaxScriptSizeExceeded { actual: ms.script_size(), limit: 100 }
+        );
+        let limits = Limits { max_witness_elems: 2, ..Limits::default() };
+        assert_eq!(
+            limits_err(Segwitv0Script::from_str_with_limits(&ms_str, &limits)),
+            LimitsError::MaxWitnessElemsExceeded { actual: 3, limit: 2 }
+        );
+
+        // The satisfaction only uses as many elements as needed
+        let sig = bitcoin::ecdsa::Signature::sighash_all(
+            secp256k1::ecdsa::Signature::from_compact(&[1; 64]).unwrap(),
+        );
+        let mut sigs = BTreeMap::new();
+        sigs.insert(keys[0], sig);
+        sigs.insert(keys[1], sig);
+        let wit = ms.satisfy(&sigs).unwrap();
+        assert_eq!(wit.len(), 3);
+        assert_eq!(ms.satisfy_with_limits(&sigs, &Limits::default()).unwrap(), wit);
+        assert_eq!(
+            limits_err(ms.satisfy_with_limits(&sigs, &limits)),
+            LimitsError::MaxWitnessElemsExceeded { actual: 3, limit: 2 }
+        );
+
+        // The search falls back to a heavier branch which needs fewer elements
+        let keys = pubkeys(5);
+        let ms = Segwitv0Script::from_str(&format!(
+            "or_i(thresh(1,pk({}),s:pk({}),s:pk({})),and_v(v:pk({}),pk({})))",
+            keys[0], keys[1], keys[2], keys[3], keys[4]
+        ))
+        .unwrap();
+        let sigs: BTreeMap<_, _> = keys.iter().map(|key| (*key, sig)).collect();
+        assert_eq!(ms.satisfy(&sigs).unwrap().len(), 4);
+        let limits = Limits { max_witness_elems: 3, ..Limits::default() };
+        assert_eq!(ms.satisfy_with_limits(&sigs, &limits).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn aliases() {
+        type StrMs = Miniscript<String, Segwitv0>;
+
+        let ms = StrMs::from_str_insane("and_v(vc:pk_k(A),andor(c:pk_h(B),older(10),0))").unwrap();
+        assert_eq!(ms.to_string(), "and_v(v:pk(A),and_n(pkh(B),older(10)))");
+        assert_eq!(ms.to_string_with_aliases(Aliases::all()), ms.to_string());
+        let aliases = Aliases::detect("and_v(vc:pk_k(A),andor(c:pk_h(B),older(10),0))").unwrap();
+        assert_eq!(aliases, Aliases { pk: false, pkh: false, and_n: false, ..Aliases::all() });
+
+        let ms = StrMs::from_str_insane("or_i(0,and_v(v:pk(A),1))").unwrap();
+        assert_eq!(ms.to_string(), "ltv:pk(A)");
+        assert_eq!(ms.to_string_with_aliases(Aliases::none()), "or_i(0,and_v(vc:pk_k(A),1))");
+
+        for s in [
+            "and_v(vc:pk_k(A),andor(c:pk_h(B),older(10),0))",
+            "and_v(v:pk(A),andor(pkh(B),older(10),0))",
+            "or_i(0,and_v(v:pk(A),1))",
+            "or_i(and_v(v:pk(A),1),0)",
+            "ltv:pk(A)",
+            "or_d(c:pk_k(A),ulc:pk_k(B))",
+            "thresh(2,c:pk_k(A),sc:pk_k(B),sln:older(10))",
+        ] {
+            let ms = StrMs::from_str_insane(s).unwrap();
+            let aliases = Aliases::detect(s).unwrap();
+            assert_eq!(ms.to_string_with_aliases(aliases), s);
+        }
+
+        // The same alias can't be both used and not used
+        assert!(matches!(
+            Aliases::detect("and_v(v:pk(A),c:pk_k(B))"),
+            Err(AliasError::Inconsistent("pk"))
+        ));
+        assert!(matches!(
+            Aliases::detect("or_b(l:pk(A),a:or_i(0,pk(B)))"),
+            Err(AliasError::Inconsistent("l"))
+        ));
+    }
+
+    #[test]
+    fn annotate() {
+        type StrMs = Miniscript<String, Segwitv0>;
+
+        let ms = StrMs::from_str_insane("or_d(pk(A),and_v(v:pk(B),older(10)))").unwrap();
+        let annotated = ms.annotate();
+        assert_eq!(annotated.script_size, ms.script_size());
+        assert_eq!(annotated.children.len(), 2);
+        assert_eq!(
+            annotated.children[1].children[1].node.as_inner(),
+            &Terminal::Older(RelLockTime::from_height(10))
+        );
+        assert_eq!(
+            annotated.to_string(),
+            "or_d [B/fsm] script: 75, sat: 74/74, dissat: -
+  c: [B/onduesm] script: 35, sat: 73/73, dissat: 1/1
+    pk_k(A) [K/onduesm] script: 34, sat: 73/73, dissat: 1/1
+  and_v [B/onfsm] script: 37, sat: 73/73, dissat: -
+    v: [V/onfsm] script: 35, sat: 73/73, dissat: -
+      c: [B/onduesm] script: 35, sat: 73/73, dissat: 1/1
+        pk_k(B) [K/onduesm] script: 34, sat: 73/73, dissat: 1/1
+    older(10) [B/zfm] script: 2, sat: 0/0, dissat: -"
+        );
+
+        let ms = StrMs::from_str_insane("thresh(2,pk(A),s:pk(B),a:multi(1,C,D))").unwrap();
+        let rendered = ms.annotate().to_string();
+        assert!(rendered.starts_with("thresh(2) [B/"));
+        assert!(rendered.contains("\n  s: [W/duesm] script: 36, sat: 73/73, dissat: 1/1\n"));
+        assert!(
+            rendered.ends_with("\n    multi(1,C,D) [B/nduesm] script: 71, sat: 74/74, dissat: 2/2")
+        );
+    }
+
+    #[test]
+    fn context_limits() {
+        type Segwitv0Ms = Miniscript<String, Segwitv0>;
+
+        let segwit = Segwitv0::limits();
+        assert_eq!(segwit.max_standard_script_size, Some(limits::MAX_STANDARD_P2WSH_SCRIPT_SIZE));
+        assert_eq!(segwit.max_witness_elems, Some(limits::MAX_STANDARD_P2WSH_STACK_ITEMS));
+        assert_eq!(segwit.max_multi_a_keys, None);
+        assert!(!segwit.uncompressed_keys);
+
+        let tap = Tap::limits();
+        assert_eq!(tap.max_ops, None);
+        assert_eq!(tap.max_multi_keys, None);
+        assert_eq!(tap.max_script_size, Some(limits::MAX_BLOCK_WEIGHT));
+        assert_eq!(tap.max_multi_a_keys, Some(limits::MAX_PUBKEYS_IN_CHECKSIGADD));
+        assert!(tap.x_only_keys);
+
+        // The limits match the ones enforced when parsing
+        let keys: Vec<_> = (0..segwit.max_multi_keys.unwrap() + 1)
+            .map(|i| format!("K{}", i))
+            .collect();
+        assert!(Segwitv0Ms::from_str_insane(&format!("multi(1,{})", keys[1..].join(","))).is_ok());
+        assert!(Segwitv0Ms::from_str_insane(&format!("multi(1,{})", keys.join(","))).is_err());
+        assert!(Segwitv0Ms::from_str_insane("multi_a(1,A,B)").is_err());
+    }
+
+    #[test]
+    fn standard_sigops() {
+        type LegacyMs = Miniscript<bitcoin::PublicKey, Legacy>;
+
+        assert_eq!(Legacy::limits().max_standard_sigops, Some(limits::MAX_P2SH_SIGOPS));
+        assert_eq!(Segwitv0::limits().max_standard_sigops, None);
+
+        let pks = pubkeys(16);
+        let thresh = |n: usize| {
+            let subs: Vec<_> = pks[..n]
+                .iter()
+                .enumerate()
+                .map(|(i, pk)| {
+                    let prefix = if i == 0 { "" } else { "a:" };
+                    // Mix in keys to fit both the script and scriptSig size limits
+                    let frag = if i < 4 { "pk" } else { "pkh" };
+                    format!("{}{}({})", prefix, frag, pk)
+                })
+                .collect();
+            format!("thresh(1,{})", subs.join(","))
+        };
+
+        let ms = LegacyMs::from_str(&thresh(15)).unwrap();
+        assert_eq!(ms.sigop_count(), 15);
+        assert!(ms.within_standard_sigops());
+
+        // One signature operation over the standardness limit
+        assert_eq!(
+            LegacyMs::from_str(&thresh(16)).unwrap_err().to_string(),
+            "Miniscript exceeds the standard signature operation limit"
+        );
+        let ms =
+            LegacyMs::from_str_ext(&thresh(16), &ExtParams::sane().nonstandard_sigops()).unwrap();
+        assert_eq!(ms.sigop_count(), 16);
+        assert_eq!(ms.sanity_check(), Err(crate::AnalysisError::NonStandardSigOps));
+        assert!(Segwitv0Script::from_str(&thresh(16)).is_ok());
+        let desc =
+            crate::Descriptor::<bitcoin::PublicKey>::from_str(&format!("sh({})", thresh(16)))
+                .unwrap();
+        assert!(desc.sanity_check().is_err());
+
+        // Multisig counts one signature operation per key, up to the consensus limit
+        let ms = LegacyMs::from_str(&format!("multi(1,{},{})", pks[0], pks[1])).unwrap();
+        assert_eq!(ms.sigop_count(), 2);
+        let sh = crate::descriptor::Sh::new_multi(2, pks[..15].to_vec()).unwrap();
+        assert!(sh.sanity_check().is_ok());
+        assert!(crate::descriptor::Sh::new_multi(2, pks.clone()).is_err());
+
+        // The compiler doesn't produce non-standard redeem scripts
+        #[cfg(feature = "compiler")]
+        {
+            use crate::policy::Concrete;
+
+            let thresh = |keys: &[bitcoin::PublicKey]| {
+                let keys: Vec<_> = keys.iter().map(|pk| format!("pk({})", pk)).collect();
+                format!("thresh(1,{})", keys.join(","))
+            };
+            let policy = Concrete::<bitcoin::PublicKey>::from_str(&format!(
+                "or({},or({},or({},{})))",
+                thresh(&pks[..4]),
+                thresh(&pks[4..8]),
+                thresh(&pks[8..12]),
+                thresh(&pks[12..])
+            ))
+            .unwrap();
+            assert_eq!(policy.compile::<Segwitv0>().unwrap().sigop_count(), 16);
+            // Every key needs its own signature operation
+            assert!(policy.compile::<Legacy>().is_err());
+            // Nor a multi too large for the redeem script
+            let policy = Concrete::<bitcoin::PublicKey>::from_str(&thresh(&pks)).unwrap();
+            assert!(policy.compile::<Legacy>().is_err());
+        }
+    }
+
+    #[test]
+    fn hash_oracle() {
+        use core::cell::Cell;
+
+        use crate::descriptor::HashLock;
+        use crate::{HashOracle, HashOracleSatisfier};
+
+        let preimage = [0xab; 32];
+        let sha256 = sha256::Hash::hash(&preimage);
+        let hash160 = hash160::Hash::hash(&preimage);
+        let ms = Segwitv0Script::from_str_insane(&format!(
+            "and_v(v:sha256({}),hash160({}))",
+            sha256, hash160
+        ))
+        .unwrap();
+
+        // Preimages are looked up lazily, one query per hash lock
+        let queries = Cell::new(0);
+        let oracle = |lock: &HashLock<bitcoin::PublicKey>| {
+            queries.set(queries.get() + 1);
+            match *lock {
+                HashLock::Sha256(h) if h == sha256 => Some(preimage),
+                HashLock::Hash160(h) if h == hash160 => Some(preimage),
+                _ => None,
+            }
+        };
+        let witness = ms.satisfy(HashOracleSatisfier(&oracle)).unwrap();
+        assert_eq!(witness, vec![preimage.to_vec(), preimage.to_vec()]);
+        assert!(queries.get() >= 2);
+
+        // Maps of preimages are oracles
+        let mut map = BTreeMap::new();
+        map.insert(HashLock::Sha256(sha256), preimage);
+        assert_eq!(map.preimage(&HashLock::Sha256(sha256)), Some(preimage));
+        assert!(ms.satisfy(HashOracleSatisfier(map.clone())).is_err());
+        map.insert(HashLock::Hash160(hash160), preimage);
+        assert_eq!(ms.satisfy(HashOracleSatisfier(map)).unwrap(), witness);
+    }
+
+    #[test]
+    fn pkh_resolver() {
+        use crate::{Descriptor, PkhResolverSatisfier};
+
+        let keys = pubkeys(2);
+        let desc = Descriptor::<bitcoin::PublicKey>::from_str(&format!(
+            "wsh(and_v(v:pk({}),pkh({})))",
+            keys[0], keys[1]
+        ))
+        .unwrap();
+        let script = desc.explicit_script().unwrap();
+        // Parsed from script, the pkh() fragment only knows the hash of its key
+        let ms = Segwitv0Script::parse_with_ext(&script, &ExtParams::allow_all()).unwrap();
+        assert!(ms.contains_raw_pkh());
+
+        let sig = bitcoin::ecdsa::Signature::sighash_all(
+            secp256k1::ecdsa::Signature::from_compact(&[1; 64]).unwrap(),
+        );
+        let mut sigs = BTreeMap::new();
+        sigs.insert(keys[0], sig);
+        sigs.insert(keys[1], sig);
+        assert!(ms.satisfy(&sigs).is_err());
+
+        let resolver = desc.pkh_resolver();
+        assert_eq!(resolver.len(), 2);
+        let witness = ms
+            .satisfy(PkhResolverSatisfier(&sigs, resolver.clone()))
+            .unwrap();
+        assert_eq!(witness, vec![sig.to_vec(), keys[1].to_bytes(), sig.to_vec()]);
+        assert_eq!(ms.resolve_raw_pkh(&resolver).satisfy(&sigs).unwrap(), witness);
+
+        // Keys not matching the hash are ignored
+        let wrong = |_: &hash160::Hash| Some(keys[0]);
+        assert!(ms.satisfy(PkhResolverSatisfier(&sigs, wrong)).is_err());
+    }
+
+    #[test]
+    fn satisfier_combinators() {
+        use crate::descriptor::HashLock;
+        use crate::{HashOracleSatisfier, Lookup, SatisfierExt};
+
+        let keys = pubkeys(2);
+        let preimage = [0xab; 32];
+        let hash = sha256::Hash::hash(&preimage);
+        let ms = Segwitv0Script::from_str_insane(&format!(
+            "and_v(v:pk({}),and_v(v:pk({}),sha256({})))",
+            keys[0], keys[1], hash
+        ))
+        .unwrap();
+
+        let sig = |b: u8| {
+            bitcoin::ecdsa::Signature::sighash_all(
+                secp256k1::ecdsa::Signature::from_compact(&[b; 64]).unwrap(),
+            )
+        };
+        let mut sigs0 = BTreeMap::new();
+        sigs0.insert(keys[0], sig(1));
+        let mut sigs1 = BTreeMap::new();
+        sigs1.insert(keys[1], sig(2));
+        let oracle = HashOracleSatisfier(|lock: &HashLock<bitcoin::PublicKey>| match *lock {
+            HashLock::Sha256(h) if h == hash => Some(preimage),
+            _ => None,
+        });
+
+        // The trace tells what the satisfaction is missing
+        let traced = (&sigs0).or_else(&sigs1).traced();
+        assert!(ms.satisfy(&traced).is_err());
+        assert!(traced
+            .lookups()
+            .contains(&(Lookup::EcdsaSig(keys[0]), true)));
+        assert!(traced
+            .lookups()
+            .contains(&(Lookup::EcdsaSig(keys[1]), true)));
+        assert_eq!(traced.missing(), vec![Lookup::Preimage(HashLock::Sha256(hash))]);
+        traced.clear();
+        assert!(traced.lookups().is_empty());
+
+        let witness = ms
+            .satisfy((&sigs0).or_else(&sigs1).or_else(&oracle))
+            .unwrap();
+        assert_eq!(witness, vec![preimage.to_vec(), sig(2).to_vec(), sig(1).to_vec()]);
+        assert_eq!(ms.satisfy((&sigs0, &sigs1, &oracle)).unwrap(), witness);
+
+        // Overrides take precedence over the wrapped satisfier
+        let overridden = (&sigs0)
+            .or_else(&sigs1)
+            .or_else(&oracle)
+            .with_override(keys[1], sig(3));
+        let witness = ms.satisfy(&overridden).unwrap();
+        assert_eq!(witness, vec![preimage.to_vec(), sig(3).to_vec(), sig(1).to_vec()]);
+        let witness = ms
+            .satisfy(overridden.with_override(keys[0], sig(4)))
+            .unwrap();
+        assert_eq!(witness, vec![preimage.to_vec(), sig(3).to_vec(), sig(4).to_vec()]);
+    }
+
+    #[test]
+    fn key_registry() {
+        use crate::{KeyRegistry, PkhResolver, SigType};
+
+        let keys = pubkeys(2);
+        let ms =
+            Segwitv0Script::from_str(&format!("or_b(pkh({}),a:pk({}))", keys[1], keys[0])).unwrap();
+        // The script only commits to the hash of the second key
+        let parsed = Segwitv0Script::parse_with_ext(&ms.encode(), &ExtParams::allow_all()).unwrap();
+        let hash = keys[1].to_pubkeyhash(SigType::Ecdsa);
+        assert!(parsed
+            .iter()
+            .any(|node| matches!(node.node, Terminal::RawPkH(h) if h == hash)));
+
+        let sig = bitcoin::ecdsa::Signature::sighash_all(
+            secp256k1::ecdsa::Signature::from_compact(&[1; 64]).unwrap(),
+        );
+        let mut sigs = BTreeMap::new();
+        sigs.insert(keys[0], sig);
+        assert!(parsed.satisfy(&sigs).is_err());
+
+        let registry: KeyRegistry = keys.iter().copied().collect();
+        assert_eq!(registry.len(), 2);
+        assert_eq!(registry.ecdsa_key(&hash), Some(keys[1]));
+        assert_eq!(PkhResolver::<bitcoin::PublicKey>::resolve_pkh(&registry, &hash), Some(keys[1]));
+        let x_only = keys[1].to_x_only_pubkey();
+        assert_eq!(registry.x_only_key(&x_only.to_pubkeyhash(SigType::Schnorr)), Some(x_only));
+        assert_eq!(registry.x_only_key(&hash), None);
+
+        let witness = parsed.satisfy((&sigs, &registry)).unwrap();
+        assert_eq!(witness, ms.satisfy(&sigs).unwrap());
+        assert!(witness.contains(&keys[1].to_bytes()));
+    }
+
+    #[test]
+    fn malleability_report() {
+        let key = pubkeys(1)[0];
+        let paths = |frags: Vec<&crate::FragmentMalleability>| {
+            frags.iter().map(|f| f.to_string()).collect::<Vec<_>>()
+        };
+
+        // Either timelock can be used, and a third party can pick the other one
+        let ms = Segwitv0Script::from_str_insane(&format!(
+            "and_v(v:pk({}),or_i(older(10),older(20)))",
+            key
+        ))
+        .unwrap();
+        let report = ms.malleability_report();
+        assert!(!report.is_non_malleable());
+        assert!(report.requires_sig());
+        assert_eq!(paths(report.malleable().collect()), vec!["[1] or_i"]);
+        assert_eq!(report.sigless_branches().count(), 0);
+        assert_eq!(report.fragments().len(), ms.iter().count());
+        assert_eq!(report.fragments()[3].to_string(), format!("[0,0,0] pk_k({})", key));
+        let or_i = &report.fragments()[4];
+        assert_eq!(ms.get_nth_child(or_i.path[0]).unwrap().ty.mall, or_i.malleability);
+
+        // The timelock branch doesn't require a signature
+        let ms = Segwitv0Script::from_str_insane(&format!(
+            "or_d(pk({}),and_v(v:pk({}),older(10)))",
+            key, key
+        ))
+        .unwrap();
+        assert_eq!(
+            paths(ms.malleability_report().sigless_branches().collect()),
+            Vec::<String>::new()
+        );
+        let ms = Segwitv0Script::from_str_insane(&format!("or_d(pk({}),older(10))", key)).unwrap();
+        let report = ms.malleability_report();
+        assert!(!report.requires_sig());
+        assert_eq!(paths(report.sigless_branches().collect()), vec!["[1] older(10)"]);
+
+        // Anyone can dissatisfy a hash lock with a wrong preimage
+        let ms = Segwitv0Script::from_str_insane(&format!(
+            "or_d(sha256({}),pk({}))",
+            sha256::Hash::hash(&[1; 32]),
+            key
+        ))
+        .unwrap();
+        let report = ms.malleability_report();
+        assert_eq!(
+            paths(report.non_unique_dissatisfactions().collect()),
+            vec![format!("[0] sha256({})", sha256::Hash::hash(&[1; 32]))]
+        );
+        assert!(!report.is_non_malleable());
+        assert_eq!(paths(report.malleable().collect()), vec!["[] or_d"]);
+    }
+
+    #[test]
+    fn key_context_checks() {
+        use crate::DescriptorPublicKey;
+
+        let compressed = "02c6047f9441ed7d6d3045406e95c07cd85c778e4b8cef3ca7abac09b95c709ee5";
+        let x_only = &compressed[2..];
+        let uncompressed = "04a34b99f22c790c4e36b2b3c2c35a36db06226e41c692fc82b8b56ac1c540c5bd5b8dec5235a0fa8722476c7709c02559e3aa73aa03918ba2d492eea75abea235";
+
+        // Keys are checked against the context in every fragment, including pkh()
+        for frag in ["pk", "pkh"] {
+            let ms = |k: &str| format!("{}({})", frag, k);
+            Miniscript::<DescriptorPublicKey, Tap>::from_str(&ms(x_only)).unwrap();
+            Miniscript::<DescriptorPublicKey, Tap>::from_str(&ms(compressed)).unwrap();
+            Miniscript::<DescriptorPublicKey, Segwitv0>::from_str(&ms(compressed)).unwrap();
+            Miniscript::<DescriptorPublicKey, Legacy>::from_str(&ms(uncompressed)).unwrap();
+            assert_eq!(
+                Miniscript::<DescriptorPublicKey, Segwitv0>::from_str(&ms(x_only))
+                    .unwrap_err()
+                    .to_string(),
+                format!("x-only key {} not allowed in Segwitv0", x_only)
+            );
+            assert_eq!(
+                Miniscript::<DescriptorPublicKey, Segwitv0>::from_str(&ms(uncompressed))
+                    .unwrap_err()
+                    .to_string(),
+                format!(
+                    "Only Compressed pubkeys are allowed in segwit context. Found {}",
+                    uncompressed
+                )
+            );
+            // Both segwit contexts report the uncompressed key through the same error
+            let uncompressed_pk = bitcoin::PublicKey::from_str(uncompressed).unwrap();
+            for err in [
+                Miniscript::<DescriptorPublicKey, Segwitv0>::from_str(&ms(uncompressed))
+                    .unwrap_err(),
+                Miniscript::<DescriptorPublicKey, Tap>::from_str(&ms(uncompressed)).unwrap_err(),
+            ] {
+                match err {
+                    Error::ContextError(
+                        crate::miniscript::context::ScriptContextError::CompressedOnly(pk),
+                    ) => {
+                        assert_eq!(pk, uncompressed_pk)
+                    }
+                    e => panic!("unexpected error {:?}", e),
+                }
+            }
+            assert!(Miniscript::<DescriptorPublicKey, Legacy>::from_str(&ms(x_only)).is_err());
+        }
+        Miniscript::<DescriptorPublicKey, Tap>::from_str(&format!("multi_a(1,{})", x_only))
+            .unwrap();
+        assert!(Miniscript::<DescriptorPublicKey, Segwitv0>::from_str(&format!(
+            "multi(1,{},{})",
+            compressed, x_only
+        ))
+        .is_err());
+
+        // Keys which can't be parsed are named along with the context
+        let err = Miniscript::<bitcoin::PublicKey, Tap>::from_str(&format!("pk({})", x_only))
+            .unwrap_err()
+            .to_string();
+        assert!(err.starts_with(&format!("unexpected «invalid key {} in TapscriptCtx: ", x_only)));
+        let err = Miniscript::<XOnlyPublicKey, Tap>::from_str(&format!("pk({})", compressed))
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains(compressed));
+    }
+
+    #[test]
+    fn signing_session() {
+        use bitcoin::hex::DisplayHex;
+        use bitcoin::secp256k1::{Keypair, Message, Secp256k1, SecretKey};
+        use bitcoin::taproot::LeafVersion;
+
+        use crate::{SessionError, SessionRound, SigningSession};
+
+        let secp = Secp256k1::new();
+        let keypair =
+            |b: u8| Keypair::from_secret_key(&secp, &SecretKey::from_slice(&[b; 32]).unwrap());
+        let (agg, a, b) = (keypair(1), keypair(2), keypair(3));
+        let (a_pk, b_pk) = (a.x_only_public_key().0, b.x_only_public_key().0);
+        let agg_pk =
+            crate::descriptor::musig_key_agg(&[a_pk.to_public_key(), b_pk.to_public_key()])
+                .x_only_public_key()
+                .0;
+        let ms = Miniscript::<XOnlyPublicKey, Tap>::from_str(&format!("pk({})", agg_pk)).unwrap();
+        let leaf_hash = TapLeafHash::from_script(&ms.encode(), LeafVersion::TapScript);
+
+        assert_eq!(
+            SigningSession::new(agg_pk, vec![], Some(leaf_hash)),
+            Err(SessionError::NoParticipants)
+        );
+        assert_eq!(
+            SigningSession::new(agg_pk, vec![b_pk, a_pk], Some(leaf_hash)),
+            Err(SessionError::AggregateKeyMismatch(agg_pk))
+        );
+        let mut session =
+            SigningSession::new(agg_pk, vec![a_pk, b_pk, a_pk], Some(leaf_hash)).unwrap();
+        assert_eq!(session.participants().count(), 2);
+        assert_eq!(session.round(), SessionRound::Commit);
+
+        let nonce = |x: &Keypair, y: &Keypair| {
+            let mut nonce = [0; 66];
+            nonce[..33].copy_from_slice(&x.public_key().serialize());
+            nonce[33..].copy_from_slice(&y.public_key().serialize());
+            nonce
+        };
+        let (nonce_a, nonce_b) = (nonce(&a, &b), nonce(&b, &a));
+        session
+            .commit_nonce(&a_pk, sha256::Hash::hash(&nonce_a))
+            .unwrap();
+        assert_eq!(
+            session.reveal_nonce(&a_pk, nonce_a),
+            Err(SessionError::WrongRound {
+                expected: SessionRound::Reveal,
+                actual: SessionRound::Commit
+            })
+        );
+        session
+            .commit_nonce(&b_pk, sha256::Hash::hash(&nonce_b))
+            .unwrap();
+        assert_eq!(session.round(), SessionRound::Reveal);
+
+        // The session persists between rounds
+        let restored: SigningSession<XOnlyPublicKey> = session.to_string().parse().unwrap();
+        assert_eq!(restored, session);
+
+        assert_eq!(
+            session.reveal_nonce(&a_pk, nonce_b),
+            Err(SessionError::CommitmentMismatch(a_pk))
+        );
+        session.reveal_nonce(&a_pk, nonce_a).unwrap();
+        session.reveal_nonce(&b_pk, nonce_b).unwrap();
+        assert_eq!(session.nonces(), Some(vec![nonce_a, nonce_b]));
+        assert!(ms.satisfy(&session).is_err());
+
+        assert_eq!(
+            session.add_partial_sig(&a_pk, [0xff; 32]),
+            Err(SessionError::InvalidPartialSig(a_pk))
+        );
+        session.add_partial_sig(&a_pk, [1; 32]).unwrap();
+        assert_eq!(
+            session.add_partial_sig(&agg_pk, [2; 32]),
+            Err(SessionError::UnknownParticipant(agg_pk))
+        );
+        let signature = bitcoin::taproot::Signature {
+            signature: secp.sign_schnorr_no_aux_rand(&Message::from_digest([3; 32]), &agg),
+            sighash_type: bitcoin::TapSighashType::Default,
+        };
+        assert_eq!(session.set_signature(signature), Err(SessionError::MissingPartialSig(b_pk)));
+        session.add_partial_sig(&b_pk, [2; 32]).unwrap();
+        session.set_signature(signature).unwrap();
+        assert_eq!(session.round(), SessionRound::Complete);
+
+        let restored: SigningSession<XOnlyPublicKey> = session.to_string().parse().unwrap();
+        assert_eq!(restored, session);
+        assert_eq!(ms.satisfy(&restored).unwrap(), vec![signature.to_vec()]);
+
+        // Inconsistent sessions don't parse
+        let tampered = session.to_string().replacen(
+            &nonce_a.as_hex().to_string(),
+            &nonce_b.as_hex().to_string(),
+            1,
+        );
+        assert_eq!(
+            tampered.parse::<SigningSession<XOnlyPublicKey>>(),
+            Err(SessionError::CommitmentMismatch(a_pk))
+        );
+    }
 }
@@ -8,19 +8,23 @@
 
 use core::{cmp, fmt, mem};
 
-use bitcoin::hashes::hash160;
+use bitcoin::hashes::{hash160, ripemd160, sha256, Hash};
+use bitcoin::hex::{DisplayHex, FromHex};
 use bitcoin::key::XOnlyPublicKey;
 use bitcoin::taproot::{ControlBlock, LeafVersion, TapLeafHash, TapNodeHash};
 use bitcoin::{absolute, relative, ScriptBuf, Sequence};
+use secp256k1_musig::musig::{PartialSignature, PublicNonce};
 use sync::Arc;
 
 use super::context::SigType;
+use super::hash256;
+use crate::descriptor::{musig_key_agg, HashLock};
 use crate::plan::AssetProvider;
 use crate::prelude::*;
 use crate::util::witness_size;
 use crate::{
-    AbsLockTime, Miniscript, MiniscriptKey, RelLockTime, ScriptContext, Terminal, Threshold,
-    ToPublicKey,
+    AbsLockTime, ChainTip, Miniscript, MiniscriptKey, RelLockTime, ScriptContext, Terminal,
+    Threshold, ToPublicKey,
 };
 
 /// Type alias for 32 byte Preimage.
@@ -37,6 +41,10 @@ pub trait Satisfier<Pk: MiniscriptKey + ToPublicKey> {
     /// Lookup the tap key spend sig
     fn lookup_tap_key_spend_sig(&self) -> Option<bitcoin::taproot::Signature> { None }
 
+    /// Given the output key of a `rawtr()` descriptor, look up a key spend schnorr signature
+    /// made with that key, without any taproot tweak
+    fn lookup_raw_tr_schnorr_sig(&self, _: &Pk) -> Option<bitcoin::taproot::Signature> { None }
+
     /// Given a public key and a associated leaf hash, look up an schnorr signature with that key
     fn lookup_tap_leaf_script_sig(
         &self,
@@ -56,85 +64,1419 @@ pub trait Satisfier<Pk: MiniscriptKey + ToPublicKey> {
     /// Given a raw `Pkh`, lookup corresponding [`bitcoin::PublicKey`]
     fn lookup_raw_pkh_pk(&self, _: &hash160::Hash) -> Option<bitcoin::PublicKey> { None }
 
-    /// Given a raw `Pkh`, lookup corresponding [`bitcoin::secp256k1::XOnlyPublicKey`]
-    fn lookup_raw_pkh_x_only_pk(&self, _: &hash160::Hash) -> Option<XOnlyPublicKey> { None }
+    /// Given a raw `Pkh`, lookup corresponding [`bitcoin::secp256k1::XOnlyPublicKey`]
+    fn lookup_raw_pkh_x_only_pk(&self, _: &hash160::Hash) -> Option<XOnlyPublicKey> { None }
+
+    /// Given a keyhash, look up the EC signature and the associated key.
+    ///
+    /// Even if signatures for public key Hashes are not available, the users
+    /// can use this map to provide pkh -> pk mapping which can be useful
+    /// for dissatisfying pkh.
+    fn lookup_raw_pkh_ecdsa_sig(
+        &self,
+        _: &hash160::Hash,
+    ) -> Option<(bitcoin::PublicKey, bitcoin::ecdsa::Signature)> {
+        None
+    }
+
+    /// Given a keyhash, look up the schnorr signature and the associated key.
+    ///
+    /// Even if signatures for public key Hashes are not available, the users
+    /// can use this map to provide pkh -> pk mapping which can be useful
+    /// for dissatisfying pkh.
+    fn lookup_raw_pkh_tap_leaf_script_sig(
+        &self,
+        _: &(hash160::Hash, TapLeafHash),
+    ) -> Option<(XOnlyPublicKey, bitcoin::taproot::Signature)> {
+        None
+    }
+
+    /// Given a SHA256 hash, look up its preimage
+    fn lookup_sha256(&self, _: &Pk::Sha256) -> Option<Preimage32> { None }
+
+    /// Given a HASH256 hash, look up its preimage
+    fn lookup_hash256(&self, _: &Pk::Hash256) -> Option<Preimage32> { None }
+
+    /// Given a RIPEMD160 hash, look up its preimage
+    fn lookup_ripemd160(&self, _: &Pk::Ripemd160) -> Option<Preimage32> { None }
+
+    /// Given a HASH160 hash, look up its preimage
+    fn lookup_hash160(&self, _: &Pk::Hash160) -> Option<Preimage32> { None }
+
+    /// Assert whether an relative locktime is satisfied
+    ///
+    /// NOTE: If a descriptor mixes time-based and height-based timelocks, the implementation of
+    /// this method MUST only allow timelocks of either unit, but not both. Allowing both could cause
+    /// miniscript to construct an invalid witness.
+    fn check_older(&self, _: relative::LockTime) -> bool { false }
+
+    /// Assert whether a absolute locktime is satisfied
+    ///
+    /// NOTE: If a descriptor mixes time-based and height-based timelocks, the implementation of
+    /// this method MUST only allow timelocks of either unit, but not both. Allowing both could cause
+    /// miniscript to construct an invalid witness.
+    fn check_after(&self, _: absolute::LockTime) -> bool { false }
+}
+
+// Allow use of `()` as a "no conditions available" satisfier
+impl<Pk: MiniscriptKey + ToPublicKey> Satisfier<Pk> for () {}
+
+impl<Pk: MiniscriptKey + ToPublicKey> Satisfier<Pk> for Sequence {
+    fn check_older(&self, n: relative::LockTime) -> bool {
+        if let Some(lt) = self.to_relative_lock_time() {
+            Satisfier::<Pk>::check_older(&lt, n)
+        } else {
+            false
+        }
+    }
+}
+
+impl<Pk: MiniscriptKey + ToPublicKey> Satisfier<Pk> for RelLockTime {
+    fn check_older(&self, n: relative::LockTime) -> bool {
+        <relative::LockTime as Satisfier<Pk>>::check_older(&(*self).into(), n)
+    }
+}
+
+impl<Pk: MiniscriptKey + ToPublicKey> Satisfier<Pk> for relative::LockTime {
+    fn check_older(&self, n: relative::LockTime) -> bool { n.is_implied_by(*self) }
+}
+
+impl<Pk: MiniscriptKey + ToPublicKey> Satisfier<Pk> for absolute::LockTime {
+    fn check_after(&self, n: absolute::LockTime) -> bool { n.is_implied_by(*self) }
+}
+
+// The transaction's nLockTime is assumed to be set to whatever the satisfaction requires, so
+// an `after` is satisfied as soon as the chain allows it.
+impl<Pk: MiniscriptKey + ToPublicKey> Satisfier<Pk> for ChainTip {
+    fn check_after(&self, n: absolute::LockTime) -> bool { self.is_final(n) }
+}
+
+/// A source of hash preimages, which may compute or fetch them lazily
+///
+/// Use [`HashOracleSatisfier`] to satisfy the hash locks of a Miniscript with
+/// an oracle, for example combined with the satisfier for the signatures in a
+/// tuple.
+pub trait HashOracle<Pk: MiniscriptKey> {
+    /// Given a hash lock, look up the preimage of its hash
+    fn preimage(&self, lock: &HashLock<Pk>) -> Option<Preimage32>;
+}
+
+impl<Pk: MiniscriptKey, F: Fn(&HashLock<Pk>) -> Option<Preimage32>> HashOracle<Pk> for F {
+    fn preimage(&self, lock: &HashLock<Pk>) -> Option<Preimage32> { self(lock) }
+}
+
+macro_rules! impl_hash_oracle_for_map {
+    ($(#[$($attr:meta)*])* impl HashOracle<Pk> for $map:ident) => {
+        $(#[$($attr)*])*
+        impl<Pk: MiniscriptKey> HashOracle<Pk> for $map<HashLock<Pk>, Preimage32> {
+            fn preimage(&self, lock: &HashLock<Pk>) -> Option<Preimage32> {
+                self.get(lock).copied()
+            }
+        }
+    };
+}
+
+impl_hash_oracle_for_map! {
+    impl HashOracle<Pk> for BTreeMap
+}
+
+impl_hash_oracle_for_map! {
+    #[cfg(feature = "std")]
+    impl HashOracle<Pk> for HashMap
+}
+
+/// A [`Satisfier`] providing the preimages of a [`HashOracle`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct HashOracleSatisfier<O>(pub O);
+
+impl<Pk: MiniscriptKey + ToPublicKey, O: HashOracle<Pk>> Satisfier<Pk> for HashOracleSatisfier<O> {
+    fn lookup_sha256(&self, h: &Pk::Sha256) -> Option<Preimage32> {
+        self.0.preimage(&HashLock::Sha256(h.clone()))
+    }
+
+    fn lookup_hash256(&self, h: &Pk::Hash256) -> Option<Preimage32> {
+        self.0.preimage(&HashLock::Hash256(h.clone()))
+    }
+
+    fn lookup_ripemd160(&self, h: &Pk::Ripemd160) -> Option<Preimage32> {
+        self.0.preimage(&HashLock::Ripemd160(h.clone()))
+    }
+
+    fn lookup_hash160(&self, h: &Pk::Hash160) -> Option<Preimage32> {
+        self.0.preimage(&HashLock::Hash160(h.clone()))
+    }
+}
+
+/// Error adding a preimage to a [`PreimageStore`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PreimageError {
+    /// Miniscript hash locks only accept 32-byte preimages; this preimage had the given length
+    InvalidLength(usize),
+    /// The preimage isn't valid hex
+    InvalidHex(String),
+}
+
+impl fmt::Display for PreimageError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PreimageError::InvalidLength(len) => {
+                write!(f, "preimage is {} bytes long, hash locks require 32 bytes", len)
+            }
+            PreimageError::InvalidHex(ref e) => write!(f, "invalid preimage hex: {}", e),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for PreimageError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> { None }
+}
+
+/// A store of hash preimages, indexed by their sha256, hash256, ripemd160 and hash160 images
+///
+/// Each preimage added to the store can satisfy any of the four hash fragments. The store
+/// is a [`Satisfier`], and therefore an [`AssetProvider`] for planning, so it can be combined
+/// with the satisfier for the signatures in a tuple. It can also be added to
+/// [`crate::plan::Assets`].
+///
+/// Only 32-byte preimages are accepted, since the hash fragments check the size of the
+/// preimage they are given. With the `serde` feature, the store serializes to the list of its
+/// preimages in hex.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PreimageStore {
+    sha256: BTreeMap<sha256::Hash, Preimage32>,
+    hash256: BTreeMap<hash256::Hash, Preimage32>,
+    ripemd160: BTreeMap<ripemd160::Hash, Preimage32>,
+    hash160: BTreeMap<hash160::Hash, Preimage32>,
+}
+
+impl PreimageStore {
+    /// Creates an empty store
+    pub fn new() -> Self { Self::default() }
+
+    /// Adds a preimage to the store
+    ///
+    /// # Errors
+    ///
+    /// If the preimage is not 32 bytes long.
+    pub fn insert(&mut self, preimage: &[u8]) -> Result<(), PreimageError> {
+        let preimage = Preimage32::try_from(preimage)
+            .map_err(|_| PreimageError::InvalidLength(preimage.len()))?;
+        self.sha256.insert(sha256::Hash::hash(&preimage), preimage);
+        self.hash256
+            .insert(hash256::Hash::hash(&preimage), preimage);
+        self.ripemd160
+            .insert(ripemd160::Hash::hash(&preimage), preimage);
+        self.hash160
+            .insert(hash160::Hash::hash(&preimage), preimage);
+        Ok(())
+    }
+
+    /// Adds a hex-encoded preimage to the store
+    pub fn insert_hex(&mut self, preimage: &str) -> Result<(), PreimageError> {
+        let preimage =
+            Vec::<u8>::from_hex(preimage).map_err(|e| PreimageError::InvalidHex(e.to_string()))?;
+        self.insert(&preimage)
+    }
+
+    /// Removes a preimage from the store, returning whether it was present
+    pub fn remove(&mut self, preimage: &Preimage32) -> bool {
+        self.hash256.remove(&hash256::Hash::hash(preimage));
+        self.ripemd160.remove(&ripemd160::Hash::hash(preimage));
+        self.hash160.remove(&hash160::Hash::hash(preimage));
+        self.sha256.remove(&sha256::Hash::hash(preimage)).is_some()
+    }
+
+    /// Number of preimages in the store
+    pub fn len(&self) -> usize { self.sha256.len() }
+
+    /// Whether the store contains no preimage
+    pub fn is_empty(&self) -> bool { self.sha256.is_empty() }
+
+    /// Iterates over the preimages of the store, ordered by their sha256 image
+    pub fn preimages(&self) -> impl Iterator<Item = &Preimage32> { self.sha256.values() }
+
+    /// The preimage of the hash of `lock`, if it is in the store
+    pub fn get<Pk: ToPublicKey>(&self, lock: &HashLock<Pk>) -> Option<Preimage32> {
+        match *lock {
+            HashLock::Sha256(ref h) => self.sha256.get(&Pk::to_sha256(h)),
+            HashLock::Hash256(ref h) => self.hash256.get(&Pk::to_hash256(h)),
+            HashLock::Ripemd160(ref h) => self.ripemd160.get(&Pk::to_ripemd160(h)),
+            HashLock::Hash160(ref h) => self.hash160.get(&Pk::to_hash160(h)),
+        }
+        .copied()
+    }
+}
+
+impl<Pk: MiniscriptKey + ToPublicKey> HashOracle<Pk> for PreimageStore {
+    fn preimage(&self, lock: &HashLock<Pk>) -> Option<Preimage32> { self.get(lock) }
+}
+
+impl<Pk: MiniscriptKey + ToPublicKey> Satisfier<Pk> for PreimageStore {
+    fn lookup_sha256(&self, h: &Pk::Sha256) -> Option<Preimage32> {
+        self.sha256.get(&Pk::to_sha256(h)).copied()
+    }
+
+    fn lookup_hash256(&self, h: &Pk::Hash256) -> Option<Preimage32> {
+        self.hash256.get(&Pk::to_hash256(h)).copied()
+    }
+
+    fn lookup_ripemd160(&self, h: &Pk::Ripemd160) -> Option<Preimage32> {
+        self.ripemd160.get(&Pk::to_ripemd160(h)).copied()
+    }
+
+    fn lookup_hash160(&self, h: &Pk::Hash160) -> Option<Preimage32> {
+        self.hash160.get(&Pk::to_hash160(h)).copied()
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for PreimageStore {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(
+            self.preimages()
+                .map(|preimage| preimage.to_lower_hex_string()),
+        )
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for PreimageStore {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let mut store = PreimageStore::new();
+        for preimage in Vec::<String>::deserialize(deserializer)? {
+            store
+                .insert_hex(&preimage)
+                .map_err(serde::de::Error::custom)?;
+        }
+        Ok(store)
+    }
+}
+
+/// Trait describing a lookup from public key hashes to public keys
+///
+/// Scripts parsed from Bitcoin Script only know the hash of the key of their
+/// `pkh` fragments, see [`Terminal::RawPkH`]. Use [`PkhResolverSatisfier`] to
+/// satisfy such scripts with the resolved keys, and
+/// [`crate::Interpreter::resolve_raw_pkh`] to interpret them.
+///
+/// The satisfier and the interpreter check that the returned key hashes to the
+/// given hash in the context of the script, so a resolver may mix hashes of
+/// ECDSA and x-only keys.
+/// [`crate::Descriptor::pkh_resolver`] provides one for the keys of a descriptor.
+pub trait PkhResolver<Pk: MiniscriptKey> {
+    /// Given a public key hash, look up the corresponding public key
+    fn resolve_pkh(&self, hash: &hash160::Hash) -> Option<Pk>;
+}
+
+impl<Pk: MiniscriptKey, F: Fn(&hash160::Hash) -> Option<Pk>> PkhResolver<Pk> for F {
+    fn resolve_pkh(&self, hash: &hash160::Hash) -> Option<Pk> { self(hash) }
+}
+
+macro_rules! impl_pkh_resolver_for_map {
+    ($(#[$($attr:meta)*])* impl PkhResolver<Pk> for $map:ident) => {
+        $(#[$($attr)*])*
+        impl<Pk: MiniscriptKey> PkhResolver<Pk> for $map<hash160::Hash, Pk> {
+            fn resolve_pkh(&self, hash: &hash160::Hash) -> Option<Pk> {
+                self.get(hash).cloned()
+            }
+        }
+    };
+}
+
+impl_pkh_resolver_for_map! {
+    impl PkhResolver<Pk> for BTreeMap
+}
+
+impl_pkh_resolver_for_map! {
+    #[cfg(feature = "std")]
+    impl PkhResolver<Pk> for HashMap
+}
+
+/// A [`Satisfier`] resolving public key hashes with a [`PkhResolver`]
+///
+/// Wraps a satisfier, the first field, and looks up the signatures of the keys
+/// resolved by the second field in it, as if the script contained the keys
+/// rather than their hashes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct PkhResolverSatisfier<S, R>(pub S, pub R);
+
+impl<S, R> PkhResolverSatisfier<S, R> {
+    /// Resolves a hash, checking the key against it
+    fn resolve<Pk>(&self, hash: &hash160::Hash, sig_type: SigType) -> Option<Pk>
+    where
+        Pk: MiniscriptKey + ToPublicKey,
+        R: PkhResolver<Pk>,
+    {
+        self.1
+            .resolve_pkh(hash)
+            .filter(|pk| pk.to_pubkeyhash(sig_type) == *hash)
+    }
+}
+
+impl<Pk, S, R> Satisfier<Pk> for PkhResolverSatisfier<S, R>
+where
+    Pk: MiniscriptKey + ToPublicKey,
+    S: Satisfier<Pk>,
+    R: PkhResolver<Pk>,
+{
+    fn lookup_ecdsa_sig(&self, p: &Pk) -> Option<bitcoin::ecdsa::Signature> {
+        self.0.lookup_ecdsa_sig(p)
+    }
+
+    fn lookup_tap_key_spend_sig(&self) -> Option<bitcoin::taproot::Signature> {
+        self.0.lookup_tap_key_spend_sig()
+    }
+
+    fn lookup_raw_tr_schnorr_sig(&self, p: &Pk) -> Option<bitcoin::taproot::Signature> {
+        self.0.lookup_raw_tr_schnorr_sig(p)
+    }
+
+    fn lookup_tap_leaf_script_sig(
+        &self,
+        p: &Pk,
+        h: &TapLeafHash,
+    ) -> Option<bitcoin::taproot::Signature> {
+        self.0.lookup_tap_leaf_script_sig(p, h)
+    }
+
+    fn lookup_tap_control_block_map(
+        &self,
+    ) -> Option<&BTreeMap<ControlBlock, (bitcoin::ScriptBuf, LeafVersion)>> {
+        self.0.lookup_tap_control_block_map()
+    }
+
+    fn lookup_raw_pkh_pk(&self, pkh: &hash160::Hash) -> Option<bitcoin::PublicKey> {
+        self.0.lookup_raw_pkh_pk(pkh).or_else(|| {
+            self.resolve::<Pk>(pkh, SigType::Ecdsa)
+                .map(|pk| pk.to_public_key())
+        })
+    }
+
+    fn lookup_raw_pkh_x_only_pk(&self, pkh: &hash160::Hash) -> Option<XOnlyPublicKey> {
+        self.0.lookup_raw_pkh_x_only_pk(pkh).or_else(|| {
+            self.resolve::<Pk>(pkh, SigType::Schnorr)
+                .map(|pk| pk.to_x_only_pubkey())
+        })
+    }
+
+    fn lookup_raw_pkh_ecdsa_sig(
+        &self,
+        pkh: &hash160::Hash,
+    ) -> Option<(bitcoin::PublicKey, bitcoin::ecdsa::Signature)> {
+        self.0.lookup_raw_pkh_ecdsa_sig(pkh).or_else(|| {
+            let pk = self.resolve::<Pk>(pkh, SigType::Ecdsa)?;
+            let sig = self.0.lookup_ecdsa_sig(&pk)?;
+            Some((pk.to_public_key(), sig))
+        })
+    }
+
+    fn lookup_raw_pkh_tap_leaf_script_sig(
+        &self,
+        pkh: &(hash160::Hash, TapLeafHash),
+    ) -> Option<(XOnlyPublicKey, bitcoin::taproot::Signature)> {
+        self.0.lookup_raw_pkh_tap_leaf_script_sig(pkh).or_else(|| {
+            let pk = self.resolve::<Pk>(&pkh.0, SigType::Schnorr)?;
+            let sig = self.0.lookup_tap_leaf_script_sig(&pk, &pkh.1)?;
+            Some((pk.to_x_only_pubkey(), sig))
+        })
+    }
+
+    fn lookup_sha256(&self, h: &Pk::Sha256) -> Option<Preimage32> { self.0.lookup_sha256(h) }
+
+    fn lookup_hash256(&self, h: &Pk::Hash256) -> Option<Preimage32> { self.0.lookup_hash256(h) }
+
+    fn lookup_ripemd160(&self, h: &Pk::Ripemd160) -> Option<Preimage32> {
+        self.0.lookup_ripemd160(h)
+    }
+
+    fn lookup_hash160(&self, h: &Pk::Hash160) -> Option<Preimage32> { self.0.lookup_hash160(h) }
+
+    fn check_older(&self, t: relative::LockTime) -> bool { self.0.check_older(t) }
+
+    fn check_after(&self, n: absolute::LockTime) -> bool { self.0.check_after(n) }
+}
+
+/// A registry of known public keys, indexed by their hashes
+///
+/// Each key is registered under both its ECDSA hash and the hash of its
+/// x-only form, so that it resolves the `pkh` fragments of any script context.
+/// The registry is a [`Satisfier`] providing the keys of the hashes, which is
+/// enough to dissatisfy the `pkh` fragments of scripts parsed from Bitcoin
+/// Script; combine it with the satisfier for the signatures in a tuple to
+/// satisfy them. It is also a [`PkhResolver`], and can be given to
+/// [`crate::psbt::PsbtExt::finalize_with_registry_mut`] for the keys missing
+/// from a PSBT.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct KeyRegistry {
+    ecdsa: BTreeMap<hash160::Hash, bitcoin::PublicKey>,
+    x_only: BTreeMap<hash160::Hash, XOnlyPublicKey>,
+}
+
+impl KeyRegistry {
+    /// Creates an empty registry
+    pub fn new() -> Self { Self::default() }
+
+    /// Registers a key
+    pub fn insert<Pk: ToPublicKey>(&mut self, pk: &Pk) {
+        self.ecdsa
+            .insert(pk.to_pubkeyhash(SigType::Ecdsa), pk.to_public_key());
+        self.x_only
+            .insert(pk.to_pubkeyhash(SigType::Schnorr), pk.to_x_only_pubkey());
+    }
+
+    /// The registered key whose ECDSA hash is `hash`
+    pub fn ecdsa_key(&self, hash: &hash160::Hash) -> Option<bitcoin::PublicKey> {
+        self.ecdsa.get(hash).copied()
+    }
+
+    /// The registered key whose x-only hash is `hash`
+    pub fn x_only_key(&self, hash: &hash160::Hash) -> Option<XOnlyPublicKey> {
+        self.x_only.get(hash).copied()
+    }
+
+    /// Iterates over the registered keys
+    pub fn keys(&self) -> impl Iterator<Item = &bitcoin::PublicKey> { self.ecdsa.values() }
+
+    /// Number of registered keys
+    pub fn len(&self) -> usize { self.ecdsa.len() }
+
+    /// Whether no key is registered
+    pub fn is_empty(&self) -> bool { self.ecdsa.is_empty() }
+}
+
+impl<Pk: ToPublicKey> Extend<Pk> for KeyRegistry {
+    fn extend<I: IntoIterator<Item = Pk>>(&mut self, iter: I) {
+        for pk in iter {
+            self.insert(&pk);
+        }
+    }
+}
+
+impl<Pk: ToPublicKey> core::iter::FromIterator<Pk> for KeyRegistry {
+    fn from_iter<I: IntoIterator<Item = Pk>>(iter: I) -> Self {
+        let mut registry = KeyRegistry::new();
+        registry.extend(iter);
+        registry
+    }
+}
+
+impl PkhResolver<bitcoin::PublicKey> for KeyRegistry {
+    fn resolve_pkh(&self, hash: &hash160::Hash) -> Option<bitcoin::PublicKey> {
+        self.ecdsa_key(hash)
+    }
+}
+
+impl PkhResolver<XOnlyPublicKey> for KeyRegistry {
+    fn resolve_pkh(&self, hash: &hash160::Hash) -> Option<XOnlyPublicKey> { self.x_only_key(hash) }
+}
+
+impl<Pk: MiniscriptKey + ToPublicKey> Satisfier<Pk> for KeyRegistry {
+    fn lookup_raw_pkh_pk(&self, pkh: &hash160::Hash) -> Option<bitcoin::PublicKey> {
+        self.ecdsa_key(pkh)
+    }
+
+    fn lookup_raw_pkh_x_only_pk(&self, pkh: &hash160::Hash) -> Option<XOnlyPublicKey> {
+        self.x_only_key(pkh)
+    }
+}
+
+/// Combinators of [`Satisfier`]s, implemented for every satisfier
+///
+/// Satisfiers can also be combined as tuples, which try each satisfier in
+/// order like [`SatisfierExt::or_else`].
+pub trait SatisfierExt<Pk: MiniscriptKey + ToPublicKey>: Satisfier<Pk> + Sized {
+    /// Looks up in `other` what this satisfier doesn't provide
+    fn or_else<S: Satisfier<Pk>>(self, other: S) -> OrElse<Self, S> { OrElse(self, other) }
+
+    /// Provides `sig` as the ECDSA signature of `pk`, rather than the one of this
+    /// satisfier
+    fn with_override(self, pk: Pk, sig: bitcoin::ecdsa::Signature) -> Override<Pk, Self> {
+        Override::new(self).with_override(pk, sig)
+    }
+
+    /// Provides `sig` as the Schnorr signature of `pk` in the tap leaf `leaf_hash`,
+    /// rather than the one of this satisfier
+    fn with_tap_override(
+        self,
+        pk: Pk,
+        leaf_hash: TapLeafHash,
+        sig: bitcoin::taproot::Signature,
+    ) -> Override<Pk, Self> {
+        Override::new(self).with_tap_override(pk, leaf_hash, sig)
+    }
+
+    /// Records the lookups made to this satisfier, see [`TracingSatisfier`]
+    fn traced(self) -> TracingSatisfier<Pk, Self> { TracingSatisfier::new(self) }
+}
+
+impl<Pk: MiniscriptKey + ToPublicKey, S: Satisfier<Pk>> SatisfierExt<Pk> for S {}
+
+/// A [`Satisfier`] looking up in the second satisfier what the first one doesn't
+/// provide, see [`SatisfierExt::or_else`]
+///
+/// Timelocks are satisfied if either satisfier satisfies them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct OrElse<A, B>(pub A, pub B);
+
+impl<Pk, A, B> Satisfier<Pk> for OrElse<A, B>
+where
+    Pk: MiniscriptKey + ToPublicKey,
+    A: Satisfier<Pk>,
+    B: Satisfier<Pk>,
+{
+    fn lookup_ecdsa_sig(&self, p: &Pk) -> Option<bitcoin::ecdsa::Signature> {
+        self.0
+            .lookup_ecdsa_sig(p)
+            .or_else(|| self.1.lookup_ecdsa_sig(p))
+    }
+
+    fn lookup_tap_key_spend_sig(&self) -> Option<bitcoin::taproot::Signature> {
+        self.0
+            .lookup_tap_key_spend_sig()
+            .or_else(|| self.1.lookup_tap_key_spend_sig())
+    }
+
+    fn lookup_raw_tr_schnorr_sig(&self, p: &Pk) -> Option<bitcoin::taproot::Signature> {
+        self.0
+            .lookup_raw_tr_schnorr_sig(p)
+            .or_else(|| self.1.lookup_raw_tr_schnorr_sig(p))
+    }
+
+    fn lookup_tap_leaf_script_sig(
+        &self,
+        p: &Pk,
+        h: &TapLeafHash,
+    ) -> Option<bitcoin::taproot::Signature> {
+        self.0
+            .lookup_tap_leaf_script_sig(p, h)
+            .or_else(|| self.1.lookup_tap_leaf_script_sig(p, h))
+    }
+
+    fn lookup_tap_control_block_map(
+        &self,
+    ) -> Option<&BTreeMap<ControlBlock, (bitcoin::ScriptBuf, LeafVersion)>> {
+        self.0
+            .lookup_tap_control_block_map()
+            .or_else(|| self.1.lookup_tap_control_block_map())
+    }
+
+    fn lookup_raw_pkh_pk(&self, pkh: &hash160::Hash) -> Option<bitcoin::PublicKey> {
+        self.0
+            .lookup_raw_pkh_pk(pkh)
+            .or_else(|| self.1.lookup_raw_pkh_pk(pkh))
+    }
+
+    fn lookup_raw_pkh_x_only_pk(&self, pkh: &hash160::Hash) -> Option<XOnlyPublicKey> {
+        self.0
+            .lookup_raw_pkh_x_only_pk(pkh)
+            .or_else(|| self.1.lookup_raw_pkh_x_only_pk(pkh))
+    }
+
+    fn lookup_raw_pkh_ecdsa_sig(
+        &self,
+        pkh: &hash160::Hash,
+    ) -> Option<(bitcoin::PublicKey, bitcoin::ecdsa::Signature)> {
+        self.0
+            .lookup_raw_pkh_ecdsa_sig(pkh)
+            .or_else(|| self.1.lookup_raw_pkh_ecdsa_sig(pkh))
+    }
+
+    fn lookup_raw_pkh_tap_leaf_script_sig(
+        &self,
+        pkh: &(hash160::Hash, TapLeafHash),
+    ) -> Option<(XOnlyPublicKey, bitcoin::taproot::Signature)> {
+        self.0
+            .lookup_raw_pkh_tap_leaf_script_sig(pkh)
+            .or_else(|| self.1.lookup_raw_pkh_tap_leaf_script_sig(pkh))
+    }
+
+    fn lookup_sha256(&self, h: &Pk::Sha256) -> Option<Preimage32> {
+        self.0.lookup_sha256(h).or_else(|| self.1.lookup_sha256(h))
+    }
+
+    fn lookup_hash256(&self, h: &Pk::Hash256) -> Option<Preimage32> {
+        self.0
+            .lookup_hash256(h)
+            .or_else(|| self.1.lookup_hash256(h))
+    }
+
+    fn lookup_ripemd160(&self, h: &Pk::Ripemd160) -> Option<Preimage32> {
+        self.0
+            .lookup_ripemd160(h)
+            .or_else(|| self.1.lookup_ripemd160(h))
+    }
+
+    fn lookup_hash160(&self, h: &Pk::Hash160) -> Option<Preimage32> {
+        self.0
+            .lookup_hash160(h)
+            .or_else(|| self.1.lookup_hash160(h))
+    }
+
+    fn check_older(&self, t: relative::LockTime) -> bool {
+        self.0.check_older(t) || self.1.check_older(t)
+    }
+
+    fn check_after(&self, n: absolute::LockTime) -> bool {
+        self.0.check_after(n) || self.1.check_after(n)
+    }
+}
+
+/// A [`Satisfier`] providing some signatures in place of the ones of the wrapped
+/// satisfier, see [`SatisfierExt::with_override`]
+///
+/// The overridden signatures are also provided for the `pkh` fragments of which
+/// only the key hash is known, see [`Terminal::RawPkH`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Override<Pk: MiniscriptKey, S> {
+    inner: S,
+    ecdsa_sigs: BTreeMap<Pk, bitcoin::ecdsa::Signature>,
+    tap_sigs: BTreeMap<(Pk, TapLeafHash), bitcoin::taproot::Signature>,
+}
+
+impl<Pk: MiniscriptKey, S> Override<Pk, S> {
+    /// Wraps a satisfier, without overriding any signature yet
+    pub fn new(inner: S) -> Self {
+        Override { inner, ecdsa_sigs: BTreeMap::new(), tap_sigs: BTreeMap::new() }
+    }
+
+    /// Provides `sig` as the ECDSA signature of `pk` too
+    pub fn with_override(mut self, pk: Pk, sig: bitcoin::ecdsa::Signature) -> Self {
+        self.ecdsa_sigs.insert(pk, sig);
+        self
+    }
+
+    /// Provides `sig` as the Schnorr signature of `pk` in the tap leaf `leaf_hash` too
+    pub fn with_tap_override(
+        mut self,
+        pk: Pk,
+        leaf_hash: TapLeafHash,
+        sig: bitcoin::taproot::Signature,
+    ) -> Self {
+        self.tap_sigs.insert((pk, leaf_hash), sig);
+        self
+    }
+
+    /// The wrapped satisfier
+    pub fn into_inner(self) -> S { self.inner }
+}
+
+impl<Pk, S> Satisfier<Pk> for Override<Pk, S>
+where
+    Pk: MiniscriptKey + ToPublicKey,
+    S: Satisfier<Pk>,
+{
+    fn lookup_ecdsa_sig(&self, p: &Pk) -> Option<bitcoin::ecdsa::Signature> {
+        self.ecdsa_sigs
+            .get(p)
+            .copied()
+            .or_else(|| self.inner.lookup_ecdsa_sig(p))
+    }
+
+    fn lookup_tap_key_spend_sig(&self) -> Option<bitcoin::taproot::Signature> {
+        self.inner.lookup_tap_key_spend_sig()
+    }
+
+    fn lookup_raw_tr_schnorr_sig(&self, p: &Pk) -> Option<bitcoin::taproot::Signature> {
+        self.inner.lookup_raw_tr_schnorr_sig(p)
+    }
+
+    fn lookup_tap_leaf_script_sig(
+        &self,
+        p: &Pk,
+        h: &TapLeafHash,
+    ) -> Option<bitcoin::taproot::Signature> {
+        self.tap_sigs
+            .get(&(p.clone(), *h))
+            .copied()
+            .or_else(|| self.inner.lookup_tap_leaf_script_sig(p, h))
+    }
+
+    fn lookup_tap_control_block_map(
+        &self,
+    ) -> Option<&BTreeMap<ControlBlock, (bitcoin::ScriptBuf, LeafVersion)>> {
+        self.inner.lookup_tap_control_block_map()
+    }
+
+    fn lookup_raw_pkh_pk(&self, pkh: &hash160::Hash) -> Option<bitcoin::PublicKey> {
+        self.inner.lookup_raw_pkh_pk(pkh)
+    }
+
+    fn lookup_raw_pkh_x_only_pk(&self, pkh: &hash160::Hash) -> Option<XOnlyPublicKey> {
+        self.inner.lookup_raw_pkh_x_only_pk(pkh)
+    }
+
+    fn lookup_raw_pkh_ecdsa_sig(
+        &self,
+        pkh: &hash160::Hash,
+    ) -> Option<(bitcoin::PublicKey, bitcoin::ecdsa::Signature)> {
+        self.ecdsa_sigs
+            .iter()
+            .find(|(pk, _)| pk.to_pubkeyhash(SigType::Ecdsa) == *pkh)
+            .map(|(pk, sig)| (pk.to_public_key(), *sig))
+            .or_else(|| self.inner.lookup_raw_pkh_ecdsa_sig(pkh))
+    }
+
+    fn lookup_raw_pkh_tap_leaf_script_sig(
+        &self,
+        pkh: &(hash160::Hash, TapLeafHash),
+    ) -> Option<(XOnlyPublicKey, bitcoin::taproot::Signature)> {
+        self.tap_sigs
+            .iter()
+            .find(|((pk, leaf_hash), _)| {
+                *leaf_hash == pkh.1 && pk.to_pubkeyhash(SigType::Schnorr) == pkh.0
+            })
+            .map(|((pk, _), sig)| (pk.to_x_only_pubkey(), *sig))
+            .or_else(|| self.inner.lookup_raw_pkh_tap_leaf_script_sig(pkh))
+    }
+
+    fn lookup_sha256(&self, h: &Pk::Sha256) -> Option<Preimage32> { self.inner.lookup_sha256(h) }
+
+    fn lookup_hash256(&self, h: &Pk::Hash256) -> Option<Preimage32> { self.inner.lookup_hash256(h) }
+
+    fn lookup_ripemd160(&self, h: &Pk::Ripemd160) -> Option<Preimage32> {
+        self.inner.lookup_ripemd160(h)
+    }
+
+    fn lookup_hash160(&self, h: &Pk::Hash160) -> Option<Preimage32> { self.inner.lookup_hash160(h) }
+
+    fn check_older(&self, t: relative::LockTime) -> bool { self.inner.check_older(t) }
+
+    fn check_after(&self, n: absolute::LockTime) -> bool { self.inner.check_after(n) }
+}
+
+/// A lookup made to a [`TracingSatisfier`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Lookup<Pk: MiniscriptKey> {
+    /// [`Satisfier::lookup_ecdsa_sig`]
+    EcdsaSig(Pk),
+    /// [`Satisfier::lookup_tap_key_spend_sig`]
+    TapKeySpendSig,
+    /// [`Satisfier::lookup_raw_tr_schnorr_sig`]
+    RawTrSchnorrSig(Pk),
+    /// [`Satisfier::lookup_tap_leaf_script_sig`]
+    TapLeafScriptSig(Pk, TapLeafHash),
+    /// [`Satisfier::lookup_tap_control_block_map`]
+    TapControlBlockMap,
+    /// [`Satisfier::lookup_raw_pkh_pk`]
+    RawPkhPk(hash160::Hash),
+    /// [`Satisfier::lookup_raw_pkh_x_only_pk`]
+    RawPkhXOnlyPk(hash160::Hash),
+    /// [`Satisfier::lookup_raw_pkh_ecdsa_sig`]
+    RawPkhEcdsaSig(hash160::Hash),
+    /// [`Satisfier::lookup_raw_pkh_tap_leaf_script_sig`]
+    RawPkhTapLeafScriptSig(hash160::Hash, TapLeafHash),
+    /// One of the preimage lookups, e.g. [`Satisfier::lookup_sha256`]
+    Preimage(HashLock<Pk>),
+    /// [`Satisfier::check_older`]
+    Older(relative::LockTime),
+    /// [`Satisfier::check_after`]
+    After(absolute::LockTime),
+}
+
+/// A [`Satisfier`] recording the lookups made to the wrapped satisfier, and
+/// whether they succeeded, see [`SatisfierExt::traced`]
+///
+/// Useful to find out which signature or preimage a satisfaction is missing.
+/// The satisfiers of this library may make the same lookup several times.
+#[derive(Debug)]
+pub struct TracingSatisfier<Pk: MiniscriptKey, S> {
+    inner: S,
+    lookups: core::cell::RefCell<Vec<(Lookup<Pk>, bool)>>,
+}
+
+impl<Pk: MiniscriptKey, S> TracingSatisfier<Pk, S> {
+    /// Wraps a satisfier, without any recorded lookup
+    pub fn new(inner: S) -> Self { TracingSatisfier { inner, lookups: Default::default() } }
+
+    /// The lookups made so far, in order, and whether each succeeded
+    pub fn lookups(&self) -> Vec<(Lookup<Pk>, bool)> { self.lookups.borrow().clone() }
+
+    /// The lookups made so far which failed, in order
+    pub fn missing(&self) -> Vec<Lookup<Pk>> {
+        self.lookups
+            .borrow()
+            .iter()
+            .filter(|(_, found)| !found)
+            .map(|(lookup, _)| lookup.clone())
+            .collect()
+    }
+
+    /// Forgets the lookups made so far
+    pub fn clear(&self) { self.lookups.borrow_mut().clear() }
+
+    /// The wrapped satisfier
+    pub fn into_inner(self) -> S { self.inner }
+
+    fn record<T>(&self, lookup: Lookup<Pk>, result: Option<T>) -> Option<T> {
+        self.lookups.borrow_mut().push((lookup, result.is_some()));
+        result
+    }
+
+    fn record_check(&self, lookup: Lookup<Pk>, result: bool) -> bool {
+        self.lookups.borrow_mut().push((lookup, result));
+        result
+    }
+}
+
+impl<Pk, S> Satisfier<Pk> for TracingSatisfier<Pk, S>
+where
+    Pk: MiniscriptKey + ToPublicKey,
+    S: Satisfier<Pk>,
+{
+    fn lookup_ecdsa_sig(&self, p: &Pk) -> Option<bitcoin::ecdsa::Signature> {
+        self.record(Lookup::EcdsaSig(p.clone()), self.inner.lookup_ecdsa_sig(p))
+    }
+
+    fn lookup_tap_key_spend_sig(&self) -> Option<bitcoin::taproot::Signature> {
+        self.record(Lookup::TapKeySpendSig, self.inner.lookup_tap_key_spend_sig())
+    }
+
+    fn lookup_raw_tr_schnorr_sig(&self, p: &Pk) -> Option<bitcoin::taproot::Signature> {
+        self.record(Lookup::RawTrSchnorrSig(p.clone()), self.inner.lookup_raw_tr_schnorr_sig(p))
+    }
+
+    fn lookup_tap_leaf_script_sig(
+        &self,
+        p: &Pk,
+        h: &TapLeafHash,
+    ) -> Option<bitcoin::taproot::Signature> {
+        self.record(
+            Lookup::TapLeafScriptSig(p.clone(), *h),
+            self.inner.lookup_tap_leaf_script_sig(p, h),
+        )
+    }
+
+    fn lookup_tap_control_block_map(
+        &self,
+    ) -> Option<&BTreeMap<ControlBlock, (bitcoin::ScriptBuf, LeafVersion)>> {
+        self.record(Lookup::TapControlBlockMap, self.inner.lookup_tap_control_block_map())
+    }
+
+    fn lookup_raw_pkh_pk(&self, pkh: &hash160::Hash) -> Option<bitcoin::PublicKey> {
+        self.record(Lookup::RawPkhPk(*pkh), self.inner.lookup_raw_pkh_pk(pkh))
+    }
+
+    fn lookup_raw_pkh_x_only_pk(&self, pkh: &hash160::Hash) -> Option<XOnlyPublicKey> {
+        self.record(Lookup::RawPkhXOnlyPk(*pkh), self.inner.lookup_raw_pkh_x_only_pk(pkh))
+    }
 
-    /// Given a keyhash, look up the EC signature and the associated key.
-    ///
-    /// Even if signatures for public key Hashes are not available, the users
-    /// can use this map to provide pkh -> pk mapping which can be useful
-    /// for dissatisfying pkh.
     fn lookup_raw_pkh_ecdsa_sig(
         &self,
-        _: &hash160::Hash,
+        pkh: &hash160::Hash,
     ) -> Option<(bitcoin::PublicKey, bitcoin::ecdsa::Signature)> {
-        None
+        self.record(Lookup::RawPkhEcdsaSig(*pkh), self.inner.lookup_raw_pkh_ecdsa_sig(pkh))
     }
 
-    /// Given a keyhash, look up the schnorr signature and the associated key.
-    ///
-    /// Even if signatures for public key Hashes are not available, the users
-    /// can use this map to provide pkh -> pk mapping which can be useful
-    /// for dissatisfying pkh.
     fn lookup_raw_pkh_tap_leaf_script_sig(
         &self,
-        _: &(hash160::Hash, TapLeafHash),
+        pkh: &(hash160::Hash, TapLeafHash),
     ) -> Option<(XOnlyPublicKey, bitcoin::taproot::Signature)> {
-        None
+        self.record(
+            Lookup::RawPkhTapLeafScriptSig(pkh.0, pkh.1),
+            self.inner.lookup_raw_pkh_tap_leaf_script_sig(pkh),
+        )
     }
 
-    /// Given a SHA256 hash, look up its preimage
-    fn lookup_sha256(&self, _: &Pk::Sha256) -> Option<Preimage32> { None }
+    fn lookup_sha256(&self, h: &Pk::Sha256) -> Option<Preimage32> {
+        self.record(Lookup::Preimage(HashLock::Sha256(h.clone())), self.inner.lookup_sha256(h))
+    }
 
-    /// Given a HASH256 hash, look up its preimage
-    fn lookup_hash256(&self, _: &Pk::Hash256) -> Option<Preimage32> { None }
+    fn lookup_hash256(&self, h: &Pk::Hash256) -> Option<Preimage32> {
+        self.record(Lookup::Preimage(HashLock::Hash256(h.clone())), self.inner.lookup_hash256(h))
+    }
 
-    /// Given a RIPEMD160 hash, look up its preimage
-    fn lookup_ripemd160(&self, _: &Pk::Ripemd160) -> Option<Preimage32> { None }
+    fn lookup_ripemd160(&self, h: &Pk::Ripemd160) -> Option<Preimage32> {
+        self.record(
+            Lookup::Preimage(HashLock::Ripemd160(h.clone())),
+            self.inner.lookup_ripemd160(h),
+        )
+    }
 
-    /// Given a HASH160 hash, look up its preimage
-    fn lookup_hash160(&self, _: &Pk::Hash160) -> Option<Preimage32> { None }
+    fn lookup_hash160(&self, h: &Pk::Hash160) -> Option<Preimage32> {
+        self.record(Lookup::Preimage(HashLock::Hash160(h.clone())), self.inner.lookup_hash160(h))
+    }
 
-    /// Assert whether an relative locktime is satisfied
+    fn check_older(&self, t: relative::LockTime) -> bool {
+        self.record_check(Lookup::Older(t), self.inner.check_older(t))
+    }
+
+    fn check_after(&self, n: absolute::LockTime) -> bool {
+        self.record_check(Lookup::After(n), self.inner.check_after(n))
+    }
+}
+
+/// Type alias for a 66 byte MuSig2 public nonce, two compressed points.
+pub type PubNonce = [u8; 66];
+
+/// Type alias for a 32 byte MuSig2 partial signature.
+pub type PartialSig = [u8; 32];
+
+/// The round a [`SigningSession`] is in
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum SessionRound {
+    /// Participants commit to the SHA256 hash of their public nonce
+    Commit,
+    /// Participants reveal the public nonce they committed to
+    Reveal,
+    /// Participants provide their partial signature
+    Sign,
+    /// The aggregate signature is known
+    Complete,
+}
+
+impl fmt::Display for SessionRound {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SessionRound::Commit => f.write_str("commit"),
+            SessionRound::Reveal => f.write_str("reveal"),
+            SessionRound::Sign => f.write_str("sign"),
+            SessionRound::Complete => f.write_str("complete"),
+        }
+    }
+}
+
+/// Error creating, updating or parsing a [`SigningSession`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SessionError<Pk: MiniscriptKey> {
+    /// The session has no participants
+    NoParticipants,
+    /// The aggregate key isn't the MuSig2 aggregate of the participants
+    AggregateKeyMismatch(Pk),
+    /// This key is not a participant of the session
+    UnknownParticipant(Pk),
+    /// The participant already provided its contribution to this round
+    Duplicate(Pk),
+    /// The session is in another round
+    WrongRound {
+        /// The round the update belongs to
+        expected: SessionRound,
+        /// The round the session is in
+        actual: SessionRound,
+    },
+    /// The revealed nonce of the participant doesn't match its commitment
+    CommitmentMismatch(Pk),
+    /// The public nonce of the participant isn't a valid MuSig2 public nonce
+    InvalidNonce(Pk),
+    /// The partial signature of the participant isn't a valid MuSig2 partial signature
+    InvalidPartialSig(Pk),
+    /// The partial signature of the participant is missing
+    MissingPartialSig(Pk),
+    /// The serialized session is malformed
+    Parse(String),
+}
+
+impl<Pk: MiniscriptKey> fmt::Display for SessionError<Pk> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SessionError::NoParticipants => f.write_str("session has no participants"),
+            SessionError::AggregateKeyMismatch(ref pk) => {
+                write!(f, "{} is not the aggregate key of the participants", pk)
+            }
+            SessionError::UnknownParticipant(ref pk) => {
+                write!(f, "{} is not a participant of the session", pk)
+            }
+            SessionError::Duplicate(ref pk) => {
+                write!(f, "{} already contributed to this round", pk)
+            }
+            SessionError::WrongRound { expected, actual } => {
+                write!(f, "session is in the {} round, not the {} round", actual, expected)
+            }
+            SessionError::CommitmentMismatch(ref pk) => {
+                write!(f, "nonce revealed by {} doesn't match its commitment", pk)
+            }
+            SessionError::InvalidNonce(ref pk) => write!(f, "invalid nonce revealed by {}", pk),
+            SessionError::InvalidPartialSig(ref pk) => {
+                write!(f, "invalid partial signature of {}", pk)
+            }
+            SessionError::MissingPartialSig(ref pk) => {
+                write!(f, "partial signature of {} is missing", pk)
+            }
+            SessionError::Parse(ref s) => write!(f, "malformed session: {}", s),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<Pk: MiniscriptKey> std::error::Error for SessionError<Pk> {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> { None }
+}
+
+/// The state of a participant of a [`SigningSession`]
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+struct SessionParticipant {
+    commitment: Option<sha256::Hash>,
+    nonce: Option<PubNonce>,
+    partial_sig: Option<PartialSig>,
+}
+
+/// A MuSig2-style signing session for an aggregate key, with nonce
+/// pre-commitments
+///
+/// The session tracks the contributions of each participant across the
+/// rounds, checking that each revealed nonce matches its commitment. The
+/// nonce and signature aggregation themselves are left to the signing
+/// software, which sets the aggregate signature once all partial signatures
+/// are known. The session then acts as a [`Satisfier`] for the aggregate key.
+///
+/// The aggregate key is bound to the participants: it must be their MuSig2
+/// aggregate, which is checked when the session is created. Revealed nonces
+/// and partial signatures are checked to be well-formed, but partial
+/// signatures aren't verified against the message.
+///
+/// The session serializes to a line-based string with [`fmt::Display`] and
+/// parses back with [`core::str::FromStr`], so that it can be persisted
+/// between rounds or passed between processes.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct SigningSession<Pk: MiniscriptKey> {
+    aggregate_key: Pk,
+    leaf_hash: Option<TapLeafHash>,
+    participants: Vec<(Pk, SessionParticipant)>,
+    signature: Option<bitcoin::taproot::Signature>,
+}
+
+impl<Pk: MiniscriptKey + ToPublicKey> SigningSession<Pk> {
+    /// Creates a session for `aggregate_key` between `participants`, for a key
+    /// spend if `leaf_hash` is `None` or a script spend of the leaf otherwise
     ///
-    /// NOTE: If a descriptor mixes time-based and height-based timelocks, the implementation of
-    /// this method MUST only allow timelocks of either unit, but not both. Allowing both could cause
-    /// miniscript to construct an invalid witness.
-    fn check_older(&self, _: relative::LockTime) -> bool { false }
+    /// Duplicate participants are only counted once. `aggregate_key` must be
+    /// the `KeyAgg` of BIP 327 of the remaining participants in the given
+    /// order, so the participants of a `musig()` descriptor key must be sorted
+    /// first. For a key spend, it is the untweaked internal key.
+    ///
+    /// # Errors
+    ///
+    /// If there are no participants, or if `aggregate_key` isn't their aggregate.
+    pub fn new(
+        aggregate_key: Pk,
+        participants: Vec<Pk>,
+        leaf_hash: Option<TapLeafHash>,
+    ) -> Result<Self, SessionError<Pk>> {
+        let mut session =
+            SigningSession { aggregate_key, leaf_hash, participants: vec![], signature: None };
+        for pk in participants {
+            if session.participant(&pk).is_err() {
+                session
+                    .participants
+                    .push((pk, SessionParticipant::default()));
+            }
+        }
+        if session.participants.is_empty() {
+            return Err(SessionError::NoParticipants);
+        }
+        let keys = session
+            .participants
+            .iter()
+            .map(|(pk, _)| pk.to_public_key())
+            .collect::<Vec<_>>();
+        if musig_key_agg(&keys).x_only_public_key().0 != session.aggregate_key.to_x_only_pubkey() {
+            return Err(SessionError::AggregateKeyMismatch(session.aggregate_key));
+        }
+        Ok(session)
+    }
+}
 
-    /// Assert whether a absolute locktime is satisfied
+impl<Pk: MiniscriptKey> SigningSession<Pk> {
+    /// The aggregate key signed for
+    pub fn aggregate_key(&self) -> &Pk { &self.aggregate_key }
+
+    /// The leaf signed for, or `None` for a key spend
+    pub fn leaf_hash(&self) -> Option<TapLeafHash> { self.leaf_hash }
+
+    /// The participants, in order
+    pub fn participants(&self) -> impl Iterator<Item = &Pk> {
+        self.participants.iter().map(|(pk, _)| pk)
+    }
+
+    /// The current round of the session
+    pub fn round(&self) -> SessionRound {
+        if self.signature.is_some() {
+            SessionRound::Complete
+        } else if self.participants.iter().all(|(_, p)| p.nonce.is_some()) {
+            SessionRound::Sign
+        } else if self
+            .participants
+            .iter()
+            .all(|(_, p)| p.commitment.is_some())
+        {
+            SessionRound::Reveal
+        } else {
+            SessionRound::Commit
+        }
+    }
+
+    /// Records the commitment of `pk` to its public nonce
+    pub fn commit_nonce(
+        &mut self,
+        pk: &Pk,
+        commitment: sha256::Hash,
+    ) -> Result<(), SessionError<Pk>> {
+        let participant = self.update(pk, SessionRound::Commit)?;
+        if participant.commitment.is_some() {
+            return Err(SessionError::Duplicate(pk.clone()));
+        }
+        participant.commitment = Some(commitment);
+        Ok(())
+    }
+
+    /// Records the public nonce of `pk`, which must match its commitment
+    pub fn reveal_nonce(&mut self, pk: &Pk, nonce: PubNonce) -> Result<(), SessionError<Pk>> {
+        let participant = self.update(pk, SessionRound::Reveal)?;
+        if participant.nonce.is_some() {
+            return Err(SessionError::Duplicate(pk.clone()));
+        }
+        if participant.commitment != Some(sha256::Hash::hash(&nonce)) {
+            return Err(SessionError::CommitmentMismatch(pk.clone()));
+        }
+        if PublicNonce::from_byte_array(&nonce).is_err() {
+            return Err(SessionError::InvalidNonce(pk.clone()));
+        }
+        participant.nonce = Some(nonce);
+        Ok(())
+    }
+
+    /// Records the partial signature of `pk`
+    pub fn add_partial_sig(&mut self, pk: &Pk, sig: PartialSig) -> Result<(), SessionError<Pk>> {
+        let participant = self.update(pk, SessionRound::Sign)?;
+        if participant.partial_sig.is_some() {
+            return Err(SessionError::Duplicate(pk.clone()));
+        }
+        if PartialSignature::from_byte_array(&sig).is_err() {
+            return Err(SessionError::InvalidPartialSig(pk.clone()));
+        }
+        participant.partial_sig = Some(sig);
+        Ok(())
+    }
+
+    /// Completes the session with the aggregate of the partial signatures
     ///
-    /// NOTE: If a descriptor mixes time-based and height-based timelocks, the implementation of
-    /// this method MUST only allow timelocks of either unit, but not both. Allowing both could cause
-    /// miniscript to construct an invalid witness.
-    fn check_after(&self, _: absolute::LockTime) -> bool { false }
+    /// # Errors
+    ///
+    /// If a partial signature is missing, or if the session is already
+    /// complete.
+    pub fn set_signature(
+        &mut self,
+        sig: bitcoin::taproot::Signature,
+    ) -> Result<(), SessionError<Pk>> {
+        self.check_round(SessionRound::Sign)?;
+        if let Some((pk, _)) = self
+            .participants
+            .iter()
+            .find(|(_, p)| p.partial_sig.is_none())
+        {
+            return Err(SessionError::MissingPartialSig(pk.clone()));
+        }
+        self.signature = Some(sig);
+        Ok(())
+    }
+
+    /// The public nonces of all participants, in order, once all are revealed
+    pub fn nonces(&self) -> Option<Vec<PubNonce>> {
+        self.participants.iter().map(|(_, p)| p.nonce).collect()
+    }
+
+    /// The partial signatures of all participants, in order, once all are known
+    pub fn partial_sigs(&self) -> Option<Vec<PartialSig>> {
+        self.participants
+            .iter()
+            .map(|(_, p)| p.partial_sig)
+            .collect()
+    }
+
+    /// The aggregate signature, once the session is complete
+    pub fn signature(&self) -> Option<bitcoin::taproot::Signature> { self.signature }
+
+    fn participant(&self, pk: &Pk) -> Result<usize, SessionError<Pk>> {
+        self.participants
+            .iter()
+            .position(|(key, _)| key == pk)
+            .ok_or_else(|| SessionError::UnknownParticipant(pk.clone()))
+    }
+
+    fn check_round(&self, expected: SessionRound) -> Result<(), SessionError<Pk>> {
+        let actual = self.round();
+        if actual == expected {
+            Ok(())
+        } else {
+            Err(SessionError::WrongRound { expected, actual })
+        }
+    }
+
+    fn update(
+        &mut self,
+        pk: &Pk,
+        round: SessionRound,
+    ) -> Result<&mut SessionParticipant, SessionError<Pk>> {
+        let index = self.participant(pk)?;
+        self.check_round(round)?;
+        Ok(&mut self.participants[index].1)
+    }
 }
 
-// Allow use of `()` as a "no conditions available" satisfier
-impl<Pk: MiniscriptKey + ToPublicKey> Satisfier<Pk> for () {}
+impl<Pk: MiniscriptKey + ToPublicKey> Satisfier<Pk> for SigningSession<Pk> {
+    fn lookup_tap_key_spend_sig(&self) -> Option<bitcoin::taproot::Signature> {
+        match self.leaf_hash {
+            None => self.signature,
+            Some(_) => None,
+        }
+    }
 
-impl<Pk: MiniscriptKey + ToPublicKey> Satisfier<Pk> for Sequence {
-    fn check_older(&self, n: relative::LockTime) -> bool {
-        if let Some(lt) = self.to_relative_lock_time() {
-            Satisfier::<Pk>::check_older(&lt, n)
+    fn lookup_tap_leaf_script_sig(
+        &self,
+        pk: &Pk,
+        h: &TapLeafHash,
+    ) -> Option<bitcoin::taproot::Signature> {
+        if *pk == self.aggregate_key && self.leaf_hash == Some(*h) {
+            self.signature
         } else {
-            false
+            None
         }
     }
 }
 
-impl<Pk: MiniscriptKey + ToPublicKey> Satisfier<Pk> for RelLockTime {
-    fn check_older(&self, n: relative::LockTime) -> bool {
-        <relative::LockTime as Satisfier<Pk>>::check_older(&(*self).into(), n)
+impl<Pk: MiniscriptKey> fmt::Display for SigningSession<Pk> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "session {} ", self.aggregate_key)?;
+        match self.leaf_hash {
+            Some(h) => writeln!(f, "{}", h)?,
+            None => writeln!(f, "keyspend")?,
+        }
+        for (pk, p) in &self.participants {
+            write!(f, "participant {}", pk)?;
+            match p.commitment {
+                Some(h) => write!(f, " {}", h)?,
+                None => f.write_str(" -")?,
+            }
+            match p.nonce {
+                Some(ref nonce) => write!(f, " {}", nonce.as_hex())?,
+                None => f.write_str(" -")?,
+            }
+            match p.partial_sig {
+                Some(ref sig) => write!(f, " {}", sig.as_hex())?,
+                None => f.write_str(" -")?,
+            }
+            writeln!(f)?;
+        }
+        if let Some(sig) = self.signature {
+            writeln!(f, "signature {}", sig.to_vec().as_hex())?;
+        }
+        Ok(())
     }
 }
 
-impl<Pk: MiniscriptKey + ToPublicKey> Satisfier<Pk> for relative::LockTime {
-    fn check_older(&self, n: relative::LockTime) -> bool { n.is_implied_by(*self) }
+impl<Pk: crate::FromStrKey + ToPublicKey> core::str::FromStr for SigningSession<Pk> {
+    type Err = SessionError<Pk>;
+
+    /// Parses a session serialized with [`fmt::Display`], replaying each
+    /// contribution so that the parsed session is consistent.
+    fn from_str(s: &str) -> Result<Self, SessionError<Pk>> {
+        fn parse<T: core::str::FromStr>(s: &str) -> Result<T, String> {
+            s.parse().map_err(|_| format!("invalid value {}", s))
+        }
+        fn parse_opt<T: core::str::FromStr>(s: &str) -> Result<Option<T>, String> {
+            if s == "-" {
+                Ok(None)
+            } else {
+                parse(s).map(Some)
+            }
+        }
+        fn parse_hex<T: FromHex>(s: &str) -> Result<Option<T>, String> {
+            if s == "-" {
+                Ok(None)
+            } else {
+                T::from_hex(s)
+                    .map(Some)
+                    .map_err(|_| format!("invalid hex {}", s))
+            }
+        }
+
+        let mut lines = s
+            .lines()
+            .map(|line| line.split_whitespace().collect::<Vec<_>>());
+        let (aggregate_key, leaf_hash) = match lines.next().as_deref() {
+            Some(["session", key, leaf]) => {
+                let leaf_hash = if *leaf == "keyspend" {
+                    None
+                } else {
+                    Some(parse(leaf).map_err(SessionError::Parse)?)
+                };
+                (parse::<Pk>(key).map_err(SessionError::Parse)?, leaf_hash)
+            }
+            _ => return Err(SessionError::Parse("missing session header".to_owned())),
+        };
+
+        let mut contributions: Vec<(Pk, _, _, _)> = vec![];
+        let mut signature = None;
+        for line in lines {
+            match line.as_slice() {
+                ["participant", key, commitment, nonce, partial_sig] => {
+                    let pk: Pk = parse(key).map_err(SessionError::Parse)?;
+                    if contributions.iter().any(|(key, ..)| *key == pk) {
+                        return Err(SessionError::Duplicate(pk));
+                    }
+                    contributions.push((
+                        pk,
+                        parse_opt::<sha256::Hash>(commitment).map_err(SessionError::Parse)?,
+                        parse_hex::<PubNonce>(nonce).map_err(SessionError::Parse)?,
+                        parse_hex::<PartialSig>(partial_sig).map_err(SessionError::Parse)?,
+                    ));
+                }
+                ["signature", sig] if signature.is_none() => {
+                    let bytes = parse_hex::<Vec<u8>>(sig)
+                        .map_err(SessionError::Parse)?
+                        .unwrap_or_default();
+                    signature = Some(
+                        bitcoin::taproot::Signature::from_slice(&bytes)
+                            .map_err(|e| SessionError::Parse(e.to_string()))?,
+                    );
+                }
+                [] => {}
+                _ => {
+                    return Err(SessionError::Parse(format!("unexpected line {}", line.join(" "))))
+                }
+            }
+        }
+
+        let participants = contributions.iter().map(|(pk, ..)| pk.clone()).collect();
+        let mut session = SigningSession::new(aggregate_key, participants, leaf_hash)?;
+        for (pk, commitment, _, _) in &contributions {
+            if let Some(commitment) = commitment {
+                session.commit_nonce(pk, *commitment)?;
+            }
+        }
+        for (pk, _, nonce, _) in &contributions {
+            if let Some(nonce) = nonce {
+                session.reveal_nonce(pk, *nonce)?;
+            }
+        }
+        for (pk, _, _, partial_sig) in &contributions {
+            if let Some(partial_sig) = partial_sig {
+                session.add_partial_sig(pk, *partial_sig)?;
+            }
+        }
+        if let Some(sig) = signature {
+            session.set_signature(sig)?;
+        }
+        Ok(session)
+    }
 }
 
-impl<Pk: MiniscriptKey + ToPublicKey> Satisfier<Pk> for absolute::LockTime {
-    fn check_after(&self, n: absolute::LockTime) -> bool { n.is_implied_by(*self) }
+#[cfg(feature = "serde")]
+impl<Pk: MiniscriptKey> serde::Serialize for SigningSession<Pk> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(&self)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, Pk: crate::FromStrKey + ToPublicKey> serde::Deserialize<'de> for SigningSession<Pk> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::Error as _;
+
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(D::Error::custom)
+    }
 }
 
 macro_rules! impl_satisfier_for_map_key_to_ecdsa_sig {
@@ -294,6 +1636,10 @@ impl<Pk: MiniscriptKey + ToPublicKey, S: Satisfier<Pk>> Satisfier<Pk> for &S {
         (**self).lookup_tap_key_spend_sig()
     }
 
+    fn lookup_raw_tr_schnorr_sig(&self, p: &Pk) -> Option<bitcoin::taproot::Signature> {
+        (**self).lookup_raw_tr_schnorr_sig(p)
+    }
+
     fn lookup_raw_pkh_tap_leaf_script_sig(
         &self,
         pkh: &(hash160::Hash, TapLeafHash),
@@ -339,6 +1685,10 @@ impl<Pk: MiniscriptKey + ToPublicKey, S: Satisfier<Pk>> Satisfier<Pk> for &mut S
         (**self).lookup_tap_key_spend_sig()
     }
 
+    fn lookup_raw_tr_schnorr_sig(&self, p: &Pk) -> Option<bitcoin::taproot::Signature> {
+        (**self).lookup_raw_tr_schnorr_sig(p)
+    }
+
     fn lookup_raw_pkh_pk(&self, pkh: &hash160::Hash) -> Option<bitcoin::PublicKey> {
         (**self).lookup_raw_pkh_pk(pkh)
     }
@@ -410,6 +1760,16 @@ macro_rules! impl_tuple_satisfier {
                 None
             }
 
+            fn lookup_raw_tr_schnorr_sig(&self, key: &Pk) -> Option<bitcoin::taproot::Signature> {
+                let &($(ref $ty,)*) = self;
+                $(
+                    if let Some(result) = $ty.lookup_raw_tr_schnorr_sig(key) {
+                        return Some(result);
+                    }
+                )*
+                None
+            }
+
             fn lookup_tap_leaf_script_sig(&self, key: &Pk, h: &TapLeafHash) -> Option<bitcoin::taproot::Signature> {
                 let &($(ref $ty,)*) = self;
                 $(
@@ -568,6 +1928,8 @@ pub enum SchnorrSigType {
         /// Leaf hash of the script
         leaf_hash: TapLeafHash,
     },
+    /// Key spend signature of a `rawtr()` output, made with the untweaked output key
+    RawKeySpend,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
@@ -678,6 +2040,13 @@ impl<Pk: MiniscriptKey + ToPublicKey> Placeholder<Pk> {
                     debug_assert!(s.len() == *size);
                     s
                 }),
+            Placeholder::SchnorrSigPk(pk, SchnorrSigType::RawKeySpend, size) => sat
+                .lookup_raw_tr_schnorr_sig(pk)
+                .map(|s| s.to_vec())
+                .map(|s| {
+                    debug_assert!(s.len() == *size);
+                    s
+                }),
             Placeholder::SchnorrSigPk(_, _, size) => {
                 sat.lookup_tap_key_spend_sig().map(|s| s.to_vec()).map(|s| {
                     debug_assert!(s.len() == *size);
@@ -912,6 +2281,43 @@ impl<Pk: MiniscriptKey + ToPublicKey> Satisfaction<Placeholder<Pk>> {
         )
     }
 
+    /// Same as [`Self::build_template`], discarding the (dis)satisfactions which need more than
+    /// `max_elems` witness elements as soon as they are compared to others
+    ///
+    /// Discarded witnesses are marked unavailable rather than impossible, since a third party
+    /// could still use them, so the template is non-malleable as without the limit. Also
+    /// returns the least number of elements of a discarded witness, if any was discarded.
+    pub(crate) fn build_template_with_max_elems<P, Ctx>(
+        term: &Terminal<Pk, Ctx>,
+        provider: &P,
+        root_has_sig: bool,
+        leaf_hash: &TapLeafHash,
+        max_elems: usize,
+    ) -> (Self, Option<usize>)
+    where
+        Ctx: ScriptContext,
+        P: AssetProvider<Pk>,
+    {
+        let mut min_discarded: Option<usize> = None;
+        let mut discard = |sat: Self| match sat.stack {
+            Witness::Stack(ref stack) if stack.len() > max_elems => {
+                let len = stack.len();
+                min_discarded = Some(min_discarded.map_or(len, |min| cmp::min(min, len)));
+                Satisfaction { stack: Witness::Unavailable, ..sat }
+            }
+            _ => sat,
+        };
+        let template = Self::satisfy_helper(
+            term,
+            provider,
+            root_has_sig,
+            leaf_hash,
+            &mut |sat1, sat2| Satisfaction::minimum(discard(sat1), discard(sat2)),
+            &mut Satisfaction::thresh,
+        );
+        (template, min_discarded)
+    }
+
     pub(crate) fn build_template_mall<P, Ctx>(
         term: &Terminal<Pk, Ctx>,
         provider: &P,
@@ -1243,6 +2649,14 @@ impl<Pk: MiniscriptKey + ToPublicKey> Satisfaction<Placeholder<Pk>> {
             &mut F,
         ) -> Satisfaction<Placeholder<Pk>>,
     {
+        if !stfr.check_search_node() {
+            return Satisfaction {
+                stack: Witness::Impossible,
+                has_sig: false,
+                relative_timelock: None,
+                absolute_timelock: None,
+            };
+        }
         match *term {
             Terminal::PkK(ref pk) => Satisfaction {
                 stack: Witness::signature::<_, Ctx>(stfr, pk, leaf_hash),
@@ -1612,6 +3026,14 @@ impl<Pk: MiniscriptKey + ToPublicKey> Satisfaction<Placeholder<Pk>> {
             &mut F,
         ) -> Satisfaction<Placeholder<Pk>>,
     {
+        if !stfr.check_search_node() {
+            return Satisfaction {
+                stack: Witness::Impossible,
+                has_sig: false,
+                relative_timelock: None,
+                absolute_timelock: None,
+            };
+        }
         match *term {
             Terminal::PkK(..) => Satisfaction {
                 stack: Witness::push_0(),
@@ -1825,6 +3247,36 @@ impl Satisfaction<Vec<u8>> {
             .expect("the same satisfier should manage to complete the template")
     }
 
+    /// Produce a non-malleable satisfaction, discarding the (dis)satisfactions of fragments
+    /// which need more than `max_elems` witness elements during the search
+    ///
+    /// See [`Satisfaction::build_template_with_max_elems`].
+    pub(super) fn satisfy_with_max_elems<Ctx, Pk, Sat>(
+        term: &Terminal<Pk, Ctx>,
+        stfr: &Sat,
+        root_has_sig: bool,
+        leaf_hash: &TapLeafHash,
+        max_elems: usize,
+    ) -> (Self, Option<usize>)
+    where
+        Ctx: ScriptContext,
+        Pk: MiniscriptKey + ToPublicKey,
+        Sat: Satisfier<Pk>,
+    {
+        let (template, min_discarded) =
+            Satisfaction::<Placeholder<Pk>>::build_template_with_max_elems(
+                term,
+                &stfr,
+                root_has_sig,
+                leaf_hash,
+                max_elems,
+            );
+        let satisfaction = template
+            .try_completing(stfr)
+            .expect("the same satisfier should manage to complete the template");
+        (satisfaction, min_discarded)
+    }
+
     /// Produce a satisfaction(possibly malleable)
     pub(super) fn satisfy_mall<Ctx, Pk, Sat>(
         term: &Terminal<Pk, Ctx>,
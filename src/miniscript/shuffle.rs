@@ -0,0 +1,385 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! Key Shuffling
+//!
+//! Permuting the keys of `multi` and `multi_a` fragments. Unlike `sortedmulti`,
+//! these fragments keep their keys in the order they are written, so a wallet
+//! always putting the same party's key first reveals it on chain. The functions
+//! in this module shuffle the keys and record the permutations, so that they
+//! can be communicated to the signers or undone.
+
+use core::fmt;
+
+use bitcoin::hashes::{sha256, Hash, HashEngine};
+use sync::Arc;
+
+use super::decode::Terminal;
+use super::{Miniscript, MiniscriptKey, ScriptContext};
+use crate::iter::TreeLike;
+use crate::prelude::*;
+use crate::Threshold;
+
+/// A permutation of the keys of a single `multi` or `multi_a` fragment
+///
+/// The key at position `i` of the permuted fragment is the key at position
+/// `self.as_slice()[i]` of the original fragment.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct KeyPermutation(Vec<usize>);
+
+impl KeyPermutation {
+    /// Creates a permutation, checking that each of `0..positions.len()` appears exactly once
+    pub fn new(positions: Vec<usize>) -> Result<Self, PermutationError> {
+        let mut seen = vec![false; positions.len()];
+        for &pos in &positions {
+            match seen.get_mut(pos) {
+                Some(seen @ false) => *seen = true,
+                _ => return Err(PermutationError::NotAPermutation(positions)),
+            }
+        }
+        Ok(KeyPermutation(positions))
+    }
+
+    /// The identity permutation on `n` keys
+    pub fn identity(n: usize) -> Self { KeyPermutation((0..n).collect()) }
+
+    /// Creates a random permutation on `n` keys using the Fisher-Yates shuffle
+    ///
+    /// `rng(m)` must return a value in `0..m`; larger values are reduced modulo `m`.
+    pub fn random<F: FnMut(usize) -> usize>(n: usize, mut rng: F) -> Self {
+        let mut positions: Vec<usize> = (0..n).collect();
+        for i in (1..n).rev() {
+            let j = rng(i + 1) % (i + 1);
+            positions.swap(i, j);
+        }
+        KeyPermutation(positions)
+    }
+
+    /// Number of keys the permutation applies to
+    pub fn len(&self) -> usize { self.0.len() }
+
+    /// Whether the permutation applies to no key
+    pub fn is_empty(&self) -> bool { self.0.is_empty() }
+
+    /// For each position in the permuted fragment, the position of the key in the original one
+    pub fn as_slice(&self) -> &[usize] { &self.0 }
+
+    /// The position in the permuted fragment of the key at `original` position
+    pub fn permuted_position(&self, original: usize) -> Option<usize> {
+        self.0.iter().position(|&pos| pos == original)
+    }
+
+    /// The permutation undoing this one
+    pub fn inverse(&self) -> Self {
+        let mut inverse = vec![0; self.0.len()];
+        for (i, &pos) in self.0.iter().enumerate() {
+            inverse[pos] = i;
+        }
+        KeyPermutation(inverse)
+    }
+
+    /// Applies the permutation to `items`
+    ///
+    /// # Panics
+    ///
+    /// If `items` doesn't have the length of the permutation.
+    pub fn apply<T: Clone>(&self, items: &[T]) -> Vec<T> {
+        assert_eq!(items.len(), self.0.len(), "permutation applied to the wrong number of items");
+        self.0.iter().map(|&pos| items[pos].clone()).collect()
+    }
+}
+
+impl fmt::Display for KeyPermutation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("[")?;
+        for (i, pos) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_str(",")?;
+            }
+            write!(f, "{}", pos)?;
+        }
+        f.write_str("]")
+    }
+}
+
+/// An error applying or creating a [`KeyPermutation`]
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum PermutationError {
+    /// The positions aren't each of `0..n` exactly once
+    NotAPermutation(Vec<usize>),
+    /// The number of permutations doesn't match the number of `multi` and `multi_a` fragments
+    FragmentCount {
+        /// The number of permutations given
+        perms: usize,
+        /// The number of fragments
+        fragments: usize,
+    },
+    /// The length of a permutation doesn't match the number of keys of its fragment
+    KeyCount {
+        /// The length of the permutation
+        perm: usize,
+        /// The number of keys of the fragment
+        keys: usize,
+    },
+}
+
+impl fmt::Display for PermutationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PermutationError::NotAPermutation(ref positions) => {
+                write!(f, "{:?} is not a permutation of 0..{}", positions, positions.len())
+            }
+            PermutationError::FragmentCount { perms, fragments } => {
+                write!(f, "{} permutations given for {} multi fragments", perms, fragments)
+            }
+            PermutationError::KeyCount { perm, keys } => write!(
+                f,
+                "permutation of {} keys given for a multi fragment with {} keys",
+                perm, keys
+            ),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for PermutationError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> { None }
+}
+
+/// Returns a random number generator for [`KeyPermutation::random`], deterministically derived
+/// from `seed`.
+///
+/// The `i`th fragment shuffled with the generator gets numbers derived from
+/// `SHA256(seed || i || counter)`.
+pub(crate) fn seeded_rng(seed: &[u8]) -> impl FnMut(usize, usize) -> usize + '_ {
+    let mut counter = 0u32;
+    move |fragment, m| {
+        let mut engine = sha256::Hash::engine();
+        engine.input(seed);
+        engine.input(&(fragment as u32).to_le_bytes());
+        engine.input(&counter.to_le_bytes());
+        counter += 1;
+        let hash = sha256::Hash::from_engine(engine);
+        let mut bytes = [0u8; 8];
+        bytes.copy_from_slice(&hash[..8]);
+        (u64::from_le_bytes(bytes) % m as u64) as usize
+    }
+}
+
+/// Key shuffling for [Miniscript]
+impl<Pk: MiniscriptKey, Ctx: ScriptContext> Miniscript<Pk, Ctx> {
+    /// The number of keys of each `multi` and `multi_a` fragment, in the order
+    /// they appear in the string representation of the miniscript.
+    pub fn multi_key_counts(&self) -> Vec<usize> {
+        self.iter()
+            .filter_map(|ms| match ms.node {
+                Terminal::Multi(ref thresh) => Some(thresh.n()),
+                Terminal::MultiA(ref thresh) => Some(thresh.n()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Shuffles the keys of every `multi` and `multi_a` fragment
+    ///
+    /// `rng(m)` must return a random value in `0..m`. Returns the shuffled
+    /// miniscript along with the permutation applied to each fragment, in the
+    /// order of [`Self::multi_key_counts`].
+    pub fn shuffle_multi_keys<F: FnMut(usize) -> usize>(
+        &self,
+        mut rng: F,
+    ) -> (Self, Vec<KeyPermutation>) {
+        let perms: Vec<_> = self
+            .multi_key_counts()
+            .into_iter()
+            .map(|n| KeyPermutation::random(n, &mut rng))
+            .collect();
+        let shuffled = self
+            .permute_multi_keys(&perms)
+            .expect("permutations match the fragments");
+        (shuffled, perms)
+    }
+
+    /// Shuffles the keys of every `multi` and `multi_a` fragment deterministically from `seed`
+    ///
+    /// The same seed always yields the same permutations, so a seed derived from
+    /// data the wallet already has (e.g. the derivation index) lets it recompute
+    /// them rather than store them.
+    pub fn shuffle_multi_keys_seeded(&self, seed: &[u8]) -> (Self, Vec<KeyPermutation>) {
+        let mut rng = seeded_rng(seed);
+        let perms: Vec<_> = self
+            .multi_key_counts()
+            .into_iter()
+            .enumerate()
+            .map(|(i, n)| KeyPermutation::random(n, |m| rng(i, m)))
+            .collect();
+        let shuffled = self
+            .permute_multi_keys(&perms)
+            .expect("permutations match the fragments");
+        (shuffled, perms)
+    }
+
+    /// Applies `perms` to the keys of the `multi` and `multi_a` fragments, in the
+    /// order of [`Self::multi_key_counts`]
+    ///
+    /// Applying the [`KeyPermutation::inverse`] of the permutations returned by a
+    /// shuffle restores the original miniscript.
+    ///
+    /// # Errors
+    ///
+    /// If the number of permutations or their lengths don't match the fragments.
+    pub fn permute_multi_keys(&self, perms: &[KeyPermutation]) -> Result<Self, PermutationError> {
+        let mut remaining = perms;
+        let ms = self.permute_multi_keys_partial(&mut remaining)?;
+        if !remaining.is_empty() {
+            return Err(PermutationError::FragmentCount {
+                perms: perms.len(),
+                fragments: perms.len() - remaining.len(),
+            });
+        }
+        Ok(ms)
+    }
+
+    /// Applies the first permutations of `perms` to the fragments of this
+    /// miniscript, and advances `perms` past them.
+    pub(crate) fn permute_multi_keys_partial(
+        &self,
+        perms: &mut &[KeyPermutation],
+    ) -> Result<Self, PermutationError> {
+        let n_multis = self.multi_key_counts().len();
+        if perms.len() < n_multis {
+            return Err(PermutationError::FragmentCount {
+                perms: perms.len(),
+                fragments: n_multis,
+            });
+        }
+        let (mine, rest) = perms.split_at(n_multis);
+        *perms = rest;
+
+        fn permute<Pk: MiniscriptKey, const MAX: usize>(
+            thresh: &Threshold<Pk, MAX>,
+            perm: &KeyPermutation,
+        ) -> Result<Threshold<Pk, MAX>, PermutationError> {
+            if perm.len() != thresh.n() {
+                return Err(PermutationError::KeyCount { perm: perm.len(), keys: thresh.n() });
+            }
+            Ok(Threshold::new(thresh.k(), perm.apply(thresh.data()))
+                .expect("same k and n as a valid threshold"))
+        }
+
+        // The post-order iterator visits the fragments from right to left, so we
+        // consume the permutations from the back.
+        let mut mine = mine.iter().rev();
+        let mut stack = vec![];
+        for item in self.rtl_post_order_iter() {
+            let new_term = match item.node.node {
+                Terminal::Multi(ref thresh) => {
+                    Terminal::Multi(permute(thresh, mine.next().expect("counted"))?)
+                }
+                Terminal::MultiA(ref thresh) => {
+                    Terminal::MultiA(permute(thresh, mine.next().expect("counted"))?)
+                }
+                Terminal::Alt(..) => Terminal::Alt(stack.pop().unwrap()),
+                Terminal::Swap(..) => Terminal::Swap(stack.pop().unwrap()),
+                Terminal::Check(..) => Terminal::Check(stack.pop().unwrap()),
+                Terminal::DupIf(..) => Terminal::DupIf(stack.pop().unwrap()),
+                Terminal::Verify(..) => Terminal::Verify(stack.pop().unwrap()),
+                Terminal::NonZero(..) => Terminal::NonZero(stack.pop().unwrap()),
+                Terminal::ZeroNotEqual(..) => Terminal::ZeroNotEqual(stack.pop().unwrap()),
+                Terminal::AndV(..) => Terminal::AndV(stack.pop().unwrap(), stack.pop().unwrap()),
+                Terminal::AndB(..) => Terminal::AndB(stack.pop().unwrap(), stack.pop().unwrap()),
+                Terminal::AndOr(..) => Terminal::AndOr(
+                    stack.pop().unwrap(),
+                    stack.pop().unwrap(),
+                    stack.pop().unwrap(),
+                ),
+                Terminal::OrB(..) => Terminal::OrB(stack.pop().unwrap(), stack.pop().unwrap()),
+                Terminal::OrD(..) => Terminal::OrD(stack.pop().unwrap(), stack.pop().unwrap()),
+                Terminal::OrC(..) => Terminal::OrC(stack.pop().unwrap(), stack.pop().unwrap()),
+                Terminal::OrI(..) => Terminal::OrI(stack.pop().unwrap(), stack.pop().unwrap()),
+                Terminal::Thresh(ref thresh) => {
+                    Terminal::Thresh(thresh.map_ref(|_| stack.pop().unwrap()))
+                }
+                ref leaf => leaf.clone(),
+            };
+
+            // Reordering keys changes neither the type nor the resource usage
+            stack.push(Arc::new(Miniscript::from_components_unchecked(
+                new_term,
+                item.node.ty,
+                item.node.ext,
+            )));
+        }
+
+        assert_eq!(stack.len(), 1);
+        Ok(Arc::try_unwrap(stack.pop().unwrap()).unwrap())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::str::FromStr;
+
+    use super::*;
+    use crate::{Segwitv0, Tap};
+
+    #[test]
+    fn key_permutation() {
+        let perm = KeyPermutation::new(vec![2, 0, 1]).unwrap();
+        assert_eq!(perm.apply(&["a", "b", "c"]), vec!["c", "a", "b"]);
+        assert_eq!(perm.inverse().apply(&perm.apply(&["a", "b", "c"])), vec!["a", "b", "c"]);
+        assert_eq!(perm.permuted_position(0), Some(1));
+        assert_eq!(perm.permuted_position(3), None);
+        assert_eq!(perm.to_string(), "[2,0,1]");
+        assert_eq!(
+            KeyPermutation::new(vec![0, 0, 1]),
+            Err(PermutationError::NotAPermutation(vec![0, 0, 1]))
+        );
+        assert!(KeyPermutation::new(vec![0, 3, 1]).is_err());
+
+        // An "rng" always returning 0 rotates the keys
+        assert_eq!(KeyPermutation::random(3, |_| 0), KeyPermutation::new(vec![1, 2, 0]).unwrap());
+        assert_eq!(KeyPermutation::random(3, |m| m - 1), KeyPermutation::identity(3));
+    }
+
+    #[test]
+    fn shuffle_multi() {
+        let ms = Miniscript::<String, Segwitv0>::from_str(
+            "or_d(multi(2,A,B,C,D),and_v(v:multi(1,E,F),older(10)))",
+        )
+        .unwrap();
+        assert_eq!(ms.multi_key_counts(), vec![4, 2]);
+
+        let (shuffled, perms) = ms.shuffle_multi_keys(|_| 0);
+        assert_eq!(shuffled.to_string(), "or_d(multi(2,B,C,D,A),and_v(v:multi(1,F,E),older(10)))");
+        assert_eq!(shuffled.ty, ms.ty);
+        assert_eq!(shuffled.ext, ms.ext);
+        let inverses: Vec<_> = perms.iter().map(KeyPermutation::inverse).collect();
+        assert_eq!(shuffled.permute_multi_keys(&inverses).unwrap(), ms);
+
+        // Seeded shuffling is deterministic and depends on the seed
+        let (seeded, perms) = ms.shuffle_multi_keys_seeded(b"seed");
+        assert_eq!(ms.shuffle_multi_keys_seeded(b"seed"), (seeded.clone(), perms.clone()));
+        assert_eq!(ms.permute_multi_keys(&perms).unwrap(), seeded);
+        assert!((0..10u8).any(
+            |i| ms.shuffle_multi_keys_seeded(&[i]).1 != ms.shuffle_multi_keys_seeded(b"seed").1
+        ));
+
+        // Wrong number or length of permutations
+        assert_eq!(
+            ms.permute_multi_keys(&perms[..1]),
+            Err(PermutationError::FragmentCount { perms: 1, fragments: 2 })
+        );
+        assert_eq!(
+            ms.permute_multi_keys(&[perms[0].clone(), perms[1].clone(), perms[1].clone()]),
+            Err(PermutationError::FragmentCount { perms: 3, fragments: 2 })
+        );
+        assert_eq!(
+            ms.permute_multi_keys(&[perms[1].clone(), perms[0].clone()]),
+            Err(PermutationError::KeyCount { perm: 4, keys: 2 })
+        );
+
+        let ms = Miniscript::<String, Tap>::from_str("and_v(v:pk(A),multi_a(2,B,C,D))").unwrap();
+        let (shuffled, _) = ms.shuffle_multi_keys(|_| 0);
+        assert_eq!(shuffled.to_string(), "and_v(v:pk(A),multi_a(2,C,D,B))");
+    }
+}
@@ -0,0 +1,144 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! # Threshold Builder
+//!
+//! Incremental construction of `thresh` fragments, type checking every child
+//! as it is added rather than the whole fragment once it is complete.
+//!
+
+use core::fmt;
+
+use super::types::{self, Correctness};
+use super::{Miniscript, ScriptContext};
+use crate::miniscript::decode::Terminal;
+use crate::prelude::*;
+use crate::sync::Arc;
+use crate::{Error, MiniscriptKey, Threshold};
+
+/// A `thresh` fragment under construction, see [`Threshold::builder`]
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct ThreshBuilder<Pk: MiniscriptKey, Ctx: ScriptContext> {
+    /// The number of children required to satisfy the threshold
+    k: usize,
+    /// The children added so far, all type checked
+    subs: Vec<Arc<Miniscript<Pk, Ctx>>>,
+}
+
+impl<Pk: MiniscriptKey, Ctx: ScriptContext> Threshold<Arc<Miniscript<Pk, Ctx>>, 0> {
+    /// Starts building a `thresh` fragment requiring `k` of its children
+    pub fn builder(k: usize) -> ThreshBuilder<Pk, Ctx> { ThreshBuilder { k, subs: vec![] } }
+}
+
+impl<Pk: MiniscriptKey, Ctx: ScriptContext> ThreshBuilder<Pk, Ctx> {
+    /// The number of children added so far
+    pub fn n(&self) -> usize { self.subs.len() }
+
+    /// Adds a child to the threshold
+    ///
+    /// # Errors
+    ///
+    /// If the child doesn't have the type required at its position: the first child
+    /// must be `Bdu` and the others `Wdu`. The error kind gives the index of the child
+    /// and the property it lacks, the child isn't added.
+    pub fn push(&mut self, sub: Arc<Miniscript<Pk, Ctx>>) -> Result<&mut Self, types::Error> {
+        let i = self.subs.len();
+        if let Err(error) = Correctness::threshold_child(i, &sub.ty.corr) {
+            return Err(types::Error {
+                fragment_string: self.display_with(&sub).to_string(),
+                error,
+            });
+        }
+        self.subs.push(sub);
+        Ok(self)
+    }
+
+    /// Builds the `thresh` fragment
+    ///
+    /// # Errors
+    ///
+    /// If `k` is zero or greater than the number of children, or if the fragment
+    /// exceeds the limits of the script context.
+    pub fn build(self) -> Result<Miniscript<Pk, Ctx>, Error> {
+        let thresh = Threshold::new(self.k, self.subs).map_err(Error::Threshold)?;
+        Miniscript::from_ast(Terminal::Thresh(thresh))
+    }
+
+    /// Displays the threshold with `sub` as an extra child
+    fn display_with<'a>(&'a self, sub: &'a Miniscript<Pk, Ctx>) -> impl fmt::Display + 'a {
+        struct DisplayWith<'a, Pk: MiniscriptKey, Ctx: ScriptContext>(
+            &'a ThreshBuilder<Pk, Ctx>,
+            &'a Miniscript<Pk, Ctx>,
+        );
+
+        impl<Pk: MiniscriptKey, Ctx: ScriptContext> fmt::Display for DisplayWith<'_, Pk, Ctx> {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "thresh({}", self.0.k)?;
+                for sub in &self.0.subs {
+                    write!(f, ",{}", sub)?;
+                }
+                write!(f, ",{})", self.1)
+            }
+        }
+
+        DisplayWith(self, sub)
+    }
+}
+
+impl<Pk: MiniscriptKey, Ctx: ScriptContext> fmt::Debug for ThreshBuilder<Pk, Ctx> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ThreshBuilder")
+            .field("k", &self.k)
+            .field("subs", &self.subs)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::str::FromStr;
+
+    use super::*;
+    use crate::miniscript::types::{Base, ErrorKind};
+    use crate::Segwitv0;
+
+    type Ms = Miniscript<String, Segwitv0>;
+
+    fn ms(s: &str) -> Arc<Ms> { Arc::new(Ms::from_str_insane(s).unwrap()) }
+
+    // `W` fragments can't be parsed at top level
+    fn swap(s: &str) -> Arc<Ms> { Arc::new(Ms::from_ast(Terminal::Swap(ms(s))).unwrap()) }
+
+    #[test]
+    fn thresh_builder() {
+        let mut builder = Threshold::builder(2);
+        builder
+            .push(ms("pk(A)"))
+            .unwrap()
+            .push(swap("pk(B)"))
+            .unwrap();
+
+        // The third child is a `B` where a `W` is expected, and isn't added
+        let err = builder.push(ms("pk(C)")).unwrap_err();
+        assert_eq!(err.error, ErrorKind::ThresholdBase(2, Base::B));
+        assert_eq!(err.fragment_string, "thresh(2,pk(A),s:pk(B),pk(C))");
+        assert_eq!(builder.n(), 2);
+
+        let err = builder.push(swap("and_v(v:pk(C),1)")).unwrap_err();
+        assert_eq!(err.error, ErrorKind::ThresholdDissat(2));
+
+        builder.push(swap("ln:older(144)")).unwrap();
+        let thresh = builder.build().unwrap();
+        assert_eq!(thresh, Ms::from_str("thresh(2,pk(A),s:pk(B),sln:older(144))").unwrap());
+
+        // The first child must be a `B`
+        let mut builder = Threshold::<Arc<Ms>, 0>::builder(1);
+        let err = builder.push(swap("pk(A)")).unwrap_err();
+        assert_eq!(err.error, ErrorKind::ThresholdBase(0, Base::W));
+
+        // Invalid `k`
+        assert!(Threshold::<Arc<Ms>, 0>::builder(0).build().is_err());
+        let mut builder = Threshold::builder(2);
+        builder.push(ms("pk(A)")).unwrap();
+        assert!(matches!(builder.build(), Err(Error::Threshold(_))));
+    }
+}
@@ -466,6 +466,25 @@ impl Correctness {
         })
     }
 
+    /// Checks the correctness properties of the `i`th child of a `thresh` fragment
+    ///
+    /// The first child must be `Bdu` and the others `Wdu`.
+    pub const fn threshold_child(i: usize, subtype: &Self) -> Result<(), ErrorKind> {
+        match (i, subtype.base) {
+            (0, Base::B) => {}
+            (0, base) => return Err(ErrorKind::ThresholdBase(i, base)),
+            (_, Base::W) => {}
+            (_, base) => return Err(ErrorKind::ThresholdBase(i, base)),
+        }
+        if !subtype.unit {
+            return Err(ErrorKind::ThresholdNonUnit(i));
+        }
+        if !subtype.dissatisfiable {
+            return Err(ErrorKind::ThresholdDissat(i));
+        }
+        Ok(())
+    }
+
     /// Constructor for the correctness properties of the `thresh` fragment
     // Cannot be constfn because it takes a closure.
     pub fn threshold<'a, I>(_k: usize, subs: I) -> Result<Self, ErrorKind>
@@ -479,18 +498,7 @@ impl Correctness {
                 Input::One | Input::OneNonZero => 1,
                 Input::Any | Input::AnyNonZero => 2, // we only check if num args is max 1
             };
-            if i == 0 && subtype.base != Base::B {
-                return Err(ErrorKind::ThresholdBase(i, subtype.base));
-            }
-            if i != 0 && subtype.base != Base::W {
-                return Err(ErrorKind::ThresholdBase(i, subtype.base));
-            }
-            if !subtype.unit {
-                return Err(ErrorKind::ThresholdNonUnit(i));
-            }
-            if !subtype.dissatisfiable {
-                return Err(ErrorKind::ThresholdDissat(i));
-            }
+            Self::threshold_child(i, subtype)?;
         }
 
         Ok(Correctness {
@@ -11,6 +11,9 @@ use crate::miniscript::context::SigType;
 use crate::prelude::*;
 use crate::{script_num_size, AbsLockTime, MiniscriptKey, RelLockTime, Terminal};
 
+/// The extra bytes taken by an uncompressed key, 65 bytes rather than 33
+const UNCOMPRESSED_EXTRA_LEN: usize = 32;
+
 /// Timelock information for satisfaction of a fragment.
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Default, Hash)]
 pub struct TimelockInfo {
@@ -190,7 +193,7 @@ impl ExtData {
         );
     }
 
-    /// Extra properties for the `pk_k` fragment.
+    /// Extra properties for the `pk_k` fragment, with a compressed key.
     pub fn pk_k<Ctx: ScriptContext>() -> Self {
         ExtData {
             pk_cost: match Ctx::sig_type() {
@@ -213,7 +216,7 @@ impl ExtData {
         }
     }
 
-    /// Extra properties for the `pk_h` fragment.
+    /// Extra properties for the `pk_h` fragment, with a compressed key.
     pub fn pk_h<Ctx: ScriptContext>() -> Self {
         ExtData {
             pk_cost: 24,
@@ -236,7 +239,32 @@ impl ExtData {
         }
     }
 
-    /// Extra properties for the `multi` fragment.
+    /// Extra properties for the `pk_k` fragment, with an uncompressed key.
+    fn pk_k_uncompressed<Ctx: ScriptContext>() -> Self {
+        let ext = Self::pk_k::<Ctx>();
+        ExtData { pk_cost: ext.pk_cost + UNCOMPRESSED_EXTRA_LEN, ..ext }
+    }
+
+    /// Extra properties for the `pk_h` fragment, with an uncompressed key.
+    fn pk_h_uncompressed<Ctx: ScriptContext>() -> Self {
+        let ext = Self::pk_h::<Ctx>();
+        let extra =
+            |(w, s): (usize, usize)| (w + UNCOMPRESSED_EXTRA_LEN, s + UNCOMPRESSED_EXTRA_LEN);
+        ExtData {
+            max_sat_size: ext.max_sat_size.map(extra),
+            max_dissat_size: ext.max_dissat_size.map(extra),
+            ..ext
+        }
+    }
+
+    /// Extra properties for the `multi` fragment, with `uncompressed` of its keys
+    /// uncompressed.
+    fn multi_uncompressed(k: usize, n: usize, uncompressed: usize) -> Self {
+        let ext = Self::multi(k, n);
+        ExtData { pk_cost: ext.pk_cost + uncompressed * UNCOMPRESSED_EXTRA_LEN, ..ext }
+    }
+
+    /// Extra properties for the `multi` fragment, with compressed keys.
     pub fn multi(k: usize, n: usize) -> Self {
         let num_cost = match (k > 16, n > 16) {
             (true, true) => 4,
@@ -932,9 +960,15 @@ impl ExtData {
         let ret = match *fragment {
             Terminal::True => Self::TRUE,
             Terminal::False => Self::FALSE,
+            Terminal::PkK(ref pk) if pk.is_uncompressed() => Self::pk_k_uncompressed::<Ctx>(),
             Terminal::PkK(..) => Self::pk_k::<Ctx>(),
+            Terminal::PkH(ref pk) if pk.is_uncompressed() => Self::pk_h_uncompressed::<Ctx>(),
             Terminal::PkH(..) | Terminal::RawPkH(..) => Self::pk_h::<Ctx>(),
-            Terminal::Multi(ref thresh) => Self::multi(thresh.k(), thresh.n()),
+            Terminal::Multi(ref thresh) => Self::multi_uncompressed(
+                thresh.k(),
+                thresh.n(),
+                thresh.iter().filter(|pk| pk.is_uncompressed()).count(),
+            ),
             Terminal::MultiA(ref thresh) => Self::multi_a(thresh.k(), thresh.n()),
             Terminal::After(t) => Self::after(t),
             Terminal::Older(t) => Self::older(t),
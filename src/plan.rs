@@ -16,20 +16,29 @@
 //! Once you've obtained signatures, hash pre-images etc required by the plan, it can create a
 //! witness/script_sig for the input.
 
+use core::cell::Cell;
+use core::fmt;
 use core::iter::FromIterator;
 
-use bitcoin::hashes::{hash160, ripemd160, sha256};
+use bitcoin::hashes::{hash160, ripemd160, sha256, Hash};
 use bitcoin::key::XOnlyPublicKey;
 use bitcoin::script::PushBytesBuf;
-use bitcoin::taproot::{ControlBlock, LeafVersion, TapLeafHash};
-use bitcoin::{absolute, bip32, psbt, relative, ScriptBuf, WitnessVersion};
+use bitcoin::taproot::{ControlBlock, LeafVersion, TapLeafHash, TapNodeHash};
+use bitcoin::{
+    absolute, bip32, psbt, relative, transaction, Amount, FeeRate, OutPoint, Psbt, ScriptBuf,
+    Sequence, SignedAmount, Transaction, TxIn, TxOut, Weight, WitnessVersion,
+};
 
-use crate::descriptor::{self, Descriptor, DescriptorType, KeyMap};
+use crate::descriptor::{self, Descriptor, DescriptorKeyParseError, DescriptorType, KeyMap};
 use crate::miniscript::hash256;
-use crate::miniscript::satisfy::{Placeholder, Satisfier, SchnorrSigType};
+use crate::miniscript::satisfy::{
+    Placeholder, Preimage32, PreimageStore, Satisfier, SchnorrSigType,
+};
 use crate::prelude::*;
 use crate::util::witness_size;
-use crate::{DefiniteDescriptorKey, DescriptorPublicKey, Error, MiniscriptKey, ToPublicKey};
+use crate::{
+    ChainTip, DefiniteDescriptorKey, DescriptorPublicKey, Error, MiniscriptKey, ToPublicKey,
+};
 
 /// Trait describing a present/missing lookup table for constructing witness templates
 ///
@@ -49,6 +58,10 @@ pub trait AssetProvider<Pk: MiniscriptKey> {
     /// Lookup the tap key spend sig and return its size
     fn provider_lookup_tap_key_spend_sig(&self, _: &Pk) -> Option<usize> { None }
 
+    /// Given the output key of a `rawtr()` descriptor, look up an untweaked key spend schnorr
+    /// signature with that key and return its size
+    fn provider_lookup_raw_tr_sig(&self, _: &Pk) -> Option<usize> { None }
+
     /// Given a public key and a associated leaf hash, look up a schnorr signature with that key
     /// and return its size
     fn provider_lookup_tap_leaf_script_sig(&self, _: &Pk, _: &TapLeafHash) -> Option<usize> { None }
@@ -110,6 +123,14 @@ pub trait AssetProvider<Pk: MiniscriptKey> {
 
     /// Assert whether an absolute locktime is satisfied
     fn check_after(&self, _: absolute::LockTime) -> bool { false }
+
+    /// Whether a Taproot output may be spent through the leaf with the given hash, or through
+    /// the key spend path if `None`
+    fn check_tap_path(&self, _: Option<&TapLeafHash>) -> bool { true }
+
+    /// Called for every node visited by the search for a witness template, which gives up on
+    /// the node, as if it couldn't be satisfied, if this returns `false`
+    fn check_search_node(&self) -> bool { true }
 }
 
 /// Wrapper around [`Assets`] that logs every query and value returned
@@ -132,6 +153,7 @@ macro_rules! impl_log_method {
 impl AssetProvider<DefiniteDescriptorKey> for LoggerAssetProvider<'_> {
     impl_log_method!(provider_lookup_ecdsa_sig, pk: &DefiniteDescriptorKey, -> bool);
     impl_log_method!(provider_lookup_tap_key_spend_sig, pk: &DefiniteDescriptorKey, -> Option<usize>);
+    impl_log_method!(provider_lookup_raw_tr_sig, pk: &DefiniteDescriptorKey, -> Option<usize>);
     impl_log_method!(provider_lookup_tap_leaf_script_sig, pk: &DefiniteDescriptorKey, leaf_hash: &TapLeafHash, -> Option<usize>);
     impl_log_method!(provider_lookup_tap_control_block_map, -> Option<&BTreeMap<ControlBlock, (bitcoin::ScriptBuf, LeafVersion)>>);
     impl_log_method!(provider_lookup_raw_pkh_pk, hash: &hash160::Hash, -> Option<bitcoin::PublicKey>);
@@ -144,6 +166,8 @@ impl AssetProvider<DefiniteDescriptorKey> for LoggerAssetProvider<'_> {
     impl_log_method!(provider_lookup_hash160, hash: &hash160::Hash, -> bool);
     impl_log_method!(check_older, s: relative::LockTime, -> bool);
     impl_log_method!(check_after, t: absolute::LockTime, -> bool);
+    impl_log_method!(check_tap_path, leaf_hash: Option<&TapLeafHash>, -> bool);
+    impl_log_method!(check_search_node, -> bool);
 }
 
 impl<T, Pk> AssetProvider<Pk> for T
@@ -159,6 +183,10 @@ where
         Satisfier::lookup_tap_key_spend_sig(self).map(|s| s.to_vec().len())
     }
 
+    fn provider_lookup_raw_tr_sig(&self, pk: &Pk) -> Option<usize> {
+        Satisfier::lookup_raw_tr_schnorr_sig(self, pk).map(|s| s.to_vec().len())
+    }
+
     fn provider_lookup_tap_leaf_script_sig(
         &self,
         pk: &Pk,
@@ -223,7 +251,7 @@ where
 /// and the timelocks needed for satisfying the plan.
 /// Calling `plan` on a Descriptor will return this structure,
 /// containing the cheapest spending path possible (considering the `Assets` given)
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Plan {
     /// This plan's witness template
     pub(crate) template: Vec<Placeholder<DefiniteDescriptorKey>>,
@@ -239,6 +267,20 @@ impl Plan {
     /// Returns the witness template
     pub fn witness_template(&self) -> &Vec<Placeholder<DefiniteDescriptorKey>> { &self.template }
 
+    /// Returns the hash of the tap leaf this plan spends through, if any
+    ///
+    /// Returns `None` for key-path spends and for non-taproot descriptors.
+    pub fn tap_leaf_hash(&self) -> Option<TapLeafHash> {
+        self.template
+            .iter()
+            .find_map(|placeholder| match placeholder {
+                Placeholder::TapScript(script) => {
+                    Some(TapLeafHash::from_script(script, LeafVersion::TapScript))
+                }
+                _ => None,
+            })
+    }
+
     /// Returns the witness version
     pub fn witness_version(&self) -> Option<WitnessVersion> {
         self.descriptor.desc_type().segwit_version()
@@ -274,13 +316,31 @@ impl Plan {
         }
     }
 
+    /// Returns the signatures and preimages this plan needs, in witness order
+    ///
+    /// The other items of the witness template (public keys, fixed pushes, taproot scripts and
+    /// control blocks) don't depend on any secret and are omitted.
+    pub fn requirements(&self) -> impl Iterator<Item = &Placeholder<DefiniteDescriptorKey>> {
+        self.template.iter().filter(|placeholder| {
+            matches!(
+                placeholder,
+                Placeholder::EcdsaSigPk(..)
+                    | Placeholder::EcdsaSigPkHash(..)
+                    | Placeholder::SchnorrSigPk(..)
+                    | Placeholder::SchnorrSigPkHash(..)
+                    | Placeholder::Sha256Preimage(..)
+                    | Placeholder::Hash256Preimage(..)
+                    | Placeholder::Ripemd160Preimage(..)
+                    | Placeholder::Hash160Preimage(..)
+            )
+        })
+    }
+
     /// Try creating the final script_sig and witness using a [`Satisfier`]
     pub fn satisfy<Sat: Satisfier<DefiniteDescriptorKey>>(
         &self,
         stfr: &Sat,
     ) -> Result<(Vec<Vec<u8>>, ScriptBuf), Error> {
-        use bitcoin::blockdata::script::Builder;
-
         let stack = self
             .template
             .iter()
@@ -288,11 +348,82 @@ impl Plan {
             .collect::<Option<Vec<Vec<u8>>>>()
             .ok_or(Error::CouldNotSatisfy)?;
 
-        Ok(match self.descriptor.desc_type() {
+        Ok(self.finalize_stack(stack))
+    }
+
+    /// Fill as much of the witness template as possible using a [`Satisfier`]
+    ///
+    /// Unlike [`Plan::satisfy`], this doesn't fail if some items are missing. The returned
+    /// [`PartialWitness`] can be handed over to the other parties of the spend, which
+    /// complete it with [`Plan::complete_partial`] using their own satisfier.
+    pub fn partial_satisfy<Sat: Satisfier<DefiniteDescriptorKey>>(
+        &self,
+        stfr: &Sat,
+    ) -> PartialWitness<DefiniteDescriptorKey> {
+        let mut partial = PartialWitness {
+            slots: self
+                .template
+                .iter()
+                .map(|placeholder| WitnessSlot::Missing(placeholder.clone()))
+                .collect(),
+        };
+        partial.fill(stfr);
+        partial
+    }
+
+    /// Restore a [`PartialWitness`] serialized with [`PartialWitness::serialize`]
+    ///
+    /// The missing items are typed again using this plan's witness template, so the plan
+    /// must be the same one the partial witness was created from.
+    pub fn partial_witness_from_slice(
+        &self,
+        bytes: &[u8],
+    ) -> Result<PartialWitness<DefiniteDescriptorKey>, PartialWitnessError> {
+        let items = PartialWitness::<DefiniteDescriptorKey>::deserialize_items(bytes)?;
+        if items.len() != self.template.len() {
+            return Err(PartialWitnessError::ItemCount {
+                items: items.len(),
+                expected: self.template.len(),
+            });
+        }
+        let slots = items
+            .into_iter()
+            .zip(self.template.iter())
+            .map(|(item, placeholder)| match item {
+                Some(data) => WitnessSlot::Filled(data),
+                None => WitnessSlot::Missing(placeholder.clone()),
+            })
+            .collect();
+        Ok(PartialWitness { slots })
+    }
+
+    /// Fill the remaining items of a [`PartialWitness`] and create the final script_sig and
+    /// witness
+    ///
+    /// Returns [`Error::CouldNotSatisfy`] if the satisfier doesn't provide every missing item.
+    pub fn complete_partial<Sat: Satisfier<DefiniteDescriptorKey>>(
+        &self,
+        mut partial: PartialWitness<DefiniteDescriptorKey>,
+        stfr: &Sat,
+    ) -> Result<(Vec<Vec<u8>>, ScriptBuf), Error> {
+        if partial.slots.len() != self.template.len() {
+            return Err(Error::CouldNotSatisfy);
+        }
+        partial.fill(stfr);
+        let stack = partial.into_stack().ok_or(Error::CouldNotSatisfy)?;
+        Ok(self.finalize_stack(stack))
+    }
+
+    /// Turn a fully satisfied witness template into the final script_sig and witness
+    fn finalize_stack(&self, stack: Vec<Vec<u8>>) -> (Vec<Vec<u8>>, ScriptBuf) {
+        use bitcoin::blockdata::script::Builder;
+
+        match self.descriptor.desc_type() {
             DescriptorType::Bare
             | DescriptorType::Sh
             | DescriptorType::Pkh
-            | DescriptorType::ShSortedMulti => (
+            | DescriptorType::ShSortedMulti
+            | DescriptorType::Data => (
                 vec![],
                 stack
                     .into_iter()
@@ -306,11 +437,13 @@ impl Plan {
             DescriptorType::Wpkh
             | DescriptorType::Wsh
             | DescriptorType::WshSortedMulti
-            | DescriptorType::Tr => (stack, ScriptBuf::new()),
+            | DescriptorType::Tr
+            | DescriptorType::RawTr
+            | DescriptorType::Wother => (stack, ScriptBuf::new()),
             DescriptorType::ShWsh | DescriptorType::ShWshSortedMulti | DescriptorType::ShWpkh => {
                 (stack, self.descriptor.unsigned_script_sig())
             }
-        })
+        }
     }
 
     /// Update a PSBT input with the metadata required to complete this plan
@@ -399,36 +532,1476 @@ impl Plan {
                     .tap_scripts
                     .insert(control_block, (tap_script, LeafVersion::TapScript));
             }
+        } else if let Descriptor::RawTr(rawtr) = &self.descriptor {
+            let pk = rawtr.as_inner();
+            for path in pk.full_derivation_paths() {
+                input
+                    .tap_key_origins
+                    .insert(pk.to_x_only_pubkey(), (vec![], (pk.master_fingerprint(), path)));
+            }
+        } else {
+            for item in &self.template {
+                if let Placeholder::EcdsaSigPk(pk) = item {
+                    let public_key = pk.to_public_key().inner;
+                    let master_fingerprint = pk.master_fingerprint();
+                    for derivation_path in pk.full_derivation_paths() {
+                        input
+                            .bip32_derivation
+                            .insert(public_key, (master_fingerprint, derivation_path));
+                    }
+                }
+            }
+
+            match &self.descriptor {
+                Descriptor::Bare(_)
+                | Descriptor::Pkh(_)
+                | Descriptor::Wpkh(_)
+                | Descriptor::Data(_)
+                | Descriptor::Wother(_) => {}
+                Descriptor::Sh(sh) => match sh.as_inner() {
+                    descriptor::ShInner::Wsh(wsh) => {
+                        input.witness_script = Some(wsh.inner_script());
+                        input.redeem_script = Some(wsh.inner_script().to_p2wsh());
+                    }
+                    descriptor::ShInner::Wpkh(..) => input.redeem_script = Some(sh.inner_script()),
+                    descriptor::ShInner::SortedMulti(_) | descriptor::ShInner::Ms(_) => {
+                        input.redeem_script = Some(sh.inner_script())
+                    }
+                },
+                Descriptor::Wsh(wsh) => input.witness_script = Some(wsh.inner_script()),
+                Descriptor::Tr(_) | Descriptor::RawTr(_) => {
+                    unreachable!("Tr and RawTr are dealt with separately")
+                }
+            }
+        }
+    }
+}
+
+/// A limit on the work done searching for a plan
+///
+/// The budget is counted while searching, see [`Descriptor::plan_with_budget`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PlanBudget {
+    /// The maximum number of nodes the search may visit
+    pub max_nodes: usize,
+}
+
+impl Default for PlanBudget {
+    fn default() -> Self { PlanBudget { max_nodes: 100_000 } }
+}
+
+/// The reason no plan was returned by [`Descriptor::plan_with_budget`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PlanError {
+    /// The search visited more nodes than the budget allows, and was given up
+    BudgetExceeded {
+        /// The maximum number of nodes allowed by the budget
+        max_nodes: usize,
+    },
+    /// The assets aren't sufficient to satisfy the descriptor
+    InsufficientAssets,
+}
+
+impl fmt::Display for PlanError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PlanError::BudgetExceeded { max_nodes } => {
+                write!(f, "planning visited more than the budget of {} nodes", max_nodes)
+            }
+            PlanError::InsufficientAssets => f.write_str("assets are insufficient for a plan"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for PlanError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> { None }
+}
+
+/// An output locked by a ranged descriptor, as tracked by a wallet
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct WalletUtxo {
+    /// The output's outpoint
+    pub outpoint: OutPoint,
+    /// The output itself
+    pub txout: TxOut,
+    /// The derivation index of the descriptor locking the output
+    pub derivation_index: u32,
+    /// The chain tip on top of which the output was confirmed, `None` if it is unconfirmed
+    pub confirmed_on: Option<ChainTip>,
+}
+
+/// Whether an output can be spent given the available assets and the state of the chain
+///
+/// See [`Descriptor::spendable_balance_classifier`].
+#[derive(Debug, Clone)]
+pub enum Spendability {
+    /// The output can be spent in the next block using this plan
+    Now(Plan),
+    /// The output can be spent using this plan once its timelocks are satisfied
+    Later(Plan),
+    /// The output can't be spent with the available assets
+    Never,
+}
+
+/// The reason an output couldn't be classified by [`Descriptor::spendable_balance_classifier`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ClassifyError {
+    /// The descriptor couldn't be derived at the output's derivation index
+    Derivation(OutPoint, descriptor::ConversionError),
+    /// The output isn't locked by the descriptor at its derivation index
+    ScriptMismatch(OutPoint, u32),
+}
+
+impl fmt::Display for ClassifyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ClassifyError::Derivation(outpoint, ref e) => {
+                write!(f, "output {}: {}", outpoint, e)
+            }
+            ClassifyError::ScriptMismatch(outpoint, index) => {
+                write!(f, "output {} is not locked by the descriptor at index {}", outpoint, index)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ClassifyError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match *self {
+            ClassifyError::Derivation(_, ref e) => Some(e),
+            ClassifyError::ScriptMismatch(..) => None,
+        }
+    }
+}
+
+impl Spendability {
+    /// Classify an output locked by `descriptor`
+    pub(crate) fn classify(
+        descriptor: Descriptor<DefiniteDescriptorKey>,
+        assets: &Assets,
+        tip: ChainTip,
+        confirmed_on: Option<ChainTip>,
+    ) -> Self {
+        let now = ChainAssets { assets, chain: Some((tip, confirmed_on)) };
+        let descriptor = match descriptor.plan(&now) {
+            Ok(plan) => return Spendability::Now(plan),
+            Err(descriptor) => descriptor,
+        };
+        match descriptor.plan(&ChainAssets { assets, chain: None }) {
+            Ok(plan) => Spendability::Later(plan),
+            Err(_) => Spendability::Never,
+        }
+    }
+}
+
+/// Version of the format of [`Plan::serialize`]
+const PLAN_FORMAT_VERSION: u8 = 1;
+
+/// Human readable part of [`Plan::to_qr_string`]
+const PLAN_HRP: &str = "mplan";
+
+/// An error decoding a plan with [`Plan::deserialize`] or [`Plan::from_qr_string`]
+#[derive(Debug)]
+pub enum PlanDecodeError {
+    /// The text isn't valid bech32 characters after a prefix
+    Bech32(bech32::primitives::decode::CheckedHrpstringError),
+    /// The text doesn't start with the `MPLAN1` prefix
+    WrongPrefix,
+    /// The encoding is too short to contain a checksum
+    TooShort,
+    /// The checksum doesn't match the encoded plan
+    ChecksumMismatch,
+    /// The format version is not supported
+    UnsupportedVersion(u8),
+    /// A field couldn't be read
+    Encoding(bitcoin::consensus::encode::Error),
+    /// A string field isn't valid UTF-8
+    Utf8(core::str::Utf8Error),
+    /// The descriptor couldn't be parsed
+    Descriptor(Error),
+    /// A key of the witness template couldn't be parsed
+    Key(DescriptorKeyParseError),
+    /// The relative timelock is disabled
+    RelativeTimelock(relative::DisabledLockTimeError),
+    /// A control block couldn't be decoded
+    ControlBlock(bitcoin::taproot::TaprootError),
+    /// A byte tagging the kind of a field has an unknown value
+    UnknownTag {
+        /// The kind of field tagged
+        field: &'static str,
+        /// The unknown tag
+        tag: u8,
+    },
+    /// Bytes are left after the plan
+    TrailingBytes,
+    /// The witness template or timelocks aren't the ones planned for the descriptor
+    TemplateMismatch,
+}
+
+impl fmt::Display for PlanDecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PlanDecodeError::Bech32(ref e) => e.fmt(f),
+            PlanDecodeError::WrongPrefix => write!(f, "expected the {} prefix", PLAN_HRP),
+            PlanDecodeError::TooShort => f.write_str("encoded plan too short"),
+            PlanDecodeError::ChecksumMismatch => f.write_str("encoded plan checksum mismatch"),
+            PlanDecodeError::UnsupportedVersion(version) => {
+                write!(f, "unsupported plan format version {}", version)
+            }
+            PlanDecodeError::Encoding(ref e) => e.fmt(f),
+            PlanDecodeError::Utf8(ref e) => e.fmt(f),
+            PlanDecodeError::Descriptor(ref e) => e.fmt(f),
+            PlanDecodeError::Key(ref e) => e.fmt(f),
+            PlanDecodeError::RelativeTimelock(ref e) => e.fmt(f),
+            PlanDecodeError::ControlBlock(ref e) => e.fmt(f),
+            PlanDecodeError::UnknownTag { field, tag } => write!(f, "invalid {} {}", field, tag),
+            PlanDecodeError::TrailingBytes => f.write_str("trailing bytes after the plan"),
+            PlanDecodeError::TemplateMismatch => {
+                f.write_str("the witness template isn't a plan of the descriptor")
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for PlanDecodeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            PlanDecodeError::Bech32(e) => Some(e),
+            PlanDecodeError::Encoding(e) => Some(e),
+            PlanDecodeError::Utf8(e) => Some(e),
+            PlanDecodeError::Descriptor(e) => Some(e),
+            PlanDecodeError::Key(e) => Some(e),
+            PlanDecodeError::RelativeTimelock(e) => Some(e),
+            PlanDecodeError::ControlBlock(e) => Some(e),
+            PlanDecodeError::WrongPrefix
+            | PlanDecodeError::TooShort
+            | PlanDecodeError::ChecksumMismatch
+            | PlanDecodeError::UnsupportedVersion(_)
+            | PlanDecodeError::UnknownTag { .. }
+            | PlanDecodeError::TrailingBytes
+            | PlanDecodeError::TemplateMismatch => None,
+        }
+    }
+}
+
+/// Answers the lookups of a planner with the items of a witness template, so that planning
+/// a descriptor with it gives back the template if it is a plan of the descriptor
+struct TemplateAssets<'a>(&'a Plan);
+
+impl<'a> AssetProvider<DefiniteDescriptorKey> for TemplateAssets<'a> {
+    fn provider_lookup_ecdsa_sig(&self, pk: &DefiniteDescriptorKey) -> bool {
+        self.0
+            .template
+            .iter()
+            .any(|p| matches!(p, Placeholder::EcdsaSigPk(sig_pk) if sig_pk == pk))
+    }
+
+    fn provider_lookup_tap_key_spend_sig(&self, pk: &DefiniteDescriptorKey) -> Option<usize> {
+        self.0.template.iter().find_map(|p| match p {
+            Placeholder::SchnorrSigPk(sig_pk, SchnorrSigType::KeySpend { .. }, size)
+                if sig_pk == pk =>
+            {
+                Some(*size)
+            }
+            _ => None,
+        })
+    }
+
+    fn provider_lookup_raw_tr_sig(&self, pk: &DefiniteDescriptorKey) -> Option<usize> {
+        self.0.template.iter().find_map(|p| match p {
+            Placeholder::SchnorrSigPk(sig_pk, SchnorrSigType::RawKeySpend, size)
+                if sig_pk == pk =>
+            {
+                Some(*size)
+            }
+            _ => None,
+        })
+    }
+
+    fn provider_lookup_tap_leaf_script_sig(
+        &self,
+        pk: &DefiniteDescriptorKey,
+        tap_leaf_hash: &TapLeafHash,
+    ) -> Option<usize> {
+        self.0.template.iter().find_map(|p| match p {
+            Placeholder::SchnorrSigPk(sig_pk, SchnorrSigType::ScriptSpend { leaf_hash }, size)
+                if sig_pk == pk && leaf_hash == tap_leaf_hash =>
+            {
+                Some(*size)
+            }
+            _ => None,
+        })
+    }
+
+    fn provider_lookup_sha256(&self, hash: &sha256::Hash) -> bool {
+        self.0
+            .template
+            .contains(&Placeholder::Sha256Preimage(*hash))
+    }
+
+    fn provider_lookup_hash256(&self, hash: &hash256::Hash) -> bool {
+        self.0
+            .template
+            .contains(&Placeholder::Hash256Preimage(*hash))
+    }
+
+    fn provider_lookup_ripemd160(&self, hash: &ripemd160::Hash) -> bool {
+        self.0
+            .template
+            .contains(&Placeholder::Ripemd160Preimage(*hash))
+    }
+
+    fn provider_lookup_hash160(&self, hash: &hash160::Hash) -> bool {
+        self.0
+            .template
+            .contains(&Placeholder::Hash160Preimage(*hash))
+    }
+
+    fn check_older(&self, s: relative::LockTime) -> bool {
+        self.0
+            .relative_timelock
+            .map_or(false, |timelock| s.is_implied_by(timelock))
+    }
+
+    fn check_after(&self, l: absolute::LockTime) -> bool {
+        self.0
+            .absolute_timelock
+            .map_or(false, |timelock| l.is_implied_by(timelock))
+    }
+}
+
+impl Plan {
+    /// Serialize the plan, e.g. for an air-gapped signer to know which witness items to fill
+    ///
+    /// The format is a version byte, currently `1`, followed by the descriptor as a
+    /// length-prefixed string, each of the absolute and relative timelocks as a `0x00` byte if
+    /// absent or a `0x01` byte and its consensus value, and the number of items of the witness
+    /// template as a compact size followed by each item as a type byte and its fields. Keys are
+    /// length-prefixed strings, so that their origins are kept. The first four bytes of the
+    /// double SHA-256 of all the above are appended as a checksum.
+    pub fn serialize(&self) -> Vec<u8> {
+        use bitcoin::consensus::Encodable;
+        use bitcoin::hashes::{sha256d, Hash};
+
+        fn write<T: Encodable>(buf: &mut Vec<u8>, value: T) {
+            value
+                .consensus_encode(buf)
+                .expect("writing to a vec can't fail");
+        }
+        fn write_size(buf: &mut Vec<u8>, size: usize) { write(buf, bitcoin::VarInt::from(size)) }
+        fn write_bytes(buf: &mut Vec<u8>, bytes: &[u8]) {
+            write_size(buf, bytes.len());
+            buf.extend_from_slice(bytes);
+        }
+        fn write_key(buf: &mut Vec<u8>, pk: &DefiniteDescriptorKey) {
+            write_bytes(buf, pk.to_string().as_bytes())
+        }
+
+        let mut buf = vec![PLAN_FORMAT_VERSION];
+        write_bytes(&mut buf, self.descriptor.to_string().as_bytes());
+        match self.absolute_timelock {
+            Some(lock_time) => {
+                buf.push(1);
+                write(&mut buf, lock_time.to_consensus_u32());
+            }
+            None => buf.push(0),
+        }
+        match self.relative_timelock {
+            Some(lock_time) => {
+                buf.push(1);
+                write(&mut buf, lock_time.to_consensus_u32());
+            }
+            None => buf.push(0),
+        }
+
+        write_size(&mut buf, self.template.len());
+        for placeholder in &self.template {
+            match placeholder {
+                Placeholder::Pubkey(pk, size) => {
+                    buf.push(0);
+                    write_key(&mut buf, pk);
+                    write_size(&mut buf, *size);
+                }
+                Placeholder::PubkeyHash(hash, size) => {
+                    buf.push(1);
+                    buf.extend_from_slice(hash.as_byte_array());
+                    write_size(&mut buf, *size);
+                }
+                Placeholder::EcdsaSigPk(pk) => {
+                    buf.push(2);
+                    write_key(&mut buf, pk);
+                }
+                Placeholder::EcdsaSigPkHash(hash) => {
+                    buf.push(3);
+                    buf.extend_from_slice(hash.as_byte_array());
+                }
+                Placeholder::SchnorrSigPk(pk, sig_type, size) => {
+                    buf.push(4);
+                    write_key(&mut buf, pk);
+                    match sig_type {
+                        SchnorrSigType::KeySpend { merkle_root: None } => buf.push(0),
+                        SchnorrSigType::KeySpend { merkle_root: Some(root) } => {
+                            buf.push(1);
+                            buf.extend_from_slice(root.as_byte_array());
+                        }
+                        SchnorrSigType::ScriptSpend { leaf_hash } => {
+                            buf.push(2);
+                            buf.extend_from_slice(leaf_hash.as_byte_array());
+                        }
+                        SchnorrSigType::RawKeySpend => buf.push(3),
+                    }
+                    write_size(&mut buf, *size);
+                }
+                Placeholder::SchnorrSigPkHash(hash, leaf_hash, size) => {
+                    buf.push(5);
+                    buf.extend_from_slice(hash.as_byte_array());
+                    buf.extend_from_slice(leaf_hash.as_byte_array());
+                    write_size(&mut buf, *size);
+                }
+                Placeholder::Sha256Preimage(hash) => {
+                    buf.push(6);
+                    buf.extend_from_slice(hash.as_byte_array());
+                }
+                Placeholder::Hash256Preimage(hash) => {
+                    buf.push(7);
+                    buf.extend_from_slice(hash.as_byte_array());
+                }
+                Placeholder::Ripemd160Preimage(hash) => {
+                    buf.push(8);
+                    buf.extend_from_slice(hash.as_byte_array());
+                }
+                Placeholder::Hash160Preimage(hash) => {
+                    buf.push(9);
+                    buf.extend_from_slice(hash.as_byte_array());
+                }
+                Placeholder::HashDissatisfaction => buf.push(10),
+                Placeholder::PushOne => buf.push(11),
+                Placeholder::PushZero => buf.push(12),
+                Placeholder::TapScript(script) => {
+                    buf.push(13);
+                    write_bytes(&mut buf, script.as_bytes());
+                }
+                Placeholder::TapControlBlock(control_block) => {
+                    buf.push(14);
+                    write_bytes(&mut buf, &control_block.serialize());
+                }
+            }
+        }
+
+        let checksum = sha256d::Hash::hash(&buf);
+        buf.extend_from_slice(&checksum[..4]);
+        buf
+    }
+
+    /// Deserialize a plan serialized by [`Plan::serialize`]
+    ///
+    /// The witness template and timelocks are checked to be the ones planned for the
+    /// descriptor with the keys, preimages and timelocks they use, so that a tampered template
+    /// isn't handed to a signer.
+    pub fn deserialize(bytes: &[u8]) -> Result<Self, PlanDecodeError> {
+        use bitcoin::consensus::encode::Error as EncodeError;
+        use bitcoin::consensus::Decodable;
+        use bitcoin::hashes::{sha256d, Hash};
+        use bitcoin::io::Read;
+
+        fn read<T: Decodable>(bytes: &mut &[u8]) -> Result<T, PlanDecodeError> {
+            T::consensus_decode(bytes).map_err(PlanDecodeError::Encoding)
+        }
+        fn read_size(bytes: &mut &[u8]) -> Result<usize, PlanDecodeError> {
+            usize::try_from(read::<bitcoin::VarInt>(bytes)?.0)
+                .map_err(|_| PlanDecodeError::Encoding(EncodeError::ParseFailed("size too large")))
+        }
+        fn read_array<const N: usize>(bytes: &mut &[u8]) -> Result<[u8; N], PlanDecodeError> {
+            let mut array = [0; N];
+            bytes
+                .read_exact(&mut array)
+                .map_err(|e| PlanDecodeError::Encoding(e.into()))?;
+            Ok(array)
+        }
+        fn read_string(bytes: &mut &[u8]) -> Result<String, PlanDecodeError> {
+            String::from_utf8(read::<Vec<u8>>(bytes)?)
+                .map_err(|e| PlanDecodeError::Utf8(e.utf8_error()))
+        }
+        fn read_key(bytes: &mut &[u8]) -> Result<DefiniteDescriptorKey, PlanDecodeError> {
+            read_string(bytes)?.parse().map_err(PlanDecodeError::Key)
+        }
+        fn unknown_tag<T>(field: &'static str, tag: u8) -> Result<T, PlanDecodeError> {
+            Err(PlanDecodeError::UnknownTag { field, tag })
+        }
+
+        if bytes.len() < 5 {
+            return Err(PlanDecodeError::TooShort);
+        }
+        let (payload, checksum) = bytes.split_at(bytes.len() - 4);
+        if sha256d::Hash::hash(payload)[..4] != *checksum {
+            return Err(PlanDecodeError::ChecksumMismatch);
+        }
+        let mut bytes = payload;
+        let bytes = &mut bytes;
+        match read::<u8>(bytes)? {
+            PLAN_FORMAT_VERSION => {}
+            version => return Err(PlanDecodeError::UnsupportedVersion(version)),
+        }
+
+        let descriptor = read_string(bytes)?
+            .parse()
+            .map_err(PlanDecodeError::Descriptor)?;
+        let absolute_timelock = match read::<u8>(bytes)? {
+            0 => None,
+            1 => Some(absolute::LockTime::from_consensus(read(bytes)?)),
+            b => return unknown_tag("timelock flag", b),
+        };
+        let relative_timelock = match read::<u8>(bytes)? {
+            0 => None,
+            1 => Some(
+                relative::LockTime::from_consensus(read(bytes)?)
+                    .map_err(PlanDecodeError::RelativeTimelock)?,
+            ),
+            b => return unknown_tag("timelock flag", b),
+        };
+
+        let len = read_size(bytes)?;
+        let mut template = Vec::new();
+        for _ in 0..len {
+            let placeholder = match read::<u8>(bytes)? {
+                0 => Placeholder::Pubkey(read_key(bytes)?, read_size(bytes)?),
+                1 => Placeholder::PubkeyHash(
+                    hash160::Hash::from_byte_array(read_array(bytes)?),
+                    read_size(bytes)?,
+                ),
+                2 => Placeholder::EcdsaSigPk(read_key(bytes)?),
+                3 => {
+                    Placeholder::EcdsaSigPkHash(hash160::Hash::from_byte_array(read_array(bytes)?))
+                }
+                4 => {
+                    let pk = read_key(bytes)?;
+                    let sig_type = match read::<u8>(bytes)? {
+                        0 => SchnorrSigType::KeySpend { merkle_root: None },
+                        1 => SchnorrSigType::KeySpend {
+                            merkle_root: Some(TapNodeHash::from_byte_array(read_array(bytes)?)),
+                        },
+                        2 => SchnorrSigType::ScriptSpend {
+                            leaf_hash: TapLeafHash::from_byte_array(read_array(bytes)?),
+                        },
+                        3 => SchnorrSigType::RawKeySpend,
+                        b => return unknown_tag("signature type", b),
+                    };
+                    Placeholder::SchnorrSigPk(pk, sig_type, read_size(bytes)?)
+                }
+                5 => Placeholder::SchnorrSigPkHash(
+                    hash160::Hash::from_byte_array(read_array(bytes)?),
+                    TapLeafHash::from_byte_array(read_array(bytes)?),
+                    read_size(bytes)?,
+                ),
+                6 => Placeholder::Sha256Preimage(sha256::Hash::from_byte_array(read_array(bytes)?)),
+                7 => {
+                    Placeholder::Hash256Preimage(hash256::Hash::from_byte_array(read_array(bytes)?))
+                }
+                8 => Placeholder::Ripemd160Preimage(ripemd160::Hash::from_byte_array(read_array(
+                    bytes,
+                )?)),
+                9 => {
+                    Placeholder::Hash160Preimage(hash160::Hash::from_byte_array(read_array(bytes)?))
+                }
+                10 => Placeholder::HashDissatisfaction,
+                11 => Placeholder::PushOne,
+                12 => Placeholder::PushZero,
+                13 => Placeholder::TapScript(ScriptBuf::from_bytes(read(bytes)?)),
+                14 => Placeholder::TapControlBlock(
+                    ControlBlock::decode(&read::<Vec<u8>>(bytes)?)
+                        .map_err(PlanDecodeError::ControlBlock)?,
+                ),
+                b => return unknown_tag("witness item type", b),
+            };
+            template.push(placeholder);
+        }
+        if !bytes.is_empty() {
+            return Err(PlanDecodeError::TrailingBytes);
+        }
+        let plan = Plan { template, absolute_timelock, relative_timelock, descriptor };
+        if !plan.is_planned_template() {
+            return Err(PlanDecodeError::TemplateMismatch);
+        }
+        Ok(plan)
+    }
+
+    /// Whether planning the descriptor with the keys, preimages and timelocks of the witness
+    /// template gives back the template and timelocks
+    fn is_planned_template(&self) -> bool {
+        let assets = TemplateAssets(self);
+        let is_planned = |replanned: Result<Plan, _>| {
+            replanned.map_or(false, |replanned| {
+                replanned.template == self.template
+                    && replanned.absolute_timelock == self.absolute_timelock
+                    && replanned.relative_timelock == self.relative_timelock
+            })
+        };
+        is_planned(self.descriptor.clone().plan(&assets))
+            || is_planned(self.descriptor.clone().plan_mall(&assets))
+    }
+
+    /// Encode the serialized plan as text, for QR codes
+    ///
+    /// The text is the bytes of [`Plan::serialize`] in upper case bech32 characters, without
+    /// a bech32 checksum, after the `MPLAN1` prefix. It only uses characters of the
+    /// alphanumeric mode of QR codes, which is denser than their byte mode.
+    pub fn to_qr_string(&self) -> String {
+        let hrp = bech32::Hrp::parse_unchecked(PLAN_HRP);
+        bech32::encode_upper::<bech32::NoChecksum>(hrp, &self.serialize())
+            .expect("no length limit without a checksum")
+    }
+
+    /// Decode a plan encoded by [`Plan::to_qr_string`], in upper or lower case
+    pub fn from_qr_string(s: &str) -> Result<Self, PlanDecodeError> {
+        let hrpstring = bech32::primitives::decode::CheckedHrpstring::new::<bech32::NoChecksum>(s)
+            .map_err(PlanDecodeError::Bech32)?;
+        if hrpstring.hrp() != bech32::Hrp::parse_unchecked(PLAN_HRP) {
+            return Err(PlanDecodeError::WrongPrefix);
+        }
+        Self::deserialize(&hrpstring.byte_iter().collect::<Vec<_>>())
+    }
+}
+
+/// A [`Plan`] along with the [`Assets`] it was computed from
+///
+/// Lets fee bumping and contingency planning engines ask how the plan changes when the
+/// feerate or the available keys change, replanning only when the plan is affected.
+#[derive(Debug, Clone)]
+pub struct PlanSnapshot {
+    plan: Plan,
+    assets: Assets,
+}
+
+/// The change of a [`PlanSnapshot`], see [`PlanSnapshot::without_key`]
+#[derive(Debug, Clone)]
+pub struct PlanDiff {
+    /// The new plan, `None` if the descriptor can't be satisfied anymore
+    pub plan: Option<Plan>,
+    /// Whether the plan had to be computed again, `false` if it was unaffected
+    pub replanned: bool,
+    /// The satisfaction weight of the new plan minus the one of the old plan, in weight
+    /// units, `None` if there is no new plan
+    pub weight_delta: Option<i64>,
+}
+
+impl PlanSnapshot {
+    /// Plans the satisfaction of `descriptor` with `assets` and keeps both
+    ///
+    /// Returns [`PlanError::InsufficientAssets`] if the assets aren't sufficient.
+    pub fn new(
+        descriptor: Descriptor<DefiniteDescriptorKey>,
+        assets: Assets,
+    ) -> Result<Self, PlanError> {
+        let plan = descriptor
+            .plan(&assets)
+            .map_err(|_| PlanError::InsufficientAssets)?;
+        Ok(PlanSnapshot { plan, assets })
+    }
+
+    /// The plan
+    pub fn plan(&self) -> &Plan { &self.plan }
+
+    /// The assets the plan was computed from
+    pub fn assets(&self) -> &Assets { &self.assets }
+
+    /// The fee paid for the satisfaction weight of the plan at `feerate`
+    ///
+    /// Returns `None` on overflow.
+    pub fn fee(&self, feerate: FeeRate) -> Option<Amount> {
+        feerate.fee_wu(Weight::from_wu(self.plan.satisfaction_weight() as u64))
+    }
+
+    /// The additional fee paid for the satisfaction weight of the plan when moving from
+    /// feerate `from` to feerate `to`, e.g. when bumping the fee of a transaction
+    ///
+    /// The feerate doesn't affect which plan is the cheapest, so no replanning is needed.
+    /// Returns `None` on overflow.
+    pub fn fee_delta(&self, from: FeeRate, to: FeeRate) -> Option<SignedAmount> {
+        let from = self.fee(from)?.to_signed().ok()?;
+        let to = self.fee(to)?.to_signed().ok()?;
+        to.checked_sub(from)
+    }
+
+    /// Whether the plan requires a signature of `pk`
+    pub fn uses_key(&self, pk: &DescriptorPublicKey) -> bool {
+        let forbidden = Assets::new().forbid_key(pk.clone());
+        self.plan
+            .template
+            .iter()
+            .any(|placeholder| match placeholder {
+                Placeholder::EcdsaSigPk(pk) | Placeholder::SchnorrSigPk(pk, ..) => {
+                    forbidden.is_forbidden_key(pk)
+                }
+                _ => false,
+            })
+    }
+
+    /// How the plan changes if `pk` becomes unavailable
+    ///
+    /// The plan is only computed again if it requires a signature of `pk`.
+    pub fn without_key(&self, pk: &DescriptorPublicKey) -> PlanDiff {
+        if !self.uses_key(pk) {
+            return PlanDiff {
+                plan: Some(self.plan.clone()),
+                replanned: false,
+                weight_delta: Some(0),
+            };
+        }
+        let assets = self.assets.clone().forbid_key(pk.clone());
+        let plan = self.plan.descriptor.clone().plan(&assets).ok();
+        let weight_delta = plan
+            .as_ref()
+            .map(|plan| plan.satisfaction_weight() as i64 - self.plan.satisfaction_weight() as i64);
+        PlanDiff { plan, replanned: true, weight_delta }
+    }
+}
+
+/// Wrapper around [`Assets`] which checks timelocks against the chain instead
+struct ChainAssets<'a> {
+    assets: &'a Assets,
+    /// The chain tip and the tip the output was confirmed on. If `None`, any timelock is
+    /// considered satisfied.
+    chain: Option<(ChainTip, Option<ChainTip>)>,
+}
+
+macro_rules! impl_delegate_method {
+    ( $name:ident, $( $arg:ident : $ty:ty, )* -> $ret_ty:ty ) => {
+        fn $name(&self, $( $arg: $ty ),* ) -> $ret_ty { self.assets.$name($( $arg ),*) }
+    }
+}
+
+impl AssetProvider<DefiniteDescriptorKey> for ChainAssets<'_> {
+    impl_delegate_method!(provider_lookup_ecdsa_sig, pk: &DefiniteDescriptorKey, -> bool);
+    impl_delegate_method!(provider_lookup_tap_key_spend_sig, pk: &DefiniteDescriptorKey, -> Option<usize>);
+    impl_delegate_method!(provider_lookup_raw_tr_sig, pk: &DefiniteDescriptorKey, -> Option<usize>);
+    impl_delegate_method!(provider_lookup_tap_leaf_script_sig, pk: &DefiniteDescriptorKey, leaf_hash: &TapLeafHash, -> Option<usize>);
+    impl_delegate_method!(provider_lookup_tap_control_block_map, -> Option<&BTreeMap<ControlBlock, (bitcoin::ScriptBuf, LeafVersion)>>);
+    impl_delegate_method!(provider_lookup_raw_pkh_pk, hash: &hash160::Hash, -> Option<bitcoin::PublicKey>);
+    impl_delegate_method!(provider_lookup_raw_pkh_x_only_pk, hash: &hash160::Hash, -> Option<XOnlyPublicKey>);
+    impl_delegate_method!(provider_lookup_raw_pkh_ecdsa_sig, hash: &hash160::Hash, -> Option<bitcoin::PublicKey>);
+    impl_delegate_method!(provider_lookup_raw_pkh_tap_leaf_script_sig, hash: &(hash160::Hash, TapLeafHash), -> Option<(XOnlyPublicKey, usize)>);
+    impl_delegate_method!(provider_lookup_sha256, hash: &sha256::Hash, -> bool);
+    impl_delegate_method!(provider_lookup_hash256, hash: &hash256::Hash, -> bool);
+    impl_delegate_method!(provider_lookup_ripemd160, hash: &ripemd160::Hash, -> bool);
+    impl_delegate_method!(provider_lookup_hash160, hash: &hash160::Hash, -> bool);
+    impl_delegate_method!(check_tap_path, leaf_hash: Option<&TapLeafHash>, -> bool);
+    impl_delegate_method!(check_search_node, -> bool);
+
+    fn check_older(&self, s: relative::LockTime) -> bool {
+        match self.chain {
+            Some((tip, confirmed_on)) => {
+                confirmed_on.map_or(false, |confirmed_on| tip.is_relative_final(&confirmed_on, s))
+            }
+            None => true,
+        }
+    }
+
+    fn check_after(&self, l: absolute::LockTime) -> bool {
+        match self.chain {
+            Some((tip, _)) => tip.is_final(l),
+            None => true,
+        }
+    }
+}
+
+/// Wrapper around an [`AssetProvider`] which counts the nodes visited by the search for a plan,
+/// and stops it once it is over budget
+pub(crate) struct BudgetAssets<'a, P> {
+    assets: &'a P,
+    max_nodes: usize,
+    visited: Cell<usize>,
+}
+
+impl<'a, P> BudgetAssets<'a, P> {
+    pub(crate) fn new(assets: &'a P, budget: PlanBudget) -> Self {
+        BudgetAssets { assets, max_nodes: budget.max_nodes, visited: Cell::new(0) }
+    }
+
+    /// Whether the search visited more nodes than the budget allows
+    pub(crate) fn exceeded(&self) -> bool { self.visited.get() > self.max_nodes }
+}
+
+impl<P: AssetProvider<DefiniteDescriptorKey>> AssetProvider<DefiniteDescriptorKey>
+    for BudgetAssets<'_, P>
+{
+    impl_delegate_method!(provider_lookup_ecdsa_sig, pk: &DefiniteDescriptorKey, -> bool);
+    impl_delegate_method!(provider_lookup_tap_key_spend_sig, pk: &DefiniteDescriptorKey, -> Option<usize>);
+    impl_delegate_method!(provider_lookup_raw_tr_sig, pk: &DefiniteDescriptorKey, -> Option<usize>);
+    impl_delegate_method!(provider_lookup_tap_leaf_script_sig, pk: &DefiniteDescriptorKey, leaf_hash: &TapLeafHash, -> Option<usize>);
+    impl_delegate_method!(provider_lookup_tap_control_block_map, -> Option<&BTreeMap<ControlBlock, (bitcoin::ScriptBuf, LeafVersion)>>);
+    impl_delegate_method!(provider_lookup_raw_pkh_pk, hash: &hash160::Hash, -> Option<bitcoin::PublicKey>);
+    impl_delegate_method!(provider_lookup_raw_pkh_x_only_pk, hash: &hash160::Hash, -> Option<XOnlyPublicKey>);
+    impl_delegate_method!(provider_lookup_raw_pkh_ecdsa_sig, hash: &hash160::Hash, -> Option<bitcoin::PublicKey>);
+    impl_delegate_method!(provider_lookup_raw_pkh_tap_leaf_script_sig, hash: &(hash160::Hash, TapLeafHash), -> Option<(XOnlyPublicKey, usize)>);
+    impl_delegate_method!(provider_lookup_sha256, hash: &sha256::Hash, -> bool);
+    impl_delegate_method!(provider_lookup_hash256, hash: &hash256::Hash, -> bool);
+    impl_delegate_method!(provider_lookup_ripemd160, hash: &ripemd160::Hash, -> bool);
+    impl_delegate_method!(provider_lookup_hash160, hash: &hash160::Hash, -> bool);
+    impl_delegate_method!(check_older, s: relative::LockTime, -> bool);
+    impl_delegate_method!(check_after, l: absolute::LockTime, -> bool);
+    impl_delegate_method!(check_tap_path, leaf_hash: Option<&TapLeafHash>, -> bool);
+
+    fn check_search_node(&self) -> bool {
+        self.visited.set(self.visited.get().saturating_add(1));
+        !self.exceeded() && self.assets.check_search_node()
+    }
+}
+
+/// Wrapper around [`Assets`] which only allows absolute timelocks of one unit
+struct LockUnitAssets<'a> {
+    assets: &'a Assets,
+    /// Whether the allowed absolute timelocks are height-based rather than time-based
+    blocks: bool,
+}
+
+impl AssetProvider<DefiniteDescriptorKey> for LockUnitAssets<'_> {
+    impl_delegate_method!(provider_lookup_ecdsa_sig, pk: &DefiniteDescriptorKey, -> bool);
+    impl_delegate_method!(provider_lookup_tap_key_spend_sig, pk: &DefiniteDescriptorKey, -> Option<usize>);
+    impl_delegate_method!(provider_lookup_raw_tr_sig, pk: &DefiniteDescriptorKey, -> Option<usize>);
+    impl_delegate_method!(provider_lookup_tap_leaf_script_sig, pk: &DefiniteDescriptorKey, leaf_hash: &TapLeafHash, -> Option<usize>);
+    impl_delegate_method!(provider_lookup_tap_control_block_map, -> Option<&BTreeMap<ControlBlock, (bitcoin::ScriptBuf, LeafVersion)>>);
+    impl_delegate_method!(provider_lookup_raw_pkh_pk, hash: &hash160::Hash, -> Option<bitcoin::PublicKey>);
+    impl_delegate_method!(provider_lookup_raw_pkh_x_only_pk, hash: &hash160::Hash, -> Option<XOnlyPublicKey>);
+    impl_delegate_method!(provider_lookup_raw_pkh_ecdsa_sig, hash: &hash160::Hash, -> Option<bitcoin::PublicKey>);
+    impl_delegate_method!(provider_lookup_raw_pkh_tap_leaf_script_sig, hash: &(hash160::Hash, TapLeafHash), -> Option<(XOnlyPublicKey, usize)>);
+    impl_delegate_method!(provider_lookup_sha256, hash: &sha256::Hash, -> bool);
+    impl_delegate_method!(provider_lookup_hash256, hash: &hash256::Hash, -> bool);
+    impl_delegate_method!(provider_lookup_ripemd160, hash: &ripemd160::Hash, -> bool);
+    impl_delegate_method!(provider_lookup_hash160, hash: &hash160::Hash, -> bool);
+    impl_delegate_method!(check_tap_path, leaf_hash: Option<&TapLeafHash>, -> bool);
+    impl_delegate_method!(check_search_node, -> bool);
+    impl_delegate_method!(check_older, s: relative::LockTime, -> bool);
+
+    fn check_after(&self, l: absolute::LockTime) -> bool {
+        l.is_block_height() == self.blocks && self.assets.check_after(l)
+    }
+}
+
+/// The plans of all the inputs of a transaction, satisfiable together
+///
+/// The inputs share the nLockTime of the transaction, so the absolute timelocks of their plans
+/// must all be height-based or all be time-based. Relative timelocks are set per input, in
+/// their nSequence, and never conflict.
+#[derive(Debug, Clone)]
+pub struct TxPlan {
+    /// The plans of the inputs, in the order the inputs were given
+    pub plans: Vec<Plan>,
+    /// The nLockTime of the transaction, the highest absolute timelock of the plans, or zero
+    pub lock_time: absolute::LockTime,
+}
+
+/// The reason no [`TxPlan`] was returned by [`TxPlan::new`]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum TxPlanError {
+    /// The assets of the input at this index aren't sufficient to satisfy its descriptor
+    InsufficientAssets(usize),
+    /// Some inputs can only be satisfied with height-based absolute timelocks and others with
+    /// time-based ones, which a single nLockTime can't satisfy together
+    ConflictingLockTimes {
+        /// The inputs planned with height-based timelocks, with their timelock
+        heights: Vec<(usize, absolute::LockTime)>,
+        /// The inputs planned with time-based timelocks, with their timelock
+        times: Vec<(usize, absolute::LockTime)>,
+    },
+}
+
+impl fmt::Display for TxPlanError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TxPlanError::InsufficientAssets(index) => {
+                write!(f, "assets of input {} are insufficient for a plan", index)
+            }
+            TxPlanError::ConflictingLockTimes { ref heights, ref times } => {
+                f.write_str("inputs need both height-based and time-based locktimes:")?;
+                for (index, lock_time) in heights.iter().chain(times) {
+                    write!(f, " input {} after {}", index, lock_time)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for TxPlanError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> { None }
+}
+
+impl TxPlan {
+    /// Plans the inputs of a transaction jointly, each input given by the descriptor locking the
+    /// spent output and the assets to satisfy it with
+    ///
+    /// Each input gets its cheapest plan if their absolute timelocks are compatible. Otherwise,
+    /// for either unit, the inputs whose timelocks are of the other unit are planned again with
+    /// timelocks of that unit or none, and the cheaper of the two outcomes is returned.
+    pub fn new(
+        inputs: &[(Descriptor<DefiniteDescriptorKey>, Assets)],
+    ) -> Result<Self, TxPlanError> {
+        let plans = inputs
+            .iter()
+            .enumerate()
+            .map(|(index, (desc, assets))| {
+                desc.clone()
+                    .plan(assets)
+                    .map_err(|_| TxPlanError::InsufficientAssets(index))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let lock_times = |blocks: bool| -> Vec<(usize, absolute::LockTime)> {
+            plans
+                .iter()
+                .enumerate()
+                .filter_map(|(index, plan)| Some((index, plan.absolute_timelock?)))
+                .filter(|(_, lock_time)| lock_time.is_block_height() == blocks)
+                .collect()
+        };
+        let (heights, times) = (lock_times(true), lock_times(false));
+        if heights.is_empty() || times.is_empty() {
+            return Ok(TxPlan::from_plans(plans));
+        }
+
+        let replan = |blocks: bool| -> Option<Vec<Plan>> {
+            plans
+                .iter()
+                .zip(inputs)
+                .map(|(plan, (desc, assets))| match plan.absolute_timelock {
+                    Some(lock_time) if lock_time.is_block_height() != blocks => {
+                        desc.clone().plan(&LockUnitAssets { assets, blocks }).ok()
+                    }
+                    _ => Some(plan.clone()),
+                })
+                .collect()
+        };
+        let weight =
+            |plans: &[Plan]| -> usize { plans.iter().map(Plan::satisfaction_weight).sum() };
+        match (replan(true), replan(false)) {
+            (Some(a), Some(b)) if weight(&b) < weight(&a) => Ok(TxPlan::from_plans(b)),
+            (Some(plans), _) | (None, Some(plans)) => Ok(TxPlan::from_plans(plans)),
+            (None, None) => Err(TxPlanError::ConflictingLockTimes { heights, times }),
+        }
+    }
+
+    fn from_plans(plans: Vec<Plan>) -> Self {
+        let lock_time = plans
+            .iter()
+            .filter_map(|plan| plan.absolute_timelock)
+            .max_by_key(|lock_time| lock_time.to_consensus_u32())
+            .unwrap_or(absolute::LockTime::ZERO);
+        TxPlan { plans, lock_time }
+    }
+
+    /// The nSequence of the input at `index`, `None` if there is no such input
+    ///
+    /// It encodes the relative timelock of the input's plan if any, which also requires the
+    /// transaction version to be at least 2. Otherwise it is the highest nSequence which still
+    /// enables the nLockTime of the transaction.
+    pub fn sequence(&self, index: usize) -> Option<Sequence> {
+        let plan = self.plans.get(index)?;
+        Some(
+            plan.relative_timelock
+                .map_or(Sequence::ENABLE_LOCKTIME_NO_RBF, |lock_time| lock_time.to_sequence()),
+        )
+    }
+}
+
+/// An unsigned transaction spending a set of outputs in full to a single destination
+///
+/// The inputs are planned jointly as by [`TxPlan::new`], and the fee is computed from the
+/// weight the transaction will have once the plans are satisfied.
+#[derive(Debug, Clone)]
+pub struct Sweep {
+    /// The unsigned transaction, with empty script sigs and witnesses
+    pub tx: Transaction,
+    /// The plans of the inputs of the transaction, in order
+    pub plans: Vec<Plan>,
+    /// The spent outputs, in the order of the inputs
+    pub prevouts: Vec<TxOut>,
+    /// The weight of the transaction once satisfied
+    pub weight: Weight,
+    /// The fee paid by the transaction
+    pub fee: Amount,
+}
+
+/// The reason no [`Sweep`] was returned by [`Sweep::new`]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum SweepError {
+    /// No outputs were given to sweep
+    NoInputs,
+    /// The inputs couldn't be planned
+    Plan(TxPlanError),
+    /// The value left after paying the fee is below the dust limit of the destination
+    InsufficientValue {
+        /// The total value of the swept outputs
+        total: Amount,
+        /// The fee of the transaction
+        fee: Amount,
+    },
+    /// The total value of the swept outputs or the fee overflowed
+    Overflow,
+}
+
+impl fmt::Display for SweepError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SweepError::NoInputs => f.write_str("no outputs to sweep"),
+            SweepError::Plan(ref e) => fmt::Display::fmt(e, f),
+            SweepError::InsufficientValue { total, fee } => write!(
+                f,
+                "swept value {} minus fee {} is below the dust limit of the destination",
+                total, fee
+            ),
+            SweepError::Overflow => f.write_str("amount overflow"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for SweepError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match *self {
+            SweepError::Plan(ref e) => Some(e),
+            SweepError::NoInputs | SweepError::InsufficientValue { .. } | SweepError::Overflow => {
+                None
+            }
+        }
+    }
+}
+
+impl Sweep {
+    /// Builds a transaction spending each of `utxos`, given by its outpoint, the output and
+    /// the descriptor locking it, to `destination` at `feerate`
+    ///
+    /// All the inputs are planned with the same `assets`. The transaction has version 2, so
+    /// that relative timelocks are enforced, and the nLockTime required by the plans.
+    pub fn new(
+        utxos: &[(OutPoint, TxOut, Descriptor<DefiniteDescriptorKey>)],
+        assets: &Assets,
+        destination: ScriptBuf,
+        feerate: FeeRate,
+    ) -> Result<Self, SweepError> {
+        if utxos.is_empty() {
+            return Err(SweepError::NoInputs);
+        }
+        let inputs: Vec<_> = utxos
+            .iter()
+            .map(|(_, _, desc)| (desc.clone(), assets.clone()))
+            .collect();
+        let tx_plan = TxPlan::new(&inputs).map_err(SweepError::Plan)?;
+
+        let total = utxos
+            .iter()
+            .try_fold(Amount::ZERO, |total, (_, txout, _)| total.checked_add(txout.value))
+            .ok_or(SweepError::Overflow)?;
+        let mut tx = Transaction {
+            version: transaction::Version::TWO,
+            lock_time: tx_plan.lock_time,
+            input: utxos
+                .iter()
+                .enumerate()
+                .map(|(index, (outpoint, _, _))| TxIn {
+                    previous_output: *outpoint,
+                    sequence: tx_plan.sequence(index).expect("one plan per input"),
+                    ..TxIn::default()
+                })
+                .collect(),
+            output: vec![TxOut { value: Amount::ZERO, script_pubkey: destination }],
+        };
+
+        // The unsigned transaction counts an empty script sig for each input, which the
+        // satisfaction weights of the plans include. If any input is segwit, the transaction
+        // has a marker and flag, and the other inputs have empty witnesses.
+        let plans = tx_plan.plans;
+        let segwit = plans.iter().any(|plan| plan.witness_version().is_some());
+        let mut weight = tx.weight().to_wu() as usize - 4 * plans.len();
+        weight += plans.iter().map(Plan::satisfaction_weight).sum::<usize>();
+        if segwit {
+            weight += 2 + plans
+                .iter()
+                .filter(|plan| plan.witness_version().is_none())
+                .count();
+        }
+        let weight = Weight::from_wu(weight as u64);
+        let fee = feerate.fee_wu(weight).ok_or(SweepError::Overflow)?;
+
+        let value = total
+            .checked_sub(fee)
+            .filter(|value| *value >= tx.output[0].script_pubkey.minimal_non_dust())
+            .ok_or(SweepError::InsufficientValue { total, fee })?;
+        tx.output[0].value = value;
+
+        let prevouts = utxos.iter().map(|(_, txout, _)| txout.clone()).collect();
+        Ok(Sweep { tx, plans, prevouts, weight, fee })
+    }
+
+    /// Converts the sweep into a PSBT, with each input updated from its plan
+    ///
+    /// The witness UTXO is set for every input. Legacy inputs also need their non-witness
+    /// UTXO, the full previous transaction, which the sweep doesn't know about.
+    pub fn into_psbt(self) -> Psbt {
+        let mut psbt =
+            Psbt::from_unsigned_tx(self.tx).expect("the script sigs and witnesses are empty");
+        for ((input, plan), prevout) in psbt.inputs.iter_mut().zip(&self.plans).zip(self.prevouts) {
+            input.witness_utxo = Some(prevout);
+            plan.update_psbt_input(input);
+        }
+        psbt
+    }
+}
+
+/// An item of a [`PartialWitness`]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum WitnessSlot<Pk: MiniscriptKey> {
+    /// The item was provided by a satisfier
+    Filled(Vec<u8>),
+    /// The item is still missing
+    Missing(Placeholder<Pk>),
+}
+
+/// A witness template where only some of the items have been satisfied
+///
+/// This is the interchange format for spends requiring data from several parties: each party
+/// fills the items it knows about and passes the result on, until the last one is able to
+/// finalize it. See [`Plan::partial_satisfy`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PartialWitness<Pk: MiniscriptKey> {
+    slots: Vec<WitnessSlot<Pk>>,
+}
+
+/// The reason a [`PartialWitness`] couldn't be restored by [`Plan::partial_witness_from_slice`]
+#[derive(Debug)]
+pub enum PartialWitnessError {
+    /// The serialized data is truncated or malformed
+    Decode(bitcoin::consensus::encode::Error),
+    /// An item is neither missing (`0x00`) nor filled (`0x01`)
+    UnknownItemType(u8),
+    /// Bytes are left after the last item
+    TrailingBytes(usize),
+    /// The number of items doesn't match the plan's witness template
+    ItemCount {
+        /// The number of items in the partial witness
+        items: usize,
+        /// The number of items in the plan's witness template
+        expected: usize,
+    },
+}
+
+impl fmt::Display for PartialWitnessError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PartialWitnessError::Decode(ref e) => write!(f, "invalid partial witness: {}", e),
+            PartialWitnessError::UnknownItemType(b) => {
+                write!(f, "unknown partial witness item type {}", b)
+            }
+            PartialWitnessError::TrailingBytes(n) => {
+                write!(f, "{} trailing bytes after the partial witness", n)
+            }
+            PartialWitnessError::ItemCount { items, expected } => {
+                write!(f, "partial witness has {} items, but the plan expects {}", items, expected)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for PartialWitnessError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match *self {
+            PartialWitnessError::Decode(ref e) => Some(e),
+            PartialWitnessError::UnknownItemType(_)
+            | PartialWitnessError::TrailingBytes(_)
+            | PartialWitnessError::ItemCount { .. } => None,
+        }
+    }
+}
+
+impl<Pk: MiniscriptKey> PartialWitness<Pk> {
+    /// Returns the items of this partial witness, in stack order
+    pub fn slots(&self) -> &[WitnessSlot<Pk>] { &self.slots }
+
+    /// Returns an iterator over the items which are still missing
+    pub fn missing(&self) -> impl Iterator<Item = &Placeholder<Pk>> {
+        self.slots.iter().filter_map(|slot| match slot {
+            WitnessSlot::Filled(_) => None,
+            WitnessSlot::Missing(placeholder) => Some(placeholder),
+        })
+    }
+
+    /// Whether every item of the witness has been filled
+    pub fn is_complete(&self) -> bool { self.missing().next().is_none() }
+
+    /// Returns the witness stack if every item has been filled
+    pub fn into_stack(self) -> Option<Vec<Vec<u8>>> {
+        self.slots
+            .into_iter()
+            .map(|slot| match slot {
+                WitnessSlot::Filled(data) => Some(data),
+                WitnessSlot::Missing(_) => None,
+            })
+            .collect()
+    }
+
+    /// Serialize the partial witness
+    ///
+    /// The format is the number of items as a compact size, followed by each item as a `0x00`
+    /// byte if it is missing, or as a `0x01` byte and the length-prefixed data if it is filled.
+    /// The placeholders themselves aren't serialized: they are recovered from the plan by
+    /// [`Plan::partial_witness_from_slice`].
+    pub fn serialize(&self) -> Vec<u8> {
+        use bitcoin::consensus::Encodable;
+
+        let mut buf = Vec::new();
+        bitcoin::VarInt::from(self.slots.len())
+            .consensus_encode(&mut buf)
+            .expect("writing to a vec can't fail");
+        for slot in &self.slots {
+            match slot {
+                WitnessSlot::Filled(data) => {
+                    buf.push(1);
+                    data.consensus_encode(&mut buf)
+                        .expect("writing to a vec can't fail");
+                }
+                WitnessSlot::Missing(_) => buf.push(0),
+            }
+        }
+        buf
+    }
+
+    fn deserialize_items(mut bytes: &[u8]) -> Result<Vec<Option<Vec<u8>>>, PartialWitnessError> {
+        use bitcoin::consensus::Decodable;
+
+        let len = bitcoin::VarInt::consensus_decode(&mut bytes)
+            .map_err(PartialWitnessError::Decode)?
+            .0;
+        let mut items = Vec::new();
+        for _ in 0..len {
+            match u8::consensus_decode(&mut bytes).map_err(PartialWitnessError::Decode)? {
+                0 => items.push(None),
+                1 => items.push(Some(
+                    Vec::<u8>::consensus_decode(&mut bytes).map_err(PartialWitnessError::Decode)?,
+                )),
+                b => return Err(PartialWitnessError::UnknownItemType(b)),
+            }
+        }
+        if !bytes.is_empty() {
+            return Err(PartialWitnessError::TrailingBytes(bytes.len()));
+        }
+        Ok(items)
+    }
+}
+
+impl<Pk: MiniscriptKey + ToPublicKey> PartialWitness<Pk> {
+    /// Fill the missing items using a [`Satisfier`]
+    ///
+    /// Items already present are left untouched. Returns the number of items which were
+    /// filled by this call.
+    pub fn fill<Sat: Satisfier<Pk>>(&mut self, stfr: &Sat) -> usize {
+        let mut filled = 0;
+        for slot in &mut self.slots {
+            if let WitnessSlot::Missing(placeholder) = slot {
+                if let Some(data) = placeholder.satisfy_self(stfr) {
+                    *slot = WitnessSlot::Filled(data);
+                    filled += 1;
+                }
+            }
+        }
+        filled
+    }
+}
+
+/// A [`Satisfier`] only providing the signatures, preimages and timelocks required by a plan
+pub(crate) struct ApprovedSatisfier<'a, Sat> {
+    pub(crate) plan: &'a Plan,
+    pub(crate) inner: &'a Sat,
+}
+
+impl<Sat> ApprovedSatisfier<'_, Sat> {
+    fn contains(&self, pred: impl Fn(&Placeholder<DefiniteDescriptorKey>) -> bool) -> bool {
+        self.plan.requirements().any(pred)
+    }
+}
+
+impl<Sat: Satisfier<DefiniteDescriptorKey>> Satisfier<DefiniteDescriptorKey>
+    for ApprovedSatisfier<'_, Sat>
+{
+    fn lookup_ecdsa_sig(&self, pk: &DefiniteDescriptorKey) -> Option<bitcoin::ecdsa::Signature> {
+        if self.contains(|p| matches!(p, Placeholder::EcdsaSigPk(k) if k == pk)) {
+            self.inner.lookup_ecdsa_sig(pk)
+        } else {
+            None
+        }
+    }
+
+    fn lookup_tap_key_spend_sig(&self) -> Option<bitcoin::taproot::Signature> {
+        if self.contains(|p| {
+            matches!(p, Placeholder::SchnorrSigPk(_, SchnorrSigType::KeySpend { .. }, _))
+        }) {
+            self.inner.lookup_tap_key_spend_sig()
+        } else {
+            None
+        }
+    }
+
+    fn lookup_raw_tr_schnorr_sig(
+        &self,
+        pk: &DefiniteDescriptorKey,
+    ) -> Option<bitcoin::taproot::Signature> {
+        if self.contains(|p| {
+            matches!(p, Placeholder::SchnorrSigPk(k, SchnorrSigType::RawKeySpend, _) if k == pk)
+        }) {
+            self.inner.lookup_raw_tr_schnorr_sig(pk)
+        } else {
+            None
+        }
+    }
+
+    fn lookup_tap_leaf_script_sig(
+        &self,
+        pk: &DefiniteDescriptorKey,
+        leaf_hash: &TapLeafHash,
+    ) -> Option<bitcoin::taproot::Signature> {
+        if self.contains(|p| {
+            matches!(
+                p,
+                Placeholder::SchnorrSigPk(k, SchnorrSigType::ScriptSpend { leaf_hash: h }, _)
+                    if k == pk && h == leaf_hash
+            )
+        }) {
+            self.inner.lookup_tap_leaf_script_sig(pk, leaf_hash)
+        } else {
+            None
+        }
+    }
+
+    fn lookup_tap_control_block_map(
+        &self,
+    ) -> Option<&BTreeMap<ControlBlock, (ScriptBuf, LeafVersion)>> {
+        self.inner.lookup_tap_control_block_map()
+    }
+
+    fn lookup_raw_pkh_pk(&self, hash: &hash160::Hash) -> Option<bitcoin::PublicKey> {
+        self.inner.lookup_raw_pkh_pk(hash)
+    }
+
+    fn lookup_raw_pkh_x_only_pk(&self, hash: &hash160::Hash) -> Option<XOnlyPublicKey> {
+        self.inner.lookup_raw_pkh_x_only_pk(hash)
+    }
+
+    fn lookup_raw_pkh_ecdsa_sig(
+        &self,
+        hash: &hash160::Hash,
+    ) -> Option<(bitcoin::PublicKey, bitcoin::ecdsa::Signature)> {
+        if self.contains(|p| matches!(p, Placeholder::EcdsaSigPkHash(h) if h == hash)) {
+            self.inner.lookup_raw_pkh_ecdsa_sig(hash)
+        } else {
+            None
+        }
+    }
+
+    fn lookup_raw_pkh_tap_leaf_script_sig(
+        &self,
+        hash: &(hash160::Hash, TapLeafHash),
+    ) -> Option<(XOnlyPublicKey, bitcoin::taproot::Signature)> {
+        if self
+            .contains(|p| matches!(p, Placeholder::SchnorrSigPkHash(h, l, _) if (*h, *l) == *hash))
+        {
+            self.inner.lookup_raw_pkh_tap_leaf_script_sig(hash)
+        } else {
+            None
+        }
+    }
+
+    fn lookup_sha256(&self, hash: &sha256::Hash) -> Option<Preimage32> {
+        if self.contains(|p| matches!(p, Placeholder::Sha256Preimage(h) if h == hash)) {
+            self.inner.lookup_sha256(hash)
+        } else {
+            None
+        }
+    }
+
+    fn lookup_hash256(&self, hash: &hash256::Hash) -> Option<Preimage32> {
+        if self.contains(|p| matches!(p, Placeholder::Hash256Preimage(h) if h == hash)) {
+            self.inner.lookup_hash256(hash)
+        } else {
+            None
+        }
+    }
+
+    fn lookup_ripemd160(&self, hash: &ripemd160::Hash) -> Option<Preimage32> {
+        if self.contains(|p| matches!(p, Placeholder::Ripemd160Preimage(h) if h == hash)) {
+            self.inner.lookup_ripemd160(hash)
         } else {
-            for item in &self.template {
-                if let Placeholder::EcdsaSigPk(pk) = item {
-                    let public_key = pk.to_public_key().inner;
-                    let master_fingerprint = pk.master_fingerprint();
-                    for derivation_path in pk.full_derivation_paths() {
-                        input
-                            .bip32_derivation
-                            .insert(public_key, (master_fingerprint, derivation_path));
-                    }
-                }
-            }
+            None
+        }
+    }
 
-            match &self.descriptor {
-                Descriptor::Bare(_) | Descriptor::Pkh(_) | Descriptor::Wpkh(_) => {}
-                Descriptor::Sh(sh) => match sh.as_inner() {
-                    descriptor::ShInner::Wsh(wsh) => {
-                        input.witness_script = Some(wsh.inner_script());
-                        input.redeem_script = Some(wsh.inner_script().to_p2wsh());
-                    }
-                    descriptor::ShInner::Wpkh(..) => input.redeem_script = Some(sh.inner_script()),
-                    descriptor::ShInner::SortedMulti(_) | descriptor::ShInner::Ms(_) => {
-                        input.redeem_script = Some(sh.inner_script())
-                    }
-                },
-                Descriptor::Wsh(wsh) => input.witness_script = Some(wsh.inner_script()),
-                Descriptor::Tr(_) => unreachable!("Tr is dealt with separately"),
+    fn lookup_hash160(&self, hash: &hash160::Hash) -> Option<Preimage32> {
+        if self.contains(|p| matches!(p, Placeholder::Hash160Preimage(h) if h == hash)) {
+            self.inner.lookup_hash160(hash)
+        } else {
+            None
+        }
+    }
+
+    fn check_older(&self, n: relative::LockTime) -> bool {
+        self.plan
+            .relative_timelock
+            .map_or(false, |approved| n.is_implied_by(approved))
+            && self.inner.check_older(n)
+    }
+
+    fn check_after(&self, n: absolute::LockTime) -> bool {
+        self.plan
+            .absolute_timelock
+            .map_or(false, |approved| n.is_implied_by(approved))
+            && self.inner.check_after(n)
+    }
+}
+
+/// Usage statistics of the spending paths of a taproot descriptor
+///
+/// Wallets can record every plan they actually broadcast, and later feed the collected counts
+/// into [`crate::descriptor::Tr::rebalance_advice`] to find out whether reorganizing the
+/// script tree would lower the expected spending weight.
+/// With the `compiler` feature, the counts can also be given to
+/// `Policy::compile_tr_with_usage` to weight the leaves of a Taproot compilation.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct UsageStats {
+    /// Number of recorded key-path spends
+    pub key_spends: u64,
+    /// Number of recorded script-path spends, per tap leaf
+    pub leaf_spends: BTreeMap<TapLeafHash, u64>,
+}
+
+impl UsageStats {
+    /// Construct an empty instance
+    pub fn new() -> Self { Self::default() }
+
+    /// Record one spend following the given plan
+    ///
+    /// Plans for non-taproot descriptors are ignored.
+    pub fn record(&mut self, plan: &Plan) {
+        if let Descriptor::Tr(_) = plan.descriptor {
+            match plan.tap_leaf_hash() {
+                Some(leaf_hash) => self.record_leaf(leaf_hash, 1),
+                None => self.key_spends += 1,
             }
         }
     }
+
+    /// Record `count` script-path spends through the leaf with hash `leaf_hash`
+    pub fn record_leaf(&mut self, leaf_hash: TapLeafHash, count: u64) {
+        *self.leaf_spends.entry(leaf_hash).or_insert(0) += count;
+    }
+
+    /// Number of recorded script-path spends through the leaf with hash `leaf_hash`
+    pub fn leaf_count(&self, leaf_hash: &TapLeafHash) -> u64 {
+        self.leaf_spends.get(leaf_hash).copied().unwrap_or(0)
+    }
+
+    /// Total number of recorded script-path spends
+    pub fn script_spends(&self) -> u64 { self.leaf_spends.values().sum() }
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
@@ -505,7 +2078,7 @@ impl TaprootAvailableLeaves {
 }
 
 /// The Assets we can use to satisfy a particular spending path
-#[derive(Debug, Default)]
+#[derive(Debug, Clone, Default)]
 pub struct Assets {
     /// Keys the user can sign for, and how.
     ///
@@ -527,6 +2100,13 @@ pub struct Assets {
     pub absolute_timelock: Option<absolute::LockTime>,
     /// Maximum relative timelock allowed
     pub relative_timelock: Option<relative::LockTime>,
+    /// Current chain tip, restricting absolute timelocks to the ones final in the next block
+    pub chain_tip: Option<ChainTip>,
+    /// Taproot leaves one of which must be used, if not empty. The key spend path is then
+    /// excluded.
+    pub required_leaves: BTreeSet<TapLeafHash>,
+    /// Keys which must not sign, matched like [`Assets::keys`]
+    pub forbidden_keys: BTreeSet<bip32::KeySource>,
 }
 
 // Checks if the `pk` is a "direct child" of the `derivation_path` provided.
@@ -553,15 +2133,25 @@ fn is_key_direct_child_of(
 }
 
 impl Assets {
-    pub(crate) fn has_ecdsa_key(&self, pk: &DefiniteDescriptorKey) -> bool {
-        self.keys.iter().any(|(keysource, can_sign)| {
-            can_sign.ecdsa
-                && pk.master_fingerprint() == keysource.0
-                && is_key_direct_child_of(pk, &keysource.1)
+    fn is_forbidden_key(&self, pk: &DefiniteDescriptorKey) -> bool {
+        self.forbidden_keys.iter().any(|keysource| {
+            pk.master_fingerprint() == keysource.0 && is_key_direct_child_of(pk, &keysource.1)
         })
     }
 
+    pub(crate) fn has_ecdsa_key(&self, pk: &DefiniteDescriptorKey) -> bool {
+        !self.is_forbidden_key(pk)
+            && self.keys.iter().any(|(keysource, can_sign)| {
+                can_sign.ecdsa
+                    && pk.master_fingerprint() == keysource.0
+                    && is_key_direct_child_of(pk, &keysource.1)
+            })
+    }
+
     pub(crate) fn has_taproot_internal_key(&self, pk: &DefiniteDescriptorKey) -> Option<usize> {
+        if self.is_forbidden_key(pk) {
+            return None;
+        }
         self.keys.iter().find_map(|(keysource, can_sign)| {
             if !can_sign.taproot.key_spend
                 || pk.master_fingerprint() != keysource.0
@@ -579,6 +2169,9 @@ impl Assets {
         pk: &DefiniteDescriptorKey,
         tap_leaf_hash: &TapLeafHash,
     ) -> Option<usize> {
+        if self.is_forbidden_key(pk) {
+            return None;
+        }
         self.keys.iter().find_map(|(keysource, can_sign)| {
             if !can_sign.taproot.script_spend.is_available(tap_leaf_hash)
                 || pk.master_fingerprint() != keysource.0
@@ -601,6 +2194,11 @@ impl AssetProvider<DefiniteDescriptorKey> for Assets {
         self.has_taproot_internal_key(pk)
     }
 
+    fn provider_lookup_raw_tr_sig(&self, pk: &DefiniteDescriptorKey) -> Option<usize> {
+        // Signing for the output key of a `rawtr()` is a key spend without a tweak
+        self.has_taproot_internal_key(pk)
+    }
+
     fn provider_lookup_tap_leaf_script_sig(
         &self,
         pk: &DefiniteDescriptorKey,
@@ -634,12 +2232,19 @@ impl AssetProvider<DefiniteDescriptorKey> for Assets {
     }
 
     fn check_after(&self, l: absolute::LockTime) -> bool {
-        if let Some(timelock) = self.absolute_timelock {
-            l.is_implied_by(timelock)
-        } else {
-            false
+        match (self.absolute_timelock, self.chain_tip) {
+            (None, None) => false,
+            (timelock, tip) => {
+                timelock.map_or(true, |timelock| l.is_implied_by(timelock))
+                    && tip.map_or(true, |tip| tip.is_final(l))
+            }
         }
     }
+
+    fn check_tap_path(&self, leaf_hash: Option<&TapLeafHash>) -> bool {
+        self.required_leaves.is_empty()
+            || leaf_hash.map_or(false, |leaf_hash| self.required_leaves.contains(leaf_hash))
+    }
 }
 
 impl FromIterator<DescriptorPublicKey> for Assets {
@@ -700,8 +2305,20 @@ impl IntoAssets for Assets {
     fn into_assets(self) -> Assets { self }
 }
 
+impl IntoAssets for &PreimageStore {
+    fn into_assets(self) -> Assets {
+        Assets {
+            sha256_preimages: self.preimages().map(|p| sha256::Hash::hash(p)).collect(),
+            hash256_preimages: self.preimages().map(|p| hash256::Hash::hash(p)).collect(),
+            ripemd160_preimages: self.preimages().map(|p| ripemd160::Hash::hash(p)).collect(),
+            hash160_preimages: self.preimages().map(|p| hash160::Hash::hash(p)).collect(),
+            ..Default::default()
+        }
+    }
+}
+
 impl Assets {
-    /// Contruct an empty instance
+    /// Construct an empty instance
     pub fn new() -> Self { Self::default() }
 
     /// Add some assets
@@ -723,8 +2340,38 @@ impl Assets {
         self
     }
 
+    /// Set the current chain tip
+    ///
+    /// Absolute timelocks will then only be considered available if a transaction using them
+    /// could be included in the next block. If a maximum absolute timelock is also set with
+    /// [`Assets::after`], both constraints apply.
+    pub fn chain_tip(mut self, tip: ChainTip) -> Self {
+        self.chain_tip = Some(tip);
+        self
+    }
+
+    /// Require the plan to spend through the Taproot leaf with the given hash
+    ///
+    /// If several leaves are required, the plan uses one of them. Descriptors which aren't
+    /// Taproot descriptors are planned as usual.
+    pub fn require_leaf(mut self, leaf_hash: TapLeafHash) -> Self {
+        self.required_leaves.insert(leaf_hash);
+        self
+    }
+
+    /// Forbid the plan from using signatures of the given key, even if it is available
+    pub fn forbid_key(mut self, pk: DescriptorPublicKey) -> Self {
+        for deriv_path in pk.full_derivation_paths() {
+            self.forbidden_keys
+                .insert((pk.master_fingerprint(), deriv_path));
+        }
+        self
+    }
+
     fn append(&mut self, b: Self) {
         self.keys.extend(b.keys);
+        self.required_leaves.extend(b.required_leaves);
+        self.forbidden_keys.extend(b.forbidden_keys);
         self.sha256_preimages.extend(b.sha256_preimages);
         self.hash256_preimages.extend(b.hash256_preimages);
         self.ripemd160_preimages.extend(b.ripemd160_preimages);
@@ -732,6 +2379,303 @@ impl Assets {
 
         self.relative_timelock = b.relative_timelock.or(self.relative_timelock);
         self.absolute_timelock = b.absolute_timelock.or(self.absolute_timelock);
+        self.chain_tip = b.chain_tip.or(self.chain_tip);
+    }
+}
+
+#[cfg(feature = "serde")]
+mod serde_impls {
+    //! With the `serde` feature, a [`Plan`] serializes to an object with its descriptor, its
+    //! timelocks as consensus values if present, and its witness template as a list of flat
+    //! objects of strings, e.g.:
+    //!
+    //! ```json
+    //! {
+    //!   "descriptor": "wpkh(02...)#...",
+    //!   "template": [{ "type": "ecdsa_sig_pk", "key": "02..." }, { "type": "pubkey", "key": "02...", "size": "34" }]
+    //! }
+    //! ```
+    //!
+    //! As with [`Plan::serialize`], everything a signer needs to fill the placeholders is kept,
+    //! including the tap leaf script and control block of a script spend.
+
+    use core::str::FromStr;
+
+    use bitcoin::hex::{DisplayHex, FromHex};
+    use serde::de::{Error as _, MapAccess, Visitor};
+    use serde::ser::SerializeMap;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    use super::*;
+
+    type Fields = BTreeMap<String, String>;
+
+    const PLACEHOLDER_TYPES: &[&str] = &[
+        "pubkey",
+        "pubkey_hash",
+        "ecdsa_sig_pk",
+        "ecdsa_sig_pk_hash",
+        "schnorr_sig_pk",
+        "schnorr_sig_pk_hash",
+        "sha256_preimage",
+        "hash256_preimage",
+        "ripemd160_preimage",
+        "hash160_preimage",
+        "hash_dissatisfaction",
+        "push_one",
+        "push_zero",
+        "tap_script",
+        "tap_control_block",
+    ];
+
+    const PLAN_FIELDS: &[&str] = &[
+        "descriptor",
+        "absolute_timelock",
+        "relative_timelock",
+        "template",
+    ];
+
+    fn field<E: serde::de::Error>(fields: &Fields, name: &'static str) -> Result<String, E> {
+        fields
+            .get(name)
+            .cloned()
+            .ok_or_else(|| E::missing_field(name))
+    }
+
+    fn parse<T: FromStr, E: serde::de::Error>(fields: &Fields, name: &'static str) -> Result<T, E>
+    where
+        T::Err: fmt::Display,
+    {
+        T::from_str(&field::<E>(fields, name)?)
+            .map_err(|e| E::custom(format_args!("{}: {}", name, e)))
+    }
+
+    fn parse_hex<E: serde::de::Error>(fields: &Fields, name: &'static str) -> Result<Vec<u8>, E> {
+        Vec::<u8>::from_hex(&field::<E>(fields, name)?)
+            .map_err(|e| E::custom(format_args!("{}: {}", name, e)))
+    }
+
+    impl Serialize for Placeholder<DefiniteDescriptorKey> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            let mut map = serializer.serialize_map(None)?;
+            match self {
+                Placeholder::Pubkey(pk, size) => {
+                    map.serialize_entry("type", "pubkey")?;
+                    map.serialize_entry("key", &pk.to_string())?;
+                    map.serialize_entry("size", &size.to_string())?;
+                }
+                Placeholder::PubkeyHash(hash, size) => {
+                    map.serialize_entry("type", "pubkey_hash")?;
+                    map.serialize_entry("hash", &hash.to_string())?;
+                    map.serialize_entry("size", &size.to_string())?;
+                }
+                Placeholder::EcdsaSigPk(pk) => {
+                    map.serialize_entry("type", "ecdsa_sig_pk")?;
+                    map.serialize_entry("key", &pk.to_string())?;
+                }
+                Placeholder::EcdsaSigPkHash(hash) => {
+                    map.serialize_entry("type", "ecdsa_sig_pk_hash")?;
+                    map.serialize_entry("hash", &hash.to_string())?;
+                }
+                Placeholder::SchnorrSigPk(pk, sig_type, size) => {
+                    map.serialize_entry("type", "schnorr_sig_pk")?;
+                    map.serialize_entry("key", &pk.to_string())?;
+                    match sig_type {
+                        SchnorrSigType::KeySpend { merkle_root } => {
+                            map.serialize_entry("kind", "tap_key_spend")?;
+                            if let Some(root) = merkle_root {
+                                map.serialize_entry("merkle_root", &root.to_string())?;
+                            }
+                        }
+                        SchnorrSigType::ScriptSpend { leaf_hash } => {
+                            map.serialize_entry("kind", "tap_script_spend")?;
+                            map.serialize_entry("leaf_hash", &leaf_hash.to_string())?;
+                        }
+                        SchnorrSigType::RawKeySpend => {
+                            map.serialize_entry("kind", "raw_tr_key_spend")?
+                        }
+                    }
+                    map.serialize_entry("size", &size.to_string())?;
+                }
+                Placeholder::SchnorrSigPkHash(hash, leaf_hash, size) => {
+                    map.serialize_entry("type", "schnorr_sig_pk_hash")?;
+                    map.serialize_entry("hash", &hash.to_string())?;
+                    map.serialize_entry("leaf_hash", &leaf_hash.to_string())?;
+                    map.serialize_entry("size", &size.to_string())?;
+                }
+                Placeholder::Sha256Preimage(hash) => {
+                    map.serialize_entry("type", "sha256_preimage")?;
+                    map.serialize_entry("hash", &hash.to_string())?;
+                }
+                Placeholder::Hash256Preimage(hash) => {
+                    map.serialize_entry("type", "hash256_preimage")?;
+                    map.serialize_entry("hash", &hash.to_string())?;
+                }
+                Placeholder::Ripemd160Preimage(hash) => {
+                    map.serialize_entry("type", "ripemd160_preimage")?;
+                    map.serialize_entry("hash", &hash.to_string())?;
+                }
+                Placeholder::Hash160Preimage(hash) => {
+                    map.serialize_entry("type", "hash160_preimage")?;
+                    map.serialize_entry("hash", &hash.to_string())?;
+                }
+                Placeholder::HashDissatisfaction => {
+                    map.serialize_entry("type", "hash_dissatisfaction")?
+                }
+                Placeholder::PushOne => map.serialize_entry("type", "push_one")?,
+                Placeholder::PushZero => map.serialize_entry("type", "push_zero")?,
+                Placeholder::TapScript(script) => {
+                    map.serialize_entry("type", "tap_script")?;
+                    map.serialize_entry("script", &script.as_bytes().to_lower_hex_string())?;
+                }
+                Placeholder::TapControlBlock(control_block) => {
+                    map.serialize_entry("type", "tap_control_block")?;
+                    map.serialize_entry(
+                        "control_block",
+                        &control_block.serialize().to_lower_hex_string(),
+                    )?;
+                }
+            }
+            map.end()
+        }
+    }
+
+    impl<'de> Deserialize<'de> for Placeholder<DefiniteDescriptorKey> {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            let fields = Fields::deserialize(deserializer)?;
+            let placeholder = match field::<D::Error>(&fields, "type")?.as_str() {
+                "pubkey" => Placeholder::Pubkey(parse(&fields, "key")?, parse(&fields, "size")?),
+                "pubkey_hash" => {
+                    Placeholder::PubkeyHash(parse(&fields, "hash")?, parse(&fields, "size")?)
+                }
+                "ecdsa_sig_pk" => Placeholder::EcdsaSigPk(parse(&fields, "key")?),
+                "ecdsa_sig_pk_hash" => Placeholder::EcdsaSigPkHash(parse(&fields, "hash")?),
+                "schnorr_sig_pk" => {
+                    let sig_type = match field::<D::Error>(&fields, "kind")?.as_str() {
+                        "tap_key_spend" => SchnorrSigType::KeySpend {
+                            merkle_root: match fields.contains_key("merkle_root") {
+                                true => Some(parse(&fields, "merkle_root")?),
+                                false => None,
+                            },
+                        },
+                        "tap_script_spend" => {
+                            SchnorrSigType::ScriptSpend { leaf_hash: parse(&fields, "leaf_hash")? }
+                        }
+                        "raw_tr_key_spend" => SchnorrSigType::RawKeySpend,
+                        kind => {
+                            return Err(D::Error::unknown_variant(
+                                kind,
+                                &["tap_key_spend", "tap_script_spend", "raw_tr_key_spend"],
+                            ))
+                        }
+                    };
+                    Placeholder::SchnorrSigPk(
+                        parse(&fields, "key")?,
+                        sig_type,
+                        parse(&fields, "size")?,
+                    )
+                }
+                "schnorr_sig_pk_hash" => Placeholder::SchnorrSigPkHash(
+                    parse(&fields, "hash")?,
+                    parse(&fields, "leaf_hash")?,
+                    parse(&fields, "size")?,
+                ),
+                "sha256_preimage" => Placeholder::Sha256Preimage(parse(&fields, "hash")?),
+                "hash256_preimage" => Placeholder::Hash256Preimage(parse(&fields, "hash")?),
+                "ripemd160_preimage" => Placeholder::Ripemd160Preimage(parse(&fields, "hash")?),
+                "hash160_preimage" => Placeholder::Hash160Preimage(parse(&fields, "hash")?),
+                "hash_dissatisfaction" => Placeholder::HashDissatisfaction,
+                "push_one" => Placeholder::PushOne,
+                "push_zero" => Placeholder::PushZero,
+                "tap_script" => {
+                    Placeholder::TapScript(ScriptBuf::from_bytes(parse_hex(&fields, "script")?))
+                }
+                "tap_control_block" => Placeholder::TapControlBlock(
+                    ControlBlock::decode(&parse_hex::<D::Error>(&fields, "control_block")?)
+                        .map_err(|e| D::Error::custom(format_args!("control_block: {}", e)))?,
+                ),
+                ty => return Err(D::Error::unknown_variant(ty, PLACEHOLDER_TYPES)),
+            };
+            Ok(placeholder)
+        }
+    }
+
+    impl Serialize for Plan {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            let len = 2
+                + usize::from(self.absolute_timelock.is_some())
+                + usize::from(self.relative_timelock.is_some());
+            let mut map = serializer.serialize_map(Some(len))?;
+            map.serialize_entry("descriptor", &self.descriptor.to_string())?;
+            if let Some(lock_time) = self.absolute_timelock {
+                map.serialize_entry("absolute_timelock", &lock_time.to_consensus_u32())?;
+            }
+            if let Some(lock_time) = self.relative_timelock {
+                map.serialize_entry("relative_timelock", &lock_time.to_consensus_u32())?;
+            }
+            map.serialize_entry("template", &self.template)?;
+            map.end()
+        }
+    }
+
+    impl<'de> Deserialize<'de> for Plan {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            struct PlanVisitor;
+
+            impl<'de> Visitor<'de> for PlanVisitor {
+                type Value = Plan;
+
+                fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result { f.write_str("a plan") }
+
+                fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Plan, A::Error> {
+                    let mut descriptor = None;
+                    let mut absolute_timelock = None;
+                    let mut relative_timelock = None;
+                    let mut template = None;
+                    while let Some(key) = map.next_key::<String>()? {
+                        match key.as_str() {
+                            "descriptor" => {
+                                let s = map.next_value::<String>()?;
+                                descriptor = Some(Descriptor::from_str(&s).map_err(|e| {
+                                    A::Error::custom(format_args!("descriptor: {}", e))
+                                })?);
+                            }
+                            "absolute_timelock" => {
+                                absolute_timelock =
+                                    Some(absolute::LockTime::from_consensus(map.next_value()?));
+                            }
+                            "relative_timelock" => {
+                                relative_timelock = Some(
+                                    relative::LockTime::from_consensus(map.next_value()?).map_err(
+                                        |e| {
+                                            A::Error::custom(format_args!(
+                                                "relative_timelock: {}",
+                                                e
+                                            ))
+                                        },
+                                    )?,
+                                );
+                            }
+                            "template" => template = Some(map.next_value()?),
+                            key => return Err(A::Error::unknown_field(key, PLAN_FIELDS)),
+                        }
+                    }
+                    let plan = Plan {
+                        template: template.ok_or_else(|| A::Error::missing_field("template"))?,
+                        absolute_timelock,
+                        relative_timelock,
+                        descriptor: descriptor
+                            .ok_or_else(|| A::Error::missing_field("descriptor"))?,
+                    };
+                    if !plan.is_planned_template() {
+                        return Err(A::Error::custom(PlanDecodeError::TemplateMismatch));
+                    }
+                    Ok(plan)
+                }
+            }
+
+            deserializer.deserialize_map(PlanVisitor)
+        }
     }
 }
 
@@ -1066,6 +3010,124 @@ mod test {
         test_inner(&desc, keys, hashes, tests);
     }
 
+    #[test]
+    fn plan_snapshot() {
+        let keys: Vec<_> = [
+            "02c2fd50ceae468857bb7eb32ae9cd4083e6c7e42fbbec179d81134b3e3830586c",
+            "0257f4a2816338436cccabc43aa724cf6e69e43e84c3c8a305212761389dd73a8a",
+        ]
+        .iter()
+        .map(|k| DescriptorPublicKey::from_str(k).unwrap())
+        .collect();
+        let desc = Descriptor::<DefiniteDescriptorKey>::from_str(&format!(
+            "wsh(or_d(pk({}),and_v(v:pk({}),older(10))))",
+            keys[0], keys[1]
+        ))
+        .unwrap();
+        let assets = Assets::new()
+            .add(keys.clone())
+            .older(relative::LockTime::from_height(10));
+        let snapshot = PlanSnapshot::new(desc.clone(), assets).unwrap();
+        let weight = snapshot.plan().satisfaction_weight();
+        assert!(snapshot.uses_key(&keys[0]));
+        assert!(!snapshot.uses_key(&keys[1]));
+
+        // Doubling the feerate doubles the fee of the satisfaction
+        let rate = FeeRate::from_sat_per_kwu(1000);
+        let double = FeeRate::from_sat_per_kwu(2000);
+        assert_eq!(snapshot.fee(rate), Some(Amount::from_sat(weight as u64)));
+        assert_eq!(snapshot.fee_delta(rate, double), Some(SignedAmount::from_sat(weight as i64)));
+
+        // Losing an unused key changes nothing
+        let diff = snapshot.without_key(&keys[1]);
+        assert!(!diff.replanned);
+        assert_eq!(diff.weight_delta, Some(0));
+
+        // Losing the used key falls back to the timelocked path: the signature of the
+        // second key replaces the one of the first, plus an empty dissatisfaction
+        let diff = snapshot.without_key(&keys[0]);
+        assert!(diff.replanned);
+        assert!(diff.plan.unwrap().relative_timelock.is_some());
+        assert_eq!(diff.weight_delta, Some(1));
+
+        // Without the timelock, there is no fallback
+        let snapshot = PlanSnapshot::new(desc.clone(), Assets::new().add(keys.clone())).unwrap();
+        let diff = snapshot.without_key(&keys[0]);
+        assert!(diff.plan.is_none());
+        assert_eq!(diff.weight_delta, None);
+
+        assert_eq!(
+            PlanSnapshot::new(desc, Assets::new().add(keys[1].clone())).unwrap_err(),
+            PlanError::InsufficientAssets
+        );
+    }
+
+    #[test]
+    fn test_plan_constraints() {
+        let keys: Vec<_> = [
+            "02c2fd50ceae468857bb7eb32ae9cd4083e6c7e42fbbec179d81134b3e3830586c",
+            "0257f4a2816338436cccabc43aa724cf6e69e43e84c3c8a305212761389dd73a8a",
+            "03500a2b48b0f66c8183cc0d6645ab21cc19c7fad8a33ff04d41c3ece54b0bc1c5",
+        ]
+        .iter()
+        .map(|k| DescriptorPublicKey::from_str(k).unwrap())
+        .collect();
+        let desc = Descriptor::<DefiniteDescriptorKey>::from_str(&format!(
+            "tr({},{{pk({}),pk({})}})",
+            keys[0], keys[1], keys[2]
+        ))
+        .unwrap();
+        let leaf_hashes: Vec<_> = desc
+            .as_tr()
+            .unwrap()
+            .iter_scripts()
+            .map(|(_, ms)| TapLeafHash::from_script(&ms.encode(), LeafVersion::TapScript))
+            .collect();
+        let all_keys = Assets::new().add(keys.clone());
+
+        // The key spend is the cheapest
+        let plan = desc.clone().plan(&all_keys).unwrap();
+        assert_eq!(plan.satisfaction_weight(), 70);
+
+        // Requiring a leaf excludes the key spend and the other leaves
+        let assets = Assets::new().add(keys.clone()).require_leaf(leaf_hashes[1]);
+        let plan = desc.clone().plan(&assets).unwrap();
+        assert!(matches!(
+            plan.template[0],
+            Placeholder::SchnorrSigPk(_, SchnorrSigType::ScriptSpend { leaf_hash }, _)
+                if leaf_hash == leaf_hashes[1]
+        ));
+        let assets = Assets::new()
+            .add(keys[0].clone())
+            .require_leaf(leaf_hashes[0]);
+        assert!(desc.clone().plan(&assets).is_err());
+
+        // Forbidding keys makes the planner use the next cheapest path
+        let assets = Assets::new()
+            .add(keys.clone())
+            .forbid_key(keys[0].clone())
+            .forbid_key(keys[2].clone());
+        let plan = desc.clone().plan(&assets).unwrap();
+        assert!(matches!(
+            plan.template[0],
+            Placeholder::SchnorrSigPk(_, SchnorrSigType::ScriptSpend { leaf_hash }, _)
+                if leaf_hash == leaf_hashes[0]
+        ));
+        let assets = all_keys
+            .forbid_key(keys[0].clone())
+            .forbid_key(keys[1].clone())
+            .forbid_key(keys[2].clone());
+        assert!(desc.clone().plan(&assets).is_err());
+
+        // Forbidden keys also apply outside of Taproot
+        let wsh = Descriptor::<DefiniteDescriptorKey>::from_str(&format!(
+            "wsh(or_d(pk({}),pk({})))",
+            keys[0], keys[1]
+        ))
+        .unwrap();
+        assert!(wsh.plan(&assets).is_err());
+    }
+
     #[test]
     fn test_hash() {
         let keys = vec![DescriptorPublicKey::from_str(
@@ -1159,4 +3221,754 @@ mod test {
         assert!(psbt_input.redeem_script.is_none(), "Redeem script present");
         assert_eq!(psbt_input.bip32_derivation.len(), 2, "Unexpected number of bip32_derivation");
     }
+
+    #[test]
+    fn test_usage_stats_rebalance() {
+        let keys: Vec<DescriptorPublicKey> = vec![
+            "02c2fd50ceae468857bb7eb32ae9cd4083e6c7e42fbbec179d81134b3e3830586c",
+            "0257f4a2816338436cccabc43aa724cf6e69e43e84c3c8a305212761389dd73a8a",
+            "03500a2b48b0f66c8183cc0d6645ab21cc19c7fad8a33ff04d41c3ece54b0bc1c5",
+            "033ad2d191da4f39512adbaac320cae1f12f298386a4e9d43fd98dec7cf5db2ac9",
+        ]
+        .into_iter()
+        .map(|k| DescriptorPublicKey::from_str(k).unwrap())
+        .collect();
+        let desc =
+            format!("tr({},{{pk({}),{{pk({}),pk({})}}}})", keys[0], keys[1], keys[2], keys[3]);
+        let desc = Descriptor::<DefiniteDescriptorKey>::from_str(&desc).unwrap();
+
+        let mut stats = UsageStats::new();
+        let deep_plan = desc
+            .clone()
+            .plan(&Assets::new().add(keys[3].clone()))
+            .unwrap();
+        let shallow_plan = desc
+            .clone()
+            .plan(&Assets::new().add(keys[1].clone()))
+            .unwrap();
+        let key_plan = desc
+            .clone()
+            .plan(&Assets::new().add(keys[0].clone()))
+            .unwrap();
+        assert!(key_plan.tap_leaf_hash().is_none());
+        for _ in 0..10 {
+            stats.record(&deep_plan);
+        }
+        stats.record(&shallow_plan);
+        stats.record(&key_plan);
+        assert_eq!(stats.key_spends, 1);
+        assert_eq!(stats.script_spends(), 11);
+
+        let tr = match desc {
+            Descriptor::Tr(ref tr) => tr,
+            _ => unreachable!(),
+        };
+        // current: (10 * 2 + 1 * 1) * 32 / 11 = 61, rebalanced: (10 * 1 + 1 * 2) * 32 / 11 = 34
+        let advice = tr
+            .rebalance_advice(&stats, bitcoin::Weight::from_wu(10))
+            .expect("rebalancing saves weight");
+        assert_eq!(advice.current_weight.to_wu(), 61);
+        assert_eq!(advice.rebalanced_weight.to_wu(), 34);
+        assert_eq!(
+            advice
+                .tree
+                .iter()
+                .find(|(d, _)| *d == 1)
+                .unwrap()
+                .1
+                .to_string(),
+            format!("pk({})", keys[3])
+        );
+        assert!(tr
+            .rebalance_advice(&stats, bitcoin::Weight::from_wu(30))
+            .is_none());
+        assert!(tr
+            .rebalance_advice(&UsageStats::new(), bitcoin::Weight::ZERO)
+            .is_none());
+    }
+
+    #[test]
+    fn test_partial_witness() {
+        let secp = bitcoin::secp256k1::Secp256k1::new();
+        let sk = bitcoin::secp256k1::SecretKey::from_slice(&[1; 32]).unwrap();
+        let pk =
+            bitcoin::PublicKey::new(bitcoin::secp256k1::PublicKey::from_secret_key(&secp, &sk));
+        let msg = bitcoin::secp256k1::Message::from_digest([2; 32]);
+        let sig = bitcoin::ecdsa::Signature {
+            signature: secp.sign_ecdsa(&msg, &sk),
+            sighash_type: bitcoin::sighash::EcdsaSighashType::All,
+        };
+        let preimage = [3; 32];
+        let hash = sha256::Hash::hash(&preimage);
+
+        let key = DefiniteDescriptorKey::from_str(&pk.to_string()).unwrap();
+        let desc = Descriptor::<DefiniteDescriptorKey>::from_str(&format!(
+            "wsh(and_v(v:pk({}),sha256({})))",
+            key, hash
+        ))
+        .unwrap();
+        let plan = desc
+            .plan(
+                &Assets::new()
+                    .add(key.as_descriptor_public_key().clone())
+                    .add(hash),
+            )
+            .unwrap();
+
+        // The first party only knows the preimage
+        struct PreimageSat(sha256::Hash, Preimage32);
+        impl Satisfier<DefiniteDescriptorKey> for PreimageSat {
+            fn lookup_sha256(&self, h: &sha256::Hash) -> Option<Preimage32> {
+                if *h == self.0 {
+                    Some(self.1)
+                } else {
+                    None
+                }
+            }
+        }
+        let partial = plan.partial_satisfy(&PreimageSat(hash, preimage));
+        assert!(!partial.is_complete());
+        assert_eq!(
+            partial.missing().collect::<Vec<_>>(),
+            vec![&Placeholder::EcdsaSigPk(key.clone())]
+        );
+        assert!(plan.complete_partial(partial.clone(), &()).is_err());
+
+        // The second party receives it and adds the signature
+        let bytes = partial.serialize();
+        let received = plan.partial_witness_from_slice(&bytes).unwrap();
+        assert_eq!(received, partial);
+        assert!(matches!(
+            plan.partial_witness_from_slice(&bytes[..bytes.len() - 1]),
+            Err(PartialWitnessError::Decode(_))
+        ));
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert!(matches!(
+            plan.partial_witness_from_slice(&trailing),
+            Err(PartialWitnessError::TrailingBytes(1))
+        ));
+
+        let mut sigs = BTreeMap::new();
+        sigs.insert(key, sig);
+        let (witness, script_sig) = plan.complete_partial(received, &sigs).unwrap();
+        assert!(script_sig.is_empty());
+        assert_eq!(witness, vec![preimage.to_vec(), sig.to_vec(),]);
+    }
+
+    #[test]
+    fn test_preimage_store() {
+        let key = "0257f4a2816338436cccabc43aa724cf6e69e43e84c3c8a305212761389dd73a8a";
+        let mut store = PreimageStore::new();
+        assert_eq!(store.insert(&[1; 20]), Err(PreimageError::InvalidLength(20)));
+        assert!(matches!(store.insert_hex("zz"), Err(PreimageError::InvalidHex(_))));
+        store.insert(&[1; 32]).unwrap();
+        store.insert_hex(&[2; 32].to_lower_hex_string()).unwrap();
+        assert_eq!(store.len(), 2);
+
+        let hash = hash160::Hash::hash(&[2; 32]);
+        let desc = Descriptor::<DefiniteDescriptorKey>::from_str(&format!(
+            "wsh(and_v(v:pk({}),hash160({})))",
+            key, hash
+        ))
+        .unwrap();
+        let assets = Assets::new().add(DescriptorPublicKey::from_str(key).unwrap());
+        assert!(desc.clone().plan(&assets).is_err());
+        let plan = desc.plan(&assets.add(&store)).unwrap();
+
+        let sig = bitcoin::ecdsa::Signature::sighash_all(
+            secp256k1::ecdsa::Signature::from_compact(&[1; 64]).unwrap(),
+        );
+        let mut sigs = BTreeMap::new();
+        sigs.insert(DefiniteDescriptorKey::from_str(key).unwrap(), sig);
+        let (witness, _) = plan.satisfy(&(&sigs, &store)).unwrap();
+        assert_eq!(witness[0], [2; 32]);
+        assert_eq!(witness[1], sig.to_vec());
+
+        assert!(store.remove(&[2; 32]));
+        assert!(!store.remove(&[2; 32]));
+        assert!(plan.satisfy(&(&sigs, &store)).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn test_preimage_store_serde() {
+        use serde_test::{assert_de_tokens_error, assert_tokens, Token};
+
+        let mut store = PreimageStore::new();
+        store.insert(&[1; 32]).unwrap();
+        assert_tokens(
+            &store,
+            &[
+                Token::Seq { len: Some(1) },
+                Token::Str("0101010101010101010101010101010101010101010101010101010101010101"),
+                Token::SeqEnd,
+            ],
+        );
+        assert_de_tokens_error::<PreimageStore>(
+            &[
+                Token::Seq { len: Some(1) },
+                Token::Str("0101"),
+                Token::SeqEnd,
+            ],
+            "preimage is 2 bytes long, hash locks require 32 bytes",
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn test_plan_serde() {
+        use serde_test::{assert_tokens, Token};
+
+        let key = "0257f4a2816338436cccabc43aa724cf6e69e43e84c3c8a305212761389dd73a8a";
+        let plan = Descriptor::<DefiniteDescriptorKey>::from_str(&format!(
+            "wsh(and_v(v:pk({}),older(5)))",
+            key
+        ))
+        .unwrap()
+        .plan(
+            &Assets::new()
+                .add(DescriptorPublicKey::from_str(key).unwrap())
+                .older(relative::LockTime::from_height(5)),
+        )
+        .unwrap();
+        assert_tokens(
+            &plan,
+            &[
+                Token::Map { len: Some(3) },
+                Token::Str("descriptor"),
+                Token::Str("wsh(and_v(v:pk(0257f4a2816338436cccabc43aa724cf6e69e43e84c3c8a305212761389dd73a8a),older(5)))#0n58nhy3"),
+                Token::Str("relative_timelock"),
+                Token::U32(5),
+                Token::Str("template"),
+                Token::Seq { len: Some(1) },
+                Token::Map { len: None },
+                Token::Str("type"),
+                Token::Str("ecdsa_sig_pk"),
+                Token::Str("key"),
+                Token::Str("0257f4a2816338436cccabc43aa724cf6e69e43e84c3c8a305212761389dd73a8a"),
+                Token::MapEnd,
+                Token::SeqEnd,
+                Token::MapEnd,
+            ],
+        );
+
+        // Script spends keep the leaf, its control block and the leaf hash of the signatures
+        let pk = DefiniteDescriptorKey::from_str(key).unwrap();
+        let leaf_hash = TapLeafHash::from_byte_array([1; 32]);
+        assert_tokens(
+            &Placeholder::SchnorrSigPk(pk, SchnorrSigType::ScriptSpend { leaf_hash }, 65),
+            &[
+                Token::Map { len: None },
+                Token::Str("type"),
+                Token::Str("schnorr_sig_pk"),
+                Token::Str("key"),
+                Token::Str("0257f4a2816338436cccabc43aa724cf6e69e43e84c3c8a305212761389dd73a8a"),
+                Token::Str("kind"),
+                Token::Str("tap_script_spend"),
+                Token::Str("leaf_hash"),
+                Token::Str("0101010101010101010101010101010101010101010101010101010101010101"),
+                Token::Str("size"),
+                Token::Str("65"),
+                Token::MapEnd,
+            ],
+        );
+        assert_tokens(
+            &Placeholder::<DefiniteDescriptorKey>::TapScript(ScriptBuf::from_hex("51").unwrap()),
+            &[
+                Token::Map { len: None },
+                Token::Str("type"),
+                Token::Str("tap_script"),
+                Token::Str("script"),
+                Token::Str("51"),
+                Token::MapEnd,
+            ],
+        );
+        let control_block = "c057f4a2816338436cccabc43aa724cf6e69e43e84c3c8a305212761389dd73a8a";
+        assert_tokens(
+            &Placeholder::<DefiniteDescriptorKey>::TapControlBlock(
+                ControlBlock::decode(
+                    &<Vec<u8> as bitcoin::hex::FromHex>::from_hex(control_block).unwrap(),
+                )
+                .unwrap(),
+            ),
+            &[
+                Token::Map { len: None },
+                Token::Str("type"),
+                Token::Str("tap_control_block"),
+                Token::Str("control_block"),
+                Token::Str(control_block),
+                Token::MapEnd,
+            ],
+        );
+    }
+
+    #[test]
+    fn test_plan_serialization() {
+        let keys = [
+            "[d34db33f/0']02c2fd50ceae468857bb7eb32ae9cd4083e6c7e42fbbec179d81134b3e3830586c",
+            "0257f4a2816338436cccabc43aa724cf6e69e43e84c3c8a305212761389dd73a8a",
+            "03500a2b48b0f66c8183cc0d6645ab21cc19c7fad8a33ff04d41c3ece54b0bc1c5",
+        ];
+        let key = |i: usize| DescriptorPublicKey::from_str(keys[i]).unwrap();
+        let hash = sha256::Hash::hash(&[3; 32]);
+        let roundtrip = |desc: &str, assets: &Assets| {
+            let desc = desc
+                .replace("K0", keys[0])
+                .replace("K1", keys[1])
+                .replace("K2", keys[2]);
+            let plan = Descriptor::<DefiniteDescriptorKey>::from_str(&desc)
+                .unwrap()
+                .plan(assets)
+                .unwrap();
+            let bytes = plan.serialize();
+            let decoded = Plan::deserialize(&bytes).unwrap();
+            assert_eq!(decoded.template, plan.template);
+            assert_eq!(decoded.descriptor, plan.descriptor);
+            assert_eq!(decoded.absolute_timelock, plan.absolute_timelock);
+            assert_eq!(decoded.relative_timelock, plan.relative_timelock);
+
+            let text = plan.to_qr_string();
+            assert!(text.starts_with("MPLAN1"));
+            assert!(text
+                .chars()
+                .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit()));
+            assert_eq!(Plan::from_qr_string(&text).unwrap().serialize(), bytes);
+            assert_eq!(
+                Plan::from_qr_string(&text.to_lowercase())
+                    .unwrap()
+                    .serialize(),
+                bytes
+            );
+            (plan, bytes)
+        };
+
+        // Script spend, with a tap script and a control block
+        let tr = "tr(K0,{pk(K1),and_v(v:pk(K2),after(10))})";
+        let (plan, _) = roundtrip(
+            tr,
+            &Assets::new()
+                .add(key(2))
+                .after(absolute::LockTime::from_consensus(10)),
+        );
+        assert!(plan
+            .template
+            .iter()
+            .any(|p| matches!(p, Placeholder::TapControlBlock(_))));
+        // Key spend, keeping the key origin
+        let (mut plan, _) = roundtrip(tr, &Assets::new().add(key(0)));
+        match plan.template[..] {
+            [Placeholder::SchnorrSigPk(
+                ref pk,
+                SchnorrSigType::KeySpend { merkle_root: Some(_) },
+                _,
+            )] => {
+                assert_eq!(pk.to_string(), keys[0])
+            }
+            ref template => panic!("unexpected template {:?}", template),
+        }
+        // A template the descriptor can't produce is rejected
+        if let Placeholder::SchnorrSigPk(ref mut pk, _, _) = plan.template[0] {
+            *pk = DefiniteDescriptorKey::from_str(keys[1]).unwrap();
+        }
+        assert!(matches!(
+            Plan::deserialize(&plan.serialize()),
+            Err(PlanDecodeError::TemplateMismatch)
+        ));
+        let (mut plan, bytes) = roundtrip(
+            "sh(wsh(and_v(v:pkh(K1),and_v(v:sha256(H),older(5)))))"
+                .replace('H', &hash.to_string())
+                .as_str(),
+            &Assets::new()
+                .add(key(1))
+                .add(hash)
+                .older(relative::LockTime::from_height(5)),
+        );
+
+        plan.relative_timelock = Some(relative::LockTime::from_height(4));
+        assert!(matches!(
+            Plan::deserialize(&plan.serialize()),
+            Err(PlanDecodeError::TemplateMismatch)
+        ));
+
+        // Corrupted, truncated and unknown versions of the encoding are rejected
+        let mut corrupted = bytes.clone();
+        corrupted[10] ^= 1;
+        assert!(matches!(Plan::deserialize(&corrupted), Err(PlanDecodeError::ChecksumMismatch)));
+        assert!(matches!(
+            Plan::deserialize(&bytes[..bytes.len() - 1]),
+            Err(PlanDecodeError::ChecksumMismatch)
+        ));
+        let mut future = bytes[..bytes.len() - 4].to_vec();
+        future[0] = 2;
+        let checksum = bitcoin::hashes::sha256d::Hash::hash(&future);
+        future.extend_from_slice(&checksum[..4]);
+        assert!(matches!(
+            Plan::deserialize(&future),
+            Err(PlanDecodeError::UnsupportedVersion(2))
+        ));
+        let other_prefix = bech32::encode_upper::<bech32::NoChecksum>(
+            bech32::Hrp::parse_unchecked("psbt"),
+            &bytes,
+        )
+        .unwrap();
+        assert!(matches!(Plan::from_qr_string(&other_prefix), Err(PlanDecodeError::WrongPrefix)));
+    }
+
+    #[test]
+    fn test_satisfy_with_review() {
+        let secp = bitcoin::secp256k1::Secp256k1::new();
+        let sk = bitcoin::secp256k1::SecretKey::from_slice(&[1; 32]).unwrap();
+        let pk =
+            bitcoin::PublicKey::new(bitcoin::secp256k1::PublicKey::from_secret_key(&secp, &sk));
+        let msg = bitcoin::secp256k1::Message::from_digest([2; 32]);
+        let sig = bitcoin::ecdsa::Signature {
+            signature: secp.sign_ecdsa(&msg, &sk),
+            sighash_type: bitcoin::sighash::EcdsaSighashType::All,
+        };
+        let preimage = [3; 32];
+        let hash = sha256::Hash::hash(&preimage);
+
+        let key = DefiniteDescriptorKey::from_str(&pk.to_string()).unwrap();
+        let other = DefiniteDescriptorKey::from_str(
+            "02c2fd50ceae468857bb7eb32ae9cd4083e6c7e42fbbec179d81134b3e3830586c",
+        )
+        .unwrap();
+        let desc = Descriptor::<DefiniteDescriptorKey>::from_str(&format!(
+            "wsh(or_d(pk({}),and_v(v:pk({}),sha256({}))))",
+            other, key, hash
+        ))
+        .unwrap();
+
+        let mut sigs = BTreeMap::new();
+        sigs.insert(key.clone(), sig);
+        let mut preimages = BTreeMap::new();
+        preimages.insert(hash, preimage);
+        struct PreimageSat(BTreeMap<sha256::Hash, Preimage32>);
+        impl Satisfier<DefiniteDescriptorKey> for PreimageSat {
+            fn lookup_sha256(&self, h: &sha256::Hash) -> Option<Preimage32> {
+                self.0.get(h).copied()
+            }
+        }
+        let satisfier = (&sigs, PreimageSat(preimages));
+
+        let mut reviewed = vec![];
+        let result = desc.satisfy_with_review(&satisfier, |plan| {
+            reviewed = plan.requirements().cloned().collect();
+            false
+        });
+        assert!(matches!(result, Err(Error::SatisfactionRejected)));
+        assert_eq!(
+            reviewed,
+            vec![
+                Placeholder::Sha256Preimage(hash),
+                Placeholder::EcdsaSigPk(key.clone())
+            ]
+        );
+
+        let (witness, script_sig) = desc.satisfy_with_review(&satisfier, |_| true).unwrap();
+        assert_eq!((witness, script_sig), desc.get_satisfaction(&satisfier).unwrap());
+
+        // Nothing is reviewed if the descriptor can't be satisfied
+        let result = desc.satisfy_with_review(&sigs, |_| panic!("nothing to review"));
+        assert!(matches!(result, Err(Error::CouldNotSatisfy)));
+    }
+
+    #[test]
+    fn test_chain_tip() {
+        let key = DescriptorPublicKey::from_str(
+            "02c2fd50ceae468857bb7eb32ae9cd4083e6c7e42fbbec179d81134b3e3830586c",
+        )
+        .unwrap();
+        let desc = format!(
+            "wsh(or_i(and_v(v:pk({}),after(100)),and_v(v:pk({}),after(500000100))))",
+            key, key
+        );
+        let desc = Descriptor::<DefiniteDescriptorKey>::from_str(&desc).unwrap();
+
+        let tip = |height, mtp| {
+            ChainTip::new(
+                absolute::Height::from_consensus(height).unwrap(),
+                absolute::Time::from_consensus(mtp).unwrap(),
+            )
+        };
+        let plan_after = |assets: Assets| {
+            desc.clone()
+                .plan(&assets.add(key.clone()))
+                .ok()
+                .map(|plan| plan.absolute_timelock.unwrap().to_consensus_u32())
+        };
+
+        // Height-based locktimes are final once the next block's height is above them
+        assert_eq!(plan_after(Assets::new().chain_tip(tip(99, 500000000))), None);
+        assert_eq!(plan_after(Assets::new().chain_tip(tip(100, 500000000))), Some(100));
+        // Time-based locktimes need to be below the median time past
+        assert_eq!(plan_after(Assets::new().chain_tip(tip(0, 500000100))), None);
+        assert_eq!(plan_after(Assets::new().chain_tip(tip(0, 500000101))), Some(500000100));
+        // Both the chain and the maximum timelock must allow it
+        let lt = absolute::LockTime::from_consensus(500000200);
+        assert_eq!(plan_after(Assets::new().after(lt).chain_tip(tip(100, 500000000))), None);
+        assert_eq!(
+            plan_after(Assets::new().after(lt).chain_tip(tip(100, 500000101))),
+            Some(500000100)
+        );
+
+        assert!(AbsLockTime::from_consensus(100)
+            .unwrap()
+            .is_satisfied_by_chain(&tip(100, 500000000)));
+        assert!(Satisfier::<DefiniteDescriptorKey>::check_after(
+            &tip(100, 500000000),
+            absolute::LockTime::from_consensus(100)
+        ));
+    }
+
+    #[test]
+    fn test_tx_plan() {
+        let keys = [
+            "02c2fd50ceae468857bb7eb32ae9cd4083e6c7e42fbbec179d81134b3e3830586c",
+            "0257f4a2816338436cccabc43aa724cf6e69e43e84c3c8a305212761389dd73a8a",
+        ];
+        let desc = |s: &str| {
+            let s = s.replace("K0", keys[0]).replace("K1", keys[1]);
+            Descriptor::<DefiniteDescriptorKey>::from_str(&s).unwrap()
+        };
+        let assets = Assets::new()
+            .add(DescriptorPublicKey::from_str(keys[0]).unwrap())
+            .add(DescriptorPublicKey::from_str(keys[1]).unwrap())
+            .chain_tip(ChainTip::new(
+                absolute::Height::from_consensus(1000).unwrap(),
+                absolute::Time::from_consensus(1_800_000_000).unwrap(),
+            ));
+        let older = desc("wsh(and_v(v:pk(K0),older(10)))");
+        let height = desc("wsh(and_v(v:pk(K0),after(100)))");
+        let time = desc("wsh(and_v(v:pk(K1),after(1700000000)))");
+        // The time-based path is the cheapest one
+        let both = desc("wsh(or_i(and_v(v:pk(K1),after(1700000000)),and_v(v:pkh(K1),after(200))))");
+
+        let tx = TxPlan::new(&[(both.clone(), assets.clone())]).unwrap();
+        assert_eq!(tx.lock_time, absolute::LockTime::from_consensus(1700000000));
+
+        // The other input forces the height-based path
+        let inputs = [
+            (height.clone(), assets.clone()),
+            (both, assets.clone()),
+            (older, assets.clone().older(relative::LockTime::from_height(10))),
+        ];
+        let tx = TxPlan::new(&inputs).unwrap();
+        assert_eq!(tx.lock_time, absolute::LockTime::from_consensus(200));
+        assert_eq!(tx.plans[0].absolute_timelock, Some(absolute::LockTime::from_consensus(100)));
+        assert_eq!(tx.sequence(0), Some(Sequence::ENABLE_LOCKTIME_NO_RBF));
+        assert_eq!(tx.sequence(2), Some(Sequence::from_height(10)));
+        assert_eq!(tx.sequence(3), None);
+
+        let inputs = [(height, assets.clone()), (time.clone(), assets.clone())];
+        assert_eq!(
+            TxPlan::new(&inputs).unwrap_err(),
+            TxPlanError::ConflictingLockTimes {
+                heights: vec![(0, absolute::LockTime::from_consensus(100))],
+                times: vec![(1, absolute::LockTime::from_consensus(1700000000))],
+            }
+        );
+        assert_eq!(
+            TxPlan::new(&[(time, Assets::new())]).unwrap_err(),
+            TxPlanError::InsufficientAssets(0)
+        );
+    }
+
+    #[test]
+    fn test_sweep() {
+        let keys = [
+            "02c2fd50ceae468857bb7eb32ae9cd4083e6c7e42fbbec179d81134b3e3830586c",
+            "0257f4a2816338436cccabc43aa724cf6e69e43e84c3c8a305212761389dd73a8a",
+        ];
+        let desc = |s: &str| {
+            let s = s.replace("K0", keys[0]).replace("K1", keys[1]);
+            Descriptor::<DefiniteDescriptorKey>::from_str(&s).unwrap()
+        };
+        let utxo = |desc: &Descriptor<DefiniteDescriptorKey>, vout: u32, sats: u64| {
+            let outpoint = OutPoint::new(bitcoin::Txid::from_byte_array([vout as u8; 32]), vout);
+            let txout =
+                TxOut { value: Amount::from_sat(sats), script_pubkey: desc.script_pubkey() };
+            (outpoint, txout, desc.clone())
+        };
+        let assets = Assets::new()
+            .add(DescriptorPublicKey::from_str(keys[0]).unwrap())
+            .add(DescriptorPublicKey::from_str(keys[1]).unwrap())
+            .older(relative::LockTime::from_height(10))
+            .after(absolute::LockTime::from_consensus(100));
+        let wpkh = desc("wpkh(K0)");
+        let feerate = FeeRate::from_sat_per_kwu(250);
+
+        let sweep =
+            Sweep::new(&[utxo(&wpkh, 0, 10_000)], &assets, wpkh.script_pubkey(), feerate).unwrap();
+        // 82 bytes of unsigned transaction, minus the empty script sig, plus the
+        // satisfaction weight, the segwit marker and flag
+        assert_eq!(sweep.weight, Weight::from_wu(328 - 4 + 112 + 2));
+        assert_eq!(sweep.fee, Amount::from_sat(110));
+        assert_eq!(sweep.tx.output[0].value, Amount::from_sat(10_000 - 110));
+        assert_eq!(sweep.tx.version, transaction::Version::TWO);
+
+        let older = desc("wsh(and_v(v:pk(K1),older(10)))");
+        let after = desc("sh(and_v(v:pk(K0),after(100)))");
+        let utxos = [
+            utxo(&wpkh, 0, 10_000),
+            utxo(&older, 1, 20_000),
+            utxo(&after, 2, 30_000),
+        ];
+        let sweep = Sweep::new(&utxos, &assets, wpkh.script_pubkey(), feerate).unwrap();
+        assert_eq!(sweep.tx.lock_time, absolute::LockTime::from_consensus(100));
+        assert_eq!(sweep.tx.input[1].sequence, Sequence::from_height(10));
+        assert_eq!(sweep.tx.input[2].sequence, Sequence::ENABLE_LOCKTIME_NO_RBF);
+        let satisfaction: usize = sweep.plans.iter().map(Plan::satisfaction_weight).sum();
+        // The legacy input has an empty witness
+        assert_eq!(
+            sweep.weight.to_wu() as usize,
+            sweep.tx.weight().to_wu() as usize - 4 * 3 + satisfaction + 2 + 1
+        );
+        assert_eq!(sweep.tx.output[0].value + sweep.fee, Amount::from_sat(60_000));
+
+        let psbt = sweep.clone().into_psbt();
+        assert_eq!(psbt.unsigned_tx, sweep.tx);
+        assert_eq!(psbt.inputs[1].witness_utxo, Some(utxos[1].1.clone()));
+        assert!(psbt.inputs[1].witness_script.is_some());
+        assert!(psbt.inputs[2].redeem_script.is_some());
+
+        assert_eq!(
+            Sweep::new(&[utxo(&wpkh, 0, 400)], &assets, wpkh.script_pubkey(), feerate).unwrap_err(),
+            SweepError::InsufficientValue {
+                total: Amount::from_sat(400),
+                fee: Amount::from_sat(110)
+            }
+        );
+        assert_eq!(
+            Sweep::new(&[], &assets, wpkh.script_pubkey(), feerate).unwrap_err(),
+            SweepError::NoInputs
+        );
+        assert_eq!(
+            Sweep::new(&utxos, &Assets::new(), wpkh.script_pubkey(), feerate).unwrap_err(),
+            SweepError::Plan(TxPlanError::InsufficientAssets(0))
+        );
+    }
+
+    #[test]
+    fn test_plan_with_budget() {
+        let keys = [
+            "02c2fd50ceae468857bb7eb32ae9cd4083e6c7e42fbbec179d81134b3e3830586c",
+            "0257f4a2816338436cccabc43aa724cf6e69e43e84c3c8a305212761389dd73a8a",
+        ];
+        let desc = Descriptor::<DefiniteDescriptorKey>::from_str(&format!(
+            "wsh(or_d(pk({}),pk({})))",
+            keys[0], keys[1]
+        ))
+        .unwrap();
+        let assets = Assets::new().add(DescriptorPublicKey::from_str(keys[1]).unwrap());
+        // The search visits or_d, and c:pk_k of both keys to satisfy it and of the first one
+        // to dissatisfy it
+        let budget = PlanBudget { max_nodes: 6 };
+        let err = desc.clone().plan_with_budget(&assets, budget).unwrap_err();
+        assert_eq!(err.1, PlanError::BudgetExceeded { max_nodes: 6 });
+        assert_eq!(err.0, desc);
+        let plan = desc
+            .clone()
+            .plan_with_budget(&assets, PlanBudget { max_nodes: 7 })
+            .unwrap();
+        assert_eq!(plan, desc.clone().plan(&assets).unwrap());
+        assert!(desc
+            .clone()
+            .plan_mall_with_budget(&assets, PlanBudget::default())
+            .is_ok());
+        let err = desc
+            .plan_with_budget(&Assets::new(), PlanBudget::default())
+            .unwrap_err();
+        assert_eq!(err.1, PlanError::InsufficientAssets);
+
+        // Thresholds are given up on while searching
+        let xpub = "xpub6BgBgsespWvERF3LHQu6CnqdvfEvtMcQjYrcRzx53QJjSxarj2afYWcLteoGVky7D3UKDP9QyrLprQ3VCECoY49yfdDEHGCtMMj92pReUsQ";
+        let subs: Vec<_> = (1..20).map(|i| format!("s:pk({}/{})", xpub, i)).collect();
+        let desc = Descriptor::<DefiniteDescriptorKey>::from_str(&format!(
+            "wsh(thresh(10,pk({}/0),{}))",
+            xpub,
+            subs.join(",")
+        ))
+        .unwrap();
+        let assets = Assets::new().add(DescriptorPublicKey::from_str(xpub).unwrap());
+        let err = desc
+            .clone()
+            .plan_with_budget(&assets, PlanBudget { max_nodes: 50 })
+            .unwrap_err();
+        assert_eq!(err.1, PlanError::BudgetExceeded { max_nodes: 50 });
+        assert!(desc
+            .plan_with_budget(&assets, PlanBudget::default())
+            .is_ok());
+    }
+
+    #[test]
+    fn test_spendable_balance_classifier() {
+        let xpub = "xpub6BgBgsespWvERF3LHQu6CnqdvfEvtMcQjYrcRzx53QJjSxarj2afYWcLteoGVky7D3UKDP9QyrLprQ3VCECoY49yfdDEHGCtMMj92pReUsQ";
+        let desc = Descriptor::<DescriptorPublicKey>::from_str(&format!(
+            "wsh(or_i(and_v(v:pk({}/0/*),older(10)),and_v(v:pk({}/1/*),after(200))))",
+            xpub, xpub
+        ))
+        .unwrap();
+        let tip = |height| {
+            ChainTip::new(absolute::Height::from_consensus(height).unwrap(), absolute::Time::MIN)
+        };
+        let utxo = |index: u32, confirmed_on: Option<u32>| WalletUtxo {
+            outpoint: OutPoint::new(bitcoin::Txid::all_zeros(), index),
+            txout: TxOut {
+                value: bitcoin::Amount::from_sat(1000),
+                script_pubkey: desc.at_derivation_index(index).unwrap().script_pubkey(),
+            },
+            derivation_index: index,
+            confirmed_on: confirmed_on.map(tip),
+        };
+        let utxos = vec![utxo(0, Some(95)), utxo(1, Some(100)), utxo(2, None)];
+        let key = |path| DescriptorPublicKey::from_str(&format!("{}/{}/*", xpub, path)).unwrap();
+
+        // Only the relative timelock path is available
+        let assets = Assets::new().add(key(0));
+        let classes = desc
+            .spendable_balance_classifier(&utxos, tip(105), &assets)
+            .unwrap();
+        assert!(matches!(classes[0].1, Spendability::Now(_)));
+        match classes[1].1 {
+            Spendability::Later(ref plan) => {
+                assert_eq!(plan.relative_timelock, Some(relative::LockTime::from_height(10)))
+            }
+            ref s => panic!("unexpected {:?}", s),
+        }
+        assert!(matches!(classes[2].1, Spendability::Later(_)));
+        assert_eq!(classes[2].0, utxos[2].outpoint);
+
+        // Only the absolute timelock path is available
+        let assets = Assets::new().add(key(1));
+        let classes = desc
+            .spendable_balance_classifier(&utxos, tip(199), &assets)
+            .unwrap();
+        assert!(classes
+            .iter()
+            .all(|(_, s)| matches!(s, Spendability::Later(_))));
+        let classes = desc
+            .spendable_balance_classifier(&utxos, tip(200), &assets)
+            .unwrap();
+        assert!(classes
+            .iter()
+            .all(|(_, s)| matches!(s, Spendability::Now(_))));
+
+        // No assets
+        let classes = desc
+            .spendable_balance_classifier(&utxos, tip(200), &Assets::new())
+            .unwrap();
+        assert!(classes
+            .iter()
+            .all(|(_, s)| matches!(s, Spendability::Never)));
+
+        // Output not locked by the descriptor at this index
+        let mut foreign = utxo(3, None);
+        foreign.derivation_index = 4;
+        assert!(matches!(
+            desc.spendable_balance_classifier(&[foreign], tip(200), &Assets::new()),
+            Err(ClassifyError::ScriptMismatch(_, 4))
+        ));
+    }
 }
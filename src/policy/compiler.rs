@@ -9,6 +9,10 @@ use core::{cmp, f64, fmt, hash, mem};
 #[cfg(feature = "std")]
 use std::error;
 
+use bitcoin::hashes::{sha256, Hash};
+use bitcoin::key::XOnlyPublicKey;
+use bitcoin::secp256k1;
+use bitcoin::taproot::TAPROOT_CONTROL_MAX_NODE_COUNT;
 use sync::Arc;
 
 use crate::miniscript::context::SigType;
@@ -18,8 +22,160 @@ use crate::policy::Concrete;
 use crate::prelude::*;
 use crate::{policy, Miniscript, MiniscriptKey, Terminal};
 
-type PolicyCache<Pk, Ctx> =
-    BTreeMap<(Concrete<Pk>, OrdF64, Option<OrdF64>), BTreeMap<CompilationKey, AstElemExt<Pk, Ctx>>>;
+/// Default for [`KeyFragment::PreferHash::max_overhead`]: the size of a single
+/// compressed public key push, i.e. the extra witness data a `pk_h` needs when
+/// it is always satisfied.
+pub const DEFAULT_PKH_MAX_OVERHEAD: usize = 34;
+
+/// Strategy used by the compiler to choose between `pk_k` and `pk_h` for
+/// single-key sub-policies.
+///
+/// A `pk_h` only commits to the hash of its public key, so the key is not
+/// revealed on chain until that branch is spent. This costs an extra public
+/// key push in the witness, partially offset by a smaller script.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Hash, Default)]
+pub enum KeyFragment {
+    /// Use whichever fragment results in the cheapest compilation.
+    #[default]
+    Cheapest,
+    /// Use `pk_h` unless its expected cost exceeds the one of `pk_k` by more
+    /// than `max_overhead` bytes.
+    ///
+    /// The expected cost accounts for the script size and for the satisfaction
+    /// and dissatisfaction sizes weighted by their probabilities. Multi-key
+    /// fragments such as `multi` are not affected.
+    PreferHash {
+        /// Maximum expected cost, in bytes, that `pk_h` may add over `pk_k`.
+        max_overhead: usize,
+    },
+}
+
+impl KeyFragment {
+    /// Prefers `pk_h` with the default overhead threshold,
+    /// [`DEFAULT_PKH_MAX_OVERHEAD`].
+    pub fn prefer_hash() -> Self {
+        KeyFragment::PreferHash { max_overhead: DEFAULT_PKH_MAX_OVERHEAD }
+    }
+}
+
+/// Strategy used to choose the internal key of a Taproot compilation, see
+/// [`TrCompileOptions`].
+#[derive(Clone, PartialEq, Eq, Debug, Hash)]
+pub enum InternalKeyStrategy<Pk: MiniscriptKey> {
+    /// Promote the most likely single key of the root-level disjunctions, or use
+    /// the unspendable key if there is none.
+    MostLikely,
+    /// Promote this key, which must be a single key of the root-level
+    /// disjunctions.
+    Key(Pk),
+    /// Never promote a key of the policy, always use the unspendable key.
+    Unspendable,
+}
+
+/// Weighting of the leaves when building the tap tree of a Taproot compilation,
+/// see [`TrCompileOptions`].
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Hash)]
+pub enum LeafWeighting {
+    /// Build a Huffman tree from the probabilities derived from the odds of the
+    /// policy, so that likely leaves are the cheapest to spend.
+    Odds,
+    /// Ignore the odds and build a balanced tree, so that no leaf is revealed as
+    /// more likely than the others by its depth.
+    Uniform,
+}
+
+/// Settings of a Taproot compilation, see [`Concrete::compile_tr_with_options`].
+#[derive(Clone, PartialEq, Eq, Debug, Hash)]
+pub struct TrCompileOptions<Pk: MiniscriptKey> {
+    /// How the internal key is chosen.
+    pub internal_key: InternalKeyStrategy<Pk>,
+    /// The internal key used when no key of the policy is promoted, e.g. a NUMS
+    /// point from [`nums_point`] or [`nums_point_with_seed`].
+    pub unspendable_key: Option<Pk>,
+    /// The maximum depth of the tap tree. If the weighted tree is deeper, a
+    /// balanced tree is built instead.
+    pub max_depth: usize,
+    /// How the leaves are weighted.
+    pub leaf_weighting: LeafWeighting,
+    /// Fragments of the policy put in tap leaves of their own: the root-level
+    /// disjunctions aren't split through them, and no key among them is
+    /// promoted to the internal key. Each must be reached from the root of the
+    /// policy through disjunctions only.
+    pub own_leaves: Vec<Concrete<Pk>>,
+}
+
+impl<Pk: MiniscriptKey> TrCompileOptions<Pk> {
+    /// The settings of [`Concrete::compile_tr`]: promote the most likely key,
+    /// weight the leaves by their odds and allow the consensus maximum depth.
+    pub fn new(unspendable_key: Option<Pk>) -> Self {
+        TrCompileOptions {
+            internal_key: InternalKeyStrategy::MostLikely,
+            unspendable_key,
+            max_depth: TAPROOT_CONTROL_MAX_NODE_COUNT,
+            leaf_weighting: LeafWeighting::Odds,
+            own_leaves: vec![],
+        }
+    }
+}
+
+impl<Pk: MiniscriptKey> Default for TrCompileOptions<Pk> {
+    fn default() -> Self { TrCompileOptions::new(None) }
+}
+
+/// The x coordinate of the NUMS point `H` of BIP 341.
+const NUMS_POINT: [u8; 32] = [
+    0x50, 0x92, 0x9b, 0x74, 0xc1, 0xa0, 0x49, 0x54, 0xb7, 0x8b, 0x4b, 0x60, 0x35, 0xe9, 0x7a, 0x5e,
+    0x07, 0x8a, 0x5a, 0x0f, 0x28, 0xec, 0x96, 0xd5, 0x47, 0xbf, 0xee, 0x9a, 0xce, 0x80, 0x3a, 0xc0,
+];
+
+/// The NUMS point `H` of BIP 341, a key whose secret key nobody knows.
+///
+/// Using it as the internal key disables the key path, but reveals so to anyone
+/// seeing the output key; see [`nums_point_with_seed`] to hide it.
+pub fn nums_point() -> XOnlyPublicKey {
+    XOnlyPublicKey::from_slice(&NUMS_POINT).expect("valid x-only key")
+}
+
+/// The point `H + rG` recommended by BIP 341, with `r` deterministically derived
+/// as the SHA256 of `seed`.
+///
+/// Nobody knows its secret key either, and it can't be told apart from other
+/// keys unless `seed` is revealed, which lets anyone check the key path is
+/// disabled. The seed should not be guessable, e.g. it can be the policy along
+/// with a secret of the wallet.
+pub fn nums_point_with_seed<C: secp256k1::Verification>(
+    secp: &secp256k1::Secp256k1<C>,
+    seed: &[u8],
+) -> XOnlyPublicKey {
+    let mut r = sha256::Hash::hash(seed).to_byte_array();
+    // Hashes above the curve order are hashed again, which practically never happens
+    let r = loop {
+        match secp256k1::Scalar::from_be_bytes(r) {
+            Ok(r) => break r,
+            Err(_) => r = sha256::Hash::hash(&r).to_byte_array(),
+        }
+    };
+    let h = secp256k1::PublicKey::from_x_only_public_key(nums_point(), secp256k1::Parity::Even);
+    h.add_exp_tweak(secp, &r)
+        .expect("H + rG is not infinity as the logarithm of H is unknown")
+        .x_only_public_key()
+        .0
+}
+
+/// Cache of the compilations of already visited sub-policies, along with the
+/// settings of the ongoing compilation.
+#[allow(clippy::type_complexity)]
+struct PolicyCache<Pk: MiniscriptKey, Ctx: ScriptContext> {
+    map: BTreeMap<
+        (Concrete<Pk>, OrdF64, Option<OrdF64>),
+        BTreeMap<CompilationKey, AstElemExt<Pk, Ctx>>,
+    >,
+    key_fragment: KeyFragment,
+}
+
+impl<Pk: MiniscriptKey, Ctx: ScriptContext> PolicyCache<Pk, Ctx> {
+    fn new(key_fragment: KeyFragment) -> Self { PolicyCache { map: BTreeMap::new(), key_fragment } }
+}
 
 /// Ordered f64 for comparison.
 #[derive(Copy, Clone, PartialEq, Debug)]
@@ -60,6 +216,23 @@ pub enum CompilerError {
         /// Maximum allowed number of Tapleaves.
         max: usize,
     },
+    /// In a Taproot compilation, the key to promote to the internal key isn't a
+    /// single key of the root-level disjunctions of the policy.
+    InternalKeyNotDisjunct,
+    /// In a Taproot compilation, a fragment to put in a Tapleaf of its own
+    /// isn't reached from the root of the policy through disjunctions only.
+    LeafNotDisjunct {
+        /// Index of the fragment in [`TrCompileOptions::own_leaves`].
+        index: usize,
+    },
+    /// In a Taproot compilation, the Tapleaves don't fit in a tree of the
+    /// maximum depth.
+    TapTreeTooDeep {
+        /// Number of Tapleaves of the compilation.
+        n: usize,
+        /// Maximum allowed depth of the tree.
+        max_depth: usize,
+    },
     ///Policy related errors
     PolicyError(policy::concrete::PolicyError),
 }
@@ -82,6 +255,15 @@ impl fmt::Display for CompilerError {
             CompilerError::TooManyTapleaves { n, max } => {
                 write!(f, "Policy had too many Tapleaves (found {}, maximum {})", n, max)
             }
+            CompilerError::InternalKeyNotDisjunct => {
+                f.write_str("Taproot internal key is not a root-level disjunction of the policy")
+            }
+            CompilerError::LeafNotDisjunct { index } => {
+                write!(f, "Tapleaf fragment {} is under a conjunction of the policy", index)
+            }
+            CompilerError::TapTreeTooDeep { n, max_depth } => {
+                write!(f, "{} Tapleaves do not fit in a tree of depth {}", n, max_depth)
+            }
             CompilerError::PolicyError(ref e) => fmt::Display::fmt(e, f),
         }
     }
@@ -97,7 +279,10 @@ impl error::Error for CompilerError {
             | ImpossibleNonMalleableCompilation
             | LimitsExceeded
             | NoInternalKey
-            | TooManyTapleaves { .. } => None,
+            | TooManyTapleaves { .. }
+            | InternalKeyNotDisjunct
+            | LeafNotDisjunct { .. }
+            | TapTreeTooDeep { .. } => None,
             PolicyError(e) => Some(e),
         }
     }
@@ -684,6 +869,12 @@ fn insert_elem<Pk: MiniscriptKey, Ctx: ScriptContext>(
         return false;
     }
 
+    // Signature operations only add up in larger fragments, so a compilation which
+    // exceeds the standard limit of the context can never be part of a standard one
+    if !elem.ms.within_standard_sigops() {
+        return false;
+    }
+
     let elem_cost = elem.cost_1d(sat_prob, dissat_prob);
 
     let elem_key = CompilationKey::from_type(elem.ms.ty, elem.ms.ext.has_free_verify, dissat_prob);
@@ -791,7 +982,10 @@ where
     //Check the cache for hits
     let ord_sat_prob = OrdF64(sat_prob);
     let ord_dissat_prob = dissat_prob.map(OrdF64);
-    if let Some(ret) = policy_cache.get(&(policy.clone(), ord_sat_prob, ord_dissat_prob)) {
+    if let Some(ret) = policy_cache
+        .map
+        .get(&(policy.clone(), ord_sat_prob, ord_dissat_prob))
+    {
         return Ok(ret.clone());
     }
 
@@ -822,8 +1016,21 @@ where
             insert_wrap!(AstElemExt::terminal(Terminal::True));
         }
         Concrete::Key(ref pk) => {
-            insert_wrap!(AstElemExt::terminal(Terminal::PkH(pk.clone())));
-            insert_wrap!(AstElemExt::terminal(Terminal::PkK(pk.clone())));
+            let pkh = AstElemExt::terminal(Terminal::PkH(pk.clone()));
+            let pkk = AstElemExt::terminal(Terminal::PkK(pk.clone()));
+            let skip_pkk = match policy_cache.key_fragment {
+                KeyFragment::Cheapest => false,
+                KeyFragment::PreferHash { max_overhead } => {
+                    let overhead =
+                        pkh.cost_1d(sat_prob, dissat_prob) - pkk.cost_1d(sat_prob, dissat_prob);
+                    overhead <= max_overhead as f64
+                }
+            };
+            insert_wrap!(pkh);
+            // Only drop `pk_k` if `pk_h` left us with something to work with.
+            if !skip_pkk || ret.is_empty() {
+                insert_wrap!(pkk);
+            }
         }
         Concrete::After(n) => insert_wrap!(AstElemExt::terminal(Terminal::After(n))),
         Concrete::Older(n) => insert_wrap!(AstElemExt::terminal(Terminal::Older(n))),
@@ -1049,8 +1256,16 @@ where
                         }
                     }
                     SigType::Ecdsa => {
+                        // Enough keys to fit under the consensus limit of `multi` may
+                        // still exceed the redeem script size limit of P2SH
                         if let Ok(pk_thresh) = pk_thresh.set_maximum() {
-                            insert_wrap!(AstElemExt::terminal(Terminal::Multi(pk_thresh)))
+                            let ast = Terminal::Multi(pk_thresh);
+                            if let Ok(ms) = Miniscript::from_ast(ast.clone()) {
+                                insert_wrap!(AstElemExt {
+                                    comp_ext_data: CompilerExtData::type_check(&ast),
+                                    ms: Arc::new(ms),
+                                })
+                            }
                         }
                     }
                 }
@@ -1078,7 +1293,9 @@ where
         // before calling this compile function
         Err(CompilerError::LimitsExceeded)
     } else {
-        policy_cache.insert((policy.clone(), ord_sat_prob, ord_dissat_prob), ret.clone());
+        policy_cache
+            .map
+            .insert((policy.clone(), ord_sat_prob, ord_dissat_prob), ret.clone());
         Ok(ret)
     }
 }
@@ -1156,7 +1373,16 @@ fn compile_tern<Pk: MiniscriptKey, Ctx: ScriptContext>(
 pub fn best_compilation<Pk: MiniscriptKey, Ctx: ScriptContext>(
     policy: &Concrete<Pk>,
 ) -> Result<Miniscript<Pk, Ctx>, CompilerError> {
-    let mut policy_cache = PolicyCache::<Pk, Ctx>::new();
+    best_compilation_with_key_fragment(policy, KeyFragment::Cheapest)
+}
+
+/// Obtain the best compilation of for p=1.0 and q=0, choosing between `pk_k`
+/// and `pk_h` according to `key_fragment`
+pub fn best_compilation_with_key_fragment<Pk: MiniscriptKey, Ctx: ScriptContext>(
+    policy: &Concrete<Pk>,
+    key_fragment: KeyFragment,
+) -> Result<Miniscript<Pk, Ctx>, CompilerError> {
+    let mut policy_cache = PolicyCache::<Pk, Ctx>::new(key_fragment);
     let x = &*best_t(&mut policy_cache, policy, 1.0, None)?.ms;
     if !x.ty.mall.safe {
         Err(CompilerError::TopLevelNonSafe)
@@ -1308,10 +1534,29 @@ mod tests {
         );
     }
 
+    #[test]
+    fn compile_key_fragment() {
+        let policy = SPolicy::from_str("or(pk(A),and(pk(B),older(100)))").expect("parse");
+        let cheapest: Miniscript<String, Segwitv0> = policy.compile().unwrap();
+        let hashed: Miniscript<String, Segwitv0> = policy
+            .compile_with_key_fragment(KeyFragment::prefer_hash())
+            .unwrap();
+        assert_eq!(cheapest.to_string(), "or_d(pk(A),and_v(v:pk(B),older(100)))");
+        assert_eq!(hashed.to_string(), "or_i(pkh(A),and_v(v:pkh(B),older(100)))");
+        assert_eq!(hashed.lift().unwrap(), cheapest.lift().unwrap());
+
+        // A zero threshold never picks a more expensive `pk_h`.
+        let no_overhead: Miniscript<String, Segwitv0> = policy
+            .compile_with_key_fragment(KeyFragment::PreferHash { max_overhead: 0 })
+            .unwrap();
+        assert_eq!(no_overhead, cheapest);
+    }
+
     #[test]
     fn compile_q() {
         let policy = SPolicy::from_str("or(1@and(pk(A),pk(B)),127@pk(C))").expect("parsing");
-        let compilation: TapAstElemExt = best_t(&mut BTreeMap::new(), &policy, 1.0, None).unwrap();
+        let compilation: TapAstElemExt =
+            best_t(&mut PolicyCache::new(KeyFragment::Cheapest), &policy, 1.0, None).unwrap();
 
         assert_eq!(compilation.cost_1d(1.0, None), 87.0 + 67.0390625);
         assert_eq!(policy.lift().unwrap().sorted(), compilation.ms.lift().unwrap().sorted());
@@ -1320,7 +1565,8 @@ mod tests {
         let policy = SPolicy::from_str(
                 "and(and(and(or(127@thresh(2,pk(A),pk(B),thresh(2,or(127@pk(A),1@pk(B)),after(100),or(and(pk(C),after(200)),and(pk(D),sha256(66687aadf862bd776c8fc18b8e9f8e20089714856ee233b3902a591d0d5f2925))),pk(E))),1@pk(F)),sha256(66687aadf862bd776c8fc18b8e9f8e20089714856ee233b3902a591d0d5f2925)),or(127@pk(G),1@after(300))),or(127@after(400),pk(H)))"
             ).expect("parsing");
-        let compilation: TapAstElemExt = best_t(&mut BTreeMap::new(), &policy, 1.0, None).unwrap();
+        let compilation: TapAstElemExt =
+            best_t(&mut PolicyCache::new(KeyFragment::Cheapest), &policy, 1.0, None).unwrap();
 
         assert_eq!(compilation.cost_1d(1.0, None), 433.0 + 275.7909749348958);
         assert_eq!(policy.lift().unwrap().sorted(), compilation.ms.lift().unwrap().sorted());
@@ -1645,4 +1891,74 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn compile_tr_with_options() {
+        use crate::Descriptor;
+
+        let height = |desc: Descriptor<String>| match desc {
+            Descriptor::Tr(tr) => tr.tap_tree().as_ref().unwrap().height(),
+            _ => unreachable!(),
+        };
+        let policy: SPolicy =
+            policy_str!("or(9@pk(A),1@or(99@and(pk(B),pk(C)),1@or(1@pk(D),1@and(pk(E),pk(F)))))");
+        let default = TrCompileOptions::new(Some("N".to_owned()));
+        assert_eq!(
+            policy.compile_tr_with_options(&default).unwrap(),
+            policy.compile_tr(Some("N".to_owned())).unwrap()
+        );
+
+        // Internal key strategies
+        let options = TrCompileOptions {
+            internal_key: InternalKeyStrategy::Key("D".to_owned()),
+            ..default.clone()
+        };
+        let desc = policy.compile_tr_with_options(&options).unwrap();
+        assert!(desc.to_string().starts_with("tr(D,"));
+        for key in ["B", "Z"] {
+            let options = TrCompileOptions {
+                internal_key: InternalKeyStrategy::Key(key.to_owned()),
+                ..default.clone()
+            };
+            assert_eq!(
+                policy.compile_tr_with_options(&options),
+                Err(CompilerError::InternalKeyNotDisjunct)
+            );
+        }
+        let options =
+            TrCompileOptions { internal_key: InternalKeyStrategy::Unspendable, ..default.clone() };
+        let desc = policy.compile_tr_with_options(&options).unwrap();
+        assert!(desc.to_string().starts_with("tr(N,"));
+        assert!(desc.to_string().contains("pk(A)"));
+        let options = TrCompileOptions { unspendable_key: None, ..options };
+        assert_eq!(policy.compile_tr_with_options(&options), Err(CompilerError::NoInternalKey));
+
+        // Leaf weighting and depth, with the leaves pk(A), and(B,C), pk(D) and and(E,F)
+        let options =
+            TrCompileOptions { internal_key: InternalKeyStrategy::Unspendable, ..default };
+        assert_eq!(height(policy.compile_tr_with_options(&options).unwrap()), 3);
+        let uniform =
+            TrCompileOptions { leaf_weighting: LeafWeighting::Uniform, ..options.clone() };
+        assert_eq!(height(policy.compile_tr_with_options(&uniform).unwrap()), 2);
+        let limited = TrCompileOptions { max_depth: 2, ..options.clone() };
+        assert_eq!(height(policy.compile_tr_with_options(&limited).unwrap()), 2);
+        let too_shallow = TrCompileOptions { max_depth: 1, ..options };
+        assert_eq!(
+            policy.compile_tr_with_options(&too_shallow),
+            Err(CompilerError::TapTreeTooDeep { n: 4, max_depth: 1 })
+        );
+    }
+
+    #[test]
+    fn nums_points() {
+        assert_eq!(
+            nums_point().to_string(),
+            "50929b74c1a04954b78b4b6035e97a5e078a5a0f28ec96d547bfee9ace803ac0"
+        );
+        let secp = secp256k1::Secp256k1::verification_only();
+        let key = nums_point_with_seed(&secp, b"seed");
+        assert_eq!(key, nums_point_with_seed(&secp, b"seed"));
+        assert_ne!(key, nums_point_with_seed(&secp, b"other seed"));
+        assert_ne!(key, nums_point());
+    }
 }
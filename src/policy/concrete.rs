@@ -11,24 +11,32 @@ use bitcoin::absolute;
 #[cfg(feature = "compiler")]
 use {
     crate::descriptor::TapTree,
+    crate::miniscript::context::SigType,
     crate::miniscript::ScriptContext,
-    crate::policy::compiler::{self, CompilerError, OrdF64},
+    crate::plan::UsageStats,
+    crate::policy::compiler::{
+        self, CompilerError, InternalKeyStrategy, KeyFragment, LeafWeighting, OrdF64,
+    },
+    crate::policy::{semantic, Liftable},
     crate::Descriptor,
     crate::Miniscript,
     crate::Tap,
+    crate::ToPublicKey,
+    bitcoin::taproot::{LeafVersion, TapLeafHash},
     core::cmp::Reverse,
 };
 
 use crate::expression::{self, FromTree};
 use crate::iter::{Tree, TreeLike};
 use crate::miniscript::types::extra_props::TimelockInfo;
+use crate::policy::ZeroLocks;
 use crate::prelude::*;
 use crate::sync::Arc;
 #[cfg(all(doc, not(feature = "compiler")))]
 use crate::Descriptor;
 use crate::{
-    errstr, AbsLockTime, Error, ForEachKey, FromStrKey, MiniscriptKey, RelLockTime, Threshold,
-    Translator,
+    errstr, policy, AbsLockTime, AbsLockTimeError, Error, ForEachKey, FromStrKey, MiniscriptKey,
+    RelLockTime, RelLockTimeError, Threshold, Translator,
 };
 
 /// Maximum TapLeafs allowed in a compiled TapTree
@@ -127,6 +135,8 @@ impl error::Error for PolicyError {
 #[cfg(feature = "compiler")]
 struct TapleafProbabilityIter<'p, Pk: MiniscriptKey> {
     stack: Vec<(f64, &'p Policy<Pk>)>,
+    /// Fragments which are leaves, even if they are disjunctions
+    own_leaves: &'p [Policy<Pk>],
 }
 
 #[cfg(feature = "compiler")]
@@ -136,6 +146,9 @@ impl<'p, Pk: MiniscriptKey> Iterator for TapleafProbabilityIter<'p, Pk> {
     fn next(&mut self) -> Option<Self::Item> {
         loop {
             let (top_prob, top) = self.stack.pop()?;
+            if self.own_leaves.contains(top) {
+                return Some((top_prob, top));
+            }
 
             match top {
                 Policy::Or(ref subs) => {
@@ -158,6 +171,26 @@ impl<'p, Pk: MiniscriptKey> Iterator for TapleafProbabilityIter<'p, Pk> {
 }
 
 impl<Pk: MiniscriptKey> Policy<Pk> {
+    /// Constructs an `older` policy from a relative locktime consensus value
+    ///
+    /// # Errors
+    ///
+    /// If `n` is zero, which doesn't restrict the spending transaction, or isn't
+    /// a valid BIP 68 relative locktime.
+    pub fn older(n: u32) -> Result<Self, RelLockTimeError> {
+        RelLockTime::from_consensus(n).map(Policy::Older)
+    }
+
+    /// Constructs an `after` policy from an absolute locktime consensus value
+    ///
+    /// # Errors
+    ///
+    /// If `n` is zero, which doesn't restrict the spending transaction, or is
+    /// above the maximum locktime allowed by Miniscript.
+    pub fn after(n: u32) -> Result<Self, AbsLockTimeError> {
+        AbsLockTime::from_consensus(n).map(Policy::After)
+    }
+
     /// Flattens the [`Policy`] tree structure into an iterator of tuples `(leaf script, leaf probability)`
     /// with leaf probabilities corresponding to odds for each sub-branch in the policy.
     /// We calculate the probability of selecting the sub-branch at every level and calculate the
@@ -182,7 +215,17 @@ impl<Pk: MiniscriptKey> Policy<Pk> {
     /// leaf-nodes to [`MAX_COMPILATION_LEAVES`].
     #[cfg(feature = "compiler")]
     fn tapleaf_probability_iter(&self) -> TapleafProbabilityIter<Pk> {
-        TapleafProbabilityIter { stack: vec![(1.0, self)] }
+        self.tapleaf_probability_iter_with(&[])
+    }
+
+    /// Same as [`Policy::tapleaf_probability_iter`], without splitting the disjunctions
+    /// of `own_leaves`.
+    #[cfg(feature = "compiler")]
+    fn tapleaf_probability_iter_with<'p>(
+        &'p self,
+        own_leaves: &'p [Policy<Pk>],
+    ) -> TapleafProbabilityIter<'p, Pk> {
+        TapleafProbabilityIter { stack: vec![(1.0, self)], own_leaves }
     }
 
     /// Extracts the internal_key from this policy tree.
@@ -206,6 +249,14 @@ impl<Pk: MiniscriptKey> Policy<Pk> {
 
     /// Compiles the [`Policy`] into a [`Descriptor::Tr`].
     ///
+    /// ### Internal key
+    ///
+    /// If one of the root-level disjunctions is a single key, the most likely one is promoted to
+    /// the internal key so it can use a key-path spend. Otherwise `unspendable_key` is used as
+    /// the internal key, and compilation fails if none is provided. Use
+    /// [`Policy::compile_tr_no_key_promotion`] to always use `unspendable_key`, or
+    /// [`Policy::compile_tr_with_options`] to tune the compilation.
+    ///
     /// ### TapTree compilation
     ///
     /// The policy tree constructed by root-level disjunctions over [`Policy::Or`] and
@@ -223,44 +274,141 @@ impl<Pk: MiniscriptKey> Policy<Pk> {
     // TODO: We might require other compile errors for Taproot.
     #[cfg(feature = "compiler")]
     pub fn compile_tr(&self, unspendable_key: Option<Pk>) -> Result<Descriptor<Pk>, CompilerError> {
-        self.is_valid().map_err(CompilerError::PolicyError)?;
+        self.compile_tr_with_options(&compiler::TrCompileOptions::new(unspendable_key))
+    }
+
+    /// Compiles the [`Policy`] into a [`Descriptor::Tr`], using `unspendable_key` as the internal
+    /// key.
+    ///
+    /// Unlike [`Policy::compile_tr`], no key of the policy is ever promoted to the internal key,
+    /// so every spending path goes through the script tree. This is useful when the internal key
+    /// is meant to be spendable by other means, or must not be one of the policy keys.
+    #[cfg(feature = "compiler")]
+    pub fn compile_tr_no_key_promotion(
+        &self,
+        unspendable_key: Pk,
+    ) -> Result<Descriptor<Pk>, CompilerError> {
+        let options = compiler::TrCompileOptions {
+            internal_key: InternalKeyStrategy::Unspendable,
+            ..compiler::TrCompileOptions::new(Some(unspendable_key))
+        };
+        self.compile_tr_with_options(&options)
+    }
+
+    /// Compiles the [`Policy`] into a [`Descriptor::Tr`], tuned by `options`.
+    ///
+    /// This is [`Policy::compile_tr`] with control over how the internal key is chosen, see
+    /// [`compiler::InternalKeyStrategy`], how the leaves are weighted in the tap tree, and its
+    /// maximum depth. When the tree weighted by the odds of the policy is deeper than
+    /// `options.max_depth`, a balanced tree is built instead.
+    ///
+    /// # Errors
+    ///
+    /// As [`Policy::compile_tr`], and also if the key of [`compiler::InternalKeyStrategy::Key`]
+    /// isn't a single key of the root-level disjunctions, or if the leaves don't fit in a tree
+    /// of the maximum depth.
+    #[cfg(feature = "compiler")]
+    pub fn compile_tr_with_options(
+        &self,
+        options: &compiler::TrCompileOptions<Pk>,
+    ) -> Result<Descriptor<Pk>, CompilerError> {
+        self.compile_tr_weighted(options, |_, prob| prob)
+    }
+
+    /// Compiles the [`Policy`] into a [`Descriptor::Tr`] as [`Policy::compile_tr_with_options`],
+    /// with the probability of each leaf, as derived from the odds, adjusted by `weight`.
+    #[cfg(feature = "compiler")]
+    fn compile_tr_weighted<F>(
+        &self,
+        options: &compiler::TrCompileOptions<Pk>,
+        weight: F,
+    ) -> Result<Descriptor<Pk>, CompilerError>
+    where
+        F: Fn(&Miniscript<Pk, Tap>, f64) -> f64,
+    {
+        self.check_validity(true)
+            .map_err(CompilerError::PolicyError)?;
         match self.is_safe_nonmalleable() {
-            (false, _) => Err(CompilerError::TopLevelNonSafe),
-            (_, false) => Err(CompilerError::ImpossibleNonMalleableCompilation),
-            _ => {
-                let (internal_key, policy) = self.clone().extract_key(unspendable_key)?;
-                policy.check_num_tapleaves()?;
-                let tree = Descriptor::new_tr(
-                    internal_key,
-                    match policy {
-                        Policy::Trivial => None,
-                        policy => {
-                            let mut leaf_compilations: Vec<(OrdF64, Miniscript<Pk, Tap>)> = vec![];
-                            for (prob, pol) in policy.tapleaf_probability_iter() {
-                                // policy corresponding to the key (replaced by unsatisfiable) is skipped
-                                if *pol == Policy::Unsatisfiable {
-                                    continue;
-                                }
-                                let compilation = compiler::best_compilation::<Pk, Tap>(pol)?;
-                                compilation
-                                    .sanity_check()
-                                    .expect("compiler produces sane output");
-                                leaf_compilations.push((OrdF64(prob), compilation));
-                            }
-                            if !leaf_compilations.is_empty() {
-                                let tap_tree = with_huffman_tree::<Pk>(leaf_compilations).unwrap();
-                                Some(tap_tree)
-                            } else {
-                                // no policies remaining once the extracted key is skipped
-                                None
-                            }
-                        }
-                    },
-                )
-                .expect("compiler produces sane output");
-                Ok(tree)
-            }
+            (false, _) => return Err(CompilerError::TopLevelNonSafe),
+            (_, false) => return Err(CompilerError::ImpossibleNonMalleableCompilation),
+            _ => {}
         }
+
+        // Leaves of their own only make for fewer leaves
+        self.check_num_tapleaves()?;
+        let own_leaves = &options.own_leaves[..];
+        let leaves: Vec<_> = self.tapleaf_probability_iter_with(own_leaves).collect();
+        if let Some(index) = own_leaves
+            .iter()
+            .position(|own_leaf| !leaves.iter().any(|(_, leaf)| *leaf == own_leaf))
+        {
+            return Err(CompilerError::LeafNotDisjunct { index });
+        }
+
+        // Only keys which aren't in leaves of their own can be promoted
+        let promotable = leaves
+            .iter()
+            .filter(|(_, leaf)| !own_leaves.contains(leaf))
+            .filter_map(|(prob, leaf)| match leaf {
+                Policy::Key(pk) => Some((OrdF64(*prob), pk)),
+                _ => None,
+            });
+        let promoted = match options.internal_key {
+            InternalKeyStrategy::MostLikely => promotable
+                .max_by_key(|(prob, _)| *prob)
+                .map(|(_, pk)| pk.clone()),
+            InternalKeyStrategy::Key(ref key) => {
+                let mut promotable = promotable;
+                if !promotable.any(|(_, pk)| pk == key) {
+                    return Err(CompilerError::InternalKeyNotDisjunct);
+                }
+                Some(key.clone())
+            }
+            InternalKeyStrategy::Unspendable => None,
+        };
+        let internal_key = match promoted {
+            Some(ref key) => key.clone(),
+            None => options
+                .unspendable_key
+                .clone()
+                .ok_or(CompilerError::NoInternalKey)?,
+        };
+
+        let tree = if *self == Policy::Trivial {
+            None
+        } else {
+            let mut leaf_compilations: Vec<(OrdF64, Miniscript<Pk, Tap>)> = vec![];
+            for (prob, pol) in leaves {
+                // the promoted key can't be used in the leaves
+                let pol = match promoted {
+                    Some(ref key) => pol.clone().translate_unsatisfiable_pk(key),
+                    None => pol.clone(),
+                };
+                // policy corresponding to the key (replaced by unsatisfiable) is skipped
+                if pol == Policy::Unsatisfiable {
+                    continue;
+                }
+                // keys may be repeated across leaves, but not within one
+                pol.check_duplicate_keys()?;
+                let compilation = compiler::best_compilation::<Pk, Tap>(&pol)?;
+                compilation
+                    .sanity_check()
+                    .expect("compiler produces sane output");
+                let prob = weight(&compilation, prob);
+                leaf_compilations.push((OrdF64(prob), compilation));
+            }
+            if !leaf_compilations.is_empty() {
+                Some(with_depth_limited_tree(
+                    leaf_compilations,
+                    options.leaf_weighting,
+                    options.max_depth,
+                )?)
+            } else {
+                // no policies remaining once the extracted key is skipped
+                None
+            }
+        };
+        Ok(Descriptor::new_tr(internal_key, tree).expect("compiler produces sane output"))
     }
 
     /// Compiles the [`Policy`] into a [`Descriptor::Tr`].
@@ -286,7 +434,7 @@ impl<Pk: MiniscriptKey> Policy<Pk> {
         &self,
         unspendable_key: Option<Pk>,
     ) -> Result<Descriptor<Pk>, Error> {
-        self.is_valid().map_err(Error::ConcretePolicy)?;
+        self.check_validity(true).map_err(Error::ConcretePolicy)?;
         match self.is_safe_nonmalleable() {
             (false, _) => Err(Error::from(CompilerError::TopLevelNonSafe)),
             (_, false) => Err(Error::from(CompilerError::ImpossibleNonMalleableCompilation)),
@@ -297,17 +445,19 @@ impl<Pk: MiniscriptKey> Policy<Pk> {
                     match policy {
                         Policy::Trivial => None,
                         policy => {
-                            let leaf_compilations: Vec<_> = policy
+                            let leaf_compilations = policy
                                 .enumerate_policy_tree(1.0)
                                 .into_iter()
                                 .filter(|x| x.1 != Arc::new(Policy::Unsatisfiable))
                                 .map(|(prob, pol)| {
-                                    (
+                                    // keys may be repeated across leaves, but not within one
+                                    pol.check_duplicate_keys().map_err(Error::ConcretePolicy)?;
+                                    Ok((
                                         OrdF64(prob),
                                         compiler::best_compilation(pol.as_ref()).unwrap(),
-                                    )
+                                    ))
                                 })
-                                .collect();
+                                .collect::<Result<Vec<_>, Error>>()?;
 
                             if !leaf_compilations.is_empty() {
                                 let tap_tree = with_huffman_tree::<Pk>(leaf_compilations).unwrap();
@@ -339,7 +489,9 @@ impl<Pk: MiniscriptKey> Policy<Pk> {
         &self,
         desc_ctx: DescriptorCtx<Pk>,
     ) -> Result<Descriptor<Pk>, Error> {
-        self.is_valid().map_err(Error::ConcretePolicy)?;
+        // Taproot allows keys to be repeated across leaves
+        self.check_validity(matches!(desc_ctx, DescriptorCtx::Tr(_)))
+            .map_err(Error::ConcretePolicy)?;
         match self.is_safe_nonmalleable() {
             (false, _) => Err(Error::from(CompilerError::TopLevelNonSafe)),
             (_, false) => Err(Error::from(CompilerError::ImpossibleNonMalleableCompilation)),
@@ -371,6 +523,332 @@ impl<Pk: MiniscriptKey> Policy<Pk> {
             _ => compiler::best_compilation(self),
         }
     }
+
+    /// Compiles the policy like [`Policy::compile`], choosing between `pk_k` and `pk_h` for
+    /// single keys according to `key_fragment`.
+    ///
+    /// Use [`KeyFragment::prefer_hash`] to keep public keys off-chain until the branch using
+    /// them is spent, at the cost of a slightly larger witness.
+    #[cfg(feature = "compiler")]
+    pub fn compile_with_key_fragment<Ctx: ScriptContext>(
+        &self,
+        key_fragment: KeyFragment,
+    ) -> Result<Miniscript<Pk, Ctx>, CompilerError> {
+        self.is_valid()?;
+        match self.is_safe_nonmalleable() {
+            (false, _) => Err(CompilerError::TopLevelNonSafe),
+            (_, false) => Err(CompilerError::ImpossibleNonMalleableCompilation),
+            _ => compiler::best_compilation_with_key_fragment(self, key_fragment),
+        }
+    }
+
+    /// Compiles the policy into a `Miniscript` and checks that the compilation has the same
+    /// semantics as the policy, see [`Policy::check_compilation`].
+    #[cfg(feature = "compiler")]
+    pub fn compile_checked<Ctx: ScriptContext>(
+        &self,
+    ) -> Result<(Miniscript<Pk, Ctx>, CompilationReport<Pk>), Error> {
+        let ms = self.compile::<Ctx>()?;
+        let report = self.check_compilation(&ms)?;
+        Ok((ms, report))
+    }
+
+    /// Compiles the policy like [`Policy::compile`], explaining any failure.
+    ///
+    /// On error, the returned [`CompilationDiagnostic`] identifies the sub-policy responsible
+    /// for the failure, when one can be found, and lists changes to the policy which may let it
+    /// compile.
+    #[cfg(feature = "compiler")]
+    pub fn compile_diagnosed<Ctx: ScriptContext>(
+        &self,
+    ) -> Result<Miniscript<Pk, Ctx>, CompilationDiagnostic<Pk>> {
+        self.compile::<Ctx>()
+            .map_err(|error| self.diagnose::<Ctx>(error))
+    }
+
+    /// Builds the diagnostic of `error`, returned when compiling this policy under `Ctx`.
+    #[cfg(feature = "compiler")]
+    fn diagnose<Ctx: ScriptContext>(&self, error: CompilerError) -> CompilationDiagnostic<Pk> {
+        let mut suggestions = vec![];
+        let sub_policy = match error {
+            CompilerError::TopLevelNonSafe => {
+                // Follow the disjunctions down to the largest branch which can be spent
+                // without a signature.
+                let mut policy = self;
+                while let Policy::Or(ref subs) = *policy {
+                    let mut unsafe_subs =
+                        subs.iter().filter(|(_, sub)| !sub.is_safe_nonmalleable().0);
+                    match unsafe_subs.next() {
+                        Some((_, sub)) if unsafe_subs.count() + 1 < subs.len() => policy = sub,
+                        _ => break,
+                    }
+                }
+                suggestions.push(PolicySuggestion::RequireSignature);
+                Some(policy.clone())
+            }
+            CompilerError::ImpossibleNonMalleableCompilation => {
+                suggestions.push(PolicySuggestion::RequireSignature);
+                self.post_order_iter()
+                    .map(|data| data.node)
+                    .find(|policy| !policy.is_safe_nonmalleable().1)
+                    .cloned()
+            }
+            CompilerError::LimitsExceeded => {
+                let sub_policy = self
+                    .post_order_iter()
+                    .map(|data| data.node)
+                    .find(|policy| {
+                        policy.compile::<Ctx>().err() == Some(CompilerError::LimitsExceeded)
+                    })
+                    .cloned();
+                if let Some(Policy::Thresh(ref thresh)) = sub_policy {
+                    suggestions
+                        .push(PolicySuggestion::SplitThreshold { k: thresh.k(), n: thresh.n() });
+                }
+                if Ctx::sig_type() != SigType::Schnorr {
+                    suggestions.push(PolicySuggestion::UseTaproot);
+                }
+                sub_policy
+            }
+            CompilerError::NoInternalKey => {
+                suggestions.push(PolicySuggestion::ProvideInternalKey);
+                None
+            }
+            CompilerError::InternalKeyNotDisjunct => {
+                suggestions.push(PolicySuggestion::ProvideInternalKey);
+                None
+            }
+            CompilerError::TooManyTapleaves { .. } | CompilerError::TapTreeTooDeep { .. } => {
+                suggestions.push(PolicySuggestion::ReduceBranches);
+                None
+            }
+            CompilerError::LeafNotDisjunct { .. } | CompilerError::PolicyError(_) => {
+                suggestions.push(PolicySuggestion::FixPolicy);
+                None
+            }
+        };
+        CompilationDiagnostic { error, sub_policy, suggestions }
+    }
+
+    /// Checks whether `ms` has exactly the same spending conditions as this policy.
+    ///
+    /// Both are lifted to semantic policies and checked to entail each other, so that every
+    /// satisfaction of one is a satisfaction of the other. Each threshold of the policy is also
+    /// looked up in the lifted miniscript, to show that no k-of-n was weakened or rearranged.
+    ///
+    /// # Errors
+    ///
+    /// If either can't be lifted, or they are too large for entailment to be checked.
+    #[cfg(feature = "compiler")]
+    pub fn check_compilation<Ctx: ScriptContext>(
+        &self,
+        ms: &Miniscript<Pk, Ctx>,
+    ) -> Result<CompilationReport<Pk>, Error> {
+        let policy = self.lift()?.normalized().sorted();
+        let compiled = ms.lift()?.normalized().sorted();
+
+        let compiled_entails_policy = compiled
+            .clone()
+            .entails(policy.clone())
+            .map_err(Error::SemanticPolicy)?;
+        let policy_entails_compiled = policy
+            .clone()
+            .entails(compiled.clone())
+            .map_err(Error::SemanticPolicy)?;
+
+        let compiled_thresholds: BTreeSet<&semantic::Policy<Pk>> = compiled
+            .pre_order_iter()
+            .filter(|node| matches!(node, semantic::Policy::Thresh(..)))
+            .collect();
+        let thresholds = policy
+            .pre_order_iter()
+            .filter_map(|node| match node {
+                semantic::Policy::Thresh(ref thresh) => Some(ThresholdCheck {
+                    k: thresh.k(),
+                    n: thresh.n(),
+                    policy: node.clone(),
+                    preserved: compiled_thresholds.contains(node),
+                }),
+                _ => None,
+            })
+            .collect();
+
+        Ok(CompilationReport {
+            identical: policy == compiled,
+            policy,
+            compiled,
+            compiled_entails_policy,
+            policy_entails_compiled,
+            thresholds,
+        })
+    }
+}
+
+/// The result of checking a compilation against its policy, see [`Policy::check_compilation`].
+///
+/// Policies are given lifted, normalized and sorted.
+#[cfg(feature = "compiler")]
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct CompilationReport<Pk: MiniscriptKey> {
+    /// The semantic policy of the input policy
+    pub policy: semantic::Policy<Pk>,
+    /// The semantic policy of the compiled miniscript
+    pub compiled: semantic::Policy<Pk>,
+    /// Whether both semantic policies are structurally identical
+    pub identical: bool,
+    /// Whether every satisfaction of the miniscript satisfies the policy, i.e. the compiler
+    /// didn't weaken the policy
+    pub compiled_entails_policy: bool,
+    /// Whether every satisfaction of the policy satisfies the miniscript, i.e. the compiler
+    /// didn't make the policy stricter
+    pub policy_entails_compiled: bool,
+    /// Every threshold of the policy, in pre-order, and whether it is found in the miniscript
+    pub thresholds: Vec<ThresholdCheck<Pk>>,
+}
+
+/// A threshold of a policy checked against its compilation
+#[cfg(feature = "compiler")]
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ThresholdCheck<Pk: MiniscriptKey> {
+    /// The number of sub-policies required
+    pub k: usize,
+    /// The number of sub-policies
+    pub n: usize,
+    /// The threshold itself
+    pub policy: semantic::Policy<Pk>,
+    /// Whether the same threshold, with the same sub-policies, is found in the miniscript
+    pub preserved: bool,
+}
+
+#[cfg(feature = "compiler")]
+impl<Pk: MiniscriptKey> CompilationReport<Pk> {
+    /// Whether the miniscript has exactly the same semantics as the policy
+    pub fn is_equivalent(&self) -> bool {
+        self.compiled_entails_policy && self.policy_entails_compiled
+    }
+
+    /// Whether every threshold of the policy is found in the miniscript
+    pub fn thresholds_preserved(&self) -> bool { self.thresholds.iter().all(|t| t.preserved) }
+}
+
+#[cfg(feature = "compiler")]
+impl<Pk: MiniscriptKey> fmt::Display for CompilationReport<Pk> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "policy: {}", self.policy)?;
+        writeln!(f, "compiled: {}", self.compiled)?;
+        writeln!(f, "identical: {}", self.identical)?;
+        writeln!(f, "compiled entails policy: {}", self.compiled_entails_policy)?;
+        writeln!(f, "policy entails compiled: {}", self.policy_entails_compiled)?;
+        for t in &self.thresholds {
+            writeln!(f, "{}-of-{} {}: {}", t.k, t.n, t.policy, t.preserved)?;
+        }
+        write!(f, "equivalent: {}", self.is_equivalent())
+    }
+}
+
+/// The explanation of a failed compilation, see [`Policy::compile_diagnosed`].
+#[cfg(feature = "compiler")]
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct CompilationDiagnostic<Pk: MiniscriptKey> {
+    /// The error returned by the compiler
+    pub error: CompilerError,
+    /// The smallest sub-policy responsible for the error, if it could be identified
+    pub sub_policy: Option<Policy<Pk>>,
+    /// Changes to the policy which may let it compile, most relevant first
+    pub suggestions: Vec<PolicySuggestion>,
+}
+
+/// A change to a policy which may let it compile, see [`CompilationDiagnostic`].
+#[cfg(feature = "compiler")]
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Hash)]
+pub enum PolicySuggestion {
+    /// Split the `k`-of-`n` threshold into smaller nested thresholds.
+    SplitThreshold {
+        /// The number of sub-policies required by the threshold
+        k: usize,
+        /// The number of sub-policies of the threshold
+        n: usize,
+    },
+    /// Compile to a Taproot descriptor, which has larger limits and puts each branch of
+    /// the policy in its own leaf.
+    UseTaproot,
+    /// Require a signature on every spending path, e.g. by adding a key to branches which
+    /// only have hashlocks or timelocks.
+    RequireSignature,
+    /// Provide an unspendable internal key to the Taproot compilation.
+    ProvideInternalKey,
+    /// Reduce the number of disjunctions of the policy.
+    ReduceBranches,
+    /// Fix the invalid policy itself, see [`PolicyError`].
+    FixPolicy,
+}
+
+#[cfg(feature = "compiler")]
+impl<Pk: MiniscriptKey> fmt::Display for CompilationDiagnostic<Pk> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.error, f)?;
+        if let Some(ref sub_policy) = self.sub_policy {
+            write!(f, " in {}", sub_policy)?;
+        }
+        for suggestion in &self.suggestions {
+            write!(f, "; {}", suggestion)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "compiler")]
+impl fmt::Display for PolicySuggestion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PolicySuggestion::SplitThreshold { k, n } => {
+                write!(f, "split the {}-of-{} threshold into smaller thresholds", k, n)
+            }
+            PolicySuggestion::UseTaproot => f.write_str("compile to a taproot descriptor"),
+            PolicySuggestion::RequireSignature => {
+                f.write_str("require a signature on every spending path")
+            }
+            PolicySuggestion::ProvideInternalKey => {
+                f.write_str("provide an unspendable internal key")
+            }
+            PolicySuggestion::ReduceBranches => f.write_str("reduce the number of branches"),
+            PolicySuggestion::FixPolicy => f.write_str("fix the policy"),
+        }
+    }
+}
+
+#[cfg(all(feature = "compiler", feature = "std"))]
+impl<Pk: MiniscriptKey> error::Error for CompilationDiagnostic<Pk> {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> { Some(&self.error) }
+}
+
+#[cfg(feature = "compiler")]
+impl<Pk: MiniscriptKey + ToPublicKey> Policy<Pk> {
+    /// Compiles the [`Policy`] into a [`Descriptor::Tr`] as [`Policy::compile_tr_with_options`],
+    /// weighting the leaves by the spends recorded in `stats`.
+    ///
+    /// The probability of each leaf is the share of the recorded script-path spends going
+    /// through it, with the probability derived from the odds of the policy counting as one
+    /// extra spend so that leaves which were never used keep their relative order. Without any
+    /// recorded script-path spend, this is [`Policy::compile_tr_with_options`]. The statistics
+    /// have no effect with [`compiler::LeafWeighting::Uniform`].
+    ///
+    /// This is how a wallet acts on [`crate::descriptor::Tr::rebalance_advice`]: recompiling its
+    /// policy with the statistics it collected puts the most used leaves closest to the root.
+    pub fn compile_tr_with_usage(
+        &self,
+        options: &compiler::TrCompileOptions<Pk>,
+        stats: &UsageStats,
+    ) -> Result<Descriptor<Pk>, CompilerError> {
+        let total = stats.script_spends();
+        if total == 0 {
+            return self.compile_tr_with_options(options);
+        }
+        self.compile_tr_weighted(options, |ms, prob| {
+            let leaf_hash = TapLeafHash::from_script(&ms.encode(), LeafVersion::TapScript);
+            (stats.leaf_count(&leaf_hash) as f64 + prob) / (total as f64 + 1.0)
+        })
+    }
 }
 
 #[cfg(feature = "compiler")]
@@ -590,6 +1068,73 @@ impl<Pk: MiniscriptKey> Policy<Pk> {
             .collect()
     }
 
+    /// Gets the keys which have to sign on every satisfying path of the policy.
+    ///
+    /// For example, the policy `and(pk(A),or(pk(B),and(pk(A),pk(C))))` requires `A` on both paths
+    /// while `B` and `C` are optional. Paths going through [`Policy::Unsatisfiable`] are ignored.
+    ///
+    /// Note that an always-required key can't simply be used as the internal key of a Taproot
+    /// compilation, as that would let it spend on its own.
+    pub fn required_keys(&self) -> BTreeSet<&Pk> {
+        use Policy::*;
+
+        // `None` stands for an unsatisfiable sub-policy, which vacuously requires every key.
+        let mut acc: Vec<Option<BTreeSet<&Pk>>> = vec![];
+        for data in self.rtl_post_order_iter() {
+            let new = match data.node {
+                Unsatisfiable => None,
+                Key(ref pk) => Some(core::iter::once(pk).collect()),
+                Trivial | Sha256(_) | Hash256(_) | Ripemd160(_) | Hash160(_) | After(_)
+                | Older(_) => Some(BTreeSet::new()),
+                And(ref subs) => (0..subs.len()).map(|_| acc.pop().unwrap()).try_fold(
+                    BTreeSet::new(),
+                    |mut keys, sub| {
+                        keys.extend(sub?);
+                        Some(keys)
+                    },
+                ),
+                Or(ref subs) => {
+                    let subs = (0..subs.len()).map(|_| acc.pop().unwrap());
+                    Self::threshold_required_keys(1, subs)
+                }
+                Thresh(ref thresh) => {
+                    let subs = (0..thresh.n()).map(|_| acc.pop().unwrap());
+                    Self::threshold_required_keys(thresh.k(), subs)
+                }
+            };
+            acc.push(new);
+        }
+        // Ok to unwrap because we know we processed at least one node.
+        acc.pop().unwrap().unwrap_or_default()
+    }
+
+    /// Combines the required keys of the children of a `k`-of-n threshold.
+    ///
+    /// A key is required by the threshold if fewer than `k` children can be satisfied without it.
+    fn threshold_required_keys<'a, I>(k: usize, subs: I) -> Option<BTreeSet<&'a Pk>>
+    where
+        I: Iterator<Item = Option<BTreeSet<&'a Pk>>>,
+    {
+        let mut n_satisfiable = 0;
+        let mut counts = BTreeMap::new();
+        for keys in subs.flatten() {
+            n_satisfiable += 1;
+            for key in keys {
+                *counts.entry(key).or_insert(0) += 1;
+            }
+        }
+        if n_satisfiable < k {
+            return None;
+        }
+        Some(
+            counts
+                .into_iter()
+                .filter(|(_, count)| n_satisfiable - count < k)
+                .map(|(key, _)| key)
+                .collect(),
+        )
+    }
+
     /// Gets the number of [TapLeaf](`TapTree::Leaf`)s considering exhaustive root-level [`Policy::Or`]
     /// and [`Policy::Thresh`] disjunctions for the `TapTree`.
     #[cfg(feature = "compiler")]
@@ -684,11 +1229,18 @@ impl<Pk: MiniscriptKey> Policy<Pk> {
     /// contains Non-two argument `and`, `or` or a `0` arg thresh.
     /// Validity condition also checks whether there is a possible satisfaction
     /// combination of timelocks and heightlocks
-    pub fn is_valid(&self) -> Result<(), PolicyError> {
+    pub fn is_valid(&self) -> Result<(), PolicyError> { self.check_validity(false) }
+
+    /// Checks the validity of the policy like [`Policy::is_valid`], except for duplicate keys
+    /// when `allow_repeated_keys` is set, as for Taproot compilation where they may end up in
+    /// different leaves.
+    fn check_validity(&self, allow_repeated_keys: bool) -> Result<(), PolicyError> {
         use Policy::*;
 
         self.check_timelocks()?;
-        self.check_duplicate_keys()?;
+        if !allow_repeated_keys {
+            self.check_duplicate_keys()?;
+        }
 
         for policy in self.pre_order_iter() {
             match *policy {
@@ -845,6 +1397,17 @@ impl<Pk: FromStrKey> str::FromStr for Policy<Pk> {
 serde_string_impl_pk!(Policy, "a miniscript concrete policy");
 
 impl<Pk: FromStrKey> Policy<Pk> {
+    /// Parses a policy which may contain the degenerate timelocks `older(0)`
+    /// and `after(0)`, which are rejected by [`str::FromStr`]
+    ///
+    /// These timelocks are replaced by `TRIVIAL`, since they are satisfied by
+    /// any transaction which may use timelocks at all, and returned so that
+    /// the caller may warn about them.
+    pub fn from_str_zero_locks(s: &str) -> Result<(Self, ZeroLocks), Error> {
+        let (s, locks) = policy::replace_zero_locks(s)?;
+        Ok((s.parse()?, locks))
+    }
+
     /// Helper function for `from_tree` to parse subexpressions with
     /// names of the form x@y
     fn from_tree_prob(
@@ -969,6 +1532,37 @@ fn with_huffman_tree<Pk: MiniscriptKey>(
     Ok(node)
 }
 
+/// Creates a tree from compiled [`Miniscript`] nodes weighted by `weighting`, falling back to a
+/// balanced tree if it is deeper than `max_depth`.
+#[cfg(feature = "compiler")]
+fn with_depth_limited_tree<Pk: MiniscriptKey>(
+    ms: Vec<(OrdF64, Miniscript<Pk, Tap>)>,
+    weighting: LeafWeighting,
+    max_depth: usize,
+) -> Result<TapTree<Pk>, CompilerError> {
+    let n = ms.len();
+    // A balanced tree of `n` leaves has depth ceil(log2(n))
+    let balanced_depth = n.next_power_of_two().trailing_zeros() as usize;
+    if balanced_depth > max_depth {
+        return Err(CompilerError::TapTreeTooDeep { n, max_depth });
+    }
+    let balanced = |ms: Vec<(OrdF64, Miniscript<Pk, Tap>)>| {
+        let uniform = ms.into_iter().map(|(_, ms)| (OrdF64(1.0), ms)).collect();
+        with_huffman_tree(uniform).expect("non-empty compilation")
+    };
+    match weighting {
+        LeafWeighting::Uniform => Ok(balanced(ms)),
+        LeafWeighting::Odds => {
+            let tree = with_huffman_tree(ms.clone()).expect("non-empty compilation");
+            if tree.height() > max_depth {
+                Ok(balanced(ms))
+            } else {
+                Ok(tree)
+            }
+        }
+    }
+}
+
 /// Enumerates a [`Policy::Thresh(k, ..n..)`] into `n` different thresh's.
 ///
 /// ## Strategy
@@ -1077,6 +1671,51 @@ mod compiler_tests {
     use super::*;
     use crate::policy::Concrete;
 
+    #[test]
+    fn compile_tr_with_usage() {
+        let keys: Vec<bitcoin::PublicKey> = [
+            "02c2fd50ceae468857bb7eb32ae9cd4083e6c7e42fbbec179d81134b3e3830586c",
+            "0257f4a2816338436cccabc43aa724cf6e69e43e84c3c8a305212761389dd73a8a",
+            "03500a2b48b0f66c8183cc0d6645ab21cc19c7fad8a33ff04d41c3ece54b0bc1c5",
+            "033ad2d191da4f39512adbaac320cae1f12f298386a4e9d43fd98dec7cf5db2ac9",
+        ]
+        .iter()
+        .map(|k| k.parse().unwrap())
+        .collect();
+        let policy: Concrete<bitcoin::PublicKey> = policy_str!(
+            "or(99@pk({}),1@or(8@pk({}),1@or(pk({}),pk({}))))",
+            keys[0],
+            keys[1],
+            keys[2],
+            keys[3]
+        );
+        let depth_of = |desc: &Descriptor<bitcoin::PublicKey>, key: &bitcoin::PublicKey| match desc
+        {
+            Descriptor::Tr(tr) => tr
+                .iter_scripts()
+                .find(|(_, ms)| ms.to_string() == format!("pk({})", key))
+                .map(|(depth, _)| depth)
+                .unwrap(),
+            _ => unreachable!(),
+        };
+        let options = compiler::TrCompileOptions::default();
+
+        let mut stats = UsageStats::new();
+        assert_eq!(
+            policy.compile_tr_with_usage(&options, &stats).unwrap(),
+            policy.compile_tr_with_options(&options).unwrap()
+        );
+        let by_odds = policy.compile_tr_with_options(&options).unwrap();
+        assert_eq!((depth_of(&by_odds, &keys[1]), depth_of(&by_odds, &keys[3])), (1, 2));
+
+        let leaf: Miniscript<bitcoin::PublicKey, Tap> = ms_str!("pk({})", keys[3]);
+        stats.record_leaf(TapLeafHash::from_script(&leaf.encode(), LeafVersion::TapScript), 10);
+        let by_usage = policy.compile_tr_with_usage(&options, &stats).unwrap();
+        assert_eq!((depth_of(&by_usage, &keys[1]), depth_of(&by_usage, &keys[3])), (2, 1));
+        // Leaves which were never used keep the order given by the odds
+        assert!(depth_of(&by_usage, &keys[1]) <= depth_of(&by_usage, &keys[2]));
+    }
+
     #[test]
     fn test_gen_comb() {
         let policies: Vec<Arc<Concrete<String>>> = vec!["pk(A)", "pk(B)", "pk(C)", "pk(D)"]
@@ -1126,6 +1765,28 @@ mod compiler_tests {
         // pk(A) promoted to the internal key, leaving the script tree empty
         assert_eq!(desc.to_string(), "tr(A)#xyg3grex");
     }
+
+    #[test]
+    fn test_tr_no_key_promotion() {
+        let policy: Policy<String> = policy_str!("or(pk(A),and(pk(B),older(10)))");
+        let desc = policy.compile_tr(Some("UNSPEND".to_owned())).unwrap();
+        let tr = match desc {
+            Descriptor::Tr(ref tr) => tr,
+            _ => unreachable!(),
+        };
+        assert_eq!(tr.internal_key(), "A");
+        assert_eq!(tr.iter_scripts().count(), 1);
+
+        let desc = policy
+            .compile_tr_no_key_promotion("UNSPEND".to_owned())
+            .unwrap();
+        let tr = match desc {
+            Descriptor::Tr(ref tr) => tr,
+            _ => unreachable!(),
+        };
+        assert_eq!(tr.internal_key(), "UNSPEND");
+        assert_eq!(tr.iter_scripts().count(), 2);
+    }
 }
 
 #[cfg(test)]
@@ -1208,6 +1869,27 @@ mod tests {
         assert_eq!(got, want);
     }
 
+    #[test]
+    fn required_keys() {
+        let required = |s: &str| {
+            let policy = Policy::<String>::from_str(s).unwrap();
+            policy
+                .required_keys()
+                .into_iter()
+                .cloned()
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(required("pk(A)"), vec!["A"]);
+        assert_eq!(required("or(pk(A),pk(B))"), Vec::<String>::new());
+        assert_eq!(required("and(pk(A),or(pk(B),and(pk(A),pk(C))))"), vec!["A"]);
+        assert_eq!(required("or(and(pk(A),pk(B)),and(pk(A),older(10)))"), vec!["A"]);
+        assert_eq!(required("thresh(2,pk(A),pk(B),pk(C))"), Vec::<String>::new());
+        assert_eq!(required("thresh(3,pk(A),pk(B),and(pk(C),pk(D)))"), vec!["A", "B", "C", "D"]);
+        assert_eq!(required("thresh(2,and(pk(A),pk(B)),pk(A),after(10))"), vec!["A"]);
+        assert_eq!(required("or(pk(A),UNSATISFIABLE)"), vec!["A"]);
+        assert_eq!(required("and(pk(A),UNSATISFIABLE)"), Vec::<String>::new());
+    }
+
     #[test]
     #[cfg(feature = "compiler")]
     fn num_tap_leaves() {
@@ -1215,10 +1897,88 @@ mod tests {
         assert_eq!(policy.num_tap_leaves(), 2);
     }
 
+    #[test]
+    #[cfg(feature = "compiler")]
+    fn check_compilation() {
+        use crate::Segwitv0;
+
+        let policy =
+            Policy::<String>::from_str("or(thresh(2,pk(A),pk(B),pk(C)),and(pk(D),older(144)))")
+                .unwrap();
+        let (_, report) = policy.compile_checked::<Segwitv0>().unwrap();
+        assert!(report.is_equivalent());
+        assert!(report.thresholds_preserved());
+        assert_eq!(report.thresholds.len(), 3);
+        assert_eq!((report.thresholds[1].k, report.thresholds[1].n), (2, 3));
+
+        // A weakened compilation
+        let policy = Policy::<String>::from_str("thresh(2,pk(A),pk(B),pk(C))").unwrap();
+        let weak = Miniscript::<String, Segwitv0>::from_str("multi(1,A,B,C)").unwrap();
+        let report = policy.check_compilation(&weak).unwrap();
+        assert!(!report.compiled_entails_policy);
+        assert!(report.policy_entails_compiled);
+        assert!(!report.is_equivalent());
+        assert!(!report.thresholds_preserved());
+        assert!(report
+            .to_string()
+            .contains("2-of-3 thresh(2,pk(A),pk(B),pk(C)): false"));
+    }
+
+    #[test]
+    #[cfg(feature = "compiler")]
+    fn compile_diagnosed() {
+        use crate::{Segwitv0, Tap};
+
+        let keys: Vec<String> = (0..120).map(|i| format!("pk(K{})", i)).collect();
+        let policy =
+            Policy::<String>::from_str(&format!("or(pk(A),thresh(60,{}))", keys.join(",")))
+                .unwrap();
+        let diag = policy.compile_diagnosed::<Segwitv0>().unwrap_err();
+        assert_eq!(diag.error, CompilerError::LimitsExceeded);
+        assert!(matches!(diag.sub_policy, Some(Policy::Thresh(ref t)) if t.n() == 120));
+        assert_eq!(
+            diag.suggestions,
+            vec![
+                PolicySuggestion::SplitThreshold { k: 60, n: 120 },
+                PolicySuggestion::UseTaproot
+            ]
+        );
+        assert!(policy.compile_diagnosed::<Tap>().is_ok());
+
+        let policy = Policy::<String>::from_str("or(pk(A),or(after(9),older(9)))").unwrap();
+        let diag = policy.compile_diagnosed::<Segwitv0>().unwrap_err();
+        assert_eq!(diag.error, CompilerError::TopLevelNonSafe);
+        assert_eq!(diag.sub_policy.unwrap().to_string(), "or(1@after(9),1@older(9))");
+        assert_eq!(diag.suggestions, vec![PolicySuggestion::RequireSignature]);
+
+        let policy = Policy::<String>::from_str("and(pk(A),or(after(9),after(10)))").unwrap();
+        let diag = policy.compile_diagnosed::<Segwitv0>().unwrap_err();
+        assert_eq!(diag.error, CompilerError::ImpossibleNonMalleableCompilation);
+        assert_eq!(diag.sub_policy.unwrap().to_string(), "or(1@after(9),1@after(10))");
+    }
+
     #[test]
     #[should_panic]
     fn check_timelocks() {
         // This implicitly tests the check_timelocks API (has height and time locks).
         let _ = Policy::<String>::from_str("and(after(10),after(500000000))").unwrap();
     }
+
+    #[test]
+    fn zero_locks() {
+        assert!(Policy::<String>::from_str("and(pk(A),older(0))").is_err());
+        assert!(Policy::<String>::older(0).is_err());
+        assert!(Policy::<String>::after(0).is_err());
+        assert_eq!(Policy::<String>::older(144).unwrap().to_string(), "older(144)");
+
+        let (policy, locks) =
+            Policy::<String>::from_str_zero_locks("or(9@and(pk(A),older(0)),1@after(0))").unwrap();
+        assert_eq!(policy.to_string(), "or(9@and(pk(A),TRIVIAL),1@TRIVIAL)");
+        assert_eq!(locks, ZeroLocks { older: 1, after: 1 });
+
+        let (policy, locks) =
+            Policy::<String>::from_str_zero_locks("and(pk(A),older(10))").unwrap();
+        assert_eq!(policy.to_string(), "and(pk(A),older(10))");
+        assert!(locks.is_empty());
+    }
 }
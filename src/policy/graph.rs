@@ -0,0 +1,168 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! # Spending Condition Graphs
+//!
+//! Export of the spending conditions of a semantic policy, or of a descriptor
+//! once lifted, as a graph whose nodes are conditions or combinations of
+//! conditions. The graph can be rendered by graph visualization tools from its
+//! DOT representation.
+//!
+
+use core::fmt;
+
+use super::semantic::Policy;
+use super::Liftable;
+use crate::descriptor::Descriptor;
+use crate::prelude::*;
+use crate::{Error, MiniscriptKey};
+
+/// A node of a [`SpendGraph`]
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SpendNode<Pk: MiniscriptKey> {
+    /// All the children must be satisfied
+    And,
+    /// Any of the children must be satisfied
+    Or,
+    /// This number of the children must be satisfied
+    Thresh(usize),
+    /// A key, timelock, hash lock, or trivial or unsatisfiable condition
+    Condition(Policy<Pk>),
+}
+
+impl<Pk: MiniscriptKey> fmt::Display for SpendNode<Pk> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SpendNode::And => f.write_str("and"),
+            SpendNode::Or => f.write_str("or"),
+            SpendNode::Thresh(k) => write!(f, "thresh({})", k),
+            SpendNode::Condition(ref policy) => fmt::Display::fmt(policy, f),
+        }
+    }
+}
+
+/// The spending conditions of a policy as a tree-shaped graph
+///
+/// The root is the node at index 0. Edges go from a combination of
+/// conditions to each of its children, in order.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SpendGraph<Pk: MiniscriptKey> {
+    /// The nodes, in pre-order
+    pub nodes: Vec<SpendNode<Pk>>,
+    /// The edges, as pairs of indexes of a parent and a child in `nodes`
+    pub edges: Vec<(usize, usize)>,
+}
+
+impl<Pk: MiniscriptKey> SpendGraph<Pk> {
+    /// The indexes of the children of the node at `index`
+    pub fn children(&self, index: usize) -> impl Iterator<Item = usize> + '_ {
+        self.edges
+            .iter()
+            .filter(move |(parent, _)| *parent == index)
+            .map(|(_, child)| *child)
+    }
+
+    /// Renders the graph in the DOT language, with combinations of conditions
+    /// drawn as ellipses and conditions as boxes
+    pub fn to_dot(&self) -> String {
+        let mut ret = String::from("digraph spend {\n");
+        for (i, node) in self.nodes.iter().enumerate() {
+            let label = node.to_string().replace('\\', "\\\\").replace('"', "\\\"");
+            let shape = match *node {
+                SpendNode::Condition(_) => "box",
+                _ => "ellipse",
+            };
+            ret.push_str(&format!("  n{} [label=\"{}\", shape={}];\n", i, label, shape));
+        }
+        for (parent, child) in &self.edges {
+            ret.push_str(&format!("  n{} -> n{};\n", parent, child));
+        }
+        ret.push('}');
+        ret
+    }
+}
+
+impl<Pk: MiniscriptKey> Policy<Pk> {
+    /// Converts the policy to a graph of its spending conditions
+    ///
+    /// Thresholds requiring all their children become [`SpendNode::And`] nodes
+    /// and thresholds requiring one of them [`SpendNode::Or`] nodes. Normalize
+    /// the policy beforehand to merge nested conjunctions and disjunctions.
+    pub fn to_spend_graph(&self) -> SpendGraph<Pk> {
+        let mut graph = SpendGraph { nodes: vec![], edges: vec![] };
+        let mut stack = vec![(self, None)];
+        while let Some((policy, parent)) = stack.pop() {
+            let index = graph.nodes.len();
+            if let Some(parent) = parent {
+                graph.edges.push((parent, index));
+            }
+            let node = match *policy {
+                Policy::Thresh(ref thresh) => {
+                    stack.extend(thresh.iter().rev().map(|sub| (&**sub, Some(index))));
+                    if thresh.is_and() {
+                        SpendNode::And
+                    } else if thresh.is_or() {
+                        SpendNode::Or
+                    } else {
+                        SpendNode::Thresh(thresh.k())
+                    }
+                }
+                ref leaf => SpendNode::Condition(leaf.clone()),
+            };
+            graph.nodes.push(node);
+        }
+        graph
+    }
+}
+
+impl<Pk: MiniscriptKey> Descriptor<Pk> {
+    /// Lifts the descriptor and converts it to a normalized graph of its
+    /// spending conditions, see [`Policy::to_spend_graph`]
+    pub fn to_spend_graph(&self) -> Result<SpendGraph<Pk>, Error> {
+        Ok(self.lift()?.normalized().to_spend_graph())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::str::FromStr;
+
+    use super::*;
+
+    #[test]
+    fn spend_graph() {
+        let desc =
+            Descriptor::<String>::from_str("wsh(or_d(multi(2,A,B,C),and_v(v:pk(D),older(144))))")
+                .unwrap();
+        let graph = desc.to_spend_graph().unwrap();
+        let labels: Vec<_> = graph.nodes.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            labels,
+            vec![
+                "or",
+                "thresh(2)",
+                "pk(A)",
+                "pk(B)",
+                "pk(C)",
+                "and",
+                "pk(D)",
+                "older(144)"
+            ]
+        );
+        assert_eq!(graph.children(0).collect::<Vec<_>>(), vec![1, 5]);
+        assert_eq!(graph.children(1).collect::<Vec<_>>(), vec![2, 3, 4]);
+        assert_eq!(graph.children(2).count(), 0);
+        assert_eq!(graph.edges.len(), graph.nodes.len() - 1);
+
+        let dot = graph.to_dot();
+        assert!(dot.starts_with("digraph spend {\n  n0 [label=\"or\", shape=ellipse];\n"));
+        assert!(dot.contains("  n7 [label=\"older(144)\", shape=box];\n"));
+        assert!(dot.contains("  n5 -> n7;\n"));
+        assert!(dot.ends_with('}'));
+
+        let graph = Policy::<String>::from_str("pk(A)")
+            .unwrap()
+            .to_spend_graph();
+        assert_eq!(graph.nodes, vec![SpendNode::Condition(Policy::Key("A".to_owned()))]);
+        assert!(graph.edges.is_empty());
+    }
+}
@@ -0,0 +1,422 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! # Compilation Hints
+//!
+//! Annotations of concrete policy strings which direct the compiler where its
+//! own choices aren't the ones wanted. A hint directly follows the closing
+//! parenthesis of the fragment it applies to:
+//!
+//! * `!leaf` puts the fragment in a tap leaf of its own, as in
+//!   `or(pk(A),or(pk(B),pk(C))!leaf)`: a disjunction isn't split over several
+//!   leaves, and a key isn't promoted to the internal key.
+//! * `^bare`, `^sh`, `^wsh`, `^shwsh` and `^tr`, after the whole policy only,
+//!   as in `thresh(2,pk(A),pk(B),pk(C))^wsh`, pick the descriptor the policy
+//!   is compiled into.
+//!
+
+use core::fmt;
+use core::str::FromStr;
+#[cfg(feature = "std")]
+use std::error;
+
+use super::compiler::{CompilerError, TrCompileOptions};
+use super::concrete::{DescriptorCtx, Policy};
+use crate::descriptor::Descriptor;
+use crate::prelude::*;
+use crate::{Error, FromStrKey, MiniscriptKey, Segwitv0};
+
+/// The descriptor a policy is compiled into, as hinted by a `^` annotation
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Hash)]
+pub enum DescriptorHint {
+    /// `^bare`, a bare descriptor
+    Bare,
+    /// `^sh`, a P2SH descriptor
+    Sh,
+    /// `^wsh`, a P2WSH descriptor
+    Wsh,
+    /// `^shwsh`, a P2WSH descriptor nested in P2SH
+    ShWsh,
+    /// `^tr`, a Taproot descriptor
+    Tr,
+}
+
+impl DescriptorHint {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "bare" => Some(DescriptorHint::Bare),
+            "sh" => Some(DescriptorHint::Sh),
+            "wsh" => Some(DescriptorHint::Wsh),
+            "shwsh" => Some(DescriptorHint::ShWsh),
+            "tr" => Some(DescriptorHint::Tr),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for DescriptorHint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            DescriptorHint::Bare => "^bare",
+            DescriptorHint::Sh => "^sh",
+            DescriptorHint::Wsh => "^wsh",
+            DescriptorHint::ShWsh => "^shwsh",
+            DescriptorHint::Tr => "^tr",
+        })
+    }
+}
+
+/// The hints parsed from a policy string by [`Policy::from_str_with_hints`]
+#[derive(Clone, PartialEq, Eq, Debug, Hash)]
+pub struct CompilationHints<Pk: MiniscriptKey> {
+    /// The descriptor to compile into, if hinted
+    pub descriptor: Option<DescriptorHint>,
+    /// The fragments hinted with `!leaf`, to be put in tap leaves of their own
+    pub own_leaves: Vec<Policy<Pk>>,
+}
+
+impl<Pk: MiniscriptKey> Default for CompilationHints<Pk> {
+    fn default() -> Self { CompilationHints { descriptor: None, own_leaves: vec![] } }
+}
+
+/// An error parsing a policy annotated with compilation hints
+#[derive(Debug)]
+pub enum HintParseError {
+    /// A fragment is hinted with an unknown `!` hint, the only one being `!leaf`
+    UnknownFragmentHint {
+        /// The name of the hint, without its `!`
+        hint: String,
+        /// The fragment the hint follows
+        fragment: String,
+    },
+    /// The policy is hinted with an unknown `^` descriptor hint
+    UnknownDescriptorHint(String),
+    /// A descriptor hint follows a fragment rather than the whole policy
+    MisplacedDescriptorHint {
+        /// The hint
+        hint: DescriptorHint,
+        /// The fragment the hint follows
+        fragment: String,
+    },
+    /// The policy, or a hinted fragment, doesn't parse once the hints are removed
+    Policy(Error),
+}
+
+impl fmt::Display for HintParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            HintParseError::UnknownFragmentHint { ref hint, ref fragment } => {
+                write!(f, "unknown hint !{} on {}, the only fragment hint is !leaf", hint, fragment)
+            }
+            HintParseError::UnknownDescriptorHint(ref hint) => write!(
+                f,
+                "unknown descriptor hint ^{}, expected one of ^bare, ^sh, ^wsh, ^shwsh or ^tr",
+                hint
+            ),
+            HintParseError::MisplacedDescriptorHint { hint, ref fragment } => write!(
+                f,
+                "descriptor hint {} on {}, descriptor hints only apply to the whole policy",
+                hint, fragment
+            ),
+            HintParseError::Policy(ref e) => fmt::Display::fmt(e, f),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl error::Error for HintParseError {
+    fn cause(&self) -> Option<&dyn error::Error> {
+        match *self {
+            HintParseError::UnknownFragmentHint { .. }
+            | HintParseError::UnknownDescriptorHint(_)
+            | HintParseError::MisplacedDescriptorHint { .. } => None,
+            HintParseError::Policy(ref e) => Some(e),
+        }
+    }
+}
+
+impl From<Error> for HintParseError {
+    fn from(e: Error) -> Self { HintParseError::Policy(e) }
+}
+
+/// An error compiling a policy according to its hints
+#[derive(Debug)]
+pub enum HintError {
+    /// Fragments were hinted with `!leaf`, but the policy is compiled into a
+    /// descriptor without tap leaves
+    LeafOutsideTaproot(DescriptorHint),
+    /// The policy couldn't be compiled, e.g. with
+    /// [`CompilerError::LeafNotDisjunct`] if a fragment hinted with `!leaf` is
+    /// under a conjunction
+    Compilation(Error),
+}
+
+impl fmt::Display for HintError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            HintError::LeafOutsideTaproot(desc) => write!(
+                f,
+                "!leaf hints require a Taproot descriptor, but the policy is hinted {}",
+                desc
+            ),
+            HintError::Compilation(ref e) => fmt::Display::fmt(e, f),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl error::Error for HintError {
+    fn cause(&self) -> Option<&dyn error::Error> {
+        match *self {
+            HintError::LeafOutsideTaproot(_) => None,
+            HintError::Compilation(ref e) => Some(e),
+        }
+    }
+}
+
+impl From<CompilerError> for HintError {
+    fn from(e: CompilerError) -> Self { HintError::Compilation(Error::CompilerError(e)) }
+}
+
+impl<Pk: FromStrKey> Policy<Pk> {
+    /// Parses a policy annotated with compilation hints, see the [module
+    /// documentation](self) for their syntax
+    ///
+    /// # Errors
+    ///
+    /// If the policy doesn't parse once its hints are removed, a hint is unknown,
+    /// or a descriptor hint doesn't follow the whole policy.
+    pub fn from_str_with_hints(s: &str) -> Result<(Self, CompilationHints<Pk>), HintParseError> {
+        let mut stripped = String::with_capacity(s.len());
+        let mut hints = CompilationHints::default();
+        // The offset in `stripped` of the fragment being parsed at each depth
+        let mut starts = vec![0];
+        let mut chars = s.chars().peekable();
+        while let Some(ch) = chars.next() {
+            stripped.push(ch);
+            match ch {
+                '(' => starts.push(stripped.len()),
+                ',' => {
+                    if let Some(start) = starts.last_mut() {
+                        *start = stripped.len();
+                    }
+                }
+                ')' => {
+                    // Unbalanced parentheses are reported by the policy parser
+                    if starts.len() > 1 {
+                        starts.pop();
+                    }
+                    let sigil = match chars.peek() {
+                        Some(&c) if c == '!' || c == '^' => c,
+                        _ => continue,
+                    };
+                    chars.next();
+                    let mut name = String::new();
+                    while let Some(&c) = chars.peek() {
+                        if !c.is_ascii_alphanumeric() {
+                            break;
+                        }
+                        name.push(c);
+                        chars.next();
+                    }
+
+                    let fragment = &stripped[starts[starts.len() - 1]..];
+                    // Strip the probability of a disjunction branch
+                    let fragment = match fragment.split_once('@') {
+                        Some((prob, frag)) if prob.chars().all(|c| c.is_ascii_digit()) => frag,
+                        _ => fragment,
+                    };
+                    if sigil == '!' {
+                        if name != "leaf" {
+                            return Err(HintParseError::UnknownFragmentHint {
+                                hint: name,
+                                fragment: fragment.to_owned(),
+                            });
+                        }
+                        hints.own_leaves.push(Policy::from_str(fragment)?);
+                    } else {
+                        let hint = DescriptorHint::from_name(&name)
+                            .ok_or(HintParseError::UnknownDescriptorHint(name))?;
+                        if starts.len() > 1 || chars.peek().is_some() {
+                            return Err(HintParseError::MisplacedDescriptorHint {
+                                hint,
+                                fragment: fragment.to_owned(),
+                            });
+                        }
+                        hints.descriptor = Some(hint);
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok((Policy::from_str(&stripped)?, hints))
+    }
+}
+
+impl<Pk: MiniscriptKey> Policy<Pk> {
+    /// Compiles the policy as directed by `hints`
+    ///
+    /// Without a descriptor hint, the policy is compiled into a Taproot descriptor
+    /// if any fragment is hinted `!leaf`, and into a P2WSH descriptor otherwise.
+    /// Taproot descriptors are compiled by [`Policy::compile_tr_with_options`], with
+    /// the fragments hinted `!leaf` as [`TrCompileOptions::own_leaves`], and
+    /// `unspendable_key` as the internal key if no key of the policy is promoted.
+    ///
+    /// # Errors
+    ///
+    /// If the hints can't be honored, or the policy can't be compiled.
+    pub fn compile_with_hints(
+        &self,
+        hints: &CompilationHints<Pk>,
+        unspendable_key: Option<Pk>,
+    ) -> Result<Descriptor<Pk>, HintError> {
+        let desc = match hints.descriptor {
+            Some(desc) => desc,
+            None if hints.own_leaves.is_empty() => DescriptorHint::Wsh,
+            None => DescriptorHint::Tr,
+        };
+        if desc != DescriptorHint::Tr && !hints.own_leaves.is_empty() {
+            return Err(HintError::LeafOutsideTaproot(desc));
+        }
+
+        let ctx = match desc {
+            DescriptorHint::Bare => DescriptorCtx::Bare,
+            DescriptorHint::Sh => DescriptorCtx::Sh,
+            DescriptorHint::Wsh => DescriptorCtx::Wsh,
+            DescriptorHint::ShWsh => DescriptorCtx::ShWsh,
+            DescriptorHint::Tr => {
+                let options = TrCompileOptions {
+                    own_leaves: hints.own_leaves.clone(),
+                    ..TrCompileOptions::new(unspendable_key)
+                };
+                return self
+                    .compile_tr_with_options(&options)
+                    .map_err(|e| HintError::Compilation(Error::CompilerError(e)));
+            }
+        };
+        self.compile_to_descriptor::<Segwitv0>(ctx)
+            .map_err(HintError::Compilation)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(s: &str) -> Result<(Policy<String>, CompilationHints<String>), HintParseError> {
+        Policy::from_str_with_hints(s)
+    }
+
+    fn tr_leaves(desc: &Descriptor<String>) -> (String, Vec<String>) {
+        match *desc {
+            Descriptor::Tr(ref tr) => (
+                tr.internal_key().clone(),
+                tr.iter_scripts().map(|(_, ms)| ms.to_string()).collect(),
+            ),
+            _ => panic!("expected a Taproot descriptor, got {}", desc),
+        }
+    }
+
+    #[test]
+    fn parse_hints() {
+        let (policy, hints) = parse("or(pk(A),or(pk(B),pk(C))!leaf)").unwrap();
+        assert_eq!(policy, Policy::from_str("or(pk(A),or(pk(B),pk(C)))").unwrap());
+        assert_eq!(hints.descriptor, None);
+        assert_eq!(hints.own_leaves, vec![Policy::from_str("or(pk(B),pk(C))").unwrap()]);
+
+        let (policy, hints) = parse("or(9@pk(A)!leaf,1@and(pk(B)!leaf,older(10)))^tr").unwrap();
+        assert_eq!(policy, Policy::from_str("or(9@pk(A),1@and(pk(B),older(10)))").unwrap());
+        assert_eq!(hints.descriptor, Some(DescriptorHint::Tr));
+        assert_eq!(
+            hints.own_leaves,
+            vec![
+                Policy::from_str("pk(A)").unwrap(),
+                Policy::from_str("pk(B)").unwrap()
+            ]
+        );
+
+        let (_, hints) = parse("thresh(2,pk(A),pk(B),pk(C))^shwsh").unwrap();
+        assert_eq!(
+            hints,
+            CompilationHints { descriptor: Some(DescriptorHint::ShWsh), own_leaves: vec![] }
+        );
+
+        // Unknown and misplaced hints
+        match parse("pk(A)!foo") {
+            Err(HintParseError::UnknownFragmentHint { hint, fragment }) => {
+                assert_eq!((hint.as_str(), fragment.as_str()), ("foo", "pk(A)"))
+            }
+            res => panic!("unexpected result {:?}", res),
+        }
+        assert!(matches!(
+            parse("pk(A)^p2pkh"),
+            Err(HintParseError::UnknownDescriptorHint(ref hint)) if hint == "p2pkh"
+        ));
+        assert!(matches!(
+            parse("or(pk(A),pk(B)^wsh)"),
+            Err(HintParseError::MisplacedDescriptorHint { hint: DescriptorHint::Wsh, .. })
+        ));
+        assert!(matches!(
+            parse("or(pk(A),pk(B))^wsh!leaf"),
+            Err(HintParseError::MisplacedDescriptorHint { hint: DescriptorHint::Wsh, .. })
+        ));
+        assert!(matches!(parse("or(pk(A),pk(B)!leaf"), Err(HintParseError::Policy(_))));
+    }
+
+    #[test]
+    fn compile_hints() {
+        // Without hints, each key gets a leaf of its own
+        let (policy, hints) = parse("or(pk(A),or(pk(B),pk(C))!leaf)").unwrap();
+        let (key, leaves) = tr_leaves(&policy.compile_tr(None).unwrap());
+        assert_eq!((key.as_str(), leaves.len()), ("A", 2));
+        let (key, leaves) = tr_leaves(&policy.compile_with_hints(&hints, None).unwrap());
+        assert_eq!(key, "A");
+        assert_eq!(leaves.len(), 1);
+
+        // A hinted key isn't promoted to the internal key, however likely
+        let (policy, hints) = parse("or(9@pk(A)!leaf,1@pk(B))").unwrap();
+        let (key, leaves) = tr_leaves(&policy.compile_with_hints(&hints, None).unwrap());
+        assert_eq!(key, "B");
+        assert_eq!(leaves, vec!["pk(A)".to_owned()]);
+        let (policy, hints) = parse("or(pk(A)!leaf,pk(B)!leaf)").unwrap();
+        assert!(matches!(
+            policy.compile_with_hints(&hints, None),
+            Err(HintError::Compilation(Error::CompilerError(CompilerError::NoInternalKey)))
+        ));
+        let (key, leaves) = tr_leaves(
+            &policy
+                .compile_with_hints(&hints, Some("U".to_owned()))
+                .unwrap(),
+        );
+        assert_eq!((key.as_str(), leaves.len()), ("U", 2));
+
+        let (policy, hints) = parse("thresh(2,pk(A),pk(B),pk(C))^sh").unwrap();
+        assert!(matches!(policy.compile_with_hints(&hints, None).unwrap(), Descriptor::Sh(_)));
+        let (policy, hints) = parse("thresh(2,pk(A),pk(B),pk(C))").unwrap();
+        assert!(matches!(policy.compile_with_hints(&hints, None).unwrap(), Descriptor::Wsh(_)));
+
+        // Hints which can't be honored
+        let (policy, hints) = parse("or(pk(A)!leaf,pk(B))^wsh").unwrap();
+        assert!(matches!(
+            policy.compile_with_hints(&hints, None),
+            Err(HintError::LeafOutsideTaproot(DescriptorHint::Wsh))
+        ));
+        let (policy, hints) = parse("or(pk(A),and(pk(B)!leaf,older(10)))").unwrap();
+        assert!(matches!(
+            policy.compile_with_hints(&hints, None),
+            Err(HintError::Compilation(Error::CompilerError(CompilerError::LeafNotDisjunct {
+                index: 0
+            })))
+        ));
+
+        // Hints go through the same compiler as compile_tr
+        let (policy, hints) = parse("or(9@pk(A),1@or(pk(B),pk(C))!leaf)").unwrap();
+        let options = TrCompileOptions {
+            own_leaves: hints.own_leaves.clone(),
+            ..TrCompileOptions::new(None)
+        };
+        assert_eq!(
+            policy.compile_with_hints(&hints, None).unwrap(),
+            policy.compile_tr_with_options(&options).unwrap()
+        );
+    }
+}
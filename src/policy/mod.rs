@@ -16,6 +16,11 @@ use std::error;
 #[cfg(feature = "compiler")]
 pub mod compiler;
 pub mod concrete;
+#[cfg(feature = "graph")]
+pub mod graph;
+#[cfg(feature = "compiler")]
+pub mod hints;
+pub mod schedule;
 pub mod semantic;
 
 pub use self::concrete::Policy as Concrete;
@@ -24,9 +29,9 @@ use crate::descriptor::Descriptor;
 use crate::iter::TreeLike as _;
 use crate::miniscript::{Miniscript, ScriptContext};
 use crate::sync::Arc;
+use crate::{expression, Error, MiniscriptKey, Terminal, Threshold};
 #[cfg(all(not(feature = "std"), not(test)))]
-use crate::Vec;
-use crate::{Error, MiniscriptKey, Terminal, Threshold};
+use crate::{String, Vec};
 
 /// Policy entailment algorithm maximum number of terminals allowed.
 const ENTAILMENT_MAX_TERMINALS: usize = 20;
@@ -88,6 +93,69 @@ impl error::Error for LiftError {
     }
 }
 
+/// The degenerate `older(0)` and `after(0)` timelocks found while parsing a
+/// policy with `from_str_zero_locks`
+///
+/// Miniscript rejects these timelocks, which don't restrict the spending
+/// transaction in any meaningful way, but policies imported from other tools
+/// may contain them. A non-empty value is meant to be reported as a lint.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default, Hash)]
+pub struct ZeroLocks {
+    /// The number of `older(0)` fragments
+    pub older: usize,
+    /// The number of `after(0)` fragments
+    pub after: usize,
+}
+
+impl ZeroLocks {
+    /// Whether no degenerate timelock was found
+    pub fn is_empty(&self) -> bool { self.older == 0 && self.after == 0 }
+}
+
+impl fmt::Display for ZeroLocks {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} older(0) and {} after(0) replaced by TRIVIAL", self.older, self.after)
+    }
+}
+
+/// Rewrites the `older(0)` and `after(0)` fragments of the policy string `s`
+/// as `TRIVIAL`, keeping any probability prefix.
+pub(crate) fn replace_zero_locks(s: &str) -> Result<(String, ZeroLocks), Error> {
+    fn write(tree: &expression::Tree, out: &mut String, locks: &mut ZeroLocks) {
+        let (prefix, frag) = match tree.name.split_once('@') {
+            Some((prob, frag)) => (&tree.name[..prob.len() + 1], frag),
+            None => ("", tree.name),
+        };
+        let zero = tree.args.len() == 1 && tree.args[0].args.is_empty() && tree.args[0].name == "0";
+        match frag {
+            "older" if zero => locks.older += 1,
+            "after" if zero => locks.after += 1,
+            _ => {
+                out.push_str(tree.name);
+                if !tree.args.is_empty() {
+                    out.push('(');
+                    for (i, arg) in tree.args.iter().enumerate() {
+                        if i > 0 {
+                            out.push(',');
+                        }
+                        write(arg, out, locks);
+                    }
+                    out.push(')');
+                }
+                return;
+            }
+        }
+        out.push_str(prefix);
+        out.push_str("TRIVIAL");
+    }
+
+    let tree = expression::Tree::from_str(s)?;
+    let mut out = String::with_capacity(s.len());
+    let mut locks = ZeroLocks::default();
+    write(&tree, &mut out, &mut locks);
+    Ok((out, locks))
+}
+
 impl<Pk: MiniscriptKey, Ctx: ScriptContext> Miniscript<Pk, Ctx> {
     /// Lifting corresponds to conversion of a miniscript into a [`Semantic`]
     /// policy for human readable or machine analysis. However, naively lifting
@@ -182,6 +250,9 @@ impl<Pk: MiniscriptKey> Liftable<Pk> for Descriptor<Pk> {
             Descriptor::Wsh(ref wsh) => wsh.lift(),
             Descriptor::Sh(ref sh) => sh.lift(),
             Descriptor::Tr(ref tr) => tr.lift(),
+            Descriptor::Data(ref data) => data.lift(),
+            Descriptor::Wother(ref wother) => wother.lift(),
+            Descriptor::RawTr(ref rawtr) => rawtr.lift(),
         }
     }
 }
@@ -235,7 +306,7 @@ mod tests {
 
     use super::*;
     #[cfg(feature = "compiler")]
-    use crate::descriptor::Tr;
+    use crate::descriptor::{KeyPosition, RepeatedKey, Tr};
     use crate::miniscript::context::Segwitv0;
     use crate::prelude::*;
     use crate::RelLockTime;
@@ -401,10 +472,27 @@ mod tests {
         }
 
         {
-            // Invalid policy compilation (Duplicate PubKeys)
+            // Keys may be repeated across leaves
             let policy: Concrete<String> = policy_str!("or(and(pk(A),pk(B)),and(pk(A),pk(D)))");
-            let descriptor = policy.compile_tr(Some(unspendable_key.clone()));
+            let descriptor = policy.compile_tr(Some(unspendable_key.clone())).unwrap();
+            let tr = match descriptor {
+                Descriptor::Tr(ref tr) => tr,
+                _ => unreachable!(),
+            };
+            assert_eq!(
+                tr.repeated_keys(),
+                vec![RepeatedKey {
+                    key: "A".to_owned(),
+                    positions: vec![
+                        KeyPosition::Leaf { index: 0, depth: 1 },
+                        KeyPosition::Leaf { index: 1, depth: 1 }
+                    ],
+                }]
+            );
 
+            // ...but not within a leaf (Duplicate PubKeys)
+            let policy: Concrete<String> = policy_str!("or(and(pk(A),pk(A)),pk(B))");
+            let descriptor = policy.compile_tr(Some(unspendable_key.clone()));
             assert_eq!(descriptor.unwrap_err().to_string(), "Policy contains duplicate keys");
         }
 
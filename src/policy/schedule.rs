@@ -0,0 +1,183 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! # Spending Schedules
+//!
+//! Conversion of a policy into the list of sets of keys which become able to
+//! spend as timelocks expire, e.g. the decaying multisig of a vault: a 3-of-3
+//! at first, a 2-of-3 after a year and a recovery key after two years.
+//!
+
+use bitcoin::{absolute, relative};
+
+use crate::policy::semantic::{PolicyError, SpendPathCount};
+use crate::policy::{Concrete, Liftable, Semantic};
+use crate::prelude::*;
+use crate::{AbsLockTime, Error, MiniscriptKey, RelLockTime};
+
+/// A point of a spending schedule, see [`Concrete::spending_schedule`]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ScheduleStep<Pk: MiniscriptKey> {
+    /// The absolute timelock which must have expired, if any
+    pub after: Option<AbsLockTime>,
+    /// The relative timelock which must have expired, if any
+    pub older: Option<RelLockTime>,
+    /// The sets of keys which can spend from this point on, and couldn't before
+    pub key_sets: Vec<BTreeSet<Pk>>,
+}
+
+/// A spending path of a policy, reduced to what matters for the schedule
+struct Authorization<Pk: MiniscriptKey> {
+    after: Option<AbsLockTime>,
+    older: Option<RelLockTime>,
+    keys: BTreeSet<Pk>,
+}
+
+impl<Pk: MiniscriptKey> Authorization<Pk> {
+    /// Reduces a spending path, returns `None` if the path can't be taken with
+    /// signatures only, or can never be taken because it mixes timelock units.
+    fn from_path(path: Vec<Semantic<Pk>>) -> Option<Self> {
+        let mut auth = Authorization { after: None, older: None, keys: BTreeSet::new() };
+        for cond in path {
+            match cond {
+                Semantic::Key(pk) => {
+                    auth.keys.insert(pk);
+                }
+                Semantic::After(t) => match auth.after {
+                    Some(max) if max.is_block_height() != t.is_block_height() => return None,
+                    Some(max) if max >= t => {}
+                    _ => auth.after = Some(t),
+                },
+                Semantic::Older(t) => match auth.older {
+                    Some(max) if max.is_height_locked() != t.is_height_locked() => return None,
+                    Some(max) if max >= t => {}
+                    _ => auth.older = Some(t),
+                },
+                Semantic::Trivial => {}
+                _ => return None,
+            }
+        }
+        Some(auth)
+    }
+
+    /// Whether `self` is available whenever `other` is, i.e. it needs a subset of
+    /// its keys and its timelocks are implied by the ones of `other`.
+    fn dominates(&self, other: &Self) -> bool {
+        let after = match (self.after, other.after) {
+            (None, _) => true,
+            (Some(_), None) => false,
+            (Some(a), Some(b)) => {
+                absolute::LockTime::from(a).is_implied_by(absolute::LockTime::from(b))
+            }
+        };
+        let older = match (self.older, other.older) {
+            (None, _) => true,
+            (Some(_), None) => false,
+            (Some(a), Some(b)) => {
+                relative::LockTime::from(a).is_implied_by(relative::LockTime::from(b))
+            }
+        };
+        after && older && self.keys.is_subset(&other.keys)
+    }
+}
+
+impl<Pk: MiniscriptKey> Concrete<Pk> {
+    /// Lists the sets of keys able to spend as the timelocks of the policy expire.
+    ///
+    /// Every step gives the absolute and relative timelocks which must have expired
+    /// and the minimal sets of keys which can then spend, excluding the ones which
+    /// already could at an earlier step. The steps are ordered by absolute then
+    /// relative timelock, the first step having no timelock if the policy can be
+    /// spent right away. Spending paths requiring hash preimages, or mixing timelock
+    /// units, are ignored.
+    ///
+    /// # Errors
+    ///
+    /// If the policy can't be lifted, or has more than `limit` spending paths, see
+    /// [`Semantic::spend_path_count`], reported as [`PolicyError::TooManySpendPaths`].
+    pub fn spending_schedule(&self, limit: usize) -> Result<Vec<ScheduleStep<Pk>>, Error> {
+        let policy = self.lift()?;
+        if policy.spend_path_count(limit) == SpendPathCount::ExceedsLimit {
+            return Err(Error::SemanticPolicy(PolicyError::TooManySpendPaths(limit)));
+        }
+
+        let mut auths: Vec<Authorization<Pk>> = vec![];
+        for auth in policy
+            .spend_paths_iter()
+            .filter_map(Authorization::from_path)
+        {
+            if auths.iter().any(|a| a.dominates(&auth)) {
+                continue;
+            }
+            auths.retain(|a| !auth.dominates(a));
+            auths.push(auth);
+        }
+
+        let mut steps: BTreeMap<_, Vec<BTreeSet<Pk>>> = BTreeMap::new();
+        for auth in auths {
+            steps
+                .entry((auth.after, auth.older))
+                .or_default()
+                .push(auth.keys);
+        }
+        Ok(steps
+            .into_iter()
+            .map(|((after, older), mut key_sets)| {
+                key_sets.sort();
+                ScheduleStep { after, older, key_sets }
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::str::FromStr;
+
+    use super::*;
+
+    fn keys(names: &[&str]) -> BTreeSet<String> { names.iter().map(|s| s.to_string()).collect() }
+
+    #[test]
+    fn spending_schedule() {
+        // A vault decaying from 3-of-3 to 2-of-3 after 1000 blocks, then to a
+        // recovery key after 2000 blocks.
+        let policy = Concrete::<String>::from_str(
+            "or(thresh(3,pk(A),pk(B),pk(C)),or(and(thresh(2,pk(A),pk(B),pk(C)),older(1000)),and(pk(R),older(2000))))",
+        )
+        .unwrap();
+        let schedule = policy.spending_schedule(100).unwrap();
+        assert_eq!(schedule.len(), 3);
+        assert_eq!(schedule[0].older, None);
+        assert_eq!(schedule[0].key_sets, vec![keys(&["A", "B", "C"])]);
+        assert_eq!(schedule[1].older, Some(RelLockTime::from_height(1000)));
+        assert_eq!(
+            schedule[1].key_sets,
+            vec![keys(&["A", "B"]), keys(&["A", "C"]), keys(&["B", "C"])]
+        );
+        assert_eq!(schedule[2].older, Some(RelLockTime::from_height(2000)));
+        assert_eq!(schedule[2].key_sets, vec![keys(&["R"])]);
+        assert!(schedule.iter().all(|step| step.after.is_none()));
+
+        // Sets available earlier aren't repeated, hash paths are ignored
+        let policy = Concrete::<String>::from_str(
+            "or(pk(A),or(and(pk(A),after(100)),and(pk(B),sha256(1111111111111111111111111111111111111111111111111111111111111111))))",
+        )
+        .unwrap();
+        let schedule = policy.spending_schedule(100).unwrap();
+        assert_eq!(
+            schedule,
+            vec![ScheduleStep { after: None, older: None, key_sets: vec![keys(&["A"])] }]
+        );
+
+        let policy = Concrete::<String>::from_str("and(pk(A),after(100))").unwrap();
+        let schedule = policy.spending_schedule(100).unwrap();
+        assert_eq!(schedule[0].after, Some(AbsLockTime::from_consensus(100).unwrap()));
+
+        assert!(matches!(
+            Concrete::<String>::from_str("thresh(2,pk(A),pk(B),pk(C))")
+                .unwrap()
+                .spending_schedule(2),
+            Err(Error::SemanticPolicy(PolicyError::TooManySpendPaths(2)))
+        ));
+    }
+}
@@ -12,13 +12,13 @@ use std::error;
 
 use bitcoin::{absolute, relative};
 
-use super::ENTAILMENT_MAX_TERMINALS;
+use super::{ZeroLocks, ENTAILMENT_MAX_TERMINALS};
 use crate::iter::{Tree, TreeLike};
 use crate::prelude::*;
 use crate::sync::Arc;
 use crate::{
-    errstr, expression, AbsLockTime, Error, ForEachKey, FromStrKey, MiniscriptKey, RelLockTime,
-    Threshold, Translator,
+    errstr, expression, policy, AbsLockTime, AbsLockTimeError, Error, ForEachKey, FromStrKey,
+    MiniscriptKey, RelLockTime, RelLockTimeError, Threshold, Translator,
 };
 
 /// Abstract policy which corresponds to the semantics of a miniscript and
@@ -60,6 +60,8 @@ pub enum PolicyError {
     InsufficientArgsforOr,
     /// Entailment max terminals exceeded.
     EntailmentMaxTerminals,
+    /// The policy has more spending paths than the given limit.
+    TooManySpendPaths(usize),
 }
 
 impl fmt::Display for PolicyError {
@@ -74,6 +76,9 @@ impl fmt::Display for PolicyError {
             PolicyError::EntailmentMaxTerminals => {
                 write!(f, "Policy entailment only supports {} terminals", ENTAILMENT_MAX_TERMINALS)
             }
+            PolicyError::TooManySpendPaths(limit) => {
+                write!(f, "policy has more than {} spending paths", limit)
+            }
         }
     }
 }
@@ -84,7 +89,8 @@ impl error::Error for PolicyError {
         match self {
             PolicyError::InsufficientArgsforAnd
             | PolicyError::InsufficientArgsforOr
-            | PolicyError::EntailmentMaxTerminals => None,
+            | PolicyError::EntailmentMaxTerminals
+            | PolicyError::TooManySpendPaths(_) => None,
         }
     }
 }
@@ -99,6 +105,26 @@ impl<Pk: MiniscriptKey> ForEachKey<Pk> for Policy<Pk> {
 }
 
 impl<Pk: MiniscriptKey> Policy<Pk> {
+    /// Constructs an `older` policy from a relative locktime consensus value
+    ///
+    /// # Errors
+    ///
+    /// If `n` is zero, which doesn't restrict the spending transaction, or isn't
+    /// a valid BIP 68 relative locktime.
+    pub fn older(n: u32) -> Result<Self, RelLockTimeError> {
+        RelLockTime::from_consensus(n).map(Policy::Older)
+    }
+
+    /// Constructs an `after` policy from an absolute locktime consensus value
+    ///
+    /// # Errors
+    ///
+    /// If `n` is zero, which doesn't restrict the spending transaction, or is
+    /// above the maximum locktime allowed by Miniscript.
+    pub fn after(n: u32) -> Result<Self, AbsLockTimeError> {
+        AbsLockTime::from_consensus(n).map(Policy::After)
+    }
+
     /// Converts a policy using one kind of public key to another type of public key.
     ///
     /// # Examples
@@ -173,33 +199,56 @@ impl<Pk: MiniscriptKey> Policy<Pk> {
 
     /// Computes whether the current policy entails the second one.
     ///
-    /// A |- B means every satisfaction of A is also a satisfaction of B.
+    /// A |- B means every satisfaction of A is also a satisfaction of B. Both
+    /// policies are normalized first. Timelocks are compared the way a
+    /// transaction satisfies them: `after(n)` implies `after(m)` for every
+    /// `m <= n` of the same unit, and timelocks of different units (heights and
+    /// times) can never be satisfied together; likewise for `older`.
     ///
     /// This implementation will run slowly for larger policies but should be
     /// sufficient for most practical policies.
+    ///
+    /// # Errors
+    ///
+    /// If the current policy has more than 20 terminals.
     // This algorithm has a naive implementation. It is possible to optimize this
     // by memoizing and maintaining a hashmap.
     pub fn entails(self, other: Policy<Pk>) -> Result<bool, PolicyError> {
         if self.n_terminals() > ENTAILMENT_MAX_TERMINALS {
             return Err(PolicyError::EntailmentMaxTerminals);
         }
-        match (self, other) {
-            (Policy::Unsatisfiable, _) => Ok(true),
-            (Policy::Trivial, Policy::Trivial) => Ok(true),
-            (Policy::Trivial, _) => Ok(false),
-            (_, Policy::Unsatisfiable) => Ok(false),
+        Ok(Policy::entails_normalized(self.normalized(), other.normalized()))
+    }
+
+    /// Computes whether both policies entail each other, i.e. have exactly the
+    /// same satisfactions, see [`Policy::entails`].
+    ///
+    /// # Errors
+    ///
+    /// If either policy has more than 20 terminals.
+    pub fn is_equivalent(self, other: Policy<Pk>) -> Result<bool, PolicyError> {
+        Ok(self.clone().entails(other.clone())? && other.entails(self)?)
+    }
+
+    // Helper function for entailment of normalized policies, splitting on the
+    // first constraint of `a` until it is trivial or unsatisfiable.
+    fn entails_normalized(a: Policy<Pk>, b: Policy<Pk>) -> bool {
+        match (a, b) {
+            (Policy::Unsatisfiable, _) => true,
+            (_, Policy::Trivial) => true,
+            (Policy::Trivial, _) => false,
+            (_, Policy::Unsatisfiable) => false,
             (a, b) => {
-                let (a_norm, b_norm) = (a.normalized(), b.normalized());
-                let first_constraint = a_norm.first_constraint();
+                let first_constraint = a.first_constraint();
                 let (a1, b1) = (
-                    a_norm.clone().satisfy_constraint(&first_constraint, true),
-                    b_norm.clone().satisfy_constraint(&first_constraint, true),
+                    a.clone().assume_constraint(&first_constraint, true),
+                    b.clone().assume_constraint(&first_constraint, true),
                 );
                 let (a2, b2) = (
-                    a_norm.satisfy_constraint(&first_constraint, false),
-                    b_norm.satisfy_constraint(&first_constraint, false),
+                    a.assume_constraint(&first_constraint, false),
+                    b.assume_constraint(&first_constraint, false),
                 );
-                Ok(Policy::entails(a1, b1)? && Policy::entails(a2, b2)?)
+                Policy::entails_normalized(a1, b1) && Policy::entails_normalized(a2, b2)
             }
         }
     }
@@ -233,32 +282,52 @@ impl<Pk: MiniscriptKey> Policy<Pk> {
     }
 
     // Helper function that takes in witness and its availability, changing it
-    // to true or false and returning the resultant normalized policy. Witness
-    // is currently encoded as policy. Only accepts leaf fragment and a
-    // normalized policy
-    pub(crate) fn satisfy_constraint(self, witness: &Policy<Pk>, available: bool) -> Policy<Pk> {
-        debug_assert!(self.clone().normalized() == self);
-        if let Policy::Thresh { .. } = *witness {
-            // We can't debug_assert on Policy::Thresh.
-            panic!("should be unreachable")
-        }
-
+    // and the timelocks it implies to true or false and returning the
+    // resultant normalized policy. Witness is currently encoded as policy.
+    fn assume_constraint(self, witness: &Policy<Pk>, available: bool) -> Policy<Pk> {
         let ret =
             match self {
                 Policy::Thresh(thresh) => Policy::Thresh(thresh.map(|sub| {
-                    Arc::new(sub.as_ref().clone().satisfy_constraint(witness, available))
+                    Arc::new(sub.as_ref().clone().assume_constraint(witness, available))
                 })),
-                ref leaf if leaf == witness => {
-                    if available {
-                        Policy::Trivial
-                    } else {
-                        Policy::Unsatisfiable
-                    }
-                }
-                x => x,
+                leaf => match leaf.implied_by(witness, available) {
+                    Some(true) => Policy::Trivial,
+                    Some(false) => Policy::Unsatisfiable,
+                    None => leaf,
+                },
             };
         ret.normalized()
     }
+
+    // Helper function returning whether this leaf policy is satisfied, if that
+    // follows from whether the leaf `witness` is.
+    fn implied_by(&self, witness: &Policy<Pk>, available: bool) -> Option<bool> {
+        if self == witness {
+            return Some(available);
+        }
+        match (self, witness) {
+            (Policy::After(m), Policy::After(n)) => {
+                implied_lock(m, n, m.is_block_height() == n.is_block_height(), available)
+            }
+            (Policy::Older(m), Policy::Older(n)) => {
+                implied_lock(m, n, m.is_height_locked() == n.is_height_locked(), available)
+            }
+            _ => None,
+        }
+    }
+}
+
+// Whether a timelock is satisfied, if it follows from whether another timelock
+// of the same kind is. Satisfying a timelock satisfies the smaller ones of its
+// unit and none of the other unit, while not satisfying it doesn't satisfy the
+// larger ones of its unit.
+fn implied_lock<T: Ord>(lock: T, witness: T, same_unit: bool, available: bool) -> Option<bool> {
+    match (same_unit, available) {
+        (false, true) => Some(false),
+        (true, true) if lock <= witness => Some(true),
+        (true, false) if lock >= witness => Some(false),
+        _ => None,
+    }
 }
 
 impl<Pk: MiniscriptKey> fmt::Debug for Policy<Pk> {
@@ -321,6 +390,19 @@ impl<Pk: FromStrKey> str::FromStr for Policy<Pk> {
 
 serde_string_impl_pk!(Policy, "a miniscript semantic policy");
 
+impl<Pk: FromStrKey> Policy<Pk> {
+    /// Parses a policy which may contain the degenerate timelocks `older(0)`
+    /// and `after(0)`, which are rejected by [`str::FromStr`]
+    ///
+    /// These timelocks are replaced by `TRIVIAL` and the policy is normalized,
+    /// so that they vanish from conjunctions and make disjunctions trivial. They
+    /// are returned so that the caller may warn about them.
+    pub fn from_str_zero_locks(s: &str) -> Result<(Self, ZeroLocks), Error> {
+        let (s, locks) = policy::replace_zero_locks(s)?;
+        Ok((s.parse::<Self>()?.normalized(), locks))
+    }
+}
+
 impl<Pk: FromStrKey> expression::FromTree for Policy<Pk> {
     fn from_tree(top: &expression::Tree) -> Result<Policy<Pk>, Error> {
         match (top.name, top.args.len()) {
@@ -614,6 +696,238 @@ impl<Pk: MiniscriptKey> Policy<Pk> {
         // Ok to unwrap because we know we processed at least one node.
         minimum_n_keys.pop().unwrap()
     }
+
+    /// Whether the policy can be satisfied using only signatures of the keys for
+    /// which `has_key` returns `true`.
+    ///
+    /// Timelocks are assumed to be eventually satisfied, while hash preimages are
+    /// assumed to be unavailable.
+    pub fn satisfiable_with_keys<F: FnMut(&Pk) -> bool>(&self, mut has_key: F) -> bool {
+        use Policy::*;
+
+        let mut satisfiable = vec![];
+        for data in self.rtl_post_order_iter() {
+            let sat = match data.node {
+                Unsatisfiable | Sha256(..) | Hash256(..) | Ripemd160(..) | Hash160(..) => false,
+                Trivial | After(..) | Older(..) => true,
+                Key(ref pk) => has_key(pk),
+                Thresh(ref thresh) => {
+                    let n_sat = (0..thresh.n())
+                        .filter(|_| satisfiable.pop().unwrap())
+                        .count();
+                    n_sat >= thresh.k()
+                }
+            };
+            satisfiable.push(sat);
+        }
+        // Ok to unwrap because we know we processed at least one node.
+        satisfiable.pop().unwrap()
+    }
+
+    /// Counts the spending paths of the policy, see [`Policy::spend_paths_iter`].
+    ///
+    /// Stops counting and returns [`SpendPathCount::ExceedsLimit`] as soon as
+    /// there are known to be more than `limit` paths, so this is cheap even for
+    /// policies with a combinatorial number of paths.
+    pub fn spend_path_count(&self, limit: usize) -> SpendPathCount {
+        use Policy::*;
+
+        // Counts are capped at `limit + 1`, which is enough to tell they exceed it.
+        let cap = limit.saturating_add(1);
+        let mut counts = vec![];
+        for data in self.rtl_post_order_iter() {
+            let count = match data.node {
+                Unsatisfiable => 0,
+                Trivial | Key(..) | After(..) | Older(..) | Sha256(..) | Hash256(..)
+                | Ripemd160(..) | Hash160(..) => 1,
+                Thresh(ref thresh) => {
+                    // The number of ways to pick `k` branches and a path in each
+                    // is the elementary symmetric polynomial of degree `k` of the
+                    // branch counts.
+                    let mut ways = vec![0usize; thresh.k() + 1];
+                    ways[0] = 1;
+                    for _ in 0..thresh.n() {
+                        let count = counts.pop().unwrap();
+                        for j in (1..=thresh.k()).rev() {
+                            ways[j] = ways[j]
+                                .saturating_add(ways[j - 1].saturating_mul(count))
+                                .min(cap);
+                        }
+                    }
+                    ways[thresh.k()]
+                }
+            };
+            counts.push(count);
+        }
+        // Ok to unwrap because we know we processed at least one node.
+        match counts.pop().unwrap() {
+            n if n > limit => SpendPathCount::ExceedsLimit,
+            n => SpendPathCount::Exact(n),
+        }
+    }
+
+    /// Lazily enumerates the spending paths of the policy.
+    ///
+    /// A spending path is a set of conditions which together satisfy the policy,
+    /// obtained by choosing `k` branches of every threshold on the way: each
+    /// path is given as the sorted list of its keys, hashes and timelocks.
+    /// Paths are not deduplicated, and a path may be a superset of another one,
+    /// e.g. `or(pk(A),and(pk(A),pk(B)))` has the paths `[pk(A)]` and
+    /// `[pk(A),pk(B)]`. Use [`Policy::spend_path_count`] beforehand to check the
+    /// number of paths is tractable.
+    pub fn spend_paths_iter(&self) -> SpendPaths<'_, Pk> { SpendPaths::new(self.clone()) }
+
+    /// The unit of the timelock, if the policy is a timelock.
+    pub fn timelock_unit(&self) -> Option<TimelockUnit> {
+        match *self {
+            Policy::Older(t) if t.is_height_locked() => Some(TimelockUnit::Blocks),
+            Policy::After(t) if t.is_block_height() => Some(TimelockUnit::Blocks),
+            Policy::Older(_) | Policy::After(_) => Some(TimelockUnit::Time),
+            _ => None,
+        }
+    }
+
+    /// Lists the spending paths of the policy which mix timelock units.
+    ///
+    /// A transaction has a single `nLockTime` and a single `nSequence` per
+    /// input, which can't be both block-based and time-based: a spending path
+    /// requiring a relative timelock in blocks and one in time, or an absolute
+    /// timelock in blocks and one in time, can never be taken. Returns `None` if
+    /// the policy has more than `limit` spending paths.
+    pub fn timelock_conflicts(&self, limit: usize) -> Option<Vec<TimelockConflict<Pk>>> {
+        if self.spend_path_count(limit) == SpendPathCount::ExceedsLimit {
+            return None;
+        }
+        // The first relative, or absolute, timelock of the path in `unit`
+        let first = |path: &[Policy<Pk>], relative: bool, unit: TimelockUnit| {
+            path.iter()
+                .find(|p| {
+                    p.timelock_unit() == Some(unit) && matches!(**p, Policy::Older(_)) == relative
+                })
+                .cloned()
+        };
+        let conflicts = self
+            .spend_paths_iter()
+            .filter_map(|path| {
+                let mixed = |relative| match (
+                    first(&path, relative, TimelockUnit::Blocks),
+                    first(&path, relative, TimelockUnit::Time),
+                ) {
+                    (Some(blocks), Some(time)) => Some((blocks, time)),
+                    _ => None,
+                };
+                let (relative, absolute) = (mixed(true), mixed(false));
+                if relative.is_none() && absolute.is_none() {
+                    return None;
+                }
+                Some(TimelockConflict { path, relative, absolute })
+            })
+            .collect();
+        Some(conflicts)
+    }
+}
+
+/// The unit of a timelock
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Hash)]
+pub enum TimelockUnit {
+    /// A block height, or a number of blocks for relative timelocks
+    Blocks,
+    /// A UNIX timestamp, or a number of 512-second intervals for relative
+    /// timelocks
+    Time,
+}
+
+/// A spending path mixing timelock units, see [`Policy::timelock_conflicts`]
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub struct TimelockConflict<Pk: MiniscriptKey> {
+    /// The conditions of the spending path
+    pub path: Vec<Policy<Pk>>,
+    /// A block-based and a time-based relative timelock of the path, if any
+    pub relative: Option<(Policy<Pk>, Policy<Pk>)>,
+    /// A block-based and a time-based absolute timelock of the path, if any
+    pub absolute: Option<(Policy<Pk>, Policy<Pk>)>,
+}
+
+impl<Pk: MiniscriptKey> fmt::Display for TimelockConflict<Pk> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let conflicts = self.relative.iter().chain(self.absolute.iter());
+        for (i, (blocks, time)) in conflicts.enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            write!(f, "{} is in blocks but {} is in time", blocks, time)?;
+        }
+        Ok(())
+    }
+}
+
+/// The number of spending paths of a policy, see [`Policy::spend_path_count`]
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Hash)]
+pub enum SpendPathCount {
+    /// The exact number of paths
+    Exact(usize),
+    /// There are more paths than the limit
+    ExceedsLimit,
+}
+
+/// Iterator over the spending paths of a policy, see [`Policy::spend_paths_iter`]
+pub struct SpendPaths<'a, Pk: MiniscriptKey> {
+    inner: Box<dyn Iterator<Item = Vec<Policy<Pk>>> + 'a>,
+}
+
+impl<'a, Pk: MiniscriptKey + 'a> SpendPaths<'a, Pk> {
+    pub(crate) fn new(policy: Policy<Pk>) -> Self {
+        SpendPaths { inner: policy_paths(Arc::new(policy)) }
+    }
+}
+
+impl<Pk: MiniscriptKey> Iterator for SpendPaths<'_, Pk> {
+    type Item = Vec<Policy<Pk>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut path = self.inner.next()?;
+        path.sort();
+        path.dedup();
+        Some(path)
+    }
+}
+
+/// The (unsorted) spending paths of `policy`.
+fn policy_paths<'a, Pk: MiniscriptKey + 'a>(
+    policy: Arc<Policy<Pk>>,
+) -> Box<dyn Iterator<Item = Vec<Policy<Pk>>> + 'a> {
+    match *policy {
+        Policy::Unsatisfiable => Box::new(core::iter::empty()),
+        Policy::Trivial => Box::new(core::iter::once(vec![])),
+        Policy::Thresh(ref thresh) => {
+            let subs: Arc<[Arc<Policy<Pk>>]> = thresh.iter().cloned().collect();
+            thresh_paths(subs, 0, thresh.k())
+        }
+        ref leaf => Box::new(core::iter::once(vec![leaf.clone()])),
+    }
+}
+
+/// The (unsorted) spending paths choosing `k` of `subs[start..]`.
+fn thresh_paths<'a, Pk: MiniscriptKey + 'a>(
+    subs: Arc<[Arc<Policy<Pk>>]>,
+    start: usize,
+    k: usize,
+) -> Box<dyn Iterator<Item = Vec<Policy<Pk>>> + 'a> {
+    if k == 0 {
+        return Box::new(core::iter::once(vec![]));
+    }
+    if subs.len() - start < k {
+        return Box::new(core::iter::empty());
+    }
+    let rest = Arc::clone(&subs);
+    let with_first = policy_paths(Arc::clone(&subs[start])).flat_map(move |path| {
+        thresh_paths(Arc::clone(&rest), start + 1, k - 1).map(move |mut rest_path| {
+            rest_path.extend(path.iter().cloned());
+            rest_path
+        })
+    });
+    let without_first = thresh_paths(subs, start + 1, k);
+    Box::new(with_first.chain(without_first))
 }
 
 impl<Pk: MiniscriptKey> Policy<Pk> {
@@ -1024,6 +1338,49 @@ mod tests {
         assert!(htlc_pol.entails(control_alice).unwrap());
     }
 
+    #[test]
+    fn entailment_timelocks() {
+        let entails = |a: &str, b: &str| {
+            StringPolicy::from_str(a)
+                .unwrap()
+                .entails(StringPolicy::from_str(b).unwrap())
+                .unwrap()
+        };
+        // Larger timelocks of a unit imply the smaller ones
+        assert!(entails("after(200)", "after(100)"));
+        assert!(!entails("after(100)", "after(200)"));
+        assert!(entails("and(pk(A),older(144))", "or(older(100),pk(B))"));
+        assert!(!entails("and(pk(A),older(100))", "or(older(144),pk(B))"));
+        // Heights and times are never satisfied together
+        assert!(!entails("after(500000100)", "after(100)"));
+        assert!(entails("and(after(100),after(500000100))", "pk(A)"));
+        assert!(entails("and(older(10),older(4194314))", "pk(A)"));
+        assert!(!entails("and(older(10),after(500000100))", "pk(A)"));
+        // Thresholds mixing timelocks
+        assert!(entails("thresh(2,after(300),after(200),pk(A))", "or(after(200),pk(A))"));
+        assert!(!entails("thresh(2,after(300),after(200),pk(A))", "and(after(200),pk(A))"));
+    }
+
+    #[test]
+    fn equivalence() {
+        let policy = |s: &str| StringPolicy::from_str(s).unwrap();
+        assert!(policy("or(pk(A),and(pk(B),pk(C)))")
+            .is_equivalent(policy("or(and(pk(C),pk(B)),pk(A))"))
+            .unwrap());
+        assert!(policy("and(after(100),after(200))")
+            .is_equivalent(policy("after(200)"))
+            .unwrap());
+        assert!(policy("thresh(2,pk(A),pk(B),pk(C))")
+            .is_equivalent(policy("or(and(pk(A),pk(B)),and(pk(A),pk(C)),and(pk(B),pk(C)))"))
+            .unwrap());
+        assert!(!policy("thresh(2,pk(A),pk(B),pk(C))")
+            .is_equivalent(policy("or(and(pk(A),pk(B)),and(pk(A),pk(C)))"))
+            .unwrap());
+        assert!(!policy("after(200)")
+            .is_equivalent(policy("after(100)"))
+            .unwrap());
+    }
+
     #[test]
     fn for_each_key() {
         let liquid_pol = StringPolicy::from_str(
@@ -1035,4 +1392,112 @@ mod tests {
         }));
         assert_eq!(count, 17);
     }
+
+    #[test]
+    fn spend_paths() {
+        let paths = |s: &str| {
+            let policy = StringPolicy::from_str(s).unwrap();
+            let paths: Vec<String> = policy
+                .spend_paths_iter()
+                .map(|path| {
+                    path.iter()
+                        .map(ToString::to_string)
+                        .collect::<Vec<_>>()
+                        .join(",")
+                })
+                .collect();
+            assert_eq!(policy.spend_path_count(usize::MAX), SpendPathCount::Exact(paths.len()));
+            paths
+        };
+
+        assert_eq!(paths("pk(A)"), vec!["pk(A)"]);
+        assert_eq!(paths("UNSATISFIABLE"), Vec::<String>::new());
+        assert_eq!(paths("TRIVIAL"), vec![""]);
+        assert_eq!(
+            paths("thresh(2,pk(A),pk(B),or(pk(C),older(10)))"),
+            vec![
+                "pk(A),pk(B)",
+                "pk(A),pk(C)",
+                "pk(A),older(10)",
+                "pk(B),pk(C)",
+                "pk(B),older(10)"
+            ]
+        );
+        assert_eq!(paths("or(pk(A),and(pk(A),pk(B)))"), vec!["pk(A)", "pk(A),pk(B)"]);
+
+        // 10 of 20 keys
+        let keys: Vec<_> = (0..20).map(|i| format!("pk(K{})", i)).collect();
+        let policy = StringPolicy::from_str(&format!("thresh(10,{})", keys.join(","))).unwrap();
+        assert_eq!(policy.spend_path_count(usize::MAX), SpendPathCount::Exact(184756));
+        assert_eq!(policy.spend_path_count(184756), SpendPathCount::Exact(184756));
+        assert_eq!(policy.spend_path_count(184755), SpendPathCount::ExceedsLimit);
+        assert_eq!(policy.spend_paths_iter().take(3).count(), 3);
+    }
+
+    #[test]
+    fn zero_locks() {
+        assert!(StringPolicy::from_str("older(0)").is_err());
+        assert!(StringPolicy::older(0).is_err());
+        assert!(StringPolicy::after(0).is_err());
+
+        let zero = |s| StringPolicy::from_str_zero_locks(s).unwrap();
+        let (policy, locks) = zero("and(pk(A),older(0))");
+        assert_eq!(policy, StringPolicy::from_str("pk(A)").unwrap());
+        assert_eq!(locks, ZeroLocks { older: 1, after: 0 });
+        let (policy, locks) = zero("or(pk(A),after(0))");
+        assert_eq!(policy, StringPolicy::Trivial);
+        assert_eq!(locks, ZeroLocks { older: 0, after: 1 });
+        assert!(zero("thresh(2,pk(A),pk(B),older(10))").1.is_empty());
+    }
+
+    #[test]
+    fn satisfiable_with_keys() {
+        let policy = StringPolicy::from_str(
+            "or(and(pk(A),sha256(1111111111111111111111111111111111111111111111111111111111111111)),thresh(2,pk(B),pk(C),after(100)))",
+        )
+        .unwrap();
+        assert!(!policy.satisfiable_with_keys(|_| false));
+        // Hash preimages are never available
+        assert!(!policy.satisfiable_with_keys(|pk| pk == "A"));
+        // Timelocks are
+        assert!(policy.satisfiable_with_keys(|pk| pk == "B"));
+        assert!(StringPolicy::Trivial.satisfiable_with_keys(|_| false));
+        assert!(!StringPolicy::Unsatisfiable.satisfiable_with_keys(|_| true));
+    }
+
+    #[test]
+    fn timelock_conflicts() {
+        let policy = StringPolicy::from_str("older(4194305)").unwrap();
+        assert_eq!(policy.timelock_unit(), Some(TimelockUnit::Time));
+        assert_eq!(StringPolicy::from_str("pk(A)").unwrap().timelock_unit(), None);
+
+        // Only the path through both relative timelocks mixes units
+        let policy = StringPolicy::from_str(
+            "or(and(pk(A),after(100)),and(older(144),or(pk(B),older(4194305))))",
+        )
+        .unwrap();
+        let conflicts = policy.timelock_conflicts(100).unwrap();
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(
+            conflicts[0].path,
+            vec![
+                StringPolicy::older(144).unwrap(),
+                StringPolicy::older(4194305).unwrap()
+            ]
+        );
+        assert_eq!(conflicts[0].absolute, None);
+        assert_eq!(
+            conflicts[0].to_string(),
+            "older(144) is in blocks but older(4194305) is in time"
+        );
+
+        // Relative and absolute timelocks don't conflict with each other
+        let policy = StringPolicy::from_str("and(older(144),after(500000001))").unwrap();
+        assert!(policy.timelock_conflicts(100).unwrap().is_empty());
+        let policy = StringPolicy::from_str("and(after(100),after(500000001))").unwrap();
+        assert!(policy.timelock_conflicts(100).unwrap()[0]
+            .absolute
+            .is_some());
+        assert!(policy.timelock_conflicts(0).is_none());
+    }
 }
@@ -4,7 +4,10 @@
 
 use core::{cmp, fmt};
 
-use bitcoin::absolute;
+use bitcoin::absolute::LOCK_TIME_THRESHOLD;
+use bitcoin::{absolute, relative};
+
+use super::locktime_arith::{BlockInterval, LockTimeArithError};
 
 /// Maximum allowed absolute locktime value.
 pub const MAX_ABSOLUTE_LOCKTIME: u32 = 0x7FFF_FFFF;
@@ -68,6 +71,120 @@ impl AbsLockTime {
 
     /// Whether this is a time-based locktime.
     pub fn is_block_time(&self) -> bool { self.0.is_block_time() }
+
+    /// Whether a transaction using this locktime could be included in the block following the
+    /// given chain tip.
+    pub fn is_satisfied_by_chain(&self, tip: &ChainTip) -> bool { tip.is_final(self.0) }
+
+    /// Constructs a height-based locktime expected to be reached `days` after the
+    /// block at height `tip`, with blocks mined every `interval`.
+    pub fn after_days(
+        tip: absolute::Height,
+        days: u32,
+        interval: BlockInterval,
+    ) -> Result<Self, LockTimeArithError> {
+        let blocks = interval.blocks_in_days(days)?;
+        Self::height_from_u64(u64::from(tip.to_consensus_u32()) + u64::from(blocks))
+    }
+
+    /// Adds `blocks` to a height-based locktime, as a safety margin.
+    ///
+    /// # Errors
+    ///
+    /// If the locktime is time-based, or the resulting height would be interpreted
+    /// as a time.
+    pub fn checked_add_blocks(self, blocks: u32) -> Result<Self, LockTimeArithError> {
+        if !self.is_block_height() {
+            return Err(LockTimeArithError::UnitMismatch);
+        }
+        Self::height_from_u64(u64::from(self.to_consensus_u32()) + u64::from(blocks))
+    }
+
+    /// Adds `seconds` to a time-based locktime, as a safety margin.
+    ///
+    /// # Errors
+    ///
+    /// If the locktime is height-based, or the result exceeds [`MAX_ABSOLUTE_LOCKTIME`].
+    pub fn checked_add_seconds(self, seconds: u32) -> Result<Self, LockTimeArithError> {
+        if !self.is_block_time() {
+            return Err(LockTimeArithError::UnitMismatch);
+        }
+        match self.to_consensus_u32().checked_add(seconds) {
+            Some(n) if n <= MAX_ABSOLUTE_LOCKTIME => {
+                Ok(AbsLockTime(absolute::LockTime::from_consensus(n)))
+            }
+            _ => Err(LockTimeArithError::Overflow),
+        }
+    }
+
+    fn height_from_u64(height: u64) -> Result<Self, LockTimeArithError> {
+        if height >= u64::from(LOCK_TIME_THRESHOLD) {
+            Err(LockTimeArithError::CrossesThreshold(height))
+        } else if height < u64::from(MIN_ABSOLUTE_LOCKTIME) {
+            Err(LockTimeArithError::Zero)
+        } else {
+            Ok(AbsLockTime(absolute::LockTime::from_consensus(height as u32)))
+        }
+    }
+}
+
+/// The state of the best chain, against which absolute locktimes are evaluated.
+///
+/// Comparing an `after` fragment to a transaction's nLockTime only tells whether the
+/// transaction is well-formed; comparing it to the chain tells whether it can be mined now.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ChainTip {
+    /// Height of the current chain tip.
+    pub height: absolute::Height,
+    /// Median time past of the current chain tip, as defined in BIP 113.
+    pub median_time_past: absolute::Time,
+}
+
+impl ChainTip {
+    /// Constructs a new `ChainTip` from the height and median time past of the tip.
+    pub fn new(height: absolute::Height, median_time_past: absolute::Time) -> Self {
+        ChainTip { height, median_time_past }
+    }
+
+    /// Whether a transaction with the given nLockTime could be included in the next block.
+    ///
+    /// Per consensus rules, a height-based locktime must be lower than the height of the next
+    /// block, and a time-based locktime must be lower than the median time past of the tip.
+    pub fn is_final(&self, lock_time: absolute::LockTime) -> bool {
+        match lock_time {
+            absolute::LockTime::Blocks(h) => h.to_consensus_u32() <= self.height.to_consensus_u32(),
+            absolute::LockTime::Seconds(t) => {
+                t.to_consensus_u32() < self.median_time_past.to_consensus_u32()
+            }
+        }
+    }
+
+    /// Whether a relative timelock on an output would be satisfied in the next block.
+    ///
+    /// `confirmed_on` is the chain tip the block confirming the output was built on. As per
+    /// BIP 68, the age of the output is measured from this block.
+    pub fn is_relative_final(
+        &self,
+        confirmed_on: &ChainTip,
+        lock_time: relative::LockTime,
+    ) -> bool {
+        match lock_time {
+            relative::LockTime::Blocks(h) => {
+                let age = self
+                    .height
+                    .to_consensus_u32()
+                    .saturating_sub(confirmed_on.height.to_consensus_u32());
+                age >= u32::from(h.value())
+            }
+            relative::LockTime::Time(t) => {
+                let age = self
+                    .median_time_past
+                    .to_consensus_u32()
+                    .saturating_sub(confirmed_on.median_time_past.to_consensus_u32());
+                age >= u32::from(t.value()) * 512
+            }
+        }
+    }
 }
 
 impl From<AbsLockTime> for absolute::LockTime {
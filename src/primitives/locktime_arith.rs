@@ -0,0 +1,176 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! Locktime Arithmetic
+//!
+//! Helpers to compute the values of `after` and `older` fragments from durations
+//! and safety margins, checking that the results stay on the same side of the
+//! height/time threshold and within the ranges allowed by BIP 65 and BIP 68.
+
+use core::fmt;
+
+use bitcoin::absolute::LOCK_TIME_THRESHOLD;
+
+/// Number of seconds in a day.
+pub const SECONDS_PER_DAY: u32 = 86_400;
+
+/// Error computing a locktime.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockTimeArithError {
+    /// A number of blocks was added to a time-based locktime, or a number of
+    /// seconds to a height-based one.
+    UnitMismatch,
+    /// A height-based absolute locktime would reach the 500,000,000 threshold,
+    /// above which it is interpreted as a time.
+    CrossesThreshold(u64),
+    /// The locktime would exceed the maximum value for its kind.
+    Overflow,
+    /// The locktime would be zero, which Miniscript doesn't allow.
+    Zero,
+}
+
+impl fmt::Display for LockTimeArithError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LockTimeArithError::UnitMismatch => {
+                f.write_str("cannot mix block heights and times in a locktime")
+            }
+            LockTimeArithError::CrossesThreshold(n) => write!(
+                f,
+                "block height {} would be interpreted as a time, heights must be below {}",
+                n, LOCK_TIME_THRESHOLD
+            ),
+            LockTimeArithError::Overflow => f.write_str("locktime exceeds its maximum value"),
+            LockTimeArithError::Zero => {
+                f.write_str("locktimes in Miniscript have a minimum value of 1")
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for LockTimeArithError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> { None }
+}
+
+/// The expected interval between blocks, used to convert durations into numbers of blocks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct BlockInterval(u32);
+
+impl BlockInterval {
+    /// The ten minute block interval targeted by Bitcoin.
+    pub const TEN_MINUTES: Self = BlockInterval(600);
+
+    /// Constructs a block interval of `seconds`, which must be nonzero.
+    pub fn from_seconds(seconds: u32) -> Option<Self> {
+        if seconds == 0 {
+            None
+        } else {
+            Some(BlockInterval(seconds))
+        }
+    }
+
+    /// The number of seconds between blocks.
+    pub fn to_seconds(self) -> u32 { self.0 }
+
+    /// The number of blocks expected to be mined in `seconds`, rounded up.
+    pub fn blocks_in_seconds(self, seconds: u32) -> u32 {
+        seconds / self.0 + u32::from(seconds % self.0 != 0)
+    }
+
+    /// The number of blocks expected to be mined in `days`, rounded up.
+    pub fn blocks_in_days(self, days: u32) -> Result<u32, LockTimeArithError> {
+        let seconds = u64::from(days) * u64::from(SECONDS_PER_DAY);
+        let interval = u64::from(self.0);
+        let blocks = seconds / interval + u64::from(seconds % interval != 0);
+        u32::try_from(blocks).map_err(|_| LockTimeArithError::Overflow)
+    }
+}
+
+impl Default for BlockInterval {
+    fn default() -> Self { BlockInterval::TEN_MINUTES }
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::absolute;
+
+    use super::*;
+    use crate::{AbsLockTime, RelLockTime};
+
+    #[test]
+    fn block_interval() {
+        let interval = BlockInterval::default();
+        assert_eq!(interval.blocks_in_seconds(600), 1);
+        assert_eq!(interval.blocks_in_seconds(601), 2);
+        assert_eq!(interval.blocks_in_days(1), Ok(144));
+        assert_eq!(BlockInterval::from_seconds(30).unwrap().blocks_in_days(1), Ok(2880));
+        assert_eq!(
+            BlockInterval::from_seconds(1)
+                .unwrap()
+                .blocks_in_days(50_000),
+            Err(LockTimeArithError::Overflow)
+        );
+        assert_eq!(BlockInterval::from_seconds(0), None);
+    }
+
+    #[test]
+    fn absolute_arith() {
+        let height = AbsLockTime::from_consensus(800_000).unwrap();
+        assert_eq!(height.checked_add_blocks(144).unwrap().to_consensus_u32(), 800_144);
+        assert_eq!(height.checked_add_seconds(1), Err(LockTimeArithError::UnitMismatch));
+        assert_eq!(
+            AbsLockTime::from_consensus(LOCK_TIME_THRESHOLD - 10)
+                .unwrap()
+                .checked_add_blocks(10),
+            Err(LockTimeArithError::CrossesThreshold(u64::from(LOCK_TIME_THRESHOLD)))
+        );
+
+        let time = AbsLockTime::from_consensus(1_700_000_000).unwrap();
+        assert_eq!(time.checked_add_seconds(60).unwrap().to_consensus_u32(), 1_700_000_060);
+        assert_eq!(time.checked_add_blocks(1), Err(LockTimeArithError::UnitMismatch));
+        assert_eq!(time.checked_add_seconds(u32::MAX), Err(LockTimeArithError::Overflow));
+
+        let tip = absolute::Height::from_consensus(800_000).unwrap();
+        let after = AbsLockTime::after_days(tip, 7, BlockInterval::default()).unwrap();
+        assert_eq!(after.to_consensus_u32(), 801_008);
+        assert!(after.is_block_height());
+        assert_eq!(
+            AbsLockTime::after_days(absolute::Height::ZERO, 0, BlockInterval::default()),
+            Err(LockTimeArithError::Zero)
+        );
+        assert!(matches!(
+            AbsLockTime::after_days(tip, 10_000, BlockInterval::from_seconds(1).unwrap()),
+            Err(LockTimeArithError::CrossesThreshold(_))
+        ));
+    }
+
+    #[test]
+    fn relative_arith() {
+        let blocks = RelLockTime::from_height(144);
+        assert_eq!(blocks.checked_add_blocks(6), Ok(RelLockTime::from_height(150)));
+        assert_eq!(blocks.checked_add_blocks(u16::MAX), Err(LockTimeArithError::Overflow));
+        assert_eq!(blocks.checked_add_seconds(512), Err(LockTimeArithError::UnitMismatch));
+
+        let time = RelLockTime::from_512_second_intervals(10);
+        assert_eq!(time.checked_add_seconds(513), Ok(RelLockTime::from_512_second_intervals(12)));
+        assert_eq!(time.checked_add_blocks(1), Err(LockTimeArithError::UnitMismatch));
+
+        assert_eq!(
+            RelLockTime::from_days_in_blocks(2, BlockInterval::default()),
+            Ok(RelLockTime::from_height(288))
+        );
+        assert_eq!(
+            RelLockTime::from_days_in_blocks(500, BlockInterval::default()),
+            Err(LockTimeArithError::Overflow)
+        );
+        assert_eq!(
+            RelLockTime::from_days_in_blocks(0, BlockInterval::default()),
+            Err(LockTimeArithError::Zero)
+        );
+        assert_eq!(
+            RelLockTime::from_days_in_time(1),
+            Ok(RelLockTime::from_512_second_intervals(169))
+        );
+        assert_eq!(RelLockTime::from_days_in_time(400), Err(LockTimeArithError::Overflow));
+    }
+}
@@ -6,6 +6,8 @@ use core::{cmp, convert, fmt};
 
 use bitcoin::{relative, Sequence};
 
+use super::locktime_arith::{BlockInterval, LockTimeArithError, SECONDS_PER_DAY};
+
 /// Error parsing an absolute locktime.
 #[derive(Debug, PartialEq)]
 pub struct RelLockTimeError {
@@ -60,6 +62,72 @@ impl RelLockTime {
 
     /// Whether this timelock is time-based.
     pub fn is_time_locked(&self) -> bool { self.0.is_time_locked() }
+
+    /// Constructs a height-based locktime of the number of blocks expected to be
+    /// mined in `days`, with blocks mined every `interval`.
+    pub fn from_days_in_blocks(
+        days: u32,
+        interval: BlockInterval,
+    ) -> Result<Self, LockTimeArithError> {
+        Self::from_blocks_u32(interval.blocks_in_days(days)?)
+    }
+
+    /// Constructs a time-based locktime of `days`, rounded up to a multiple of
+    /// 512 seconds.
+    pub fn from_days_in_time(days: u32) -> Result<Self, LockTimeArithError> {
+        let seconds = days
+            .checked_mul(SECONDS_PER_DAY)
+            .ok_or(LockTimeArithError::Overflow)?;
+        Self::from_seconds_u64(u64::from(seconds))
+    }
+
+    /// Adds `blocks` to a height-based locktime, as a safety margin.
+    ///
+    /// # Errors
+    ///
+    /// If the locktime is time-based, or the result exceeds the 65535 blocks
+    /// allowed by BIP 68.
+    pub fn checked_add_blocks(self, blocks: u16) -> Result<Self, LockTimeArithError> {
+        match relative::LockTime::from(self) {
+            relative::LockTime::Blocks(h) => {
+                Self::from_blocks_u32(u32::from(h.value()) + u32::from(blocks))
+            }
+            relative::LockTime::Time(_) => Err(LockTimeArithError::UnitMismatch),
+        }
+    }
+
+    /// Adds `seconds`, rounded up to a multiple of 512 seconds, to a time-based
+    /// locktime, as a safety margin.
+    ///
+    /// # Errors
+    ///
+    /// If the locktime is height-based, or the result exceeds the 65535 intervals
+    /// of 512 seconds allowed by BIP 68.
+    pub fn checked_add_seconds(self, seconds: u32) -> Result<Self, LockTimeArithError> {
+        match relative::LockTime::from(self) {
+            relative::LockTime::Time(t) => {
+                Self::from_seconds_u64(u64::from(t.value()) * 512 + u64::from(seconds))
+            }
+            relative::LockTime::Blocks(_) => Err(LockTimeArithError::UnitMismatch),
+        }
+    }
+
+    fn from_blocks_u32(blocks: u32) -> Result<Self, LockTimeArithError> {
+        match u16::try_from(blocks) {
+            Ok(0) => Err(LockTimeArithError::Zero),
+            Ok(blocks) => Ok(Self::from_height(blocks)),
+            Err(_) => Err(LockTimeArithError::Overflow),
+        }
+    }
+
+    fn from_seconds_u64(seconds: u64) -> Result<Self, LockTimeArithError> {
+        let intervals = seconds / 512 + u64::from(seconds % 512 != 0);
+        match u16::try_from(intervals) {
+            Ok(0) => Err(LockTimeArithError::Zero),
+            Ok(intervals) => Ok(Self::from_512_second_intervals(intervals)),
+            Err(_) => Err(LockTimeArithError::Overflow),
+        }
+    }
 }
 
 impl convert::TryFrom<Sequence> for RelLockTime {
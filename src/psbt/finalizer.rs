@@ -20,12 +20,14 @@ use bitcoin::sighash::Prevouts;
 use bitcoin::taproot::LeafVersion;
 use bitcoin::{PublicKey, Script, ScriptBuf, TxOut, Witness};
 
-use super::{sanity_check, Error, InputError, Psbt, PsbtInputSatisfier};
+use super::{sanity_check, Error, InputError, InputProgress, Psbt, PsbtInputSatisfier};
+use crate::descriptor::DescriptorType;
+use crate::plan::Plan;
 use crate::prelude::*;
-use crate::util::witness_size;
+use crate::util::{witness_size, witness_to_scriptsig};
 use crate::{
-    interpreter, BareCtx, Descriptor, ExtParams, Legacy, Miniscript, Satisfier, Segwitv0, SigType,
-    Tap, ToPublicKey,
+    interpreter, BareCtx, DefiniteDescriptorKey, Descriptor, ExtParams, KeyRegistry, Legacy,
+    Miniscript, Satisfier, Segwitv0, SigType, Tap, ToPublicKey,
 };
 
 // Satisfy the taproot descriptor. It is not possible to infer the complete
@@ -36,6 +38,7 @@ fn construct_tap_witness(
     spk: &Script,
     sat: &PsbtInputSatisfier,
     allow_mall: bool,
+    registry: &KeyRegistry,
 ) -> Result<Vec<Vec<u8>>, InputError> {
     // When miniscript tries to finalize the PSBT, it doesn't have the full descriptor (which contained a pkh() fragment)
     // and instead resorts to parsing the raw script sig, which is translated into a "expr_raw_pkh" internally.
@@ -51,6 +54,11 @@ fn construct_tap_witness(
             map.insert(hash, bitcoin_key);
         }
     }
+    // Keys the finalizer was given in addition to the ones of the PSBT
+    for key in registry.keys() {
+        let x_only_key = key.to_x_only_pubkey();
+        map.insert(x_only_key.to_pubkeyhash(SigType::Schnorr), x_only_key);
+    }
     assert!(spk.is_p2tr());
 
     // try the key spend path first
@@ -142,7 +150,11 @@ pub(super) fn prevouts(psbt: &Psbt) -> Result<Vec<&bitcoin::TxOut>, super::Error
 // We parse the insane version while satisfying because
 // we want to move the script is probably already created
 // and we want to satisfy it in any way possible.
-fn get_descriptor(psbt: &Psbt, index: usize) -> Result<Descriptor<PublicKey>, InputError> {
+fn get_descriptor(
+    psbt: &Psbt,
+    index: usize,
+    registry: &KeyRegistry,
+) -> Result<Descriptor<PublicKey>, InputError> {
     let mut map: BTreeMap<hash160::Hash, PublicKey> = BTreeMap::new();
     let psbt_inputs = &psbt.inputs;
     for psbt_input in psbt_inputs {
@@ -154,6 +166,9 @@ fn get_descriptor(psbt: &Psbt, index: usize) -> Result<Descriptor<PublicKey>, In
             map.insert(hash, bitcoin_key);
         }
     }
+    for key in registry.keys() {
+        map.insert(key.pubkey_hash().to_raw_hash(), *key);
+    }
 
     // Figure out Scriptpubkey
     let script_pubkey = get_scriptpubkey(psbt, index)?;
@@ -401,6 +416,7 @@ fn finalize_input_helper<C: secp256k1::Verification>(
     index: usize,
     secp: &Secp256k1<C>,
     allow_mall: bool,
+    registry: &KeyRegistry,
 ) -> Result<(Witness, ScriptBuf), super::Error> {
     let (witness, script_sig) = {
         let spk = get_scriptpubkey(psbt, index).map_err(|e| Error::InputError(e, index))?;
@@ -408,12 +424,13 @@ fn finalize_input_helper<C: secp256k1::Verification>(
 
         if spk.is_p2tr() {
             // Deal with tr case separately, unfortunately we cannot infer the full descriptor for Tr
-            let wit = construct_tap_witness(&spk, &sat, allow_mall)
+            let wit = construct_tap_witness(&spk, &sat, allow_mall, registry)
                 .map_err(|e| Error::InputError(e, index))?;
             (wit, ScriptBuf::new())
         } else {
             // Get a descriptor for this input.
-            let desc = get_descriptor(psbt, index).map_err(|e| Error::InputError(e, index))?;
+            let desc =
+                get_descriptor(psbt, index, registry).map_err(|e| Error::InputError(e, index))?;
 
             //generate the satisfaction witness and scriptsig
             let sat = PsbtInputSatisfier::new(psbt, index);
@@ -440,8 +457,91 @@ pub(super) fn finalize_input<C: secp256k1::Verification>(
     secp: &Secp256k1<C>,
     allow_mall: bool,
 ) -> Result<(), super::Error> {
-    let (witness, script_sig) = finalize_input_helper(psbt, index, secp, allow_mall)?;
+    finalize_input_with_registry(psbt, index, secp, allow_mall, &KeyRegistry::new())
+}
+
+// Same as `finalize_input`, also resolving the key hashes of the input's scripts with the
+// keys of `registry`.
+pub(super) fn finalize_input_with_registry<C: secp256k1::Verification>(
+    psbt: &mut Psbt,
+    index: usize,
+    secp: &Secp256k1<C>,
+    allow_mall: bool,
+    registry: &KeyRegistry,
+) -> Result<(), super::Error> {
+    let (witness, script_sig) = finalize_input_helper(psbt, index, secp, allow_mall, registry)?;
+    set_final_fields(psbt, index, witness, script_sig);
+    Ok(())
+}
+
+// Finalize an input according to `plan` if every item of its witness is available.
+pub(super) fn finalize_input_partial<C: secp256k1::Verification>(
+    psbt: &mut Psbt,
+    index: usize,
+    secp: &Secp256k1<C>,
+    plan: &Plan,
+) -> Result<InputProgress, super::Error> {
+    let input = &psbt.inputs[index];
+    if input.final_script_sig.is_some() || input.final_script_witness.is_some() {
+        return Ok(InputProgress::Finalized);
+    }
+
+    let sat = PsbtInputSatisfier::new(psbt, index);
+    let witness = plan.partial_satisfy(&sat);
+    let absolute_timelock = plan
+        .absolute_timelock
+        .filter(|&n| !Satisfier::<DefiniteDescriptorKey>::check_after(&sat, n));
+    let relative_timelock = plan
+        .relative_timelock
+        .filter(|&n| !Satisfier::<DefiniteDescriptorKey>::check_older(&sat, n));
+    if !witness.is_complete() || absolute_timelock.is_some() || relative_timelock.is_some() {
+        return Ok(InputProgress::Pending { witness, absolute_timelock, relative_timelock });
+    }
+
+    let stack = witness.into_stack().expect("the witness is complete");
+    let (witness, script_sig) = plan_stack_to_final(&plan.descriptor, stack);
+    let witness = Witness::from_slice(&witness);
+    let utxos = prevouts(psbt)?;
+    let utxos = &Prevouts::All(&utxos);
+    interpreter_inp_check(psbt, secp, index, utxos, &witness, &script_sig)?;
+
+    set_final_fields(psbt, index, witness, script_sig);
+    Ok(InputProgress::Finalized)
+}
 
+// Turn the satisfied witness template of a plan into the final witness and script_sig,
+// adding the witness or redeem script which are not part of the template.
+fn plan_stack_to_final(
+    desc: &Descriptor<DefiniteDescriptorKey>,
+    mut stack: Vec<Vec<u8>>,
+) -> (Vec<Vec<u8>>, ScriptBuf) {
+    match desc.desc_type() {
+        DescriptorType::Wsh
+        | DescriptorType::WshSortedMulti
+        | DescriptorType::ShWsh
+        | DescriptorType::ShWshSortedMulti => {
+            let witness_script = desc.explicit_script().expect("wsh has a witness script");
+            stack.push(witness_script.into_bytes());
+            (stack, desc.unsigned_script_sig())
+        }
+        DescriptorType::Sh | DescriptorType::ShSortedMulti => {
+            let redeem_script = desc.explicit_script().expect("sh has a redeem script");
+            stack.push(redeem_script.into_bytes());
+            (vec![], witness_to_scriptsig(&stack))
+        }
+        DescriptorType::Bare | DescriptorType::Pkh | DescriptorType::Data => {
+            (vec![], witness_to_scriptsig(&stack))
+        }
+        DescriptorType::Wpkh
+        | DescriptorType::ShWpkh
+        | DescriptorType::Tr
+        | DescriptorType::RawTr
+        | DescriptorType::Wother => (stack, desc.unsigned_script_sig()),
+    }
+}
+
+// Set the final fields of a psbt input, clearing the other ones as required by BIP 174.
+fn set_final_fields(psbt: &mut Psbt, index: usize, witness: Witness, script_sig: ScriptBuf) {
     // Now mutate the psbt input. Note that we cannot error after this point.
     // If the input is mutated, it means that the finalization succeeded.
     {
@@ -460,8 +560,6 @@ pub(super) fn finalize_input<C: secp256k1::Verification>(
             Some(witness)
         };
     }
-
-    Ok(())
 }
 
 #[cfg(test)]
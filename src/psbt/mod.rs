@@ -8,11 +8,12 @@
 //!
 
 use core::convert::TryFrom;
-use core::fmt;
+use core::{cmp, fmt};
 #[cfg(feature = "std")]
 use std::error;
 
 use bitcoin::hashes::{hash160, sha256d, Hash};
+use bitcoin::key::{TapTweak, TweakedPublicKey};
 use bitcoin::psbt::{self, Psbt};
 #[cfg(not(test))] // https://github.com/rust-lang/rust/issues/121684
 use bitcoin::secp256k1;
@@ -21,17 +22,21 @@ use bitcoin::sighash::{self, SighashCache};
 use bitcoin::taproot::{self, ControlBlock, LeafVersion, TapLeafHash};
 use bitcoin::{absolute, bip32, relative, transaction, Script, ScriptBuf};
 
+use crate::descriptor::set::{ScriptOrigin, ScriptSet};
 use crate::miniscript::context::SigType;
+use crate::plan::{PartialWitness, Plan};
 use crate::prelude::*;
 use crate::{
-    descriptor, interpreter, DefiniteDescriptorKey, Descriptor, DescriptorPublicKey, MiniscriptKey,
-    Preimage32, Satisfier, ToPublicKey, Translator,
+    descriptor, interpreter, DefiniteDescriptorKey, Descriptor, DescriptorPublicKey, KeyRegistry,
+    MiniscriptKey, Preimage32, Satisfier, ToPublicKey, Translator,
 };
 
 mod finalizer;
+mod signer;
 
 #[allow(deprecated)]
 pub use self::finalizer::{finalize, finalize_mall, interpreter_check};
+pub use self::signer::{sign_with_descriptor, SignError, SignerProvider};
 
 /// Error type for entire Psbt
 #[derive(Debug)]
@@ -52,6 +57,13 @@ pub enum Error {
         /// requested index
         index: usize,
     },
+    /// Wrong Plan Count
+    WrongPlanCount {
+        /// Input count in psbt
+        inputs: usize,
+        /// Plan count
+        plans: usize,
+    },
 }
 
 impl fmt::Display for Error {
@@ -66,6 +78,9 @@ impl fmt::Display for Error {
                 "psbt input index {} out of bounds: psbt.inputs.len() {}",
                 index, psbt_inp
             ),
+            Error::WrongPlanCount { inputs, plans } => {
+                write!(f, "PSBT had {} inputs but {} plans were given", inputs, plans)
+            }
         }
     }
 }
@@ -77,7 +92,7 @@ impl error::Error for Error {
 
         match self {
             InputError(e, _) => Some(e),
-            WrongInputCount { .. } | InputIdxOutofBounds { .. } => None,
+            WrongInputCount { .. } | InputIdxOutofBounds { .. } | WrongPlanCount { .. } => None,
         }
     }
 }
@@ -259,6 +274,17 @@ impl<Pk: MiniscriptKey + ToPublicKey> Satisfier<Pk> for PsbtInputSatisfier<'_> {
         self.psbt.inputs[self.index].tap_key_sig
     }
 
+    fn lookup_raw_tr_schnorr_sig(&self, pk: &Pk) -> Option<bitcoin::taproot::Signature> {
+        // The key spend signature is only made by `pk` if it is the output key of the utxo
+        let output_key = TweakedPublicKey::dangerous_assume_tweaked(pk.to_x_only_pubkey());
+        let spk = finalizer::get_scriptpubkey(self.psbt, self.index).ok()?;
+        if spk == ScriptBuf::new_p2tr_tweaked(output_key) {
+            self.psbt.inputs[self.index].tap_key_sig
+        } else {
+            None
+        }
+    }
+
     fn lookup_tap_leaf_script_sig(
         &self,
         pk: &Pk,
@@ -365,6 +391,175 @@ impl<Pk: MiniscriptKey + ToPublicKey> Satisfier<Pk> for PsbtInputSatisfier<'_> {
     }
 }
 
+/// Decodes vendor signatures carried in the proprietary fields of a psbt input
+///
+/// Used by [`ProprietaryInputSatisfier`] to find signatures which are not in the
+/// `partial_sigs`, `tap_key_sig` or `tap_script_sigs` fields, for instance signatures
+/// delivered by a HSM in its own format. Every proprietary field of the input is offered
+/// to the codec, which must ignore the fields it does not recognize.
+pub trait ProprietarySigCodec {
+    /// Decodes the ecdsa signature of `pk` from a proprietary field, if it carries one
+    fn decode_ecdsa_sig(
+        &self,
+        _key: &psbt::raw::ProprietaryKey,
+        _value: &[u8],
+        _pk: &bitcoin::PublicKey,
+    ) -> Option<bitcoin::ecdsa::Signature> {
+        None
+    }
+
+    /// Decodes the schnorr signature of `pk` from a proprietary field, if it carries one
+    ///
+    /// `leaf` is the leaf hash of the script being satisfied, or `None` for a key spend. For a
+    /// key spend, `pk` is the output key which signs, i.e. `tap_internal_key` tweaked with
+    /// `tap_merkle_root`, not the internal key itself.
+    fn decode_schnorr_sig(
+        &self,
+        _key: &psbt::raw::ProprietaryKey,
+        _value: &[u8],
+        _pk: &bitcoin::key::XOnlyPublicKey,
+        _leaf: Option<&TapLeafHash>,
+    ) -> Option<taproot::Signature> {
+        None
+    }
+}
+
+/// Psbt satisfier which also reads signatures from the proprietary fields of the input
+///
+/// Signatures are looked up in the standard fields first, as by [`PsbtInputSatisfier`], and
+/// then decoded from the proprietary fields by the codec. Everything else is looked up as by
+/// [`PsbtInputSatisfier`].
+pub struct ProprietaryInputSatisfier<'psbt, C> {
+    /// The satisfier for the standard fields
+    pub inner: PsbtInputSatisfier<'psbt>,
+    /// The codec decoding the proprietary fields
+    pub codec: C,
+}
+
+impl<'psbt, C: ProprietarySigCodec> ProprietaryInputSatisfier<'psbt, C> {
+    /// create a new ProprietaryInputSatisfier from
+    /// psbt, index and codec
+    pub fn new(psbt: &'psbt Psbt, index: usize, codec: C) -> Self {
+        Self { inner: PsbtInputSatisfier::new(psbt, index), codec }
+    }
+
+    fn input(&self) -> &psbt::Input { &self.inner.psbt.inputs[self.inner.index] }
+
+    fn proprietary_ecdsa_sig(&self, pk: &bitcoin::PublicKey) -> Option<bitcoin::ecdsa::Signature> {
+        self.input()
+            .proprietary
+            .iter()
+            .find_map(|(key, value)| self.codec.decode_ecdsa_sig(key, value, pk))
+    }
+
+    fn proprietary_schnorr_sig(
+        &self,
+        pk: &bitcoin::key::XOnlyPublicKey,
+        leaf: Option<&TapLeafHash>,
+    ) -> Option<taproot::Signature> {
+        self.input()
+            .proprietary
+            .iter()
+            .find_map(|(key, value)| self.codec.decode_schnorr_sig(key, value, pk, leaf))
+    }
+}
+
+impl<Pk: MiniscriptKey + ToPublicKey, C: ProprietarySigCodec> Satisfier<Pk>
+    for ProprietaryInputSatisfier<'_, C>
+{
+    fn lookup_tap_key_spend_sig(&self) -> Option<bitcoin::taproot::Signature> {
+        <PsbtInputSatisfier as Satisfier<Pk>>::lookup_tap_key_spend_sig(&self.inner).or_else(|| {
+            let input = self.input();
+            let secp = Secp256k1::verification_only();
+            let (output_key, _) = input
+                .tap_internal_key?
+                .tap_tweak(&secp, input.tap_merkle_root);
+            self.proprietary_schnorr_sig(&output_key.to_x_only_public_key(), None)
+        })
+    }
+
+    fn lookup_tap_leaf_script_sig(
+        &self,
+        pk: &Pk,
+        lh: &TapLeafHash,
+    ) -> Option<bitcoin::taproot::Signature> {
+        Satisfier::lookup_tap_leaf_script_sig(&self.inner, pk, lh)
+            .or_else(|| self.proprietary_schnorr_sig(&pk.to_x_only_pubkey(), Some(lh)))
+    }
+
+    fn lookup_raw_tr_schnorr_sig(&self, pk: &Pk) -> Option<bitcoin::taproot::Signature> {
+        Satisfier::lookup_raw_tr_schnorr_sig(&self.inner, pk)
+    }
+
+    fn lookup_raw_pkh_pk(&self, pkh: &hash160::Hash) -> Option<bitcoin::PublicKey> {
+        <PsbtInputSatisfier as Satisfier<Pk>>::lookup_raw_pkh_pk(&self.inner, pkh)
+    }
+
+    fn lookup_tap_control_block_map(
+        &self,
+    ) -> Option<&BTreeMap<ControlBlock, (bitcoin::ScriptBuf, LeafVersion)>> {
+        <PsbtInputSatisfier as Satisfier<Pk>>::lookup_tap_control_block_map(&self.inner)
+    }
+
+    fn lookup_raw_pkh_tap_leaf_script_sig(
+        &self,
+        pkh: &(hash160::Hash, TapLeafHash),
+    ) -> Option<(bitcoin::secp256k1::XOnlyPublicKey, bitcoin::taproot::Signature)> {
+        <PsbtInputSatisfier as Satisfier<Pk>>::lookup_raw_pkh_tap_leaf_script_sig(&self.inner, pkh)
+            .or_else(|| {
+                // The keys with a signature in the proprietary fields can only be found
+                // through their origin.
+                self.input()
+                    .tap_key_origins
+                    .keys()
+                    .filter(|pk| pk.to_pubkeyhash(SigType::Schnorr) == pkh.0)
+                    .find_map(|pk| Some((*pk, self.proprietary_schnorr_sig(pk, Some(&pkh.1))?)))
+            })
+    }
+
+    fn lookup_ecdsa_sig(&self, pk: &Pk) -> Option<bitcoin::ecdsa::Signature> {
+        Satisfier::lookup_ecdsa_sig(&self.inner, pk)
+            .or_else(|| self.proprietary_ecdsa_sig(&pk.to_public_key()))
+    }
+
+    fn lookup_raw_pkh_ecdsa_sig(
+        &self,
+        pkh: &hash160::Hash,
+    ) -> Option<(bitcoin::PublicKey, bitcoin::ecdsa::Signature)> {
+        <PsbtInputSatisfier as Satisfier<Pk>>::lookup_raw_pkh_ecdsa_sig(&self.inner, pkh).or_else(
+            || {
+                let pk =
+                    <PsbtInputSatisfier as Satisfier<Pk>>::lookup_raw_pkh_pk(&self.inner, pkh)?;
+                Some((pk, self.proprietary_ecdsa_sig(&pk)?))
+            },
+        )
+    }
+
+    fn check_after(&self, n: absolute::LockTime) -> bool {
+        <PsbtInputSatisfier as Satisfier<Pk>>::check_after(&self.inner, n)
+    }
+
+    fn check_older(&self, n: relative::LockTime) -> bool {
+        <PsbtInputSatisfier as Satisfier<Pk>>::check_older(&self.inner, n)
+    }
+
+    fn lookup_hash160(&self, h: &Pk::Hash160) -> Option<Preimage32> {
+        Satisfier::<Pk>::lookup_hash160(&self.inner, h)
+    }
+
+    fn lookup_sha256(&self, h: &Pk::Sha256) -> Option<Preimage32> {
+        Satisfier::<Pk>::lookup_sha256(&self.inner, h)
+    }
+
+    fn lookup_hash256(&self, h: &Pk::Hash256) -> Option<Preimage32> {
+        Satisfier::<Pk>::lookup_hash256(&self.inner, h)
+    }
+
+    fn lookup_ripemd160(&self, h: &Pk::Ripemd160) -> Option<Preimage32> {
+        Satisfier::<Pk>::lookup_ripemd160(&self.inner, h)
+    }
+}
+
 fn try_vec_as_preimage32(vec: &[u8]) -> Option<Preimage32> {
     if vec.len() == 32 {
         let mut arr = [0u8; 32];
@@ -447,6 +642,19 @@ pub trait PsbtExt {
         secp: &secp256k1::Secp256k1<C>,
     ) -> Result<(), Vec<Error>>;
 
+    /// Same as [`PsbtExt::finalize_mut`], also resolving the public key hashes of the
+    /// scripts with the keys of `registry`
+    ///
+    /// The finalizer parses the scripts of the inputs, which only contain the hashes of the
+    /// keys of their `pkh` fragments. It resolves them with the keys in the `bip32_derivation`
+    /// and `tap_key_origins` fields of the PSBT, which is not enough to dissatisfy the `pkh`
+    /// fragments of keys not known to the updater. `registry` provides the missing keys.
+    fn finalize_with_registry_mut<C: secp256k1::Verification>(
+        &mut self,
+        secp: &secp256k1::Secp256k1<C>,
+        registry: &KeyRegistry,
+    ) -> Result<(), Vec<Error>>;
+
     /// Same as [`PsbtExt::finalize_mut`], but does not mutate the input psbt and
     /// returns a new psbt
     ///
@@ -510,6 +718,28 @@ pub trait PsbtExt {
         index: usize,
     ) -> Result<Psbt, (Psbt, Error)>;
 
+    /// Finalize the inputs for which every signature, preimage and timelock is available, and
+    /// report the progress of the others.
+    ///
+    /// Unlike [`PsbtExt::finalize_mut`], each input is spent according to its plan in `plans`,
+    /// e.g. obtained from [`Descriptor::plan`] with the assets of every party to the spend. This
+    /// lets the witness of the inputs which can't be finalized yet be built partially, with
+    /// placeholders for the items still missing. Those inputs are left untouched, and inputs
+    /// which are already finalized are skipped.
+    ///
+    /// Finalized inputs go through the same interpreter check as with [`PsbtExt::finalize_mut`].
+    ///
+    /// # Errors:
+    ///
+    /// - [`Error::WrongPlanCount`] if there isn't exactly one plan per input
+    /// - Input error if the interpreter check of a completed input fails. The inputs before it
+    ///   may have been finalized.
+    fn finalize_partial<C: secp256k1::Verification>(
+        &mut self,
+        secp: &secp256k1::Secp256k1<C>,
+        plans: &[Plan],
+    ) -> Result<Vec<InputProgress>, Error>;
+
     /// Psbt extractor as defined in BIP174 that takes in a psbt reference
     /// and outputs a extracted [`bitcoin::Transaction`].
     ///
@@ -561,6 +791,34 @@ pub trait PsbtExt {
         descriptor: &Descriptor<DefiniteDescriptorKey>,
     ) -> Result<(), OutputUpdateError>;
 
+    /// Update every PSBT input and output whose `script_pubkey` is produced by one of the
+    /// `descriptors`
+    ///
+    /// Each descriptor is derived from index 0 until `gap_limit` consecutive indexes don't match
+    /// any input or output, as a wallet would when looking for its own scripts. Multipath
+    /// descriptors are split into their single-path descriptors. The matching inputs and outputs
+    /// are then updated with [`PsbtExt::update_input_with_descriptor`] and
+    /// [`PsbtExt::update_output_with_descriptor`], filling their BIP 32 derivations, witness and
+    /// redeem scripts and taproot data.
+    ///
+    /// Inputs without `witness_utxo` nor `non_witness_utxo` are left untouched. When several
+    /// descriptors produce the same script, the first descriptor in `descriptors` is used.
+    ///
+    /// Returns which descriptor and derivation index each input and output was updated with.
+    /// Inputs and outputs which match a descriptor but can't be updated with it, e.g. an input
+    /// with only a `witness_utxo` matching a legacy descriptor, are left untouched and reported
+    /// in [`DescriptorsUpdate::failed_inputs`] and [`DescriptorsUpdate::failed_outputs`].
+    ///
+    /// # Errors
+    ///
+    /// If a descriptor can't be split or derived, in which case the PSBT is left untouched.
+    fn update_with_descriptors<C: secp256k1::Verification>(
+        &mut self,
+        secp: &secp256k1::Secp256k1<C>,
+        descriptors: &[Descriptor<DescriptorPublicKey>],
+        gap_limit: u32,
+    ) -> Result<DescriptorsUpdate, DescriptorsUpdateError>;
+
     /// Get the sighash message(data to sign) at input index `idx`.
     ///
     /// Based on the sighash
@@ -612,6 +870,26 @@ impl PsbtExt for Psbt {
         }
     }
 
+    fn finalize_with_registry_mut<C: secp256k1::Verification>(
+        &mut self,
+        secp: &secp256k1::Secp256k1<C>,
+        registry: &KeyRegistry,
+    ) -> Result<(), Vec<Error>> {
+        let errors: Vec<_> = (0..self.inputs.len())
+            .filter_map(|index| {
+                finalizer::finalize_input_with_registry(
+                    self, index, secp, /*allow_mall*/ false, registry,
+                )
+                .err()
+            })
+            .collect();
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
     fn finalize<C: secp256k1::Verification>(
         mut self,
         secp: &secp256k1::Secp256k1<C>,
@@ -696,6 +974,21 @@ impl PsbtExt for Psbt {
         }
     }
 
+    fn finalize_partial<C: secp256k1::Verification>(
+        &mut self,
+        secp: &secp256k1::Secp256k1<C>,
+        plans: &[Plan],
+    ) -> Result<Vec<InputProgress>, Error> {
+        if plans.len() != self.inputs.len() {
+            return Err(Error::WrongPlanCount { inputs: self.inputs.len(), plans: plans.len() });
+        }
+        plans
+            .iter()
+            .enumerate()
+            .map(|(index, plan)| finalizer::finalize_input_partial(self, index, secp, plan))
+            .collect()
+    }
+
     fn extract<C: secp256k1::Verification>(
         &self,
         secp: &Secp256k1<C>,
@@ -812,6 +1105,137 @@ impl PsbtExt for Psbt {
         Ok(())
     }
 
+    fn update_with_descriptors<C: secp256k1::Verification>(
+        &mut self,
+        secp: &secp256k1::Secp256k1<C>,
+        descriptors: &[Descriptor<DescriptorPublicKey>],
+        gap_limit: u32,
+    ) -> Result<DescriptorsUpdate, DescriptorsUpdateError> {
+        // Single-path descriptors, along with the position of the descriptor they come from
+        let mut singles = Vec::new();
+        for (position, descriptor) in descriptors.iter().enumerate() {
+            let paths = descriptor
+                .clone()
+                .into_single_descriptors()
+                .map_err(|e| DescriptorsUpdateError::Multipath(position, e))?;
+            singles.extend(paths.into_iter().map(|path| (position, path)));
+        }
+
+        let input_spks: Vec<Option<ScriptBuf>> = (0..self.inputs.len())
+            .map(|index| input_script_pubkey(self, index))
+            .collect();
+        let output_spks: Vec<ScriptBuf> = self
+            .unsigned_tx
+            .output
+            .iter()
+            .map(|txout| txout.script_pubkey.clone())
+            .collect();
+        let targets: BTreeSet<&Script> = input_spks
+            .iter()
+            .flatten()
+            .chain(&output_spks)
+            .map(ScriptBuf::as_script)
+            .collect();
+
+        // For each script, the first (single-path descriptor, index) producing it
+        let mut matches = BTreeMap::<&Script, (usize, u32)>::new();
+        // For each single-path descriptor, the end of the range derived so far and of the range
+        // to derive
+        let mut derived = vec![0; singles.len()];
+        let mut wanted: Vec<u32> = singles
+            .iter()
+            .map(|(_, single)| if single.has_wildcard() { gap_limit } else { 1 })
+            .collect();
+        loop {
+            let mut extended = false;
+            for (n, (_, single)) in singles.iter().enumerate() {
+                if derived[n] >= wanted[n] {
+                    continue;
+                }
+                let mut set = ScriptSet::new();
+                set.insert_descriptor(secp, single, derived[n]..wanted[n])
+                    .map_err(DescriptorsUpdateError::Derivation)?;
+                derived[n] = wanted[n];
+                extended = true;
+
+                for spk in &targets {
+                    if let Some(origin) = set.origins(spk).first() {
+                        let found = (n, origin.index);
+                        let best = matches.entry(spk).or_insert(found);
+                        *best = cmp::min(*best, found);
+                        if single.has_wildcard() {
+                            let end = origin
+                                .index
+                                .saturating_add(1)
+                                .saturating_add(gap_limit)
+                                .min(1 << 31);
+                            wanted[n] = cmp::max(wanted[n], end);
+                        }
+                    }
+                }
+            }
+            if !extended {
+                break;
+            }
+        }
+
+        // Derive every match before updating anything, so that a derivation error leaves the
+        // PSBT untouched
+        let derive = |found: Option<&(usize, u32)>| {
+            found
+                .map(|&(n, index)| {
+                    let (position, ref single) = singles[n];
+                    single
+                        .at_derivation_index(index)
+                        .map(|desc| (desc, ScriptOrigin { descriptor: position, index }))
+                        .map_err(DescriptorsUpdateError::Derivation)
+                })
+                .transpose()
+        };
+        let input_matches = input_spks
+            .iter()
+            .map(|spk| derive(spk.as_deref().and_then(|spk| matches.get(spk))))
+            .collect::<Result<Vec<_>, _>>()?;
+        let output_matches = output_spks
+            .iter()
+            .map(|spk| derive(matches.get(spk.as_script())))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut update = DescriptorsUpdate {
+            inputs: vec![None; self.inputs.len()],
+            outputs: vec![None; self.outputs.len()],
+            failed_inputs: vec![],
+            failed_outputs: vec![],
+        };
+        for (index, found) in input_matches.into_iter().enumerate() {
+            if let Some((desc, origin)) = found {
+                // Restore the input if the update fails halfway
+                let input = self.inputs[index].clone();
+                match self.update_input_with_descriptor(index, &desc) {
+                    Ok(()) => update.inputs[index] = Some(origin),
+                    Err(e) => {
+                        self.inputs[index] = input;
+                        update.failed_inputs.push((index, e));
+                    }
+                }
+            }
+        }
+        for (index, found) in output_matches.into_iter().enumerate() {
+            if let Some((desc, origin)) = found {
+                let output = self.outputs[index].clone();
+                match self.update_output_with_descriptor(index, &desc) {
+                    Ok(()) => update.outputs[index] = Some(origin),
+                    Err(e) => {
+                        self.outputs[index] = output;
+                        update.failed_outputs.push((index, e));
+                    }
+                }
+            }
+        }
+
+        Ok(update)
+    }
+
     fn sighash_msg<T: Borrow<bitcoin::Transaction>>(
         &self,
         idx: usize,
@@ -901,6 +1325,27 @@ impl PsbtExt for Psbt {
     }
 }
 
+/// Progress of the finalization of a psbt input, as reported by [`PsbtExt::finalize_partial`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InputProgress {
+    /// The input is finalized
+    Finalized,
+    /// The input can't be finalized yet
+    Pending {
+        /// The witness built so far, with placeholders for the missing signatures and preimages
+        witness: PartialWitness<DefiniteDescriptorKey>,
+        /// The absolute timelock of the plan, if the transaction doesn't satisfy it
+        absolute_timelock: Option<absolute::LockTime>,
+        /// The relative timelock of the plan, if the input doesn't satisfy it
+        relative_timelock: Option<relative::LockTime>,
+    },
+}
+
+impl InputProgress {
+    /// Whether the input is finalized
+    pub fn is_finalized(&self) -> bool { matches!(self, InputProgress::Finalized) }
+}
+
 /// Extension trait for PSBT inputs
 pub trait PsbtInputExt {
     /// Given the descriptor for a utxo being spent populate the PSBT input's fields so it can be signed.
@@ -974,6 +1419,8 @@ impl PsbtOutputExt for psbt::Output {
 struct KeySourceLookUp(
     pub BTreeMap<secp256k1::PublicKey, bip32::KeySource>,
     pub secp256k1::Secp256k1<VerifyOnly>,
+    // MuSig2 keys, whose participants are added to the PSBT instead of themselves
+    pub Vec<DefiniteDescriptorKey>,
 );
 
 impl Translator<DefiniteDescriptorKey> for KeySourceLookUp {
@@ -985,6 +1432,10 @@ impl Translator<DefiniteDescriptorKey> for KeySourceLookUp {
         xpk: &DefiniteDescriptorKey,
     ) -> Result<bitcoin::PublicKey, descriptor::ConversionError> {
         let derived = xpk.derive_public_key(&self.1)?;
+        if let DescriptorPublicKey::Musig(_) = xpk.as_descriptor_public_key() {
+            self.2.push(xpk.clone());
+            return Ok(derived);
+        }
         self.0.insert(
             derived.to_public_key().inner,
             (
@@ -999,6 +1450,43 @@ impl Translator<DefiniteDescriptorKey> for KeySourceLookUp {
     translate_hash_clone!(DescriptorPublicKey, bitcoin::PublicKey, descriptor::ConversionError);
 }
 
+/// Adds the origins of the keys aggregated by a MuSig2 key to `bip32_derivation` and the keys
+/// themselves to the participant pubkeys field of BIP 373, and returns whether the key is such an
+/// aggregate
+///
+/// An aggregate has no origin of its own since it isn't derived from a master key, so callers
+/// don't add one.
+fn add_musig_participants<F: PsbtFields>(
+    item: &mut F,
+    xpk: &DefiniteDescriptorKey,
+    secp: &Secp256k1<VerifyOnly>,
+) -> Result<bool, descriptor::ConversionError> {
+    let musig = match xpk.as_descriptor_public_key() {
+        DescriptorPublicKey::Musig(musig) => musig,
+        _ => return Ok(false),
+    };
+    let mut participants = Vec::with_capacity(musig.keys().len());
+    for key in musig.keys() {
+        let key = DefiniteDescriptorKey::try_from(key.clone())?;
+        let path = key
+            .full_derivation_path()
+            .ok_or(descriptor::ConversionError::MultiKey)?;
+        let derived = key.derive_public_key(secp)?.inner;
+        participants.push(derived.serialize());
+        item.bip32_derivation()
+            .insert(derived, (key.master_fingerprint(), path));
+    }
+    // In the order they were aggregated in
+    participants.sort();
+    let aggregate = xpk.derive_public_key(secp)?.inner;
+    let key = psbt::raw::Key {
+        type_value: F::MUSIG2_PARTICIPANT_PUBKEYS,
+        key: aggregate.serialize().to_vec(),
+    };
+    item.unknown().insert(key, participants.concat());
+    Ok(true)
+}
+
 // Provides generalized access to PSBT fields common to inputs and outputs
 trait PsbtFields {
     // Common fields are returned as a mutable ref of the same type
@@ -1011,9 +1499,11 @@ trait PsbtFields {
     ) -> &mut BTreeMap<bitcoin::key::XOnlyPublicKey, (Vec<TapLeafHash>, bip32::KeySource)>;
     #[allow(dead_code)]
     fn proprietary(&mut self) -> &mut BTreeMap<psbt::raw::ProprietaryKey, Vec<u8>>;
-    #[allow(dead_code)]
     fn unknown(&mut self) -> &mut BTreeMap<psbt::raw::Key, Vec<u8>>;
 
+    // Key type of the MuSig2 participant pubkeys field of BIP 373, which has no field of its own
+    const MUSIG2_PARTICIPANT_PUBKEYS: u8;
+
     // `tap_tree` only appears in psbt::Output, so it's returned as an option of a mutable ref
     fn tap_tree(&mut self) -> Option<&mut Option<taproot::TapTree>> { None }
 
@@ -1042,9 +1532,10 @@ impl PsbtFields for psbt::Input {
     fn proprietary(&mut self) -> &mut BTreeMap<psbt::raw::ProprietaryKey, Vec<u8>> {
         &mut self.proprietary
     }
-    #[allow(dead_code)]
     fn unknown(&mut self) -> &mut BTreeMap<psbt::raw::Key, Vec<u8>> { &mut self.unknown }
 
+    const MUSIG2_PARTICIPANT_PUBKEYS: u8 = 0x1a;
+
     fn tap_scripts(&mut self) -> Option<&mut BTreeMap<ControlBlock, (ScriptBuf, LeafVersion)>> {
         Some(&mut self.tap_scripts)
     }
@@ -1071,9 +1562,10 @@ impl PsbtFields for psbt::Output {
     fn proprietary(&mut self) -> &mut BTreeMap<psbt::raw::ProprietaryKey, Vec<u8>> {
         &mut self.proprietary
     }
-    #[allow(dead_code)]
     fn unknown(&mut self) -> &mut BTreeMap<psbt::raw::Key, Vec<u8>> { &mut self.unknown }
 
+    const MUSIG2_PARTICIPANT_PUBKEYS: u8 = 0x08;
+
     fn tap_tree(&mut self) -> Option<&mut Option<taproot::TapTree>> { Some(&mut self.tap_tree) }
 }
 
@@ -1105,18 +1597,20 @@ fn update_item_with_descriptor_helper<F: PsbtFields>(
                 *merkle_root = spend_info.merkle_root();
             }
             *item.tap_internal_key() = Some(ik_derived);
-            item.tap_key_origins().insert(
-                ik_derived,
-                (
-                    vec![],
+            if !add_musig_participants(item, ik_xpk, &secp)? {
+                item.tap_key_origins().insert(
+                    ik_derived,
                     (
-                        ik_xpk.master_fingerprint(),
-                        ik_xpk
-                            .full_derivation_path()
-                            .ok_or(descriptor::ConversionError::MultiKey)?,
+                        vec![],
+                        (
+                            ik_xpk.master_fingerprint(),
+                            ik_xpk
+                                .full_derivation_path()
+                                .ok_or(descriptor::ConversionError::MultiKey)?,
+                        ),
                     ),
-                ),
-            );
+                );
+            }
 
             let mut builder = taproot::TaprootBuilder::new();
 
@@ -1138,6 +1632,9 @@ fn update_item_with_descriptor_helper<F: PsbtFields>(
 
                 for (pk_pkh_derived, pk_pkh_xpk) in ms_derived.iter_pk().zip(ms.iter_pk()) {
                     let (xonly, xpk) = (pk_pkh_derived.to_x_only_pubkey(), pk_pkh_xpk);
+                    if add_musig_participants(item, &xpk, &secp)? {
+                        continue;
+                    }
 
                     let xpk_full_derivation_path = xpk
                         .full_derivation_path()
@@ -1181,7 +1678,8 @@ fn update_item_with_descriptor_helper<F: PsbtFields>(
 
         derived
     } else {
-        let mut bip32_derivation = KeySourceLookUp(BTreeMap::new(), Secp256k1::verification_only());
+        let mut bip32_derivation =
+            KeySourceLookUp(BTreeMap::new(), Secp256k1::verification_only(), vec![]);
         let derived = descriptor
             .translate_pk(&mut bip32_derivation)
             .map_err(|e| e.expect_translator_err("No Outer Context errors in translations"))?;
@@ -1192,10 +1690,26 @@ fn update_item_with_descriptor_helper<F: PsbtFields>(
             }
         }
 
-        item.bip32_derivation().append(&mut bip32_derivation.0);
+        if let Descriptor::RawTr(_) = derived {
+            // The output key of a rawtr is a taproot key, signing without script path
+            for (pk, key_source) in bip32_derivation.0 {
+                item.tap_key_origins()
+                    .insert(pk.x_only_public_key().0, (vec![], key_source));
+            }
+        } else {
+            item.bip32_derivation().append(&mut bip32_derivation.0);
+        }
+        for xpk in &bip32_derivation.2 {
+            add_musig_participants(item, xpk, &secp)?;
+        }
 
         match &derived {
-            Descriptor::Bare(_) | Descriptor::Pkh(_) | Descriptor::Wpkh(_) => {}
+            Descriptor::Bare(_)
+            | Descriptor::Pkh(_)
+            | Descriptor::Wpkh(_)
+            | Descriptor::Data(_)
+            | Descriptor::Wother(_)
+            | Descriptor::RawTr(_) => {}
             Descriptor::Sh(sh) => match sh.as_inner() {
                 descriptor::ShInner::Wsh(wsh) => {
                     *item.witness_script() = Some(wsh.inner_script());
@@ -1307,6 +1821,77 @@ impl error::Error for OutputUpdateError {
     }
 }
 
+/// The inputs and outputs updated by [`PsbtExt::update_with_descriptors`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DescriptorsUpdate {
+    /// For each input, the descriptor and derivation index it was updated with, if any
+    ///
+    /// [`ScriptOrigin::descriptor`] is the position of the descriptor in the slice given to
+    /// [`PsbtExt::update_with_descriptors`], even for multipath descriptors.
+    pub inputs: Vec<Option<ScriptOrigin>>,
+    /// For each output, the descriptor and derivation index it was updated with, if any
+    pub outputs: Vec<Option<ScriptOrigin>>,
+    /// The inputs which matched a descriptor but couldn't be updated with it, left untouched
+    pub failed_inputs: Vec<(usize, UtxoUpdateError)>,
+    /// The outputs which matched a descriptor but couldn't be updated with it, left untouched
+    pub failed_outputs: Vec<(usize, OutputUpdateError)>,
+}
+
+impl DescriptorsUpdate {
+    /// Number of inputs that were updated
+    pub fn n_inputs(&self) -> usize { self.inputs.iter().flatten().count() }
+
+    /// Number of outputs that were updated
+    pub fn n_outputs(&self) -> usize { self.outputs.iter().flatten().count() }
+}
+
+/// Return error type for [`PsbtExt::update_with_descriptors`]
+#[derive(Debug)]
+pub enum DescriptorsUpdateError {
+    /// The descriptor at this position couldn't be split into single-path descriptors
+    Multipath(usize, crate::Error),
+    /// A descriptor couldn't be derived
+    Derivation(descriptor::ConversionError),
+}
+
+impl fmt::Display for DescriptorsUpdateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DescriptorsUpdateError::Multipath(position, e) => {
+                write!(f, "descriptor {}: {}", position, e)
+            }
+            DescriptorsUpdateError::Derivation(e) => write!(f, "Key derivation error {}", e),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl error::Error for DescriptorsUpdateError {
+    fn cause(&self) -> Option<&dyn error::Error> {
+        use self::DescriptorsUpdateError::*;
+
+        match self {
+            Multipath(_, e) => Some(e),
+            Derivation(e) => Some(e),
+        }
+    }
+}
+
+/// The `script_pubkey` spent by the input at `index`, if its utxo is known
+fn input_script_pubkey(psbt: &Psbt, index: usize) -> Option<ScriptBuf> {
+    let input = &psbt.inputs[index];
+    if let Some(ref witness_utxo) = input.witness_utxo {
+        return Some(witness_utxo.script_pubkey.clone());
+    }
+    let vout = psbt.unsigned_tx.input.get(index)?.previous_output.vout;
+    input
+        .non_witness_utxo
+        .as_ref()?
+        .output
+        .get(vout as usize)
+        .map(|txout| txout.script_pubkey.clone())
+}
+
 /// Return error type for [`PsbtExt::sighash_msg`]
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum SighashError {
@@ -1428,6 +2013,87 @@ mod tests {
         assert_eq!(tx, expected);
     }
 
+    #[test]
+    fn test_proprietary_sigs() {
+        struct HsmCodec;
+
+        impl ProprietarySigCodec for HsmCodec {
+            fn decode_ecdsa_sig(
+                &self,
+                key: &psbt::raw::ProprietaryKey,
+                value: &[u8],
+                pk: &bitcoin::PublicKey,
+            ) -> Option<bitcoin::ecdsa::Signature> {
+                if key.prefix == b"hsm" && key.subtype == 0 && key.key == pk.to_bytes() {
+                    bitcoin::ecdsa::Signature::from_slice(value).ok()
+                } else {
+                    None
+                }
+            }
+
+            fn decode_schnorr_sig(
+                &self,
+                key: &psbt::raw::ProprietaryKey,
+                value: &[u8],
+                pk: &XOnlyPublicKey,
+                leaf: Option<&TapLeafHash>,
+            ) -> Option<taproot::Signature> {
+                if key.prefix == b"hsm" && key.subtype == 1 && key.key == pk.serialize() {
+                    assert!(leaf.is_none());
+                    taproot::Signature::from_slice(value).ok()
+                } else {
+                    None
+                }
+            }
+        }
+
+        let secp = Secp256k1::new();
+        let sk = bitcoin::secp256k1::SecretKey::from_slice(&[1; 32]).unwrap();
+        let pk = bitcoin::PublicKey::new(PublicKey::from_secret_key(&secp, &sk));
+        let (x_only, _) = pk.inner.x_only_public_key();
+        let msg = bitcoin::secp256k1::Message::from_digest([2; 32]);
+        let ecdsa_sig = bitcoin::ecdsa::Signature::sighash_all(secp.sign_ecdsa(&msg, &sk));
+        // Key spends are signed by the output key
+        let tweaked = sk.keypair(&secp).tap_tweak(&secp, None);
+        let output_key = tweaked.to_inner().x_only_public_key().0;
+        let schnorr_sig = taproot::Signature {
+            signature: secp.sign_schnorr_no_aux_rand(&msg, &tweaked.to_inner()),
+            sighash_type: bitcoin::TapSighashType::Default,
+        };
+
+        let tx = bitcoin::Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![TxIn::default()],
+            output: vec![],
+        };
+        let mut psbt = Psbt::from_unsigned_tx(tx).unwrap();
+        let proprietary_key =
+            |subtype, key| psbt::raw::ProprietaryKey { prefix: b"hsm".to_vec(), subtype, key };
+        psbt.inputs[0]
+            .proprietary
+            .insert(proprietary_key(0, pk.to_bytes()), ecdsa_sig.to_vec());
+        psbt.inputs[0]
+            .proprietary
+            .insert(proprietary_key(1, output_key.serialize().to_vec()), schnorr_sig.to_vec());
+        psbt.inputs[0].tap_internal_key = Some(x_only);
+
+        let wpkh = Descriptor::new_wpkh(pk).unwrap();
+        assert!(wpkh
+            .get_satisfaction(PsbtInputSatisfier::new(&psbt, 0))
+            .is_err());
+        let (witness, _) = wpkh
+            .get_satisfaction(ProprietaryInputSatisfier::new(&psbt, 0, HsmCodec))
+            .unwrap();
+        assert_eq!(witness, vec![ecdsa_sig.to_vec(), pk.to_bytes()]);
+
+        let tr = Descriptor::new_tr(x_only, None).unwrap();
+        let (witness, _) = tr
+            .get_satisfaction(ProprietaryInputSatisfier::new(&psbt, 0, HsmCodec))
+            .unwrap();
+        assert_eq!(witness, vec![schnorr_sig.to_vec()]);
+    }
+
     #[test]
     fn test_update_item_tr_no_script() {
         // keys taken from: https://github.com/bitcoin/bips/blob/master/bip-0086.mediawiki#Specifications
@@ -1457,6 +2123,72 @@ mod tests {
         assert_eq!(psbt_output.tap_tree, None);
     }
 
+    #[test]
+    fn test_update_item_tr_musig() {
+        let root_xpub = Xpub::from_str("xpub661MyMwAqRbcFkPHucMnrGNzDwb6teAX1RbKQmqtEF8kK3Z7LZ59qafCjB9eCRLiTVG3uxBxgKvRgbubRhqSKXnGGb1aoaqLrpMBDrVxga8").unwrap();
+        let fingerprint = root_xpub.fingerprint();
+        let xpub = format!("[{}/86'/0'/0']xpub6BgBgsespWvERF3LHQu6CnqdvfEvtMcQjYrcRzx53QJjSxarj2afYWcLteoGVky7D3UKDP9QyrLprQ3VCECoY49yfdDEHGCtMMj92pReUsQ", fingerprint);
+        let single_pk = "03a34b99f22c790c4e36b2b3c2c35a36db06226e41c692fc82b8b56ac1c540c5bd";
+        let single = format!("[deadbeef/1]{}", single_pk);
+        let desc = format!("tr(musig({}/0/0,{}),pk(musig({}/0/1,{})))", xpub, single, xpub, single);
+        let desc = Descriptor::<DefiniteDescriptorKey>::from_str(&desc).unwrap();
+
+        let mut psbt_input = psbt::Input::default();
+        psbt_input.update_with_descriptor_unchecked(&desc).unwrap();
+        let mut psbt_output = psbt::Output::default();
+        psbt_output.update_with_descriptor_unchecked(&desc).unwrap();
+
+        // The aggregate keys have no origins, the participants have theirs
+        assert!(psbt_input.tap_internal_key.is_some());
+        assert!(psbt_input.tap_key_origins.is_empty());
+        let origins = psbt_input
+            .bip32_derivation
+            .values()
+            .map(|(fingerprint, path)| (*fingerprint, path.to_string()))
+            .collect::<Vec<_>>();
+        assert_eq!(psbt_input.bip32_derivation.len(), 3);
+        assert!(origins.contains(&(fingerprint, "86'/0'/0'/0/0".to_owned())));
+        assert!(origins.contains(&(fingerprint, "86'/0'/0'/0/1".to_owned())));
+        let single_pk = secp256k1::PublicKey::from_str(single_pk).unwrap();
+        assert_eq!(
+            psbt_input.bip32_derivation.get(&single_pk),
+            Some(&(
+                bip32::Fingerprint::from_str("deadbeef").unwrap(),
+                DerivationPath::from_str("m/1").unwrap()
+            ))
+        );
+
+        // Each aggregate lists its participants
+        let internal_key = psbt_input.tap_internal_key.unwrap();
+        assert_eq!(psbt_input.unknown.len(), 2);
+        assert!(psbt_input
+            .unknown
+            .keys()
+            .any(|key| key.key[1..] == internal_key.serialize()[..]));
+        for (key, participants) in &psbt_input.unknown {
+            assert_eq!(key.type_value, 0x1a);
+            assert_eq!(key.key.len(), 33);
+            assert_eq!(participants.len(), 66);
+            assert!(participants
+                .chunks(33)
+                .any(|key| key == single_pk.serialize()));
+        }
+
+        assert_eq!(psbt_output.bip32_derivation, psbt_input.bip32_derivation);
+        assert!(psbt_output.tap_key_origins.is_empty());
+        let output_fields = psbt_output
+            .unknown
+            .iter()
+            .map(|(key, participants)| (key.type_value, &key.key, participants))
+            .collect::<Vec<_>>();
+        let input_fields = psbt_input
+            .unknown
+            .iter()
+            .map(|(key, participants)| (0x08, &key.key, participants))
+            .collect::<Vec<_>>();
+        assert_eq!(output_fields, input_fields);
+    }
+
     #[test]
     fn test_update_item_tr_with_tapscript() {
         use crate::Tap;
@@ -1660,6 +2392,246 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_finalize_partial() {
+        let secp = Secp256k1::new();
+        let sks: Vec<_> = (1..=2)
+            .map(|i| bitcoin::secp256k1::SecretKey::from_slice(&[i; 32]).unwrap())
+            .collect();
+        let pks: Vec<_> = sks
+            .iter()
+            .map(|sk| bitcoin::PublicKey::new(PublicKey::from_secret_key(&secp, sk)))
+            .collect();
+        let desc = format!("wsh(multi(2,{},{}))", pks[0], pks[1]);
+        let desc = Descriptor::<DefiniteDescriptorKey>::from_str(&desc).unwrap();
+        let assets = crate::plan::Assets::new()
+            .add(DescriptorPublicKey::from_str(&pks[0].to_string()).unwrap())
+            .add(DescriptorPublicKey::from_str(&pks[1].to_string()).unwrap());
+        let plans = [desc.clone().plan(&assets).unwrap()];
+
+        let tx = bitcoin::Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![TxIn::default()],
+            output: vec![],
+        };
+        let mut psbt = Psbt::from_unsigned_tx(tx).unwrap();
+        psbt.inputs[0].witness_utxo =
+            Some(TxOut { value: Amount::from_sat(10_000), script_pubkey: desc.script_pubkey() });
+        psbt.update_input_with_descriptor(0, &desc).unwrap();
+
+        assert!(matches!(
+            psbt.finalize_partial(&secp, &[]),
+            Err(Error::WrongPlanCount { inputs: 1, plans: 0 })
+        ));
+
+        let msg = psbt
+            .sighash_msg(0, &mut SighashCache::new(&psbt.unsigned_tx), None)
+            .unwrap()
+            .to_secp_msg();
+        let mut expected_missing = 2;
+        for (sk, pk) in sks.iter().zip(&pks) {
+            let progress = psbt.finalize_partial(&secp, &plans).unwrap();
+            match &progress[0] {
+                InputProgress::Pending { witness, absolute_timelock, relative_timelock } => {
+                    assert_eq!(witness.missing().count(), expected_missing);
+                    assert_eq!(*absolute_timelock, None);
+                    assert_eq!(*relative_timelock, None);
+                }
+                InputProgress::Finalized => panic!("finalized without every signature"),
+            }
+            assert!(psbt.inputs[0].final_script_witness.is_none());
+
+            let sig = bitcoin::ecdsa::Signature::sighash_all(secp.sign_ecdsa(&msg, sk));
+            psbt.inputs[0].partial_sigs.insert(*pk, sig);
+            expected_missing -= 1;
+        }
+
+        let progress = psbt.finalize_partial(&secp, &plans).unwrap();
+        assert_eq!(progress, vec![InputProgress::Finalized]);
+        assert!(psbt.inputs[0].final_script_witness.is_some());
+        assert!(psbt.inputs[0].partial_sigs.is_empty());
+        // Finalized inputs are skipped.
+        assert!(psbt.finalize_partial(&secp, &plans).unwrap()[0].is_finalized());
+    }
+
+    #[test]
+    fn test_rawtr_key_spend() {
+        let secp = Secp256k1::new();
+        let sk = bitcoin::secp256k1::SecretKey::from_slice(&[3; 32]).unwrap();
+        let keypair = sk.keypair(&secp);
+        let (x_only, _) = keypair.x_only_public_key();
+        let desc =
+            Descriptor::<DefiniteDescriptorKey>::from_str(&format!("rawtr({})", x_only)).unwrap();
+        let assets = crate::plan::Assets::new()
+            .add(DescriptorPublicKey::from_str(&x_only.to_string()).unwrap());
+        let plan = desc.clone().plan(&assets).unwrap();
+
+        let tx = bitcoin::Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![TxIn::default()],
+            output: vec![],
+        };
+        let mut psbt = Psbt::from_unsigned_tx(tx).unwrap();
+        psbt.inputs[0].witness_utxo =
+            Some(TxOut { value: Amount::from_sat(10_000), script_pubkey: desc.script_pubkey() });
+        psbt.update_input_with_descriptor(0, &desc).unwrap();
+        assert!(psbt.inputs[0].tap_key_origins.contains_key(&x_only));
+        assert!(psbt.inputs[0].bip32_derivation.is_empty());
+
+        // The signature is made with the untweaked output key
+        let msg = psbt
+            .sighash_msg(0, &mut SighashCache::new(&psbt.unsigned_tx), None)
+            .unwrap()
+            .to_secp_msg();
+        let sig = taproot::Signature {
+            signature: secp.sign_schnorr_no_aux_rand(&msg, &keypair),
+            sighash_type: bitcoin::TapSighashType::Default,
+        };
+        psbt.inputs[0].tap_key_sig = Some(sig);
+
+        let sat = PsbtInputSatisfier::new(&psbt, 0);
+        let (witness, script_sig) = plan.satisfy(&sat).unwrap();
+        assert_eq!(witness, vec![sig.to_vec()]);
+        assert_eq!(script_sig, ScriptBuf::new());
+        assert_eq!(desc.get_satisfaction(&sat).unwrap().0, witness);
+
+        // The key spend signature isn't used for other keys
+        let other = Descriptor::<DefiniteDescriptorKey>::from_str(
+            "rawtr(a34b99f22c790c4e36b2b3c2c35a36db06226e41c692fc82b8b56ac1c540c5bd)",
+        )
+        .unwrap();
+        assert!(other.get_satisfaction(&sat).is_err());
+
+        psbt.finalize_mut(&secp).unwrap();
+        assert_eq!(
+            psbt.inputs[0]
+                .final_script_witness
+                .as_ref()
+                .unwrap()
+                .to_vec(),
+            witness
+        );
+    }
+
+    #[test]
+    fn test_finalize_with_registry() {
+        let secp = Secp256k1::new();
+        let sks: Vec<_> = (1..=2)
+            .map(|i| bitcoin::secp256k1::SecretKey::from_slice(&[i; 32]).unwrap())
+            .collect();
+        let pks: Vec<_> = sks
+            .iter()
+            .map(|sk| bitcoin::PublicKey::new(PublicKey::from_secret_key(&secp, sk)))
+            .collect();
+        let desc = format!("wsh(or_b(pkh({}),a:pk({})))", pks[1], pks[0]);
+        let desc = Descriptor::<DefiniteDescriptorKey>::from_str(&desc).unwrap();
+
+        let tx = bitcoin::Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![TxIn::default()],
+            output: vec![],
+        };
+        let mut psbt = Psbt::from_unsigned_tx(tx).unwrap();
+        psbt.inputs[0].witness_utxo =
+            Some(TxOut { value: Amount::from_sat(10_000), script_pubkey: desc.script_pubkey() });
+        psbt.inputs[0].witness_script = Some(desc.explicit_script().unwrap());
+        let msg = psbt
+            .sighash_msg(0, &mut SighashCache::new(&psbt.unsigned_tx), None)
+            .unwrap()
+            .to_secp_msg();
+        let sig = bitcoin::ecdsa::Signature::sighash_all(secp.sign_ecdsa(&msg, &sks[0]));
+        psbt.inputs[0].partial_sigs.insert(pks[0], sig);
+
+        // The PSBT doesn't tell the key of the pkh fragment, which can't be dissatisfied
+        let mut unresolved = psbt.clone();
+        assert!(unresolved.finalize_mut(&secp).is_err());
+        assert!(unresolved
+            .finalize_with_registry_mut(&secp, &KeyRegistry::new())
+            .is_err());
+
+        let registry: KeyRegistry = pks[1..].iter().copied().collect();
+        psbt.finalize_with_registry_mut(&secp, &registry).unwrap();
+        let witness = psbt.inputs[0].final_script_witness.as_ref().unwrap();
+        assert!(witness.iter().any(|item| item == pks[1].to_bytes()));
+        assert!(witness.iter().any(|item| item == sig.to_vec()));
+        psbt.extract(&secp).unwrap();
+    }
+
+    #[test]
+    fn test_update_with_descriptors() {
+        let secp = Secp256k1::verification_only();
+        let xpub = "[73c5da0a/84'/0'/0']xpub6BgBgsespWvERF3LHQu6CnqdvfEvtMcQjYrcRzx53QJjSxarj2afYWcLteoGVky7D3UKDP9QyrLprQ3VCECoY49yfdDEHGCtMMj92pReUsQ";
+        let wpkh = Descriptor::from_str(&format!("wpkh({}/<0;1>/*)", xpub)).unwrap();
+        let tr = Descriptor::from_str(&format!("tr({}/2/*)", xpub)).unwrap();
+        let descriptors = [wpkh.clone(), tr.clone()];
+        let spk = |desc: &Descriptor<DescriptorPublicKey>, index| {
+            desc.at_derivation_index(index).unwrap().script_pubkey()
+        };
+        let mut paths = wpkh.clone().into_single_descriptors().unwrap();
+        let (receive, change) = (paths.remove(0), paths.remove(0));
+
+        let utxo = |script_pubkey| TxOut { value: Amount::from_sat(1_000), script_pubkey };
+        let tx = bitcoin::Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![TxIn::default(); 5],
+            output: vec![utxo(spk(&change, 2)), utxo(ScriptBuf::new_op_return([]))],
+        };
+        let mut psbt = Psbt::from_unsigned_tx(tx).unwrap();
+        // Index 15 is only reached because index 7 extends the window
+        psbt.inputs[0].witness_utxo = Some(utxo(spk(&receive, 7)));
+        psbt.inputs[1].witness_utxo = Some(utxo(spk(&receive, 15)));
+        psbt.inputs[2].witness_utxo = Some(utxo(spk(&receive, 40)));
+        psbt.inputs[3].witness_utxo = Some(utxo(spk(&tr, 3)));
+
+        let update = psbt
+            .update_with_descriptors(&secp, &descriptors, 10)
+            .unwrap();
+        let origin = |descriptor, index| Some(ScriptOrigin { descriptor, index });
+        assert_eq!(update.inputs, vec![origin(0, 7), origin(0, 15), None, origin(1, 3), None]);
+        assert_eq!(update.outputs, vec![origin(0, 2), None]);
+        assert_eq!((update.n_inputs(), update.n_outputs()), (3, 1));
+
+        let (_, path) = psbt.inputs[1].bip32_derivation.values().next().unwrap();
+        assert_eq!(path.to_string(), "84'/0'/0'/0/15");
+        let (_, path) = psbt.outputs[0].bip32_derivation.values().next().unwrap();
+        assert_eq!(path.to_string(), "84'/0'/0'/1/2");
+        assert!(psbt.inputs[2].bip32_derivation.is_empty());
+        assert!(psbt.inputs[3].tap_internal_key.is_some());
+        assert_eq!(psbt.inputs[3].tap_key_origins.len(), 1);
+
+        assert!(update.failed_inputs.is_empty() && update.failed_outputs.is_empty());
+
+        // A legacy descriptor can't be checked against a witness_utxo alone, which doesn't
+        // prevent updating the other inputs
+        let pkh = Descriptor::from_str(&format!("pkh({}/0/*)", xpub)).unwrap();
+        psbt.inputs[4].witness_utxo = Some(utxo(spk(&pkh, 0)));
+        psbt.inputs[2].witness_utxo = Some(utxo(spk(&pkh, 1)));
+        psbt.inputs[2].non_witness_utxo = Some(bitcoin::Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![],
+            output: vec![utxo(spk(&pkh, 1))],
+        });
+        psbt.unsigned_tx.input[2].previous_output = OutPoint::new(
+            psbt.inputs[2]
+                .non_witness_utxo
+                .as_ref()
+                .unwrap()
+                .compute_txid(),
+            0,
+        );
+        let before = psbt.inputs[4].clone();
+        let update = psbt.update_with_descriptors(&secp, &[pkh], 10).unwrap();
+        assert_eq!(update.inputs[2], origin(0, 1));
+        assert_eq!(update.failed_inputs, vec![(4, UtxoUpdateError::UtxoCheck)]);
+        assert_eq!(psbt.inputs[4], before);
+        assert_eq!(psbt.inputs[2].bip32_derivation.len(), 1);
+    }
+
     #[test]
     fn test_update_output_checks() {
         let desc = "tr([73c5da0a/86'/0'/0']xpub6BgBgsespWvERF3LHQu6CnqdvfEvtMcQjYrcRzx53QJjSxarj2afYWcLteoGVky7D3UKDP9QyrLprQ3VCECoY49yfdDEHGCtMMj92pReUsQ/0/0)";
@@ -0,0 +1,424 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! # PSBT Signer
+//!
+//! Implementation of the Signer role defined in BIP 174 for the inputs locked by
+//! a descriptor, with keys derived from a [`SignerProvider`].
+//!
+
+use core::fmt;
+#[cfg(feature = "std")]
+use std::error;
+
+use bitcoin::bip32::{self, ChildNumber};
+use bitcoin::key::{Keypair, TapTweak, XOnlyPublicKey};
+use bitcoin::psbt::Psbt;
+use bitcoin::secp256k1::{self, Secp256k1};
+use bitcoin::sighash::SighashCache;
+use bitcoin::taproot::{LeafVersion, TapLeafHash};
+use bitcoin::{ecdsa, sighash, taproot};
+
+use super::{finalizer, PsbtExt, SighashError, UtxoUpdateError};
+use crate::descriptor::erase_xpriv;
+use crate::prelude::*;
+use crate::{DefiniteDescriptorKey, Descriptor, DescriptorPublicKey, ForEachKey};
+
+/// A source of secret keys to sign PSBT inputs with, see [`sign_with_descriptor`]
+pub trait SignerProvider {
+    /// Looks up the secret key of the public key with origin `key_source`
+    fn secret_key<C: secp256k1::Signing>(
+        &self,
+        key_source: &bip32::KeySource,
+        secp: &Secp256k1<C>,
+    ) -> Option<secp256k1::SecretKey>;
+
+    /// Fresh auxiliary randomness for a Schnorr signature, as recommended by BIP 340
+    ///
+    /// Each call must return new random bytes. Defaults to none, in which case Schnorr
+    /// signatures are made without auxiliary randomness.
+    fn aux_rand(&self) -> Option<[u8; 32]> { None }
+}
+
+/// Derives the keys whose origin is this master key
+impl SignerProvider for bip32::Xpriv {
+    fn secret_key<C: secp256k1::Signing>(
+        &self,
+        key_source: &bip32::KeySource,
+        secp: &Secp256k1<C>,
+    ) -> Option<secp256k1::SecretKey> {
+        if self.fingerprint(secp) != key_source.0 {
+            return None;
+        }
+        let mut xpriv = self.derive_priv(secp, &key_source.1).ok()?;
+        let sk = xpriv.private_key;
+        erase_xpriv(&mut xpriv);
+        Some(sk)
+    }
+}
+
+impl<T: SignerProvider> SignerProvider for &T {
+    fn secret_key<C: secp256k1::Signing>(
+        &self,
+        key_source: &bip32::KeySource,
+        secp: &Secp256k1<C>,
+    ) -> Option<secp256k1::SecretKey> {
+        (**self).secret_key(key_source, secp)
+    }
+
+    fn aux_rand(&self) -> Option<[u8; 32]> { (**self).aux_rand() }
+}
+
+/// Error signing a PSBT, see [`sign_with_descriptor`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SignError {
+    /// The input couldn't be updated with the descriptor
+    Update(usize, UtxoUpdateError),
+    /// The sighash of the input couldn't be computed
+    Sighash(usize, SighashError),
+}
+
+impl fmt::Display for SignError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SignError::Update(index, ref e) => write!(f, "update of input {}: {}", index, e),
+            SignError::Sighash(index, ref e) => write!(f, "sighash of input {}: {}", index, e),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl error::Error for SignError {
+    fn cause(&self) -> Option<&dyn error::Error> {
+        match self {
+            SignError::Update(_, ref e) => Some(e),
+            SignError::Sighash(_, ref e) => Some(e),
+        }
+    }
+}
+
+/// Signs every input of `psbt` locked by `descriptor` with the keys of `provider`
+///
+/// An input is locked by the descriptor if its utxo pays to the descriptor derived
+/// at the last step of one of the key origins of the input, as recorded by
+/// [`PsbtExt::update_input_with_descriptor`], or derived at any index if it has no
+/// wildcard. Such inputs are updated with the derived descriptor, then every key of
+/// the descriptor whose secret key is known to `provider` signs:
+///
+/// * with ECDSA into `partial_sigs` for pre-taproot descriptors,
+/// * with Schnorr into `tap_key_sig` for the internal key of `tr` descriptors and
+///   the key of `rawtr` descriptors, and into `tap_script_sigs` for the keys of
+///   each leaf of `tr` descriptors.
+///
+/// The sighash type of each input is used, defaulting to `ALL` and `DEFAULT`.
+/// Existing signatures are replaced. Returns the indexes of the signed inputs. The
+/// secret keys returned by `provider` and the keypairs made from them are erased
+/// once the input is signed.
+///
+/// # Errors
+///
+/// If an input locked by the descriptor can't be updated, or its sighash can't
+/// be computed, e.g. because the utxo of another input is missing.
+pub fn sign_with_descriptor<P, C>(
+    psbt: &mut Psbt,
+    descriptor: &Descriptor<DescriptorPublicKey>,
+    provider: &P,
+    secp: &Secp256k1<C>,
+) -> Result<Vec<usize>, SignError>
+where
+    P: SignerProvider,
+    C: secp256k1::Signing + secp256k1::Verification,
+{
+    // A descriptor with mismatched multipath keys locks nothing
+    let descriptors = descriptor
+        .clone()
+        .into_single_descriptors()
+        .unwrap_or_default();
+    let mut cache = SighashCache::new(psbt.unsigned_tx.clone());
+    let mut signed = vec![];
+    for index in 0..psbt.inputs.len() {
+        let derived = match input_descriptor(psbt, index, &descriptors) {
+            Some(derived) => derived,
+            None => continue,
+        };
+        psbt.update_input_with_descriptor(index, &derived)
+            .map_err(|e| SignError::Update(index, e))?;
+        if sign_input(psbt, index, &derived, provider, secp, &mut cache)? {
+            signed.push(index);
+        }
+    }
+    Ok(signed)
+}
+
+/// Finds the descriptor locking the input, derived at the right index
+fn input_descriptor(
+    psbt: &Psbt,
+    index: usize,
+    descriptors: &[Descriptor<DescriptorPublicKey>],
+) -> Option<Descriptor<DefiniteDescriptorKey>> {
+    let utxo = finalizer::get_utxo(psbt, index).ok()?;
+    let input = &psbt.inputs[index];
+    let mut indexes: Vec<u32> = input
+        .bip32_derivation
+        .values()
+        .chain(input.tap_key_origins.values().map(|(_, source)| source))
+        .filter_map(|(_, path)| match path.into_iter().last() {
+            Some(ChildNumber::Normal { index }) => Some(*index),
+            _ => None,
+        })
+        .collect();
+    indexes.sort_unstable();
+    indexes.dedup();
+
+    descriptors.iter().find_map(|desc| {
+        let candidates = if desc.has_wildcard() {
+            &indexes[..]
+        } else {
+            &[0][..]
+        };
+        candidates.iter().find_map(|&i| {
+            let derived = desc.at_derivation_index(i).ok()?;
+            if derived.script_pubkey() == utxo.script_pubkey {
+                Some(derived)
+            } else {
+                None
+            }
+        })
+    })
+}
+
+/// The keypairs of the keys of a descriptor, which are erased when dropped
+struct Keypairs(Vec<(bitcoin::PublicKey, Keypair)>);
+
+impl Drop for Keypairs {
+    fn drop(&mut self) {
+        for (_, keypair) in &mut self.0 {
+            keypair.non_secure_erase();
+        }
+    }
+}
+
+/// Signs the input with the keys of the derived descriptor known to the provider,
+/// returning whether a signature was added
+fn sign_input<P, C>(
+    psbt: &mut Psbt,
+    index: usize,
+    derived: &Descriptor<DefiniteDescriptorKey>,
+    provider: &P,
+    secp: &Secp256k1<C>,
+    cache: &mut SighashCache<bitcoin::Transaction>,
+) -> Result<bool, SignError>
+where
+    P: SignerProvider,
+    C: secp256k1::Signing + secp256k1::Verification,
+{
+    let keypair = |key: &DefiniteDescriptorKey| {
+        let pk = key.derive_public_key(secp).ok()?;
+        let source = (key.master_fingerprint(), key.full_derivation_path()?);
+        let mut sk = provider.secret_key(&source, secp)?;
+        let mut keypair = Keypair::from_secret_key(secp, &sk);
+        sk.non_secure_erase();
+        // Never sign with a key which isn't the one of the descriptor
+        if keypair.public_key() != pk.inner {
+            keypair.non_secure_erase();
+            return None;
+        }
+        Some((pk, keypair))
+    };
+    let sighash = |psbt: &Psbt, cache: &mut SighashCache<_>, leaf_hash| {
+        psbt.sighash_msg(index, cache, leaf_hash)
+            .map(|msg| msg.to_secp_msg())
+            .map_err(|e| SignError::Sighash(index, e))
+    };
+    let sign_schnorr = |msg: &secp256k1::Message, keypair: &Keypair| match provider.aux_rand() {
+        Some(aux_rand) => secp.sign_schnorr_with_aux_rand(msg, keypair, &aux_rand),
+        None => secp.sign_schnorr_no_aux_rand(msg, keypair),
+    };
+    let mut signed = false;
+
+    match derived {
+        Descriptor::Tr(tr) => {
+            let sighash_type = tap_sighash_type(psbt, index)?;
+            let internal = Keypairs(keypair(tr.internal_key()).into_iter().collect());
+            if let Some((_, keypair)) = internal.0.first() {
+                let msg = sighash(psbt, cache, None)?;
+                let merkle_root = tr.spend_info().merkle_root();
+                let mut tweaked = keypair.tap_tweak(secp, merkle_root).to_keypair();
+                let signature = sign_schnorr(&msg, &tweaked);
+                tweaked.non_secure_erase();
+                psbt.inputs[index].tap_key_sig =
+                    Some(taproot::Signature { signature, sighash_type });
+                signed = true;
+            }
+            for (_, ms) in tr.iter_scripts() {
+                let leaf_hash = TapLeafHash::from_script(&ms.encode(), LeafVersion::TapScript);
+                let mut keys = Keypairs(vec![]);
+                ms.for_each_key(|key| {
+                    keys.0.extend(keypair(key));
+                    true
+                });
+                if keys.0.is_empty() {
+                    continue;
+                }
+                let msg = sighash(psbt, cache, Some(leaf_hash))?;
+                for (pk, keypair) in &keys.0 {
+                    let signature = sign_schnorr(&msg, keypair);
+                    let xonly = XOnlyPublicKey::from(pk.inner);
+                    psbt.inputs[index]
+                        .tap_script_sigs
+                        .insert((xonly, leaf_hash), taproot::Signature { signature, sighash_type });
+                    signed = true;
+                }
+            }
+        }
+        Descriptor::RawTr(rawtr) => {
+            let sighash_type = tap_sighash_type(psbt, index)?;
+            let internal = Keypairs(keypair(rawtr.as_inner()).into_iter().collect());
+            if let Some((_, keypair)) = internal.0.first() {
+                let msg = sighash(psbt, cache, None)?;
+                let signature = sign_schnorr(&msg, keypair);
+                psbt.inputs[index].tap_key_sig =
+                    Some(taproot::Signature { signature, sighash_type });
+                signed = true;
+            }
+        }
+        Descriptor::Bare(..)
+        | Descriptor::Pkh(..)
+        | Descriptor::Wpkh(..)
+        | Descriptor::Sh(..)
+        | Descriptor::Wsh(..) => {
+            let sighash_type = psbt.inputs[index]
+                .sighash_type
+                .map(|ty| ty.ecdsa_hash_ty())
+                .unwrap_or(Ok(sighash::EcdsaSighashType::All))
+                .map_err(|_| SignError::Sighash(index, SighashError::InvalidSighashType))?;
+            let mut keys = Keypairs(vec![]);
+            derived.for_each_key(|key| {
+                keys.0.extend(keypair(key));
+                true
+            });
+            if keys.0.is_empty() {
+                return Ok(false);
+            }
+            let msg = sighash(psbt, cache, None)?;
+            for (pk, keypair) in &keys.0 {
+                let mut sk = keypair.secret_key();
+                let signature = secp.sign_ecdsa(&msg, &sk);
+                sk.non_secure_erase();
+                psbt.inputs[index]
+                    .partial_sigs
+                    .insert(*pk, ecdsa::Signature { signature, sighash_type });
+                signed = true;
+            }
+        }
+        // Nothing to sign
+        Descriptor::Data(..) | Descriptor::Wother(..) => {}
+    }
+    Ok(signed)
+}
+
+/// The taproot sighash type of the input
+fn tap_sighash_type(psbt: &Psbt, index: usize) -> Result<sighash::TapSighashType, SignError> {
+    psbt.inputs[index]
+        .sighash_type
+        .map(|ty| ty.taproot_hash_ty())
+        .unwrap_or(Ok(sighash::TapSighashType::Default))
+        .map_err(|_| SignError::Sighash(index, SighashError::InvalidSighashType))
+}
+
+#[cfg(test)]
+mod tests {
+    use core::str::FromStr;
+
+    use bitcoin::bip32::{Xpriv, Xpub};
+    use bitcoin::{absolute, transaction, Amount, Network, Transaction, TxIn, TxOut};
+
+    use super::*;
+
+    #[test]
+    fn sign_wpkh_and_tr() {
+        let secp = Secp256k1::new();
+        let xpriv = Xpriv::new_master(Network::Bitcoin, &[1; 32]).unwrap();
+        let xpub = Xpub::from_priv(&secp, &xpriv);
+        let wpkh =
+            Descriptor::<DescriptorPublicKey>::from_str(&format!("wpkh({}/0/*)", xpub)).unwrap();
+        let tr = Descriptor::<DescriptorPublicKey>::from_str(&format!(
+            "tr({}/1/*,pk({}/2/*))",
+            xpub, xpub
+        ))
+        .unwrap();
+        let other = Descriptor::<DescriptorPublicKey>::from_str(
+            "wpkh(02a34b99f22c790c4e36b2b3c2c35a36db06226e41c692fc82b8b56ac1c540c5bd)",
+        )
+        .unwrap();
+
+        let tx = Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![TxIn::default(); 3],
+            output: vec![],
+        };
+        let mut psbt = Psbt::from_unsigned_tx(tx).unwrap();
+        let inputs = [(&wpkh, 3), (&tr, 5), (&other, 0)];
+        for (index, (desc, child)) in inputs.iter().enumerate() {
+            let derived = desc.at_derivation_index(*child).unwrap();
+            psbt.inputs[index].witness_utxo = Some(TxOut {
+                value: Amount::from_sat(10_000),
+                script_pubkey: derived.script_pubkey(),
+            });
+            psbt.update_input_with_descriptor(index, &derived).unwrap();
+        }
+
+        assert_eq!(sign_with_descriptor(&mut psbt, &wpkh, &xpriv, &secp), Ok(vec![0]));
+        assert_eq!(psbt.inputs[0].partial_sigs.len(), 1);
+        assert_eq!(sign_with_descriptor(&mut psbt, &tr, &xpriv, &secp), Ok(vec![1]));
+        assert!(psbt.inputs[1].tap_key_sig.is_some());
+        assert_eq!(psbt.inputs[1].tap_script_sigs.len(), 1);
+        // Keys of other masters aren't signed with
+        let stranger = Xpriv::new_master(Network::Bitcoin, &[2; 32]).unwrap();
+        assert_eq!(sign_with_descriptor(&mut psbt, &other, &stranger, &secp), Ok(vec![]));
+        assert!(psbt.inputs[2].partial_sigs.is_empty());
+
+        psbt.finalize_inp_mut(&secp, 0).unwrap();
+        psbt.finalize_inp_mut(&secp, 1).unwrap();
+        assert!(psbt.finalize_inp_mut(&secp, 2).is_err());
+    }
+
+    #[test]
+    fn sign_with_aux_rand() {
+        struct AuxRand(Xpriv);
+
+        impl SignerProvider for AuxRand {
+            fn secret_key<C: secp256k1::Signing>(
+                &self,
+                key_source: &bip32::KeySource,
+                secp: &Secp256k1<C>,
+            ) -> Option<secp256k1::SecretKey> {
+                self.0.secret_key(key_source, secp)
+            }
+
+            fn aux_rand(&self) -> Option<[u8; 32]> { Some([7; 32]) }
+        }
+
+        let secp = Secp256k1::new();
+        let xpriv = Xpriv::new_master(Network::Bitcoin, &[1; 32]).unwrap();
+        let xpub = Xpub::from_priv(&secp, &xpriv);
+        let tr = Descriptor::<DescriptorPublicKey>::from_str(&format!("tr({}/0/*)", xpub)).unwrap();
+        let derived = tr.at_derivation_index(0).unwrap();
+        let tx = Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![TxIn::default()],
+            output: vec![],
+        };
+        let mut psbt = Psbt::from_unsigned_tx(tx).unwrap();
+        psbt.inputs[0].witness_utxo =
+            Some(TxOut { value: Amount::from_sat(10_000), script_pubkey: derived.script_pubkey() });
+        psbt.update_input_with_descriptor(0, &derived).unwrap();
+
+        let mut with_aux_rand = psbt.clone();
+        sign_with_descriptor(&mut psbt, &tr, &xpriv, &secp).unwrap();
+        sign_with_descriptor(&mut with_aux_rand, &tr, &AuxRand(xpriv), &secp).unwrap();
+        assert_ne!(psbt.inputs[0].tap_key_sig, with_aux_rand.inputs[0].tap_key_sig);
+        with_aux_rand.finalize_inp_mut(&secp, 0).unwrap();
+    }
+}
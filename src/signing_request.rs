@@ -0,0 +1,684 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! # Signing Requests
+//!
+//! Stateless request and response types for remote signers. A [`SigningRequest`]
+//! lists the sighashes a [`Plan`] needs signatures for and the keys which must sign
+//! them, a [`SigningResponse`] carries the signatures back and can be used as a
+//! [`Satisfier`] of the plan. With the `serde` feature both serialize to flat JSON
+//! objects of strings, e.g. for webhooks:
+//!
+//! ```json
+//! { "sighashes": [{ "key": "02...", "kind": "ecdsa", "sighash_type": "SIGHASH_ALL", "sighash": "6b..." }] }
+//! { "signatures": [{ "key": "02...", "kind": "ecdsa", "signature": "3044..." }] }
+//! ```
+//!
+
+use core::fmt;
+#[cfg(feature = "std")]
+use std::error;
+
+use bitcoin::hashes::hash160;
+use bitcoin::key::TapTweak;
+use bitcoin::psbt::{Psbt, PsbtSighashType};
+use bitcoin::secp256k1;
+use bitcoin::sighash::SighashCache;
+use bitcoin::taproot::{TapLeafHash, TapNodeHash};
+
+use crate::miniscript::satisfy::{Placeholder, SchnorrSigType};
+use crate::plan::Plan;
+use crate::prelude::*;
+use crate::psbt::{PsbtExt, SighashError};
+use crate::{DefiniteDescriptorKey, Satisfier, ToPublicKey};
+
+/// Error creating a signing request or checking its response
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// The sighash of a requested signature couldn't be computed
+    Sighash(SighashError),
+    /// The plan needs a signature for a raw public key hash, whose key is unknown
+    RawPkh(hash160::Hash),
+    /// The response has a signature which wasn't requested
+    Unrequested(DefiniteDescriptorKey),
+    /// The response has an invalid signature
+    InvalidSignature(DefiniteDescriptorKey),
+    /// The response has a signature whose sighash type isn't the requested one
+    WrongSighashType(DefiniteDescriptorKey, PsbtSighashType),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Sighash(ref e) => write!(f, "sighash: {}", e),
+            Error::RawPkh(ref h) => write!(f, "can't request a signature for raw pkh {}", h),
+            Error::Unrequested(ref pk) => write!(f, "signature by {} wasn't requested", pk),
+            Error::InvalidSignature(ref pk) => write!(f, "invalid signature by {}", pk),
+            Error::WrongSighashType(ref pk, ty) => {
+                write!(f, "signature by {} isn't of sighash type {}", pk, ty)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl error::Error for Error {
+    fn cause(&self) -> Option<&dyn error::Error> {
+        match self {
+            Error::Sighash(e) => Some(e),
+            Error::RawPkh(_)
+            | Error::Unrequested(_)
+            | Error::InvalidSignature(_)
+            | Error::WrongSighashType(..) => None,
+        }
+    }
+}
+
+/// The kind of signature requested
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum SignatureKind {
+    /// An ECDSA signature, legacy or segwit v0
+    Ecdsa,
+    /// A Schnorr signature for a taproot key spend, by the key tweaked with the merkle root
+    TapKeySpend {
+        /// Merkle root to tweak the key, if any
+        merkle_root: Option<TapNodeHash>,
+    },
+    /// A Schnorr signature for a taproot script spend
+    TapScriptSpend {
+        /// Leaf hash of the script
+        leaf_hash: TapLeafHash,
+    },
+    /// A Schnorr signature for the key spend of a `rawtr()` output, by the untweaked key
+    RawTrKeySpend,
+}
+
+/// A sighash to be signed by a key
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SighashRequest {
+    /// The key which must sign
+    pub key: DefiniteDescriptorKey,
+    /// The kind of signature
+    pub kind: SignatureKind,
+    /// The sighash type the sighash was computed with, which the signature must carry
+    pub sighash_type: PsbtSighashType,
+    /// The sighash to sign
+    pub sighash: [u8; 32],
+}
+
+impl SighashRequest {
+    /// The message to sign
+    pub fn message(&self) -> secp256k1::Message { secp256k1::Message::from_digest(self.sighash) }
+}
+
+/// The signatures a plan needs
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SigningRequest {
+    /// The sighashes to sign, in witness order
+    pub sighashes: Vec<SighashRequest>,
+}
+
+impl SigningRequest {
+    /// The signatures `plan` needs to spend the input `index` of `psbt`
+    ///
+    /// The input must have been updated with [`Plan::update_psbt_input`], and its sighash
+    /// type is used if it is set. Only signatures are requested, the other requirements of
+    /// the plan, e.g. hash preimages, must be satisfied separately.
+    pub fn from_plan(plan: &Plan, psbt: &Psbt, index: usize) -> Result<Self, Error> {
+        let mut cache = SighashCache::new(&psbt.unsigned_tx);
+        let mut sighashes = vec![];
+        for placeholder in plan.requirements() {
+            let (key, kind) = match placeholder {
+                Placeholder::EcdsaSigPk(pk) => (pk, SignatureKind::Ecdsa),
+                Placeholder::SchnorrSigPk(pk, SchnorrSigType::KeySpend { merkle_root }, _) => {
+                    (pk, SignatureKind::TapKeySpend { merkle_root: *merkle_root })
+                }
+                Placeholder::SchnorrSigPk(pk, SchnorrSigType::ScriptSpend { leaf_hash }, _) => {
+                    (pk, SignatureKind::TapScriptSpend { leaf_hash: *leaf_hash })
+                }
+                Placeholder::SchnorrSigPk(pk, SchnorrSigType::RawKeySpend, _) => {
+                    (pk, SignatureKind::RawTrKeySpend)
+                }
+                Placeholder::EcdsaSigPkHash(h) | Placeholder::SchnorrSigPkHash(h, ..) => {
+                    return Err(Error::RawPkh(*h))
+                }
+                _ => continue,
+            };
+            let leaf_hash = match kind {
+                SignatureKind::TapScriptSpend { leaf_hash } => Some(leaf_hash),
+                _ => None,
+            };
+            // The same defaults as `PsbtExt::sighash_msg`
+            let sighash_type = psbt.inputs[index]
+                .sighash_type
+                .unwrap_or_else(|| match kind {
+                    SignatureKind::Ecdsa => bitcoin::EcdsaSighashType::All.into(),
+                    _ => bitcoin::TapSighashType::Default.into(),
+                });
+            let msg = psbt
+                .sighash_msg(index, &mut cache, leaf_hash)
+                .map_err(Error::Sighash)?;
+            let request = SighashRequest {
+                key: key.clone(),
+                kind,
+                sighash_type,
+                sighash: msg.to_secp_msg().as_ref().to_owned(),
+            };
+            if !sighashes.contains(&request) {
+                sighashes.push(request);
+            }
+        }
+        Ok(SigningRequest { sighashes })
+    }
+}
+
+/// A signature, ECDSA or Schnorr
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AnySignature {
+    /// An ECDSA signature
+    Ecdsa(bitcoin::ecdsa::Signature),
+    /// A Schnorr signature
+    Schnorr(bitcoin::taproot::Signature),
+}
+
+/// A signature of a requested sighash
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SighashSignature {
+    /// The key which signed
+    pub key: DefiniteDescriptorKey,
+    /// The kind of signature
+    pub kind: SignatureKind,
+    /// The signature
+    pub signature: AnySignature,
+}
+
+/// The signatures of a [`SigningRequest`]
+///
+/// It can be passed to [`Plan::satisfy`] to sign with the signatures it contains.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SigningResponse {
+    /// The signatures, possibly of a subset of the sighashes requested
+    pub signatures: Vec<SighashSignature>,
+}
+
+impl SigningResponse {
+    /// Answers `request` with the signatures `satisfier` has, e.g. on the signer side
+    pub fn from_satisfier<S: Satisfier<DefiniteDescriptorKey>>(
+        request: &SigningRequest,
+        satisfier: &S,
+    ) -> Self {
+        let signatures = request
+            .sighashes
+            .iter()
+            .filter_map(|req| {
+                let signature = match req.kind {
+                    SignatureKind::Ecdsa => {
+                        AnySignature::Ecdsa(satisfier.lookup_ecdsa_sig(&req.key)?)
+                    }
+                    SignatureKind::TapKeySpend { .. } => {
+                        AnySignature::Schnorr(satisfier.lookup_tap_key_spend_sig()?)
+                    }
+                    SignatureKind::TapScriptSpend { leaf_hash } => AnySignature::Schnorr(
+                        satisfier.lookup_tap_leaf_script_sig(&req.key, &leaf_hash)?,
+                    ),
+                    SignatureKind::RawTrKeySpend => {
+                        AnySignature::Schnorr(satisfier.lookup_raw_tr_schnorr_sig(&req.key)?)
+                    }
+                };
+                Some(SighashSignature { key: req.key.clone(), kind: req.kind, signature })
+            })
+            .collect();
+        SigningResponse { signatures }
+    }
+
+    /// Checks that every signature is a valid signature of a sighash of `request`, and
+    /// carries the sighash type it was computed with
+    pub fn verify<C: secp256k1::Verification>(
+        &self,
+        secp: &secp256k1::Secp256k1<C>,
+        request: &SigningRequest,
+    ) -> Result<(), Error> {
+        for sig in &self.signatures {
+            let req = request
+                .sighashes
+                .iter()
+                .find(|req| req.key == sig.key && req.kind == sig.kind)
+                .ok_or_else(|| Error::Unrequested(sig.key.clone()))?;
+            let sighash_type_matches = match sig.signature {
+                AnySignature::Ecdsa(ecdsa) => {
+                    req.sighash_type.ecdsa_hash_ty() == Ok(ecdsa.sighash_type)
+                }
+                AnySignature::Schnorr(schnorr) => {
+                    req.sighash_type.taproot_hash_ty() == Ok(schnorr.sighash_type)
+                }
+            };
+            if !sighash_type_matches {
+                return Err(Error::WrongSighashType(sig.key.clone(), req.sighash_type));
+            }
+            let valid = match (sig.kind, sig.signature) {
+                (SignatureKind::Ecdsa, AnySignature::Ecdsa(ecdsa)) => secp
+                    .verify_ecdsa(&req.message(), &ecdsa.signature, &sig.key.to_public_key().inner)
+                    .is_ok(),
+                (SignatureKind::TapKeySpend { merkle_root }, AnySignature::Schnorr(schnorr)) => {
+                    let (output_key, _) = sig.key.to_x_only_pubkey().tap_tweak(secp, merkle_root);
+                    secp.verify_schnorr(
+                        &schnorr.signature,
+                        &req.message(),
+                        &output_key.to_x_only_public_key(),
+                    )
+                    .is_ok()
+                }
+                (
+                    SignatureKind::TapScriptSpend { .. } | SignatureKind::RawTrKeySpend,
+                    AnySignature::Schnorr(schnorr),
+                ) => secp
+                    .verify_schnorr(&schnorr.signature, &req.message(), &sig.key.to_x_only_pubkey())
+                    .is_ok(),
+                _ => false,
+            };
+            if !valid {
+                return Err(Error::InvalidSignature(sig.key.clone()));
+            }
+        }
+        Ok(())
+    }
+
+    fn find(&self, f: impl Fn(&SighashSignature) -> bool) -> Option<AnySignature> {
+        self.signatures
+            .iter()
+            .find(|sig| f(sig))
+            .map(|sig| sig.signature)
+    }
+}
+
+impl Satisfier<DefiniteDescriptorKey> for SigningResponse {
+    fn lookup_ecdsa_sig(&self, pk: &DefiniteDescriptorKey) -> Option<bitcoin::ecdsa::Signature> {
+        match self.find(|sig| sig.key == *pk && sig.kind == SignatureKind::Ecdsa)? {
+            AnySignature::Ecdsa(sig) => Some(sig),
+            AnySignature::Schnorr(_) => None,
+        }
+    }
+
+    fn lookup_tap_key_spend_sig(&self) -> Option<bitcoin::taproot::Signature> {
+        match self.find(|sig| matches!(sig.kind, SignatureKind::TapKeySpend { .. }))? {
+            AnySignature::Schnorr(sig) => Some(sig),
+            AnySignature::Ecdsa(_) => None,
+        }
+    }
+
+    fn lookup_raw_tr_schnorr_sig(
+        &self,
+        pk: &DefiniteDescriptorKey,
+    ) -> Option<bitcoin::taproot::Signature> {
+        match self.find(|sig| sig.key == *pk && sig.kind == SignatureKind::RawTrKeySpend)? {
+            AnySignature::Schnorr(sig) => Some(sig),
+            AnySignature::Ecdsa(_) => None,
+        }
+    }
+
+    fn lookup_tap_leaf_script_sig(
+        &self,
+        pk: &DefiniteDescriptorKey,
+        leaf_hash: &TapLeafHash,
+    ) -> Option<bitcoin::taproot::Signature> {
+        let kind = SignatureKind::TapScriptSpend { leaf_hash: *leaf_hash };
+        match self.find(|sig| sig.key == *pk && sig.kind == kind)? {
+            AnySignature::Schnorr(sig) => Some(sig),
+            AnySignature::Ecdsa(_) => None,
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+mod serde_impls {
+    use core::str::FromStr;
+
+    use bitcoin::hex::{DisplayHex, FromHex};
+    use serde::de::Error as _;
+    use serde::ser::SerializeMap;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    use super::*;
+
+    type Fields = BTreeMap<String, String>;
+
+    fn field<E: serde::de::Error>(fields: &Fields, name: &'static str) -> Result<String, E> {
+        fields
+            .get(name)
+            .cloned()
+            .ok_or_else(|| E::missing_field(name))
+    }
+
+    fn parse<T: FromStr, E: serde::de::Error>(name: &str, s: &str) -> Result<T, E>
+    where
+        T::Err: fmt::Display,
+    {
+        T::from_str(s).map_err(|e| E::custom(format_args!("{}: {}", name, e)))
+    }
+
+    fn serialize_key_and_kind<M: SerializeMap>(
+        map: &mut M,
+        key: &DefiniteDescriptorKey,
+        kind: &SignatureKind,
+    ) -> Result<(), M::Error> {
+        map.serialize_entry("key", &key.to_string())?;
+        match kind {
+            SignatureKind::Ecdsa => map.serialize_entry("kind", "ecdsa"),
+            SignatureKind::TapKeySpend { merkle_root } => {
+                map.serialize_entry("kind", "tap_key_spend")?;
+                match merkle_root {
+                    Some(root) => map.serialize_entry("merkle_root", &root.to_string()),
+                    None => Ok(()),
+                }
+            }
+            SignatureKind::TapScriptSpend { leaf_hash } => {
+                map.serialize_entry("kind", "tap_script_spend")?;
+                map.serialize_entry("leaf_hash", &leaf_hash.to_string())
+            }
+            SignatureKind::RawTrKeySpend => map.serialize_entry("kind", "raw_tr_key_spend"),
+        }
+    }
+
+    fn deserialize_key_and_kind<E: serde::de::Error>(
+        fields: &Fields,
+    ) -> Result<(DefiniteDescriptorKey, SignatureKind), E> {
+        let key = parse("key", &field::<E>(fields, "key")?)?;
+        let kind = match field::<E>(fields, "kind")?.as_str() {
+            "ecdsa" => SignatureKind::Ecdsa,
+            "tap_key_spend" => SignatureKind::TapKeySpend {
+                merkle_root: match fields.get("merkle_root") {
+                    Some(root) => Some(parse("merkle_root", root)?),
+                    None => None,
+                },
+            },
+            "tap_script_spend" => SignatureKind::TapScriptSpend {
+                leaf_hash: parse("leaf_hash", &field::<E>(fields, "leaf_hash")?)?,
+            },
+            "raw_tr_key_spend" => SignatureKind::RawTrKeySpend,
+            kind => {
+                return Err(E::unknown_variant(
+                    kind,
+                    &[
+                        "ecdsa",
+                        "tap_key_spend",
+                        "tap_script_spend",
+                        "raw_tr_key_spend",
+                    ],
+                ))
+            }
+        };
+        Ok((key, kind))
+    }
+
+    impl Serialize for SighashRequest {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            let mut map = serializer.serialize_map(None)?;
+            serialize_key_and_kind(&mut map, &self.key, &self.kind)?;
+            map.serialize_entry("sighash_type", &self.sighash_type.to_string())?;
+            map.serialize_entry("sighash", &self.sighash.to_lower_hex_string())?;
+            map.end()
+        }
+    }
+
+    impl<'de> Deserialize<'de> for SighashRequest {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            let fields = Fields::deserialize(deserializer)?;
+            let (key, kind) = deserialize_key_and_kind(&fields)?;
+            let sighash_type = parse("sighash_type", &field::<D::Error>(&fields, "sighash_type")?)?;
+            let sighash = <[u8; 32]>::from_hex(&field::<D::Error>(&fields, "sighash")?)
+                .map_err(|e| D::Error::custom(format_args!("sighash: {}", e)))?;
+            Ok(SighashRequest { key, kind, sighash_type, sighash })
+        }
+    }
+
+    impl Serialize for SighashSignature {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            let mut map = serializer.serialize_map(None)?;
+            serialize_key_and_kind(&mut map, &self.key, &self.kind)?;
+            let signature = match self.signature {
+                AnySignature::Ecdsa(sig) => sig.to_vec(),
+                AnySignature::Schnorr(sig) => sig.to_vec(),
+            };
+            map.serialize_entry("signature", &signature.to_lower_hex_string())?;
+            map.end()
+        }
+    }
+
+    impl<'de> Deserialize<'de> for SighashSignature {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            let fields = Fields::deserialize(deserializer)?;
+            let (key, kind) = deserialize_key_and_kind(&fields)?;
+            let bytes = Vec::<u8>::from_hex(&field::<D::Error>(&fields, "signature")?)
+                .map_err(|e| D::Error::custom(format_args!("signature: {}", e)))?;
+            let signature = match kind {
+                SignatureKind::Ecdsa => bitcoin::ecdsa::Signature::from_slice(&bytes)
+                    .map(AnySignature::Ecdsa)
+                    .map_err(|e| D::Error::custom(format_args!("signature: {}", e)))?,
+                _ => bitcoin::taproot::Signature::from_slice(&bytes)
+                    .map(AnySignature::Schnorr)
+                    .map_err(|e| D::Error::custom(format_args!("signature: {}", e)))?,
+            };
+            Ok(SighashSignature { key, kind, signature })
+        }
+    }
+
+    impl Serialize for SigningRequest {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            let mut map = serializer.serialize_map(Some(1))?;
+            map.serialize_entry("sighashes", &self.sighashes)?;
+            map.end()
+        }
+    }
+
+    impl<'de> Deserialize<'de> for SigningRequest {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            let mut map = BTreeMap::<String, Vec<SighashRequest>>::deserialize(deserializer)?;
+            let sighashes = map
+                .remove("sighashes")
+                .ok_or_else(|| D::Error::missing_field("sighashes"))?;
+            Ok(SigningRequest { sighashes })
+        }
+    }
+
+    impl Serialize for SigningResponse {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            let mut map = serializer.serialize_map(Some(1))?;
+            map.serialize_entry("signatures", &self.signatures)?;
+            map.end()
+        }
+    }
+
+    impl<'de> Deserialize<'de> for SigningResponse {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            let mut map = BTreeMap::<String, Vec<SighashSignature>>::deserialize(deserializer)?;
+            let signatures = map
+                .remove("signatures")
+                .ok_or_else(|| D::Error::missing_field("signatures"))?;
+            Ok(SigningResponse { signatures })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::str::FromStr;
+
+    use bitcoin::transaction::Version;
+    use bitcoin::{absolute, Amount, OutPoint, ScriptBuf, Sequence, Transaction, TxIn, TxOut};
+
+    use super::*;
+    use crate::descriptor::{DescriptorPublicKey, SinglePub, SinglePubKey};
+    use crate::plan::Assets;
+    use crate::Descriptor;
+
+    fn psbt_for(desc: &Descriptor<DefiniteDescriptorKey>, plan: &Plan) -> Psbt {
+        let tx = Transaction {
+            version: Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::null(),
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence::MAX,
+                witness: Default::default(),
+            }],
+            output: vec![TxOut { value: Amount::from_sat(1000), script_pubkey: ScriptBuf::new() }],
+        };
+        let mut psbt = Psbt::from_unsigned_tx(tx).unwrap();
+        psbt.inputs[0].witness_utxo =
+            Some(TxOut { value: Amount::from_sat(2000), script_pubkey: desc.script_pubkey() });
+        plan.update_psbt_input(&mut psbt.inputs[0]);
+        psbt
+    }
+
+    fn single(pk: secp256k1::PublicKey) -> DescriptorPublicKey {
+        DescriptorPublicKey::Single(SinglePub {
+            origin: None,
+            key: SinglePubKey::FullKey(bitcoin::PublicKey::new(pk)),
+        })
+    }
+
+    #[test]
+    fn signing_request() {
+        let secp = secp256k1::Secp256k1::new();
+        let sks: Vec<_> = (1..=3u8)
+            .map(|i| secp256k1::SecretKey::from_slice(&[i; 32]).unwrap())
+            .collect();
+        let pks: Vec<_> = sks.iter().map(|sk| sk.public_key(&secp)).collect();
+
+        // A 2-of-3 multisig, signed by the first two keys
+        let desc = Descriptor::<DefiniteDescriptorKey>::from_str(&format!(
+            "wsh(multi(2,{},{},{}))",
+            pks[0], pks[1], pks[2]
+        ))
+        .unwrap();
+        let assets = Assets::new().add(single(pks[0])).add(single(pks[1]));
+        let plan = desc.clone().plan(&assets).unwrap();
+        let psbt = psbt_for(&desc, &plan);
+        let request = SigningRequest::from_plan(&plan, &psbt, 0).unwrap();
+        assert_eq!(request.sighashes.len(), 2);
+        assert!(request
+            .sighashes
+            .iter()
+            .all(|req| req.kind == SignatureKind::Ecdsa
+                && req.sighash == request.sighashes[0].sighash));
+
+        // The signer only knows about the requested sighashes and its keys
+        let mut sigs = BTreeMap::new();
+        for (req, sk) in request.sighashes.iter().zip(&sks) {
+            let sig = secp.sign_ecdsa(&req.message(), sk);
+            sigs.insert(req.key.clone(), bitcoin::ecdsa::Signature::sighash_all(sig));
+        }
+        let response = SigningResponse::from_satisfier(&request, &sigs);
+        assert_eq!(response.signatures.len(), 2);
+        response.verify(&secp, &request).unwrap();
+        let (witness, _) = plan.satisfy(&response).unwrap();
+        assert_eq!(witness.len(), 3);
+
+        // Signatures of the wrong sighash are rejected
+        let mut bad = response.clone();
+        let sig = secp.sign_ecdsa(&secp256k1::Message::from_digest([1; 32]), &sks[0]);
+        bad.signatures[0].signature =
+            AnySignature::Ecdsa(bitcoin::ecdsa::Signature::sighash_all(sig));
+        assert_eq!(
+            bad.verify(&secp, &request),
+            Err(Error::InvalidSignature(request.sighashes[0].key.clone()))
+        );
+
+        // Signatures of the right sighash with another sighash type are rejected
+        let mut bad = response.clone();
+        let sig = secp.sign_ecdsa(&request.sighashes[0].message(), &sks[0]);
+        bad.signatures[0].signature = AnySignature::Ecdsa(bitcoin::ecdsa::Signature {
+            signature: sig,
+            sighash_type: bitcoin::EcdsaSighashType::None,
+        });
+        assert_eq!(
+            bad.verify(&secp, &request),
+            Err(Error::WrongSighashType(
+                request.sighashes[0].key.clone(),
+                bitcoin::EcdsaSighashType::All.into()
+            ))
+        );
+
+        // A taproot script spend
+        let desc = Descriptor::<DefiniteDescriptorKey>::from_str(&format!(
+            "tr({},pk({}))",
+            pks[0],
+            pks[2].x_only_public_key().0
+        ))
+        .unwrap();
+        let assets = Assets::new().add(DescriptorPublicKey::Single(SinglePub {
+            origin: None,
+            key: SinglePubKey::XOnly(pks[2].x_only_public_key().0),
+        }));
+        let plan = desc.clone().plan(&assets).unwrap();
+        let psbt = psbt_for(&desc, &plan);
+        let request = SigningRequest::from_plan(&plan, &psbt, 0).unwrap();
+        assert_eq!(request.sighashes.len(), 1);
+        assert!(matches!(request.sighashes[0].kind, SignatureKind::TapScriptSpend { .. }));
+        let keypair = secp256k1::Keypair::from_secret_key(&secp, &sks[2]);
+        let signature = secp.sign_schnorr(&request.sighashes[0].message(), &keypair);
+        let response = SigningResponse {
+            signatures: vec![SighashSignature {
+                key: request.sighashes[0].key.clone(),
+                kind: request.sighashes[0].kind,
+                signature: AnySignature::Schnorr(bitcoin::taproot::Signature {
+                    signature,
+                    sighash_type: bitcoin::TapSighashType::Default,
+                }),
+            }],
+        };
+        response.verify(&secp, &request).unwrap();
+        plan.satisfy(&response).unwrap();
+
+        let mut bad = response.clone();
+        bad.signatures[0].signature = AnySignature::Schnorr(bitcoin::taproot::Signature {
+            signature,
+            sighash_type: bitcoin::TapSighashType::All,
+        });
+        assert_eq!(
+            bad.verify(&secp, &request),
+            Err(Error::WrongSighashType(
+                request.sighashes[0].key.clone(),
+                bitcoin::TapSighashType::Default.into()
+            ))
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn signing_request_serde() {
+        use serde_test::{assert_tokens, Token};
+
+        let key = DefiniteDescriptorKey::from_str(
+            "02c2122e30e73f7fe37986e3f81ded00158e94b7ad472369b83bbdd28a9a198a39",
+        )
+        .unwrap();
+        let request = SigningRequest {
+            sighashes: vec![SighashRequest {
+                key,
+                kind: SignatureKind::Ecdsa,
+                sighash_type: bitcoin::EcdsaSighashType::All.into(),
+                sighash: [0xab; 32],
+            }],
+        };
+        assert_tokens(
+            &request,
+            &[
+                Token::Map { len: Some(1) },
+                Token::Str("sighashes"),
+                Token::Seq { len: Some(1) },
+                Token::Map { len: None },
+                Token::Str("key"),
+                Token::Str("02c2122e30e73f7fe37986e3f81ded00158e94b7ad472369b83bbdd28a9a198a39"),
+                Token::Str("kind"),
+                Token::Str("ecdsa"),
+                Token::Str("sighash_type"),
+                Token::Str("SIGHASH_ALL"),
+                Token::Str("sighash"),
+                Token::Str("abababababababababababababababababababababababababababababababab"),
+                Token::MapEnd,
+                Token::SeqEnd,
+                Token::MapEnd,
+            ],
+        );
+    }
+}
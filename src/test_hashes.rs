@@ -0,0 +1,229 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! # Test Hashes
+//!
+//! Deterministic stand-ins for the hash types of [`MiniscriptKey`], for property
+//! tests which need to choose the preimages of hashes, or to make hashes collide,
+//! e.g. to exercise satisfiers on edge cases which real hash functions make
+//! unreachable. Wrapping any key in a [`TestKey`] swaps its associated hash types
+//! for [`TestHash`], so that `Descriptor<TestKey<Pk>>` parses, encodes and
+//! satisfies like `Descriptor<Pk>`, with the hashes of the test double.
+//!
+//! The test double is trivially broken on purpose: scripts it produces check real
+//! hashes of preimages which don't match, never use them with real funds.
+//!
+
+use core::fmt;
+use core::str::FromStr;
+
+use bitcoin::hashes::{hash160, ripemd160, sha256, Hash};
+
+use crate::miniscript::context::SigType;
+use crate::miniscript::satisfy::Preimage32;
+use crate::{hash256, MiniscriptKey, ToPublicKey};
+
+/// A deterministic stand-in for a hash, the first four bytes of its preimage
+///
+/// Any preimage sharing its first four bytes with another collides with it, and
+/// [`TestHash::preimage`] gives a preimage of any hash. It is displayed and
+/// parsed as eight hex digits.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TestHash(pub u32);
+
+impl TestHash {
+    /// The test hash of `preimage`, its first four bytes, big-endian and
+    /// zero-padded if the preimage is shorter
+    pub fn of(preimage: &[u8]) -> Self {
+        let mut bytes = [0; 4];
+        let len = preimage.len().min(4);
+        bytes[..len].copy_from_slice(&preimage[..len]);
+        TestHash(u32::from_be_bytes(bytes))
+    }
+
+    /// A preimage of the hash, its bytes followed by zeros
+    pub fn preimage(&self) -> Preimage32 {
+        let mut preimage = [0; 32];
+        preimage[..4].copy_from_slice(&self.0.to_be_bytes());
+        preimage
+    }
+
+    /// The hash, padded to 32 bytes, as encoded in scripts by [`TestKey`]
+    fn to_byte_array_32(self) -> [u8; 32] { self.preimage() }
+
+    /// The hash, padded to 20 bytes, as encoded in scripts by [`TestKey`]
+    fn to_byte_array_20(self) -> [u8; 20] {
+        let mut bytes = [0; 20];
+        bytes[..4].copy_from_slice(&self.0.to_be_bytes());
+        bytes
+    }
+}
+
+impl fmt::Display for TestHash {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { write!(f, "{:08x}", self.0) }
+}
+
+impl FromStr for TestHash {
+    type Err = TestHashParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.len() != 8 {
+            return Err(TestHashParseError::Length(s.len()));
+        }
+        u32::from_str_radix(s, 16)
+            .map(TestHash)
+            .map_err(TestHashParseError::Hex)
+    }
+}
+
+/// An error parsing a [`TestHash`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TestHashParseError {
+    /// The string doesn't have eight characters, carrying its length
+    Length(usize),
+    /// The string isn't hex
+    Hex(core::num::ParseIntError),
+}
+
+impl fmt::Display for TestHashParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TestHashParseError::Length(len) => {
+                write!(f, "test hash must be 8 hex digits, got {} characters", len)
+            }
+            TestHashParseError::Hex(ref e) => write!(f, "test hash: {}", e),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for TestHashParseError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match *self {
+            TestHashParseError::Length(_) => None,
+            TestHashParseError::Hex(ref e) => Some(e),
+        }
+    }
+}
+
+/// A key whose associated hash types are all [`TestHash`]
+///
+/// It behaves like the wrapped key otherwise, and converts to the same public key.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TestKey<Pk>(pub Pk);
+
+impl<Pk: fmt::Display> fmt::Display for TestKey<Pk> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { self.0.fmt(f) }
+}
+
+impl<Pk: FromStr> FromStr for TestKey<Pk> {
+    type Err = Pk::Err;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> { Pk::from_str(s).map(TestKey) }
+}
+
+impl<Pk: MiniscriptKey> MiniscriptKey for TestKey<Pk> {
+    fn uncompressed_key(&self) -> Option<bitcoin::PublicKey> { self.0.uncompressed_key() }
+
+    fn is_x_only_key(&self) -> bool { self.0.is_x_only_key() }
+
+    fn is_musig_key(&self) -> bool { self.0.is_musig_key() }
+
+    fn sorting_key(&self) -> Option<bitcoin::secp256k1::XOnlyPublicKey> { self.0.sorting_key() }
+
+    fn num_der_paths(&self) -> usize { self.0.num_der_paths() }
+
+    type Sha256 = TestHash;
+    type Hash256 = TestHash;
+    type Ripemd160 = TestHash;
+    type Hash160 = TestHash;
+}
+
+impl<Pk: ToPublicKey> ToPublicKey for TestKey<Pk> {
+    fn to_public_key(&self) -> bitcoin::PublicKey { self.0.to_public_key() }
+
+    fn to_x_only_pubkey(&self) -> bitcoin::secp256k1::XOnlyPublicKey { self.0.to_x_only_pubkey() }
+
+    fn to_pubkeyhash(&self, sig_type: SigType) -> hash160::Hash { self.0.to_pubkeyhash(sig_type) }
+
+    fn to_sha256(hash: &TestHash) -> sha256::Hash {
+        sha256::Hash::from_byte_array(hash.to_byte_array_32())
+    }
+
+    fn to_hash256(hash: &TestHash) -> hash256::Hash {
+        hash256::Hash::from_byte_array(hash.to_byte_array_32())
+    }
+
+    fn to_ripemd160(hash: &TestHash) -> ripemd160::Hash {
+        ripemd160::Hash::from_byte_array(hash.to_byte_array_20())
+    }
+
+    fn to_hash160(hash: &TestHash) -> hash160::Hash {
+        hash160::Hash::from_byte_array(hash.to_byte_array_20())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::secp256k1;
+
+    use super::*;
+    use crate::descriptor::HashLock;
+    use crate::miniscript::satisfy::HashOracleSatisfier;
+    use crate::prelude::*;
+    use crate::Descriptor;
+
+    type Key = TestKey<bitcoin::PublicKey>;
+
+    #[test]
+    fn test_hashes() {
+        // Colliding and crafted preimages
+        assert_eq!(TestHash::of(b"abcd1"), TestHash::of(b"abcd2"));
+        assert_eq!(TestHash::of(&TestHash(42).preimage()), TestHash(42));
+        assert_eq!(TestHash::from_str("0000002a").unwrap(), TestHash(42));
+        assert_eq!(TestHash::from_str("2a"), Err(TestHashParseError::Length(2)));
+        assert!(matches!(TestHash::from_str("0000002g"), Err(TestHashParseError::Hex(_))));
+
+        let secp = secp256k1::Secp256k1::new();
+        let sk = secp256k1::SecretKey::from_slice(&[1; 32]).unwrap();
+        let pk = TestKey(bitcoin::PublicKey::new(sk.public_key(&secp)));
+        let msg = secp256k1::Message::from_digest([2; 32]);
+        let sig = bitcoin::ecdsa::Signature::sighash_all(secp.sign_ecdsa(&msg, &sk));
+        let mut sigs = BTreeMap::new();
+        sigs.insert(pk.clone(), sig);
+
+        // The same hash locked with two hash functions
+        let desc = Descriptor::<Key>::from_str(&format!(
+            "wsh(and_v(v:pk({}),or_i(sha256(0000002a),hash160(0000002a))))",
+            pk
+        ))
+        .unwrap();
+        let script = desc.explicit_script().unwrap();
+        assert!(script
+            .as_bytes()
+            .windows(32)
+            .any(|w| w == TestHash(42).preimage()));
+
+        // A satisfier only knowing the preimage for hash160 must take its branch,
+        // which is malleable as anyone knowing the sha256 preimage could take the other
+        let oracle = |lock: &HashLock<Key>| match lock {
+            HashLock::Hash160(h) => Some(h.preimage()),
+            _ => None,
+        };
+        assert!(desc
+            .get_satisfaction((&sigs, HashOracleSatisfier(oracle)))
+            .is_err());
+        let (witness, script_sig) = desc
+            .get_satisfaction_mall((&sigs, HashOracleSatisfier(oracle)))
+            .unwrap();
+        assert!(script_sig.is_empty());
+        assert_eq!(witness.len(), 4);
+        assert_eq!(witness[0], TestHash(42).preimage());
+        assert!(witness[1].is_empty());
+
+        // Without any preimage the descriptor can't be satisfied
+        let oracle = |_: &HashLock<Key>| None;
+        assert!(desc
+            .get_satisfaction_mall((&sigs, HashOracleSatisfier(oracle)))
+            .is_err());
+    }
+}
@@ -12,7 +12,15 @@ use bitcoin::key::XOnlyPublicKey;
 use bitcoin::secp256k1;
 
 use crate::miniscript::context::SigType;
-use crate::{hash256, ToPublicKey, Translator};
+use crate::{hash256, Descriptor, DescriptorPublicKey, ToPublicKey, Translator};
+
+/// An extended public key for descriptor tests, see [`xpub_desc`]
+pub const XPUB: &str = "xpub6ERApfZwUNrhLCkDtcHTcxd75RbzS1ed54G1LkBUHQVHQKqhMkhgbmJbZRkrgZw4koxb5JaHWkY4ALHY2grBGRjaDMzQLcgJvLJuZZvRcEL";
+
+/// Parses a descriptor, replacing `XPUB` with [`XPUB`]
+pub fn xpub_desc(s: &str) -> Descriptor<DescriptorPublicKey> {
+    Descriptor::from_str(&s.replace("XPUB", XPUB)).unwrap()
+}
 
 /// Translate from a String MiniscriptKey type to bitcoin::PublicKey
 /// If the hashmap is populated, this will lookup for keys in HashMap